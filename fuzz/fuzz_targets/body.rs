@@ -0,0 +1,24 @@
+//! Feeds arbitrary bytes to the body parser. The first byte selects the
+//! format version, the rest is the decrypted body, parsed with and without
+//! the schema.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mopm::core::{
+    encoder::Body,
+    encoding::{capability::Capabilities, version::Version},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&version, body)) = data.split_first() else {
+        return;
+    };
+    if let Some(version) = Version::from_u8(version) {
+        for capabilities in [
+            Capabilities::default(),
+            Capabilities::default().with(Capabilities::SCHEMA),
+        ] {
+            let _ = Body::try_from_bytes(version, capabilities, body);
+        }
+    }
+});
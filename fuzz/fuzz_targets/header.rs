@@ -0,0 +1,10 @@
+//! Feeds arbitrary bytes to the header parser.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mopm::core::encoder::Header;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Header::try_from_bytes(data);
+    let _ = Header::try_from_reader(&mut &data[..]);
+});
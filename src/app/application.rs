@@ -1,22 +1,106 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::{self, IsTerminal, Read},
+    path::Path,
+    time::Duration,
+};
+
 use inotify::{Inotify, WatchMask};
+use zeroize::Zeroizing;
 
 use crate::{
     cli::{
-        config::{Command, Config},
-        terminal::Terminal,
+        config::{Command, Config, Options},
+        editor::{Document, Editor, EditorError},
+        interact::Interact,
+        menu::Menu,
+        terminal,
     },
     core::{
-        encoder::{Encoder, EncoderError},
-        encoding::version::Version,
-        encryptor::{DynamicEncryptor, Encryprtor},
-        identifiers::Identifiable,
-        manager::PasswordManager,
+        catalog::{self, Catalog},
+        clock,
+        diff::{self, Change},
+        encoder::{Body, Encoder, EncoderError, Header, StoredVault},
+        encoding::{
+            layout::{self, Field},
+            version::Version,
+        },
+        encryptor::{AESEncryptor, DynamicEncryptor, Encryprtor},
+        entry,
+        executor::Executor,
+        generator::{self, Profile},
+        golden, hint,
+        identifiers::{self, Identifiable},
+        identity,
+        kdf::{self, Calibration, Kdf, KdfParams},
+        keycache::KeyCache,
+        manager::{PasswordManager, PasswordManagerError},
+        memsec,
+        policy::AccessPolicy,
+        pool::Pool,
+        refactor, rng,
+        scan::{Leak, Scanner},
+        schema::Schema,
+        session::Session,
+        site, strength, timelock, trace,
+    },
+    diagnostics::{
+        bug::OrBug,
+        exposure::Exposure,
+        incident::{self, IncidentLog},
+    },
+    exec::sandbox,
+    interop::{
+        bitwarden, dotenv,
+        git::{self, HookStatus},
+        handoff::{self, Handoff},
+        history,
     },
-    log::logger::Logger,
-    storage::store::{Storage, StorageError},
+    log::{logger::Logger, table::Table},
+    storage::{
+        shield::{self, Watcher},
+        store::{Storage, StorageError},
+        system,
+        vaults::Vaults,
+    },
+};
+
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+use crate::core::conflict::{
+    Answer, Conflict, ConflictPolicy, ConflictReport, Resolution, Resolver, Side,
 };
+#[cfg(feature = "hidden-volume")]
+use crate::core::hidden::{self, HiddenKey};
+#[cfg(feature = "browser")]
+use crate::interop::browser;
+#[cfg(feature = "hashivault")]
+use crate::interop::hashivault::HashiVault;
+#[cfg(feature = "monitor")]
+use crate::interop::hibp::Hibp;
+#[cfg(feature = "k8s")]
+use crate::interop::kubernetes::{self, Direction, Kubernetes};
+#[cfg(feature = "otpauth")]
+use crate::interop::otpauth;
+#[cfg(feature = "share")]
+use crate::interop::share::{self, Sealed};
+#[cfg(feature = "tpm")]
+use crate::interop::tpm;
+#[cfg(feature = "legacy-layout")]
+use crate::storage::store::LegacyRoot;
 
-use super::constants;
+#[cfg(feature = "monitor")]
+use super::monitor::{self, Finding, Notification};
+use super::{
+    constants::{self, INVALID_TERRAFORM_QUERY as INVALID_QUERY},
+    guard::PromptGuard,
+    hooks::{Context, Event, FailurePolicy},
+    settings::Settings,
+};
 
 pub struct App<T>
 where
@@ -24,6 +108,19 @@ where
 {
     config: Config,
     logger: Logger<T>,
+    interact: Interact,
+    settings: Settings,
+    key_cache: KeyCache,
+    /// The bytes the vault was read from, for writing it to `--out`.
+    source: Vec<u8>,
+    /// Set when the installed vault was unlocked as a hidden vault, which
+    /// is then saved into the slack instead.
+    #[cfg(feature = "hidden-volume")]
+    hidden: Option<HiddenKey>,
+    /// The key slots of the installed vault and the secret sealed to the
+    /// TPM, once looked up.
+    #[cfg(feature = "tpm")]
+    tpm: Option<Option<(tpm::Slots, Zeroizing<Vec<u8>>)>>,
 }
 
 impl<T> App<T>
@@ -31,127 +128,3747 @@ where
     T: term::Terminal,
 {
     pub fn new(config: Config, logger: Logger<T>) -> Self {
-        App { config, logger }
+        let interact = Interact::new(config.assume_yes, config.non_interactive)
+            .password_source(config.password_source.build());
+        App {
+            config,
+            logger,
+            interact,
+            settings: Settings::default(),
+            key_cache: KeyCache::default(),
+            source: Vec::new(),
+            #[cfg(feature = "hidden-volume")]
+            hidden: None,
+            #[cfg(feature = "tpm")]
+            tpm: None,
+        }
+    }
+
+    pub fn run(&mut self) {
+        if self.handle_breaking_arguments() {
+            return;
+        }
+
+        let config_file = Storage::config_file().or_bug("cannot locate the config file");
+        self.settings = match Settings::load(&config_file) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        rng::configure(self.settings.rng);
+        if self.settings.memsec {
+            if let Err(err) = memsec::harden() {
+                self.logger.fatal(format!("{}\n", err).as_ref());
+            }
+        }
+        if let Some(timeout) = self.settings.prompt_timeout {
+            terminal::configure_timeout(timeout);
+        }
+        // `doctor` reports a failing source instead.
+        if !matches!(self.config.command, Some(Command::Doctor)) {
+            if let Err(err) = rng::source().health_check() {
+                self.logger.error(&err);
+                self.logger.fatal(constants::RNG_UNHEALTHY.as_ref());
+            }
+        }
+
+        #[cfg(feature = "legacy-layout")]
+        self.migrate_legacy_root();
+        if let Ok(path) = Storage::device_id_file() {
+            identity::configure_device_id_file(path);
+        }
+
+        let command = match self.config.command.take() {
+            None => {
+                self.logger.info(constants::NO_COMMAND_SPECIFIED.as_ref());
+                return;
+            }
+            Some(v) => v,
+        };
+
+        match command {
+            Command::Init(options) => self.handle_init(&options),
+            Command::Clear(now, undo, session) => match (session, undo) {
+                (true, _) => self.handle_clear_session(),
+                (false, true) => self.handle_clear_undo(),
+                (false, false) => self.handle_clear(now),
+            },
+            Command::Store(key, value, options, _, true) => self.with_init(|app| {
+                app.handle_store_session(key.as_ref(), value.as_deref(), &options)
+            }),
+            Command::Store(key, value, options, force, false) => self
+                .with_init(|app| app.handle_store(key.as_ref(), value.as_deref(), &options, force)),
+            Command::Get(keys, options) => self.with_init(|app| app.handle_get(&keys, &options)),
+            Command::Exists(key) => self.with_init(|app| app.handle_exists(key.as_ref())),
+            Command::Touch(key) => self.with_init(|app| app.handle_touch(key.as_ref())),
+            Command::History(key, n) => {
+                self.with_init(|app| app.handle_history(key.as_ref(), n.as_deref()))
+            }
+            Command::Lookup => self.with_init(|app| app.handle_lookup()),
+            Command::TerraformExternal => self.with_init(|app| app.handle_terraform_external()),
+            Command::Rotate(key, options, copy) => {
+                self.with_init(|app| app.handle_rotate(key.as_ref(), &options, copy))
+            }
+            Command::Generate(options) => self.handle_generate(&options),
+            Command::Lease(key, command, ttl, network) => self.with_init(|app| {
+                app.handle_lease(key.as_ref(), command.as_ref(), ttl.as_deref(), network)
+            }),
+            Command::Audit(options, summary) => {
+                self.with_init(|app| app.handle_audit(&options, summary))
+            }
+            Command::Delete(key) => self.with_init(|app| app.handle_delete(key.as_ref())),
+            Command::Edit(key, insecure_tmp) => {
+                self.with_init(|app| app.handle_edit(key.as_ref(), insecure_tmp))
+            }
+            Command::Policy(key, policy) => {
+                self.with_init(|app| app.handle_policy(key.as_ref(), policy.as_deref()))
+            }
+            Command::Scan(dir, staged) => {
+                self.with_init(|app| app.handle_scan(dir.as_deref(), staged))
+            }
+            Command::GuardHistory => self.with_init(|app| app.handle_guard_history()),
+            Command::Hooks(action, force) => match action.as_str() {
+                "install-git" => self.handle_install_git_hook(force),
+                _ => self
+                    .logger
+                    .fatal("invalid argument, accepted: `install-git`".as_ref()),
+            },
+            Command::Compact(days) => self.with_init(|app| app.handle_compact(days.as_deref())),
+            Command::Refactor(operation, from, to, dry_run) => {
+                self.with_init(|app| app.handle_refactor(&operation, &from, to.as_deref(), dry_run))
+            }
+            Command::RestoreBackup(n) => self.with_init(|app| app.handle_restore_backup(&n)),
+            Command::Open(path, key) => self.handle_open(path.as_ref(), key.as_deref()),
+            Command::Verify(path, options) => self.handle_verify(path.as_ref(), &options),
+            #[cfg(feature = "crdt")]
+            Command::Merge(path, options) => {
+                self.with_init(|app| app.handle_merge(path.as_ref(), &options))
+            }
+            Command::Info => self.with_init(|app| app.handle_info()),
+            Command::Dump(debug) => self.with_init(|app| app.handle_dump(debug)),
+            Command::Doctor => self.handle_doctor(),
+            Command::Selftest => self.handle_selftest(),
+            Command::Bench(target, apply) => self.handle_bench(target.as_deref(), apply),
+            Command::Diff(before, after, show_values, json) => {
+                self.handle_diff(before.as_ref(), after.as_ref(), show_values, json)
+            }
+            Command::List(pattern, options, reverse, json) => {
+                self.with_init(|app| app.handle_list(pattern.as_deref(), &options, reverse, json))
+            }
+            Command::Search(pattern, all_vaults) => {
+                self.with_init(|app| app.handle_search(&pattern, all_vaults))
+            }
+            Command::Match(url) => self.with_init(|app| app.handle_match(&url)),
+            Command::Menu(v) => match v.as_str() {
+                "copy" | "type" => self.with_init(|app| app.handle_menu(v == "type")),
+                _ => self
+                    .logger
+                    .fatal("invalid argument, accepted: `copy`, `type`".as_ref()),
+            },
+            #[cfg(feature = "fuse")]
+            Command::Mount(dir) => self.with_init(|app| app.handle_mount(dir.as_ref())),
+            #[cfg(feature = "k8s")]
+            Command::K8sSync(options) => self.with_init(|app| app.handle_k8s_sync(&options)),
+            #[cfg(feature = "web")]
+            Command::Web(options) => self.with_init(|app| app.handle_web(&options)),
+            #[cfg(feature = "ssh-agent")]
+            Command::SshAgent(options) => self.with_init(|app| app.handle_ssh_agent(&options)),
+            #[cfg(feature = "monitor")]
+            Command::Monitor(options, once, offline) => {
+                self.with_init(|app| app.handle_monitor(&options, once, offline))
+            }
+            #[cfg(feature = "monitor")]
+            Command::Notifications(clear) => self.with_init(|app| app.handle_notifications(clear)),
+            Command::Handoff(key, receive) => match receive {
+                true => self.with_init(|app| app.handle_receive_handoff(key.as_deref())),
+                false => {
+                    self.with_init(|app| app.handle_handoff(key.as_deref().unwrap_or_default()))
+                }
+            },
+            #[cfg(feature = "share")]
+            Command::ShareLink(key, options) => {
+                self.with_init(|app| app.handle_share_link(key.as_ref(), &options))
+            }
+            #[cfg(feature = "share")]
+            Command::ReceiveLink(link, key) => {
+                self.with_init(|app| app.handle_receive_link(link.as_ref(), key.as_deref()))
+            }
+            #[cfg(any(feature = "browser", feature = "hashivault"))]
+            Command::Import(format, options) => {
+                self.with_init(|app| app.handle_import(format.as_ref(), &options))
+            }
+            Command::Export(format, options) => {
+                self.with_init(|app| app.handle_export(format.as_deref(), &options))
+            }
+            Command::Publish(options) => self.with_init(|app| app.handle_publish(&options)),
+            Command::Subscribe(path) => self.handle_subscribe(path.as_ref()),
+            #[cfg(feature = "self-update")]
+            Command::SelfUpdate(check) => self.handle_self_update(check),
+            #[cfg(feature = "hidden-volume")]
+            Command::Hidden(v) => match v.as_str() {
+                "create" => self.with_init(|app| app.handle_hidden_create()),
+                _ => self
+                    .logger
+                    .fatal("invalid argument, accepted: `create`".as_ref()),
+            },
+            #[cfg(feature = "otpauth")]
+            Command::Otpauth(v, options) => match v.as_str() {
+                "import" => self.with_init(|app| app.handle_otpauth_import(&options)),
+                _ => self
+                    .logger
+                    .fatal("invalid argument, accepted: `import`".as_ref()),
+            },
+            #[cfg(feature = "tpm")]
+            Command::Tpm(v, options) => match v.as_str() {
+                "enroll" => self.with_init(|app| app.handle_tpm_enroll(&options)),
+                "remove" => self.with_init(|app| app.handle_tpm_remove()),
+                "password" => self.with_init(|app| app.handle_tpm_password()),
+                _ => self
+                    .logger
+                    .fatal("invalid argument, accepted: `enroll`, `remove`, `password`".as_ref()),
+            },
+
+            Command::Catalog(action, path) => match (action.as_str(), path) {
+                ("export", path) => {
+                    self.with_init(|app| app.handle_catalog_export(path.as_deref()))
+                }
+                ("verify", Some(path)) => {
+                    self.with_init(|app| app.handle_catalog_verify(path.as_ref()))
+                }
+                ("verify", None) => self.logger.fatal(constants::MISSING_CATALOG.as_ref()),
+                _ => self
+                    .logger
+                    .fatal("invalid argument, accepted: `export`, `verify`".as_ref()),
+            },
+            Command::Panic(self_destruct) => self.handle_panic(self_destruct),
+            Command::Shield(v) => match v.as_str() {
+                "up" => self.with_init(|app| app.handle_shield_up()),
+                "down" => self.handle_shield_down(),
+                "incidents" => self.handle_incidents(),
+                "status" => self.handle_shield_status(),
+                "reset" => self.handle_shield_reset(),
+                _ => self.logger.fatal(
+                    "invalid argument, accepted: `up`, `down`, `incidents`, `status`, `reset`"
+                        .as_ref(),
+                ),
+            },
+        }
+    }
+
+    /// Moves a root left in `~/.mopm` by early versions, once, and says
+    /// so: hooks and mounts may still point at the old path.
+    #[cfg(feature = "legacy-layout")]
+    fn migrate_legacy_root(&mut self) {
+        match Storage::migrate_legacy_root() {
+            Ok(LegacyRoot::Absent) => {}
+            Ok(LegacyRoot::Moved(legacy)) => {
+                let root = Storage::root().or_bug("cannot locate the root");
+                self.logger.info(
+                    format!(
+                        "{}{} to {}\n",
+                        constants::LEGACY_ROOT_MOVED,
+                        legacy.display(),
+                        root.display()
+                    )
+                    .as_ref(),
+                );
+                if self.settings.hooks.mentions(constants::LEGACY_ROOT_NAME) {
+                    self.logger.warn(constants::LEGACY_ROOT_IN_HOOKS.as_ref());
+                }
+            }
+            Ok(LegacyRoot::Ignored(legacy)) => {
+                let root = Storage::root().or_bug("cannot locate the root");
+                self.logger.warn(
+                    format!(
+                        "{}{}{}{}\n",
+                        constants::LEGACY_ROOT_IGNORED,
+                        legacy.display(),
+                        constants::LEGACY_ROOT_IGNORED_HINT,
+                        root.display()
+                    )
+                    .as_ref(),
+                )
+            }
+            Ok(LegacyRoot::Shielded(_)) => {
+                self.logger.warn(constants::LEGACY_ROOT_SHIELDED.as_ref())
+            }
+            Err(err) => self
+                .logger
+                .warn(format!("{}{}\n", constants::LEGACY_ROOT_NOT_MOVED, err).as_ref()),
+        }
+    }
+
+    fn handle_breaking_arguments(&mut self) -> bool {
+        if self.config.show_version {
+            self.logger
+                .info(format!("Version: {}\n", Version::current_version()).as_ref());
+            return true;
+        }
+        if self.config.show_help {
+            self.logger.info(constants::HELP_MESSAGE.as_ref());
+            return true;
+        }
+        false
+    }
+
+    fn handle_init(&mut self, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|v| !constants::INIT_OPTIONS.contains(&v.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_INIT_OPTION, name).as_ref());
+        }
+        if self.config.system {
+            return self.handle_system_init(options);
+        }
+        if Storage::is_initialized().or_bug("cannot locate the storage") {
+            self.logger.warn(constants::ALREADY_INITIALIZED.as_ref());
+            return;
+        }
+
+        let password = self.prompt_password();
+        let hint = options.get(constants::HINT_OPTION);
+        if let Some(Err(err)) = hint.map(|v| hint::check(v, password.trim())) {
+            self.logger.fatal(format!("{}\n", err).as_ref());
+        }
+        let hasher_id = self.init_hasher(options);
+        let bound = self.init_bound_values(options);
+        let backend = options
+            .get(constants::BACKEND_OPTION)
+            .map_or("file", String::as_str);
+        if backend != "file" && !(cfg!(feature = "sqlite") && backend == "sqlite") {
+            self.logger.fatal(constants::UNKNOWN_BACKEND.as_ref());
+        }
+        self.logger.info(constants::CALIBRATING.as_ref());
+        self.logger.flush();
+        let calibration = self.calibrate(constants::DEFAULT_UNLOCK_MS);
+        let mut pm = match Kdf::argon2id(calibration.params)
+            .map_err(PasswordManagerError::from)
+            .and_then(|kdf| PasswordManager::init(password.trim(), kdf))
+            .map(|v| v.with_hasher(hasher_id))
+            .and_then(|mut v| v.bind_values(bound).map(|_| v))
+        {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+
+        #[cfg(feature = "sqlite")]
+        let result = match backend {
+            "sqlite" => Storage::init_sqlite(&mut pm),
+            _ => Storage::init(&mut pm),
+        };
+        #[cfg(not(feature = "sqlite"))]
+        let result = Storage::init(&mut pm);
+        match result {
+            Ok(_) => self.logger.info(constants::INIT_SUCCESSFULL.as_ref()),
+            Err(StorageError::RootAlreadyExistsErorr) => return,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        }
+        if let Some(Err(err)) = hint.map(|v| Storage::save_hint(v)) {
+            self.logger.error(&err);
+        }
+    }
+
+    /// Creates the system vault and the key it is locked by, which only
+    /// root can do: the key must be readable by root alone.
+    fn handle_system_init(&mut self, options: &Options) {
+        if options.contains_key(constants::HINT_OPTION)
+            || options
+                .get(constants::BACKEND_OPTION)
+                .is_some_and(|v| v != "file")
+        {
+            self.logger
+                .fatal(constants::SYSTEM_INIT_UNSUPPORTED.as_ref());
+        }
+        if !nix::unistd::geteuid().is_root() {
+            self.logger.fatal(constants::SYSTEM_NOT_ROOT.as_ref());
+        }
+        if system::is_initialized() {
+            self.logger.warn(constants::ALREADY_INITIALIZED.as_ref());
+            return;
+        }
+        let hasher_id = self.init_hasher(options);
+        let bound = self.init_bound_values(options);
+        let key = match system::create_key(Path::new(system::KEY_FILE))
+            .and_then(|key| system::create_parent(Path::new(system::DATA_FILE)).map(|_| key))
+        {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let mut pm = PasswordManager::init_with_key(&key).with_hasher(hasher_id);
+        pm.bind_values(bound)
+            .or_bug("a new vault has no values to encrypt again");
+        let path = Path::new(system::DATA_FILE);
+        match Storage::save_file(&mut pm, path, &self.settings.backups) {
+            Ok(_) => self.logger.info(
+                format!(
+                    "{}{}, locked by {}\n",
+                    constants::SYSTEM_INIT_SUCCESSFUL,
+                    system::DATA_FILE,
+                    system::KEY_FILE
+                )
+                .as_ref(),
+            ),
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    fn init_bound_values(&mut self, options: &Options) -> bool {
+        match options.get(constants::VALUES_OPTION).map(String::as_str) {
+            None | Some("bound") => true,
+            Some("unbound") => false,
+            Some(_) => self.logger.fatal(constants::UNKNOWN_VALUES.as_ref()),
+        }
+    }
+
+    fn init_hasher(&mut self, options: &Options) -> u8 {
+        match options.get(constants::HASHER_OPTION) {
+            Some(name) => match identifiers::hasher_id_from_name(name) {
+                Some(v) => v,
+                None => self.logger.fatal(constants::UNKNOWN_HASHER.as_ref()),
+            },
+            None => identifiers::DEFAULT_HASHER_ID,
+        }
+    }
+
+    /// Moves the storage into the trash, purged after the grace period or
+    /// right away with `now`. Storages cleared before the grace period are
+    /// purged on the way.
+    fn handle_clear(&mut self, now: bool) {
+        if Storage::is_initialized().unwrap_or_default() {
+            let mut bytes = [0; 3];
+            rng::fill(&mut bytes);
+            let phrase = format!("clear-{}", hex::encode(bytes));
+            match self.interact.confirm_phrase(
+                &mut self.logger,
+                constants::CLEAR_CONFIRMATION,
+                &phrase,
+            ) {
+                Ok(true) => {}
+                Ok(false) => self.logger.fatal(constants::ABORTED.as_ref()),
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            }
+        }
+        let cleared = match Storage::clear() {
+            Ok(_) => {
+                self.run_hook(Event::Clear, Context::default());
+                true
+            }
+            Err(StorageError::RootDoesNotExistErorr) => false,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        if let Err(err) = Storage::purge_trash(now) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::CANNOT_PURGE_TRASH.as_ref());
+        }
+        match (cleared, now) {
+            (true, true) => self.logger.info(constants::CLEAR_SUCCESSFUL.as_ref()),
+            (true, false) => self.logger.info(constants::CLEAR_TRASHED.as_ref()),
+            (false, true) => self.logger.info(constants::TRASH_PURGED.as_ref()),
+            (false, false) => self.logger.info(constants::NOT_INITIALIZED.as_ref()),
+        }
+    }
+
+    fn handle_clear_undo(&mut self) {
+        match Storage::restore_trash() {
+            Ok(Some(_)) => self.logger.info(constants::CLEAR_UNDONE.as_ref()),
+            Ok(None) => self.logger.fatal(constants::NOTHING_TO_UNDO.as_ref()),
+            Err(StorageError::RootAlreadyExistsErorr) => {
+                self.logger.fatal(constants::STORAGE_IN_THE_WAY.as_ref())
+            }
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        }
+    }
+
+    fn handle_store(&mut self, key: &str, value: Option<&str>, options: &Options, force: bool) {
+        let mut options = options.clone();
+        let [not_before, expires] =
+            [constants::NOT_BEFORE_OPTION, constants::EXPIRES_OPTION].map(|name| {
+                options
+                    .remove(name)
+                    .map(|v| match timelock::parse_time(&v) {
+                        Ok(v) => v,
+                        Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+                    })
+            });
+        if let Some(name) = options
+            .keys()
+            .find(|name| !entry::LOGIN_FIELDS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_STORE_OPTION, name).as_ref());
+        }
+        for (name, value) in options.iter_mut() {
+            *value = match site::lint(name, value) {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            };
+        }
+        let mut pm = self.get_password_manager();
+        let value = self.store_value(value);
+        let value = value.as_str();
+        let mut fields = options.clone();
+        let times = [
+            (timelock::NOT_BEFORE_FIELD, not_before),
+            (timelock::EXPIRES_FIELD, expires),
+        ];
+        for (name, time) in times {
+            if let Some(time) = time {
+                fields.insert(name.to_string(), time.to_string());
+            }
+        }
+        let overwrite = pm.overwrite(key, value, &fields);
+        if let Some(overwrite) = self.or_fatal(overwrite) {
+            let changed = |yes| if yes { "yes" } else { "no" };
+            let fields = match overwrite.fields.is_empty() {
+                true => "none".to_string(),
+                false => overwrite.fields.join(","),
+            };
+            self.logger.info(
+                format!(
+                    "`{}` already exists\n  value changed: {}\n  fields changed: {}\n",
+                    key,
+                    changed(overwrite.value_changed),
+                    fields
+                )
+                .as_ref(),
+            );
+            if !force {
+                self.confirm(constants::STORE_OVER_CONFIRMATION);
+            }
+        }
+        let reused_by = pm.reused_by(key, value);
+        if !reused_by.is_empty() {
+            self.logger
+                .warn(format!("{}{}\n", constants::PASSWORD_REUSED, reused_by.join(", ")).as_ref());
+        }
+        let result = pm.store_password(key.into(), value);
+        self.or_fatal(result);
+        for (name, value) in &options {
+            let result = pm.set_meta(key, name, value);
+            self.or_fatal(result);
+        }
+        if let Some(not_before) = not_before {
+            let result = pm.lock_until(key, not_before);
+            self.or_fatal(result);
+        }
+        if let Some(expires) = expires {
+            let result = pm.expire_at(key, expires);
+            self.or_fatal(result);
+        }
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.run_hook(Event::Store, Self::hook_context(&pm, key));
+        self.logger.info(constants::STORE_SUCCESSFUL.as_ref());
+    }
+
+    /// The value given on the command line, or asked for.
+    fn store_value(&mut self, value: Option<&str>) -> String {
+        match value {
+            Some(v) => {
+                self.logger.warn(constants::SECRET_IN_ARGV.as_ref());
+                v.to_string()
+            }
+            None => match self
+                .interact
+                .secret(&mut self.logger, constants::VALUE_PROMPT)
+            {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            },
+        }
+    }
+
+    /// Keeps `value` for the login session only, never writing it to the
+    /// vault, see `session`.
+    fn handle_store_session(&mut self, key: &str, value: Option<&str>, options: &Options) {
+        if !options.is_empty() {
+            self.logger.fatal(constants::SESSION_WITH_FIELDS.as_ref());
+        }
+        let pm = self.get_password_manager();
+        if pm.contains(key) {
+            self.logger.fatal(constants::SESSION_KEY_IN_VAULT.as_ref());
+        }
+        let value = self.store_value(value);
+        let sealed = match Storage::read_session() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let session = match sealed {
+            Some(sealed) => Session::open(&pm, &sealed),
+            None => Ok(Session::default()),
+        };
+        let mut session = match session {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        session.insert(key.to_string(), &value);
+        let result = session
+            .seal(&pm)
+            .map(|sealed| Storage::write_session(&sealed));
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+        self.logger.info(constants::SESSION_STORED.as_ref());
+    }
+
+    /// The session entry of `key`, if there is one that opens with `pm`.
+    fn session_value(
+        &mut self,
+        pm: &PasswordManager<DynamicEncryptor>,
+        key: &str,
+    ) -> Option<String> {
+        // Sessions belong to the login of a user, not to the machine.
+        if self.config.system {
+            return None;
+        }
+        let sealed = Storage::read_session().ok().flatten()?;
+        match Session::open(pm, &sealed) {
+            Ok(session) => session.get(key).map(str::to_string),
+            Err(err) => {
+                self.logger.warn(format!("{}\n", err).as_ref());
+                None
+            }
+        }
+    }
+
+    fn handle_clear_session(&mut self) {
+        match Storage::forget_session() {
+            Ok(true) => self.logger.info(constants::SESSION_FORGOTTEN.as_ref()),
+            Ok(false) => self.logger.info(constants::NO_SESSION.as_ref()),
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    /// Prints the value of a single key as it is, or those of several,
+    /// given as arguments or in `--from-file`, in `--format` (JSON by
+    /// default) with a single unlock. The file is read before the vault is
+    /// opened, like the keys of `lookup`.
+    fn handle_get(&mut self, keys: &[String], options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::GET_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_GET_OPTION, name).as_ref());
+        }
+        let format = options.get("format").map(String::as_str);
+        if !matches!(format, None | Some("json" | "env")) {
+            self.logger.fatal(constants::UNKNOWN_GET_FORMAT.as_ref());
+        }
+        let mut keys = keys.to_vec();
+        if let Some(path) = options.get("from-file") {
+            match read_key_file(path) {
+                Ok(v) => keys.extend(v),
+                Err(err) => self.logger.fatal(format!("{}: {}\n", path, err).as_ref()),
+            }
+        }
+        let mut seen = HashSet::new();
+        keys.retain(|key| seen.insert(key.clone()));
+        if format == Some("env") {
+            if let Err(err) = dotenv::check_names(keys.iter().map(String::as_str)) {
+                self.logger.fatal(format!("{}\n", err).as_ref());
+            }
+        }
+
+        let mut pm = self.get_password_manager();
+        let mut secrets = Vec::new();
+        let mut refreshed = false;
+        for key in &keys {
+            if !pm.contains(key) {
+                if let Some(value) = self.session_value(&pm, key) {
+                    secrets.push((key.as_str(), value));
+                    continue;
+                }
+            }
+            match pm.resolve_password(key, &Executor::default()) {
+                Ok((value, was_refreshed)) => {
+                    refreshed |= was_refreshed;
+                    secrets.push((key.as_str(), value));
+                }
+                Err(err) => {
+                    if matches!(err, PasswordManagerError::TimeLocked(_)) {
+                        self.warn_clock_skew(&pm);
+                    }
+                    match keys.len() {
+                        1 => self.logger.fatal(err.to_string().as_ref()),
+                        _ => self.logger.fatal(format!("{}: {}\n", key, err).as_ref()),
+                    }
+                }
+            }
+        }
+        // A mirror keeps the value it was published with.
+        if refreshed && !pm.is_mirror() {
+            if let Err(err) = self.save_password_manager(&mut pm) {
+                self.logger.error(&err);
+                self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+            };
+        }
+        for (key, _) in &secrets {
+            self.run_hook(Event::Get, Self::hook_context(&pm, key));
+        }
+        let output = match (format, &secrets[..]) {
+            (None, [(_, value)]) => value.clone(),
+            (Some("env"), _) => {
+                let secrets: Vec<_> = secrets.iter().map(|(k, v)| (*k, v.as_str())).collect();
+                match dotenv::to_env(&secrets) {
+                    Ok(v) => v,
+                    Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+                }
+            }
+            _ => {
+                let secrets: serde_json::Map<String, serde_json::Value> = secrets
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.as_str().into()))
+                    .collect();
+                format!("{}\n", serde_json::Value::from(secrets))
+            }
+        };
+        self.logger.info(output.as_ref());
+    }
+
+    /// Silent so that scripts can branch on the exit status alone.
+    fn handle_exists(&mut self, key: &str) {
+        let pm = self.get_password_manager();
+        if !pm.contains(key) && self.session_value(&pm, key).is_none() {
+            std::process::exit(constants::EXIT_MISSING);
+        }
+    }
+
+    fn handle_touch(&mut self, key: &str) {
+        let mut pm = self.get_password_manager();
+        let result = pm.touch(key);
+        let created = self.or_fatal(result);
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        if created {
+            self.run_hook(Event::Store, Self::hook_context(&pm, key));
+            self.logger.info(constants::TOUCH_CREATED.as_ref());
+        }
+    }
+
+    /// Lists the previous values of an entry, or prints the <n>th most
+    /// recent one.
+    fn handle_history(&mut self, key: &str, n: Option<&str>) {
+        let n = n.map(|n| match n.parse::<usize>() {
+            Ok(v) if v > 0 => v,
+            _ => self
+                .logger
+                .fatal(constants::INVALID_HISTORY_NUMBER.as_ref()),
+        });
+        let mut pm = self.get_password_manager();
+        let result = pm.history(key);
+        let versions = self.or_fatal(result);
+        let Some(n) = n else {
+            if versions.is_empty() {
+                self.logger.info(constants::NO_HISTORY.as_ref());
+            }
+            for (i, version) in versions.iter().enumerate() {
+                self.logger.info(
+                    format!("{}  {}\n", i + 1, timelock::format_time(version.modified)).as_ref(),
+                );
+            }
+            return;
+        };
+        let Some(version) = versions.get(n - 1) else {
+            self.logger.fatal(constants::NO_SUCH_VERSION.as_ref());
+        };
+        self.run_hook(Event::Get, Self::hook_context(&pm, key));
+        self.logger.info(version.value.as_ref());
+    }
+
+    /// Keys are read before the vault is opened, so that confirmations of
+    /// guarded entries do not consume them.
+    fn handle_lookup(&mut self) {
+        let mut keys = Vec::new();
+        for line in std::io::stdin().lines() {
+            match line {
+                Ok(v) if !v.trim().is_empty() => keys.push(v.trim().to_string()),
+                Ok(_) => {}
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            }
+        }
+        let mut pm = self.get_password_manager();
+        let mut secrets = serde_json::Map::new();
+        let mut refreshed = false;
+        for key in keys {
+            let value = match pm.resolve_password(&key, &Executor::default()) {
+                Ok((v, was_refreshed)) => {
+                    refreshed |= was_refreshed;
+                    serde_json::Value::from(v)
+                }
+                Err(
+                    PasswordManagerError::NoPasswordFound
+                    | PasswordManagerError::AccessDenied
+                    | PasswordManagerError::TimeLocked(_),
+                ) => serde_json::Value::Null,
+                Err(err) => self.logger.fatal(format!("{}: {}\n", key, err).as_ref()),
+            };
+            secrets.insert(key, value);
+        }
+        if refreshed {
+            if let Err(err) = self.save_password_manager(&mut pm) {
+                self.logger.error(&err);
+                self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+            };
+        }
+        self.logger
+            .info(format!("{}\n", serde_json::Value::from(secrets)).as_ref());
+    }
+
+    /// The protocol of Terraform's `external` data source: a JSON object of
+    /// strings on stdin and one on stdout. Any failure fails the plan, as
+    /// Terraform expects, with the reason on stderr.
+    fn handle_terraform_external(&mut self) {
+        let query: serde_json::Map<String, serde_json::Value> =
+            match serde_json::from_reader(std::io::stdin()) {
+                Ok(v) => v,
+                Err(err) => terraform_error(&format!("{}: {}", INVALID_QUERY, err)),
+            };
+        let mut pm = self.get_password_manager();
+        let mut result = serde_json::Map::new();
+        let mut refreshed = false;
+        for (name, key) in query {
+            let Some(key) = key.as_str() else {
+                terraform_error(&format!("{}: `{}` is not a string", INVALID_QUERY, name))
+            };
+            match pm.resolve_password(key, &Executor::default()) {
+                Ok((v, was_refreshed)) => {
+                    refreshed |= was_refreshed;
+                    result.insert(name, v.into());
+                }
+                Err(err) => terraform_error(&format!("{}: {}", key, err)),
+            }
+        }
+        if refreshed {
+            if let Err(err) = self.save_password_manager(&mut pm) {
+                self.logger.error(&err);
+                self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+            };
+        }
+        // The messages go to stderr for this command, the result does not.
+        println!("{}", serde_json::Value::from(result));
+    }
+
+    /// Replaces the password of `key` with a generated one. The rotate hook
+    /// changes it where it is used first, and nothing is saved if it fails.
+    fn handle_rotate(&mut self, key: &str, options: &Options, copy: bool) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::ROTATE_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_ROTATE_OPTION, name).as_ref());
+        }
+        let profile = self.generator_profile(options);
+        let mut pm = self.get_password_manager();
+        if pm.is_dynamic(key) {
+            self.logger.fatal(constants::CANNOT_ROTATE_DYNAMIC.as_ref());
+        }
+        let result = pm.get_password(key);
+        let old = self.or_fatal(result);
+        let new = profile.generate();
+
+        let mut context = Self::hook_context(&pm, key);
+        context.message = self.config.message.clone();
+        let input = format!("{}\n{}\n", old, new);
+        if let Err(err) = self
+            .settings
+            .hooks
+            .run_with_input(Event::Rotate, &context, Some(&input))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::ROTATE_HOOK_FAILED, err).as_ref());
+        }
+
+        let result = pm.store_password(key.into(), &new);
+        self.or_fatal(result);
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.run_hook(Event::Store, Self::hook_context(&pm, key));
+        if !copy {
+            self.logger.info(format!("{}\n", new).as_ref());
+            return;
+        }
+        if let Err(err) = Menu::copy(&new) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::CANNOT_COPY_ROTATED.as_ref());
+        }
+        self.logger.info(constants::ROTATED_AND_COPIED.as_ref());
+    }
+
+    fn handle_generate(&mut self, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::GENERATE_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_GENERATE_OPTION, name).as_ref());
+        }
+        let password = self.generator_profile(options).generate();
+        self.logger.info(format!("{}\n", password).as_ref());
+    }
+
+    /// The generator profile named by `--profile`, or the default one, with
+    /// the length of `--length` if given.
+    fn generator_profile(&mut self, options: &Options) -> Profile {
+        let name = options
+            .get("profile")
+            .map_or(generator::DEFAULT_PROFILE, String::as_str);
+        let profile = match self.settings.profiles.get(name).cloned() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        match self.count_option(options, "length") {
+            Some(0) => self.logger.fatal(constants::EMPTY_GENERATE_LENGTH.as_ref()),
+            Some(length) => profile.with_length(length),
+            None => profile,
+        }
+    }
+
+    fn handle_lease(&mut self, key: &str, command: &str, ttl: Option<&str>, network: bool) {
+        let ttl = match ttl.map(str::parse::<u64>) {
+            None => constants::DEFAULT_LEASE_TTL,
+            Some(Ok(v)) => v,
+            Some(Err(_)) => self
+                .logger
+                .fatal("invalid argument, expected a number of seconds".as_ref()),
+        };
+        let mut pm = self.get_password_manager();
+        let result = pm.store_dynamic(key.into(), command, ttl, network);
+        self.or_fatal(result);
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger.info(constants::LEASE_SUCCESSFUL.as_ref());
+    }
+
+    fn handle_edit(&mut self, key: &str, insecure_tmp: bool) {
+        let mut pm = self.get_password_manager();
+        if pm.is_dynamic(key) {
+            self.logger.fatal(constants::CANNOT_EDIT_DYNAMIC.as_ref());
+        }
+        let password = match pm.get_password(key) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        let document = Document {
+            password,
+            fields: entry::LOGIN_FIELDS
+                .iter()
+                .map(|name| {
+                    (
+                        name.to_string(),
+                        pm.meta(key, name).unwrap_or_default().to_string(),
+                    )
+                })
+                .collect(),
+        };
+
+        let edited = match Editor::edit(&document.render(), insecure_tmp)
+            .and_then(|text| Document::parse(&text, &entry::LOGIN_FIELDS))
+        {
+            Ok(v) => v,
+            Err(EditorError::NoTmpfs) => self.logger.fatal(constants::NO_TMPFS.as_ref()),
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        if edited == document {
+            self.logger.info(constants::NOTHING_CHANGED.as_ref());
+            return;
+        }
+
+        if edited.password != document.password {
+            let result = pm.store_password(key.into(), &edited.password);
+            self.or_fatal(result);
+        }
+        for name in entry::LOGIN_FIELDS {
+            let result = match edited.fields.iter().rev().find(|(field, _)| field == name) {
+                Some((_, value)) if !value.is_empty() => match site::lint(name, value) {
+                    Ok(v) => pm.set_meta(key, name, &v),
+                    Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+                },
+                _ => pm.remove_meta(key, name),
+            };
+            self.or_fatal(result);
+        }
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger.info(constants::STORE_SUCCESSFUL.as_ref());
+    }
+
+    fn handle_list(&mut self, pattern: Option<&str>, options: &Options, reverse: bool, json: bool) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::LIST_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_LIST_OPTION, name).as_ref());
+        }
+        let offset = self.count_option(options, "offset").unwrap_or(0);
+        let limit = self.count_option(options, "limit").unwrap_or(usize::MAX);
+        let fields: Vec<&str> = options
+            .get("fields")
+            .map(|v| v.split(',').collect())
+            .unwrap_or_default();
+        let sort = options.get("sort").map_or("name", String::as_str);
+        if !["name", "modified", "expires"].contains(&sort) {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_LIST_SORT, sort).as_ref());
+        }
+        let columns: Vec<&str> = match options.get("columns") {
+            Some(v) => v.split(',').map(str::trim).collect(),
+            None => constants::LIST_COLUMNS.to_vec(),
+        };
+        if let Some(column) = columns
+            .iter()
+            .find(|v| !constants::LIST_COLUMNS.contains(v))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_LIST_COLUMN, column).as_ref());
+        }
+        // Pipes keep getting the tab separated names scripts were written for.
+        let table = json || options.contains_key("columns") || std::io::stdout().is_terminal();
+
+        let pm = self.get_password_manager();
+        if table {
+            self.warn_clock_skew(&pm);
+        }
+        let mut entries: Vec<_> = pm.entries(pattern.unwrap_or_default()).collect();
+        match sort {
+            "modified" => entries.sort_by_key(|(_, entry)| entry.modified()),
+            // Entries that never expire come last.
+            "expires" => entries.sort_by_key(|(key, _)| pm.expires(key).unwrap_or(u64::MAX)),
+            _ => {}
+        }
+        if reverse {
+            entries.reverse();
+        }
+        let entries = entries.into_iter().skip(offset).take(limit);
+
+        if !table {
+            for (key, entry) in entries {
+                let mut line = key.to_string();
+                for field in &fields {
+                    line.push('\t');
+                    line.push_str(entry.meta(field).unwrap_or_default());
+                }
+                line.push('\n');
+                self.logger.info(line.as_ref());
+                self.logger.flush();
+            }
+            return;
+        }
+
+        let headers = columns.iter().chain(&fields).map(|v| v.to_string());
+        let mut listing = Table::new(headers.collect());
+        let mut expired = Vec::new();
+        let now = clock::now();
+        for (key, entry) in entries {
+            let expires = pm.expires(key);
+            expired.push(expires.is_some_and(|v| v <= now));
+            let row = columns.iter().map(|column| match *column {
+                "name" => key.to_string(),
+                "tags" => entry
+                    .meta(entry::TAGS_FIELD)
+                    .unwrap_or_default()
+                    .to_string(),
+                "modified" => timelock::format_time(entry.modified()),
+                _ => expires.map(timelock::format_time).unwrap_or_default(),
+            });
+            let meta = fields
+                .iter()
+                .map(|field| entry.meta(field).unwrap_or_default().to_string());
+            listing.push(row.chain(meta).collect());
+        }
+
+        if json {
+            self.logger
+                .info(format!("{}\n", listing.to_json()).as_ref());
+            return;
+        }
+        let width = std::env::var("COLUMNS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(std::io::stdout().is_terminal().then_some(80));
+        let mut lines = listing.render(width).into_iter();
+        if let Some(header) = lines.next() {
+            self.logger
+                .colored(term::color::BRIGHT_BLUE, format!("{}\n", header).as_ref());
+        }
+        for (line, expired) in lines.zip(expired) {
+            let line = format!("{}\n", line);
+            match expired {
+                true => self.logger.warn(line.as_ref()),
+                false => self.logger.info(line.as_ref()),
+            }
+        }
+        self.logger.flush();
+    }
+
+    /// Lists the keys containing `pattern` in the vault, and with
+    /// `all_vaults` in every vault of the config file too, each unlocked
+    /// with its own password.
+    fn handle_search(&mut self, pattern: &str, all_vaults: bool) {
+        let vaults = match all_vaults {
+            true => self.settings.vaults.clone(),
+            false => Vaults::default(),
+        };
+        if all_vaults && vaults.iter().next().is_none() {
+            self.logger.warn(constants::NO_OTHER_VAULTS.as_ref());
+        }
+
+        let label = self
+            .config
+            .vault
+            .clone()
+            .unwrap_or_else(|| constants::INSTALLED_VAULT_LABEL.to_string());
+        let pm = self.get_password_manager();
+        let mut found: Vec<(String, String)> = pm
+            .entries(pattern)
+            .map(|(key, _)| (label.clone(), key.to_string()))
+            .collect();
+        for (label, path) in vaults.iter() {
+            let prompt = format!("{}{}: ", constants::VAULT_PASSWORD_PROMPT, label);
+            let pm = self.open_vault_file(&path.to_string_lossy(), &prompt);
+            found.extend(
+                pm.entries(pattern)
+                    .map(|(key, _)| (label.to_string(), key.to_string())),
+            );
+        }
+
+        // Pipes get tab separated lines, as for `list`.
+        if !std::io::stdout().is_terminal() {
+            for (label, key) in found {
+                self.logger.info(format!("{}\t{}\n", label, key).as_ref());
+            }
+            self.logger.flush();
+            return;
+        }
+        if found.is_empty() {
+            self.logger.info(constants::NO_SEARCH_MATCH.as_ref());
+            return;
+        }
+        let mut table = Table::new(vec!["vault".to_string(), "name".to_string()]);
+        for (label, key) in found {
+            table.push(vec![label, key]);
+        }
+        let width = std::env::var("COLUMNS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(80);
+        let mut lines = table.render(Some(width)).into_iter();
+        if let Some(header) = lines.next() {
+            self.logger
+                .colored(term::color::BRIGHT_BLUE, format!("{}\n", header).as_ref());
+        }
+        for line in lines {
+            self.logger.info(format!("{}\n", line).as_ref());
+        }
+        self.logger.flush();
+    }
+
+    fn handle_match(&mut self, url: &str) {
+        let pm = self.get_password_manager();
+        let matches = match pm.matching(url) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        if matches.is_empty() {
+            self.logger.fatal(constants::NO_MATCHING_ENTRIES.as_ref());
+        }
+        for (key, _) in matches {
+            self.logger.info(format!("{}\n", key).as_ref());
+        }
+    }
+
+    fn count_option(&mut self, options: &Options, name: &str) -> Option<usize> {
+        let value = options.get(name)?;
+        match value.parse() {
+            Ok(v) => Some(v),
+            Err(_) => self
+                .logger
+                .fatal(format!("invalid --{}, expected a number\n", name).as_ref()),
+        }
+    }
+
+    fn handle_audit(&mut self, options: &Options, summary: bool) {
+        let pool = self.pool(options);
+        let mut pm = self.get_password_manager();
+        match pm.backfill_fingerprints(&pool) {
+            Ok(0) => {}
+            Ok(_) => {
+                if let Err(err) = self.save_password_manager(&mut pm) {
+                    self.logger.error(&err);
+                    self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+                };
+            }
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        }
+
+        if summary {
+            self.audit_summary(&pm);
+            return;
+        }
+        let groups = pm.reuse_groups();
+        if groups.is_empty() {
+            self.logger.info(constants::NO_REUSE_FOUND.as_ref());
+            return;
+        }
+        for group in groups {
+            self.logger
+                .warn(format!("Reused password: {}\n", group.join(", ")).as_ref());
+        }
+    }
+
+    /// How many entries need attention and which, from what the vault
+    /// records and what `monitor` last reported, so nothing is decrypted.
+    fn audit_summary<U: Encryprtor>(&mut self, pm: &PasswordManager<U>) {
+        let now = clock::now();
+        let stale_before = now.saturating_sub(constants::STALE_DAYS * 24 * 60 * 60);
+        let entries: Vec<_> = pm.entries("").collect();
+        let stale = entries
+            .iter()
+            .filter(|(key, _)| pm.value_modified(key).is_some_and(|v| v < stale_before))
+            .map(|(key, _)| *key)
+            .collect();
+        // Oldest first, they are the first to rotate.
+        let mut unrotated: Vec<&str> = entries
+            .iter()
+            .map(|(key, _)| *key)
+            .filter(|key| pm.is_unrotated_import(key))
+            .collect();
+        unrotated.sort_by_key(|key| pm.value_modified(key));
+        let reused = pm.reuse_groups().concat();
+        let expired = entries
+            .iter()
+            .filter(|(key, _)| pm.expires(key).is_some_and(|v| v <= now))
+            .map(|(key, _)| *key)
+            .collect();
+        #[cfg(feature = "monitor")]
+        let breached = Some(("breached", self.reported_breaches(pm)));
+        #[cfg(not(feature = "monitor"))]
+        let breached = None;
+        let categories: Vec<(&str, Vec<&str>)> = [
+            (constants::STALE_CATEGORY, stale),
+            ("reused", reused),
+            ("expired", expired),
+            (constants::UNROTATED_CATEGORY, unrotated),
+        ]
+        .into_iter()
+        .chain(breached)
+        .collect();
+
+        let mut table = Table::new(vec!["finding".to_string(), "entries".to_string()]);
+        table.push(vec!["total".to_string(), entries.len().to_string()]);
+        for (name, keys) in &categories {
+            table.push(vec![name.to_string(), keys.len().to_string()]);
+        }
+        for line in table.render(None) {
+            self.logger.info(format!("{}\n", line).as_ref());
+        }
+        for (name, keys) in categories.iter().filter(|(_, keys)| !keys.is_empty()) {
+            self.logger
+                .warn(format!("\n{}: {}\n", name, keys.join(", ")).as_ref());
+        }
+    }
+
+    /// The entries `monitor` found in breaches, as last reported.
+    #[cfg(feature = "monitor")]
+    fn reported_breaches<'a, U: Encryprtor>(&mut self, pm: &'a PasswordManager<U>) -> Vec<&'a str> {
+        let path = Storage::notifications_file().or_bug("cannot locate the notifications");
+        let notifications = match monitor::load(&path) {
+            Ok(v) => v,
+            Err(err) => self
+                .logger
+                .fatal(format!("{}: {}\n", path.display(), err).as_ref()),
+        };
+        pm.keys()
+            .into_iter()
+            .filter(|key| {
+                let tag = hex::encode(pm.key_tag(key));
+                notifications
+                    .iter()
+                    .any(|v| v.tag == tag && matches!(v.finding, Finding::Breached(_)))
+            })
+            .collect()
+    }
+
+    fn handle_delete(&mut self, key: &str) {
+        let mut pm = self.get_password_manager();
+        if pm.keys().contains(&key) {
+            self.confirm(&format!("{}`{}`?", constants::DELETE_CONFIRMATION, key));
+        }
+        if let Err(err) = pm.delete(key) {
+            self.logger.fatal(err.to_string().as_ref());
+        }
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.run_hook(Event::Delete, Self::hook_context(&pm, key));
+        self.logger.info(constants::DELETE_SUCCESSFUL.as_ref());
+    }
+
+    fn handle_policy(&mut self, key: &str, policy: Option<&str>) {
+        let mut pm = self.get_password_manager();
+        let Some(policy) = policy else {
+            if !pm.keys().contains(&key) {
+                self.logger
+                    .fatal(PasswordManagerError::NoPasswordFound.to_string().as_ref());
+            }
+            self.logger.info(format!("{}\n", pm.policy(key)).as_ref());
+            return;
+        };
+        let policy = match AccessPolicy::parse(policy) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        if let Err(err) = pm.set_policy(key, policy) {
+            self.logger.fatal(format!("{}\n", err).as_ref());
+        }
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger.info(constants::POLICY_SUCCESSFUL.as_ref());
+    }
+
+    fn handle_scan(&mut self, dir: Option<&str>, staged: bool) {
+        let dir = Path::new(dir.unwrap_or("."));
+        let staged_files = match staged {
+            true => match git::staged_files() {
+                Ok(v) => Some(v),
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            },
+            false => None,
+        };
+        let mut pm = self.get_password_manager();
+        let result = pm.backfill_fingerprints(&Pool::default());
+        self.or_fatal(result);
+        let scanner = Scanner::new(&pm);
+        let findings = match staged_files {
+            Some(files) => files
+                .iter()
+                .flat_map(|(path, contents)| scanner.scan_bytes(path, contents))
+                .collect(),
+            None => match scanner.scan_dir(dir) {
+                Ok((findings, unreadable)) => {
+                    for v in unreadable {
+                        self.logger
+                            .warn(format!("{}: {}\n", v.path.display(), v.error).as_ref());
+                    }
+                    findings
+                }
+                Err(err) => self
+                    .logger
+                    .fatal(format!("{}: {}\n", dir.display(), err).as_ref()),
+            },
+        };
+        if findings.is_empty() {
+            self.logger.info(constants::NO_SECRETS_FOUND.as_ref());
+            return;
+        }
+        for finding in &findings {
+            self.logger.warn(
+                format!(
+                    "{}:{}: {}\n",
+                    finding.path.display(),
+                    finding.line,
+                    describe_leak(&finding.leak)
+                )
+                .as_ref(),
+            );
+        }
+        self.logger
+            .fatal(format!("{}{}\n", constants::SECRETS_FOUND, findings.len()).as_ref());
+    }
+
+    fn handle_guard_history(&mut self) {
+        let home = Storage::homedir().or_bug("cannot locate the home directory");
+        let mut pm = self.get_password_manager();
+        let result = pm.backfill_fingerprints(&Pool::default());
+        self.or_fatal(result);
+        let scanner = Scanner::new(&pm);
+
+        let mut matches = Vec::new();
+        for path in history::history_files(&home) {
+            let contents = match std::fs::read(&path) {
+                Ok(v) => v,
+                Err(err) => {
+                    self.logger
+                        .warn(format!("{}: {}\n", path.display(), err).as_ref());
+                    continue;
+                }
+            };
+            let findings = scanner.scan_bytes(&path, &contents);
+            if findings.is_empty() {
+                continue;
+            }
+            for finding in &findings {
+                self.logger.warn(
+                    format!(
+                        "{}:{}: {}\n",
+                        finding.path.display(),
+                        finding.line,
+                        describe_leak(&finding.leak)
+                    )
+                    .as_ref(),
+                );
+            }
+            let lines: BTreeSet<usize> = findings.iter().map(|v| v.line).collect();
+            matches.push((path, lines));
+        }
+        if matches.is_empty() {
+            self.logger.info(constants::NO_SECRETS_IN_HISTORY.as_ref());
+            return;
+        }
+
+        match self
+            .interact
+            .confirm(&mut self.logger, constants::SCRUB_CONFIRMATION)
+        {
+            Ok(true) => {}
+            Ok(false) => self.logger.fatal(constants::ABORTED.as_ref()),
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+        let mut removed = 0;
+        for (path, lines) in &matches {
+            match history::scrub(path, lines) {
+                Ok(v) => removed += v,
+                Err(err) => self
+                    .logger
+                    .fatal(format!("{}: {}\n", path.display(), err).as_ref()),
+            }
+        }
+        self.logger
+            .info(format!("{}{}\n", constants::HISTORY_SCRUBBED, removed).as_ref());
+        self.logger.warn(constants::OPEN_SHELLS_WARNING.as_ref());
+    }
+
+    fn handle_install_git_hook(&mut self, force: bool) {
+        let executable = match std::env::current_exe() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let message = match git::install_hook(&executable, force) {
+            Ok(HookStatus::Installed(path)) => format!("Installed the hook at {}", path.display()),
+            Ok(HookStatus::Updated(path)) => format!("Updated the hook at {}", path.display()),
+            Ok(HookStatus::UpToDate(path)) => {
+                format!("The hook at {} is up to date", path.display())
+            }
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        self.logger.info(format!("{}\n", message).as_ref());
+    }
+
+    fn handle_compact(&mut self, days: Option<&str>) {
+        let retention = match days.map(str::parse::<u64>) {
+            None => Some(constants::TOMBSTONE_RETENTION_DAYS * 24 * 60 * 60),
+            Some(Ok(v)) => v.checked_mul(24 * 60 * 60),
+            Some(Err(_)) => None,
+        };
+        let Some(retention) = retention else {
+            self.logger
+                .fatal("invalid argument, expected a number of days".as_ref())
+        };
+        let mut pm = self.get_password_manager();
+        let removed = pm.compact(clock::now().saturating_sub(retention));
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger
+            .info(format!("Removed {} expired tombstone(s)\n", removed).as_ref());
+    }
+
+    fn handle_refactor(&mut self, operation: &str, from: &str, to: Option<&str>, dry_run: bool) {
+        let mut pm = self.get_password_manager();
+        let plan = match operation {
+            "--rename-prefix" => pm.plan_rename_prefix(from, to.unwrap_or_default()),
+            _ => pm.plan_retag(from, to),
+        };
+        let changes = match plan {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        if changes.is_empty() {
+            self.logger.info(constants::NOTHING_TO_REFACTOR.as_ref());
+            return;
+        }
+        for change in &changes {
+            let line = match change {
+                refactor::Change::Rename { from, to } => format!("  {} -> {}\n", from, to),
+                refactor::Change::Retag { key, before, after } => format!(
+                    "  {}: {} -> {}\n",
+                    key,
+                    before,
+                    after.as_deref().unwrap_or("(no tags)")
+                ),
+            };
+            self.logger.info(line.as_ref());
+        }
+        self.logger
+            .info(format!("{} entry(ies) would change\n", changes.len()).as_ref());
+        if dry_run {
+            return;
+        }
+        self.confirm(constants::REFACTOR_CONFIRMATION);
+
+        let result = pm.apply(&changes);
+        self.or_fatal(result);
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger.info(constants::STORE_SUCCESSFUL.as_ref());
+    }
+
+    fn handle_restore_backup(&mut self, n: &str) {
+        let n = match n.parse::<usize>() {
+            Ok(v) if v > 0 => v,
+            _ => self
+                .logger
+                .fatal("invalid argument, expected the number of a backup".as_ref()),
+        };
+        let path = Storage::backup_file(n).or_bug("cannot locate the storage");
+        let vault = match std::fs::read(&path) {
+            Ok(v) => v,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                self.logger.fatal(constants::NO_SUCH_BACKUP.as_ref())
+            }
+            Err(err) => self
+                .logger
+                .fatal(format!("{}: {}\n", path.display(), err).as_ref()),
+        };
+        self.decode_password_manager(
+            &StoredVault::File(vault.clone()),
+            constants::PASSWORD_PROMPT,
+            false,
+        );
+        self.confirm(constants::RESTORE_BACKUP_CONFIRMATION);
+        if let Err(err) = Storage::restore_backup(&vault, &self.settings.backups) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger.info(constants::BACKUP_RESTORED.as_ref());
+    }
+
+    #[cfg(feature = "crdt")]
+    fn handle_merge(&mut self, path: &str, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| *name != constants::ON_CONFLICT_OPTION)
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_MERGE_OPTION, name).as_ref());
+        }
+        // Conflicts are asked about when someone is there to answer.
+        let policy = match options.contains_key(constants::ON_CONFLICT_OPTION) {
+            false if self.interact.can_ask() => ConflictPolicy::Ask,
+            _ => self.conflict_policy(options),
+        };
+        let mut pm = self.get_password_manager();
+        let mut other = self.open_vault_file(path, constants::OTHER_PASSWORD_PROMPT);
+        if other.vault_id() != pm.vault_id() {
+            self.logger.warn(constants::DIFFERENT_VAULT.as_ref());
+        }
+
+        let (result, conflicts) = {
+            let mut resolver = self.resolver(policy);
+            (pm.merge(&mut other, &mut resolver), resolver.report)
+        };
+        let report = match result {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger.info(
+            format!(
+                "Merged: {} added, {} updated, {} deleted\n",
+                report.added, report.updated, report.deleted
+            )
+            .as_ref(),
+        );
+        self.report_conflicts(&conflicts);
+    }
+
+    fn handle_diff(&mut self, before: &str, after: &str, show_values: bool, json: bool) {
+        let prompt = |path: &str| format!("{}{}: ", constants::VAULT_PASSWORD_PROMPT, path);
+        let mut old = self.open_vault_file(before, &prompt(before));
+        let mut new = self.open_vault_file(after, &prompt(after));
+        let differences = match diff::diff(&mut old, &mut new) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+
+        if json {
+            let differences: Vec<_> = differences
+                .iter()
+                .map(|v| {
+                    let mut object = serde_json::json!({
+                        "key": v.key,
+                        "change": format!("{:?}", v.change).to_lowercase(),
+                    });
+                    if show_values {
+                        object["before"] = serde_json::json!(v.before);
+                        object["after"] = serde_json::json!(v.after);
+                    }
+                    object
+                })
+                .collect();
+            let json = serde_json::to_string_pretty(&differences).or_bug("json values serialize");
+            self.logger.info(format!("{}\n", json).as_ref());
+            return;
+        }
+
+        if differences.is_empty() {
+            self.logger.info(constants::NO_DIFFERENCES.as_ref());
+        }
+        for difference in differences {
+            let (sign, color) = change_marker(difference.change);
+            let mut line = format!("{} {}", sign, difference.key);
+            if show_values {
+                let show = |v: Option<String>| v.unwrap_or_else(|| "(none)".to_string());
+                line.push_str(&format!(
+                    ": {} -> {}",
+                    show(difference.before),
+                    show(difference.after)
+                ));
+            }
+            line.push('\n');
+            self.logger.colored(color, line.as_ref());
+        }
+    }
+
+    fn handle_catalog_export(&mut self, path: Option<&str>) {
+        let pm = self.get_password_manager();
+        let catalog = Catalog::of(&pm, clock::now());
+        let signed = catalog.sign(&catalog::signing_key(&pm));
+        let Some(path) = path else {
+            self.logger.info(format!("{}\n", signed).as_ref());
+            return;
+        };
+        if let Err(err) = std::fs::write(path, format!("{}\n", signed)) {
+            self.logger.fatal(format!("{}: {}\n", path, err).as_ref());
+        }
+        self.logger.info(
+            format!(
+                "Exported a catalog of {} entries to {}\n",
+                catalog.entries.len(),
+                path
+            )
+            .as_ref(),
+        );
+    }
+
+    fn handle_catalog_verify(&mut self, path: &str) {
+        let text = match std::fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}: {}\n", path, err).as_ref()),
+        };
+        let pm = self.get_password_manager();
+        let published = match Catalog::verify(&text, &catalog::signing_key(&pm).verifying_key()) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let changes = published.changes(&Catalog::of(&pm, clock::now()));
+        if changes.is_empty() {
+            self.logger.info(constants::CATALOG_MATCHES.as_ref());
+            return;
+        }
+        for (key, change) in changes {
+            let (sign, color) = change_marker(change);
+            self.logger
+                .colored(color, format!("{} {}\n", sign, key).as_ref());
+        }
+        self.logger.fatal(constants::CATALOG_MISMATCH.as_ref());
+    }
+
+    fn handle_bench(&mut self, target: Option<&str>, apply: bool) {
+        let target = match target
+            .map(str::parse)
+            .unwrap_or(Ok(constants::DEFAULT_UNLOCK_MS))
+        {
+            Ok(v) => v,
+            Err(_) => self
+                .logger
+                .fatal("invalid argument, expected a number of milliseconds\n".as_ref()),
+        };
+        self.logger.info(constants::CALIBRATING.as_ref());
+        self.logger.flush();
+        let calibration = self.calibrate(target);
+        self.logger.info(
+            format!(
+                "Argon2id {} takes {} ms (target: {} ms)\n",
+                format_kdf_params(&calibration.params),
+                calibration.elapsed.as_millis(),
+                target,
+            )
+            .as_ref(),
+        );
+
+        if apply {
+            self.with_init(|app| app.apply_kdf_params(calibration.params));
+        }
+    }
+
+    fn apply_kdf_params(&mut self, params: KdfParams) {
+        let vault = match self.read_vault() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        let password = self.prompt_password();
+        let mut pm = self.decode_with_password(&vault, &password);
+        let key = match self.vault_key(&password, self.vault_path().is_none()) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        self.rekey(&mut pm, params, &key);
+
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger.info(constants::KDF_APPLIED.as_ref());
+    }
+
+    /// Re-encrypts `pm` with a master key derived from `key` with `params`
+    /// and a fresh salt.
+    fn rekey(&mut self, pm: &mut PasswordManager<DynamicEncryptor>, params: KdfParams, key: &[u8]) {
+        let id = pm.encryptor_id();
+        let result = Kdf::argon2id(params)
+            .and_then(|kdf| Ok((kdf, kdf.derive(key)?)))
+            .map_err(PasswordManagerError::from)
+            .and_then(|(kdf, key)| {
+                let encryptor = identifiers::encryptor_from_id(id, &key)
+                    .or_bug("the encryptor of an opened vault is supported");
+                pm.rekey(DynamicEncryptor(id, encryptor), kdf, &key)
+            });
+        self.or_fatal(result);
+    }
+
+    /// The KDF parameters of `pm`, or new ones for vaults without.
+    fn kdf_params<U: Encryprtor>(&mut self, pm: &PasswordManager<U>) -> KdfParams {
+        match pm.kdf().params() {
+            Some(v) => v,
+            None => self.calibrate(constants::DEFAULT_UNLOCK_MS).params,
+        }
+    }
+
+    fn calibrate(&mut self, target_ms: u64) -> Calibration {
+        match kdf::calibrate(Duration::from_millis(target_ms)) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    fn handle_info(&mut self) {
+        let pm = self.get_password_manager();
+        let device = identity::current_device_id();
+        let last_device = if identity::is_unset(pm.last_device()) {
+            "unknown".to_string()
+        } else {
+            identity::format_id(pm.last_device())
+        };
+
+        self.logger.info(
+            format!(
+                "Vault:       {}\nFormat:      {}\nFeatures:    {}\nLast writer: {}\nThis device: {}\nEntries:     {}\nSchema:      {}\nKDF:         {}\nHasher:      {}\nClock:       {}\n",
+                identity::format_id(pm.vault_id()),
+                pm.version(),
+                match pm.capabilities().is_empty() {
+                    true => "none".to_string(),
+                    false => pm.capabilities().names().join(", "),
+                },
+                last_device,
+                identity::format_id(&device),
+                pm.keys().len(),
+                describe_schema(&pm.schema()),
+                pm.kdf()
+                    .params()
+                    .map_or("none".to_string(), |v| format!("Argon2id {}", format_kdf_params(&v))),
+                identifiers::hasher_name(pm.hasher_id()),
+                match clock::is_behind(pm.last_modified()) {
+                    true => "behind the last change of the vault",
+                    false => "ok",
+                },
+            )
+            .as_ref(),
+        );
+    }
+
+    /// Shows where the fields of the vault file are, and with `debug` where
+    /// the records of its decrypted body are, which takes the password.
+    /// Values are never shown, keys and metadata names are.
+    fn handle_dump(&mut self, debug: bool) {
+        let vault = match self.read_vault() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        if let Err(err) = vault.version() {
+            self.logger.fatal(format!("{}\n", err).as_ref());
+        }
+        let Some(bytes) = vault.file() else {
+            self.logger.fatal(constants::DUMP_NOT_A_FILE.as_ref());
+        };
+        let Some(header) = layout::header(bytes) else {
+            self.logger
+                .fatal(format!("{}\n", EncoderError::InvalidHeaderSize).as_ref());
+        };
+        let header_size = header.iter().map(|v| v.length).sum();
+        let mut body = None;
+        if debug {
+            let password = self.prompt_password_with(constants::PASSWORD_PROMPT);
+            match Encoder::decrypt_body(password.trim().as_ref(), bytes, &mut self.key_cache) {
+                Ok(v) => body = Some(v),
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            }
+        }
+        let slack = body.as_ref().map(|(_, _, slack)| *slack);
+
+        self.print_fields(&format!("Header, {} bytes:", header_size), &header);
+        self.print_fields(
+            &format!("Segments, {} bytes in all:", bytes.len()),
+            &layout::segments(header_size, bytes.len(), bytes[1], slack),
+        );
+        if let Some((header, plaintext, _)) = body {
+            let (fields, result) =
+                Body::layout(header.version(), header.capabilities(), &plaintext);
+            self.print_fields(
+                &format!(
+                    "Body, {} bytes decrypted, offsets into it:",
+                    plaintext.len()
+                ),
+                &fields,
+            );
+            if let Err(err) = result {
+                self.logger.fatal(format!("{}\n", err).as_ref());
+            }
+        }
+    }
+
+    fn print_fields(&mut self, title: &str, fields: &[Field]) {
+        let mut out = format!("{}\n", title);
+        for field in fields {
+            let line = format!(
+                "  {:#010x} {:>8}  {:<16} {}",
+                field.offset, field.length, field.name, field.value
+            );
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        self.logger.info(out.as_ref());
+    }
+
+    /// Checks the machine mopm runs on, each reported as ok or with what
+    /// is wrong, and fails if any does. Exposures of memory and the
+    /// confinement of mopm are reported without failing.
+    fn handle_doctor(&mut self) {
+        let source = rng::source();
+        let checks = [
+            (
+                format!("Entropy source ({})", source),
+                source.health_check().map_err(|err| err.to_string()),
+            ),
+            ("Command sandbox".to_string(), sandbox::check()),
+        ];
+        let mut failed = false;
+        for (name, result) in checks {
+            self.logger.info(format!("{}: ", name).as_ref());
+            match result {
+                Ok(()) => self.logger.colored(term::color::GREEN, b"ok\n"),
+                Err(err) => {
+                    failed = true;
+                    self.logger.warn(format!("{}\n", err).as_ref());
+                }
+            }
+        }
+
+        // Exposures are reported, they are up to the system to fix.
+        for exposure in Exposure::ALL {
+            self.logger.info(format!("{}: ", exposure.name()).as_ref());
+            match exposure.check() {
+                Ok(()) => self.logger.colored(term::color::GREEN, b"ok\n"),
+                Err(risk) if exposure.mitigated_by_memsec() && memsec::is_hardened() => {
+                    self.logger.colored(
+                        term::color::GREEN,
+                        format!("{}, mopm is not dumpable (memsec)\n", risk).as_ref(),
+                    )
+                }
+                Err(risk) => self.logger.warn(format!("{}\n", risk).as_ref()),
+            }
+        }
+        match memsec::is_hardened() {
+            true => self
+                .logger
+                .colored(term::color::GREEN, constants::MEMSEC_ON.as_ref()),
+            false => self.logger.info(constants::MEMSEC_OFF.as_ref()),
+        }
+        match sandbox::scopes_abstract_sockets() {
+            true => self.logger.colored(
+                term::color::GREEN,
+                constants::ABSTRACT_SOCKETS_SCOPED.as_ref(),
+            ),
+            false => self.logger.info(constants::ABSTRACT_SOCKETS_OPEN.as_ref()),
+        }
+        match sandbox::confinement() {
+            Some((module, label)) => self.logger.colored(
+                term::color::GREEN,
+                format!("{}{}: {}\n", constants::CONFINED, module, label).as_ref(),
+            ),
+            None => self.logger.info(constants::UNCONFINED.as_ref()),
+        }
+
+        if failed {
+            self.logger.fatal(constants::DOCTOR_FAILED.as_ref());
+        }
+    }
+
+    /// Decodes the vault files of every released format bundled into the
+    /// binary, for packagers to check a build.
+    fn handle_selftest(&mut self) {
+        let mut failed = false;
+        for (version, result) in golden::check_all() {
+            self.logger.info(format!("Format {}: ", version).as_ref());
+            match result {
+                Ok(()) => self.logger.colored(term::color::GREEN, b"ok\n"),
+                Err(err) => {
+                    failed = true;
+                    self.logger.warn(format!("{}\n", err).as_ref());
+                }
+            }
+        }
+        if failed {
+            self.logger.fatal(constants::SELFTEST_FAILED.as_ref());
+        }
+    }
+
+    fn handle_menu(&mut self, autotype: bool) {
+        let mut pm = self.get_password_manager();
+        let selection = match Menu::select(&pm.keys()) {
+            Ok(Some(v)) => v,
+            Ok(None) => return,
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger.fatal(constants::CANNOT_RUN_MENU.as_ref());
+            }
+        };
+        let password = match pm.resolve_password(&selection, &Executor::default()) {
+            Ok((v, _)) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+
+        let result = if autotype {
+            Menu::autotype(&password)
+        } else {
+            Menu::copy(&password)
+        };
+        if let Err(err) = result {
+            self.logger.error(&err);
+            self.logger
+                .fatal(constants::CANNOT_OUTPUT_SELECTION.as_ref());
+        }
+    }
+
+    /// Acts without asking and keeps going when a step fails, so one
+    /// missing tool does not stop the rest.
+    fn handle_panic(&mut self, self_destruct: bool) {
+        match Menu::clear_clipboard() {
+            Ok(_) => self.logger.info(constants::CLIPBOARD_CLEARED.as_ref()),
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger.warn(constants::CANNOT_CLEAR_CLIPBOARD.as_ref());
+            }
+        }
+        if Storage::forget_session().unwrap_or_default() {
+            self.logger.info(constants::SESSION_FORGOTTEN.as_ref());
+        }
+        #[cfg(feature = "ssh-agent")]
+        self.lock_ssh_agent();
+        if !self_destruct {
+            return;
+        }
+
+        if Storage::is_shielded().unwrap_or_default() {
+            if let Err(err) = self.lower_shield() {
+                self.logger.error(&err);
+                self.logger.warn(constants::CANNOT_LOWER_SHIELD.as_ref());
+            }
+        }
+        self.wipe_storage();
+    }
+
+    /// Clears and purges the storage without asking, for self-destruct.
+    fn wipe_storage(&mut self) {
+        let _ = Storage::forget_session();
+        match Storage::clear().and_then(|_| Storage::purge_trash(true)) {
+            Ok(_) => {
+                self.run_hook(Event::Clear, Context::default());
+                self.logger.info(constants::CLEAR_SUCCESSFUL.as_ref());
+            }
+            Err(StorageError::RootDoesNotExistErorr) => {
+                self.logger.info(constants::NOT_INITIALIZED.as_ref());
+            }
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        }
+    }
+
+    fn handle_open(&mut self, path: &str, key: Option<&str>) {
+        let mut pm = self.open_vault_file(path, constants::PASSWORD_PROMPT);
+
+        match key {
+            Some(key) => match pm.get_password(key) {
+                Ok(v) => self.logger.info(v.as_ref()),
+                Err(err) => self.logger.fatal(err.to_string().as_ref()),
+            },
+            None => {
+                for key in pm.keys() {
+                    self.logger.info(format!("{}\n", key).as_ref());
+                }
+            }
+        }
+    }
+
+    /// Checks a copy of a vault step by step and prints a line for each
+    /// step, stopping at the first step that leaves nothing to check.
+    fn handle_verify(&mut self, path: &str, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::VERIFY_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_VERIFY_OPTION, name).as_ref());
+        }
+        let sample = match options.get("sample").map(String::as_str) {
+            Some("all") => None,
+            Some(_) => self.count_option(options, "sample"),
+            None => Some(constants::DEFAULT_VERIFY_SAMPLE),
+        };
+        let catalog = options
+            .get("catalog")
+            .map(|path| match std::fs::read_to_string(path) {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}: {}\n", path, err).as_ref()),
+            });
+        let vault = match std::fs::read(path) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}: {}\n", path, err).as_ref()),
+        };
+
+        let header = match Header::try_from_reader(&mut vault.as_slice()) {
+            Ok(v) => v,
+            Err(err) => self.verify_failed("header", &err.to_string()),
+        };
+        self.verify_passed(
+            "header",
+            &format!(
+                "format {}, vault {}, generation {}",
+                header.version(),
+                identity::format_id(header.vault_id()),
+                header.generation()
+            ),
+        );
+
+        let attempts = match self.interact.can_retry_password() {
+            true => constants::PASSWORD_ATTEMPTS,
+            false => 1,
+        };
+        let mut attempt = 1;
+        let result = loop {
+            let password = self.prompt_password_with(constants::PASSWORD_PROMPT);
+            let result = Encoder::decode(
+                password.trim().as_ref(),
+                &mut vault.as_slice(),
+                &mut self.key_cache,
+            );
+            match result {
+                Err(EncoderError::IvalidKeyError) if attempt < attempts => {
+                    self.logger.warn(constants::WRONG_PASSWORD.as_ref());
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        // A wrong password and a damaged body fail the authentication tag
+        // alike, nothing in the file tells them apart.
+        let mut pm = match result {
+            Ok(v) => v,
+            Err(EncoderError::IvalidKeyError) => {
+                self.verify_failed("integrity", constants::INTEGRITY_FAILED)
+            }
+            Err(err) => self.verify_failed("integrity", &err.to_string()),
+        };
+        self.verify_passed("integrity", "authentication tag and body checksum");
+
+        let count = pm.keys().len();
+        let mut failed = false;
+        match catalog {
+            Some(text) => {
+                let key = catalog::signing_key(&pm).verifying_key();
+                let published = match Catalog::verify(&text, &key) {
+                    Ok(v) => v,
+                    Err(err) => self.verify_failed("entries", &err.to_string()),
+                };
+                let missing: Vec<String> = published
+                    .changes(&Catalog::of(&pm, clock::now()))
+                    .into_iter()
+                    .filter(|(_, change)| *change == Change::Removed)
+                    .map(|(key, _)| key)
+                    .collect();
+                failed |= !missing.is_empty();
+                self.verify_step(
+                    missing.is_empty(),
+                    "entries",
+                    &match missing.is_empty() {
+                        true => format!(
+                            "{} entries, all {} in the catalog",
+                            count,
+                            published.entries.len()
+                        ),
+                        false => format!("{} entries, missing: {}", count, missing.join(", ")),
+                    },
+                );
+            }
+            None => self.verify_passed("entries", &format!("{} entries", count)),
+        }
+
+        let report = pm.verify_sample(sample);
+        failed |= !report.failed.is_empty();
+        self.verify_step(
+            report.failed.is_empty(),
+            "decrypt",
+            &match report.failed.is_empty() {
+                true => format!("{} of {} entries", report.checked, count),
+                false => format!("cannot decrypt: {}", report.failed.join(", ")),
+            },
+        );
+
+        match failed {
+            true => self.logger.fatal(constants::BACKUP_BROKEN.as_ref()),
+            false => self.logger.info(constants::BACKUP_OK.as_ref()),
+        }
+    }
+
+    fn verify_step(&mut self, passed: bool, step: &str, detail: &str) {
+        match passed {
+            true => self
+                .logger
+                .colored(term::color::GREEN, format!("PASS  {:<10}", step).as_ref()),
+            false => self
+                .logger
+                .colored(term::color::RED, format!("FAIL  {:<10}", step).as_ref()),
+        }
+        self.logger.info(format!("{}\n", detail).as_ref());
+    }
+
+    fn verify_passed(&mut self, step: &str, detail: &str) {
+        self.verify_step(true, step, detail);
+    }
+
+    fn verify_failed(&mut self, step: &str, detail: &str) -> ! {
+        self.verify_step(false, step, detail);
+        self.logger.fatal(constants::BACKUP_BROKEN.as_ref())
+    }
+
+    #[cfg(feature = "fuse")]
+    fn handle_mount(&mut self, dir: &str) {
+        use crate::{
+            fuse::vaultfs::VaultFs,
+            interop::logind::{self, LockEvent},
+        };
+        use fuser::MountOption;
+
+        let pm = self.get_password_manager();
+        let mut config = fuser::Config::default();
+        config.mount_options = vec![
+            MountOption::FSName("mopm".to_string()),
+            MountOption::DefaultPermissions,
+            MountOption::NoExec,
+            MountOption::NoSuid,
+            MountOption::NoDev,
+        ];
+
+        let session = match fuser::spawn_mount(
+            VaultFs::new(pm, self.settings.backups.clone()),
+            dir,
+            &config,
+        ) {
+            Ok(v) => v,
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger.fatal(constants::CANNOT_MOUNT_VAULT.as_ref());
+            }
+        };
+        self.logger
+            .info(format!("{}{}\n", constants::MOUNT_SUCCESSFUL, dir).as_ref());
+        self.logger.info(constants::MOUNT_LOCK_PROMPT.as_ref());
+        self.logger.flush();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let watcher = sender.clone();
+        std::thread::spawn(move || {
+            let _ = std::io::stdin().read_line(&mut String::new());
+            let _ = sender.send(None);
+        });
+        std::thread::spawn(move || {
+            let _ = watcher.send(Some(logind::wait()));
+        });
+        // Enter sends `None`, the watcher its event or why it gave up.
+        let mut locked = None;
+        while let Ok(Some(event)) = receiver.recv() {
+            match event {
+                Ok(event) => {
+                    locked = Some(event);
+                    break;
+                }
+                Err(err) => {
+                    self.logger.error(&err);
+                    self.logger.warn(constants::CANNOT_WATCH_SESSION.as_ref());
+                }
+            }
+        }
+        // The machine does not sleep before the vault is unmounted.
+        let mut inhibitor = None;
+        if let Some((event, held)) = locked {
+            inhibitor = Some(held);
+            self.logger.warn(match event {
+                LockEvent::Locked => constants::SESSION_LOCKED.as_ref(),
+                LockEvent::Suspended => constants::MACHINE_SUSPENDED.as_ref(),
+            });
+            if let Err(err) = Menu::clear_clipboard() {
+                self.logger.error(&err);
+                self.logger.warn(constants::CANNOT_CLEAR_CLIPBOARD.as_ref());
+            }
+        }
+        if let Err(err) = session.umount_and_join() {
+            self.logger.error(&err);
+            self.logger.fatal(constants::CANNOT_UNMOUNT_VAULT.as_ref());
+        }
+        drop(inhibitor);
+        self.logger.info(constants::UNMOUNT_SUCCESSFUL.as_ref());
+    }
+
+    #[cfg(feature = "self-update")]
+    fn handle_self_update(&mut self, check: bool) {
+        use crate::update::updater::Updater;
+
+        let updater = match Updater::new() {
+            Ok(v) => v,
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger.fatal(constants::CANNOT_UPDATE.as_ref());
+            }
+        };
+        let release = match updater.newer_release() {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                self.logger.info(
+                    format!("{}{}\n", constants::UP_TO_DATE, Updater::current_version()).as_ref(),
+                );
+                return;
+            }
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger.fatal(constants::CANNOT_UPDATE.as_ref());
+            }
+        };
+
+        self.logger
+            .info(format!("{}{}\n", constants::UPDATE_AVAILABLE, release.version).as_ref());
+        if check {
+            return;
+        }
+        self.confirm(constants::UPDATE_CONFIRMATION);
+
+        match updater.install(&release) {
+            Ok(path) => self
+                .logger
+                .info(format!("{}{}\n", constants::UPDATE_SUCCESSFUL, path.display()).as_ref()),
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger.fatal(constants::CANNOT_UPDATE.as_ref());
+            }
+        }
+    }
+
+    #[cfg(any(feature = "browser", feature = "hashivault"))]
+    fn handle_import(&mut self, format: &str, options: &Options) {
+        let policy = self.conflict_policy(options);
+        #[cfg(feature = "hashivault")]
+        let pool = self.pool(options);
+        let mut pm = self.get_password_manager();
+        let (result, conflicts) = match format {
+            #[cfg(feature = "browser")]
+            "browser" => {
+                let logins = self.browser_logins(options);
+                let mut resolver = self.resolver(policy);
+                let result = browser::import(&mut pm, &logins, &mut resolver);
+                (result.map_err(|err| err.to_string()), resolver.report)
+            }
+            #[cfg(feature = "hashivault")]
+            "hashivault" => {
+                let vault = self.hashivault_from(options);
+                let mut resolver = self.resolver(policy);
+                let result = vault.import(&mut pm, &mut resolver, &pool);
+                (result.map_err(|err| err.to_string()), resolver.report)
+            }
+            _ => self.logger.fatal(constants::UNKNOWN_FORMAT.as_ref()),
+        };
+        let count = match result {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.as_ref()),
+        };
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger
+            .info(format!("Imported {} password(s)\n", count).as_ref());
+        self.report_conflicts(&conflicts);
+    }
+
+    /// Links are read before the vault is opened, like the keys of `lookup`.
+    #[cfg(feature = "otpauth")]
+    fn handle_otpauth_import(&mut self, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| *name != constants::ON_CONFLICT_OPTION)
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_OTPAUTH_OPTION, name).as_ref());
+        }
+        let policy = self.conflict_policy(options);
+        let mut accounts = Vec::new();
+        let mut batches = (std::collections::BTreeSet::new(), 1);
+        for (number, line) in std::io::stdin().lines().enumerate() {
+            let line = match line {
+                Ok(v) if v.trim().is_empty() => continue,
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            };
+            let migration = match otpauth::parse(&line) {
+                Ok(v) => v,
+                Err(err) => self
+                    .logger
+                    .fatal(format!("line {}: {}\n", number + 1, err).as_ref()),
+            };
+            batches.0.insert(migration.batch.0);
+            batches.1 = batches.1.max(migration.batch.1);
+            accounts.extend(migration.accounts);
+        }
+        if batches.0.is_empty() {
+            self.logger.fatal(constants::NO_MIGRATION_LINKS.as_ref());
+        }
+        if (batches.0.len() as u64) < batches.1 {
+            self.logger.warn(
+                format!(
+                    "Read {} of the {} QR codes of this export, import the others too\n",
+                    batches.0.len(),
+                    batches.1
+                )
+                .as_ref(),
+            );
+        }
+
+        let mut pm = self.get_password_manager();
+        let (result, conflicts) = {
+            let mut resolver = self.resolver(policy);
+            let result = otpauth::import(&mut pm, &accounts, &mut resolver);
+            (result.map_err(|err| err.to_string()), resolver.report)
+        };
+        let count = match result {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.logger
+            .info(format!("Imported {} account(s)\n", count).as_ref());
+        self.report_conflicts(&conflicts);
+    }
+
+    #[cfg(feature = "browser")]
+    fn browser_logins(&mut self, options: &Options) -> Vec<browser::Login> {
+        let Some(profile) = options.get("profile") else {
+            self.logger.fatal(constants::MISSING_PROFILE.as_ref())
+        };
+        match browser::read_profile(Path::new(profile)) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        }
+    }
+
+    /// The `--on-conflict` policy, overwriting by default as imports and
+    /// merges always did.
+    #[cfg(any(
+        feature = "browser",
+        feature = "crdt",
+        feature = "hashivault",
+        feature = "otpauth"
+    ))]
+    fn conflict_policy(&mut self, options: &Options) -> ConflictPolicy {
+        match options
+            .get(constants::ON_CONFLICT_OPTION)
+            .map(|v| ConflictPolicy::parse(v))
+        {
+            None => ConflictPolicy::Overwrite,
+            Some(Ok(v)) => v,
+            Some(Err(err)) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    #[cfg(any(
+        feature = "browser",
+        feature = "crdt",
+        feature = "hashivault",
+        feature = "otpauth"
+    ))]
+    fn resolver(&mut self, policy: ConflictPolicy) -> Resolver<'_> {
+        let (interact, logger) = (&self.interact, &mut self.logger);
+        Resolver::new(policy, move |conflict| {
+            logger.warn(format!("{}`{}`\n", constants::CONFLICT_QUESTION, conflict.key).as_ref());
+            for line in conflict_table(conflict) {
+                logger.info(format!("  {}\n", line).as_ref());
+            }
+            let (choice, all) = match interact.choose_for_all(
+                logger,
+                constants::CONFLICT_PROMPT,
+                &constants::CONFLICT_CHOICES,
+            ) {
+                Ok(v) => v,
+                Err(err) => logger.fatal(format!("{}\n", err).as_ref()),
+            };
+            let resolution = match choice {
+                0 => Resolution::Skip,
+                1 => Resolution::Overwrite,
+                _ => Resolution::KeepBoth,
+            };
+            Answer { resolution, all }
+        })
+    }
+
+    #[cfg(any(
+        feature = "browser",
+        feature = "crdt",
+        feature = "hashivault",
+        feature = "otpauth"
+    ))]
+    fn report_conflicts(&mut self, report: &ConflictReport) {
+        if report.unchanged > 0 {
+            self.logger
+                .info(format!("{} entry(ies) were already stored\n", report.unchanged).as_ref());
+        }
+        if report.conflicts() == 0 {
+            return;
+        }
+        self.logger
+            .warn(format!("{} conflict(s) with existing entries\n", report.conflicts()).as_ref());
+        for key in &report.skipped {
+            self.logger
+                .info(format!("  skipped      {}\n", key).as_ref());
+        }
+        for key in &report.overwritten {
+            self.logger
+                .info(format!("  overwritten  {}\n", key).as_ref());
+        }
+        for (key, kept) in &report.kept_both {
+            self.logger
+                .info(format!("  kept both    {} (new one as {})\n", key, kept).as_ref());
+        }
+    }
+
+    /// Syncs once, or every `--interval` seconds until interrupted. Each
+    /// round reads the vault again, so entries stored in the meantime are
+    /// picked up without asking for the password again.
+    #[cfg(feature = "k8s")]
+    fn handle_k8s_sync(&mut self, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::K8S_SYNC_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_K8S_SYNC_OPTION, name).as_ref());
+        }
+        let direction = match options.get("direction").map(|v| Direction::parse(v)) {
+            None => Direction::ToCluster,
+            Some(Some(v)) => v,
+            Some(None) => self.logger.fatal(constants::INVALID_DIRECTION.as_ref()),
+        };
+        let interval = self
+            .count_option(options, "interval")
+            .map(|v| Duration::from_secs(v as u64));
+        let token = options
+            .get("token-file")
+            .map(|path| match std::fs::read_to_string(path) {
+                Ok(v) => v.trim().to_string(),
+                Err(err) => self.logger.fatal(format!("{}: {}\n", path, err).as_ref()),
+            });
+        let cluster = match Kubernetes::new(
+            options
+                .get("server")
+                .map_or(kubernetes::DEFAULT_SERVER, String::as_str),
+            token.as_deref(),
+            options
+                .get("namespace")
+                .map_or(kubernetes::DEFAULT_NAMESPACE, String::as_str),
+            options
+                .get("prefix")
+                .map_or(kubernetes::DEFAULT_PREFIX, String::as_str),
+        ) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let selector = options.get("selector").map(String::as_str);
+
+        let password = self.prompt_password();
+        loop {
+            let vault = match Storage::read_installed() {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(err.to_string().as_ref()),
+            };
+            let mut pm = self.decode_with_password(&vault, &password);
+            match cluster.sync(&mut pm, selector, direction) {
+                Ok(changed) => {
+                    if direction == Direction::ToVault && changed > 0 {
+                        if let Err(err) = self.save_password_manager(&mut pm) {
+                            self.logger.error(&err);
+                            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+                        };
+                    }
+                    self.logger
+                        .info(format!("Synced {} field(s)\n", changed).as_ref());
+                }
+                Err(err) if interval.is_some() => self.logger.warn(format!("{}\n", err).as_ref()),
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            }
+            let Some(interval) = interval else {
+                break;
+            };
+            self.logger.flush();
+            std::thread::sleep(interval);
+        }
+    }
+
+    /// Serves until interrupted. The vault is read once at startup and only
+    /// unlocked in the browser, so the password is never asked for here.
+    #[cfg(feature = "web")]
+    fn handle_web(&mut self, options: &Options) {
+        use crate::web::server::Server;
+
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::WEB_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_WEB_OPTION, name).as_ref());
+        }
+        let listen = options
+            .get("listen")
+            .map_or(constants::DEFAULT_WEB_LISTEN, String::as_str);
+        let addr: std::net::SocketAddr = match listen.parse() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}: {}\n", listen, err).as_ref()),
+        };
+        if !addr.ip().is_loopback() {
+            self.logger.fatal(constants::WEB_NOT_LOOPBACK.as_ref());
+        }
+        let vault = match self.read_vault() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let listener = match std::net::TcpListener::bind(addr) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}: {}\n", listen, err).as_ref()),
+        };
+        let addr = match listener.local_addr() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        self.logger
+            .info(format!("{}http://{}\n", constants::WEB_SERVING, addr).as_ref());
+        self.logger.flush();
+        if let Err(err) = Server::new(vault, addr).serve(&listener) {
+            self.logger.fatal(format!("{}\n", err).as_ref());
+        }
+    }
+
+    /// Serves the SSH keys of the vault until interrupted. The keys are
+    /// read once at startup and only held in memory.
+    #[cfg(feature = "ssh-agent")]
+    fn handle_ssh_agent(&mut self, options: &Options) {
+        use std::path::PathBuf;
+
+        use crate::interop::sshagent::{self, Agent};
+
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::SSH_AGENT_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_SSH_AGENT_OPTION, name).as_ref());
+        }
+        let socket = match options.get("socket") {
+            Some(v) => PathBuf::from(v),
+            None => match Storage::ssh_agent_socket() {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            },
+        };
+        let mut pm = self.get_password_manager();
+        let filter = match (options.get("filter"), options.get("tag")) {
+            (None, None) => Some(constants::DEFAULT_SSH_KEY_FILTER),
+            (filter, _) => filter.map(String::as_str),
+        };
+        let mut agent = Agent::default();
+        if let Some(allowed) = options.get("allow") {
+            agent.allow(
+                allowed
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(String::from)
+                    .collect(),
+            );
+        }
+        agent.limit(
+            self.count_option(options, "rate")
+                .unwrap_or(constants::DEFAULT_SSH_AGENT_RATE),
+        );
+        for key in pm.select(filter, options.get("tag").map(String::as_str)) {
+            let value = match pm.get_password(&key) {
+                Ok(v) => Zeroizing::new(v),
+                Err(err) => self.logger.fatal(format!("{}: {}\n", key, err).as_ref()),
+            };
+            if let Err(err) = agent.add(&key, &value) {
+                self.logger
+                    .warn(format!("Not serving {}: {}\n", key, err).as_ref());
+            }
+        }
+        drop(pm);
+        if agent.is_empty() {
+            self.logger.fatal(constants::NO_SSH_KEYS.as_ref());
+        }
+        let listener = match sshagent::bind(&socket) {
+            Ok(v) => v,
+            Err(err) => self
+                .logger
+                .fatal(format!("{}: {}\n", socket.display(), err).as_ref()),
+        };
+        self.logger.info(
+            format!(
+                "Serving {} SSH key(s), stop it with Ctrl-C: SSH_AUTH_SOCK={}\n",
+                agent.len(),
+                socket.display()
+            )
+            .as_ref(),
+        );
+        self.logger.flush();
+        // The agent serves on threads of its own and reports refusals here,
+        // where the logger is.
+        let (sender, denials) = std::sync::mpsc::channel();
+        let serving = std::thread::spawn(move || agent.serve(&listener, sender));
+        for denied in denials {
+            self.logger.warn(format!("{}\n", denied).as_ref());
+            self.logger.flush();
+        }
+        let result = serving
+            .join()
+            .unwrap_or_else(|v| std::panic::resume_unwind(v));
+        if let Err(err) = result {
+            self.logger.fatal(format!("{}\n", err).as_ref());
+        }
+    }
+
+    /// Audits the installed vault once, or every `--interval` seconds until
+    /// interrupted. The password is asked for once and the vault read again
+    /// for every round, so entries stored in the meantime are checked too.
+    #[cfg(feature = "monitor")]
+    fn handle_monitor(&mut self, options: &Options, once: bool, offline: bool) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::MONITOR_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_MONITOR_OPTION, name).as_ref());
+        }
+        let interval = self
+            .count_option(options, "interval")
+            .unwrap_or(constants::DEFAULT_MONITOR_INTERVAL_SECS);
+        let expiring = self
+            .count_option(options, "expiring")
+            .unwrap_or(constants::DEFAULT_EXPIRING_DAYS);
+        let path = Storage::notifications_file().or_bug("cannot locate the notifications");
+
+        let Some(within) = (expiring as u64).checked_mul(24 * 60 * 60) else {
+            self.logger
+                .fatal("invalid --expiring, expected a number of days\n".as_ref())
+        };
+        let password = self.prompt_password();
+        loop {
+            // A round that fails is tried again at the next one, e.g. once
+            // the password changed back or the network is up again.
+            match self.monitor_round(&password, offline, within, &path) {
+                Ok(()) => {}
+                Err(err) if !once => self.logger.warn(format!("{}\n", err).as_ref()),
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            }
+            if once {
+                break;
+            }
+            self.logger.flush();
+            std::thread::sleep(std::time::Duration::from_secs(interval as u64));
+        }
+    }
+
+    /// Audits the installed vault once and reports what it finds, even when
+    /// some entries could not be looked up.
+    #[cfg(feature = "monitor")]
+    fn monitor_round(
+        &mut self,
+        password: &str,
+        offline: bool,
+        within: u64,
+        path: &Path,
+    ) -> Result<(), String> {
+        use crate::core::policy::Unattended;
+
+        let vault = Storage::read_installed().map_err(|err| err.to_string())?;
+        let pm = self
+            .vault_key(password, self.vault_path().is_none())
+            .and_then(|key| Encoder::decode_stored(&key, &vault, &mut self.key_cache))
+            .map_err(|err| format!("{}{}", constants::MONITOR_CANNOT_OPEN, err))?;
+        // Nobody is at the terminal to answer, entries with an access
+        // policy are not checked against breaches.
+        let mut pm = pm.with_guard(Box::new(Unattended));
+        let mut hibp = Hibp::default();
+        self.warn_clock_skew(&pm);
+        let now = clock::now();
+        let (findings, error) =
+            monitor::audit(&mut pm, (!offline).then_some(&mut hibp), now, within);
+        self.report_findings(&pm, findings, now, path);
+        match error {
+            Some(err) => Err(format!("{}{}", constants::BREACHES_UNCHECKED, err)),
+            None => Ok(()),
+        }
+    }
+
+    /// Reports the findings not yet in the notifications file at `path`,
+    /// with a desktop notification each, and adds them to it.
+    #[cfg(feature = "monitor")]
+    fn report_findings<U: Encryprtor>(
+        &mut self,
+        pm: &PasswordManager<U>,
+        findings: Vec<(String, Finding)>,
+        now: u64,
+        path: &Path,
+    ) {
+        let known = match monitor::load(path) {
+            Ok(v) => v,
+            Err(err) => self
+                .logger
+                .fatal(format!("{}: {}\n", path.display(), err).as_ref()),
+        };
+        let fresh: Vec<(String, Notification)> = findings
+            .into_iter()
+            .map(|(key, finding)| {
+                let tag = hex::encode(pm.key_tag(&key));
+                (
+                    key,
+                    Notification {
+                        time: now,
+                        tag,
+                        finding,
+                    },
+                )
+            })
+            .filter(|(_, new)| !known.iter().any(|old| new.repeats(old)))
+            .collect();
+        if fresh.is_empty() {
+            self.logger.info(constants::NO_NEW_FINDINGS.as_ref());
+            return;
+        }
+
+        let mut desktop = true;
+        for (key, notification) in &fresh {
+            let line = format!("{}: {}", key, notification.finding);
+            self.logger.warn(format!("{}\n", line).as_ref());
+            if !desktop {
+                continue;
+            }
+            if let Err(err) = monitor::notify_desktop("mopm", &line) {
+                self.logger
+                    .warn(format!("{}{}\n", constants::CANNOT_NOTIFY, err).as_ref());
+                desktop = false;
+            }
+        }
+        let fresh: Vec<Notification> = fresh.into_iter().map(|(_, v)| v).collect();
+        if let Err(err) = monitor::append(path, &fresh) {
+            self.logger
+                .fatal(format!("{}: {}\n", path.display(), err).as_ref());
+        }
+    }
+
+    /// Lists the findings of `monitor` by key, which takes the vault, or
+    /// clears them.
+    #[cfg(feature = "monitor")]
+    fn handle_notifications(&mut self, clear: bool) {
+        let path = Storage::notifications_file().or_bug("cannot locate the notifications");
+        if clear {
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => self
+                    .logger
+                    .fatal(format!("{}: {}\n", path.display(), err).as_ref()),
+            }
+            self.logger.info(constants::NOTIFICATIONS_CLEARED.as_ref());
+            return;
+        }
+        let notifications = match monitor::load(&path) {
+            Ok(v) => v,
+            Err(err) => self
+                .logger
+                .fatal(format!("{}: {}\n", path.display(), err).as_ref()),
+        };
+        if notifications.is_empty() {
+            self.logger.info(constants::NO_NOTIFICATIONS.as_ref());
+            return;
+        }
+
+        let pm = self.get_password_manager();
+        let keys: std::collections::HashMap<String, &str> = pm
+            .keys()
+            .into_iter()
+            .map(|key| (hex::encode(pm.key_tag(key)), key))
+            .collect();
+        let mut table = Table::new(vec![
+            "time".to_string(),
+            "entry".to_string(),
+            "finding".to_string(),
+        ]);
+        for notification in notifications {
+            let key = keys.get(&notification.tag).map_or("(deleted)", |key| key);
+            table.push(vec![
+                timelock::format_time(notification.time),
+                key.to_string(),
+                notification.finding.to_string(),
+            ]);
+        }
+        for line in table.render(None) {
+            self.logger.info(format!("{}\n", line).as_ref());
+        }
+    }
+
+    #[cfg(feature = "share")]
+    fn handle_share_link(&mut self, key: &str, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::SHARE_LINK_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_SHARE_LINK_OPTION, name).as_ref());
+        }
+        let ttl = match options.get("ttl").map(|v| share::parse_ttl(v)) {
+            None => Duration::from_secs(constants::DEFAULT_SHARE_TTL),
+            Some(Some(v)) => v,
+            Some(None) => self.logger.fatal(constants::INVALID_TTL.as_ref()),
+        };
+        let mut pm = self.get_password_manager();
+        let (password, refreshed) = match pm.resolve_password(key, &Executor::default()) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        if refreshed {
+            if let Err(err) = self.save_password_manager(&mut pm) {
+                self.logger.error(&err);
+                self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+            };
+        }
+        self.run_hook(Event::Get, Self::hook_context(&pm, key));
+        let sealed = Sealed::new(key, &password);
+
+        if let Some(relay) = options.get("relay") {
+            if let Err(err) = sealed.upload(relay, ttl) {
+                self.logger.fatal(format!("{}\n", err).as_ref());
+            }
+            self.logger
+                .info(format!("{}\n", sealed.link(relay)).as_ref());
+            return;
+        }
+        let listen = options
+            .get("listen")
+            .map_or(constants::DEFAULT_SHARE_LISTEN, String::as_str);
+        let listener = match std::net::TcpListener::bind(listen) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}: {}\n", listen, err).as_ref()),
+        };
+        let addr = match listener.local_addr() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        self.logger
+            .info(format!("{}\n", sealed.link(&share::served_base(addr))).as_ref());
+        self.logger.info(constants::WAITING_FOR_RECIPIENT.as_ref());
+        self.logger.flush();
+        match sealed.serve_once(&listener, ttl) {
+            Ok(()) => self.logger.info(constants::LINK_USED.as_ref()),
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    /// The vault is unlocked before the link is used up, so a mistyped
+    /// master password does not lose the entry.
+    #[cfg(feature = "share")]
+    fn handle_receive_link(&mut self, link: &str, key: Option<&str>) {
+        let mut pm = self.get_password_manager();
+        let (name, value) = match share::receive(link) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        self.store_received(&mut pm, key.unwrap_or(&name), &value);
+    }
+
+    /// Stores an entry received from another machine, asking before it
+    /// replaces one.
+    fn store_received(
+        &mut self,
+        pm: &mut PasswordManager<DynamicEncryptor>,
+        key: &str,
+        value: &str,
+    ) {
+        if pm.keys().contains(&key) {
+            self.confirm(&format!("{}`{}`?", constants::OVERWRITE_CONFIRMATION, key));
+        }
+        let result = pm.store_password(key.into(), value);
+        self.or_fatal(result);
+        if let Err(err) = self.save_password_manager(pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        self.run_hook(Event::Store, Self::hook_context(pm, key));
+        self.logger
+            .info(format!("{}`{}`\n", constants::RECEIVED_ENTRY, key).as_ref());
+    }
+
+    /// Shows the entry as chunks to type on another machine, and the code
+    /// that opens them.
+    fn handle_handoff(&mut self, key: &str) {
+        let mut pm = self.get_password_manager();
+        let (password, refreshed) = match pm.resolve_password(key, &Executor::default()) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        if refreshed {
+            if let Err(err) = self.save_password_manager(&mut pm) {
+                self.logger.error(&err);
+                self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+            };
+        }
+        self.run_hook(Event::Get, Self::hook_context(&pm, key));
+        let sealed = match Handoff::seal(key, &password) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let mut out = String::from(constants::HANDOFF_CHUNKS);
+        for (i, chunk) in sealed.chunks.iter().enumerate() {
+            out.push_str(&format!("{:>6}  {}\n", i + 1, chunk));
+        }
+        out.push_str(constants::HANDOFF_CODE);
+        self.logger.info(out.as_ref());
+        self.logger
+            .info(format!("        {}\n", sealed.code.as_str()).as_ref());
+    }
+
+    /// The vault is unlocked before the chunks are typed, so a mistyped
+    /// master password does not waste them. A chunk that does not match
+    /// its check digit is asked for again.
+    fn handle_receive_handoff(&mut self, key: Option<&str>) {
+        let mut pm = self.get_password_manager();
+        self.logger.info(constants::HANDOFF_TYPE_CHUNKS.as_ref());
+        let mut digits = Vec::new();
+        let mut number = 1;
+        while number <= handoff::MAX_CHUNKS {
+            let line = match self
+                .interact
+                .line(&mut self.logger, &format!("{:>6}: ", number))
+            {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            };
+            if line.trim().is_empty() {
+                break;
+            }
+            match handoff::parse_chunk(number, &line) {
+                Ok(v) => {
+                    digits.extend(v);
+                    number += 1;
+                }
+                Err(err) => self.logger.warn(format!("{}\n", err).as_ref()),
+            }
+        }
+        let code = match self
+            .interact
+            .secret(&mut self.logger, constants::HANDOFF_CODE_PROMPT)
+        {
+            Ok(v) => Zeroizing::new(v),
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let (name, value) = match handoff::open(&digits, &code) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        self.store_received(&mut pm, key.unwrap_or(&name), &value);
+    }
+
+    fn handle_export(&mut self, format: Option<&str>, options: &Options) {
+        let format = format.or(options.get("format").map(String::as_str));
+        let mut pm = self.get_password_manager();
+        let result = match format {
+            None | Some("vault") => self.export_vault(&mut pm, options),
+            Some("bitwarden-json") => self.export_bitwarden(&mut pm, options),
+            #[cfg(feature = "hashivault")]
+            Some("hashivault") => self
+                .hashivault_from(options)
+                .export(&mut pm)
+                .map_err(|err| err.to_string()),
+            _ => self.logger.fatal(constants::UNKNOWN_FORMAT.as_ref()),
+        };
+        match result {
+            Ok(count) => self
+                .logger
+                .info(format!("Exported {} password(s)\n", count).as_ref()),
+            Err(err) => self.logger.fatal(err.as_ref()),
+        }
+    }
+
+    /// A vault of its own: new password, identity and salt, with the key
+    /// derivation parameters of this vault.
+    fn export_vault(
+        &mut self,
+        pm: &mut PasswordManager<DynamicEncryptor>,
+        options: &Options,
+    ) -> Result<usize, String> {
+        let keys = pm.select(
+            options.get("filter").map(String::as_str),
+            options.get("tag").map(String::as_str),
+        );
+        if keys.is_empty() {
+            return Err(constants::NOTHING_TO_EXPORT.to_string());
+        }
+        let mut export = self.new_export_vault(pm)?;
+        pm.copy_into(&keys, &mut export)
+            .map_err(|err| err.to_string())?;
+
+        let path = options
+            .get("output")
+            .map_or(constants::DEFAULT_VAULT_EXPORT, String::as_str);
+        let mut writer = Storage::get_private_writer(Path::new(path));
+        Encoder::encode(&mut writer, &mut export).map_err(|err| err.to_string())?;
+        writer.commit().map_err(|err| err.to_string())?;
+        Ok(keys.len())
+    }
+
+    /// An empty vault for entries leaving `pm`, under `export_password`.
+    fn new_export_vault(
+        &mut self,
+        pm: &PasswordManager<DynamicEncryptor>,
+    ) -> Result<PasswordManager<AESEncryptor>, String> {
+        let password = self.export_password()?;
+        let params = match pm.kdf().params() {
+            Some(v) => v,
+            None => self.calibrate(constants::DEFAULT_UNLOCK_MS).params,
+        };
+        Kdf::argon2id(params)
+            .map_err(PasswordManagerError::from)
+            .and_then(|kdf| PasswordManager::init(password.trim(), kdf))
+            .map(|v| v.with_hasher(pm.hasher_id()))
+            .map_err(|err| err.to_string())
+    }
+
+    /// A password typed twice and strong enough to travel, or a passphrase
+    /// generated when none is typed, which is shown once.
+    fn export_password(&mut self) -> Result<String, String> {
+        let password = self.prompt_password_with(constants::EXPORT_PASSWORD_PROMPT);
+        if password.trim().is_empty() {
+            let passphrase = generator::passphrase(constants::MIN_EXPORT_PASSWORD_BITS);
+            self.logger.info(
+                format!("{}{}\n", constants::GENERATED_EXPORT_PASSPHRASE, passphrase).as_ref(),
+            );
+            return Ok(passphrase);
+        }
+        let bits = strength::bits(password.trim());
+        if bits < constants::MIN_EXPORT_PASSWORD_BITS {
+            return Err(format!(
+                "The password of the exported vault is too weak (about {:.0} bits, at least {:.0} needed), leave it empty to generate one\n",
+                bits,
+                constants::MIN_EXPORT_PASSWORD_BITS
+            ));
+        }
+        if self.prompt_password_with(constants::REPEAT_PASSWORD_PROMPT) != password {
+            return Err(constants::PASSWORD_MISMATCH.to_string());
+        }
+        Ok(password)
+    }
+
+    /// Publishes the selected entries to a read-only mirror at `--out`.
+    /// An existing mirror is opened and updated in place, keeping its
+    /// password and identity, and is not rewritten when the entries it
+    /// holds did not change.
+    fn handle_publish(&mut self, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !constants::PUBLISH_OPTIONS.contains(&name.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_PUBLISH_OPTION, name).as_ref());
+        }
+        let (filter, tag) = (
+            options.get("filter").map(String::as_str),
+            options.get("tag").map(String::as_str),
+        );
+        if filter.is_none() && tag.is_none() {
+            self.logger
+                .fatal(constants::PUBLISH_WITHOUT_FILTER.as_ref());
+        }
+        let out = match options.get("out").or(self.config.out.as_ref()) {
+            Some(v) => v.clone(),
+            None => self.logger.fatal(constants::MISSING_PUBLISH_OUT.as_ref()),
+        };
+        let mut pm = self.get_password_manager();
+        let keys = pm.select(filter, tag);
+        if keys.is_empty() {
+            self.logger.fatal(constants::NOTHING_TO_EXPORT.as_ref());
+        }
+        let changed = match Path::new(&out).exists() {
+            true => {
+                let mut mirror = self.open_vault_file(&out, constants::MIRROR_PASSWORD_PROMPT);
+                if !mirror.is_mirror() {
+                    self.logger
+                        .fatal(format!("{}{}\n", constants::NOT_A_MIRROR, out).as_ref());
+                }
+                self.publish_mirror(&mut pm, &keys, &mut mirror, &out)
+            }
+            false => {
+                let mut mirror = match self.new_export_vault(&pm) {
+                    Ok(v) => v,
+                    Err(err) => self.logger.fatal(err.as_ref()),
+                };
+                self.publish_mirror(&mut pm, &keys, &mut mirror, &out)
+            }
+        };
+        match changed {
+            true => self
+                .logger
+                .info(format!("Published {} entries to {}\n", keys.len(), out).as_ref()),
+            false => self.logger.info(constants::MIRROR_UNCHANGED.as_ref()),
+        }
+    }
+
+    fn publish_mirror<U>(
+        &mut self,
+        pm: &mut PasswordManager<DynamicEncryptor>,
+        keys: &[String],
+        mirror: &mut PasswordManager<U>,
+        out: &str,
+    ) -> bool
+    where
+        U: Encryprtor + Identifiable,
+    {
+        let result = pm.publish_into(keys, mirror);
+        if !self.or_fatal(result) {
+            return false;
+        }
+        let mut writer = Storage::get_private_writer(Path::new(out));
+        let result = Encoder::encode(&mut writer, mirror)
+            .map_err(StorageError::from)
+            .and_then(|_| writer.commit());
+        if let Err(err) = result {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref());
+        }
+        true
+    }
+
+    /// Installs a published mirror as the vault of this machine. The vault
+    /// file links to it, so pulling the dotfiles updates the entries.
+    fn handle_subscribe(&mut self, path: &str) {
+        let pm = self.open_vault_file(path, constants::MIRROR_PASSWORD_PROMPT);
+        if !pm.is_mirror() {
+            self.logger
+                .fatal(format!("{}{}\n", constants::NOT_A_MIRROR, path).as_ref());
+        }
+        match Storage::subscribe(Path::new(path)) {
+            Ok(()) => self
+                .logger
+                .info(format!("{}{} entries\n", constants::SUBSCRIBED, pm.keys().len()).as_ref()),
+            Err(StorageError::RootAlreadyExistsErorr) => {
+                self.logger.fatal(constants::SUBSCRIBE_OVER_VAULT.as_ref())
+            }
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+            }
+        }
+    }
+
+    fn export_bitwarden(
+        &mut self,
+        pm: &mut PasswordManager<DynamicEncryptor>,
+        options: &Options,
+    ) -> Result<usize, String> {
+        let path = options
+            .get("output")
+            .map_or(constants::DEFAULT_BITWARDEN_EXPORT, String::as_str);
+        let export = bitwarden::export(pm).map_err(|err| err.to_string())?;
+        let mut writer = Storage::get_private_writer(Path::new(path));
+        serde_json::to_writer_pretty(&mut writer, &export).map_err(|err| err.to_string())?;
+        writer.commit().map_err(|err| err.to_string())?;
+        Ok(export["items"].as_array().map_or(0, Vec::len))
+    }
+
+    /// The worker pool of `--jobs`, one worker per CPU by default.
+    fn pool(&mut self, options: &Options) -> Pool {
+        match options.get("jobs").map(|v| v.parse()) {
+            None => Pool::default(),
+            Some(Ok(jobs)) => Pool::new(jobs),
+            Some(Err(_)) => self.logger.fatal(constants::INVALID_JOBS.as_ref()),
+        }
+    }
+
+    #[cfg(feature = "hashivault")]
+    fn hashivault_from(&mut self, options: &Options) -> HashiVault {
+        let option = |name: &str, env: &str| {
+            options
+                .get(name)
+                .cloned()
+                .or_else(|| std::env::var(env).ok())
+        };
+        let (Some(addr), Some(token)) =
+            (option("addr", "VAULT_ADDR"), option("token", "VAULT_TOKEN"))
+        else {
+            self.logger
+                .fatal(constants::MISSING_HASHIVAULT_OPTIONS.as_ref())
+        };
+        let path = options.get("path").map_or("secret/", String::as_str);
+        HashiVault::new(&addr, &token, path)
+    }
+
+    fn prompt_password(&mut self) -> String {
+        self.prompt_password_with(constants::PASSWORD_PROMPT)
+    }
+
+    fn prompt_password_with(&mut self, prompt: &str) -> String {
+        let _span = trace::span("password prompt");
+        match self.interact.password(&mut self.logger, prompt) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    fn confirm(&mut self, question: &str) {
+        match self.interact.confirm(&mut self.logger, question) {
+            Ok(true) => {}
+            Ok(false) => self.logger.fatal(constants::ABORTED.as_ref()),
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    fn get_password_manager(&mut self) -> PasswordManager<DynamicEncryptor> {
+        let vault = match self.read_vault() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        if self.config.system {
+            let key = match system::read_key(Path::new(system::KEY_FILE)) {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            };
+            let result = Encoder::decode_stored(&key, &vault, &mut self.key_cache);
+            return self.unlocked(result);
+        }
+        let installed = self.config.vault.is_none() && self.config.out.is_none();
+        self.decode_password_manager(&vault, constants::PASSWORD_PROMPT, installed)
     }
 
-    pub fn run(&mut self) {
-        if self.handle_breaking_arguments() {
-            return;
+    /// The vault given with `--vault`, else the system vault with
+    /// `--system`, `None` for the installed one.
+    fn vault_path(&self) -> Option<&str> {
+        match self.config.system {
+            true => self.config.vault.as_deref().or(Some(system::DATA_FILE)),
+            false => self.config.vault.as_deref(),
         }
+    }
 
-        let command = match self.config.command.take() {
-            None => {
-                self.logger.info(constants::NO_COMMAND_SPECIFIED.as_ref());
-                return;
+    /// The vault given with `--vault`, read from stdin for `-`, the system
+    /// vault or the installed one.
+    fn read_vault(&mut self) -> Result<StoredVault, StorageError> {
+        let mut bytes = Vec::new();
+        let vault = match self.vault_path() {
+            Some("-") => {
+                io::stdin().lock().read_to_end(&mut bytes)?;
+                StoredVault::File(bytes)
             }
-            Some(v) => v,
-        };
-
-        match command {
-            Command::Init => self.handle_init(),
-            Command::Clear => self.handle_clear(),
-            Command::Store(key, value) => {
-                self.with_init(|app| app.handle_store(key.as_ref(), value.as_ref()))
+            Some(path) => {
+                Storage::get_reader(Path::new(path))?.read_to_end(&mut bytes)?;
+                StoredVault::File(bytes)
             }
-            Command::Get(key) => self.with_init(|app| app.handle_get(key.as_ref())),
+            None => Storage::read_installed()?,
+        };
+        self.source = vault.file().unwrap_or_default().to_vec();
+        Ok(vault)
+    }
 
-            Command::Shield(v) => match v.as_str() {
-                "up" => self.with_init(|app| app.handle_shield_up()),
-                "down" => self.handle_shield_down(),
-                _ => self
-                    .logger
-                    .fatal("invalid argument, accepted: `up`, `down`".as_ref()),
-            },
+    fn open_vault_file(&mut self, path: &str, prompt: &str) -> PasswordManager<DynamicEncryptor> {
+        let mut vault = Vec::new();
+        let read =
+            Storage::get_reader(Path::new(path)).and_then(|mut v| Ok(v.read_to_end(&mut vault)?));
+        if let Err(err) = read {
+            self.logger.error(&err);
+            self.logger.fatal(constants::CANNOT_OPEN_VAULT.as_ref());
         }
+        self.decode_password_manager(&StoredVault::File(vault), prompt, false)
     }
 
-    fn handle_breaking_arguments(&mut self) -> bool {
-        if self.config.show_version {
-            self.logger
-                .info(format!("Version: {}\n", Version::current_version()).as_ref());
-            return true;
+    /// Asks for the password again after a typo, up to
+    /// `PASSWORD_ATTEMPTS` times when it is typed in. For the `installed`
+    /// vault the hint is shown once `HINT_AFTER_FAILURES` attempts failed,
+    /// and a password that does not open it may open a hidden vault.
+    fn decode_password_manager(
+        &mut self,
+        vault: &StoredVault,
+        prompt: &str,
+        installed: bool,
+    ) -> PasswordManager<DynamicEncryptor> {
+        if let Err(err @ EncoderError::NewerFormat(_)) = vault.version() {
+            self.logger.fatal(format!("{}\n", err).as_ref());
         }
-        if self.config.show_help {
-            self.logger.info(constants::HELP_MESSAGE.as_ref());
-            return true;
-        }
-        false
+        let hint = match installed {
+            true => Storage::hint().unwrap_or_default(),
+            false => None,
+        };
+        let attempts = match self.interact.can_retry_password() {
+            true => constants::PASSWORD_ATTEMPTS,
+            false => 1,
+        };
+        let mut attempt = 1;
+        let result = loop {
+            let password = self.prompt_password_with(prompt);
+            let result = self
+                .vault_key(&password, installed)
+                .and_then(|key| Encoder::decode_stored(&key, vault, &mut self.key_cache));
+            #[cfg(feature = "hidden-volume")]
+            let result = match (result, vault) {
+                (Err(EncoderError::IvalidKeyError), StoredVault::File(vault)) if installed => {
+                    match hidden::open(vault, password.trim().as_ref()) {
+                        Some((pm, key)) => {
+                            self.hidden = Some(key);
+                            Ok(pm)
+                        }
+                        None => Err(EncoderError::IvalidKeyError),
+                    }
+                }
+                (result, _) => result,
+            };
+            match result {
+                Err(EncoderError::IvalidKeyError) if attempt < attempts => {
+                    self.logger.warn(constants::WRONG_PASSWORD.as_ref());
+                    if let Some(hint) = hint
+                        .as_deref()
+                        .filter(|_| attempt == constants::HINT_AFTER_FAILURES)
+                    {
+                        self.logger
+                            .info(format!("{}{}\n", constants::HINT_PREFIX, hint).as_ref());
+                    }
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        self.unlocked(result)
     }
 
-    fn handle_init(&mut self) {
-        if Storage::is_initialized().unwrap() {
-            self.logger.warn(constants::ALREADY_INITIALIZED.as_ref());
-            return;
-        }
+    fn decode_with_password(
+        &mut self,
+        vault: &StoredVault,
+        password: &str,
+    ) -> PasswordManager<DynamicEncryptor> {
+        let result = self
+            .vault_key(password, self.vault_path().is_none())
+            .and_then(|key| Encoder::decode_stored(&key, vault, &mut self.key_cache));
+        self.unlocked(result)
+    }
 
-        let password = self.prompt_password();
-        let mut pm = PasswordManager::init(password.trim());
+    /// What the vault is encrypted with for `password`: for the installed
+    /// vault bound to the TPM, the share its key slot holds for the password
+    /// followed by the sealed secret.
+    fn vault_key(
+        &mut self,
+        password: &str,
+        installed: bool,
+    ) -> Result<Zeroizing<Vec<u8>>, EncoderError> {
+        #[cfg(feature = "tpm")]
+        if let Some((slots, secret)) = installed.then(|| self.tpm()).flatten() {
+            return match slots.open(password.trim()) {
+                Ok(share) => Ok(tpm::bind(&share, &secret)),
+                Err(tpm::TpmError::WrongPassword) => Err(EncoderError::IvalidKeyError),
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            };
+        }
+        #[cfg(not(feature = "tpm"))]
+        let _ = installed;
+        Ok(Zeroizing::new(password.trim().as_bytes().to_vec()))
+    }
 
-        match Storage::init(&mut pm) {
-            Ok(_) => self.logger.info(constants::INIT_SUCCESSFULL.as_ref()),
-            Err(StorageError::RootAlreadyExistsErorr) => {}
-            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+    /// The key slots of the installed vault and the secret sealed to the
+    /// TPM, `None` when it is not bound. The secret is unsealed once, or
+    /// taken from the recovery code when the TPM refuses, e.g. after a
+    /// firmware update changed the PCRs.
+    #[cfg(feature = "tpm")]
+    fn tpm(&mut self) -> Option<(tpm::Slots, Zeroizing<Vec<u8>>)> {
+        if self.tpm.is_none() {
+            self.tpm = Some(self.unseal_tpm_secret());
         }
+        self.tpm.clone().flatten()
     }
 
-    fn handle_clear(&mut self) {
-        match Storage::clear() {
-            Ok(_) => {
-                self.logger.info(constants::CLEAR_SUCCESSFUL.as_ref());
+    #[cfg(feature = "tpm")]
+    fn unseal_tpm_secret(&mut self) -> Option<(tpm::Slots, Zeroizing<Vec<u8>>)> {
+        let path = Storage::tpm_file().or_bug("cannot locate the storage");
+        let text = match std::fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => self
+                .logger
+                .fatal(format!("{}: {}\n", path.display(), err).as_ref()),
+        };
+        let slots = match tpm::Slots::parse(&text) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let generation = Storage::read_installed()
+            .map_err(|err| err.to_string())
+            .and_then(|v| v.generation().map_err(|err| err.to_string()));
+        match generation {
+            Ok(v) if v < slots.generation => {
+                self.logger.warn(constants::TPM_ENROLL_INCOMPLETE.as_ref());
+                return None;
             }
-            Err(StorageError::RootDoesNotExistErorr) => {
-                self.logger.info(constants::NOT_INITIALIZED.as_ref());
+            Ok(_) => {}
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+        let secret = match slots.sealed.unseal() {
+            Ok(v) => v,
+            Err(err) => {
+                self.logger.warn(format!("{}\n", err).as_ref());
+                let code = self.prompt_password_with(constants::TPM_RECOVERY_PROMPT);
+                match slots.recover(&code) {
+                    Ok(v) => v,
+                    Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+                }
             }
+        };
+        Some((slots, secret))
+    }
+
+    /// Binds the installed vault to the TPM: it is re-encrypted with a new
+    /// key, whose shares are kept in key slots for the password and for
+    /// the TPM, sealed to the PCRs of `--pcrs`. A vault already bound keeps
+    /// its key, the secret sealed again to the current values of the PCRs,
+    /// e.g. after a firmware update.
+    #[cfg(feature = "tpm")]
+    fn handle_tpm_enroll(&mut self, options: &Options) {
+        if let Some(name) = options
+            .keys()
+            .find(|v| !constants::TPM_OPTIONS.contains(&v.as_str()))
+        {
+            self.logger
+                .fatal(format!("{}{}\n", constants::UNKNOWN_TPM_OPTION, name).as_ref());
+        }
+        if self.vault_path().is_some() {
+            self.logger.fatal(constants::TPM_INSTALLED_ONLY.as_ref());
+        }
+        let pcrs = options
+            .get("pcrs")
+            .map_or(tpm::DEFAULT_PCRS, String::as_str);
+        if let Err(err) = tpm::check_pcrs(pcrs) {
+            self.logger.fatal(format!("{}\n", err).as_ref());
+        }
+        let path = Storage::tpm_file().or_bug("cannot locate the storage");
+        let vault = match self.read_vault() {
+            Ok(v) => v,
             Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        let password = self.prompt_password();
+        let mut pm = self.decode_with_password(&vault, &password);
+        if let Some((mut slots, secret)) = self.tpm() {
+            slots.sealed = match tpm::Sealed::seal(&secret, pcrs) {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            };
+            self.write_slots(&slots, &path);
+            self.logger.info(constants::TPM_RESEALED.as_ref());
+            return;
         }
-    }
+        self.confirm(constants::TPM_CONFIRMATION);
 
-    fn handle_store(&mut self, key: &str, value: &str) {
-        let mut pm = self.get_password_manager();
-        pm.store_password(key.into(), value).unwrap();
+        // Written first: a vault bound to slots that were not kept could
+        // not be opened again. The vault saved next is one generation up.
+        let params = self.kdf_params(&pm);
+        let enrollment =
+            match tpm::Slots::enroll(password.trim(), pcrs, params, pm.generation() + 1) {
+                Ok(v) => v,
+                Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+            };
+        self.write_slots(&enrollment.slots, &path);
+        self.rekey(&mut pm, params, &enrollment.key);
         if let Err(err) = self.save_password_manager(&mut pm) {
+            let _ = std::fs::remove_file(&path);
             self.logger.error(&err);
             self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
         };
-        self.logger.info(constants::STORE_SUCCESSFUL.as_ref());
+        // The backups, including the one taken by this save, still open
+        // with the password alone.
+        match Storage::remove_backups() {
+            Ok(0) => {}
+            Ok(n) => self
+                .logger
+                .info(format!("{}{}\n", constants::TPM_BACKUPS_REMOVED, n).as_ref()),
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger
+                    .warn(constants::TPM_BACKUPS_NOT_REMOVED.as_ref())
+            }
+        }
+        self.logger
+            .info(format!("{}{}\n", constants::TPM_ENROLLED, *enrollment.recovery_code).as_ref());
     }
 
-    fn handle_get(&mut self, key: &str) {
-        let mut pm = self.get_password_manager();
-        self.logger.info(pm.get_password(key).unwrap().as_ref());
+    /// Changes the password of the installed vault bound to the TPM. Only
+    /// its key slot is written again, the vault keeps its key.
+    #[cfg(feature = "tpm")]
+    fn handle_tpm_password(&mut self) {
+        if self.vault_path().is_some() {
+            self.logger.fatal(constants::TPM_INSTALLED_ONLY.as_ref());
+        }
+        let path = Storage::tpm_file().or_bug("cannot locate the storage");
+        let vault = match self.read_vault() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        let password = self.prompt_password();
+        let pm = self.decode_with_password(&vault, &password);
+        let Some((mut slots, _)) = self.tpm() else {
+            self.logger.fatal(constants::TPM_NOT_ENROLLED.as_ref())
+        };
+        let share = match slots.open(password.trim()) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+        let new_password = self.prompt_password_with(constants::TPM_NEW_PASSWORD_PROMPT);
+        if new_password.trim().is_empty() {
+            self.logger.fatal(constants::TPM_EMPTY_PASSWORD.as_ref());
+        }
+        if self.prompt_password_with(constants::REPEAT_PASSWORD_PROMPT) != new_password {
+            self.logger.fatal(constants::PASSWORD_MISMATCH.as_ref());
+        }
+        let params = self.kdf_params(&pm);
+        if let Err(err) = slots.set_password(&share, new_password.trim(), params) {
+            self.logger.fatal(format!("{}\n", err).as_ref());
+        }
+        self.write_slots(&slots, &path);
+        self.logger.info(constants::TPM_PASSWORD_CHANGED.as_ref());
     }
 
-    fn prompt_password(&mut self) -> String {
-        self.logger.info(constants::PASSWORD_PROMPT.as_ref());
-        self.logger.flush();
-        Terminal::read_password()
+    #[cfg(feature = "tpm")]
+    fn write_slots(&mut self, slots: &tpm::Slots, path: &Path) {
+        let mut writer = Storage::get_private_writer(path);
+        let result = io::Write::write_all(&mut writer, slots.to_text().as_bytes())
+            .map_err(StorageError::from)
+            .and_then(|_| writer.commit());
+        if let Err(err) = result {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        }
     }
 
-    fn get_password_manager(&mut self) -> PasswordManager<DynamicEncryptor> {
-        let password = self.prompt_password();
-        let mut pm_reader = match Storage::get_data_reader() {
+    /// Re-encrypts the installed vault with the password alone.
+    #[cfg(feature = "tpm")]
+    fn handle_tpm_remove(&mut self) {
+        if self.vault_path().is_some() {
+            self.logger.fatal(constants::TPM_INSTALLED_ONLY.as_ref());
+        }
+        let path = Storage::tpm_file().or_bug("cannot locate the storage");
+        if !path.exists() {
+            self.logger.fatal(constants::TPM_NOT_ENROLLED.as_ref());
+        }
+        let vault = match self.read_vault() {
             Ok(v) => v,
             Err(err) => self.logger.fatal(err.to_string().as_ref()),
         };
-        match Encoder::decode(password.trim().as_ref(), &mut pm_reader) {
-            Ok(v) => v,
+        let password = self.prompt_password();
+        let mut pm = self.decode_with_password(&vault, &password);
+        let params = self.kdf_params(&pm);
+        self.rekey(&mut pm, params, password.trim().as_bytes());
+        if let Err(err) = self.save_password_manager(&mut pm) {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref())
+        };
+        if let Err(err) = std::fs::remove_file(&path) {
+            self.logger
+                .fatal(format!("{}: {}, remove it by hand\n", path.display(), err).as_ref());
+        }
+        self.logger.info(constants::TPM_REMOVED.as_ref());
+    }
+
+    fn unlocked(
+        &mut self,
+        result: Result<PasswordManager<DynamicEncryptor>, EncoderError>,
+    ) -> PasswordManager<DynamicEncryptor> {
+        let pm = match result {
+            Ok(v) => v.with_guard(Box::new(PromptGuard::new(
+                Interact::new(self.config.assume_yes, self.config.non_interactive)
+                    .password_source(self.config.password_source.build()),
+            ))),
             Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+
+        let last_device = pm.last_device();
+        if !identity::is_unset(last_device) && *last_device != identity::current_device_id() {
+            self.logger.warn(constants::DIFFERENT_DEVICE.as_ref());
+        }
+        if let Some(reason) = pm.version().deprecation() {
+            self.logger.warn(
+                format!(
+                    "The vault is stored in the deprecated format {} ({}), it is upgraded when saved\n",
+                    pm.version(),
+                    reason
+                )
+                .as_ref(),
+            );
+        }
+        let ignored = pm.capabilities().ignored();
+        if !ignored.is_empty() {
+            self.logger.warn(
+                format!(
+                    "The vault uses features this version of mopm ignores ({}), they are dropped when it is saved\n",
+                    ignored.names().join(", ")
+                )
+                .as_ref(),
+            );
         }
+        pm
     }
 
     fn save_password_manager<U>(
-        &self,
+        &mut self,
         password_manager: &mut PasswordManager<U>,
-    ) -> Result<(), EncoderError>
+    ) -> Result<(), StorageError>
     where
         U: Encryprtor + Identifiable,
     {
-        let mut writer = Storage::get_data_writer().unwrap();
-        Encoder::encode(&mut writer, password_manager)
+        // A vault given with `--vault` is written back in place, the system
+        // vault like the installed one.
+        let result = match (self.config.out.as_deref(), self.vault_path()) {
+            (None, Some("-")) => self.logger.fatal(constants::NO_VAULT_OUTPUT.as_ref()),
+            (Some(path), vault) => {
+                let in_place = vault.is_some_and(|v| is_same_file(v, path));
+                Storage::save_to(password_manager, &self.source, path, in_place)
+            }
+            (None, Some(path)) if is_same_file(path, system::DATA_FILE) => {
+                Storage::save_file(password_manager, Path::new(path), &self.settings.backups)
+            }
+            (None, Some(path)) => Storage::save_to(password_manager, &self.source, path, true),
+            #[cfg(feature = "hidden-volume")]
+            (None, None) => match &self.hidden {
+                Some(key) => Storage::save_hidden(password_manager, key),
+                None => Storage::save(password_manager, &self.settings.backups),
+            },
+            #[cfg(not(feature = "hidden-volume"))]
+            (None, None) => Storage::save(password_manager, &self.settings.backups),
+        };
+        match result {
+            Err(err @ StorageError::ConflictError { .. }) => {
+                self.logger.error(&err);
+                self.logger.fatal(constants::SAVE_CONFLICT.as_ref())
+            }
+            result => result,
+        }
+    }
+
+    fn or_fatal<V>(&mut self, result: Result<V, PasswordManagerError>) -> V {
+        match result {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    /// Time locks and expiry dates are checked against the system clock,
+    /// which cannot be right if it is behind changes already made.
+    fn warn_clock_skew<U: Encryprtor>(&mut self, pm: &PasswordManager<U>) {
+        let latest = pm.last_modified();
+        if clock::is_behind(latest) {
+            self.logger.warn(
+                format!(
+                    "{}{}, time locks and expiry dates may be off\n",
+                    constants::CLOCK_BEHIND,
+                    timelock::format_time(latest)
+                )
+                .as_ref(),
+            );
+        }
+    }
+
+    fn hook_context<'a, U>(pm: &PasswordManager<U>, key: &'a str) -> Context<'a>
+    where
+        U: Encryprtor,
+    {
+        Context {
+            key: Some(key),
+            vault_id: Some(identity::format_id(pm.vault_id())),
+            message: None,
+        }
+    }
+
+    /// The `--message` goes to the hooks of changes only, reads have none.
+    fn run_hook(&mut self, event: Event, mut context: Context) {
+        if event != Event::Get {
+            context.message = self.config.message.clone();
+        }
+        let Err(err) = self.settings.hooks.run(event, &context) else {
+            return;
+        };
+        match self.settings.hooks.failure() {
+            FailurePolicy::Ignore => {}
+            FailurePolicy::Warn => self.logger.warn(format!("{}\n", err).as_ref()),
+            FailurePolicy::Abort => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
     }
 
     fn with_init(&mut self, f: impl FnOnce(&mut Self)) {
-        if !Storage::is_initialized().unwrap() {
+        if self.config.vault.is_some() {
+            f(self);
+        } else if self.config.system && !system::is_initialized() {
+            self.logger
+                .fatal(constants::SYSTEM_NOT_INITIALIZED.as_ref());
+        } else if !self.config.system
+            && !Storage::is_initialized().or_bug("cannot locate the storage")
+        {
             self.logger.fatal(constants::NOT_INITIALIZED.as_ref());
         } else {
             f(self);
@@ -159,6 +3876,7 @@ where
     }
 
     fn handle_shield_up(&mut self) {
+        let incidents = self.incident_log();
         if let Err(err) = Storage::create_dummy() {
             self.logger.error(&err);
             self.logger.fatal("Cannot create dummy directory".as_ref());
@@ -207,9 +3925,14 @@ where
             }
         }
 
+        let pid_file = Storage::shield_pid_file().or_bug("cannot locate the shield pid file");
+        if let Err(err) = Watcher::register(&pid_file) {
+            self.logger.error(&err);
+            self.logger.warn(constants::WATCHER_NOT_RECORDED.as_ref());
+        }
         self.logger
             .info("The shield is now up! Waiting for honeypot changes...\n".as_ref());
-        let mut inotify = Inotify::init().unwrap();
+        let mut inotify = Inotify::init().or_bug("cannot initialize inotify");
         'outer: loop {
             inotify
                 .watches()
@@ -217,43 +3940,403 @@ where
                     format!("{}", honeypot_file.to_string_lossy()),
                     WatchMask::OPEN,
                 )
-                .unwrap();
+                .or_bug("cannot watch the honeypot file");
 
             let mut buffer = [0; 1024];
-            let events = inotify.read_events_blocking(&mut buffer).unwrap();
-            for _ in events {
+            let events = inotify
+                .read_events_blocking(&mut buffer)
+                .or_bug("cannot read inotify events");
+            if events.into_iter().next().is_some() {
+                let record = incident::capture(&honeypot_file, "open");
                 self.logger.info(
                     "The honeypot file has been touched! Triggering self-destruct\n".as_ref(),
                 );
-                self.handle_shield_down();
-                std::thread::sleep(std::time::Duration::from_millis(1000));
-                self.handle_clear();
+                if let Err(err) = incidents.append(&record) {
+                    self.logger.error(&err);
+                    self.logger.warn(constants::INCIDENT_NOT_RECORDED.as_ref());
+                }
+                if let Err(err) = self.lower_shield() {
+                    self.logger.error(&err);
+                    self.logger.warn(constants::CANNOT_LOWER_SHIELD.as_ref());
+                }
+                let _ = Watcher::unregister(&pid_file);
+                self.wipe_storage();
                 self.logger.info("All files have been deleted\n".as_ref());
                 break 'outer;
             }
         }
     }
 
-    fn handle_shield_down(&mut self) {
-        let root_dir = match Storage::root() {
-            Ok(data) => data,
-            Err(err) => {
+    /// Creates an empty hidden vault in the slack of the installed vault,
+    /// replacing whatever the slack held. The outer vault is saved first if
+    /// it has no slack yet.
+    #[cfg(feature = "hidden-volume")]
+    fn handle_hidden_create(&mut self) {
+        let mut pm = self.get_password_manager();
+        if self.hidden.is_some() {
+            self.logger.fatal(constants::HIDDEN_FROM_HIDDEN.as_ref());
+        }
+        if !pm.has_slack() {
+            if let Err(err) = self.save_password_manager(&mut pm) {
                 self.logger.error(&err);
-                self.logger.fatal("Cannot get root path".as_ref());
+                self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref());
             }
+        }
+        let params = match pm.kdf().params() {
+            Some(v) => v,
+            None => self
+                .logger
+                .fatal(format!("{}\n", hidden::HiddenError::NoKdfParams).as_ref()),
         };
+        self.confirm(constants::HIDDEN_CONFIRMATION);
 
-        let command = std::process::Command::new("umount")
-            .arg(format!("{}", root_dir.to_string_lossy()))
-            .spawn();
+        let password = self.prompt_password_with(constants::HIDDEN_PASSWORD_PROMPT);
+        if password.trim().is_empty() {
+            self.logger.fatal(constants::EMPTY_HIDDEN_PASSWORD.as_ref());
+        }
+        if self.prompt_password_with(constants::REPEAT_PASSWORD_PROMPT) != password {
+            self.logger.fatal(constants::PASSWORD_MISMATCH.as_ref());
+        }
+        let opens_outer = Storage::read_installed().is_ok_and(|vault| {
+            Encoder::decode_stored(password.trim().as_ref(), &vault, &mut self.key_cache).is_ok()
+        });
+        if opens_outer {
+            self.logger
+                .fatal(constants::HIDDEN_PASSWORD_IS_OUTER.as_ref());
+        }
 
-        match command {
-            Ok(_) => {}
+        let result = HiddenKey::new(password.trim().as_ref(), params)
+            .and_then(|key| hidden::create(&key))
+            .map_err(StorageError::from)
+            .and_then(|slack| Storage::replace_slack(&slack));
+        if let Err(err) = result {
+            self.logger.error(&err);
+            self.logger.fatal(constants::ERROR_WHILE_SAVING.as_ref());
+        }
+        self.logger.info(constants::HIDDEN_CREATED.as_ref());
+    }
+
+    /// Derives the key for incident records while the vault is still
+    /// reachable, checking the password against it.
+    fn incident_log(&mut self) -> IncidentLog {
+        let path = Storage::incident_log().or_bug("cannot locate the incident log");
+        let vault = match Storage::read_installed() {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(err.to_string().as_ref()),
+        };
+        let password = self.prompt_password();
+        let pm = self.decode_with_password(&vault, &password);
+        let params = self.kdf_params(&pm);
+        match IncidentLog::new(path, password.trim(), params) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        }
+    }
+
+    fn handle_incidents(&mut self) {
+        let path = Storage::incident_log().or_bug("cannot locate the incident log");
+        if !path.exists() {
+            self.logger.info(constants::NO_INCIDENTS.as_ref());
+            return;
+        }
+        let password = self.prompt_password();
+        let incidents = match incident::read(&path, password.trim()) {
+            Ok(v) => v,
+            Err(err) => self.logger.fatal(format!("{}\n", err).as_ref()),
+        };
+
+        for (i, incident) in incidents.iter().enumerate() {
+            if !incident.chained {
+                self.logger.warn(
+                    format!("Record {} does not follow the one before it, records may have been removed\n", i + 1)
+                        .as_ref(),
+                );
+            }
+            let record = &incident.record;
+            self.logger.info(
+                format!(
+                    "{}  {} {}\n",
+                    timelock::format_time(record["time"].as_u64().unwrap_or_default()),
+                    record["event"].as_str().unwrap_or("?"),
+                    record["path"].as_str().unwrap_or("?"),
+                )
+                .as_ref(),
+            );
+            let processes = record["processes"]
+                .as_array()
+                .map_or(&[][..], Vec::as_slice);
+            if processes.is_empty() {
+                self.logger
+                    .info("  no process held the file open\n".as_ref());
+            }
+            for process in processes {
+                let cmdline: Vec<&str> = process["cmdline"].as_array().map_or(vec![], |v| {
+                    v.iter().filter_map(serde_json::Value::as_str).collect()
+                });
+                self.logger.info(
+                    format!(
+                        "  pid {} ({}) uid {} ppid {}: {}\n",
+                        process["pid"].as_str().unwrap_or("?"),
+                        process["comm"].as_str().unwrap_or("?"),
+                        process["uid"].as_str().unwrap_or("?"),
+                        process["ppid"].as_str().unwrap_or("?"),
+                        cmdline.join(" "),
+                    )
+                    .as_ref(),
+                );
+            }
+        }
+    }
+
+    fn handle_shield_status(&mut self) {
+        let root_dir = Storage::root().or_bug("cannot locate the root");
+        let dummy = Storage::dummy().or_bug("cannot locate the dummy directory");
+        let pid_file = Storage::shield_pid_file().or_bug("cannot locate the shield pid file");
+        let incidents = Storage::incident_log().or_bug("cannot locate the incident log");
+        let secs = |time: std::time::SystemTime| {
+            time.duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        };
+
+        let mounted = match shield::is_mount_point(&root_dir) {
+            Ok(v) => v,
             Err(err) => {
                 self.logger.error(&err);
-                self.logger.fatal("Cannot mount overlayfs".as_ref());
+                self.logger.fatal("Cannot read the mount table\n".as_ref());
+            }
+        };
+        self.logger.info(
+            match mounted {
+                true => format!("Mount:        active on {}\n", root_dir.display()),
+                false => "Mount:        inactive\n".to_string(),
+            }
+            .as_ref(),
+        );
+
+        let decoys = shield::decoys(&dummy).unwrap_or_default();
+        let decoys: Vec<_> = decoys.iter().map(|v| v.display().to_string()).collect();
+        self.logger.info(
+            match decoys.is_empty() {
+                true => "Decoys:       none\n".to_string(),
+                false => format!("Decoys:       {}\n", decoys.join(", ")),
+            }
+            .as_ref(),
+        );
+
+        let watcher = Watcher::load(&pid_file).ok().flatten();
+        let alive = watcher.as_ref().is_some_and(Watcher::is_alive);
+        self.logger.info(
+            match &watcher {
+                Some(v) if alive => format!(
+                    "Watcher:      pid {}, since {}\n",
+                    v.pid,
+                    timelock::format_time(secs(v.since))
+                ),
+                Some(v) => format!("Watcher:      not running (stale pid {})\n", v.pid),
+                None => "Watcher:      not running\n".to_string(),
+            }
+            .as_ref(),
+        );
+
+        let triggered = std::fs::metadata(&incidents)
+            .and_then(|v| v.modified())
+            .ok();
+        self.logger.info(
+            match triggered {
+                Some(v) => format!("Last trigger: {}\n", timelock::format_time(secs(v))),
+                None => "Last trigger: never\n".to_string(),
+            }
+            .as_ref(),
+        );
+
+        if mounted && !alive {
+            self.logger.warn(constants::STALE_SHIELD.as_ref());
+        }
+    }
+
+    /// Tears down what a shield left behind. The watcher goes first, since
+    /// removing the decoys it watches would trigger it.
+    fn handle_shield_reset(&mut self) {
+        let root_dir = Storage::root().or_bug("cannot locate the root");
+        let dummy = Storage::dummy().or_bug("cannot locate the dummy directory");
+        let pid_file = Storage::shield_pid_file().or_bug("cannot locate the shield pid file");
+
+        let watcher = Watcher::load(&pid_file).ok().flatten();
+        if let Some(watcher) = watcher.filter(Watcher::is_alive) {
+            let killed = std::process::Command::new("kill")
+                .arg(watcher.pid.to_string())
+                .status();
+            if !killed.is_ok_and(|v| v.success()) {
+                self.logger
+                    .fatal(format!("Cannot stop the watching process {}\n", watcher.pid).as_ref());
+            }
+        }
+        if let Err(err) = Watcher::unregister(&pid_file) {
+            self.logger.error(&err);
+        }
+
+        if shield::is_mount_point(&root_dir).unwrap_or_default() {
+            let unmounted = std::process::Command::new("umount").arg(&root_dir).output();
+            match unmounted {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => {
+                    self.logger.info(format!("{}\n", output.status).as_ref());
+                    self.logger.fatal("Cannot unmount directory\n".as_ref());
+                }
+                Err(err) => {
+                    self.logger.error(&err);
+                    self.logger.fatal("Cannot unmount directory\n".as_ref());
+                }
             }
         }
+
+        let result = match std::fs::remove_dir_all(&dummy) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Storage::create_dummy(),
+        };
+        if let Err(err) = result {
+            self.logger.error(&err);
+            self.logger.fatal("Cannot recreate the decoys\n".as_ref());
+        }
+        self.logger.info(constants::SHIELD_RESET.as_ref());
+    }
+
+    fn handle_shield_down(&mut self) {
+        if let Err(err) = self.lower_shield() {
+            self.logger.error(&err);
+            self.logger.fatal(constants::CANNOT_LOWER_SHIELD.as_ref());
+        }
         self.logger.info("The shield is now down!\n".as_ref());
     }
+
+    /// Unmounts the shield and waits for `umount`, so that whatever runs
+    /// next sees the real storage.
+    fn lower_shield(&mut self) -> io::Result<()> {
+        let root_dir = Storage::root().map_err(io::Error::other)?;
+        let status = std::process::Command::new("umount")
+            .arg(&root_dir)
+            .status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(io::Error::other(format!("umount: {}", status))),
+        }
+    }
+
+    /// Locks the running ssh agent, which keeps its keys in memory.
+    #[cfg(feature = "ssh-agent")]
+    fn lock_ssh_agent(&mut self) {
+        let socket = match Storage::ssh_agent_socket() {
+            Ok(v) if v.exists() => v,
+            _ => return,
+        };
+        match crate::interop::sshagent::lock(&socket) {
+            Ok(_) => self.logger.info(constants::SSH_AGENT_LOCKED.as_ref()),
+            Err(err) => {
+                self.logger.error(&err);
+                self.logger.warn(constants::CANNOT_LOCK_SSH_AGENT.as_ref());
+            }
+        }
+    }
+}
+
+/// Terraform shows what a failing external program wrote to stderr.
+fn terraform_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
+fn describe_leak(leak: &Leak) -> String {
+    match leak {
+        Leak::VaultSecret(key) => format!("the password of `{}`", key),
+        Leak::Pattern(name) => format!("a possible {}", name),
+    }
+}
+
+fn change_marker(change: Change) -> (char, term::color::Color) {
+    match change {
+        Change::Added => ('+', term::color::GREEN),
+        Change::Removed => ('-', term::color::RED),
+        Change::Changed => ('~', term::color::YELLOW),
+    }
+}
+
+/// Both sides of a conflict next to each other, passwords masked.
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+fn conflict_table(conflict: &Conflict) -> Vec<String> {
+    let mut table = Table::new(vec![
+        String::new(),
+        "mine".to_string(),
+        "theirs".to_string(),
+    ]);
+    let row = |name: &str, cell: fn(&Side) -> String| {
+        vec![
+            name.to_string(),
+            cell(&conflict.mine),
+            cell(&conflict.theirs),
+        ]
+    };
+    table.push(row("password", |v| {
+        format!("{} ({} chars)", "*".repeat(v.length.min(8)), v.length)
+    }));
+    table.push(row("modified", |v| {
+        v.modified
+            .map_or("(imported)".to_string(), timelock::format_time)
+    }));
+    table.push(row("fields", |v| match v.fields.is_empty() {
+        true => "(none)".to_string(),
+        false => v.fields.join(", "),
+    }));
+    table.render(None)
+}
+
+fn format_kdf_params(params: &KdfParams) -> String {
+    format!(
+        "memory {} MiB, iterations {}, parallelism {}",
+        params.memory_kib / 1024,
+        params.iterations,
+        params.parallelism
+    )
+}
+
+/// The keys listed in the file at `path`, or stdin for `-`, one per line;
+/// blank lines and `#` comments are skipped.
+fn read_key_file(path: &str) -> io::Result<Vec<String>> {
+    let text = match path {
+        "-" => io::read_to_string(io::stdin())?,
+        path => std::fs::read_to_string(path)?,
+    };
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Whether `a` and `b` name the same file, false if either is missing.
+fn is_same_file(a: &str, b: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+fn describe_schema(schema: &Schema) -> String {
+    let policies = schema.policies();
+    format!(
+        "{} fields; templates: {}; policies: {}",
+        schema.fields().len(),
+        schema.templates().join(", "),
+        match policies.is_empty() {
+            true => "none".to_string(),
+            false => policies.join(", "),
+        }
+    )
 }
@@ -1,17 +1,792 @@
+pub const DEFAULT_UNLOCK_MS: u64 = 500;
+pub const CALIBRATING: &str = "Calibrating the key derivation for this machine...\n";
+pub const KDF_APPLIED: &str =
+    "The vault has been re-encrypted with the new key derivation parameters\n";
 pub const INIT_SUCCESSFULL: &str = "The momp storage has been successfully initialized!\n";
 pub const PASSWORD_PROMPT: &str = "Enter your password: ";
 pub const ALREADY_INITIALIZED: &str =
     "The mopm storage has already been initialized. Cannot initialize it one more time\n";
 pub const STORE_SUCCESSFUL: &str = "Suceessfuly stored the password\n";
+pub const SESSION_STORED: &str =
+    "Stored the password for this login session, it is never written to the vault\n";
+pub const SESSION_WITH_FIELDS: &str = "Session entries have no fields, store only a value\n";
+pub const SESSION_KEY_IN_VAULT: &str =
+    "This key is stored in the vault, session entries cannot shadow it\n";
+pub const SESSION_FORGOTTEN: &str = "Forgot the session entries\n";
+#[cfg(feature = "ssh-agent")]
+pub const SSH_AGENT_LOCKED: &str = "Locked the ssh agent\n";
+pub const NO_SESSION: &str = "There are no session entries\n";
+pub const TOUCH_CREATED: &str = "Reserved the key with an empty placeholder\n";
+pub const NO_HISTORY: &str = "The entry has no previous values\n";
+pub const NO_SUCH_VERSION: &str = "The entry has fewer previous values, see `history <key>`\n";
+pub const INVALID_HISTORY_NUMBER: &str =
+    "invalid argument, expected the number of a previous value\n";
+/// The exit status of `exists` for a missing key.
+pub const EXIT_MISSING: i32 = 4;
+pub const CLOCK_BEHIND: &str =
+    "Warning: the system clock is behind the last change of the vault, made at ";
+pub const ROTATE_OPTIONS: [&str; 2] = ["profile", "length"];
+pub const UNKNOWN_ROTATE_OPTION: &str =
+    "Unknown option, expected one of --profile, --length, --copy, got: ";
+pub const GENERATE_OPTIONS: [&str; 2] = ["profile", "length"];
+pub const UNKNOWN_GENERATE_OPTION: &str =
+    "Unknown option, expected one of --profile, --length, got: ";
+pub const EMPTY_GENERATE_LENGTH: &str = "--length must be at least 1 character\n";
+pub const CANNOT_ROTATE_DYNAMIC: &str =
+    "This entry is a leased secret, its command produces the passwords\n";
+pub const ROTATE_HOOK_FAILED: &str = "The password was not changed, ";
+pub const CANNOT_COPY_ROTATED: &str =
+    "The password was rotated but cannot be copied, print it with `mopm get`\n";
+pub const ROTATED_AND_COPIED: &str = "Rotated the password and copied it to the clipboard\n";
+pub const LEASE_SUCCESSFUL: &str = "Successfully stored the lease command\n";
+pub const DEFAULT_LEASE_TTL: u64 = 300;
+pub const CANNOT_EDIT_DYNAMIC: &str =
+    "This entry is a leased secret, change its command with `mopm lease`\n";
+pub const NO_TMPFS: &str =
+    "No tmpfs is available for the temporary file, pass --insecure-tmp to use the regular temporary directory\n";
+pub const NOTHING_CHANGED: &str = "Nothing changed\n";
+pub const NOTHING_TO_REFACTOR: &str = "No entry matches, nothing to change\n";
+pub const REFACTOR_CONFIRMATION: &str = "Apply these changes?";
+pub const STORE_OVER_CONFIRMATION: &str = "Store over it?";
+pub const PASSWORD_REUSED: &str = "Warning: this password is already used by: ";
+/// Entries not changed for this long are reported by `audit --summary`.
+pub const STALE_DAYS: u64 = 365;
+pub const STALE_CATEGORY: &str = "unchanged for a year";
+pub const UNROTATED_CATEGORY: &str = "imported, never rotated";
+pub const NO_REUSE_FOUND: &str = "No reused passwords found\n";
+pub const DELETE_CONFIRMATION: &str = "Delete ";
+pub const CLEAR_CONFIRMATION: &str = "Clear the whole mopm storage?";
+pub const ABORTED: &str = "Aborted\n";
+pub const DELETE_SUCCESSFUL: &str = "Successfully deleted the password\n";
+pub const TOMBSTONE_RETENTION_DAYS: u64 = 90;
 pub const CLEAR_SUCCESSFUL: &str = "The momp storage has been cleared. All data is lost\n";
+pub const CLEAR_TRASHED: &str =
+    "The mopm storage has been moved to the trash and is purged in 7 days. \
+Restore it with `mopm clear --undo`, or purge it now with `mopm clear --now`\n";
+pub const TRASH_PURGED: &str = "The trash has been purged\n";
+pub const CANNOT_PURGE_TRASH: &str = "Cannot purge the trash\n";
+pub const CLEAR_UNDONE: &str = "The mopm storage has been restored from the trash\n";
+pub const NOTHING_TO_UNDO: &str = "There is no cleared storage to restore\n";
+pub const STORAGE_IN_THE_WAY: &str =
+    "A mopm storage exists, clear it or move it away before restoring the previous one\n";
 pub const NOT_INITIALIZED: &str =
     "The mopm storage has not been initialized. Initialize it with: `mopm init`\n";
+pub const SYSTEM_NOT_INITIALIZED: &str =
+    "The system vault has not been initialized. Initialize it as root with: `mopm init --system`\n";
+pub const SYSTEM_NOT_ROOT: &str =
+    "Only root can initialize the system vault, its key must be readable by root alone\n";
+pub const SYSTEM_INIT_UNSUPPORTED: &str =
+    "The system vault has no password hint and is kept in a single file\n";
+pub const SYSTEM_INIT_SUCCESSFUL: &str = "Initialized the system vault ";
+pub const SAVE_CONFLICT: &str =
+    "The vault was changed by another mopm process since it was opened. Nothing was saved, run the command again\n";
 pub const ERROR_WHILE_SAVING: &str = "An error occured while saving the storage file\n";
+pub const RNG_UNHEALTHY: &str =
+    "The system random number generator failed a health check. Refusing to continue\n";
+pub const DOCTOR_FAILED: &str = "Some checks failed\n";
+pub const MEMSEC_ON: &str = "Memory protection: on\n";
+pub const MEMSEC_OFF: &str =
+    "Memory protection: off, set `memsec = true` in the config file to keep secrets out of core dumps and debuggers\n";
+pub const ABSTRACT_SOCKETS_SCOPED: &str =
+    "Abstract sockets: out of reach of commands without network\n";
+pub const ABSTRACT_SOCKETS_OPEN: &str =
+    "Abstract sockets: reachable by commands without network, scoping them needs Linux 6.12\n";
+pub const CONFINED: &str = "Confinement: ";
+pub const UNCONFINED: &str =
+    "Confinement: none, no AppArmor profile or SELinux domain restricts mopm and the commands it runs\n";
+pub const SELFTEST_FAILED: &str =
+    "This build cannot read vaults of every released format, do not ship it\n";
+pub const DIFFERENT_DEVICE: &str = "Warning: this vault was last written on a different device\n";
+pub const VAULT_PASSWORD_PROMPT: &str = "Enter the password of ";
+/// How `search` labels the installed vault.
+pub const INSTALLED_VAULT_LABEL: &str = "installed";
+pub const NO_OTHER_VAULTS: &str =
+    "Warning: no other vaults are configured, add them as `vault.<label> = <path>`\n";
+pub const NO_SEARCH_MATCH: &str = "No entry matches\n";
+pub const REVEAL_CONFIRMATION: &str = "Reveal ";
+pub const REAUTH_PROMPT: &str = "Enter your password again to reveal ";
+pub const POLICY_SUCCESSFUL: &str = "Successfully changed the access policy\n";
+pub const NO_SECRETS_FOUND: &str = "No secrets found\n";
+pub const SECRETS_FOUND: &str = "Secrets found: ";
+pub const NO_SECRETS_IN_HISTORY: &str = "No secrets found in the shell history\n";
+pub const SCRUB_CONFIRMATION: &str = "Remove these lines from the history files?";
+pub const HISTORY_SCRUBBED: &str = "Lines removed from the history: ";
+pub const OPEN_SHELLS_WARNING: &str =
+    "Shells that are still open keep their history in memory and may write it back, run `history -c` in them\n";
+pub const SECRET_IN_ARGV: &str =
+    "The password was passed as an argument and may be kept in the shell history, prefer `store <key> --stdin`\n";
+pub const INVALID_TERRAFORM_QUERY: &str = "Expected a JSON object of names to vault keys on stdin";
+pub const VALUE_PROMPT: &str = "Enter the password to store: ";
+pub const MISSING_CATALOG: &str = "Missing the catalog file to verify\n";
+pub const CATALOG_MATCHES: &str = "The vault matches the catalog\n";
+pub const CATALOG_MISMATCH: &str = "The vault does not match the catalog\n";
+pub const NO_DIFFERENCES: &str = "The vaults contain the same entries\n";
+#[cfg(feature = "crdt")]
+pub const OTHER_PASSWORD_PROMPT: &str = "Enter the password of the other vault: ";
+#[cfg(feature = "crdt")]
+pub const DIFFERENT_VAULT: &str =
+    "Warning: the other file is a different vault, merging all of its entries\n";
+pub const NO_INCIDENTS: &str = "No incidents recorded\n";
+pub const INCIDENT_NOT_RECORDED: &str = "Cannot record the incident, wiping anyway\n";
+pub const WATCHER_NOT_RECORDED: &str =
+    "Warning: cannot record the watching process, `mopm shield status` will not show it\n";
+pub const STALE_SHIELD: &str =
+    "Warning: the shield is mounted but no process watches it, clean it up with `mopm shield reset`\n";
+#[cfg(feature = "legacy-layout")]
+pub const LEGACY_ROOT_NAME: &str = ".mopm";
+#[cfg(feature = "legacy-layout")]
+pub const LEGACY_ROOT_MOVED: &str = "Moved the storage from ";
+#[cfg(feature = "legacy-layout")]
+pub const LEGACY_ROOT_IN_HOOKS: &str =
+    "Warning: some hooks still use ~/.mopm, point them at the new location in the config file\n";
+#[cfg(feature = "legacy-layout")]
+pub const LEGACY_ROOT_IGNORED: &str = "Warning: ignoring the storage left in ";
+#[cfg(feature = "legacy-layout")]
+pub const LEGACY_ROOT_IGNORED_HINT: &str = ", remove it once nothing is missing from ";
+#[cfg(feature = "legacy-layout")]
+pub const LEGACY_ROOT_SHIELDED: &str =
+    "Warning: the storage is still in ~/.mopm, lower the shield to move it to the data directory\n";
+#[cfg(feature = "legacy-layout")]
+pub const LEGACY_ROOT_NOT_MOVED: &str =
+    "Warning: cannot move the storage from ~/.mopm, it is used where it is: ";
+pub const SHIELD_RESET: &str = "The shield has been reset, raise it again with `mopm shield up`\n";
+pub const CANNOT_OPEN_VAULT: &str = "Cannot open the vault file\n";
+pub const CANNOT_RUN_MENU: &str = "Cannot run the menu command (set it with MOPM_MENU)\n";
+pub const CANNOT_CLEAR_CLIPBOARD: &str = "Cannot clear the clipboard (set MOPM_CLIPBOARD)\n";
+#[cfg(feature = "ssh-agent")]
+pub const CANNOT_LOCK_SSH_AGENT: &str = "Cannot lock the ssh agent\n";
+pub const CANNOT_LOWER_SHIELD: &str = "Cannot unmount the shield\n";
+pub const CLIPBOARD_CLEARED: &str = "The clipboard has been cleared\n";
+pub const CANNOT_OUTPUT_SELECTION: &str =
+    "Cannot copy or type the selected password (set MOPM_CLIPBOARD or MOPM_AUTOTYPE)\n";
+#[cfg(feature = "fuse")]
+pub const CANNOT_MOUNT_VAULT: &str = "Cannot mount the vault\n";
+#[cfg(feature = "fuse")]
+pub const CANNOT_UNMOUNT_VAULT: &str = "Cannot unmount the vault\n";
+#[cfg(feature = "fuse")]
+pub const MOUNT_SUCCESSFUL: &str = "The vault has been mounted at ";
+#[cfg(feature = "fuse")]
+pub const MOUNT_LOCK_PROMPT: &str =
+    "Press Enter to lock and unmount the vault, it is also locked with the session\n";
+#[cfg(feature = "fuse")]
+pub const CANNOT_WATCH_SESSION: &str =
+    "Cannot watch the session, the vault stays mounted when it is locked\n";
+#[cfg(feature = "fuse")]
+pub const SESSION_LOCKED: &str = "The session was locked, locking the vault\n";
+#[cfg(feature = "fuse")]
+pub const MACHINE_SUSPENDED: &str = "The machine went to sleep, locking the vault\n";
+#[cfg(feature = "fuse")]
+pub const UNMOUNT_SUCCESSFUL: &str = "The vault has been locked and unmounted\n";
+#[cfg(feature = "self-update")]
+pub const UP_TO_DATE: &str = "mopm is up to date, version ";
+#[cfg(feature = "self-update")]
+pub const UPDATE_AVAILABLE: &str = "A new version of mopm is available: ";
+#[cfg(feature = "self-update")]
+pub const UPDATE_CONFIRMATION: &str = "Replace the installed mopm binary?";
+#[cfg(feature = "self-update")]
+pub const UPDATE_SUCCESSFUL: &str = "Successfully updated mopm at ";
+#[cfg(feature = "self-update")]
+pub const CANNOT_UPDATE: &str = "Cannot update mopm, the installed binary was left unchanged\n";
+pub const UNKNOWN_FORMAT: &str =
+    "Unknown format, expected one of: bitwarden-json, browser, hashivault\n";
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+pub const ON_CONFLICT_OPTION: &str = "on-conflict";
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+pub const CONFLICT_QUESTION: &str = "Another password is stored under ";
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+pub const CONFLICT_PROMPT: &str = "Keep";
+/// In the order of skip, overwrite and keep both.
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+pub const CONFLICT_CHOICES: [&str; 3] = ["mine", "theirs", "both"];
+#[cfg(feature = "crdt")]
+pub const UNKNOWN_MERGE_OPTION: &str = "Unknown option, expected --on-conflict, got: ";
+#[cfg(feature = "otpauth")]
+pub const UNKNOWN_OTPAUTH_OPTION: &str = "Unknown option, expected --on-conflict, got: ";
+#[cfg(feature = "otpauth")]
+pub const NO_MIGRATION_LINKS: &str =
+    "No links read, pass the otpauth-migration:// links decoded from the QR codes on stdin\n";
+#[cfg(feature = "browser")]
+pub const MISSING_PROFILE: &str = "Missing the browser profile directory (pass --profile)\n";
+pub const DEFAULT_BITWARDEN_EXPORT: &str = "bitwarden_export.json";
+pub const INVALID_JOBS: &str = "--jobs must be a number of workers above 0\n";
+pub const NO_VAULT_OUTPUT: &str =
+    "The vault was read from stdin, pass `--out <path>` or `--out -` to write the changes\n";
+pub const DEFAULT_VAULT_EXPORT: &str = "mopm_export.data";
+pub const EXPORT_PASSWORD_PROMPT: &str =
+    "Enter a password for the exported vault, or nothing to generate one: ";
+/// Exports travel over email and chat, where they can be attacked offline.
+pub const MIN_EXPORT_PASSWORD_BITS: f64 = 60.0;
+pub const GENERATED_EXPORT_PASSPHRASE: &str =
+    "Passphrase of the exported vault, hand it over apart from the file: ";
+pub const NOTHING_TO_EXPORT: &str = "No entry matches the filter and tag\n";
+pub const GET_OPTIONS: [&str; 2] = ["from-file", "format"];
+pub const UNKNOWN_GET_OPTION: &str =
+    "Unknown option for `get`, accepted: --from-file, --format: --";
+pub const UNKNOWN_GET_FORMAT: &str = "Unknown format for `get`, expected json or env\n";
+pub const PUBLISH_OPTIONS: [&str; 3] = ["filter", "tag", "out"];
+pub const UNKNOWN_PUBLISH_OPTION: &str =
+    "Unknown option for `publish`, accepted: --filter, --tag, --out: --";
+pub const PUBLISH_WITHOUT_FILTER: &str =
+    "Select the entries to publish with --filter or --tag, a mirror is not meant for all of them\n";
+pub const MISSING_PUBLISH_OUT: &str = "Missing --out <file> for the mirror\n";
+pub const MIRROR_PASSWORD_PROMPT: &str = "Enter the password of the mirror: ";
+pub const NOT_A_MIRROR: &str = "Not a mirror published by `mopm publish`: ";
+pub const MIRROR_UNCHANGED: &str = "Nothing changed, the mirror was left as it is\n";
+pub const SUBSCRIBED: &str = "Subscribed to the mirror, read-only: ";
+pub const SUBSCRIBE_OVER_VAULT: &str =
+    "A vault is already installed, read the mirror with `mopm --vault <file>` instead\n";
+pub const NOT_BEFORE_OPTION: &str = "not-before";
+pub const EXPIRES_OPTION: &str = "expires";
+pub const UNKNOWN_STORE_OPTION: &str =
+    "Unknown option, expected one of --username, --url, --match, --notes, --tags, --not-before, --expires, got: ";
+pub const NO_MATCHING_ENTRIES: &str = "No entry matches this URL\n";
+pub const LIST_OPTIONS: [&str; 5] = ["offset", "limit", "fields", "sort", "columns"];
+pub const UNKNOWN_LIST_OPTION: &str =
+    "Unknown option, expected one of --offset, --limit, --fields, --sort, --columns, --reverse, --json, got: ";
+pub const LIST_COLUMNS: [&str; 4] = ["name", "tags", "modified", "expires"];
+pub const UNKNOWN_LIST_SORT: &str =
+    "Unknown --sort, expected one of name, modified, expires, got: ";
+pub const UNKNOWN_LIST_COLUMN: &str =
+    "Unknown column, expected one of name, tags, modified, expires, got: ";
+#[cfg(feature = "k8s")]
+pub const K8S_SYNC_OPTIONS: [&str; 7] = [
+    "selector",
+    "namespace",
+    "server",
+    "token-file",
+    "direction",
+    "interval",
+    "prefix",
+];
+#[cfg(feature = "k8s")]
+pub const UNKNOWN_K8S_SYNC_OPTION: &str =
+    "Unknown option, expected one of --selector, --namespace, --server, --token-file, --direction, --interval, --prefix, got: ";
+#[cfg(feature = "k8s")]
+pub const INVALID_DIRECTION: &str = "Invalid --direction, expected `to-cluster` or `to-vault`\n";
+#[cfg(feature = "web")]
+pub const WEB_OPTIONS: [&str; 1] = ["listen"];
+#[cfg(feature = "web")]
+pub const UNKNOWN_WEB_OPTION: &str = "Unknown option, expected --listen, got: ";
+#[cfg(feature = "web")]
+pub const DEFAULT_WEB_LISTEN: &str = "127.0.0.1:9090";
+#[cfg(feature = "web")]
+pub const WEB_NOT_LOOPBACK: &str =
+    "The web UI only listens on loopback addresses, e.g. 127.0.0.1:9090 or [::1]:9090\n";
+#[cfg(feature = "web")]
+pub const WEB_SERVING: &str = "Serving the web UI, stop it with Ctrl-C: ";
+#[cfg(feature = "ssh-agent")]
+pub const SSH_AGENT_OPTIONS: [&str; 5] = ["socket", "filter", "tag", "allow", "rate"];
+#[cfg(feature = "ssh-agent")]
+pub const UNKNOWN_SSH_AGENT_OPTION: &str =
+    "Unknown option, expected one of --socket, --filter, --tag, --allow, --rate, got: ";
+/// Signatures a minute, plenty for interactive use and a busy git push.
+#[cfg(feature = "ssh-agent")]
+pub const DEFAULT_SSH_AGENT_RATE: usize = 60;
+#[cfg(feature = "ssh-agent")]
+pub const DEFAULT_SSH_KEY_FILTER: &str = "ssh/*";
+#[cfg(feature = "ssh-agent")]
+pub const NO_SSH_KEYS: &str =
+    "No SSH keys to serve, store them under ssh/, e.g. with `tr -d '\\n' < ~/.ssh/id_ed25519 | mopm store ssh/github --stdin`\n";
+#[cfg(feature = "monitor")]
+pub const MONITOR_OPTIONS: [&str; 2] = ["interval", "expiring"];
+#[cfg(feature = "monitor")]
+pub const UNKNOWN_MONITOR_OPTION: &str =
+    "Unknown option, expected --interval, --expiring, --once or --offline, got: ";
+#[cfg(feature = "monitor")]
+pub const DEFAULT_MONITOR_INTERVAL_SECS: usize = 24 * 60 * 60;
+#[cfg(feature = "monitor")]
+pub const DEFAULT_EXPIRING_DAYS: usize = 14;
+#[cfg(feature = "monitor")]
+pub const NO_NEW_FINDINGS: &str = "No new findings\n";
+#[cfg(feature = "monitor")]
+pub const CANNOT_NOTIFY: &str = "Cannot show a desktop notification: ";
+#[cfg(feature = "monitor")]
+pub const MONITOR_CANNOT_OPEN: &str =
+    "Cannot open the vault with the password given, was it changed? ";
+#[cfg(feature = "monitor")]
+pub const BREACHES_UNCHECKED: &str =
+    "Some entries were not checked against breaches, the findings are partial: ";
+#[cfg(feature = "monitor")]
+pub const NO_NOTIFICATIONS: &str = "No notifications\n";
+#[cfg(feature = "monitor")]
+pub const NOTIFICATIONS_CLEARED: &str = "The notifications have been cleared\n";
+#[cfg(feature = "hashivault")]
+pub const MISSING_HASHIVAULT_OPTIONS: &str =
+    "Missing vault address or token (pass --addr and --token or set VAULT_ADDR and VAULT_TOKEN)\n";
+#[cfg(feature = "share")]
+pub const DEFAULT_SHARE_TTL: u64 = 600;
+#[cfg(feature = "share")]
+pub const DEFAULT_SHARE_LISTEN: &str = "127.0.0.1:0";
+#[cfg(feature = "share")]
+pub const SHARE_LINK_OPTIONS: [&str; 3] = ["ttl", "relay", "listen"];
+#[cfg(feature = "share")]
+pub const UNKNOWN_SHARE_LINK_OPTION: &str =
+    "Unknown option, expected one of --ttl, --relay, --listen, got: ";
+#[cfg(feature = "share")]
+pub const INVALID_TTL: &str = "Invalid --ttl, expected a duration such as 90s, 10m or 2h\n";
+#[cfg(feature = "share")]
+pub const WAITING_FOR_RECIPIENT: &str =
+    "Waiting for the link to be used, it works once and expires with --ttl\n";
+#[cfg(feature = "share")]
+pub const LINK_USED: &str = "The link was used and no longer works\n";
+pub const OVERWRITE_CONFIRMATION: &str = "Overwrite ";
+pub const RECEIVED_ENTRY: &str = "Stored the shared entry as ";
+pub const HANDOFF_CHUNKS: &str =
+    "Type these chunks into `mopm handoff --receive` on the other machine:\n";
+pub const HANDOFF_CODE: &str = "Then the code, carried apart from the chunks, e.g. read aloud:\n";
+pub const HANDOFF_TYPE_CHUNKS: &str =
+    "Type the chunks, one per line, and an empty line after the last\n";
+pub const HANDOFF_CODE_PROMPT: &str = "Code: ";
+pub const PASSWORD_ATTEMPTS: usize = 3;
+pub const WRONG_PASSWORD: &str = "Wrong password, try again\n";
+pub const HINT_OPTION: &str = "hint";
+pub const HASHER_OPTION: &str = "hasher";
+pub const BACKEND_OPTION: &str = "backend";
+pub const VALUES_OPTION: &str = "values";
+pub const INIT_OPTIONS: [&str; 4] = [HINT_OPTION, HASHER_OPTION, BACKEND_OPTION, VALUES_OPTION];
+pub const UNKNOWN_INIT_OPTION: &str =
+    "Unknown option, expected --hint, --hasher, --backend or --values, got: ";
+#[cfg(feature = "sqlite")]
+pub const UNKNOWN_BACKEND: &str = "Unknown backend, expected one of: file, sqlite\n";
+#[cfg(not(feature = "sqlite"))]
+pub const UNKNOWN_BACKEND: &str =
+    "Unknown backend, expected file (sqlite needs the `sqlite` feature)\n";
+pub const UNKNOWN_HASHER: &str = "Unknown hasher, expected one of: sha256, sha512-256, blake2b\n";
+pub const UNKNOWN_VALUES: &str = "Unknown values, expected one of: bound, unbound\n";
+/// Wrong passwords in a row before the hint is shown.
+pub const HINT_AFTER_FAILURES: usize = 2;
+pub const HINT_PREFIX: &str = "Password hint (stored unencrypted): ";
+#[cfg(feature = "hidden-volume")]
+pub const HIDDEN_CONFIRMATION: &str =
+    "Create a hidden vault? It replaces any hidden vault this vault file may hold";
+#[cfg(feature = "hidden-volume")]
+pub const HIDDEN_PASSWORD_PROMPT: &str = "Enter the password of the hidden vault: ";
+pub const REPEAT_PASSWORD_PROMPT: &str = "Enter it again: ";
+#[cfg(feature = "hidden-volume")]
+pub const EMPTY_HIDDEN_PASSWORD: &str = "The password of the hidden vault cannot be empty\n";
+pub const PASSWORD_MISMATCH: &str = "The passwords do not match\n";
+#[cfg(feature = "hidden-volume")]
+pub const HIDDEN_PASSWORD_IS_OUTER: &str =
+    "The hidden vault needs a password other than the one of the vault\n";
+#[cfg(feature = "hidden-volume")]
+pub const HIDDEN_FROM_HIDDEN: &str = "Unlock the outer vault to create a hidden vault\n";
+#[cfg(feature = "hidden-volume")]
+pub const HIDDEN_CREATED: &str = "Created the hidden vault, unlock with its password to use it\n";
+#[cfg(feature = "tpm")]
+pub const TPM_OPTIONS: [&str; 1] = ["pcrs"];
+#[cfg(feature = "tpm")]
+pub const UNKNOWN_TPM_OPTION: &str = "Unknown option, expected --pcrs, got: ";
+#[cfg(feature = "tpm")]
+pub const TPM_INSTALLED_ONLY: &str = "Only the installed vault can be bound to the TPM\n";
+#[cfg(feature = "tpm")]
+pub const TPM_CONFIRMATION: &str =
+    "Bind the vault to this machine? Anywhere else it takes the password and the recovery code shown next, and its backups are deleted";
+#[cfg(feature = "tpm")]
+pub const TPM_ENROLLED: &str =
+    "The vault is bound to the TPM of this machine. Keep this recovery code safe, with the password it opens the vault when the TPM does not:\n";
+#[cfg(feature = "tpm")]
+pub const TPM_BACKUPS_REMOVED: &str = "Deleted the backups the password alone opens: ";
+#[cfg(feature = "tpm")]
+pub const TPM_BACKUPS_NOT_REMOVED: &str =
+    "Warning: cannot delete the backups, the password alone opens them, delete the .data.bak.<n> files by hand\n";
+#[cfg(feature = "tpm")]
+pub const TPM_ENROLL_INCOMPLETE: &str =
+    "Warning: binding the vault to the TPM did not complete, the password alone opens it, run `mopm tpm enroll` again\n";
+#[cfg(feature = "tpm")]
+pub const TPM_RESEALED: &str = "The key has been sealed again to the current boot state\n";
+#[cfg(feature = "tpm")]
+pub const TPM_NOT_ENROLLED: &str = "The vault is not bound to the TPM\n";
+#[cfg(feature = "tpm")]
+pub const TPM_REMOVED: &str =
+    "The vault is no longer bound to the TPM, the password alone opens it\n";
+#[cfg(feature = "tpm")]
+pub const TPM_RECOVERY_PROMPT: &str = "Enter the recovery code: ";
+#[cfg(feature = "tpm")]
+pub const TPM_NEW_PASSWORD_PROMPT: &str = "Enter the new password: ";
+#[cfg(feature = "tpm")]
+pub const TPM_EMPTY_PASSWORD: &str = "The password cannot be empty\n";
+#[cfg(feature = "tpm")]
+pub const TPM_PASSWORD_CHANGED: &str =
+    "Changed the password, the recovery code and the TPM open the vault with it\n";
+pub const RESTORE_BACKUP_CONFIRMATION: &str =
+    "Replace the vault with this backup? Changes made since are lost unless backups are enabled";
+pub const NO_SUCH_BACKUP: &str =
+    "There is no such backup, `backup.before_write = true` enables them\n";
+pub const BACKUP_RESTORED: &str = "The backup has been restored\n";
+pub const DEFAULT_VERIFY_SAMPLE: usize = 10;
+pub const VERIFY_OPTIONS: [&str; 2] = ["sample", "catalog"];
+pub const UNKNOWN_VERIFY_OPTION: &str =
+    "Unknown option, expected one of --sample, --catalog, got: ";
+pub const INTEGRITY_FAILED: &str =
+    "the authentication tag does not match: wrong password, or the file is damaged";
+pub const BACKUP_OK: &str = "The backup can be restored\n";
+pub const BACKUP_BROKEN: &str = "The backup cannot be fully restored\n";
 pub const NO_COMMAND_SPECIFIED: &str = "No command specified\nUsage: mopm [COMMAND] [OPTIONS..]\n";
 
+pub const DUMP_NOT_A_FILE: &str =
+    "The vault is kept in a database, dump shows the layout of vault files, e.g. from `export`\n";
 pub const HELP_MESSAGE: &str = r#"Usage: mopm [COMMAND] [OPTIONS..]
 
+Commands:
+  init [--hint <text>]     Initialize the mopm storage; the optional hint is
+                           stored UNENCRYPTED and shown after repeated wrong
+                           passwords, one resembling the password is refused;
+                           --hasher <sha256|sha512-256|blake2b> picks the
+                           integrity hash of the vault (default: sha256);
+                           --backend <file|sqlite> keeps the vault in a
+                           single file (default) or as individually
+                           encrypted rows of an SQLite database;
+                           --values <bound|unbound> binds each value to its
+                           key and the vault (default), so that it cannot be
+                           moved to another entry, or keeps them readable
+                           by versions of mopm before bound values
+  clear [--now] [--undo]   Move the mopm storage to the trash after typing a
+                           confirmation phrase; it is overwritten and removed
+                           after 7 days, or at once with --now. --undo restores
+                           the storage cleared last, --session only forgets the
+                           session entries
+  store <key> <value>      Store a password, optionally with --username, --url,
+                           --match <rule,..>, --notes and --tags <tag,..>;
+                           --not-before <date> makes mopm refuse to show it
+                           until then (UTC), a guard against using it early,
+                           not encryption: the password alone still opens
+                           it; --expires <date> records when it runs out.
+                           The url matches its whole site, rules are a
+                           domain, host:<host>, prefix:<url> or exact:<url>.
+                           Storing over a key shows what changes and asks
+                           first unless --force; the value it replaces is
+                           kept, see history
+  store <key> --stdin      Same, reading the password from stdin so that it does
+                           not end up in the shell history
+  store <key> <value> --session
+                           Keep a password for this login session only (or
+                           with --stdin), sealed under the master password in
+                           $XDG_RUNTIME_DIR (a tmpfs) and never written to the
+                           vault; get and exists find it after the vault
+  get <key>..              Print a stored password; several keys, and those
+                           listed one per line in --from-file <path> (`-` for
+                           stdin), are printed with a single unlock in
+                           --format json (default) or env, `NAME='value'`
+                           lines to source
+  exists <key>             Exit with 0 if the key is stored, 4 otherwise,
+                           printing nothing
+  rotate <key>             Replace a password with a generated one and print it,
+                           options: --profile <name> (default: default),
+                           --length <n> to override its length, --copy to copy
+                           it instead; the previous one is kept, see history
+  generate                 Print a generated password, options: --profile
+                           <name> (default: default), --length <n> to override
+                           its length
+  touch <key>              Mark an entry as modified now, or reserve the key
+                           with an empty placeholder
+  history <key> [n]        List the values an entry had before it was stored
+                           over or rotated, the last 5, or print the <n>th
+                           most recent one
+  lookup --batch           Read keys from stdin, one per line, and print a JSON
+                           object of their passwords with a single unlock
+                           (null for keys that are missing or denied)
+  terraform-external       Act as a Terraform external data source: read a JSON
+                           object of names to keys from stdin and print the
+                           names with their passwords; wrap the result in
+                           sensitive(), Terraform keeps it in the state
+  lease <key> <cmd> [ttl]  Store a command whose output is cached for [ttl]
+                           seconds (default: 300); it runs sandboxed without
+                           network, unless --network is given
+  edit <key>               Edit a password and its fields in $EDITOR on a tmpfs
+                           (--insecure-tmp allows other temporary directories)
+  delete <key>             Delete a stored password
+  audit [--reuse]          Report entries that share the same password, options:
+                           --jobs <n> workers decrypting entries without a
+                           fingerprint (default: one per CPU), --summary to
+                           count and list the entries not changed in a year,
+                           reused, expired, found in breaches by `monitor` or
+                           imported and never rotated, oldest first
+  policy <key> [policy]    Show or set what reading an entry requires: confirm,
+                           reauth (the master password again), both or none
+  scan [dir] [--staged]    Report lines under [dir] (default: .) that contain a
+                           stored password or a well-known credential format,
+                           --staged scans the files staged in git instead
+  guard-history            Look for stored passwords and credentials in the shell
+                           history files and offer to remove those lines
+  hooks install-git        Install or update a git pre-commit hook running
+                           `scan --staged` (--force replaces another hook,
+                           skip it once with MOPM_SKIP_SCAN=1)
+  refactor --rename-prefix <old> <new>
+                           Move every key starting with <old> to start with <new>
+  refactor --retag <old> [new]
+                           Rename the tag <old> on every entry, or remove it;
+                           refactors show the planned changes and apply all of
+                           them or none (--dry-run only shows them)
+  compact [days]           Forget deletions older than [days] (default: 90)
+  restore-backup <n>       Replace the vault with its <n>th most recent backup,
+                           after checking that it opens with the master password
+  shield <up|down>         Raise or lower the honeypot shield; a trigger is
+                           recorded, encrypted with the master password, in
+                           $XDG_STATE_HOME/mopm/incidents before the wipe
+  shield incidents         Decrypt and show the recorded triggers
+  shield status            Show whether the shield is mounted, its decoys, the
+                           process watching them and the last trigger
+  shield reset             Stop the watching process, unmount the shield and
+                           recreate the decoys, for a shield left behind
+  panic [--self-destruct]  Clear the clipboard, forget the session entries and
+                           lock the ssh agent, meant to be bound to a hotkey
+                           of the desktop; --self-destruct also lowers the
+                           shield and deletes the storage without asking
+  open <vault-file> [key]  List or print entries of a vault file without installing it
+  verify <vault-file>      Check that a backup of the vault would restore: header,
+                           authentication tag, entry count and a test decryption
+                           of --sample <n|all> entries (default: 10); --catalog
+                           <file> also checks that no cataloged entry is missing
+  info                     Show the vault and device identities
+  dump [--debug]           Show where each field of the vault file is, for
+                           debugging the format (see docs/format.md); --debug
+                           asks for the password to show the records of the
+                           body too, keys but never values
+  doctor                   Check that the configured entropy source works and
+                           that commands can be sandboxed, and report
+                           unencrypted swap, core dump handlers, ptrace open
+                           to the user's processes and the AppArmor profile
+                           or SELinux domain confining mopm
+  selftest                 Check that this build still reads vault files of
+                           every released format, bundled into it
+  diff <vault-a> <vault-b> Show keys added, removed or changed from one vault file
+                           to another, options: --show-values, --json
+  catalog export [file]    Write a signed listing of key names, tags and
+                           modification times, without any secrets
+  catalog verify <file>    Check that the vault matches a signed catalog
+  bench [ms] [--apply]     Find key derivation parameters that take about [ms]
+                           to unlock (default: 500), --apply re-encrypts the
+                           vault with them
+  list [pattern]           List entry names, optionally filtered by a substring,
+                           without decrypting any value, as a table on a
+                           terminal. Options: --offset, --limit, --sort
+                           <name|modified|expires> with --reverse, --columns
+                           <name,tags,modified,expires>, --fields <name,..> to
+                           show metadata and --json
+  search [--all-vaults] <pattern>
+                           List the keys containing <pattern> labeled by vault;
+                           --all-vaults also searches the vaults of the config
+                           file, asking for the password of each
+  match <url>              List the entries for a site, closest match first
+  menu [copy|type]         Pick an entry with dmenu/rofi and copy or type its password
+  merge <vault-file>       Merge another replica of the vault (requires the `crdt` feature),
+                           options: --on-conflict, as for import, asking by
+                           default when run in a terminal
+  mount <dir>              Expose entries as files under <dir> until Enter is
+                           pressed, or the session locks or the machine sleeps,
+                           which also clears the clipboard (requires the `fuse`
+                           feature; `logind` listens to systemd-logind signals
+                           instead of polling loginctl)
+  import hashivault        Import from a HashiCorp Vault KV v2 engine (requires the
+                           `hashivault` feature), options: --addr, --token, --path,
+                           --jobs <n> secrets fetched at once (default: one
+                           per CPU)
+  import browser           Import logins saved by Chromium or Firefox (requires the
+                           `browser` feature), options: --profile <dir>
+                           Imports take --on-conflict <policy> for keys that
+                           hold a different password: overwrite (default),
+                           skip, keep-both (adds a -2 suffix) or ask, and
+                           report the conflicts. Asking shows both entries,
+                           passwords masked, to keep mine, theirs or both,
+                           in uppercase for all remaining conflicts
+  export [vault]           Write a vault file with the entries matching --filter
+                           <glob> (e.g. "work/*") and --tag <tag>, or all of
+                           them, encrypted with a new password of at least 60
+                           bits, or a generated passphrase, options:
+                           --output (default: mopm_export.data); open it with
+                           `open` or hand it over
+  export hashivault        Export to a HashiCorp Vault KV v2 engine, same options
+  export bitwarden-json    Write an unencrypted Bitwarden import file, options:
+                           --output (default: bitwarden_export.json)
+  publish --filter <glob> --out <file>
+                           Write a read-only mirror of the matching entries
+                           (or --tag <tag>) to commit to a dotfiles repository;
+                           publishing again updates it with the same password,
+                           and leaves the file alone when nothing changed
+  subscribe <file>         Use a mirror as the vault of this machine, linked so
+                           that pulling it brings the changes; saving is
+                           refused. With a vault installed, read it with
+                           `mopm --vault <file>`
+  otpauth import           Import the accounts of Google Authenticator transfer QR
+                           codes, read from stdin as otpauth-migration:// links,
+                           one per line, into otp/<issuer>:<name> (requires the
+                           `otpauth` feature), options: --on-conflict
+  hidden create            Create an empty hidden vault inside the vault file,
+                           opened instead of the vault when its own password is
+                           entered (requires the `hidden-volume` feature)
+  tpm enroll               Bind the vault to the TPM of this machine: it is
+                           re-encrypted with a key held in slots for the
+                           password and for a secret sealed to the boot state,
+                           --pcrs (default: sha256:0,2,4,7). A recovery code
+                           is shown once for when the TPM refuses, e.g. after
+                           a firmware update; enroll again then to seal the
+                           secret anew. `tpm password` changes the password,
+                           `tpm remove` unbinds the vault (requires the `tpm`
+                           feature and tpm2-tools)
+  self-update [--check]    Install the latest signed release (requires the
+                           `self-update` feature), --check only reports it
+  k8s-sync                 Fill Kubernetes Secrets annotated with mopm/entries:
+                           <field>=<key>,.. from the vault (requires the `k8s`
+                           feature), options: --selector <labels>, --namespace,
+                           --server (default: kubectl proxy, https or loopback
+                           only), --token-file, --direction
+                           <to-cluster|to-vault>, --interval <secs> to keep
+                           syncing, --prefix (default: k8s/), the only keys
+                           annotations may name
+  web                      Serve a web UI to list, search, show and copy entries
+                           once unlocked with the master password (requires the
+                           `web` feature), --listen <addr> (default:
+                           127.0.0.1:9090, loopback only); logins are refused
+                           for a while after wrong passwords, and there is no
+                           WebAuthn
+  ssh-agent                Serve the OpenSSH ed25519 keys stored under ssh/ to
+                           ssh through SSH_AUTH_SOCK, so that they never sit
+                           unencrypted on the disk; store a key with its lines
+                           joined, e.g. `tr -d '\n' < ~/.ssh/id_ed25519 |
+                           mopm store ssh/github --stdin` (requires the
+                           `ssh-agent` feature), options: --socket <path>
+                           (default: $XDG_RUNTIME_DIR/mopm/ssh-agent),
+                           --filter, --tag, --allow <program>,.. to admit
+                           only these programs, e.g. ssh,git, --rate <n>
+                           signatures a minute for each user at most
+                           (default: 60)
+  monitor                  Check every entry against the Have I Been Pwned
+                           breach list and report expired or expiring ones,
+                           with a desktop notification for each new finding
+                           (requires the `monitor` feature), options:
+                           --interval <secs> (default: a day), --expiring
+                           <days> (default: 14), --once, --offline (expiry
+                           only)
+  notifications [--clear]  List the findings of monitor, or clear them
+
+  share-link <key>         Print a one-time link to the entry, encrypted on this
+                           machine with the key in the link fragment (requires
+                           the `share` feature), options: --ttl (default: 10m),
+                           --relay <url> to upload the ciphertext instead of
+                           serving it, --listen <addr> (default: 127.0.0.1:0)
+  receive-link <link> [key]
+                           Store the entry behind a link, under [key] if given
+  handoff <key>            Show the entry encrypted to a one-time key, as short
+                           chunks to type on another machine without network
+                           or clipboard, and the code that opens them
+  handoff --receive [key]  Type in the chunks and the code of a handoff, and
+                           store the entry, under [key] if given
 Options:
   -h, --help         Display this message
   -v, --version      Display the current version
+  -y, --yes          Answer yes to every confirmation
+      --no-color     Do not color the output (also set by NO_COLOR, and implied
+                     when the output is not a capable terminal)
+      --non-interactive
+                     Fail instead of asking for input
+      --password-source=<tty|pinentry|keyring|pam|fd:N>
+                     Where to read the master password from
+                     (pinentry when MOPM_PINENTRY is set, else tty); pam
+                     reads the keyring after checking the login through
+                     MOPM_PAM_SERVICE (default: login), requires the `pam`
+                     feature; typing it is retried up to 3 times, set
+                     MOPM_MASK_INPUT=1 to echo a * per character
+      --vault <path> Work on this vault file instead of the installed one,
+                     written back in place; `-` reads it from stdin, answer
+                     confirmations with --yes then
+      --out <path>   Write the changed vault here instead, `-` for stdout
+                     (messages then go to stderr)
+      --message <text>
+                     Why the vault is changed, e.g. "rotated after incident",
+                     passed to the store, delete and clear hooks as
+                     MOPM_MESSAGE, such as for a git commit message
+      --system       Work on the vault of the machine in
+                     /var/lib/mopm/system.data, unlocked without a password
+                     by the key in /etc/mopm/system.key, which must be owned
+                     by root and readable by root alone, e.g. for daemons
+                     reading their secrets at boot with
+                     `mopm get --system <key>`; create both as root with
+                     `mopm init --system`
+      --crash-report Show a redacted report if mopm crashes and offer to
+                     save or submit it (also set by MOPM_CRASH_REPORT=1)
+      --trace[=human|json]
+                     Print how long each step took (unlock, decrypt, write..)
+                     to stderr once the command finishes
+
+Hooks (in ~/.config/mopm/config, or under $XDG_CONFIG_HOME):
+  on_store, on_get, on_delete, on_clear = <cmd>
+                     Run <cmd> after the event with MOPM_EVENT, MOPM_KEY,
+                     MOPM_VAULT_ID and MOPM_MESSAGE (--message) set, never
+                     a password
+  on_rotate = <cmd>  Run <cmd> before a rotated password is saved, with the
+                     old and the new password on stdin, one per line, e.g.
+                     to change it on the site; nothing is saved if it fails
+  hook_timeout = <secs>
+                     Kill hooks running longer (default: 10)
+  hook_failure = <ignore|warn|abort>
+                     What a failing hook does (default: warn); abort exits
+                     with an error, before the password is printed for get
+  hook_network = <true|false>
+                     Let hooks open network sockets (default: false); hooks,
+                     leased commands and pinentry run with a minimal
+                     environment, no new privileges and a seccomp filter
+                     that also keeps them from reading other processes
+
+Backups (in the same file):
+  backup.before_write = <true|false>
+                     Copy the vault to .data.bak.<n> next to it in
+                     ~/.local/share/mopm (or under $XDG_DATA_HOME) before
+                     every write, .data.bak.1 being the most recent
+                     (default: false)
+  backup.keep = <n>  How many copies to keep (default: 5)
+  backup.max_age_days = <days>
+                     Also drop copies older than <days>, except the most
+                     recent
+
+Generator profiles (in the same file):
+  generate.<name> = <length> <class,..>
+                     Add or replace a profile for generate and rotate; the
+                     classes are lower, upper, letters, digits, alnum and
+                     symbols, unambiguous drops look-alikes such as 0 and O.
+                     Built in: default = 24 alnum,symbols, pin = 6 digits,
+                     wifi = 63 alnum, bank = 16 letters,digits,unambiguous
+
+Vaults (in the same file):
+  vault.<label> = <path>
+                     Another vault file, searched by search --all-vaults
+
+Memory protection (in the same file):
+  memsec = <true|false>
+                     Make mopm non-dumpable at startup, which keeps its
+                     memory out of core dumps and other processes of the
+                     user from attaching to it; derived keys are also marked
+                     MADV_DONTDUMP (default: false)
+
+Password prompts (in the same file):
+  prompt_timeout = <seconds>
+                     Give up on a password prompt nobody answers in time,
+                     0 to wait forever (default: 0); prompts read the
+                     controlling terminal, never stdin, and drop what was
+                     typed before they showed
+
+Randomness (in the same file):
+  rng = <getrandom|urandom|rdrand>
+                     Where keys, salts, nonces and generated passwords come
+                     from (default: getrandom); urandom reads /dev/urandom,
+                     rdrand mixes the CPU's RDRAND into getrandom
 "#;
@@ -0,0 +1,38 @@
+use crate::{cli::interact::Interact, core::policy::AccessGuard, log::logger::Logger};
+
+use super::constants;
+
+/// Asks on the terminal before a guarded entry is revealed, honoring
+/// `--yes`, `--non-interactive` and the configured password source.
+pub struct PromptGuard {
+    interact: Interact,
+}
+
+impl PromptGuard {
+    pub fn new(interact: Interact) -> Self {
+        Self { interact }
+    }
+}
+
+impl std::fmt::Debug for PromptGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PromptGuard")
+    }
+}
+
+impl AccessGuard for PromptGuard {
+    fn confirm(&mut self, key: &str) -> bool {
+        let question = format!("{}`{}`?", constants::REVEAL_CONFIRMATION, key);
+        self.interact
+            .confirm(&mut Logger::default(), &question)
+            .unwrap_or(false)
+    }
+
+    fn reauthenticate(&mut self, key: &str) -> Option<String> {
+        let prompt = format!("{}`{}`: ", constants::REAUTH_PROMPT, key);
+        self.interact
+            .password(&mut Logger::default(), &prompt)
+            .ok()
+            .map(|v| v.trim().to_string())
+    }
+}
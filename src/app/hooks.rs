@@ -0,0 +1,317 @@
+//! User commands run on vault events, configured in the config file:
+//!
+//! ```text
+//! on_store = notify-send mopm "stored $MOPM_KEY"
+//! on_get = logger -t mopm "read $MOPM_KEY"
+//! on_delete = git -C ~/.local/share/mopm commit -qam "${MOPM_MESSAGE:-delete $MOPM_KEY}"
+//! hook_timeout = 10
+//! hook_failure = warn
+//! hook_network = false
+//! ```
+//!
+//! Hooks only ever receive metadata in their environment, never a secret.
+//! The one exception is `on_rotate`, which runs before a rotated password
+//! is saved and reads the old and the new password on stdin, one per line,
+//! to change the password where it is used. That is also why hooks run
+//! without network unless `hook_network` allows it, see `exec::sandbox`.
+
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+
+use thiserror::Error;
+
+use crate::core::executor::{Executor, ExecutorError};
+
+const EVENT_PREFIX: &str = "on_";
+const PREFIX: &str = "hook_";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const TIMEOUT_SETTING: &str = "hook_timeout";
+const FAILURE_SETTING: &str = "hook_failure";
+const NETWORK_SETTING: &str = "hook_network";
+
+#[derive(Error, Debug)]
+pub enum HookError {
+    #[error("unknown config setting `{0}`")]
+    UnknownSetting(String),
+    #[error("invalid value for `{0}`: `{1}`")]
+    InvalidValue(&'static str, String),
+    #[error("the {0} hook failed: {1}")]
+    Failed(Event, ExecutorError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    Store,
+    Get,
+    Delete,
+    Clear,
+    Rotate,
+}
+
+impl Event {
+    const ALL: [Self; 5] = [
+        Self::Store,
+        Self::Get,
+        Self::Delete,
+        Self::Clear,
+        Self::Rotate,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Store => "store",
+            Self::Get => "get",
+            Self::Delete => "delete",
+            Self::Clear => "clear",
+            Self::Rotate => "rotate",
+        }
+    }
+
+    fn setting(self) -> String {
+        format!("{}{}", EVENT_PREFIX, self.name())
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What a failing or timed out hook does to the command that ran it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    Ignore,
+    #[default]
+    Warn,
+    /// Stops mopm with an error. The `get` hook runs before the password is
+    /// printed, so it is not revealed when the hook fails.
+    Abort,
+}
+
+impl FailurePolicy {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ignore" => Some(Self::Ignore),
+            "warn" => Some(Self::Warn),
+            "abort" => Some(Self::Abort),
+            _ => None,
+        }
+    }
+}
+
+/// Metadata passed to a hook as `MOPM_*` environment variables.
+#[derive(Debug, Default)]
+pub struct Context<'a> {
+    pub key: Option<&'a str>,
+    pub vault_id: Option<String>,
+    /// The `--message` of a change, such as "rotated after incident".
+    pub message: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hooks {
+    commands: BTreeMap<Event, String>,
+    timeout: Duration,
+    failure: FailurePolicy,
+    network: bool,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+            timeout: DEFAULT_TIMEOUT,
+            failure: FailurePolicy::default(),
+            network: false,
+        }
+    }
+}
+
+impl Hooks {
+    /// The hooks of the `on_` and `hook_` settings of the config file.
+    pub fn from_settings(settings: &[(&str, &str)]) -> Result<Self, HookError> {
+        let mut hooks = Self::default();
+        for &(name, value) in settings {
+            match name {
+                TIMEOUT_SETTING => {
+                    hooks.timeout = value
+                        .parse()
+                        .map(Duration::from_secs)
+                        .map_err(|_| HookError::InvalidValue(TIMEOUT_SETTING, value.to_string()))?
+                }
+                FAILURE_SETTING => {
+                    hooks.failure = FailurePolicy::parse(value).ok_or_else(|| {
+                        HookError::InvalidValue(FAILURE_SETTING, value.to_string())
+                    })?
+                }
+                NETWORK_SETTING => {
+                    hooks.network = value
+                        .parse()
+                        .map_err(|_| HookError::InvalidValue(NETWORK_SETTING, value.to_string()))?
+                }
+                _ => {
+                    let event = Event::ALL
+                        .into_iter()
+                        .find(|event| event.setting() == name)
+                        .ok_or_else(|| HookError::UnknownSetting(name.to_string()))?;
+                    hooks.commands.insert(event, value.to_string());
+                }
+            }
+        }
+        Ok(hooks)
+    }
+
+    pub fn failure(&self) -> FailurePolicy {
+        self.failure
+    }
+
+    /// Whether the command of a hook contains `text`.
+    #[cfg(feature = "legacy-layout")]
+    pub fn mentions(&self, text: &str) -> bool {
+        self.commands.values().any(|v| v.contains(text))
+    }
+
+    /// Runs the hook of `event`, if one is configured.
+    pub fn run(&self, event: Event, context: &Context) -> Result<(), HookError> {
+        self.run_with_input(event, context, None)
+    }
+
+    /// Like `run`, writing `input` to the stdin of the hook.
+    pub fn run_with_input(
+        &self,
+        event: Event,
+        context: &Context,
+        input: Option<&str>,
+    ) -> Result<(), HookError> {
+        let Some(command) = self.commands.get(&event) else {
+            return Ok(());
+        };
+        let mut executor = Executor::new(self.timeout)
+            .network(self.network)
+            .env("MOPM_EVENT", event.name());
+        if let Some(input) = input {
+            executor = executor.stdin(input);
+        }
+        if let Some(key) = context.key {
+            executor = executor.env("MOPM_KEY", key);
+        }
+        if let Some(vault_id) = &context.vault_id {
+            executor = executor.env("MOPM_VAULT_ID", vault_id);
+        }
+        if let Some(message) = &context.message {
+            executor = executor.env("MOPM_MESSAGE", message);
+        }
+        executor
+            .run(command)
+            .map(|_| ())
+            .map_err(|err| HookError::Failed(event, err))
+    }
+}
+
+pub fn is_hook_setting(name: &str) -> bool {
+    name.starts_with(EVENT_PREFIX) || name.starts_with(PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let hooks = Hooks::from_settings(&[
+            ("on_store", "echo \"$MOPM_KEY\""),
+            ("hook_timeout", "3"),
+            ("hook_failure", "abort"),
+        ])
+        .unwrap();
+        assert_eq!(hooks.commands[&Event::Store], "echo \"$MOPM_KEY\"");
+        assert_eq!(hooks.timeout, Duration::from_secs(3));
+        assert_eq!(hooks.failure(), FailurePolicy::Abort);
+        assert!(!hooks.network);
+        let network = Hooks::from_settings(&[("hook_network", "true")]).unwrap();
+        assert!(network.network);
+
+        assert!(matches!(
+            Hooks::from_settings(&[("on_save", "true")]),
+            Err(HookError::UnknownSetting(_))
+        ));
+        assert!(matches!(
+            Hooks::from_settings(&[("hook_failure", "panic")]),
+            Err(HookError::InvalidValue(FAILURE_SETTING, _))
+        ));
+        assert!(matches!(
+            Hooks::from_settings(&[("hook_network", "yes")]),
+            Err(HookError::InvalidValue(NETWORK_SETTING, _))
+        ));
+    }
+
+    #[cfg(feature = "legacy-layout")]
+    #[test]
+    fn test_mentions() {
+        let hooks = Hooks::from_settings(&[("on_delete", "git -C ~/.mopm commit -qam x")]).unwrap();
+        assert!(hooks.mentions(".mopm"));
+        assert!(!Hooks::default().mentions(".mopm"));
+    }
+
+    #[test]
+    fn test_run() {
+        let hooks = Hooks::from_settings(&[
+            ("on_get", "[ \"$MOPM_EVENT:$MOPM_KEY\" = get:work/db ]"),
+            ("on_delete", "exit 1"),
+        ])
+        .unwrap();
+        let context = Context {
+            key: Some("work/db"),
+            vault_id: None,
+            message: None,
+        };
+        assert!(hooks.run(Event::Get, &context).is_ok());
+        assert!(hooks.run(Event::Store, &context).is_ok());
+        assert!(matches!(
+            hooks.run(Event::Delete, &context),
+            Err(HookError::Failed(
+                Event::Delete,
+                ExecutorError::CommandFailed(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let hooks =
+            Hooks::from_settings(&[("on_rotate", "read old; read new; [ \"$old:$new\" = a:b ]")])
+                .unwrap();
+        let context = Context::default();
+        assert!(hooks
+            .run_with_input(Event::Rotate, &context, Some("a\nb\n"))
+            .is_ok());
+        assert!(hooks
+            .run_with_input(Event::Rotate, &context, Some("a\nc\n"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_message() {
+        let hooks = Hooks::from_settings(&[(
+            "on_store",
+            "[ \"$MOPM_MESSAGE\" = \"rotated after incident\" ]",
+        )])
+        .unwrap();
+        let context = Context {
+            message: Some("rotated after incident".to_string()),
+            ..Context::default()
+        };
+        assert!(hooks.run(Event::Store, &context).is_ok());
+        assert!(hooks.run(Event::Store, &Context::default()).is_err());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let hooks =
+            Hooks::from_settings(&[("on_clear", "sleep 5"), ("hook_timeout", "0")]).unwrap();
+        assert!(matches!(
+            hooks.run(Event::Clear, &Context::default()),
+            Err(HookError::Failed(Event::Clear, ExecutorError::Timeout))
+        ));
+    }
+}
@@ -1,2 +1,7 @@
 pub mod application;
 pub mod constants;
+pub mod guard;
+pub mod hooks;
+#[cfg(feature = "monitor")]
+pub mod monitor;
+pub mod settings;
@@ -0,0 +1,245 @@
+//! Findings of `monitor`: entries whose password shows up in known breaches,
+//! and entries that expired or expire soon. Findings are kept in the
+//! notifications file, one JSON object per line, until they are cleared.
+//! Entries are named there by their tag rather than their key, so that the
+//! file tells nothing without the vault.
+
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    process::Command,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    core::{encryptor::Encryprtor, manager::PasswordManager, timelock},
+    interop::hibp::{Hibp, HibpError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finding {
+    /// Found in that many breaches.
+    Breached(u64),
+    /// Expired at that time.
+    Expired(u64),
+    /// Expires at that time.
+    Expiring(u64),
+}
+
+impl Finding {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Breached(_) => "breached",
+            Self::Expired(_) => "expired",
+            Self::Expiring(_) => "expiring",
+        }
+    }
+
+    fn value(&self) -> u64 {
+        match *self {
+            Self::Breached(v) | Self::Expired(v) | Self::Expiring(v) => v,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Breached(count) => write!(f, "found in {} breaches", count),
+            Self::Expired(at) => write!(f, "expired on {}", timelock::format_time(at)),
+            Self::Expiring(at) => write!(f, "expires on {}", timelock::format_time(at)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub time: u64,
+    /// The hex tag of the entry, see `PasswordManager::key_tag`.
+    pub tag: String,
+    pub finding: Finding,
+}
+
+impl Notification {
+    /// Whether `self` tells the same as `other`, whenever either was found.
+    /// A breach count that grew is not news.
+    pub fn repeats(&self, other: &Self) -> bool {
+        self.tag == other.tag && self.finding.kind() == other.finding.kind()
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "time": self.time,
+            "tag": self.tag,
+            "kind": self.finding.kind(),
+            "value": self.finding.value(),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let number = |name| value.get(name).and_then(Value::as_u64);
+        let finding = match value.get("kind")?.as_str()? {
+            "breached" => Finding::Breached(number("value")?),
+            "expired" => Finding::Expired(number("value")?),
+            "expiring" => Finding::Expiring(number("value")?),
+            _ => return None,
+        };
+        Some(Self {
+            time: number("time")?,
+            tag: value.get("tag")?.as_str()?.to_string(),
+            finding,
+        })
+    }
+}
+
+/// The findings for every entry of `pm` at `now`, with the key they were
+/// found for. Breaches are only looked up with `hibp`; entries that cannot
+/// be read unattended are skipped. A failed lookup ends the lookups, it is
+/// returned with the findings, expiry for every entry included.
+pub fn audit<T: Encryprtor>(
+    pm: &mut PasswordManager<T>,
+    mut hibp: Option<&mut Hibp>,
+    now: u64,
+    expiring_within: u64,
+) -> (Vec<(String, Finding)>, Option<HibpError>) {
+    let keys: Vec<String> = pm.keys().into_iter().map(str::to_string).collect();
+    let (mut findings, mut error) = (Vec::new(), None);
+    for key in keys {
+        match pm.expires(&key) {
+            Some(at) if at <= now => findings.push((key.clone(), Finding::Expired(at))),
+            Some(at) if at - now <= expiring_within => {
+                findings.push((key.clone(), Finding::Expiring(at)))
+            }
+            _ => {}
+        }
+        let Some(lookup) = hibp.as_deref_mut() else {
+            continue;
+        };
+        let Ok(password) = pm.get_password(&key) else {
+            continue;
+        };
+        match lookup.count(password.as_bytes()) {
+            Ok(0) => {}
+            Ok(count) => findings.push((key, Finding::Breached(count))),
+            Err(err) => {
+                error = Some(err);
+                hibp = None;
+            }
+        }
+    }
+    (findings, error)
+}
+
+/// The notifications in the file at `path`, none if there is no file.
+/// Lines that cannot be read are skipped.
+pub fn load(path: &Path) -> io::Result<Vec<Notification>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(v) => v,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .filter_map(|value| Notification::from_json(&value))
+        .collect())
+}
+
+pub fn append(path: &Path, notifications: &[Notification]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    for notification in notifications {
+        writeln!(file, "{}", notification.to_json())?;
+    }
+    Ok(())
+}
+
+/// Shows a desktop notification with `notify-send`.
+pub fn notify_desktop(summary: &str, body: &str) -> io::Result<()> {
+    let status = Command::new("notify-send")
+        .args(["--app-name=mopm", summary, body])
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "notify-send exited with {}",
+            status
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn test_audit_expiry() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foobar"));
+        for key in ["old", "soon", "later", "never"] {
+            pm.store_password(key.to_string(), "secret").unwrap();
+        }
+        let now = 100 * DAY;
+        pm.expire_at("old", now - DAY).unwrap();
+        pm.expire_at("soon", now + DAY).unwrap();
+        pm.expire_at("later", now + 30 * DAY).unwrap();
+
+        let (findings, error) = audit(&mut pm, None, now, 14 * DAY);
+        assert!(error.is_none());
+        assert_eq!(
+            findings,
+            vec![
+                ("old".to_string(), Finding::Expired(now - DAY)),
+                ("soon".to_string(), Finding::Expiring(now + DAY)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_notifications_file() {
+        let dir = std::env::temp_dir().join(format!("mopm-monitor-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("notifications");
+        assert_eq!(load(&path).unwrap(), Vec::new());
+
+        let breached = Notification {
+            time: 1,
+            tag: "ab".to_string(),
+            finding: Finding::Breached(3),
+        };
+        let expired = Notification {
+            time: 2,
+            tag: "ab".to_string(),
+            finding: Finding::Expired(5),
+        };
+        append(&path, std::slice::from_ref(&breached)).unwrap();
+        append(&path, std::slice::from_ref(&expired)).unwrap();
+        assert_eq!(
+            load(&path).unwrap(),
+            vec![breached.clone(), expired.clone()]
+        );
+
+        let grown = Notification {
+            time: 3,
+            finding: Finding::Breached(7),
+            ..breached.clone()
+        };
+        assert!(grown.repeats(&breached));
+        assert!(!grown.repeats(&expired));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
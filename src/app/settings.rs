@@ -0,0 +1,174 @@
+//! The config file, read once at startup. It holds `name = value` lines,
+//! blank lines and `#` comments, and every setting belongs to one module,
+//! which is handed its own settings in the order they are written:
+//!
+//! - `on_*` and `hook_*` to the hooks,
+//! - `backup.*` to the backups,
+//! - `generate.*` to the generator profiles,
+//! - `vault.*` to the other vaults,
+//! - `rng`, `memsec` and `prompt_timeout`, where the last setting wins.
+//!
+//! A setting no module takes is an error.
+
+use std::{io, path::Path, time::Duration};
+
+use thiserror::Error;
+
+use super::hooks::{self, HookError, Hooks};
+use crate::{
+    cli::terminal::{self, TerminalError},
+    core::{
+        generator::{self, GeneratorError, Profiles},
+        memsec::{self, MemsecError},
+        rng::{self, RngError},
+    },
+    storage::{
+        backup::{self, BackupError, BackupPolicy},
+        vaults::{self, Vaults, VaultsError},
+    },
+};
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("cannot read the config file: `{0}`")]
+    IoError(#[from] io::Error),
+    #[error("invalid config line {0}: `{1}`")]
+    InvalidLine(usize, String),
+    #[error("unknown config setting `{0}`")]
+    UnknownSetting(String),
+    #[error("{0}")]
+    HookError(#[from] HookError),
+    #[error("{0}")]
+    BackupError(#[from] BackupError),
+    #[error("{0}")]
+    GeneratorError(#[from] GeneratorError),
+    #[error("{0}")]
+    VaultsError(#[from] VaultsError),
+    #[error("{0}")]
+    RngError(#[from] RngError),
+    #[error("{0}")]
+    MemsecError(#[from] MemsecError),
+    #[error("{0}")]
+    TerminalError(#[from] TerminalError),
+}
+
+#[derive(Debug, Default)]
+pub struct Settings {
+    pub hooks: Hooks,
+    pub backups: BackupPolicy,
+    pub profiles: Profiles,
+    pub vaults: Vaults,
+    pub rng: rng::Source,
+    pub memsec: bool,
+    pub prompt_timeout: Option<Duration>,
+}
+
+impl Settings {
+    /// Reads the config file at `path`, the defaults if it does not exist.
+    pub fn load(path: &Path) -> Result<Self, SettingsError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SettingsError> {
+        let mut hooks = Vec::new();
+        let mut backups = Vec::new();
+        let mut profiles = Vec::new();
+        let mut vaults = Vec::new();
+        let (mut rng, mut memsec, mut prompt_timeout) = (None, None, None);
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(SettingsError::InvalidLine(number + 1, line.to_string()));
+            };
+            let setting = (name.trim(), value.trim());
+            match setting.0 {
+                name if hooks::is_hook_setting(name) => hooks.push(setting),
+                name if backup::is_backup_setting(name) => backups.push(setting),
+                name if generator::is_profile_setting(name) => profiles.push(setting),
+                name if vaults::is_vault_setting(name) => vaults.push(setting),
+                rng::SETTING => rng = Some(setting.1),
+                memsec::SETTING => memsec = Some(setting.1),
+                terminal::TIMEOUT_SETTING => prompt_timeout = Some(setting.1),
+                name => return Err(SettingsError::UnknownSetting(name.to_string())),
+            }
+        }
+        Ok(Self {
+            hooks: Hooks::from_settings(&hooks)?,
+            backups: BackupPolicy::from_settings(&backups)?,
+            profiles: Profiles::from_settings(&profiles)?,
+            vaults: Vaults::from_settings(&vaults)?,
+            rng: rng.map_or(Ok(rng::Source::default()), rng::Source::parse)?,
+            memsec: memsec.map_or(Ok(false), memsec::parse)?,
+            prompt_timeout: prompt_timeout.map_or(Ok(None), terminal::parse_timeout)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let settings = Settings::parse(
+            "# comment\n\non_store = true\nbackup.keep=2\ngenerate.hex = 32 digits\n\
+             vault.work = /tmp/work.data\nrng = urandom\nmemsec = true\nmemsec = false\n\
+             prompt_timeout = 30\n",
+        )
+        .unwrap();
+        assert_eq!(
+            settings.hooks,
+            Hooks::from_settings(&[("on_store", "true")]).unwrap()
+        );
+        assert_eq!(
+            settings.backups,
+            BackupPolicy::from_settings(&[("backup.keep", "2")]).unwrap()
+        );
+        assert!(settings.profiles.get("hex").is_ok());
+        assert_eq!(settings.vaults.iter().count(), 1);
+        assert_eq!(settings.rng, rng::Source::Urandom);
+        assert!(!settings.memsec);
+        assert_eq!(settings.prompt_timeout, Some(Duration::from_secs(30)));
+
+        let defaults = Settings::parse("").unwrap();
+        assert_eq!(defaults.rng, rng::Source::default());
+        assert_eq!(defaults.prompt_timeout, None);
+        assert!(matches!(
+            Settings::parse("on_store"),
+            Err(SettingsError::InvalidLine(1, _))
+        ));
+        assert!(matches!(
+            Settings::parse("\nstore = true"),
+            Err(SettingsError::UnknownSetting(_))
+        ));
+        assert!(matches!(
+            Settings::parse("on_save = true"),
+            Err(SettingsError::HookError(HookError::UnknownSetting(_)))
+        ));
+        assert!(matches!(
+            Settings::parse("rng = dice"),
+            Err(SettingsError::RngError(_))
+        ));
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("mopm-settings-{}", std::process::id()));
+        assert!(Settings::load(&path)
+            .unwrap()
+            .vaults
+            .iter()
+            .next()
+            .is_none());
+        std::fs::write(&path, "prompt_timeout = 0\n").unwrap();
+        assert_eq!(Settings::load(&path).unwrap().prompt_timeout, None);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,5 +1,11 @@
+use std::iter::Peekable;
+
 use thiserror::Error;
 
+use crate::core::trace;
+
+use super::password::PasswordSourceKind;
+
 #[derive(Error, Debug)]
 pub enum CliError {
     #[error("invalid command specified")]
@@ -12,17 +18,109 @@ pub enum CliError {
 
 #[derive(Debug, Clone)]
 pub enum Command {
-    Init,
-    Clear,
-    Store(String, String),
-    Get(String),
+    Init(Options),
+    /// `--now`, `--undo` and `--session`.
+    Clear(bool, bool, bool),
+    /// A `None` value is read from stdin (`--stdin`), then `--force` and
+    /// `--session`.
+    Store(String, Option<String>, Options, bool, bool),
+    /// The keys, added to by `--from-file`, and the options.
+    Get(Vec<String>, Options),
+    Exists(String),
+    Touch(String),
+    /// The key and the number of the previous value to print.
+    History(String, Option<String>),
+    Lookup,
+    TerraformExternal,
+    /// The key, its command, its TTL and `--network`.
+    Lease(String, String, Option<String>, bool),
+    /// The key, its options and `--copy`.
+    Rotate(String, Options, bool),
+    Generate(Options),
+    Delete(String),
+    Edit(String, bool),
+    /// `--summary`.
+    Audit(Options, bool),
+    Compact(Option<String>),
+    /// The operation, its arguments and whether it is a dry run.
+    Refactor(String, String, Option<String>, bool),
+    RestoreBackup(String),
+    Scan(Option<String>, bool),
+    GuardHistory,
+    Hooks(String, bool),
+    Policy(String, Option<String>),
     Shield(String),
+    Panic(bool),
+    Catalog(String, Option<String>),
+    Open(String, Option<String>),
+    Verify(String, Options),
+    List(Option<String>, Options, bool, bool),
+    /// The pattern and `--all-vaults`.
+    Search(String, bool),
+    Match(String),
+    Info,
+    /// `--debug`.
+    Dump(bool),
+    Doctor,
+    Selftest,
+    Bench(Option<String>, bool),
+    Diff(String, String, bool, bool),
+    #[cfg(feature = "crdt")]
+    Merge(String, Options),
+    Menu(String),
+    #[cfg(feature = "fuse")]
+    Mount(String),
+    #[cfg(feature = "k8s")]
+    K8sSync(Options),
+    #[cfg(feature = "web")]
+    Web(Options),
+    #[cfg(feature = "ssh-agent")]
+    SshAgent(Options),
+    /// `--once` and `--offline`.
+    #[cfg(feature = "monitor")]
+    Monitor(Options, bool, bool),
+    /// `--clear`.
+    #[cfg(feature = "monitor")]
+    Notifications(bool),
+    /// The key to hand off, or with `--receive` the key to store the
+    /// entry under.
+    Handoff(Option<String>, bool),
+    #[cfg(feature = "share")]
+    ShareLink(String, Options),
+    #[cfg(feature = "share")]
+    ReceiveLink(String, Option<String>),
+    #[cfg(any(feature = "browser", feature = "hashivault"))]
+    Import(String, Options),
+    Export(Option<String>, Options),
+    Publish(Options),
+    Subscribe(String),
+    #[cfg(feature = "self-update")]
+    SelfUpdate(bool),
+    #[cfg(feature = "hidden-volume")]
+    Hidden(String),
+    #[cfg(feature = "otpauth")]
+    Otpauth(String, Options),
+    #[cfg(feature = "tpm")]
+    Tpm(String, Options),
 }
 
+/// Named `--name value` options following the positional arguments.
+pub type Options = std::collections::BTreeMap<String, String>;
+
 #[derive(Debug, Clone)]
 pub enum Argument {
     Help,
     Version,
+    AssumeYes,
+    NoColor,
+    NonInteractive,
+    PasswordSource(PasswordSourceKind),
+    Vault(String),
+    Out(String),
+    Message(String),
+    System,
+    CrashReport,
+    Trace(trace::Format),
 }
 
 impl<'a> TryFrom<&'a str> for Argument {
@@ -32,6 +130,40 @@ impl<'a> TryFrom<&'a str> for Argument {
         Ok(match value {
             "-v" | "--version" => Self::Version,
             "-h" | "--help" => Self::Help,
+            "-y" | "--yes" => Self::AssumeYes,
+            "--no-color" => Self::NoColor,
+            "--non-interactive" => Self::NonInteractive,
+            "--system" => Self::System,
+            "--crash-report" => Self::CrashReport,
+            "--trace" => Self::Trace(trace::Format::Human),
+            arg if arg.starts_with("--trace=") => Self::Trace(
+                arg.strip_prefix("--trace=")
+                    .and_then(trace::Format::parse)
+                    .ok_or_else(|| CliError::InvalidArgumentError(arg.to_string()))?,
+            ),
+            arg if arg.starts_with("--password-source=") => Self::PasswordSource(
+                arg.strip_prefix("--password-source=")
+                    .and_then(PasswordSourceKind::parse)
+                    .ok_or_else(|| CliError::InvalidArgumentError(arg.to_string()))?,
+            ),
+            arg if arg.starts_with("--vault=") => Self::Vault(
+                arg.strip_prefix("--vault=")
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| CliError::InvalidArgumentError(arg.to_string()))?
+                    .to_string(),
+            ),
+            arg if arg.starts_with("--out=") => Self::Out(
+                arg.strip_prefix("--out=")
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| CliError::InvalidArgumentError(arg.to_string()))?
+                    .to_string(),
+            ),
+            arg if arg.starts_with("--message=") => Self::Message(
+                arg.strip_prefix("--message=")
+                    .filter(|v| !v.trim().is_empty())
+                    .ok_or_else(|| CliError::InvalidArgumentError(arg.to_string()))?
+                    .to_string(),
+            ),
             arg => return Err(CliError::InvalidArgumentError(arg.to_string())),
         })
     }
@@ -42,38 +174,448 @@ impl<'a> TryFrom<&'a str> for Command {
 
     fn try_from(value: &'a str) -> Result<Self, Self::Error> {
         match value {
-            "init" => Ok(Self::Init),
-            "clear" => Ok(Self::Clear),
-            "store" => Ok(Self::Store("".to_string(), "".to_string())),
-            "get" => Ok(Self::Get("".to_string())),
+            "init" => Ok(Self::Init(Options::new())),
+            "clear" => Ok(Self::Clear(false, false, false)),
+            "store" => Ok(Self::Store(
+                "".to_string(),
+                None,
+                Options::new(),
+                false,
+                false,
+            )),
+            "get" => Ok(Self::Get(Vec::new(), Options::new())),
+            "exists" => Ok(Self::Exists("".to_string())),
+            "touch" => Ok(Self::Touch("".to_string())),
+            "history" => Ok(Self::History("".to_string(), None)),
+            "lookup" => Ok(Self::Lookup),
+            "terraform-external" => Ok(Self::TerraformExternal),
+            "lease" => Ok(Self::Lease("".to_string(), "".to_string(), None, false)),
+            "rotate" => Ok(Self::Rotate("".to_string(), Options::new(), false)),
+            "generate" => Ok(Self::Generate(Options::new())),
+            "delete" => Ok(Self::Delete("".to_string())),
+            "edit" => Ok(Self::Edit("".to_string(), false)),
+            "audit" => Ok(Self::Audit(Options::new(), false)),
+            "compact" => Ok(Self::Compact(None)),
+            "refactor" => Ok(Self::Refactor("".to_string(), "".to_string(), None, false)),
+            "restore-backup" => Ok(Self::RestoreBackup("".to_string())),
+            "scan" => Ok(Self::Scan(None, false)),
+            "guard-history" => Ok(Self::GuardHistory),
+            "hooks" => Ok(Self::Hooks("".to_string(), false)),
+            "policy" => Ok(Self::Policy("".to_string(), None)),
             "shield" => Ok(Self::Shield("".to_string())),
+            "panic" => Ok(Self::Panic(false)),
+            "catalog" => Ok(Self::Catalog("".to_string(), None)),
+            "open" => Ok(Self::Open("".to_string(), None)),
+            "verify" => Ok(Self::Verify("".to_string(), Options::new())),
+            "list" => Ok(Self::List(None, Options::new(), false, false)),
+            "search" => Ok(Self::Search("".to_string(), false)),
+            "match" => Ok(Self::Match("".to_string())),
+            "info" => Ok(Self::Info),
+            "dump" => Ok(Self::Dump(false)),
+            "doctor" => Ok(Self::Doctor),
+            "selftest" => Ok(Self::Selftest),
+            "bench" => Ok(Self::Bench(None, false)),
+            "diff" => Ok(Self::Diff("".to_string(), "".to_string(), false, false)),
+            #[cfg(feature = "crdt")]
+            "merge" => Ok(Self::Merge("".to_string(), Options::new())),
+            "menu" => Ok(Self::Menu("".to_string())),
+            #[cfg(feature = "fuse")]
+            "mount" => Ok(Self::Mount("".to_string())),
+            #[cfg(feature = "k8s")]
+            "k8s-sync" => Ok(Self::K8sSync(Options::new())),
+            #[cfg(feature = "web")]
+            "web" => Ok(Self::Web(Options::new())),
+            #[cfg(feature = "ssh-agent")]
+            "ssh-agent" => Ok(Self::SshAgent(Options::new())),
+            #[cfg(feature = "monitor")]
+            "monitor" => Ok(Self::Monitor(Options::new(), false, false)),
+            #[cfg(feature = "monitor")]
+            "notifications" => Ok(Self::Notifications(false)),
+            "handoff" => Ok(Self::Handoff(None, false)),
+            #[cfg(feature = "share")]
+            "share-link" => Ok(Self::ShareLink("".to_string(), Options::new())),
+            #[cfg(feature = "share")]
+            "receive-link" => Ok(Self::ReceiveLink("".to_string(), None)),
+            #[cfg(any(feature = "browser", feature = "hashivault"))]
+            "import" => Ok(Self::Import("".to_string(), Options::new())),
+            "export" => Ok(Self::Export(None, Options::new())),
+            "publish" => Ok(Self::Publish(Options::new())),
+            "subscribe" => Ok(Self::Subscribe("".to_string())),
+            #[cfg(feature = "self-update")]
+            "self-update" => Ok(Self::SelfUpdate(false)),
+            #[cfg(feature = "hidden-volume")]
+            "hidden" => Ok(Self::Hidden("".to_string())),
+            #[cfg(feature = "otpauth")]
+            "otpauth" => Ok(Self::Otpauth("".to_string(), Options::new())),
+            #[cfg(feature = "tpm")]
+            "tpm" => Ok(Self::Tpm("".to_string(), Options::new())),
             _ => Err(CliError::InvalidCommandError),
         }
     }
 }
 
 impl Command {
-    fn parse_extra(self, args: &mut impl Iterator<Item = String>) -> Result<Self, CliError> {
+    fn parse_extra(
+        self,
+        args: &mut Peekable<impl Iterator<Item = String>>,
+    ) -> Result<Self, CliError> {
         match self {
-            Self::Store(_, _) => Ok(Self::Store(
+            Self::Init(_) => Ok(Self::Init(self.parse_options(args)?)),
+            Self::Clear(_, _, _) => {
+                let (mut now, mut undo, mut session) = (false, false, false);
+                while let Some(flag) =
+                    args.next_if(|v| v == "--now" || v == "--undo" || v == "--session")
+                {
+                    now |= flag == "--now";
+                    undo |= flag == "--undo";
+                    session |= flag == "--session";
+                }
+                Ok(Self::Clear(now, undo, session))
+            }
+            Self::Store(_, _, _, _, _) => {
+                let key = args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self.clone(), "key: string, position: 1".to_string())
+                })?;
+                let value = match args.next_if(|v| v == "--stdin") {
+                    Some(_) => None,
+                    None => Some(args.next().ok_or_else(|| {
+                        CliError::MissingArgument(
+                            self.clone(),
+                            "value: string or --stdin, position: 2".to_string(),
+                        )
+                    })?),
+                };
+                let (options, flags) = self.parse_options_and_flags(args, &["force", "session"])?;
+                Ok(Self::Store(
+                    key,
+                    value,
+                    options,
+                    flags.contains(&"force"),
+                    flags.contains(&"session"),
+                ))
+            }
+            Self::Lookup => match args.next_if(|v| v == "--batch") {
+                Some(_) => Ok(self),
+                None => Err(CliError::MissingArgument(self, "--batch".to_string())),
+            },
+            Self::Get(_, _) => {
+                let mut keys = Vec::new();
+                while let Some(key) = args.next_if(|v| !v.starts_with('-')) {
+                    keys.push(key);
+                }
+                let options = self.parse_options(args)?;
+                if keys.is_empty() && !options.contains_key("from-file") {
+                    return Err(CliError::MissingArgument(
+                        self,
+                        "key: string or --from-file, position: 1".to_string(),
+                    ));
+                }
+                Ok(Self::Get(keys, options))
+            }
+            Self::Exists(_) => Ok(Self::Exists(args.next().ok_or(
+                CliError::MissingArgument(self, "key: string, position: 1".to_string()),
+            )?)),
+            Self::Touch(_) => Ok(Self::Touch(args.next().ok_or(
+                CliError::MissingArgument(self, "key: string, position: 1".to_string()),
+            )?)),
+            Self::History(_, _) => Ok(Self::History(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "key: string, position: 1".to_string())
+                })?,
+                args.next_if(|v| !v.starts_with('-')),
+            )),
+            Self::Rotate(_, _, _) => {
+                let key = args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self.clone(), "key: string, position: 1".to_string())
+                })?;
+                let (options, flags) = self.parse_options_and_flags(args, &["copy"])?;
+                Ok(Self::Rotate(key, options, !flags.is_empty()))
+            }
+            Self::Generate(_) => Ok(Self::Generate(self.parse_options(args)?)),
+            Self::Lease(_, _, _, _) => Ok(Self::Lease(
                 args.next().ok_or_else(|| {
                     CliError::MissingArgument(self.clone(), "key: string, position: 1".to_string())
                 })?,
-                args.next().ok_or(CliError::MissingArgument(
-                    self,
-                    "value: string, position: 2".to_string(),
-                ))?,
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "command: string, position: 2".to_string())
+                })?,
+                args.next_if(|v| !v.starts_with('-')),
+                args.next_if(|v| v == "--network").is_some(),
+            )),
+            Self::Edit(_, _) => Ok(Self::Edit(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "key: string, position: 1".to_string())
+                })?,
+                args.next_if(|v| v == "--insecure-tmp").is_some(),
+            )),
+            #[cfg(feature = "self-update")]
+            Self::SelfUpdate(_) => Ok(Self::SelfUpdate(args.next_if(|v| v == "--check").is_some())),
+            Self::Delete(_) => Ok(Self::Delete(args.next().ok_or(
+                CliError::MissingArgument(self, "key: string, position: 1".to_string()),
+            )?)),
+            Self::Audit(_, _) => {
+                let _ = args.next_if(|v| v == "--reuse");
+                let (options, flags) = self.parse_options_and_flags(args, &["summary"])?;
+                Ok(Self::Audit(options, !flags.is_empty()))
+            }
+            Self::Policy(_, _) => Ok(Self::Policy(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "key: string, position: 1".to_string())
+                })?,
+                args.next_if(|v| !v.starts_with('-')),
+            )),
+            Self::Search(_, _) => {
+                let mut all_vaults = args.next_if(|v| v == "--all-vaults").is_some();
+                let pattern = args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "pattern: string, position: 1".to_string())
+                })?;
+                all_vaults |= args.next_if(|v| v == "--all-vaults").is_some();
+                Ok(Self::Search(pattern, all_vaults))
+            }
+            Self::Scan(_, _) => {
+                let dir = args.next_if(|v| !v.starts_with('-'));
+                let staged = args.next_if(|v| v == "--staged").is_some();
+                Ok(Self::Scan(dir, staged))
+            }
+            Self::Hooks(_, _) => Ok(Self::Hooks(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "install-git, position: 1".to_string())
+                })?,
+                args.next_if(|v| v == "--force").is_some(),
+            )),
+            #[cfg(feature = "hidden-volume")]
+            Self::Hidden(_) => Ok(Self::Hidden(args.next().ok_or(
+                CliError::MissingArgument(self, "create, position: 1".to_string()),
+            )?)),
+            #[cfg(feature = "otpauth")]
+            Self::Otpauth(_, _) => Ok(Self::Otpauth(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self.clone(), "import, position: 1".to_string())
+                })?,
+                self.parse_options(args)?,
             )),
-            Self::Get(_) => Ok(Self::Get(args.next().ok_or(CliError::MissingArgument(
-                self,
-                "key: string, position: 1".to_string(),
-            ))?)),
+            #[cfg(feature = "tpm")]
+            Self::Tpm(_, _) => Ok(Self::Tpm(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(
+                        self.clone(),
+                        "enroll | remove | password, position: 1".to_string(),
+                    )
+                })?,
+                self.parse_options(args)?,
+            )),
+            Self::Refactor(_, _, _, _) => {
+                let operation = args
+                    .next_if(|v| v == "--rename-prefix" || v == "--retag")
+                    .ok_or_else(|| {
+                        CliError::MissingArgument(
+                            self.clone(),
+                            "--rename-prefix | --retag, position: 1".to_string(),
+                        )
+                    })?;
+                let from = args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self.clone(), "old, position: 2".to_string())
+                })?;
+                let to = args.next_if(|v| v != "--dry-run");
+                if to.is_none() && operation == "--rename-prefix" {
+                    return Err(CliError::MissingArgument(
+                        self,
+                        "new, position: 3".to_string(),
+                    ));
+                }
+                let dry_run = args.next_if(|v| v == "--dry-run").is_some();
+                Ok(Self::Refactor(operation, from, to, dry_run))
+            }
+            Self::Compact(_) => Ok(Self::Compact(args.next_if(|v| !v.starts_with('-')))),
+            Self::RestoreBackup(_) => Ok(Self::RestoreBackup(args.next().ok_or(
+                CliError::MissingArgument(self, "n: number, position: 1".to_string()),
+            )?)),
             Self::Shield(_) => Ok(Self::Shield(args.next().ok_or(
-                CliError::MissingArgument(self, "up | down, position: 1".to_string()),
+                CliError::MissingArgument(
+                    self,
+                    "up | down | status | reset, position: 1".to_string(),
+                ),
+            )?)),
+            Self::Panic(_) => Ok(Self::Panic(
+                args.next_if(|v| v == "--self-destruct").is_some(),
+            )),
+            Self::Catalog(_, _) => Ok(Self::Catalog(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "export | verify, position: 1".to_string())
+                })?,
+                args.next_if(|v| !v.starts_with('-')),
+            )),
+            Self::Open(_, _) => Ok(Self::Open(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "vault-file: path, position: 1".to_string())
+                })?,
+                args.next_if(|v| !v.starts_with('-')),
+            )),
+            Self::Verify(_, _) => Ok(Self::Verify(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(
+                        self.clone(),
+                        "vault-file: path, position: 1".to_string(),
+                    )
+                })?,
+                self.parse_options(args)?,
+            )),
+            Self::Diff(_, _, _, _) => {
+                let before = args.next().ok_or_else(|| {
+                    CliError::MissingArgument(
+                        self.clone(),
+                        "vault-a: path, position: 1".to_string(),
+                    )
+                })?;
+                let after = args.next().ok_or_else(|| {
+                    CliError::MissingArgument(
+                        self.clone(),
+                        "vault-b: path, position: 2".to_string(),
+                    )
+                })?;
+                let (mut show_values, mut json) = (false, false);
+                while let Some(flag) = args.next_if(|v| v == "--show-values" || v == "--json") {
+                    show_values |= flag == "--show-values";
+                    json |= flag == "--json";
+                }
+                Ok(Self::Diff(before, after, show_values, json))
+            }
+            Self::Bench(_, _) => Ok(Self::Bench(
+                args.next_if(|v| !v.starts_with('-')),
+                args.next_if(|v| v == "--apply").is_some(),
+            )),
+            Self::List(_, _, _, _) => {
+                let pattern = args.next_if(|v| !v.starts_with('-'));
+                let (mut options, mut reverse, mut json) = (Options::new(), false, false);
+                // The flags take no value, so they cannot go through `parse_options`.
+                while let Some(name) =
+                    args.next_if(|v| v.starts_with("--") && Argument::try_from(v.as_str()).is_err())
+                {
+                    match name.as_str() {
+                        "--reverse" => reverse = true,
+                        "--json" => json = true,
+                        _ => {
+                            let value = args.next().ok_or_else(|| {
+                                CliError::MissingArgument(self.clone(), format!("{}: value", name))
+                            })?;
+                            options.insert(name.trim_start_matches("--").to_string(), value);
+                        }
+                    }
+                }
+                Ok(Self::List(pattern, options, reverse, json))
+            }
+            Self::Match(_) => Ok(Self::Match(args.next().ok_or(
+                CliError::MissingArgument(self, "url, position: 1".to_string()),
+            )?)),
+            Self::Menu(_) => Ok(Self::Menu(
+                args.next_if(|v| !v.starts_with('-'))
+                    .unwrap_or_else(|| "copy".to_string()),
+            )),
+            #[cfg(feature = "crdt")]
+            Self::Merge(_, _) => Ok(Self::Merge(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(
+                        self.clone(),
+                        "vault-file: path, position: 1".to_string(),
+                    )
+                })?,
+                self.parse_options(args)?,
+            )),
+            #[cfg(feature = "fuse")]
+            Self::Mount(_) => Ok(Self::Mount(args.next().ok_or(
+                CliError::MissingArgument(self, "dir: path, position: 1".to_string()),
+            )?)),
+            #[cfg(feature = "k8s")]
+            Self::K8sSync(_) => Ok(Self::K8sSync(self.parse_options(args)?)),
+            #[cfg(feature = "web")]
+            Self::Web(_) => Ok(Self::Web(self.parse_options(args)?)),
+            Self::Dump(_) => Ok(Self::Dump(args.next_if(|v| v == "--debug").is_some())),
+            #[cfg(feature = "ssh-agent")]
+            Self::SshAgent(_) => Ok(Self::SshAgent(self.parse_options(args)?)),
+            #[cfg(feature = "monitor")]
+            Self::Monitor(_, _, _) => {
+                let (options, flags) = self.parse_options_and_flags(args, &["once", "offline"])?;
+                let (once, offline) = (flags.contains(&"once"), flags.contains(&"offline"));
+                Ok(Self::Monitor(options, once, offline))
+            }
+            #[cfg(feature = "monitor")]
+            Self::Notifications(_) => Ok(Self::Notifications(
+                args.next_if(|v| v == "--clear").is_some(),
+            )),
+            Self::Handoff(_, _) => {
+                let receive = args.next_if(|v| v == "--receive").is_some();
+                let key = args.next_if(|v| !v.starts_with('-'));
+                if !receive && key.is_none() {
+                    return Err(CliError::MissingArgument(
+                        self,
+                        "key: string, position: 1".to_string(),
+                    ));
+                }
+                Ok(Self::Handoff(key, receive))
+            }
+            #[cfg(feature = "share")]
+            Self::ShareLink(_, _) => Ok(Self::ShareLink(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self.clone(), "key: string, position: 1".to_string())
+                })?,
+                self.parse_options(args)?,
+            )),
+            #[cfg(feature = "share")]
+            Self::ReceiveLink(_, _) => Ok(Self::ReceiveLink(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self, "link: url, position: 1".to_string())
+                })?,
+                args.next_if(|v| !v.starts_with('-')),
+            )),
+            #[cfg(any(feature = "browser", feature = "hashivault"))]
+            Self::Import(_, _) => Ok(Self::Import(
+                args.next().ok_or_else(|| {
+                    CliError::MissingArgument(self.clone(), "format, position: 1".to_string())
+                })?,
+                self.parse_options(args)?,
+            )),
+            Self::Export(_, _) => Ok(Self::Export(
+                args.next_if(|v| !v.starts_with('-')),
+                self.parse_options(args)?,
+            )),
+            Self::Publish(_) => Ok(Self::Publish(self.parse_options(args)?)),
+            Self::Subscribe(_) => Ok(Self::Subscribe(args.next().ok_or(
+                CliError::MissingArgument(self, "mirror-file: path, position: 1".to_string()),
             )?)),
             _ => Ok(self),
         }
     }
+
+    fn parse_options(
+        &self,
+        args: &mut Peekable<impl Iterator<Item = String>>,
+    ) -> Result<Options, CliError> {
+        Ok(self.parse_options_and_flags(args, &[])?.0)
+    }
+
+    /// Like `parse_options`, where the names in `flags` take no value and
+    /// are returned apart when given.
+    fn parse_options_and_flags<'a>(
+        &self,
+        args: &mut Peekable<impl Iterator<Item = String>>,
+        flags: &[&'a str],
+    ) -> Result<(Options, Vec<&'a str>), CliError> {
+        let (mut options, mut given) = (Options::new(), Vec::new());
+        while let Some(name) =
+            args.next_if(|v| v.starts_with("--") && Argument::try_from(v.as_str()).is_err())
+        {
+            let name = name.trim_start_matches("--");
+            if let Some(flag) = flags.iter().find(|v| **v == name) {
+                given.push(*flag);
+                continue;
+            }
+            let value = args.next().ok_or_else(|| {
+                CliError::MissingArgument(self.clone(), format!("--{}: value", name))
+            })?;
+            options.insert(name.to_string(), value);
+        }
+        Ok((options, given))
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -81,6 +623,20 @@ pub struct Config {
     pub command: Option<Command>,
     pub show_help: bool,
     pub show_version: bool,
+    pub assume_yes: bool,
+    pub no_color: bool,
+    pub non_interactive: bool,
+    pub password_source: PasswordSourceKind,
+    /// The vault file to use instead of the installed one, `-` for stdin.
+    pub vault: Option<String>,
+    /// Where changes to the vault are written, `-` for stdout.
+    pub out: Option<String>,
+    /// Why the vault is being changed, handed to the hooks of the change.
+    pub message: Option<String>,
+    /// Use the vault of the machine, locked by the system key.
+    pub system: bool,
+    pub crash_report: bool,
+    pub trace: Option<trace::Format>,
 }
 
 impl Config {
@@ -95,33 +651,63 @@ impl Config {
     }
 
     fn from_iter(args: &mut impl Iterator<Item = String>) -> Result<Self, CliError> {
-        let mut args = args.skip(1);
-        let mut config = Self::default();
-        let maybe_command = match args.next() {
-            Some(v) => v,
-            None => return Ok(config),
-        };
-        let mut first_arg = vec![];
-
-        match Command::try_from(maybe_command.as_str()) {
-            Ok(command) => config = config.with_command(Some(command.parse_extra(&mut args)?)),
-            Err(_) if maybe_command.starts_with('-') => first_arg.push(maybe_command),
-            Err(err) => return Err(err),
+        let mut args = args.skip(1).peekable();
+        let mut config = Self::default().apply_leading(&mut args, |v| v.starts_with('-'))?;
+
+        if let Some(command) = args.next() {
+            // Also right after the command, as in `mopm get --system <key>`.
+            config = config.apply_leading(&mut args, |v| {
+                takes_value(v) || Argument::try_from(v).is_ok()
+            })?;
+            let command = Command::try_from(command.as_str())?.parse_extra(&mut args)?;
+            config = config.with_command(Some(command));
         }
 
-        first_arg
-            .into_iter()
-            .chain(args)
-            .try_fold(config, |acc, v| {
-                Ok(acc.apply_argument(Argument::try_from(v.as_str())?))
-            })
+        args.try_fold(config, |acc, v| {
+            Ok(acc.apply_argument(Argument::try_from(v.as_str())?))
+        })
+    }
+
+    /// Applies the arguments at the front of `args` for which `is_argument`
+    /// holds.
+    fn apply_leading(
+        mut self,
+        args: &mut Peekable<impl Iterator<Item = String>>,
+        is_argument: impl Fn(&str) -> bool,
+    ) -> Result<Self, CliError> {
+        while let Some(mut argument) = args.next_if(|v| is_argument(v)) {
+            // `--vault <path>` and `--out <path>` take `-` as a value.
+            if takes_value(&argument) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| CliError::InvalidArgumentError(argument.clone()))?;
+                argument = format!("{}={}", argument, value);
+            }
+            self = self.apply_argument(Argument::try_from(argument.as_str())?);
+        }
+        Ok(self)
     }
 
     pub fn apply_argument(mut self, argument: Argument) -> Self {
         match argument {
             Argument::Version => self.show_version = true,
             Argument::Help => self.show_help = true,
+            Argument::AssumeYes => self.assume_yes = true,
+            Argument::NoColor => self.no_color = true,
+            Argument::NonInteractive => self.non_interactive = true,
+            Argument::PasswordSource(kind) => self.password_source = kind,
+            Argument::Vault(path) => self.vault = Some(path),
+            Argument::Out(path) => self.out = Some(path),
+            Argument::Message(message) => self.message = Some(message),
+            Argument::System => self.system = true,
+            Argument::CrashReport => self.crash_report = true,
+            Argument::Trace(format) => self.trace = Some(format),
         }
         self
     }
 }
+
+/// Whether `argument` takes its value as the next argument.
+fn takes_value(argument: &str) -> bool {
+    matches!(argument, "--vault" | "--out" | "--message")
+}
@@ -0,0 +1,188 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use nix::sys::statfs::{statfs, TMPFS_MAGIC};
+use thiserror::Error;
+
+use crate::core::rng;
+
+const EDITOR_ENVS: [&str; 2] = ["VISUAL", "EDITOR"];
+const DEFAULT_EDITOR: &str = "vi";
+const SHM_DIR: &str = "/dev/shm";
+
+#[derive(Error, Debug)]
+pub enum EditorError {
+    #[error("no tmpfs directory is available for the temporary file")]
+    NoTmpfs,
+    #[error("error while running the editor: `{0}`")]
+    IoError(#[from] io::Error),
+    #[error("the editor exited with {0}")]
+    EditorFailed(String),
+    #[error("the first line must contain the password")]
+    MissingPassword,
+    #[error("invalid line `{0}`, expected `<field>: <value>`")]
+    InvalidLine(String),
+    #[error("unknown field `{0}`")]
+    UnknownField(String),
+}
+
+/// An entry as text: the password on the first line followed by one
+/// `<field>: <value>` line per field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    pub password: String,
+    pub fields: Vec<(String, String)>,
+}
+
+impl Document {
+    pub fn render(&self) -> String {
+        let mut text = format!("{}\n", self.password);
+        for (name, value) in &self.fields {
+            text.push_str(&format!("{}: {}\n", name, value));
+        }
+        text
+    }
+
+    /// Parses `text`, accepting only the fields in `known`.
+    pub fn parse(text: &str, known: &[&str]) -> Result<Self, EditorError> {
+        let mut lines = text.lines();
+        let password = lines
+            .next()
+            .filter(|v| !v.is_empty())
+            .ok_or(EditorError::MissingPassword)?
+            .to_string();
+
+        let mut fields = vec![];
+        for line in lines.filter(|v| !v.trim().is_empty()) {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| EditorError::InvalidLine(line.to_string()))?;
+            let name = name.trim();
+            if !known.contains(&name) {
+                return Err(EditorError::UnknownField(name.to_string()));
+            }
+            fields.push((name.to_string(), value.trim().to_string()));
+        }
+        Ok(Self { password, fields })
+    }
+}
+
+pub struct Editor;
+
+impl Editor {
+    /// Writes `text` to a private temporary file, opens it in `$VISUAL` or
+    /// `$EDITOR` and returns the saved contents. The file lives on a tmpfs
+    /// so the plaintext never reaches a disk, unless `insecure_tmp` allows
+    /// falling back to the regular temporary directory.
+    pub fn edit(text: &str, insecure_tmp: bool) -> Result<String, EditorError> {
+        let dir = match Self::tmpfs_dir() {
+            Some(dir) => dir,
+            None if insecure_tmp => std::env::temp_dir(),
+            None => return Err(EditorError::NoTmpfs),
+        };
+        let path = dir.join(format!("mopm-{}.txt", Self::random_suffix()));
+
+        let result = Self::write_private(&path, text.as_bytes())
+            .map_err(EditorError::from)
+            .and_then(|_| Self::run_editor(&path))
+            .and_then(|_| std::fs::read_to_string(&path).map_err(EditorError::from));
+        let _ = Self::shred(&path);
+        result
+    }
+
+    fn tmpfs_dir() -> Option<PathBuf> {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .into_iter()
+            .chain([PathBuf::from(SHM_DIR)])
+            .find(|dir| statfs(dir).is_ok_and(|v| v.filesystem_type() == TMPFS_MAGIC))
+    }
+
+    fn random_suffix() -> String {
+        let mut bytes = [0; 8];
+        rng::fill(&mut bytes);
+        hex::encode(bytes)
+    }
+
+    fn write_private(path: &Path, data: &[u8]) -> io::Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(data)
+    }
+
+    fn run_editor(path: &Path) -> Result<(), EditorError> {
+        let editor = EDITOR_ENVS
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+            .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(path)
+            .status()?;
+        if !status.success() {
+            return Err(EditorError::EditorFailed(status.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Overwrites the file with zeros before removing it.
+    fn shred(path: &Path) -> io::Result<()> {
+        if let Ok(metadata) = std::fs::metadata(path) {
+            let mut file = OpenOptions::new().write(true).open(path)?;
+            file.write_all(&vec![0; metadata.len() as usize])?;
+            file.sync_all()?;
+        }
+        std::fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: [&str; 2] = ["username", "url"];
+
+    #[test]
+    fn test_document() {
+        let document = Document {
+            password: "hunter2".to_string(),
+            fields: vec![
+                ("username".to_string(), "me".to_string()),
+                ("url".to_string(), "".to_string()),
+            ],
+        };
+        let text = document.render();
+
+        assert_eq!(text, "hunter2\nusername: me\nurl: \n");
+        assert_eq!(Document::parse(&text, &KNOWN).unwrap(), document);
+        assert!(matches!(
+            Document::parse("\nusername: me", &KNOWN),
+            Err(EditorError::MissingPassword)
+        ));
+        assert!(matches!(
+            Document::parse("pw\nusername", &KNOWN),
+            Err(EditorError::InvalidLine(_))
+        ));
+        assert!(matches!(
+            Document::parse("pw\nother: x", &KNOWN),
+            Err(EditorError::UnknownField(_))
+        ));
+    }
+
+    #[test]
+    fn test_edit() {
+        std::env::set_var("VISUAL", "sed -i s/old/new/");
+        let edited = Editor::edit("old\n", true).unwrap();
+        assert_eq!(edited, "new\n");
+    }
+}
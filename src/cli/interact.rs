@@ -0,0 +1,291 @@
+use std::io::{self, BufRead, IsTerminal};
+
+use thiserror::Error;
+
+use crate::log::logger::Logger;
+
+use super::{
+    password::{PasswordSource, TtySource},
+    terminal::Terminal,
+};
+
+const NO_COLOR_ENV: &str = "NO_COLOR";
+
+#[derive(Error, Debug)]
+pub enum InteractError {
+    #[error("input is required, but mopm runs non-interactively")]
+    InputRequired,
+    #[error("error while reading the answer: `{0}`")]
+    IoError(#[from] io::Error),
+}
+
+/// Every question mopm asks goes through here so that `--yes` and
+/// `--non-interactive` behave the same for all commands.
+pub struct Interact {
+    assume_yes: bool,
+    non_interactive: bool,
+    password_source: Box<dyn PasswordSource>,
+}
+
+impl Interact {
+    pub fn new(assume_yes: bool, non_interactive: bool) -> Self {
+        Self {
+            assume_yes,
+            non_interactive,
+            password_source: Box::new(TtySource),
+        }
+    }
+
+    pub fn password_source(mut self, source: Box<dyn PasswordSource>) -> Self {
+        self.password_source = source;
+        self
+    }
+
+    /// Reads the master password from the configured source. Sources
+    /// that need no typing still work with `--non-interactive`.
+    pub fn password<T: term::Terminal>(
+        &self,
+        logger: &mut Logger<T>,
+        prompt: &str,
+    ) -> Result<String, InteractError> {
+        if self.non_interactive && self.password_source.is_interactive() {
+            return Err(InteractError::InputRequired);
+        }
+        logger.flush();
+        Ok(self.password_source.read_password(prompt)?)
+    }
+
+    /// Whether a wrong master password can be typed again.
+    pub fn can_retry_password(&self) -> bool {
+        !self.non_interactive && self.password_source.is_interactive()
+    }
+
+    /// Whether questions reach someone at a terminal, rather than being
+    /// answered by `--yes` or refused.
+    #[cfg(feature = "crdt")]
+    pub fn can_ask(&self) -> bool {
+        self.preset_answer().is_none() && io::stdin().is_terminal()
+    }
+
+    /// Reads a secret other than the master password from stdin, without
+    /// echoing it when stdin is a terminal. Only the first line is used.
+    pub fn secret<T: term::Terminal>(
+        &self,
+        logger: &mut Logger<T>,
+        prompt: &str,
+    ) -> Result<String, InteractError> {
+        let stdin = io::stdin();
+        if stdin.is_terminal() {
+            if self.non_interactive {
+                return Err(InteractError::InputRequired);
+            }
+            logger.flush();
+            return Ok(Terminal::prompt_password(prompt)?);
+        }
+        let mut line = String::new();
+        stdin.lock().read_line(&mut line)?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// Reads a line typed after `prompt`, or the next line of stdin when it
+    /// is not a terminal. Empty at the end of the input.
+    pub fn line<T: term::Terminal>(
+        &self,
+        logger: &mut Logger<T>,
+        prompt: &str,
+    ) -> Result<String, InteractError> {
+        let stdin = io::stdin();
+        if stdin.is_terminal() {
+            if self.non_interactive {
+                return Err(InteractError::InputRequired);
+            }
+            logger.info(prompt.as_ref());
+            logger.flush();
+        }
+        let mut line = String::new();
+        stdin.lock().read_line(&mut line)?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// Asks a yes/no `question`, defaulting to no. Always yes with
+    /// `--yes`, and an error when no answer can be asked for.
+    pub fn confirm<T: term::Terminal>(
+        &self,
+        logger: &mut Logger<T>,
+        question: &str,
+    ) -> Result<bool, InteractError> {
+        if let Some(answer) = self.preset_answer() {
+            return answer;
+        }
+        logger.info(format!("{} [y/N] ", question).as_ref());
+        logger.flush();
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(is_yes(&answer))
+    }
+
+    /// Asks to type `phrase` back before something that cannot be undone
+    /// by answering yes by mistake. Always confirmed with `--yes`.
+    pub fn confirm_phrase<T: term::Terminal>(
+        &self,
+        logger: &mut Logger<T>,
+        question: &str,
+        phrase: &str,
+    ) -> Result<bool, InteractError> {
+        if let Some(answer) = self.preset_answer() {
+            return answer;
+        }
+        logger.info(format!("{}\nType `{}` to confirm: ", question, phrase).as_ref());
+        logger.flush();
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(answer.trim() == phrase)
+    }
+
+    /// Asks to pick one of `choices` by name or first letter, returning its
+    /// index. Anything else picks the first choice, as does `--yes`. An
+    /// uppercase first letter, or the name after `all`, picks the choice
+    /// for the remaining questions as well, which is returned alongside.
+    #[cfg(any(
+        feature = "browser",
+        feature = "crdt",
+        feature = "hashivault",
+        feature = "otpauth"
+    ))]
+    pub fn choose_for_all<T: term::Terminal>(
+        &self,
+        logger: &mut Logger<T>,
+        question: &str,
+        choices: &[&str],
+    ) -> Result<(usize, bool), InteractError> {
+        if let Some(answer) = self.preset_answer() {
+            return answer.map(|_| (0, false));
+        }
+        let options: Vec<String> = choices
+            .iter()
+            .map(|v| format!("[{}]{}", &v[..1], &v[1..]))
+            .collect();
+        logger.info(
+            format!(
+                "{} {} (uppercase for all remaining) ",
+                question,
+                options.join(", ")
+            )
+            .as_ref(),
+        );
+        logger.flush();
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(pick_for_all(&answer, choices))
+    }
+
+    fn preset_answer(&self) -> Option<Result<bool, InteractError>> {
+        if self.assume_yes {
+            Some(Ok(true))
+        } else if self.non_interactive {
+            Some(Err(InteractError::InputRequired))
+        } else {
+            None
+        }
+    }
+}
+
+/// Colors are used unless disabled with `--no-color` or the `NO_COLOR`
+/// environment variable.
+pub fn use_color(no_color: bool) -> bool {
+    !no_color && std::env::var_os(NO_COLOR_ENV).is_none_or(|v| v.is_empty())
+}
+
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+fn pick(answer: &str, choices: &[&str]) -> usize {
+    let answer = answer.trim().to_lowercase();
+    choices
+        .iter()
+        .position(|v| *v == answer || (answer.len() == 1 && v.starts_with(&answer)))
+        .unwrap_or(0)
+}
+
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+fn pick_for_all(answer: &str, choices: &[&str]) -> (usize, bool) {
+    let answer = answer.trim();
+    match answer.strip_prefix("all ") {
+        Some(rest) => (pick(rest, choices), true),
+        None => (
+            pick(answer, choices),
+            answer.len() == 1 && answer.chars().all(|v| v.is_uppercase()),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_yes() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes(" YES "));
+        assert!(!is_yes("\n"));
+        assert!(!is_yes("no"));
+    }
+
+    #[cfg(any(
+        feature = "browser",
+        feature = "crdt",
+        feature = "hashivault",
+        feature = "otpauth"
+    ))]
+    #[test]
+    fn test_pick() {
+        let choices = ["skip", "overwrite", "keep both"];
+        assert_eq!(pick("o\n", &choices), 1);
+        assert_eq!(pick("Keep both", &choices), 2);
+        assert_eq!(pick("\n", &choices), 0);
+        assert_eq!(pick("overwr", &choices), 0);
+    }
+
+    #[cfg(any(
+        feature = "browser",
+        feature = "crdt",
+        feature = "hashivault",
+        feature = "otpauth"
+    ))]
+    #[test]
+    fn test_pick_for_all() {
+        let choices = ["mine", "theirs", "keep both"];
+        assert_eq!(pick_for_all("t\n", &choices), (1, false));
+        assert_eq!(pick_for_all("T\n", &choices), (1, true));
+        assert_eq!(pick_for_all("all keep both", &choices), (2, true));
+        assert_eq!(pick_for_all("\n", &choices), (0, false));
+    }
+
+    #[test]
+    fn test_preset_answer() {
+        assert!(matches!(
+            Interact::new(true, true).preset_answer(),
+            Some(Ok(true))
+        ));
+        assert!(matches!(
+            Interact::new(false, true).preset_answer(),
+            Some(Err(InteractError::InputRequired))
+        ));
+        assert!(Interact::new(false, false).preset_answer().is_none());
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+const MENU_ENV: &str = "MOPM_MENU";
+const CLIPBOARD_ENV: &str = "MOPM_CLIPBOARD";
+const AUTOTYPE_ENV: &str = "MOPM_AUTOTYPE";
+
+pub struct Menu;
+
+impl Menu {
+    /// Pipes `items` into the configured dmenu-compatible program and returns
+    /// the selected line, or `None` if the selection was cancelled.
+    pub fn select(items: &[&str]) -> io::Result<Option<String>> {
+        let output = Self::run(&Self::menu_command(), items.join("\n").as_bytes())?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        let selection = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(Some(selection).filter(|v| !v.is_empty()))
+    }
+
+    pub fn copy(value: &str) -> io::Result<()> {
+        Self::run_checked(&Self::clipboard_command(), value.as_bytes())
+    }
+
+    /// Replaces the clipboard contents with nothing.
+    pub fn clear_clipboard() -> io::Result<()> {
+        Self::copy("")
+    }
+
+    pub fn autotype(value: &str) -> io::Result<()> {
+        Self::run_checked(&Self::autotype_command(), value.as_bytes())
+    }
+
+    fn menu_command() -> String {
+        std::env::var(MENU_ENV).unwrap_or_else(|_| "dmenu".to_string())
+    }
+
+    fn clipboard_command() -> String {
+        std::env::var(CLIPBOARD_ENV).unwrap_or_else(|_| {
+            if Self::is_wayland() {
+                "wl-copy".to_string()
+            } else {
+                "xclip -selection clipboard".to_string()
+            }
+        })
+    }
+
+    fn autotype_command() -> String {
+        std::env::var(AUTOTYPE_ENV).unwrap_or_else(|_| {
+            if Self::is_wayland() {
+                "wtype -".to_string()
+            } else {
+                "xdotool type --clearmodifiers --file -".to_string()
+            }
+        })
+    }
+
+    fn is_wayland() -> bool {
+        std::env::var_os("WAYLAND_DISPLAY").is_some()
+    }
+
+    fn run_checked(command: &str, input: &[u8]) -> io::Result<()> {
+        let output = Self::run(command, input)?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "`{}` exited with {}",
+                command, output.status
+            )));
+        }
+        Ok(())
+    }
+
+    fn run(command: &str, input: &[u8]) -> io::Result<std::process::Output> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input)?;
+        }
+        child.wait_with_output()
+    }
+}
@@ -1,2 +1,8 @@
 pub mod config;
+pub mod editor;
+pub mod interact;
+pub mod menu;
+#[cfg(feature = "pam")]
+pub mod pam;
+pub mod password;
 pub mod terminal;
@@ -0,0 +1,273 @@
+//! Checks the login of the current user through PAM before the master
+//! password is released from the desktop keyring, for
+//! `--password-source=pam`. The PAM stack of the service decides what the
+//! check is, e.g. the login password or a fingerprint with pam_fprintd.
+//!
+//! Threat model: this is a convenience unlock, not an extra layer of
+//! protection for the vault. The master password still sits in the
+//! keyring, and any process running as the user can ask the keyring for
+//! it without going through PAM. What the check adds is that someone at
+//! an unattended, logged-in session cannot unlock the vault through mopm
+//! without knowing the login. The vault is only as strong as the login,
+//! so weak login passwords make this weaker than typing the master
+//! password.
+//!
+//! libpam is loaded at runtime, so building with the `pam` feature needs
+//! no PAM headers, and a missing libpam is an error when unlocking.
+
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    io, ptr,
+};
+
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::terminal::Terminal;
+
+const LIBRARY: &CStr = c"libpam.so.0";
+const SERVICE_ENV: &str = "MOPM_PAM_SERVICE";
+const DEFAULT_SERVICE: &str = "login";
+
+const PAM_SUCCESS: c_int = 0;
+const PAM_BUF_ERR: c_int = 5;
+const PAM_CONV_ERR: c_int = 19;
+const PAM_PROMPT_ECHO_OFF: c_int = 1;
+const PAM_PROMPT_ECHO_ON: c_int = 2;
+const PAM_ERROR_MSG: c_int = 3;
+const PAM_TEXT_INFO: c_int = 4;
+
+#[derive(Error, Debug)]
+pub enum PamError {
+    #[error("cannot load libpam: `{0}`")]
+    LoadError(String),
+    #[error("cannot find the current user")]
+    UnknownUser,
+    #[error("PAM authentication failed: `{0}`")]
+    AuthenticationFailed(String),
+}
+
+impl From<PamError> for io::Error {
+    fn from(value: PamError) -> Self {
+        io::Error::new(io::ErrorKind::PermissionDenied, value)
+    }
+}
+
+#[repr(C)]
+struct PamMessage {
+    msg_style: c_int,
+    msg: *const c_char,
+}
+
+#[repr(C)]
+struct PamResponse {
+    resp: *mut c_char,
+    resp_retcode: c_int,
+}
+
+type Conversation = extern "C" fn(
+    num_msg: c_int,
+    msg: *mut *const PamMessage,
+    resp: *mut *mut PamResponse,
+    appdata_ptr: *mut c_void,
+) -> c_int;
+
+#[repr(C)]
+struct PamConv {
+    conv: Conversation,
+    appdata_ptr: *mut c_void,
+}
+
+type PamStart = unsafe extern "C" fn(
+    service: *const c_char,
+    user: *const c_char,
+    conv: *const PamConv,
+    pamh: *mut *mut c_void,
+) -> c_int;
+type PamCall = unsafe extern "C" fn(pamh: *mut c_void, flags: c_int) -> c_int;
+type PamStrerror = unsafe extern "C" fn(pamh: *mut c_void, errnum: c_int) -> *const c_char;
+
+struct Library {
+    handle: *mut c_void,
+    start: PamStart,
+    authenticate: PamCall,
+    acct_mgmt: PamCall,
+    end: PamCall,
+    strerror: PamStrerror,
+}
+
+impl Library {
+    fn load() -> Result<Self, PamError> {
+        // SAFETY: the symbols are looked up by their documented names and
+        // cast to their documented signatures.
+        unsafe {
+            let handle = libc::dlopen(LIBRARY.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+            if handle.is_null() {
+                return Err(PamError::LoadError(dl_error()));
+            }
+            let symbol = |name: &CStr| {
+                let symbol = libc::dlsym(handle, name.as_ptr());
+                if symbol.is_null() {
+                    Err(PamError::LoadError(dl_error()))
+                } else {
+                    Ok(symbol)
+                }
+            };
+            let library = (|| {
+                Ok(Self {
+                    handle,
+                    start: std::mem::transmute::<*mut c_void, PamStart>(symbol(c"pam_start")?),
+                    authenticate: std::mem::transmute::<*mut c_void, PamCall>(symbol(
+                        c"pam_authenticate",
+                    )?),
+                    acct_mgmt: std::mem::transmute::<*mut c_void, PamCall>(symbol(
+                        c"pam_acct_mgmt",
+                    )?),
+                    end: std::mem::transmute::<*mut c_void, PamCall>(symbol(c"pam_end")?),
+                    strerror: std::mem::transmute::<*mut c_void, PamStrerror>(symbol(
+                        c"pam_strerror",
+                    )?),
+                })
+            })();
+            if library.is_err() {
+                libc::dlclose(handle);
+            }
+            library
+        }
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        // SAFETY: the handle came from dlopen and no symbol outlives self.
+        unsafe {
+            libc::dlclose(self.handle);
+        }
+    }
+}
+
+fn dl_error() -> String {
+    // SAFETY: dlerror returns null or a valid C string.
+    let error = unsafe { libc::dlerror() };
+    if error.is_null() {
+        "unknown error".to_string()
+    } else {
+        // SAFETY: checked for null above.
+        unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Authenticates the current user with the PAM service `MOPM_PAM_SERVICE`,
+/// `login` by default, and checks that the account is usable.
+pub fn authenticate() -> Result<(), PamError> {
+    let service = std::env::var(SERVICE_ENV).unwrap_or_else(|_| DEFAULT_SERVICE.to_string());
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())
+        .ok()
+        .flatten()
+        .ok_or(PamError::UnknownUser)?;
+    let service = CString::new(service).map_err(|_| PamError::UnknownUser)?;
+    let name = CString::new(user.name).map_err(|_| PamError::UnknownUser)?;
+
+    let library = Library::load()?;
+    let conv = PamConv {
+        conv: converse,
+        appdata_ptr: ptr::null_mut(),
+    };
+    let mut pamh = ptr::null_mut();
+    // SAFETY: every pointer is valid for the duration of the calls, and
+    // pamh is only used between pam_start and pam_end.
+    unsafe {
+        let status = (library.start)(service.as_ptr(), name.as_ptr(), &conv, &mut pamh);
+        if status != PAM_SUCCESS {
+            return Err(PamError::AuthenticationFailed(format!(
+                "pam_start returned {}",
+                status
+            )));
+        }
+        let mut status = (library.authenticate)(pamh, 0);
+        if status == PAM_SUCCESS {
+            status = (library.acct_mgmt)(pamh, 0);
+        }
+        let result = match status {
+            PAM_SUCCESS => Ok(()),
+            status => Err(PamError::AuthenticationFailed(
+                CStr::from_ptr((library.strerror)(pamh, status))
+                    .to_string_lossy()
+                    .into_owned(),
+            )),
+        };
+        (library.end)(pamh, status);
+        result
+    }
+}
+
+/// Answers the prompts of the PAM modules on the terminal, never on
+/// stdin. Responses are allocated with malloc, PAM frees them.
+extern "C" fn converse(
+    num_msg: c_int,
+    msg: *mut *const PamMessage,
+    resp: *mut *mut PamResponse,
+    _appdata_ptr: *mut c_void,
+) -> c_int {
+    let Ok(count) = usize::try_from(num_msg) else {
+        return PAM_CONV_ERR;
+    };
+    // SAFETY: PAM passes `num_msg` message pointers and a response pointer
+    // to fill, and takes ownership of the calloc'd responses on success.
+    unsafe {
+        let responses = libc::calloc(count, std::mem::size_of::<PamResponse>()) as *mut PamResponse;
+        if responses.is_null() {
+            return PAM_BUF_ERR;
+        }
+        for index in 0..count {
+            let message = &**msg.add(index);
+            let text = if message.msg.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(message.msg).to_string_lossy().into_owned()
+            };
+            let answer = match message.msg_style {
+                PAM_PROMPT_ECHO_OFF => Terminal::prompt_password(&text).ok().map(Zeroizing::new),
+                PAM_PROMPT_ECHO_ON => Terminal::prompt_line(&text).ok().map(Zeroizing::new),
+                PAM_ERROR_MSG | PAM_TEXT_INFO => {
+                    eprintln!("{}", text);
+                    continue;
+                }
+                _ => None,
+            };
+            let Some(answer) = answer.and_then(|v| nul_terminated(&v)) else {
+                free_responses(responses, index);
+                return PAM_CONV_ERR;
+            };
+            *responses.add(index) = PamResponse {
+                resp: libc::strdup(answer.as_ptr() as *const c_char),
+                resp_retcode: 0,
+            };
+        }
+        *resp = responses;
+    }
+    PAM_SUCCESS
+}
+
+/// Frees the first `count` responses and the array itself.
+unsafe fn free_responses(responses: *mut PamResponse, count: usize) {
+    for index in 0..count {
+        libc::free((*responses.add(index)).resp as *mut c_void);
+    }
+    libc::free(responses as *mut c_void);
+}
+
+/// `value` followed by a nul, in memory cleared once dropped. `None` if
+/// it holds a nul already.
+fn nul_terminated(value: &str) -> Option<Zeroizing<Vec<u8>>> {
+    if value.contains('\0') {
+        return None;
+    }
+    // Allocated in full first, so that no reallocation leaves a copy.
+    let mut bytes = Zeroizing::new(Vec::with_capacity(value.len() + 1));
+    bytes.extend_from_slice(value.as_bytes());
+    bytes.push(0);
+    Some(bytes)
+}
@@ -0,0 +1,313 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{Command, Stdio},
+};
+
+use super::terminal::Terminal;
+use crate::exec::sandbox::Sandbox;
+
+const PINENTRY_ENV: &str = "MOPM_PINENTRY";
+const KEYRING_ENV: &str = "MOPM_KEYRING_COMMAND";
+const DEFAULT_KEYRING_COMMAND: &str = "secret-tool lookup service mopm";
+const FALLBACK_WARNING: &str = "Cannot read the password, trying the terminal instead: ";
+/// What pinentries need to show a window or draw on the terminal.
+const PINENTRY_PASSED_ENV: [&str; 12] = [
+    "PATH",
+    "HOME",
+    "LANG",
+    "LC_ALL",
+    "TERM",
+    "GPG_TTY",
+    "DISPLAY",
+    "XAUTHORITY",
+    "WAYLAND_DISPLAY",
+    "XDG_RUNTIME_DIR",
+    "XDG_SESSION_TYPE",
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+/// Where the master password comes from.
+pub trait PasswordSource: Send {
+    fn read_password(&self, prompt: &str) -> io::Result<String>;
+
+    /// Whether reading the password needs a person to type it.
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+/// The kind of password source selected with `--password-source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PasswordSourceKind {
+    /// Pinentry when `MOPM_PINENTRY` is set, falling back to the terminal.
+    #[default]
+    Auto,
+    Tty,
+    Pinentry,
+    Keyring,
+    /// The keyring, after the user passed PAM authentication.
+    #[cfg(feature = "pam")]
+    Pam,
+    Fd(u32),
+}
+
+impl PasswordSourceKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "auto" => Self::Auto,
+            "tty" => Self::Tty,
+            "pinentry" => Self::Pinentry,
+            "keyring" => Self::Keyring,
+            #[cfg(feature = "pam")]
+            "pam" => Self::Pam,
+            fd => Self::Fd(fd.strip_prefix("fd:")?.parse().ok()?),
+        })
+    }
+
+    pub fn build(self) -> Box<dyn PasswordSource> {
+        match self {
+            Self::Auto => match std::env::var(PINENTRY_ENV) {
+                Ok(program) => Box::new(Fallback(
+                    Box::new(PinentrySource::new(program)),
+                    Box::new(TtySource),
+                )),
+                Err(_) => Box::new(TtySource),
+            },
+            Self::Tty => Box::new(TtySource),
+            Self::Pinentry => Box::new(PinentrySource::new(
+                std::env::var(PINENTRY_ENV).unwrap_or_else(|_| "pinentry".to_string()),
+            )),
+            Self::Keyring => Box::new(KeyringSource),
+            #[cfg(feature = "pam")]
+            Self::Pam => Box::new(PamSource),
+            Self::Fd(fd) => Box::new(FdSource(fd)),
+        }
+    }
+}
+
+pub struct TtySource;
+
+impl PasswordSource for TtySource {
+    fn read_password(&self, prompt: &str) -> io::Result<String> {
+        Terminal::prompt_password(prompt)
+    }
+}
+
+/// Reads the first line of an inherited file descriptor, like GnuPG's
+/// `--passphrase-fd`.
+pub struct FdSource(pub u32);
+
+impl PasswordSource for FdSource {
+    fn read_password(&self, _prompt: &str) -> io::Result<String> {
+        let file = std::fs::File::open(format!("/dev/fd/{}", self.0))?;
+        let mut line = String::new();
+        BufReader::new(file).read_line(&mut line)?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+/// Asks the desktop keyring, by default through `secret-tool`.
+pub struct KeyringSource;
+
+impl PasswordSource for KeyringSource {
+    fn read_password(&self, _prompt: &str) -> io::Result<String> {
+        let command =
+            std::env::var(KEYRING_ENV).unwrap_or_else(|_| DEFAULT_KEYRING_COMMAND.to_string());
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "`{}` exited with {}",
+                command, output.status
+            )));
+        }
+        String::from_utf8(output.stdout)
+            .map(|v| v.trim_end_matches('\n').to_string())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+/// Releases the password in the keyring to users that pass PAM, see
+/// `cli::pam` for what this does and does not protect against.
+#[cfg(feature = "pam")]
+pub struct PamSource;
+
+#[cfg(feature = "pam")]
+impl PasswordSource for PamSource {
+    fn read_password(&self, prompt: &str) -> io::Result<String> {
+        super::pam::authenticate()?;
+        KeyringSource.read_password(prompt)
+    }
+}
+
+/// Talks the Assuan protocol to a pinentry program, as GnuPG does. It runs
+/// in the sandbox without network, the typed password is all it sees.
+pub struct PinentrySource {
+    program: String,
+}
+
+impl PinentrySource {
+    pub fn new(program: String) -> Self {
+        Self { program }
+    }
+
+    fn expect_ok(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+        let mut data = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "pinentry closed the connection",
+                ));
+            }
+            let line = line.trim_end_matches('\n');
+            match line.split_once(' ').unwrap_or((line, "")) {
+                ("OK", _) => return Ok(data),
+                ("D", value) => data = Some(unescape(value)),
+                ("ERR", reason) => return Err(io::Error::other(reason.to_string())),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl PasswordSource for PinentrySource {
+    fn read_password(&self, prompt: &str) -> io::Result<String> {
+        let mut child = Sandbox::new(&PINENTRY_PASSED_ENV)
+            .apply(&mut Command::new(&self.program))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut input = child.stdin.take().expect("stdin is piped");
+        let mut output = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        let mut commands = vec![
+            format!("SETDESC {}", escape("Unlock the mopm vault")),
+            format!("SETPROMPT {}", escape(prompt.trim_end())),
+        ];
+        if let Ok(tty) = nix::unistd::ttyname(io::stdin()) {
+            commands.push(format!("OPTION ttyname={}", tty.display()));
+        }
+        if let Ok(term) = std::env::var("TERM") {
+            commands.push(format!("OPTION ttytype={}", term));
+        }
+
+        let result = (|| {
+            Self::expect_ok(&mut output)?;
+            for command in commands {
+                writeln!(input, "{}", command)?;
+                // Older pinentries reject unknown options, which is harmless.
+                let _ = Self::expect_ok(&mut output);
+            }
+            writeln!(input, "GETPIN")?;
+            Self::expect_ok(&mut output)
+        })();
+
+        let _ = writeln!(input, "BYE");
+        drop(input);
+        let _ = child.wait();
+        Ok(result?.unwrap_or_default())
+    }
+}
+
+/// Tries the first source and falls back to the second if it fails, after
+/// telling why on stderr: a broken pinentry should not go unnoticed.
+pub struct Fallback(Box<dyn PasswordSource>, Box<dyn PasswordSource>);
+
+impl PasswordSource for Fallback {
+    fn read_password(&self, prompt: &str) -> io::Result<String> {
+        self.0.read_password(prompt).or_else(|err| {
+            eprintln!("{}{}", FALLBACK_WARNING, err);
+            self.1.read_password(prompt)
+        })
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            PasswordSourceKind::parse("tty"),
+            Some(PasswordSourceKind::Tty)
+        );
+        assert_eq!(
+            PasswordSourceKind::parse("fd:3"),
+            Some(PasswordSourceKind::Fd(3))
+        );
+        assert_eq!(PasswordSourceKind::parse("fd:x"), None);
+        assert_eq!(PasswordSourceKind::parse("other"), None);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a%b\nc"), "a%25b%0Ac");
+        assert_eq!(unescape("a%25b%0Ac"), "a%b\nc");
+        assert_eq!(unescape("100%"), "100%");
+    }
+
+    #[test]
+    fn test_pinentry() {
+        let script = std::env::temp_dir().join(format!("mopm-pinentry-{}", std::process::id()));
+        std::fs::write(
+            &script,
+            "#!/bin/sh\necho 'OK ready'\nwhile read cmd rest; do\n  case $cmd in\n    GETPIN) echo 'D hunter%252'; echo OK ;;\n    BYE) echo OK; exit 0 ;;\n    *) echo OK ;;\n  esac\ndone\n",
+        )
+        .unwrap();
+        std::process::Command::new("chmod")
+            .arg("+x")
+            .arg(&script)
+            .status()
+            .unwrap();
+
+        let source = PinentrySource::new(script.display().to_string());
+        assert_eq!(source.read_password("Password:").unwrap(), "hunter%2");
+        std::fs::remove_file(script).unwrap();
+    }
+}
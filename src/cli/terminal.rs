@@ -1,9 +1,295 @@
-pub struct Terminal;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, IsTerminal, Read, Write},
+    os::fd::AsFd,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    poll::{self, PollFd, PollFlags, PollTimeout},
+    sys::termios::{self, FlushArg, LocalFlags, SetArg, Termios},
+    unistd,
+};
+use thiserror::Error;
+
+pub const TIMEOUT_SETTING: &str = "prompt_timeout";
+
+const TTY: &str = "/dev/tty";
+const MASK_ENV: &str = "MOPM_MASK_INPUT";
+const MASK: &[u8] = b"*";
+const ERASE: &[u8] = b"\x08 \x08";
+
+const INTERRUPT: u8 = 0x03;
+const END_OF_FILE: u8 = 0x04;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+const KILL_LINE: u8 = 0x15;
+const ESCAPE: u8 = 0x1b;
+
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TerminalError {
+    #[error("invalid value for `prompt_timeout`: `{0}`, expected a number of seconds")]
+    InvalidTimeout(String),
+}
+
+/// Parses the value of the `prompt_timeout` setting, `None` when it is 0.
+pub fn parse_timeout(value: &str) -> Result<Option<Duration>, TerminalError> {
+    match value.parse() {
+        Ok(0) => Ok(None),
+        Ok(seconds) => Ok(Some(Duration::from_secs(seconds))),
+        Err(_) => Err(TerminalError::InvalidTimeout(value.to_string())),
+    }
+}
 
-impl Terminal {}
+/// Makes password prompts give up after `timeout`.
+pub fn configure_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+pub struct Terminal;
 
 impl Terminal {
-    pub fn read_password() -> String {
-        rpassword::read_password().unwrap()
+    /// Reads a password from the controlling terminal without echoing it,
+    /// printing a `*` per character when `MOPM_MASK_INPUT` is set.
+    /// Backspace and Ctrl-U edit the line, Ctrl-C gives up with an
+    /// `Interrupted` error, and the terminal is restored either way.
+    ///
+    /// Stdin is never read, so piped data cannot be taken for a password,
+    /// and neither is what was typed before the prompt showed. Without a
+    /// terminal to type on, or with mopm in the background of it, this
+    /// fails rather than wait; it gives up with a `TimedOut` error once
+    /// the configured timeout passes.
+    pub fn prompt_password(prompt: &str) -> io::Result<String> {
+        let tty = Self::open(prompt)?;
+        let mask = std::env::var_os(MASK_ENV).is_some_and(|v| !v.is_empty() && v != "0");
+        let raw = RawMode::enable(&tty)?;
+        termios::tcflush(&tty, FlushArg::TCIFLUSH)?;
+        let mut output = tty.try_clone()?;
+        let mut input = Timed::new(raw.tty, TIMEOUT.get().copied());
+        let password = read_line(&mut input, &mut output, mask);
+        drop(raw);
+        if password.is_err() {
+            output.write_all(b"\n")?;
+        }
+        password
+    }
+
+    /// Reads a line from the controlling terminal with echo, e.g. the user
+    /// name a PAM module asks for. Like passwords, it is never read from
+    /// stdin.
+    #[cfg(feature = "pam")]
+    pub fn prompt_line(prompt: &str) -> io::Result<String> {
+        use std::io::{BufRead, BufReader};
+
+        let tty = Self::open(prompt)?;
+        let mut line = String::new();
+        BufReader::new(Timed::new(&tty, TIMEOUT.get().copied())).read_line(&mut line)?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// The controlling terminal, with `prompt` written to it, as long as
+    /// mopm is in its foreground.
+    fn open(prompt: &str) -> io::Result<File> {
+        let mut tty = match OpenOptions::new().read(true).write(true).open(TTY) {
+            Ok(v) if v.is_terminal() => v,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no terminal to type the password on, see --password-source",
+                ))
+            }
+        };
+        if unistd::tcgetpgrp(&tty).ok() != Some(unistd::getpgrp()) {
+            return Err(io::Error::other(
+                "mopm runs in the background of the terminal, bring it to the foreground",
+            ));
+        }
+        tty.write_all(prompt.as_bytes())?;
+        tty.flush()?;
+        Ok(tty)
+    }
+}
+
+/// Turns off echo, line editing and signals on the terminal until dropped.
+struct RawMode<'a> {
+    tty: &'a File,
+    original: Termios,
+}
+
+impl<'a> RawMode<'a> {
+    fn enable(tty: &'a File) -> io::Result<Self> {
+        let original = termios::tcgetattr(tty)?;
+        let mut raw = original.clone();
+        raw.local_flags
+            .remove(LocalFlags::ECHO | LocalFlags::ICANON | LocalFlags::ISIG | LocalFlags::IEXTEN);
+        termios::tcsetattr(tty, SetArg::TCSANOW, &raw)?;
+        Ok(Self { tty, original })
+    }
+}
+
+impl Drop for RawMode<'_> {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(self.tty, SetArg::TCSANOW, &self.original);
+    }
+}
+
+/// Reads from a file, failing with `TimedOut` once `timeout` has passed
+/// since it was made.
+struct Timed<'a> {
+    file: &'a File,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Timed<'a> {
+    fn new(file: &'a File, timeout: Option<Duration>) -> Self {
+        Self {
+            file,
+            timeout,
+            deadline: timeout.map(|v| Instant::now() + v),
+        }
+    }
+}
+
+impl Read for Timed<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let (Some(timeout), Some(deadline)) = (self.timeout, self.deadline) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let mut fds = [PollFd::new(self.file.as_fd(), PollFlags::POLLIN)];
+            let ready = poll::poll(
+                &mut fds,
+                PollTimeout::try_from(left).unwrap_or(PollTimeout::MAX),
+            )?;
+            if ready == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no password was typed within {} seconds", timeout.as_secs()),
+                ));
+            }
+        }
+        self.file.read(buf)
+    }
+}
+
+/// Reads keys from `input` until Enter and edits the line accordingly,
+/// echoing a mask for each character to `feedback` if `mask` is set.
+fn read_line<W: Write>(input: &mut impl Read, feedback: &mut W, mask: bool) -> io::Result<String> {
+    let mut line = String::new();
+    let mut pending = Vec::new();
+    // Unbuffered, so that input typed after Enter stays for the next read.
+    #[allow(clippy::unbuffered_bytes)]
+    let mut bytes = input.bytes().peekable();
+    let echo = |feedback: &mut W, bytes: &[u8]| match mask {
+        true => feedback.write_all(bytes).and_then(|_| feedback.flush()),
+        false => Ok(()),
+    };
+    while let Some(byte) = bytes.next().transpose()? {
+        match byte {
+            b'\r' | b'\n' => {
+                feedback.write_all(b"\n")?;
+                return Ok(line);
+            }
+            INTERRUPT => return Err(io::ErrorKind::Interrupted.into()),
+            END_OF_FILE if line.is_empty() => break,
+            BACKSPACE | DELETE => {
+                if line.pop().is_some() {
+                    echo(feedback, ERASE)?;
+                }
+            }
+            KILL_LINE => {
+                for _ in line.drain(..) {
+                    echo(feedback, ERASE)?;
+                }
+            }
+            ESCAPE => {
+                // Arrow and function keys, e.g. `\x1b[D`, end with a letter or `~`.
+                // What follows a bare escape is read as typed.
+                if bytes.next_if(|v| matches!(v, Ok(b'['))).is_some() {
+                    while let Some(byte) = bytes.next().transpose()? {
+                        if (0x40..=0x7e).contains(&byte) {
+                            break;
+                        }
+                    }
+                }
+            }
+            byte if byte < 0x20 => {}
+            byte => {
+                pending.push(byte);
+                match std::str::from_utf8(&pending) {
+                    Ok(v) => {
+                        line.push_str(v);
+                        pending.clear();
+                        echo(feedback, MASK)?;
+                    }
+                    Err(err) if err.error_len().is_some() => pending.clear(),
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+    Err(io::ErrorKind::UnexpectedEof.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &[u8], mask: bool) -> (io::Result<String>, String) {
+        let mut feedback = Vec::new();
+        let line = read_line(&mut &input[..], &mut feedback, mask);
+        (line, String::from_utf8(feedback).unwrap())
+    }
+
+    #[test]
+    fn test_read_line() {
+        let (line, feedback) = read(b"hunx\x7fter2\r", false);
+        assert_eq!(line.unwrap(), "hunter2");
+        assert_eq!(feedback, "\n");
+
+        let (line, feedback) = read("pä\x7fa\x1b[Ds\n".as_bytes(), true);
+        assert_eq!(line.unwrap(), "pas");
+        assert_eq!(feedback, "**\x08 \x08**\n");
+
+        let (line, _) = read(b"wrong\x15right\n", false);
+        assert_eq!(line.unwrap(), "right");
+
+        let (line, _) = read(b"a\x1bbc\x1b[1;5Cd\n", false);
+        assert_eq!(line.unwrap(), "abcd");
+    }
+
+    #[test]
+    fn test_timed() {
+        let (read, write) = unistd::pipe().unwrap();
+        let (read, mut write) = (File::from(read), File::from(write));
+        write.write_all(b"typed\n").unwrap();
+        let mut input = Timed::new(&read, Some(Duration::from_millis(50)));
+        let line = read_line(&mut input, &mut Vec::new(), false);
+        assert_eq!(line.unwrap(), "typed");
+
+        let err = read_line(&mut input, &mut Vec::new(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("30"), Ok(Some(Duration::from_secs(30))));
+        assert_eq!(parse_timeout("0"), Ok(None));
+        assert_eq!(
+            parse_timeout("1m"),
+            Err(TerminalError::InvalidTimeout("1m".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_read_line_gives_up() {
+        let (line, _) = read(b"hun\x03ter2\n", false);
+        assert_eq!(line.unwrap_err().kind(), io::ErrorKind::Interrupted);
+        let (line, _) = read(b"\x04", false);
+        assert_eq!(line.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        let (line, _) = read(b"no enter", false);
+        assert_eq!(line.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
     }
 }
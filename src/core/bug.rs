@@ -0,0 +1,67 @@
+use std::fmt::Display;
+
+/// Panic payload for broken invariants. It only carries static strings,
+/// so a crash report built from it cannot leak vault contents the way the
+/// formatted message of `unwrap` could.
+#[derive(Debug)]
+pub struct Bug {
+    pub context: &'static str,
+    pub error: &'static str,
+}
+
+impl Display for Bug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.context, self.error)
+    }
+}
+
+/// Replaces `unwrap` and `expect` outside of tests.
+pub trait OrBug<T> {
+    /// Panics with a `Bug` naming `context` and the type of the error,
+    /// never its value.
+    fn or_bug(self, context: &'static str) -> T;
+}
+
+impl<T, E> OrBug<T> for Result<T, E> {
+    #[track_caller]
+    fn or_bug(self, context: &'static str) -> T {
+        match self {
+            Ok(v) => v,
+            Err(_) => std::panic::panic_any(Bug {
+                context,
+                error: std::any::type_name::<E>(),
+            }),
+        }
+    }
+}
+
+impl<T> OrBug<T> for Option<T> {
+    #[track_caller]
+    fn or_bug(self, context: &'static str) -> T {
+        match self {
+            Some(v) => v,
+            None => std::panic::panic_any(Bug {
+                context,
+                error: "None",
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_or_bug() {
+        assert_eq!(Ok::<_, String>(1).or_bug("ok"), 1);
+        assert_eq!(Some(2).or_bug("some"), 2);
+
+        let payload =
+            std::panic::catch_unwind(|| Err::<(), _>("secret".to_string()).or_bug("test"))
+                .unwrap_err();
+        let bug = payload.downcast_ref::<Bug>().unwrap();
+        assert_eq!(bug.context, "test");
+        assert!(!bug.to_string().contains("secret"));
+    }
+}
@@ -0,0 +1,284 @@
+//! Signed listings of what a vault contains, without any secrets: key
+//! names, tags and modification times. Catalogs are signed with an ed25519
+//! key derived from the vault's fingerprint key, so only someone who can
+//! unlock the vault can publish one and anyone holding it can check one.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use super::{
+    diff::Change,
+    encryptor::Encryprtor,
+    entry::TAGS_FIELD,
+    fingerprint,
+    identity::{self, VaultId},
+    manager::PasswordManager,
+};
+
+pub const CATALOG_VERSION: u64 = 1;
+
+const SIGNING_KEY_CONTEXT: &[u8] = b"mopm-catalog-signing-key";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CatalogError {
+    #[error("malformed catalog: {0}")]
+    Malformed(&'static str),
+    #[error("unsupported catalog version: {0}")]
+    UnsupportedVersion(u64),
+    #[error("the catalog was signed by a different vault")]
+    ForeignSigner,
+    #[error("the catalog signature is not valid")]
+    InvalidSignature,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub key: String,
+    pub tags: Vec<String>,
+    pub modified: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Catalog {
+    pub vault_id: VaultId,
+    pub generated: u64,
+    pub entries: Vec<CatalogEntry>,
+}
+
+impl Catalog {
+    /// Lists every entry of the vault in key order. Nothing is decrypted.
+    pub fn of<T>(pm: &PasswordManager<T>, generated: u64) -> Self
+    where
+        T: Encryprtor,
+    {
+        let mut entries: Vec<CatalogEntry> = pm
+            .kv
+            .iter()
+            .map(|(key, entry)| CatalogEntry {
+                key: key.clone(),
+                tags: entry.meta.get(TAGS_FIELD).map_or_else(Vec::new, |v| {
+                    v.split(',')
+                        .map(str::trim)
+                        .filter(|v| !v.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }),
+                modified: entry.modified,
+            })
+            .collect();
+        entries.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        Self {
+            vault_id: *pm.vault_id(),
+            generated,
+            entries,
+        }
+    }
+
+    /// Renders the catalog as JSON with a signature over the rest of the
+    /// document.
+    pub fn sign(&self, key: &SigningKey) -> String {
+        let mut document = self.unsigned(&key.verifying_key());
+        let signature = key.sign(&canonical(&document));
+        document["signature"] = json!(hex::encode(signature.to_bytes()));
+        serde_json::to_string_pretty(&document).expect("json values serialize")
+    }
+
+    /// Parses a signed catalog and checks that `key` signed it.
+    pub fn verify(text: &str, key: &VerifyingKey) -> Result<Self, CatalogError> {
+        let mut document: Value =
+            serde_json::from_str(text).map_err(|_| CatalogError::Malformed("not json"))?;
+        let object = document
+            .as_object_mut()
+            .ok_or(CatalogError::Malformed("not an object"))?;
+        let signature = object
+            .remove("signature")
+            .and_then(|v| hex::decode(v.as_str()?).ok())
+            .and_then(|v| Signature::from_slice(&v).ok())
+            .ok_or(CatalogError::Malformed("missing signature"))?;
+
+        let version = object
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or(CatalogError::Malformed("missing version"))?;
+        if version != CATALOG_VERSION {
+            return Err(CatalogError::UnsupportedVersion(version));
+        }
+        let signer = object
+            .get("public_key")
+            .and_then(Value::as_str)
+            .and_then(|v| hex::decode(v).ok())
+            .ok_or(CatalogError::Malformed("missing public key"))?;
+        if signer != key.as_bytes() {
+            return Err(CatalogError::ForeignSigner);
+        }
+        key.verify_strict(&canonical(&document), &signature)
+            .map_err(|_| CatalogError::InvalidSignature)?;
+
+        Self::from_json(&document)
+    }
+
+    /// How the entries of `current` differ from the ones in this catalog,
+    /// in key order.
+    pub fn changes(&self, current: &Catalog) -> Vec<(String, Change)> {
+        let by_key = |catalog: &'_ Catalog| -> BTreeMap<String, CatalogEntry> {
+            catalog
+                .entries
+                .iter()
+                .map(|v| (v.key.clone(), v.clone()))
+                .collect()
+        };
+        let (published, current) = (by_key(self), by_key(current));
+        let keys: BTreeSet<&String> = published.keys().chain(current.keys()).collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let change = match (published.get(key), current.get(key)) {
+                    (Some(a), Some(b)) if a == b => return None,
+                    (Some(_), Some(_)) => Change::Changed,
+                    (Some(_), None) => Change::Removed,
+                    _ => Change::Added,
+                };
+                Some((key.clone(), change))
+            })
+            .collect()
+    }
+
+    fn unsigned(&self, key: &VerifyingKey) -> Value {
+        let entries: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|v| json!({ "key": v.key, "tags": v.tags, "modified": v.modified }))
+            .collect();
+        json!({
+            "version": CATALOG_VERSION,
+            "vault": identity::format_id(&self.vault_id),
+            "generated": self.generated,
+            "public_key": hex::encode(key.as_bytes()),
+            "entries": entries,
+        })
+    }
+
+    fn from_json(document: &Value) -> Result<Self, CatalogError> {
+        let vault_id = document["vault"]
+            .as_str()
+            .and_then(|v| hex::decode(v.replace('-', "")).ok())
+            .and_then(|v| VaultId::try_from(v).ok())
+            .ok_or(CatalogError::Malformed("invalid vault id"))?;
+        let generated = document["generated"]
+            .as_u64()
+            .ok_or(CatalogError::Malformed("missing generation time"))?;
+        let entries = document["entries"]
+            .as_array()
+            .ok_or(CatalogError::Malformed("missing entries"))?
+            .iter()
+            .map(|v| {
+                let tags = v["tags"]
+                    .as_array()?
+                    .iter()
+                    .map(|v| v.as_str().map(str::to_string))
+                    .collect::<Option<_>>()?;
+                Some(CatalogEntry {
+                    key: v["key"].as_str()?.to_string(),
+                    tags,
+                    modified: v["modified"].as_u64()?,
+                })
+            })
+            .collect::<Option<_>>()
+            .ok_or(CatalogError::Malformed("invalid entry"))?;
+        Ok(Self {
+            vault_id,
+            generated,
+            entries,
+        })
+    }
+}
+
+/// The ed25519 key a vault signs its catalogs with. It is derived from the
+/// fingerprint key, which never leaves the encrypted body.
+pub fn signing_key<T>(pm: &PasswordManager<T>) -> SigningKey
+where
+    T: Encryprtor,
+{
+    SigningKey::from_bytes(&fingerprint::fingerprint(
+        &pm.fingerprint_key,
+        SIGNING_KEY_CONTEXT,
+    ))
+}
+
+/// Object keys are sorted, so serializing a parsed document again yields
+/// the bytes that were signed.
+fn canonical(document: &Value) -> Vec<u8> {
+    serde_json::to_vec(document).expect("json values serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    fn vault() -> PasswordManager<AESEncryptor> {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("a"));
+        pm.store_password("b".to_string(), "secret").unwrap();
+        pm.store_password("a".to_string(), "secret").unwrap();
+        pm.set_meta("a", TAGS_FIELD, "team, prod,").unwrap();
+        pm
+    }
+
+    #[test]
+    fn test_catalog() {
+        let pm = vault();
+        let catalog = Catalog::of(&pm, 42);
+        assert_eq!(catalog.entries.len(), 2);
+        assert_eq!(catalog.entries[0].key, "a");
+        assert_eq!(catalog.entries[0].tags, ["team", "prod"]);
+        assert!(catalog.entries[1].tags.is_empty());
+
+        let key = signing_key(&pm);
+        let signed = catalog.sign(&key);
+        assert!(!signed.contains("secret"));
+        assert_eq!(Catalog::verify(&signed, &key.verifying_key()), Ok(catalog));
+    }
+
+    #[test]
+    fn test_verify_rejects() {
+        let pm = vault();
+        let key = signing_key(&pm);
+        let signed = Catalog::of(&pm, 42).sign(&key);
+
+        let tampered = signed.replace("\"b\"", "\"c\"");
+        assert_eq!(
+            Catalog::verify(&tampered, &key.verifying_key()),
+            Err(CatalogError::InvalidSignature)
+        );
+
+        let other = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("a"));
+        assert_eq!(
+            Catalog::verify(&signed, &signing_key(&other).verifying_key()),
+            Err(CatalogError::ForeignSigner)
+        );
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut pm = vault();
+        let published = Catalog::of(&pm, 42);
+        assert!(published.changes(&Catalog::of(&pm, 43)).is_empty());
+
+        pm.delete("a").unwrap();
+        pm.store_password("c".to_string(), "secret").unwrap();
+        pm.set_meta("b", TAGS_FIELD, "new").unwrap();
+        assert_eq!(
+            published.changes(&Catalog::of(&pm, 43)),
+            [
+                ("a".to_string(), Change::Removed),
+                ("b".to_string(), Change::Changed),
+                ("c".to_string(), Change::Added),
+            ]
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the unix epoch, clamped to 0 if the clock is before it.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or(0)
+}
+
+/// How far the clock may trail timestamps written on other machines
+/// before it looks wrong.
+pub const SKEW_TOLERANCE: u64 = 5 * 60;
+
+/// Whether the clock reads earlier than `latest`, a time already written to
+/// the vault, by more than the tolerance: the system time is then wrong on
+/// this machine or the one that wrote it.
+pub fn is_behind(latest: u64) -> bool {
+    now().saturating_add(SKEW_TOLERANCE) < latest
+}
+
+/// Hybrid logical timestamp for a new write that supersedes `previous`: the
+/// wall clock when it is ahead, otherwise one tick past `previous`, so
+/// edits made after observing a skewed replica still win.
+pub fn after(previous: u64) -> u64 {
+    now().max(previous.saturating_add(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_after() {
+        assert!(after(0) >= now());
+        assert_eq!(after(u64::MAX - 1), u64::MAX);
+        assert_eq!(after(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_is_behind() {
+        assert!(!is_behind(0));
+        assert!(!is_behind(now() + SKEW_TOLERANCE));
+        assert!(is_behind(now() + 2 * SKEW_TOLERANCE));
+    }
+}
@@ -0,0 +1,340 @@
+//! What happens when an imported or merged entry meets an existing entry
+//! with the same key and a different password. Identical entries are not
+//! conflicts and are left alone. When asked, both sides are shown without
+//! their passwords, and an answer can be given for all remaining conflicts.
+
+use thiserror::Error;
+
+use super::{
+    ct,
+    encryptor::Encryprtor,
+    entry::{self, Entry},
+    fingerprint,
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("invalid conflict policy `{0}`, expected skip, overwrite, keep-both or ask")]
+pub struct ConflictPolicyError(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    KeepBoth,
+    Ask,
+}
+
+impl ConflictPolicy {
+    pub fn parse(value: &str) -> Result<Self, ConflictPolicyError> {
+        match value {
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            "keep-both" => Ok(Self::KeepBoth),
+            "ask" => Ok(Self::Ask),
+            _ => Err(ConflictPolicyError(value.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Skip,
+    Overwrite,
+    /// Keeps the existing entry and stores the new one under the first free
+    /// key with a `-2`, `-3`.. suffix.
+    KeepBoth,
+}
+
+impl From<Resolution> for ConflictPolicy {
+    fn from(resolution: Resolution) -> Self {
+        match resolution {
+            Resolution::Skip => Self::Skip,
+            Resolution::Overwrite => Self::Overwrite,
+            Resolution::KeepBoth => Self::KeepBoth,
+        }
+    }
+}
+
+/// What is shown of one side of a conflict: the length of the password
+/// rather than the password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Side {
+    pub length: usize,
+    /// `None` for imported entries, which carry no time.
+    pub modified: Option<u64>,
+    pub fields: Vec<String>,
+}
+
+impl Side {
+    pub(in crate::core) fn new(value: &[u8], entry: Option<&Entry>) -> Self {
+        Self {
+            length: String::from_utf8_lossy(value).chars().count(),
+            modified: entry.map(Entry::modified),
+            fields: entry
+                .map(|v| {
+                    v.meta
+                        .keys()
+                        .filter(|name| !entry::is_sealed_meta(name))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A conflict as it is asked about: the entry of the vault is `mine`, the
+/// imported or merged one `theirs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict<'a> {
+    pub key: &'a str,
+    pub mine: Side,
+    pub theirs: Side,
+}
+
+/// An answer to a conflict, applied to the remaining conflicts as well
+/// when `all` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Answer {
+    pub resolution: Resolution,
+    pub all: bool,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ConflictReport {
+    pub added: usize,
+    pub unchanged: usize,
+    pub skipped: Vec<String>,
+    pub overwritten: Vec<String>,
+    /// Pairs of the existing key and the key the new entry was stored under.
+    pub kept_both: Vec<(String, String)>,
+}
+
+impl ConflictReport {
+    pub fn conflicts(&self) -> usize {
+        self.skipped.len() + self.overwritten.len() + self.kept_both.len()
+    }
+}
+
+/// Applies a policy to each conflict and records what was done. `ask` is
+/// only called for [`ConflictPolicy::Ask`], until it answers for all.
+pub struct Resolver<'a> {
+    policy: ConflictPolicy,
+    ask: Box<dyn FnMut(&Conflict) -> Answer + 'a>,
+    pub report: ConflictReport,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(policy: ConflictPolicy, ask: impl FnMut(&Conflict) -> Answer + 'a) -> Self {
+        Self {
+            policy,
+            ask: Box::new(ask),
+            report: ConflictReport::default(),
+        }
+    }
+
+    /// Resolves a conflict on `key` with `theirs`, and returns the key to
+    /// write the new entry to, `None` to leave it out.
+    pub(in crate::core) fn target<T: Encryprtor>(
+        &mut self,
+        pm: &mut PasswordManager<T>,
+        key: &str,
+        theirs: Side,
+    ) -> Result<Option<String>, PasswordManagerError> {
+        let resolution = match self.policy {
+            ConflictPolicy::Skip => Resolution::Skip,
+            ConflictPolicy::Overwrite => Resolution::Overwrite,
+            ConflictPolicy::KeepBoth => Resolution::KeepBoth,
+            ConflictPolicy::Ask => {
+                let mine = Side::new(pm.decrypt_password(key)?.as_bytes(), pm.kv.get(key));
+                let answer = (self.ask)(&Conflict { key, mine, theirs });
+                if answer.all {
+                    self.policy = answer.resolution.into();
+                }
+                answer.resolution
+            }
+        };
+        Ok(match resolution {
+            Resolution::Skip => {
+                self.report.skipped.push(key.to_string());
+                None
+            }
+            Resolution::Overwrite => {
+                self.report.overwritten.push(key.to_string());
+                Some(key.to_string())
+            }
+            Resolution::KeepBoth => {
+                let free = pm.free_key(key);
+                self.report.kept_both.push((key.to_string(), free.clone()));
+                Some(free)
+            }
+        })
+    }
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Stores `value` under `key`, or where `resolver` decides if another
+    /// password is stored there already. Returns the key the value ended up
+    /// under, `None` if it was identical or skipped.
+    #[cfg_attr(
+        not(any(feature = "browser", feature = "hashivault", feature = "otpauth")),
+        allow(dead_code)
+    )]
+    pub fn store_resolved(
+        &mut self,
+        key: String,
+        value: &str,
+        resolver: &mut Resolver,
+    ) -> Result<Option<String>, PasswordManagerError> {
+        let target = if !self.kv.contains_key(&key) {
+            resolver.report.added += 1;
+            key
+        } else if self.holds(&key, value.as_bytes())? {
+            resolver.report.unchanged += 1;
+            return Ok(None);
+        } else {
+            match resolver.target(self, &key, Side::new(value.as_bytes(), None))? {
+                Some(v) => v,
+                None => return Ok(None),
+            }
+        };
+        self.store_password(target.clone(), value)?;
+        Ok(Some(target))
+    }
+
+    /// Whether the entry under `key` holds `value`, decided by the
+    /// fingerprint when the entry has one.
+    pub(in crate::core) fn holds(
+        &mut self,
+        key: &str,
+        value: &[u8],
+    ) -> Result<bool, PasswordManagerError> {
+        match self.kv.get(key).and_then(|v| v.fingerprint) {
+            Some(stored) => Ok(ct::eq(
+                &stored,
+                &fingerprint::fingerprint(&self.fingerprint_key, value),
+            )),
+            None => Ok(ct::eq(self.decrypt_password(key)?.as_bytes(), value)),
+        }
+    }
+
+    pub(in crate::core) fn free_key(&self, key: &str) -> String {
+        (2..)
+            .map(|n| format!("{}-{}", key, n))
+            .find(|v| !self.kv.contains_key(v))
+            .expect("the suffixes are unbounded")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    fn vault() -> PasswordManager<AESEncryptor> {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        pm.store_password("db".to_string(), "old").unwrap();
+        pm
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            ConflictPolicy::parse("keep-both"),
+            Ok(ConflictPolicy::KeepBoth)
+        );
+        assert!(ConflictPolicy::parse("merge").is_err());
+    }
+
+    #[test]
+    fn test_store_resolved() {
+        let mut pm = vault();
+        let mut resolver = Resolver::new(ConflictPolicy::Skip, |_| unreachable!());
+        assert_eq!(
+            pm.store_resolved("db".to_string(), "old", &mut resolver),
+            Ok(None)
+        );
+        assert_eq!(
+            pm.store_resolved("db".to_string(), "new", &mut resolver),
+            Ok(None)
+        );
+        assert_eq!(
+            pm.store_resolved("web".to_string(), "x", &mut resolver),
+            Ok(Some("web".to_string()))
+        );
+        assert_eq!(pm.get_password("db"), Ok("old".to_string()));
+        assert_eq!(
+            resolver.report,
+            ConflictReport {
+                added: 1,
+                unchanged: 1,
+                skipped: vec!["db".to_string()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_keep_both() {
+        let mut pm = vault();
+        pm.store_password("db-2".to_string(), "taken").unwrap();
+        let mut resolver = Resolver::new(ConflictPolicy::KeepBoth, |_| unreachable!());
+        assert_eq!(
+            pm.store_resolved("db".to_string(), "new", &mut resolver),
+            Ok(Some("db-3".to_string()))
+        );
+        assert_eq!(pm.get_password("db"), Ok("old".to_string()));
+        assert_eq!(pm.get_password("db-3"), Ok("new".to_string()));
+        assert_eq!(resolver.report.conflicts(), 1);
+    }
+
+    #[test]
+    fn test_ask() {
+        let mut pm = vault();
+        let mut asked = vec![];
+        let mut resolver = Resolver::new(ConflictPolicy::Ask, |conflict: &Conflict| {
+            asked.push(conflict.key.to_string());
+            assert_eq!(conflict.mine.length, 3);
+            assert_eq!(conflict.theirs.length, 5);
+            assert_eq!(conflict.theirs.modified, None);
+            Answer {
+                resolution: Resolution::Overwrite,
+                all: false,
+            }
+        });
+        pm.store_resolved("db".to_string(), "newer", &mut resolver)
+            .unwrap();
+        assert_eq!(resolver.report.overwritten, vec!["db".to_string()]);
+        drop(resolver);
+        assert_eq!(asked, vec!["db".to_string()]);
+        assert_eq!(pm.get_password("db"), Ok("newer".to_string()));
+    }
+
+    #[test]
+    fn test_answer_for_all() {
+        let mut pm = vault();
+        pm.store_password("web".to_string(), "old").unwrap();
+        let mut asked = 0;
+        let mut resolver = Resolver::new(ConflictPolicy::Ask, |_: &Conflict| {
+            asked += 1;
+            Answer {
+                resolution: Resolution::Skip,
+                all: true,
+            }
+        });
+        for key in ["db", "web"] {
+            pm.store_resolved(key.to_string(), "new", &mut resolver)
+                .unwrap();
+        }
+        assert_eq!(resolver.report.skipped, vec!["db", "web"]);
+        drop(resolver);
+        assert_eq!(asked, 1);
+    }
+}
@@ -0,0 +1,208 @@
+//! Conflict-free merging of two replicas of the same vault.
+//!
+//! Every entry and tombstone is treated as a last-writer-wins register
+//! stamped with its hybrid logical timestamp (see [`clock::after`]). Ties
+//! are broken by the id of the device that last wrote each replica, so both
+//! sides of a merge converge to the same state regardless of merge order.
+//!
+//! A remote entry that wins over a local entry with a different password is
+//! a conflict for the [`Resolver`]. Overwriting keeps the merge
+//! conflict-free; the other policies trade convergence for keeping the
+//! local password.
+//!
+//! [`clock::after`]: super::clock::after
+
+use super::{
+    conflict::{Resolver, Side},
+    encryptor::Encryprtor,
+    entry::{self, Entry},
+    fingerprint,
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Merges `other` into `self`, re-encrypting adopted values under this
+    /// vault's key.
+    pub fn merge<U>(
+        &mut self,
+        other: &mut PasswordManager<U>,
+        resolver: &mut Resolver,
+    ) -> Result<MergeReport, PasswordManagerError>
+    where
+        U: Encryprtor,
+    {
+        let mut report = MergeReport::default();
+        self.adopt_schema(&other.schema());
+        let remote_wins_ties = other.last_device > self.last_device;
+        let wins =
+            |remote: u64, local: u64| remote > local || (remote == local && remote_wins_ties);
+
+        for (key_hash, &deleted) in &other.tombstones {
+            let local = self.tombstones.entry(*key_hash).or_insert(deleted);
+            *local = (*local).max(deleted);
+
+            let doomed: Vec<String> = self
+                .kv
+                .iter()
+                .filter(|(key, entry)| {
+                    entry::key_hash(key) == *key_hash && wins(deleted, entry.modified)
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in doomed {
+                self.kv.remove(&key);
+                report.deleted += 1;
+            }
+        }
+
+        let remotes: Vec<(String, Entry)> = other
+            .kv
+            .iter()
+            .map(|(key, remote)| (key.clone(), remote.clone()))
+            .collect();
+        for (key, remote) in &remotes {
+            let key_hash = entry::key_hash(key);
+            if let Some(&deleted) = self.tombstones.get(&key_hash) {
+                if !wins(remote.modified, deleted) {
+                    continue;
+                }
+            }
+
+            let existed = match self.kv.get(key) {
+                Some(local) if !wins(remote.modified, local.modified) => continue,
+                Some(_) => true,
+                None => false,
+            };
+
+            let value = other.decrypt_value(key, &remote.value)?;
+            let changed = existed && !self.holds(key, &value)?;
+            let target = match changed {
+                true => match resolver.target(self, key, Side::new(&value, Some(remote)))? {
+                    Some(v) => v,
+                    None => continue,
+                },
+                false => key.clone(),
+            };
+            let fingerprint = fingerprint::fingerprint(&self.fingerprint_key, &value);
+            let value = self.encrypt_value(&target, &value)?;
+            let mut entry = Entry::new(value, remote.modified).with_fingerprint(Some(fingerprint));
+            match changed {
+                true => self.supersede(&target, &mut entry),
+                false => self.keep_history(&target, &mut entry),
+            }
+            self.tombstones.remove(&entry::key_hash(&target));
+            self.kv.insert(target.clone(), entry);
+
+            if existed && target == *key {
+                report.updated += 1;
+            } else {
+                report.added += 1;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::{conflict::ConflictPolicy, encryptor::AESEncryptor};
+
+    use super::*;
+
+    fn lww() -> Resolver<'static> {
+        Resolver::new(ConflictPolicy::Overwrite, |_| unreachable!())
+    }
+
+    fn replica(key: &str, device: u8) -> PasswordManager<AESEncryptor> {
+        PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new(key))
+            .with_identity([1; 16], [device; 16])
+    }
+
+    #[test]
+    fn test_merge_add_update() {
+        let mut a = replica("a", 1);
+        let mut b = replica("b", 2);
+        let _ = a.store_password("shared".to_owned(), "old");
+        let _ = b.store_password("shared".to_owned(), "new");
+        let _ = b.store_password("only-b".to_owned(), "x");
+        b.kv.get_mut("shared").unwrap().modified += 10;
+
+        let report = a.merge(&mut b, &mut lww()).unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(report.updated, 1);
+        assert_eq!(a.get_password("shared"), Ok("new".to_owned()));
+        assert_eq!(a.get_password("only-b"), Ok("x".to_owned()));
+    }
+
+    #[test]
+    fn test_merge_tombstones() {
+        let mut a = replica("a", 1);
+        let mut b = replica("b", 2);
+        let _ = a.store_password("gone".to_owned(), "v");
+        let _ = b.store_password("gone".to_owned(), "v");
+        b.kv.get_mut("gone").unwrap().modified = a.kv["gone"].modified;
+        let _ = b.delete("gone");
+
+        assert_eq!(a.merge(&mut b, &mut lww()).unwrap().deleted, 1);
+        assert!(a.get_password("gone").is_err());
+
+        // merging the stale replica back must not resurrect the entry
+        let mut stale = replica("c", 3);
+        let _ = stale.store_password("gone".to_owned(), "v");
+        stale.kv.get_mut("gone").unwrap().modified = 1;
+        assert_eq!(
+            a.merge(&mut stale, &mut lww()).unwrap(),
+            MergeReport::default()
+        );
+        assert!(a.get_password("gone").is_err());
+    }
+
+    #[test]
+    fn test_merge_converges_on_ties() {
+        let mut a = replica("a", 1);
+        let mut b = replica("b", 2);
+        let _ = a.store_password("k".to_owned(), "from-a");
+        let _ = b.store_password("k".to_owned(), "from-b");
+        b.kv.get_mut("k").unwrap().modified = a.kv["k"].modified;
+
+        let _ = a.merge(&mut b, &mut lww()).unwrap();
+        let _ = b.merge(&mut a, &mut lww()).unwrap();
+        assert_eq!(a.get_password("k"), Ok("from-b".to_owned()));
+        assert_eq!(b.get_password("k"), Ok("from-b".to_owned()));
+    }
+
+    #[test]
+    fn test_merge_keep_both() {
+        let mut a = replica("a", 1);
+        let mut b = replica("b", 2);
+        let _ = a.store_password("k".to_owned(), "local");
+        let _ = a.store_password("same".to_owned(), "v");
+        let _ = b.store_password("k".to_owned(), "remote");
+        let _ = b.store_password("same".to_owned(), "v");
+        b.kv.get_mut("k").unwrap().modified += 10;
+        b.kv.get_mut("same").unwrap().modified += 10;
+
+        let mut resolver = Resolver::new(ConflictPolicy::KeepBoth, |_| unreachable!());
+        let report = a.merge(&mut b, &mut resolver).unwrap();
+        assert_eq!((report.added, report.updated), (1, 1));
+        assert_eq!(
+            resolver.report.kept_both,
+            vec![("k".to_owned(), "k-2".to_owned())]
+        );
+        assert_eq!(a.get_password("k"), Ok("local".to_owned()));
+        assert_eq!(a.get_password("k-2"), Ok("remote".to_owned()));
+    }
+}
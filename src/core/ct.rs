@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use subtle::ConstantTimeEq;
+
+use super::rng;
+
+const REJECT_DELAY: Duration = Duration::from_millis(100);
+const REJECT_JITTER_MS: u64 = 100;
+
+/// Compares two secrets in time that depends only on their lengths.
+pub fn eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Like `eq`, for an optional fingerprint, digest or tag.
+pub fn eq_opt<const N: usize>(a: Option<&[u8; N]>, b: &[u8; N]) -> bool {
+    a.is_some_and(|a| eq(a, b))
+}
+
+/// Sleeps for a fixed delay plus random jitter. Every path that rejects a
+/// password or key calls this before reporting the failure, so the time
+/// to an error says nothing about how far verification got and guessing
+/// is slowed down.
+pub fn reject() {
+    std::thread::sleep(REJECT_DELAY + jitter());
+}
+
+fn jitter() -> Duration {
+    Duration::from_millis(rng::next_u64() % REJECT_JITTER_MS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eq() {
+        assert!(eq(b"secret", b"secret"));
+        assert!(!eq(b"secret", b"secreT"));
+        assert!(!eq(b"secret", b"secret!"));
+        assert!(eq(b"", b""));
+    }
+
+    #[test]
+    fn test_eq_opt() {
+        assert!(eq_opt(Some(&[1; 4]), &[1; 4]));
+        assert!(!eq_opt(Some(&[1; 4]), &[2; 4]));
+        assert!(!eq_opt(None, &[1; 4]));
+    }
+
+    #[test]
+    fn test_jitter() {
+        assert!(jitter() < Duration::from_millis(REJECT_JITTER_MS));
+    }
+}
@@ -0,0 +1,221 @@
+//! Entry-level comparison of two vaults, and of an entry with what would
+//! be stored over it.
+
+use std::collections::BTreeMap;
+
+use super::{
+    ct,
+    encryptor::Encryprtor,
+    entry, fingerprint,
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A key that differs between two vaults, with its decrypted values on
+/// either side.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Difference {
+    pub key: String,
+    pub change: Change,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Keys added, removed or changed going from `before` to `after`, in key
+/// order. An entry changed if its value or its metadata differ; cached
+/// values of leased secrets are ignored.
+pub fn diff<A, B>(
+    before: &mut PasswordManager<A>,
+    after: &mut PasswordManager<B>,
+) -> Result<Vec<Difference>, PasswordManagerError>
+where
+    A: Encryprtor,
+    B: Encryprtor,
+{
+    let mut keys: Vec<String> = before.kv.keys().chain(after.kv.keys()).cloned().collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let mut differences = Vec::new();
+    for key in keys {
+        let old = before
+            .kv
+            .contains_key(&key)
+            .then(|| before.get_password(&key))
+            .transpose()?;
+        let new = after
+            .kv
+            .contains_key(&key)
+            .then(|| after.get_password(&key))
+            .transpose()?;
+
+        let change = match (&old, &new) {
+            (None, _) => Change::Added,
+            (_, None) => Change::Removed,
+            (Some(old), Some(new)) if old != new || !same_meta(before, after, &key) => {
+                Change::Changed
+            }
+            _ => continue,
+        };
+        differences.push(Difference {
+            key,
+            change,
+            before: old,
+            after: new,
+        });
+    }
+    Ok(differences)
+}
+
+/// What storing over an existing entry changes, without the values.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Overwrite {
+    pub value_changed: bool,
+    /// The names of the metadata fields set to a different value.
+    pub fields: Vec<String>,
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// What storing `value` and `fields` under `key` would change, `None`
+    /// if there is no entry to overwrite. The stored value is compared by
+    /// fingerprint when it has one, so it is not decrypted.
+    pub fn overwrite(
+        &mut self,
+        key: &str,
+        value: &str,
+        fields: &BTreeMap<String, String>,
+    ) -> Result<Option<Overwrite>, PasswordManagerError> {
+        let Some(entry) = self.kv.get(key) else {
+            return Ok(None);
+        };
+        let changed: Vec<String> = fields
+            .iter()
+            .filter(|(name, value)| entry.meta(name) != Some(value.as_str()))
+            .map(|(name, _)| name.clone())
+            .collect();
+        let value_changed = match entry.fingerprint {
+            Some(stored) => {
+                let fingerprint = fingerprint::fingerprint(&self.fingerprint_key, value.as_ref());
+                !ct::eq(&stored, &fingerprint)
+            }
+            None => {
+                let stored = entry.value.clone();
+                !ct::eq(&self.decrypt_value(key, &stored)?, value.as_ref())
+            }
+        };
+        Ok(Some(Overwrite {
+            value_changed,
+            fields: changed,
+        }))
+    }
+}
+
+fn same_meta<A, B>(before: &PasswordManager<A>, after: &PasswordManager<B>, key: &str) -> bool
+where
+    A: Encryprtor,
+    B: Encryprtor,
+{
+    visible_meta(&before.kv[key].meta) == visible_meta(&after.kv[key].meta)
+}
+
+fn visible_meta(meta: &BTreeMap<String, String>) -> Vec<(&String, &String)> {
+    meta.iter()
+        .filter(|(name, _)| !entry::is_sealed_meta(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let mut a = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("a"));
+        let mut b = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("b"));
+        for pm in [&mut a, &mut b] {
+            let _ = pm.store_password("same".to_owned(), "1");
+            let _ = pm.store_password("meta".to_owned(), "2");
+        }
+        let _ = a.store_password("removed".to_owned(), "3");
+        let _ = a.store_password("changed".to_owned(), "4");
+        let _ = b.store_password("changed".to_owned(), "5");
+        let _ = b.store_password("added".to_owned(), "6");
+        let _ = b.set_meta("meta", "url", "https://example.com");
+
+        let differences: Vec<_> = diff(&mut a, &mut b)
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.key, v.change, v.before, v.after))
+            .collect();
+        assert_eq!(
+            differences,
+            [
+                (
+                    "added".to_owned(),
+                    Change::Added,
+                    None,
+                    Some("6".to_owned())
+                ),
+                (
+                    "changed".to_owned(),
+                    Change::Changed,
+                    Some("4".to_owned()),
+                    Some("5".to_owned())
+                ),
+                (
+                    "meta".to_owned(),
+                    Change::Changed,
+                    Some("2".to_owned()),
+                    Some("2".to_owned())
+                ),
+                (
+                    "removed".to_owned(),
+                    Change::Removed,
+                    Some("3".to_owned()),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("a"));
+        pm.store_password("key".to_owned(), "old").unwrap();
+        pm.set_meta("key", "url", "https://example.com").unwrap();
+        let fields = BTreeMap::from([
+            ("url".to_owned(), "https://example.com".to_owned()),
+            ("notes".to_owned(), "new".to_owned()),
+        ]);
+
+        assert_eq!(pm.overwrite("missing", "old", &fields), Ok(None));
+        assert_eq!(
+            pm.overwrite("key", "old", &fields),
+            Ok(Some(Overwrite {
+                value_changed: false,
+                fields: vec!["notes".to_owned()],
+            }))
+        );
+        pm.kv.get_mut("key").unwrap().fingerprint = None;
+        assert_eq!(
+            pm.overwrite("key", "new", &BTreeMap::new()),
+            Ok(Some(Overwrite {
+                value_changed: true,
+                fields: Vec::new(),
+            }))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Dynamic secrets: entries whose stored value is a command producing a
+//! short-lived credential, cached in the vault until its TTL runs out.
+
+use std::collections::BTreeMap;
+
+use super::{
+    clock,
+    encryptor::Encryprtor,
+    entry::{self, Entry},
+    executor::Executor,
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+pub(in crate::core) const KIND: &str = "kind";
+const KIND_DYNAMIC: &str = "dynamic";
+pub(in crate::core) const TTL: &str = "ttl";
+pub(in crate::core) const CACHE: &str = "cache";
+pub(in crate::core) const CACHE_EXPIRES: &str = "cache_expires";
+/// Set to `true` when the command may use the network, see `exec::sandbox`.
+pub(in crate::core) const NETWORK: &str = "network";
+
+/// Whether the metadata `name` holds a cached value rather than something
+/// the user stored.
+pub(in crate::core) fn is_cache_meta(name: &str) -> bool {
+    name == CACHE || name == CACHE_EXPIRES
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    pub fn store_dynamic(
+        &mut self,
+        key: String,
+        command: &str,
+        ttl: u64,
+        network: bool,
+    ) -> Result<(), PasswordManagerError> {
+        let encrypted_command = self.encrypt_value(&key, command.as_ref())?;
+        if !entry::fits(&key, &encrypted_command) {
+            return Err(PasswordManagerError::EntryTooLarge);
+        }
+        let modified = clock::after(self.kv.get(&key).map_or(0, |v| v.modified));
+        let mut meta = BTreeMap::from([
+            (KIND.to_string(), KIND_DYNAMIC.to_string()),
+            (TTL.to_string(), ttl.to_string()),
+        ]);
+        if network {
+            meta.insert(NETWORK.to_string(), true.to_string());
+        }
+
+        self.tombstones.remove(&entry::key_hash(&key));
+        self.kv
+            .insert(key, Entry::new(encrypted_command, modified).with_meta(meta));
+        Ok(())
+    }
+
+    pub fn is_dynamic(&self, key: &str) -> bool {
+        self.kv
+            .get(key)
+            .is_some_and(|entry| entry.meta(KIND) == Some(KIND_DYNAMIC))
+    }
+
+    /// Returns the secret stored under `key`. Dynamic entries are served
+    /// from the cache while it is fresh and otherwise refreshed with
+    /// `executor`, with network only if the entry allows it; the returned
+    /// flag tells whether the cache was updated and the vault needs to be
+    /// saved.
+    pub fn resolve_password(
+        &mut self,
+        key: &str,
+        executor: &Executor,
+    ) -> Result<(String, bool), PasswordManagerError> {
+        self.authorize(key)?;
+        if !self.is_dynamic(key) {
+            return Ok((self.decrypt_password(key)?, false));
+        }
+
+        let now = clock::now();
+        let entry = &self.kv[key];
+        let cached = entry
+            .meta(CACHE_EXPIRES)
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|&expires| expires > now)
+            .and_then(|_| hex::decode(entry.meta(CACHE)?).ok());
+        if let Some(cached) = cached {
+            let value = self.decrypt_value(key, &cached)?;
+            return Ok((
+                String::from_utf8(value.to_vec()).or(Err(PasswordManagerError::NoPasswordFound))?,
+                false,
+            ));
+        }
+
+        let ttl = entry
+            .meta(TTL)
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+        let network = entry.meta(NETWORK) == Some("true");
+        let command = self.decrypt_password(key)?;
+        let value = executor.clone().network(network).run(&command)?;
+
+        let cache = hex::encode(self.encrypt_value(key, value.as_ref())?);
+        if !entry::meta_fits(CACHE, &cache) {
+            return Err(PasswordManagerError::EntryTooLarge);
+        }
+        let entry = self.kv.get_mut(key).expect("entry exists");
+        entry.meta.insert(CACHE.to_string(), cache);
+        entry.meta.insert(
+            CACHE_EXPIRES.to_string(),
+            now.saturating_add(ttl).to_string(),
+        );
+        Ok((value, true))
+    }
+
+    /// Forgets every cached value, e.g. before the encryption key changes.
+    pub(in crate::core) fn drop_dynamic_caches(&mut self) {
+        for entry in self.kv.values_mut() {
+            entry.meta.remove(CACHE);
+            entry.meta.remove(CACHE_EXPIRES);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_caches() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let executor = Executor::default();
+        let _ = pm.store_password("static".to_owned(), "bar");
+        let _ = pm.store_dynamic("token".to_owned(), "echo $$", 3600, false);
+
+        assert!(pm.is_dynamic("token"));
+        assert!(!pm.is_dynamic("static"));
+        assert_eq!(
+            pm.resolve_password("static", &executor),
+            Ok(("bar".to_owned(), false))
+        );
+
+        let (first, refreshed) = pm.resolve_password("token", &executor).unwrap();
+        assert!(refreshed);
+        assert_eq!(pm.resolve_password("token", &executor), Ok((first, false)));
+    }
+
+    #[test]
+    fn test_resolve_expired() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let executor = Executor::default();
+        let _ = pm.store_dynamic("token".to_owned(), "echo fresh", 0, false);
+
+        assert_eq!(
+            pm.resolve_password("token", &executor),
+            Ok(("fresh".to_owned(), true))
+        );
+        assert_eq!(
+            pm.resolve_password("token", &executor),
+            Ok(("fresh".to_owned(), true))
+        );
+    }
+}
@@ -1,182 +1,927 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     io::{self, Read, Write},
     mem::size_of,
 };
 
 use thiserror::Error;
 
+#[cfg(feature = "sqlite")]
+use super::rows::Rows;
 use super::{
-    encoding::version::Version,
+    ct,
+    encoding::{
+        capability::{Capabilities, CapabilityError},
+        layout::Field,
+        version::Version,
+    },
     encryptor::{DynamicEncryptor, Encryprtor, EncryprtorError},
-    hasher::{Hasher, Sha256Hasher},
-    identifiers::{encryptor_from_id, Identifiable},
-    manager::PasswordManager,
+    entry::{self, Entry, KeyHash},
+    fingerprint::{Fingerprint, FingerprintKey, FINGERPRINT_LENGTH},
+    hasher::{Hasher, DIGEST_LENGTH},
+    identifiers::{self, encryptor_from_id, hasher_from_id, Identifiable},
+    identity::{self, DeviceId, VaultId, ID_LENGTH},
+    kdf::{Kdf, KdfError},
+    keycache::KeyCache,
+    manager::{PasswordManager, PasswordManagerError},
+    schema::{Schema, MAX_SCHEMA_LENGTH},
+    trace,
 };
 
 #[derive(Error, Debug)]
 pub enum EncoderError {
     #[error("cannot parse the body")]
     BodyParseError,
+    #[error("the body exceeds the size limits")]
+    BodyLimitError,
     #[error("header size is not correct")]
     InvalidHeaderSize,
     #[error("could not read/write from/to io")]
     IoError(#[from] io::Error),
     #[error("invalid header format")]
     HeaderParseError,
+    #[error("this vault was created by a newer mopm (format v0.{0}); please upgrade")]
+    NewerFormat(u8),
+    #[error("{0}")]
+    CapabilityError(#[from] CapabilityError),
     #[error("unsupported encryptor version")]
     UnsupportedEncryptorVersionError,
+    #[error("unsupported hasher")]
+    UnsupportedHasherError,
     #[error("invalid key")]
     IvalidKeyError,
     #[error("encryptor error: `{0}`")]
     EncryptorError(#[from] EncryprtorError),
+    #[error("key derivation error: `{0}`")]
+    KdfError(#[from] KdfError),
+    #[error("cannot migrate the vault: `{0}`")]
+    MigrationError(#[from] PasswordManagerError),
 }
 
+/// Size of the space a vault file may carry after the body, which holds
+/// either random bytes or a hidden vault. Nothing in the file tells which,
+/// nor whether the space is there: the body is tried with and without it.
+pub const SLACK_SIZE: usize = 64 * 1024;
+
+/// The derived key of a vault, with the encryptor and hasher it uses.
+type Opened = (Vec<u8>, Box<dyn Encryprtor + Send>, Box<dyn Hasher>);
+
 pub struct Encoder {}
 
 impl Encoder {
+    /// Decodes a vault, deriving its key through `cache`.
     pub fn decode(
         key: &[u8],
         reader: &mut impl Read,
+        cache: &mut KeyCache,
     ) -> Result<PasswordManager<DynamicEncryptor>, EncoderError> {
+        let _span = trace::span("decode");
         let header = Header::try_from_reader(reader)?;
-        let mut encryptor = encryptor_from_id(header.encryptor_id, key)
-            .ok_or(EncoderError::UnsupportedEncryptorVersionError)?;
+        let (key, mut encryptor, mut hasher) = Self::open(&header, key, cache)?;
 
         let mut buf = Vec::new();
-        let _ = reader.read_to_end(&mut buf)?;
-        let body_decrypted = encryptor.decrypt(&buf)?;
-
-        if header
-            .body_sha
-            .iter()
-            .ne(Sha256Hasher::new().hash(&body_decrypted).iter())
         {
-            return Err(EncoderError::IvalidKeyError);
+            let _span = trace::span("read");
+            let _ = reader.read_to_end(&mut buf)?;
+        }
+        let (body_decrypted, slack) =
+            Self::decrypt(&header, &buf, encryptor.as_mut(), hasher.as_mut())?;
+        let body = {
+            let _span = trace::span("parse body");
+            Body::try_from_bytes(header.version, header.capabilities, body_decrypted.as_ref())?
         };
+        let mut pm = Self::assemble(&header, body, encryptor, hasher, &key)?;
+        pm.set_slack(slack);
+        Ok(pm)
+    }
 
-        let kv = Body::try_from_bytes(body_decrypted.as_ref()).unwrap();
+    /// Decrypts the body that follows `header`, with or without the slack,
+    /// and checks it against the digest of formats without a merkle root.
+    /// Returns whether the slack is there.
+    fn decrypt(
+        header: &Header,
+        buf: &[u8],
+        encryptor: &mut (dyn Encryprtor + Send),
+        hasher: &mut dyn Hasher,
+    ) -> Result<(Box<[u8]>, bool), EncoderError> {
+        let _span = trace::span("decrypt");
+        let merkle = header.version.has_merkle_root();
+        let aad = header.associated_data();
+        let (decrypted, slack) = match encryptor.decrypt(buf, &aad) {
+            Err(EncryprtorError::DecryptionError(_)) if buf.len() > SLACK_SIZE => (
+                encryptor.decrypt(&buf[..buf.len() - SLACK_SIZE], &aad),
+                true,
+            ),
+            decrypted => (decrypted, false),
+        };
+        match decrypted {
+            Ok(v) if merkle || ct::eq(&header.body_sha, &hasher.hash(&v)) => Ok((v, slack)),
+            Ok(_) | Err(EncryprtorError::DecryptionError(_)) => {
+                ct::reject();
+                Err(EncoderError::IvalidKeyError)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
 
-        Ok(PasswordManager::from_raw_parts(
-            kv,
+    /// The format and the decrypted body of the vault file `bytes`, and
+    /// whether it carries slack, for `mopm dump --debug`. The entries are
+    /// not checked against the merkle root.
+    pub fn decrypt_body(
+        key: &[u8],
+        mut bytes: &[u8],
+        cache: &mut KeyCache,
+    ) -> Result<(Header, Box<[u8]>, bool), EncoderError> {
+        let header = Header::try_from_reader(&mut bytes)?;
+        let (_, mut encryptor, mut hasher) = Self::open(&header, key, cache)?;
+        let (body, slack) = Self::decrypt(&header, bytes, encryptor.as_mut(), hasher.as_mut())?;
+        Ok((header, body, slack))
+    }
+
+    /// Decodes a vault however it is kept.
+    pub fn decode_stored(
+        key: &[u8],
+        vault: &StoredVault,
+        cache: &mut KeyCache,
+    ) -> Result<PasswordManager<DynamicEncryptor>, EncoderError> {
+        match vault {
+            StoredVault::File(bytes) => Self::decode(key, &mut bytes.as_slice(), cache),
+            #[cfg(feature = "sqlite")]
+            StoredVault::Rows(rows) => Self::decode_rows(key, rows, cache),
+        }
+    }
+
+    /// Derives the key of the vault behind `header`, along with the
+    /// encryptor and hasher it was written with.
+    pub(in crate::core) fn open(
+        header: &Header,
+        key: &[u8],
+        cache: &mut KeyCache,
+    ) -> Result<Opened, EncoderError> {
+        header.capabilities.check()?;
+        let key = {
+            let _span = trace::span("kdf");
+            cache.derive(&header.vault_id, &header.kdf, key)?
+        };
+        let encryptor = encryptor_from_id(header.encryptor_id, &key)
+            .ok_or(EncoderError::UnsupportedEncryptorVersionError)?;
+        let hasher =
+            hasher_from_id(header.hasher_id).ok_or(EncoderError::UnsupportedHasherError)?;
+        Ok((key, encryptor, hasher))
+    }
+
+    /// Builds the manager from a decrypted body, checking it against the
+    /// merkle root of formats that have one.
+    pub(in crate::core) fn assemble(
+        header: &Header,
+        body: Body,
+        encryptor: Box<dyn Encryprtor + Send>,
+        mut hasher: Box<dyn Hasher>,
+        key: &[u8],
+    ) -> Result<PasswordManager<DynamicEncryptor>, EncoderError> {
+        let mut pm = PasswordManager::from_raw_parts(
+            body.kv,
             DynamicEncryptor(header.encryptor_id, encryptor),
-        ))
+        );
+        if let Some(fingerprint_key) = body.fingerprint_key {
+            pm = pm.with_fingerprint_key(fingerprint_key);
+        }
+        if let Some(schema) = &body.schema {
+            let schema = Schema::from_bytes(schema).ok_or(EncoderError::BodyParseError)?;
+            pm.adopt_schema(&schema);
+        }
+        let mut pm = pm
+            .with_tombstones(body.tombstones)
+            .with_identity(header.vault_id, header.device_id)
+            .with_generation(header.generation)
+            .with_kdf(header.kdf)
+            .with_hasher(header.hasher_id)
+            .with_format(header.version, header.capabilities)
+            .with_master_key(key);
+        if header.version.has_merkle_root() {
+            let _span = trace::span("merkle root");
+            let root = pm.merkle.root(
+                header.hasher_id,
+                hasher.as_mut(),
+                &mut pm.kv,
+                &pm.tombstones,
+                &pm.fingerprint_key,
+            );
+            if !ct::eq(&header.body_sha, &root) {
+                ct::reject();
+                return Err(EncoderError::IvalidKeyError);
+            }
+        }
+        if !header.version.has_namespace_keys() {
+            pm.migrate_namespaces()?;
+        }
+        Ok(pm)
     }
 
     pub fn encode<T>(w: &mut impl Write, pm: &mut PasswordManager<T>) -> Result<(), EncoderError>
     where
         T: Encryprtor + Identifiable,
     {
-        let body_bytes = Body::to_bytes(&pm.kv);
-        let body_sha = Sha256Hasher::new().hash(&body_bytes);
+        let _span = trace::span("encode");
+        let schema = pm.schema().to_bytes();
+        let body_bytes = {
+            let _span = trace::span("serialize body");
+            Body::to_bytes(&pm.kv, &pm.tombstones, &pm.fingerprint_key, Some(&schema))
+        };
+        let body_sha = Self::merkle_root(pm)?;
 
-        let body_encrypted = pm.encryptor.encrypt(&body_bytes)?;
+        let header = Header::next(pm, body_sha);
 
-        let header = Header {
-            version: Version::current_version(),
-            encryptor_id: pm.encryptor.id(),
-            body_sha: body_sha[..].try_into().unwrap_or([0; 32]),
+        let body_encrypted = {
+            let _span = trace::span("encrypt");
+            pm.encryptor
+                .encrypt(&body_bytes, &header.associated_data())?
         };
 
         let bytes = header.to_bytes();
-        let _ = w.write(&bytes)?;
-        let _ = w.write(&body_encrypted)?;
+        w.write_all(&bytes)?;
+        w.write_all(&body_encrypted)?;
+        pm.generation = header.generation;
         Ok(())
     }
+
+    /// Writes `pm` in the format of `version` as it was released, values of
+    /// formats without namespace keys under the vault key. Only the golden
+    /// files are written this way.
+    #[cfg(test)]
+    pub(in crate::core) fn encode_version<T>(
+        w: &mut impl Write,
+        pm: &mut PasswordManager<T>,
+        version: Version,
+    ) -> Result<(), EncoderError>
+    where
+        T: Encryprtor + Identifiable,
+    {
+        let mut kv = pm.kv.clone();
+        if !version.has_namespace_keys() {
+            for (key, entry) in kv.iter_mut() {
+                let value = pm.decrypt_value(key, &entry.value)?;
+                entry.value = pm.encryptor.encrypt(&value, &[])?;
+            }
+        }
+
+        let mut body = Vec::new();
+        if version.has_tombstones() {
+            if version.has_fingerprints() {
+                body.extend(pm.fingerprint_key);
+            }
+            body.extend((kv.len() as u64).to_be_bytes());
+            for (key, entry) in &kv {
+                if version.has_metadata() {
+                    Body::write_entry(&mut body, key, entry);
+                    continue;
+                }
+                body.extend((key.len() as u64).to_be_bytes());
+                body.extend((entry.value.len() as u64).to_be_bytes());
+                body.extend(entry.modified.to_be_bytes());
+                if version.has_fingerprints() {
+                    body.extend(entry.fingerprint.unwrap_or([0; FINGERPRINT_LENGTH]));
+                }
+                body.extend(key.as_bytes());
+                body.extend(entry.value.iter());
+            }
+            body.extend((pm.tombstones.len() as u64).to_be_bytes());
+            for (key_hash, deleted) in &pm.tombstones {
+                body.extend(key_hash);
+                body.extend(deleted.to_be_bytes());
+            }
+        } else {
+            for (key, entry) in &kv {
+                body.extend((key.len() as u64).to_be_bytes());
+                body.extend((entry.value.len() as u64).to_be_bytes());
+                body.extend(key.as_bytes());
+                body.extend(entry.value.iter());
+            }
+        }
+
+        let body_sha = match version.has_merkle_root() {
+            true => Self::merkle_root(pm)?,
+            false => hasher_from_id(pm.hasher_id)
+                .ok_or(EncoderError::UnsupportedHasherError)?
+                .hash(&body)[..]
+                .try_into()
+                .or(Err(EncoderError::UnsupportedHasherError))?,
+        };
+        let header = Header {
+            version,
+            capabilities: pm.capabilities.retained(),
+            ..Header::next(pm, body_sha)
+        };
+        let body_encrypted = pm.encryptor.encrypt(&body, &header.associated_data())?;
+        w.write_all(&header.to_bytes())?;
+        w.write_all(&body_encrypted)?;
+        Ok(())
+    }
+
+    pub(in crate::core) fn merkle_root<T: Encryprtor>(
+        pm: &mut PasswordManager<T>,
+    ) -> Result<[u8; DIGEST_LENGTH], EncoderError> {
+        let _span = trace::span("merkle root");
+        let mut hasher =
+            hasher_from_id(pm.hasher_id).ok_or(EncoderError::UnsupportedHasherError)?;
+        Ok(pm.merkle.root(
+            pm.hasher_id,
+            hasher.as_mut(),
+            &mut pm.kv,
+            &pm.tombstones,
+            &pm.fingerprint_key,
+        ))
+    }
+}
+
+/// A vault as it is kept: the bytes of a vault file, or the rows of a
+/// database.
+pub enum StoredVault {
+    File(Vec<u8>),
+    #[cfg(feature = "sqlite")]
+    Rows(Rows),
+}
+
+impl StoredVault {
+    /// The bytes of a vault file, `None` for a database.
+    pub fn file(&self) -> Option<&[u8]> {
+        match self {
+            Self::File(bytes) => Some(bytes),
+            #[cfg(feature = "sqlite")]
+            Self::Rows(_) => None,
+        }
+    }
+
+    /// The format of the vault, read from its first byte alone so that a
+    /// vault from a newer mopm is told before asking for its password.
+    pub fn version(&self) -> Result<Version, EncoderError> {
+        let first = match self {
+            Self::File(bytes) => bytes.first(),
+            #[cfg(feature = "sqlite")]
+            Self::Rows(rows) => rows.header.first(),
+        };
+        Header::parse_version(*first.ok_or(EncoderError::HeaderParseError)?)
+    }
+
+    /// The generation of the vault, read from its header alone.
+    pub fn generation(&self) -> Result<u64, EncoderError> {
+        let mut header = match self {
+            Self::File(bytes) => bytes.as_slice(),
+            #[cfg(feature = "sqlite")]
+            Self::Rows(rows) => rows.header.as_slice(),
+        };
+        Ok(Header::try_from_reader(&mut header)?.generation())
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Header {
     version: Version,
     encryptor_id: u8,
-    body_sha: [u8; 32],
+    body_sha: [u8; DIGEST_LENGTH],
+    vault_id: VaultId,
+    device_id: DeviceId,
+    generation: u64,
+    kdf: Kdf,
+    hasher_id: u8,
+    capabilities: Capabilities,
 }
 
 impl Header {
-    const SIZE: usize = std::mem::size_of::<Self>();
+    const LEGACY_SIZE: usize = 2 + DIGEST_LENGTH;
+    const IDENTITY_SIZE: usize = Self::LEGACY_SIZE + 2 * ID_LENGTH;
+    const GENERATION_SIZE: usize = Self::IDENTITY_SIZE + size_of::<u64>();
+    const KDF_SIZE: usize = Self::GENERATION_SIZE + Kdf::ENCODED_SIZE;
+    const HASHER_SIZE: usize = Self::KDF_SIZE + 1;
+    const SIZE: usize = Self::HASHER_SIZE + Capabilities::ENCODED_SIZE;
+
+    fn size(version: Version) -> usize {
+        if version.has_capabilities() {
+            Self::SIZE
+        } else if version.has_hasher_id() {
+            Self::HASHER_SIZE
+        } else if version.has_kdf() {
+            Self::KDF_SIZE
+        } else if version.has_generation() {
+            Self::GENERATION_SIZE
+        } else if version.has_identity() {
+            Self::IDENTITY_SIZE
+        } else {
+            Self::LEGACY_SIZE
+        }
+    }
+
+    /// The header the next save of `pm` is written with, over a body whose
+    /// digest or merkle root is `body_sha`.
+    pub(in crate::core) fn next<T>(pm: &PasswordManager<T>, body_sha: [u8; DIGEST_LENGTH]) -> Self
+    where
+        T: Encryprtor + Identifiable,
+    {
+        Self {
+            version: Version::current_version(),
+            encryptor_id: pm.encryptor.id(),
+            body_sha,
+            vault_id: pm.vault_id,
+            device_id: identity::current_device_id(),
+            generation: pm.generation + 1,
+            kdf: pm.kdf,
+            hasher_id: pm.hasher_id,
+            // Every save writes the schema.
+            capabilities: pm.capabilities.retained().with(Capabilities::SCHEMA),
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn vault_id(&self) -> &VaultId {
+        &self.vault_id
+    }
+
+    #[cfg_attr(not(feature = "hidden-volume"), allow(dead_code))]
+    pub fn kdf(&self) -> Kdf {
+        self.kdf
+    }
+
+    /// How many times the vault has been saved, zero for vaults written
+    /// before the counter existed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 
     pub fn try_from_reader(r: &mut impl Read) -> Result<Self, EncoderError> {
-        let mut buf = [0; Self::SIZE];
-        let n = r.read(&mut buf).map_err(EncoderError::IoError)?;
-        if n != buf.len() {
+        let mut buf = vec![0; 1];
+        Self::read_exact(r, &mut buf)?;
+        let version = Self::parse_version(buf[0])?;
+
+        buf.resize(Self::size(version), 0);
+        Self::read_exact(r, &mut buf[1..])?;
+        Self::try_from_bytes(&buf)
+    }
+
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, EncoderError> {
+        let version = Self::parse_version(*bytes.first().ok_or(EncoderError::HeaderParseError)?)?;
+        if bytes.len() != Self::size(version) {
             return Err(EncoderError::InvalidHeaderSize);
         }
 
-        Self::try_from_bytes(buf)
-    }
-    pub fn try_from_bytes(bytes: [u8; Self::SIZE]) -> Result<Self, EncoderError> {
-        let version = Version::from_u8(bytes[0]).ok_or(EncoderError::HeaderParseError)?;
         let encoder_id = bytes[1];
-        let body_sha = bytes[2..]
+        let body_sha = bytes[2..Self::LEGACY_SIZE]
             .try_into()
             .or(Err(EncoderError::HeaderParseError))?;
+        let (vault_id, device_id) = if version.has_identity() {
+            let ids = &bytes[Self::LEGACY_SIZE..Self::IDENTITY_SIZE];
+            (
+                ids[..ID_LENGTH]
+                    .try_into()
+                    .or(Err(EncoderError::HeaderParseError))?,
+                ids[ID_LENGTH..]
+                    .try_into()
+                    .or(Err(EncoderError::HeaderParseError))?,
+            )
+        } else {
+            ([0; ID_LENGTH], [0; ID_LENGTH])
+        };
+        let generation = if version.has_generation() {
+            u64::from_be_bytes(
+                bytes[Self::IDENTITY_SIZE..Self::GENERATION_SIZE]
+                    .try_into()
+                    .or(Err(EncoderError::HeaderParseError))?,
+            )
+        } else {
+            0
+        };
+        let kdf = if version.has_kdf() {
+            Kdf::try_from_bytes(
+                bytes[Self::GENERATION_SIZE..Self::KDF_SIZE]
+                    .try_into()
+                    .or(Err(EncoderError::HeaderParseError))?,
+            )?
+        } else {
+            Kdf::Raw
+        };
+        let hasher_id = match version.has_hasher_id() {
+            true => bytes[Self::KDF_SIZE],
+            false => identifiers::DEFAULT_HASHER_ID,
+        };
+        let capabilities = match version.has_capabilities() {
+            true => Capabilities::from_bytes(
+                bytes[Self::HASHER_SIZE..Self::SIZE]
+                    .try_into()
+                    .or(Err(EncoderError::HeaderParseError))?,
+            ),
+            false => Capabilities::default(),
+        };
 
         Ok(Self {
             version,
             encryptor_id: encoder_id,
             body_sha,
+            vault_id,
+            device_id,
+            generation,
+            kdf,
+            hasher_id,
+            capabilities,
         })
     }
 
-    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
-        let mut res = [0; Self::SIZE];
-        res[0] = Version::current_version().to_u8();
-        res[1] = self.encryptor_id;
-        res[2..].copy_from_slice(&self.body_sha);
+    /// Versions past the current one are told apart from garbage, the
+    /// format only ever grows.
+    fn parse_version(byte: u8) -> Result<Version, EncoderError> {
+        match Version::from_u8(byte) {
+            Some(v) => Ok(v),
+            None if byte > Version::current_version().to_u8() && byte < 0x40 => {
+                Err(EncoderError::NewerFormat(byte))
+            }
+            None => Err(EncoderError::HeaderParseError),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = vec![self.version.to_u8(), self.encryptor_id];
+        res.extend_from_slice(&self.body_sha);
+        if self.version.has_identity() {
+            res.extend_from_slice(&self.vault_id);
+            res.extend_from_slice(&self.device_id);
+        }
+        if self.version.has_generation() {
+            res.extend_from_slice(&self.generation.to_be_bytes());
+        }
+        if self.version.has_kdf() {
+            res.extend_from_slice(&self.kdf.to_bytes());
+        }
+        if self.version.has_hasher_id() {
+            res.push(self.hasher_id);
+        }
+        if self.version.has_capabilities() {
+            res.extend_from_slice(&self.capabilities.to_bytes());
+        }
         res
     }
+
+    pub fn associated_data(&self) -> Vec<u8> {
+        if self.version.binds_header() {
+            self.to_bytes()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn read_exact(r: &mut impl Read, buf: &mut [u8]) -> Result<(), EncoderError> {
+        r.read_exact(buf).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => EncoderError::InvalidHeaderSize,
+            _ => EncoderError::IoError(err),
+        })
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    kv: HashMap<String, Box<[u8]>>,
+    pub kv: HashMap<String, Entry>,
+    pub tombstones: HashMap<KeyHash, u64>,
+    pub fingerprint_key: Option<FingerprintKey>,
+    /// The schema as it is stored, parsed once the body is checked.
+    pub schema: Option<Vec<u8>>,
 }
 
 impl Body {
-    pub fn to_bytes(kv: &HashMap<String, Box<[u8]>>) -> Vec<u8> {
-        kv.iter().fold(Vec::new(), |mut acc, (key, value)| {
-            acc.extend((key.len() as u64).to_be_bytes());
-            acc.extend((value.len() as u64).to_be_bytes());
-            acc.extend(key.as_bytes());
-            acc.extend(value.iter());
-            acc
-        })
+    /// Serializes a body in the format of v0.5 on, ending with `schema`
+    /// when the `SCHEMA` capability is set.
+    pub fn to_bytes(
+        kv: &HashMap<String, Entry>,
+        tombstones: &HashMap<KeyHash, u64>,
+        fingerprint_key: &FingerprintKey,
+        schema: Option<&[u8]>,
+    ) -> Vec<u8> {
+        let mut res = Vec::new();
+        res.extend(fingerprint_key);
+        res.extend((kv.len() as u64).to_be_bytes());
+        for (key, entry) in kv {
+            Self::write_entry(&mut res, key, entry);
+        }
+
+        res.extend((tombstones.len() as u64).to_be_bytes());
+        for (key_hash, deleted) in tombstones {
+            res.extend(key_hash);
+            res.extend(deleted.to_be_bytes());
+        }
+        if let Some(schema) = schema {
+            res.extend((schema.len() as u64).to_be_bytes());
+            res.extend(schema);
+        }
+        res
     }
 
-    pub fn try_from_bytes(bytes: &[u8]) -> Result<HashMap<String, Box<[u8]>>, EncoderError> {
-        if bytes.is_empty() {
-            return Ok(HashMap::new());
+    pub(in crate::core) fn write_entry(res: &mut Vec<u8>, key: &str, entry: &Entry) {
+        res.extend((key.len() as u64).to_be_bytes());
+        res.extend((entry.value.len() as u64).to_be_bytes());
+        res.extend(entry.modified.to_be_bytes());
+        res.extend(entry.fingerprint.unwrap_or([0; FINGERPRINT_LENGTH]));
+        res.extend(key.as_bytes());
+        res.extend(entry.value.iter());
+
+        res.extend((entry.meta.len() as u64).to_be_bytes());
+        for (name, value) in &entry.meta {
+            res.extend((name.len() as u64).to_be_bytes());
+            res.extend((value.len() as u64).to_be_bytes());
+            res.extend(name.as_bytes());
+            res.extend(value.as_bytes());
+        }
+    }
+
+    pub fn try_from_bytes(
+        version: Version,
+        capabilities: Capabilities,
+        bytes: &[u8],
+    ) -> Result<Self, EncoderError> {
+        let mut reader = BodyReader::new(bytes);
+        if !version.has_tombstones() {
+            return Self::try_from_legacy_reader(reader);
         }
         let mut kv = HashMap::new();
-        let mut iter = bytes.iter().copied().peekable();
+        let mut tombstones = HashMap::new();
 
-        while iter.peek().is_some() {
-            let key_length = Self::read_u64(&mut iter)? as usize;
-            let value_length = Self::read_u64(&mut iter)? as usize;
+        let fingerprint_key = if version.has_fingerprints() {
+            Some(reader.read_array()?)
+        } else {
+            None
+        };
 
-            let key: Vec<u8> = iter.by_ref().take(key_length).collect();
-            if key.len() != key_length {
-                return Err(EncoderError::BodyParseError);
+        let entry_size = 3 * size_of::<u64>()
+            + if version.has_fingerprints() {
+                FINGERPRINT_LENGTH
+            } else {
+                0
             }
-            let value: Vec<u8> = iter.by_ref().take(value_length).collect();
-            if value.len() != value_length {
-                return Err(EncoderError::BodyParseError);
+            + if version.has_metadata() {
+                size_of::<u64>()
+            } else {
+                0
+            };
+        for _ in 0..reader.read_count(entry_size)? {
+            let (key, entry) = Self::read_entry(&mut reader, version)?;
+            kv.insert(key, entry);
+        }
+
+        for _ in 0..reader.read_count(size_of::<KeyHash>() + size_of::<u64>())? {
+            let key_hash = reader.read_array()?;
+            tombstones.insert(key_hash, reader.read_u64()?);
+        }
+
+        let schema = match capabilities.contains(Capabilities::SCHEMA) {
+            true => {
+                let length = reader.read_length(MAX_SCHEMA_LENGTH)?;
+                Some(reader.read_bytes(length)?.to_vec())
             }
+            false => None,
+        };
+
+        if !reader.is_empty() && capabilities.ignored().is_empty() {
+            return Err(EncoderError::BodyParseError);
+        }
+        Ok(Self {
+            kv,
+            tombstones,
+            fingerprint_key,
+            schema,
+        })
+    }
 
-            let key_string = String::from_utf8(key).or(Err(EncoderError::BodyParseError))?;
-            kv.insert(key_string, value.into_boxed_slice());
+    fn read_entry(
+        reader: &mut BodyReader,
+        version: Version,
+    ) -> Result<(String, Entry), EncoderError> {
+        let key_length = reader.read_length(entry::MAX_KEY_LENGTH)?;
+        let value_length = reader.read_length(entry::MAX_VALUE_LENGTH)?;
+        let modified = reader.read_u64()?;
+        let fingerprint = if version.has_fingerprints() {
+            Some(reader.read_array()?).filter(|v: &Fingerprint| v.iter().any(|&b| b != 0))
+        } else {
+            None
+        };
+        let key = reader.read_string(key_length)?;
+        let value = reader.read_bytes(value_length)?;
+
+        let mut meta = BTreeMap::new();
+        if version.has_metadata() {
+            for _ in 0..reader.read_count(2 * size_of::<u64>())? {
+                let name_length = reader.read_length(entry::MAX_META_LENGTH)?;
+                let value_length = reader.read_length(entry::MAX_META_LENGTH)?;
+                let name = reader.read_string(name_length)?;
+                meta.insert(name, reader.read_string(value_length)?);
+            }
         }
 
-        Ok(kv)
+        let entry = Entry::new(value.into(), modified)
+            .with_fingerprint(fingerprint)
+            .with_meta(meta);
+        Ok((key, entry))
     }
 
-    fn read_u64(iter: &mut impl Iterator<Item = u8>) -> Result<u64, EncoderError> {
-        Ok(u64::from_be_bytes(
-            iter.take(size_of::<u64>())
-                .collect::<Vec<u8>>()
-                .try_into()
-                .or(Err(EncoderError::BodyParseError))?,
-        ))
+    /// Where each record of a decrypted body is, with its key but not its
+    /// value. A body that cannot be parsed is described up to where it
+    /// goes wrong, along with the error.
+    pub fn layout(
+        version: Version,
+        capabilities: Capabilities,
+        bytes: &[u8],
+    ) -> (Vec<Field>, Result<(), EncoderError>) {
+        let mut fields = Vec::new();
+        let result = Self::walk(version, capabilities, bytes, &mut fields);
+        (fields, result)
+    }
+
+    fn walk(
+        version: Version,
+        capabilities: Capabilities,
+        bytes: &[u8],
+        fields: &mut Vec<Field>,
+    ) -> Result<(), EncoderError> {
+        let mut reader = BodyReader::new(bytes);
+        let mut field = |reader: &BodyReader, start: usize, name: String, value: String| {
+            let end = bytes.len() - reader.bytes.len();
+            fields.push(Field {
+                offset: start,
+                length: end - start,
+                name,
+                value,
+            });
+            end
+        };
+        let mut start = 0;
+        if !version.has_tombstones() {
+            for n in 0.. {
+                if reader.is_empty() {
+                    return Ok(());
+                }
+                let key_length = reader.read_length(entry::MAX_KEY_LENGTH)?;
+                let value_length = reader.read_length(entry::MAX_VALUE_LENGTH)?;
+                let key = reader.read_string(key_length)?;
+                reader.read_bytes(value_length)?;
+                let value = format!("{:?}, value {} bytes", key, value_length);
+                start = field(&reader, start, format!("record[{}]", n), value);
+            }
+        }
+        if version.has_fingerprints() {
+            reader.read_array::<FINGERPRINT_LENGTH>()?;
+            start = field(&reader, start, "fingerprint_key".to_string(), String::new());
+        }
+        let count = reader.read_u64()?;
+        start = field(&reader, start, "entry count".to_string(), count.to_string());
+        for n in 0..count {
+            let (key, entry) = Self::read_entry(&mut reader, version)?;
+            let mut value = format!(
+                "{:?}, value {} bytes, modified {}",
+                key,
+                entry.value.len(),
+                entry.modified
+            );
+            if !entry.meta.is_empty() {
+                let names: Vec<&str> = entry.meta.keys().map(String::as_str).collect();
+                value.push_str(&format!(", meta {}", names.join(", ")));
+            }
+            start = field(&reader, start, format!("entry[{}]", n), value);
+        }
+        let count = reader.read_u64()?;
+        start = field(
+            &reader,
+            start,
+            "tombstone count".to_string(),
+            count.to_string(),
+        );
+        for n in 0..count {
+            reader.read_array::<{ size_of::<KeyHash>() }>()?;
+            let deleted = reader.read_u64()?;
+            let value = format!("deleted {}", deleted);
+            start = field(&reader, start, format!("tombstone[{}]", n), value);
+        }
+        if capabilities.contains(Capabilities::SCHEMA) {
+            let length = reader.read_length(MAX_SCHEMA_LENGTH)?;
+            start = field(
+                &reader,
+                start,
+                "schema length".to_string(),
+                length.to_string(),
+            );
+            reader.read_bytes(length)?;
+            start = field(&reader, start, "schema".to_string(), String::new());
+        }
+        if !reader.is_empty() {
+            reader.read_bytes(reader.bytes.len())?;
+            let ignored = capabilities.ignored();
+            if !ignored.is_empty() {
+                let value = format!("data of {}", ignored.names().join(", "));
+                field(&reader, start, "optional".to_string(), value);
+                return Ok(());
+            }
+            field(
+                &reader,
+                start,
+                "trailing".to_string(),
+                "not part of the format".to_string(),
+            );
+            return Err(EncoderError::BodyParseError);
+        }
+        Ok(())
+    }
+
+    /// Parses a single entry as written by `write_entry`.
+    #[cfg(feature = "sqlite")]
+    pub(in crate::core) fn entry_from_bytes(bytes: &[u8]) -> Result<(String, Entry), EncoderError> {
+        let mut reader = BodyReader::new(bytes);
+        let entry = Self::read_entry(&mut reader, Version::current_version())?;
+        if !reader.is_empty() {
+            return Err(EncoderError::BodyParseError);
+        }
+        Ok(entry)
+    }
+
+    /// Parses bodies written before entries carried timestamps, which are a
+    /// plain sequence of key/value records.
+    fn try_from_legacy_reader(mut reader: BodyReader) -> Result<Self, EncoderError> {
+        let mut kv = HashMap::new();
+
+        while !reader.is_empty() {
+            let key_length = reader.read_length(entry::MAX_KEY_LENGTH)?;
+            let value_length = reader.read_length(entry::MAX_VALUE_LENGTH)?;
+            let key = reader.read_string(key_length)?;
+            let value = reader.read_bytes(value_length)?;
+            kv.insert(key, Entry::new(value.into(), 0));
+        }
+
+        Ok(Self {
+            kv,
+            tombstones: HashMap::new(),
+            fingerprint_key: None,
+            schema: None,
+        })
+    }
+}
+
+/// Reads the body without trusting any length in it: every length and
+/// count is checked against the remaining input before it is used.
+struct BodyReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> BodyReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], EncoderError> {
+        let (bytes, rest) = self
+            .bytes
+            .split_at_checked(length)
+            .ok_or(EncoderError::BodyParseError)?;
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], EncoderError> {
+        self.read_bytes(N)?
+            .try_into()
+            .or(Err(EncoderError::BodyParseError))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, EncoderError> {
+        self.read_array().map(u64::from_be_bytes)
+    }
+
+    /// Reads the length of a field that may be at most `limit` bytes long.
+    fn read_length(&mut self, limit: usize) -> Result<usize, EncoderError> {
+        let length = usize::try_from(self.read_u64()?).or(Err(EncoderError::BodyLimitError))?;
+        if length > limit {
+            return Err(EncoderError::BodyLimitError);
+        }
+        if length > self.bytes.len() {
+            return Err(EncoderError::BodyParseError);
+        }
+        Ok(length)
+    }
+
+    /// Reads the number of records that follow, each at least `record_size`
+    /// bytes long.
+    fn read_count(&mut self, record_size: usize) -> Result<usize, EncoderError> {
+        let count = self.read_u64()?;
+        if count > (self.bytes.len() / record_size) as u64 {
+            return Err(EncoderError::BodyParseError);
+        }
+        Ok(count as usize)
+    }
+
+    fn read_string(&mut self, length: usize) -> Result<String, EncoderError> {
+        String::from_utf8(self.read_bytes(length)?.to_vec()).or(Err(EncoderError::BodyParseError))
     }
 }
 
@@ -184,30 +929,150 @@ impl Body {
 mod tests {
     use std::io::Cursor;
 
-    use crate::core::encryptor::{AESEncryptor, BlankEncryptor};
+    use crate::core::{
+        encoding::layout,
+        encryptor::{AESEncryptor, BlankEncryptor},
+        hasher::{Hasher, Sha256Hasher},
+        identifiers::hasher_id_from_name,
+        kdf::KdfParams,
+    };
 
     use super::*;
 
+    const TEST_KDF_PARAMS: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn schema() -> Capabilities {
+        Capabilities::default().with(Capabilities::SCHEMA)
+    }
+
     #[test]
     pub fn test_body() {
         let mut kv = HashMap::new();
         kv.insert(
             "foo".to_string(),
-            "bar".bytes().collect::<Vec<u8>>().into_boxed_slice(),
+            Entry::new("bar".bytes().collect::<Vec<u8>>().into_boxed_slice(), 1),
         );
         kv.insert(
             "".to_string(),
-            "".bytes().collect::<Vec<u8>>().into_boxed_slice(),
+            Entry::new("".bytes().collect::<Vec<u8>>().into_boxed_slice(), 0),
         );
         kv.insert(
             "ƥƫƯȭ".to_string(),
-            "ƥḌ ".bytes().collect::<Vec<u8>>().into_boxed_slice(),
+            Entry::new("ƥḌ ".bytes().collect::<Vec<u8>>().into_boxed_slice(), 2),
+        );
+        kv.insert(
+            "fp".to_string(),
+            Entry::new(Box::new([1]), 3)
+                .with_fingerprint(Some([9; FINGERPRINT_LENGTH]))
+                .with_meta(BTreeMap::from([("kind".to_string(), "ƥḌ".to_string())])),
         );
+        let mut tombstones = HashMap::new();
+        tombstones.insert([5; 32], 10);
+
+        let body = Body::try_from_bytes(
+            Version::current_version(),
+            schema(),
+            Body::to_bytes(&kv, &tombstones, &[7; FINGERPRINT_LENGTH], Some(b"{}")).as_ref(),
+        )
+        .unwrap();
+        assert_eq!(kv, body.kv);
+        assert_eq!(tombstones, body.tombstones);
+        assert_eq!(body.fingerprint_key, Some([7; FINGERPRINT_LENGTH]));
+        assert_eq!(kv.len(), 4);
+    }
+
+    #[test]
+    pub fn test_legacy_body() {
+        let mut bytes = Vec::new();
+        bytes.extend(3u64.to_be_bytes());
+        bytes.extend(3u64.to_be_bytes());
+        bytes.extend(b"foobar");
+
+        let body = Body::try_from_bytes(Version::V0_2, Capabilities::default(), &bytes).unwrap();
         assert_eq!(
-            kv,
-            Body::try_from_bytes(Body::to_bytes(&kv).as_ref()).unwrap()
+            body.kv.get("foo"),
+            Some(&Entry::new(b"bar".to_vec().into_boxed_slice(), 0))
         );
-        assert_eq!(kv.len(), 3);
+        assert!(
+            Body::try_from_bytes(Version::V0_2, Capabilities::default(), &bytes[..10]).is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_malformed_body() {
+        let mut kv = HashMap::new();
+        kv.insert(
+            "foo".to_string(),
+            Entry::new(Box::new([1, 2, 3]), 1)
+                .with_meta(BTreeMap::from([("url".to_string(), "x".to_string())])),
+        );
+        let mut tombstones = HashMap::new();
+        tombstones.insert([5; 32], 10);
+        let bytes = Body::to_bytes(&kv, &tombstones, &[7; FINGERPRINT_LENGTH], Some(b"{}"));
+
+        for version in [Version::V0_2, Version::current_version()] {
+            for end in 0..bytes.len() {
+                let _ = Body::try_from_bytes(version, schema(), &bytes[..end]);
+            }
+        }
+
+        let mut state = 0x2545f4914f6cdd1d_u64;
+        for _ in 0..2000 {
+            let mut mutated = bytes.clone();
+            for _ in 0..4 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let index = state as usize % mutated.len();
+                mutated[index] = (state >> 32) as u8;
+            }
+            let _ = Body::try_from_bytes(Version::current_version(), schema(), &mutated);
+        }
+
+        let mut huge_count = vec![0; FINGERPRINT_LENGTH];
+        huge_count.extend(u64::MAX.to_be_bytes());
+        assert!(matches!(
+            Body::try_from_bytes(Version::current_version(), schema(), &huge_count),
+            Err(EncoderError::BodyParseError)
+        ));
+
+        let mut huge_key = vec![0; FINGERPRINT_LENGTH];
+        huge_key.extend(1u64.to_be_bytes());
+        huge_key.extend(u64::MAX.to_be_bytes());
+        huge_key.extend(vec![0; 64]);
+        assert!(matches!(
+            Body::try_from_bytes(Version::current_version(), schema(), &huge_key),
+            Err(EncoderError::BodyLimitError)
+        ));
+        assert!(matches!(
+            Body::try_from_bytes(
+                Version::V0_2,
+                Capabilities::default(),
+                &u64::MAX.to_be_bytes()
+            ),
+            Err(EncoderError::BodyLimitError)
+        ));
+    }
+
+    #[test]
+    pub fn test_optional_data() {
+        let mut bytes = Body::to_bytes(&HashMap::new(), &HashMap::new(), &[7; 32], Some(b"{}"));
+        let version = Version::current_version();
+        let body = Body::try_from_bytes(version, schema(), &bytes).unwrap();
+        assert_eq!(body.schema.as_deref(), Some(&b"{}"[..]));
+        assert!(Body::try_from_bytes(version, Capabilities::default(), &bytes).is_err());
+
+        let ignored = Capabilities::from_bytes([0, 0x10, 0, 0]);
+        let body = Body::try_from_bytes(version, ignored, &bytes).unwrap();
+        assert_eq!(body.schema, None);
+        bytes.extend(b"newer");
+        assert!(Body::try_from_bytes(version, schema(), &bytes).is_err());
+        let body = Body::try_from_bytes(version, schema().with(1 << 20), &bytes).unwrap();
+        assert_eq!(body.schema.as_deref(), Some(&b"{}"[..]));
     }
 
     #[test]
@@ -216,12 +1081,37 @@ mod tests {
             version: Version::V0_0,
             encryptor_id: 100,
             body_sha: [1; 32],
+            vault_id: [0; ID_LENGTH],
+            device_id: [0; ID_LENGTH],
+            generation: 0,
+            kdf: Kdf::Raw,
+            hasher_id: identifiers::DEFAULT_HASHER_ID,
+            capabilities: Capabilities::default(),
         };
 
         let bytes = a.to_bytes();
-        let b = Header::try_from_bytes(bytes).unwrap();
+        let b = Header::try_from_bytes(&bytes).unwrap();
+
+        assert_eq!(a, b);
+
+        let a = Header {
+            version: Version::current_version(),
+            encryptor_id: 1,
+            body_sha: [1; 32],
+            vault_id: [2; ID_LENGTH],
+            device_id: [3; ID_LENGTH],
+            generation: 4,
+            kdf: Kdf::argon2id(TEST_KDF_PARAMS).unwrap(),
+            hasher_id: 2,
+            capabilities: Capabilities::from_bytes([0, 1, 0, 2]),
+        };
+        let b = Header::try_from_reader(&mut Cursor::new(a.to_bytes())).unwrap();
 
-        assert_eq!(a, b)
+        assert_eq!(a, b);
+        assert!(matches!(
+            Header::try_from_reader(&mut Cursor::new(&a.to_bytes()[..40])),
+            Err(EncoderError::InvalidHeaderSize)
+        ));
     }
 
     #[test]
@@ -230,27 +1120,253 @@ mod tests {
         let _ = pm.store_password("foo".to_string(), "bar");
         let _ = pm.store_password("foo2".to_string(), "baz");
         let mut v = Vec::new();
-        Encoder::encode(&mut v, &mut pm);
+        Encoder::encode(&mut v, &mut pm).unwrap();
         let mut c = Cursor::new(v);
-        let pm2 = Encoder::decode(b"foobar", &mut c).unwrap();
+        let pm2 = Encoder::decode(b"foobar", &mut c, &mut KeyCache::default()).unwrap();
         assert_eq!(pm.encryptor.id(), pm2.encryptor.id());
         assert_eq!(pm.kv, pm2.kv);
+        assert_eq!(pm.vault_id(), pm2.vault_id());
+        assert_eq!(pm2.last_device(), &identity::current_device_id());
+        assert_eq!(pm.generation(), 1);
+        assert_eq!(pm2.generation(), 1);
 
         assert_eq!(pm.get_password("foo2"), Ok("baz".to_string()))
     }
 
+    #[test]
+    pub fn test_encoder_kdf() {
+        let kdf = Kdf::argon2id(TEST_KDF_PARAMS).unwrap();
+        let mut pm = PasswordManager::from_raw_parts(
+            HashMap::new(),
+            AESEncryptor::new(kdf.derive(b"foobar").unwrap()),
+        )
+        .with_kdf(kdf);
+        let _ = pm.store_password("foo".to_string(), "bar");
+        let mut v = Vec::new();
+        Encoder::encode(&mut v, &mut pm).unwrap();
+
+        let mut pm2 = Encoder::decode(
+            b"foobar",
+            &mut Cursor::new(v.clone()),
+            &mut KeyCache::default(),
+        )
+        .unwrap();
+        assert_eq!(pm2.kdf(), kdf);
+        assert_eq!(pm2.get_password("foo"), Ok("bar".to_string()));
+        assert!(Encoder::decode(b"foobaz", &mut Cursor::new(v), &mut KeyCache::default()).is_err());
+    }
+
+    #[test]
+    pub fn test_encoder_hashers() {
+        for name in ["sha256", "sha512-256", "blake2b"] {
+            let hasher_id = hasher_id_from_name(name).unwrap();
+            let mut pm =
+                PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foobar"))
+                    .with_hasher(hasher_id);
+            let _ = pm.store_password("foo".to_string(), "bar");
+            let mut v = Vec::new();
+            Encoder::encode(&mut v, &mut pm).unwrap();
+
+            let mut pm2 = Encoder::decode(
+                b"foobar",
+                &mut Cursor::new(v.clone()),
+                &mut KeyCache::default(),
+            )
+            .unwrap();
+            assert_eq!(pm2.hasher_id(), hasher_id);
+            assert_eq!(pm2.get_password("foo"), Ok("bar".to_string()));
+
+            v[Header::KDF_SIZE] = (hasher_id + 1) % 3;
+            assert!(matches!(
+                Encoder::decode(b"foobar", &mut Cursor::new(v), &mut KeyCache::default()),
+                Err(EncoderError::IvalidKeyError)
+            ));
+        }
+    }
+
+    #[test]
+    pub fn test_encoder_namespaces() {
+        let mut pm = PasswordManager::init("foobar", Kdf::Raw).unwrap();
+        let _ = pm.store_password("work/db".to_string(), "bar");
+        let mut v = Vec::new();
+        Encoder::encode(&mut v, &mut pm).unwrap();
+
+        let mut pm2 =
+            Encoder::decode(b"foobar", &mut Cursor::new(v), &mut KeyCache::default()).unwrap();
+        assert_eq!(pm2.get_password("work/db"), Ok("bar".to_string()));
+    }
+
     #[test]
     pub fn test_different_encoder() {
         let mut pm = PasswordManager::from_raw_parts(HashMap::new(), BlankEncryptor::new());
         let _ = pm.store_password("foo".to_string(), "bar");
         let _ = pm.store_password("foo2".to_string(), "baz");
         let mut v = Vec::new();
-        Encoder::encode(&mut v, &mut pm);
+        Encoder::encode(&mut v, &mut pm).unwrap();
         let mut c = Cursor::new(v);
-        let pm2 = Encoder::decode(b"foobar", &mut c).unwrap();
+        let pm2 = Encoder::decode(b"foobar", &mut c, &mut KeyCache::default()).unwrap();
         assert_eq!(pm.encryptor.id(), pm2.encryptor.id());
         assert_eq!(pm.kv, pm2.kv);
 
         assert_eq!(pm.get_password("foo2"), Ok("baz".to_string()))
     }
+
+    #[test]
+    pub fn test_decode_unsupported_format() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foobar"));
+        let mut v = Vec::new();
+        Encoder::encode(&mut v, &mut pm).unwrap();
+
+        let mut compressed = v.clone();
+        compressed[Header::SIZE - 1] = Capabilities::COMPRESSION as u8;
+        assert!(matches!(
+            Encoder::decode(
+                b"foobar",
+                &mut Cursor::new(compressed),
+                &mut KeyCache::default()
+            ),
+            Err(EncoderError::CapabilityError(CapabilityError::Unsupported(
+                _
+            )))
+        ));
+
+        let newer = Version::current_version().to_u8() + 1;
+        v[0] = newer;
+        assert!(matches!(
+            StoredVault::File(v.clone()).version(),
+            Err(EncoderError::NewerFormat(n)) if n == newer
+        ));
+        assert!(matches!(
+            Encoder::decode(b"foobar", &mut Cursor::new(v), &mut KeyCache::default()),
+            Err(EncoderError::NewerFormat(n)) if n == newer
+        ));
+        assert!(matches!(
+            StoredVault::File(vec![0xff]).version(),
+            Err(EncoderError::HeaderParseError)
+        ));
+        assert!(matches!(
+            StoredVault::File(Vec::new()).version(),
+            Err(EncoderError::HeaderParseError)
+        ));
+    }
+
+    #[test]
+    pub fn test_decode_unbound_header() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foobar"));
+        let _ = pm.store_password("foo".to_string(), "bar");
+
+        let mut body_bytes = Vec::new();
+        for (key, entry) in &pm.kv {
+            body_bytes.extend((key.len() as u64).to_be_bytes());
+            body_bytes.extend((entry.value.len() as u64).to_be_bytes());
+            body_bytes.extend(key.as_bytes());
+            body_bytes.extend(entry.value.iter());
+        }
+        let header = Header {
+            version: Version::V0_0,
+            encryptor_id: pm.encryptor.id(),
+            body_sha: Sha256Hasher::new().hash(&body_bytes)[..]
+                .try_into()
+                .unwrap(),
+            vault_id: [0; ID_LENGTH],
+            device_id: [0; ID_LENGTH],
+            generation: 0,
+            kdf: Kdf::Raw,
+            hasher_id: identifiers::DEFAULT_HASHER_ID,
+            capabilities: Capabilities::default(),
+        };
+        let mut v = header.to_bytes();
+        v.extend(pm.encryptor.encrypt(&body_bytes, &[]).unwrap().iter());
+
+        let mut pm2 =
+            Encoder::decode(b"foobar", &mut Cursor::new(v), &mut KeyCache::default()).unwrap();
+        assert_eq!(pm2.get_password("foo"), Ok("bar".to_string()))
+    }
+
+    #[test]
+    pub fn test_decode_body_digest() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foobar"));
+        let _ = pm.store_password("foo".to_string(), "bar");
+        let body_bytes = Body::to_bytes(&pm.kv, &pm.tombstones, &pm.fingerprint_key, None);
+        let header = Header {
+            version: Version::V0_9,
+            encryptor_id: pm.encryptor.id(),
+            body_sha: Sha256Hasher::new().hash(&body_bytes)[..]
+                .try_into()
+                .unwrap(),
+            vault_id: [1; ID_LENGTH],
+            device_id: [0; ID_LENGTH],
+            generation: 1,
+            kdf: Kdf::Raw,
+            hasher_id: identifiers::DEFAULT_HASHER_ID,
+            capabilities: Capabilities::default(),
+        };
+        let mut v = header.to_bytes();
+        v.extend(
+            pm.encryptor
+                .encrypt(&body_bytes, &header.associated_data())
+                .unwrap()
+                .iter(),
+        );
+
+        let mut pm2 =
+            Encoder::decode(b"foobar", &mut Cursor::new(v), &mut KeyCache::default()).unwrap();
+        assert_eq!(pm2.get_password("foo"), Ok("bar".to_string()))
+    }
+
+    #[test]
+    pub fn test_layout() {
+        for id in 0..=Version::current_version().to_u8() {
+            let version = Version::from_u8(id).unwrap();
+            assert_eq!(layout::header_size(version), Header::size(version));
+        }
+
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foobar"));
+        pm.store_password("foo".to_string(), "hunter2").unwrap();
+        pm.store_password("gone".to_string(), "x").unwrap();
+        pm.delete("gone").unwrap();
+        let mut v = Vec::new();
+        Encoder::encode(&mut v, &mut pm).unwrap();
+        let (header, body, slack) =
+            Encoder::decrypt_body(b"foobar", &v, &mut KeyCache::default()).unwrap();
+        let (version, capabilities) = (header.version(), header.capabilities());
+        assert_eq!(version, Version::current_version());
+        assert_eq!(capabilities, schema());
+        assert!(!slack);
+
+        let (fields, result) = Body::layout(version, capabilities, &body);
+        assert!(result.is_ok());
+        let names: Vec<&str> = fields.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "fingerprint_key",
+                "entry count",
+                "entry[0]",
+                "tombstone count",
+                "tombstone[0]",
+                "schema length",
+                "schema"
+            ]
+        );
+        assert!(fields[2].value.starts_with("\"foo\""));
+        let last = fields.last().unwrap();
+        assert_eq!(last.offset + last.length, body.len());
+
+        let (fields, result) = Body::layout(version, capabilities, &body[..body.len() - 1]);
+        assert!(result.is_err());
+        assert_eq!(fields.len(), 5);
+        assert!(Encoder::decrypt_body(b"foobaz", &v, &mut KeyCache::default()).is_err());
+    }
+
+    #[test]
+    pub fn test_spliced_header() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foobar"));
+        let _ = pm.store_password("foo".to_string(), "bar");
+        let mut v = Vec::new();
+        Encoder::encode(&mut v, &mut pm).unwrap();
+
+        v[0] = Version::V0_0.to_u8();
+        assert!(Encoder::decode(b"foobar", &mut Cursor::new(v), &mut KeyCache::default()).is_err());
+    }
 }
@@ -0,0 +1,147 @@
+//! Features a vault uses, as bits in the header from v0.11 on. The low half
+//! holds required features: a vault with a required bit this binary does
+//! not support is not opened, and the error names the feature when it is
+//! known. The high half holds optional features, which a binary that does
+//! not support them may ignore; they are dropped when it saves the vault.
+//! The data of an optional feature follows the data of the format, so a
+//! binary that ignores the feature stops reading before it.
+
+use thiserror::Error;
+
+/// Features whose names are known, supported or not.
+const NAMES: [(u32, &str); 5] = [
+    (Capabilities::COMPRESSION, "compression"),
+    (Capabilities::KEY_SLOTS, "key slots"),
+    (Capabilities::MIRROR, "read-only mirror"),
+    (Capabilities::BOUND_VALUES, "bound values"),
+    (Capabilities::SCHEMA, "schema"),
+];
+/// Features this binary reads and writes.
+const SUPPORTED: u32 = Capabilities::MIRROR | Capabilities::BOUND_VALUES | Capabilities::SCHEMA;
+const OPTIONAL: u32 = 0xffff_0000;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CapabilityError {
+    #[error("the vault uses {}, which this version of mopm does not support, update mopm", .0.join(", "))]
+    Unsupported(Vec<&'static str>),
+    #[error("the vault uses features unknown to this version of mopm ({0:#010x}), update mopm")]
+    Unknown(u32),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const ENCODED_SIZE: usize = 4;
+
+    /// The body is compressed before it is encrypted.
+    pub const COMPRESSION: u32 = 1 << 0;
+    /// The vault key is wrapped by several passwords or devices.
+    pub const KEY_SLOTS: u32 = 1 << 1;
+    /// The vault is a read-only mirror of another one, see `mirror`.
+    pub const MIRROR: u32 = 1 << 2;
+    /// Entry values are bound to their key and the vault, see `namespace`.
+    pub const BOUND_VALUES: u32 = 1 << 3;
+    /// The body ends with a description of the entry fields, see `schema`.
+    /// Optional.
+    pub const SCHEMA: u32 = 1 << 16;
+
+    pub fn from_bytes(bytes: [u8; Self::ENCODED_SIZE]) -> Self {
+        Self(u32::from_be_bytes(bytes))
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::ENCODED_SIZE] {
+        self.0.to_be_bytes()
+    }
+
+    /// Fails unless every required feature is supported.
+    pub fn check(self) -> Result<(), CapabilityError> {
+        let missing = self.0 & !OPTIONAL & !SUPPORTED;
+        if missing == 0 {
+            return Ok(());
+        }
+        let named: Vec<&str> = NAMES
+            .iter()
+            .filter(|(bit, _)| missing & bit != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        let unnamed = NAMES.iter().fold(missing, |acc, (bit, _)| acc & !bit);
+        match unnamed {
+            0 => Err(CapabilityError::Unsupported(named)),
+            _ => Err(CapabilityError::Unknown(missing)),
+        }
+    }
+
+    /// Optional features that are ignored, and dropped on the next save.
+    pub fn ignored(self) -> Self {
+        Self(self.0 & OPTIONAL & !SUPPORTED)
+    }
+
+    /// The features kept when the vault is saved.
+    pub fn retained(self) -> Self {
+        Self(self.0 & SUPPORTED)
+    }
+
+    pub fn with(self, bit: u32) -> Self {
+        Self(self.0 | bit)
+    }
+
+    pub fn without(self, bit: u32) -> Self {
+        Self(self.0 & !bit)
+    }
+
+    pub fn contains(self, bit: u32) -> bool {
+        self.0 & bit != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The names of the features, unknown ones as bits.
+    pub fn names(self) -> Vec<String> {
+        (0..u32::BITS)
+            .map(|n| 1 << n)
+            .filter(|bit| self.0 & bit != 0)
+            .map(|bit| match NAMES.iter().find(|(v, _)| *v == bit) {
+                Some((_, name)) => name.to_string(),
+                None => format!("{:#010x}", bit),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(Capabilities::default().check(), Ok(()));
+        assert_eq!(
+            Capabilities(Capabilities::COMPRESSION | Capabilities::KEY_SLOTS).check(),
+            Err(CapabilityError::Unsupported(vec![
+                "compression",
+                "key slots"
+            ]))
+        );
+        assert_eq!(
+            Capabilities(Capabilities::COMPRESSION | 1 << 7).check(),
+            Err(CapabilityError::Unknown(0x81))
+        );
+        assert_eq!(Capabilities(1 << 20).check(), Ok(()));
+        assert_eq!(
+            Capabilities::default().with(Capabilities::MIRROR).check(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_ignored_optional_features() {
+        let capabilities = Capabilities::from_bytes([0, 0x10, 0, 0]);
+        assert_eq!(capabilities.ignored(), capabilities);
+        assert!(capabilities.retained().is_empty());
+        assert_eq!(capabilities.names(), vec!["0x00100000"]);
+        assert_eq!(capabilities.to_bytes(), [0, 0x10, 0, 0]);
+    }
+}
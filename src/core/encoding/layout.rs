@@ -0,0 +1,475 @@
+//! Where every field of a vault file is, for `mopm dump`. The format
+//! reference in docs/format.md is generated from the same header table by
+//! `write_format_docs`, and a test fails when it is out of date, so that it
+//! cannot drift from the encoder.
+//!
+//! A vault file is the header, then the encrypted body, then maybe the
+//! slack; the records of the body are found by `Body::layout` once it is
+//! decrypted.
+
+use std::mem::size_of;
+
+use super::{capability::Capabilities, version::Version};
+use crate::core::{
+    encoder::SLACK_SIZE,
+    hasher::DIGEST_LENGTH,
+    identifiers,
+    identity::{self, ID_LENGTH},
+    kdf::Kdf,
+    nonce::NONCE_LENGTH,
+};
+
+/// Length of the AES-GCM authentication tag closing an encrypted body.
+const TAG_LENGTH: usize = 16;
+
+/// A field of a vault file, at `offset` into the part it is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub offset: usize,
+    pub length: usize,
+    pub name: String,
+    /// What the field holds, never a secret.
+    pub value: String,
+}
+
+/// Name, length, the formats that have it and what it holds.
+type HeaderField = (&'static str, usize, fn(Version) -> bool, &'static str);
+
+fn always(_: Version) -> bool {
+    true
+}
+
+/// The header fields in the order they are written.
+const HEADER: [HeaderField; 9] = [
+    ("version", 1, always, "format of the vault"),
+    (
+        "encryptor",
+        1,
+        always,
+        "cipher of the body: 0 none, 1 AES-256-GCM",
+    ),
+    (
+        "digest",
+        DIGEST_LENGTH,
+        always,
+        "digest of the plaintext body, the Merkle root over the entries from v0.10",
+    ),
+    (
+        "vault_id",
+        ID_LENGTH,
+        Version::has_identity,
+        "UUID of the vault",
+    ),
+    (
+        "device_id",
+        ID_LENGTH,
+        Version::has_identity,
+        "UUID of the device that wrote it",
+    ),
+    (
+        "generation",
+        size_of::<u64>(),
+        Version::has_generation,
+        "how many times the vault was saved",
+    ),
+    (
+        "kdf",
+        Kdf::ENCODED_SIZE,
+        Version::has_kdf,
+        "0 when the password is the key, or 1 for Argon2id then memory (KiB), \
+         iterations and parallelism as u32 and a 16-byte salt",
+    ),
+    (
+        "hasher",
+        1,
+        Version::has_hasher_id,
+        "hasher of the digest: 0 SHA-256, 1 SHA-512/256, 2 BLAKE2b",
+    ),
+    (
+        "capabilities",
+        Capabilities::ENCODED_SIZE,
+        Version::has_capabilities,
+        "bits of the features the vault uses",
+    ),
+];
+
+/// The size of the header of `version`.
+pub fn header_size(version: Version) -> usize {
+    HEADER
+        .iter()
+        .filter(|(_, _, present, _)| present(version))
+        .map(|(_, length, _, _)| length)
+        .sum()
+}
+
+/// The header fields at the start of `bytes`, `None` unless they start
+/// with a whole header of a known format.
+pub fn header(bytes: &[u8]) -> Option<Vec<Field>> {
+    let version = Version::from_u8(*bytes.first()?)?;
+    if bytes.len() < header_size(version) {
+        return None;
+    }
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    for (name, length, present, _) in HEADER {
+        if !present(version) {
+            continue;
+        }
+        fields.push(Field {
+            offset,
+            length,
+            name: name.to_string(),
+            value: describe_header_field(name, &bytes[offset..offset + length]),
+        });
+        offset += length;
+    }
+    Some(fields)
+}
+
+fn describe_header_field(name: &str, bytes: &[u8]) -> String {
+    match name {
+        "version" => Version::from_u8(bytes[0]).map_or("unknown".to_string(), |v| v.to_string()),
+        "encryptor" => format!("{} ({})", bytes[0], identifiers::encryptor_name(bytes[0])),
+        "hasher" => format!("{} ({})", bytes[0], identifiers::hasher_name(bytes[0])),
+        "vault_id" | "device_id" => {
+            identity::format_id(bytes.try_into().expect("ids are ID_LENGTH long"))
+        }
+        "generation" => u64::from_be_bytes(bytes.try_into().expect("a u64")).to_string(),
+        "kdf" => match Kdf::try_from_bytes(bytes.try_into().expect("kdf is ENCODED_SIZE long")) {
+            Ok(Kdf::Raw) => "none, the password is the key".to_string(),
+            Ok(Kdf::Argon2id { params, salt }) => format!(
+                "Argon2id, memory {} KiB, iterations {}, parallelism {}, salt {}",
+                params.memory_kib,
+                params.iterations,
+                params.parallelism,
+                hex::encode(salt)
+            ),
+            Err(err) => err.to_string(),
+        },
+        "capabilities" => {
+            let capabilities = Capabilities::from_bytes(
+                bytes
+                    .try_into()
+                    .expect("capabilities are ENCODED_SIZE long"),
+            );
+            match capabilities.is_empty() {
+                true => "none".to_string(),
+                false => capabilities.names().join(", "),
+            }
+        }
+        _ => hex::encode(bytes),
+    }
+}
+
+/// What follows a header of `header_size` bytes in a file of `length`:
+/// the encrypted body, split as its encryptor writes it, and the slack.
+/// Only the password tells whether there is slack, `None` when it is not
+/// known.
+pub fn segments(
+    header_size: usize,
+    length: usize,
+    encryptor_id: u8,
+    slack: Option<bool>,
+) -> Vec<Field> {
+    let body_end = match slack {
+        Some(true) => length.saturating_sub(SLACK_SIZE).max(header_size),
+        _ => length,
+    };
+    let mut fields = Vec::new();
+    let mut push = |offset: usize, end: usize, name: &str, value: &str| {
+        fields.push(Field {
+            offset,
+            length: end - offset,
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    };
+    let body_length = body_end - header_size;
+    if encryptor_id == identifiers::AESENCRYPTOR_ID && body_length >= NONCE_LENGTH + TAG_LENGTH {
+        let (nonce_end, tag_start) = (header_size + NONCE_LENGTH, body_end - TAG_LENGTH);
+        push(header_size, nonce_end, "nonce", "");
+        push(nonce_end, tag_start, "ciphertext", "the encrypted body");
+        push(tag_start, body_end, "tag", "");
+    } else {
+        push(header_size, body_end, "body", "");
+    }
+    match slack {
+        Some(true) => push(body_end, length, "slack", "random bytes or a hidden vault"),
+        None if body_length > SLACK_SIZE => push(
+            length - SLACK_SIZE,
+            length,
+            "slack?",
+            "maybe slack, only the password tells",
+        ),
+        _ => {}
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fmt::Write, path::Path};
+
+    use super::*;
+
+    const REFERENCE: &str = "docs/format.md";
+
+    /// Like the header fields, with lengths that may be those of another
+    /// field.
+    type Row = (
+        &'static str,
+        &'static str,
+        fn(Version) -> bool,
+        &'static str,
+    );
+    /// A row with its length written out, as the tables hold it.
+    type Line = (&'static str, String, fn(Version) -> bool, &'static str);
+
+    const BODY: [Row; 7] = [
+        (
+            "fingerprint_key",
+            "32",
+            Version::has_fingerprints,
+            "key of the entry fingerprints",
+        ),
+        ("entry count", "8", Version::has_tombstones, ""),
+        (
+            "entries",
+            "",
+            Version::has_tombstones,
+            "entry records, in no particular order",
+        ),
+        ("tombstone count", "8", Version::has_tombstones, ""),
+        (
+            "tombstones",
+            "",
+            Version::has_tombstones,
+            "tombstone records",
+        ),
+        (
+            "schema length",
+            "8",
+            Version::has_capabilities,
+            "with the `schema` capability",
+        ),
+        (
+            "schema",
+            "schema length",
+            Version::has_capabilities,
+            "JSON description of the entry fields",
+        ),
+    ];
+
+    const ENTRY: [Row; 8] = [
+        ("key length", "8", Version::has_tombstones, ""),
+        ("value length", "8", Version::has_tombstones, ""),
+        (
+            "modified",
+            "8",
+            Version::has_tombstones,
+            "unix time of the last change",
+        ),
+        (
+            "fingerprint",
+            "32",
+            Version::has_fingerprints,
+            "zeros for none",
+        ),
+        ("key", "key length", Version::has_tombstones, "UTF-8"),
+        (
+            "value",
+            "value length",
+            Version::has_tombstones,
+            "encrypted on its own, under the key of its namespace from v0.8",
+        ),
+        ("meta count", "8", Version::has_metadata, ""),
+        (
+            "meta",
+            "",
+            Version::has_metadata,
+            "name length (8), value length (8), name and value, UTF-8",
+        ),
+    ];
+
+    const TOMBSTONE: [Row; 2] = [
+        (
+            "key hash",
+            "32",
+            Version::has_tombstones,
+            "SHA-256 of the deleted key",
+        ),
+        (
+            "deleted",
+            "8",
+            Version::has_tombstones,
+            "unix time of the deletion",
+        ),
+    ];
+
+    /// What each format changed.
+    const CHANGES: [(Version, &str); 12] = [
+        (
+            Version::V0_0,
+            "header of version, encryptor and digest; body of key/value records",
+        ),
+        (
+            Version::V0_1,
+            "the header is the associated data of the body",
+        ),
+        (Version::V0_2, "vault and device UUIDs"),
+        (Version::V0_3, "entry timestamps and tombstones"),
+        (Version::V0_4, "fingerprint key and entry fingerprints"),
+        (Version::V0_5, "entry metadata"),
+        (Version::V0_6, "generation counter"),
+        (Version::V0_7, "key derivation function and its salt"),
+        (
+            Version::V0_8,
+            "namespaced values are encrypted under their namespace key",
+        ),
+        (Version::V0_9, "hasher of the digest"),
+        (
+            Version::V0_10,
+            "the digest is the Merkle root over the entries",
+        ),
+        (
+            Version::V0_11,
+            "capability bits, the optional `schema` one for the schema of the entry fields",
+        ),
+    ];
+
+    /// The format reference, as docs/format.md holds it.
+    fn reference() -> String {
+        let mut out = format!(
+            "# Vault file format\n\n\
+             Generated from `src/core/encoding/layout.rs` by `cargo test -- --ignored \
+             write_format_docs`, do not edit. `mopm dump` shows where these fields are in \
+             a vault file, `mopm dump --debug` the records of its body too.\n\n\
+             A vault file is the header, then the body encrypted with the header as \
+             associated data (from v0.1), then maybe {} bytes of slack: random bytes or a \
+             hidden vault. The body is tried with and without the slack. An AES-256-GCM \
+             body is a {}-byte nonce, the ciphertext and a {}-byte tag.\n\n\
+             Integers are unsigned and big-endian, lengths in bytes. The current format \
+             is {}.\n",
+            SLACK_SIZE,
+            NONCE_LENGTH,
+            TAG_LENGTH,
+            Version::current_version()
+        );
+        let header =
+            HEADER.map(|(name, length, present, holds)| (name, length.to_string(), present, holds));
+        table(&mut out, "Header", &header);
+        let owned =
+            |(name, length, present, holds): Row| (name, length.to_string(), present, holds);
+        table(&mut out, "Body", &BODY.map(owned));
+        out.push_str(
+            "\nBodies before v0.3 are a sequence of key length (8), value length (8), key \
+             and value records. The data of optional capabilities ends the body, a reader \
+             that ignores them stops before it.\n",
+        );
+        table(&mut out, "Entry record", &ENTRY.map(owned));
+        table(&mut out, "Tombstone record", &TOMBSTONE.map(owned));
+        out.push_str("\n## Formats\n\n| Format | Changes |\n|---|---|\n");
+        for (version, change) in CHANGES {
+            let _ = writeln!(out, "| {} | {} |", version, change);
+        }
+        out
+    }
+
+    fn table(out: &mut String, title: &str, rows: &[Line]) {
+        let _ = write!(
+            out,
+            "\n## {}\n\n| Field | Length | Since | Holds |\n|---|---|---|---|\n",
+            title
+        );
+        for (name, length, present, holds) in rows {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                name,
+                length,
+                since(*present),
+                holds
+            );
+        }
+    }
+
+    /// The first format with the field.
+    fn since(present: fn(Version) -> bool) -> Version {
+        (0..=Version::current_version().to_u8())
+            .filter_map(Version::from_u8)
+            .find(|&v| present(v))
+            .expect("fields are in the current format")
+    }
+
+    #[test]
+    fn test_header() {
+        assert_eq!(header_size(Version::V0_0), 2 + DIGEST_LENGTH);
+        assert_eq!(
+            header_size(Version::V0_2),
+            2 + DIGEST_LENGTH + 2 * ID_LENGTH
+        );
+
+        let mut bytes = vec![Version::V0_6.to_u8(), identifiers::AESENCRYPTOR_ID];
+        bytes.extend([0; DIGEST_LENGTH + 2 * ID_LENGTH]);
+        bytes.extend(7u64.to_be_bytes());
+        let fields = header(&bytes).unwrap();
+        let names: Vec<&str> = fields.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "version",
+                "encryptor",
+                "digest",
+                "vault_id",
+                "device_id",
+                "generation"
+            ]
+        );
+        assert_eq!(fields[1].value, "1 (aes-256-gcm)");
+        assert_eq!(fields[5].offset, 2 + DIGEST_LENGTH + 2 * ID_LENGTH);
+        assert_eq!(fields[5].value, "7");
+        assert_eq!(header(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(header(&[0xff]), None);
+    }
+
+    #[test]
+    fn test_segments() {
+        let fields = segments(
+            10,
+            10 + 100 + SLACK_SIZE,
+            identifiers::AESENCRYPTOR_ID,
+            Some(true),
+        );
+        let names: Vec<&str> = fields.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["nonce", "ciphertext", "tag", "slack"]);
+        assert_eq!(fields[1].offset, 10 + NONCE_LENGTH);
+        assert_eq!(fields[3].offset, 110);
+        assert_eq!(segments(10, 20, 0, Some(false))[0].length, 10);
+        assert_eq!(segments(10, 20 + SLACK_SIZE, 0, None)[1].name, "slack?");
+    }
+
+    #[test]
+    fn test_reference_is_current() {
+        let changes: Vec<u8> = CHANGES.iter().map(|(v, _)| v.to_u8()).collect();
+        let expected: Vec<u8> = (0..=Version::current_version().to_u8()).collect();
+        assert_eq!(changes, expected, "every format needs its changes listed");
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(REFERENCE);
+        let written = std::fs::read_to_string(path).unwrap_or_default();
+        assert!(
+            written == reference(),
+            "{} is out of date, run `cargo test -- --ignored write_format_docs`",
+            REFERENCE
+        );
+    }
+
+    /// Writes the format reference, run with `cargo test -- --ignored`
+    /// after changing the format.
+    #[test]
+    #[ignore]
+    fn write_format_docs() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(REFERENCE);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, reference()).unwrap();
+    }
+}
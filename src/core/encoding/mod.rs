@@ -1 +1,3 @@
+pub mod capability;
+pub mod layout;
 pub mod version;
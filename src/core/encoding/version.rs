@@ -2,10 +2,24 @@ use std::fmt::Display;
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive, IntoPrimitive, Clone, Copy)]
+/// The format of a vault, stored as its first byte. Every format, past and
+/// future, starts with it whatever follows, so that older builds tell a
+/// vault from a newer mopm apart from a corrupt file.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, TryFromPrimitive, IntoPrimitive, Clone, Copy)]
 #[repr(u8)]
 pub enum Version {
     V0_0,
+    V0_1,
+    V0_2,
+    V0_3,
+    V0_4,
+    V0_5,
+    V0_6,
+    V0_7,
+    V0_8,
+    V0_9,
+    V0_10,
+    V0_11,
 }
 
 impl Version {
@@ -18,7 +32,76 @@ impl Version {
     }
 
     pub fn current_version() -> Self {
-        Self::V0_0
+        Self::V0_11
+    }
+
+    /// Why vaults in this format should be saved again, which upgrades them.
+    pub fn deprecation(self) -> Option<&'static str> {
+        if !self.binds_header() {
+            Some("the header is not authenticated")
+        } else if !self.has_identity() {
+            Some("the vault has no identity to sync or merge with")
+        } else if !self.has_namespace_keys() {
+            Some("namespaced entries share the vault key")
+        } else {
+            None
+        }
+    }
+
+    /// Whether the header is bound to the body as AES-GCM associated data.
+    pub fn binds_header(self) -> bool {
+        self >= Self::V0_1
+    }
+
+    /// Whether the header records the vault UUID and the writing device.
+    pub fn has_identity(self) -> bool {
+        self >= Self::V0_2
+    }
+
+    /// Whether the body records entry timestamps and deletion tombstones.
+    pub fn has_tombstones(self) -> bool {
+        self >= Self::V0_3
+    }
+
+    /// Whether the body carries the fingerprint key and entry fingerprints.
+    pub fn has_fingerprints(self) -> bool {
+        self >= Self::V0_4
+    }
+
+    /// Whether every entry carries a metadata map.
+    pub fn has_metadata(self) -> bool {
+        self >= Self::V0_5
+    }
+
+    /// Whether the header carries the generation counter of the vault.
+    pub fn has_generation(self) -> bool {
+        self >= Self::V0_6
+    }
+
+    /// Whether the header carries the key derivation function and its salt.
+    pub fn has_kdf(self) -> bool {
+        self >= Self::V0_7
+    }
+
+    /// Whether namespaced values are encrypted with their namespace key.
+    pub fn has_namespace_keys(self) -> bool {
+        self >= Self::V0_8
+    }
+
+    /// Whether the header records the hasher of the body digest.
+    pub fn has_hasher_id(self) -> bool {
+        self >= Self::V0_9
+    }
+
+    /// Whether the header digest is the root of a Merkle tree over the
+    /// entries rather than the digest of the whole body.
+    pub fn has_merkle_root(self) -> bool {
+        self >= Self::V0_10
+    }
+
+    /// Whether the header carries the capability bits of the vault.
+    pub fn has_capabilities(self) -> bool {
+        self >= Self::V0_11
     }
 }
 
@@ -26,6 +109,17 @@ impl Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
             Version::V0_0 => write!(f, "v0.0"),
+            Version::V0_1 => write!(f, "v0.1"),
+            Version::V0_2 => write!(f, "v0.2"),
+            Version::V0_3 => write!(f, "v0.3"),
+            Version::V0_4 => write!(f, "v0.4"),
+            Version::V0_5 => write!(f, "v0.5"),
+            Version::V0_6 => write!(f, "v0.6"),
+            Version::V0_7 => write!(f, "v0.7"),
+            Version::V0_8 => write!(f, "v0.8"),
+            Version::V0_9 => write!(f, "v0.9"),
+            Version::V0_10 => write!(f, "v0.10"),
+            Version::V0_11 => write!(f, "v0.11"),
         }
     }
 }
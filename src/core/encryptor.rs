@@ -1,36 +1,43 @@
 use std::iter;
 
 use aes_gcm::{
-    aead::{Aead, OsRng},
-    AeadCore, KeyInit, KeySizeUser,
+    aead::{Aead, Payload},
+    KeyInit, KeySizeUser,
 };
 use thiserror::Error;
 
+use super::nonce::{NonceError, NonceGenerator, NONCE_LENGTH};
+
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum EncryprtorError {
     #[error("cannot encrypt the data")]
     EncryptionError(String),
     #[error("cannot decrypt the data")]
     DecryptionError(String),
+    #[error("cannot generate a nonce: `{0}`")]
+    NonceUnavailable(#[from] NonceError),
 }
 
+/// `aad` is associated data that is authenticated but not encrypted; the
+/// same bytes must be supplied to `decrypt` for it to succeed.
 pub trait Encryprtor {
-    fn encrypt(&mut self, data: &[u8]) -> Result<Box<[u8]>, EncryprtorError>;
+    fn encrypt(&mut self, data: &[u8], aad: &[u8]) -> Result<Box<[u8]>, EncryprtorError>;
 
-    fn decrypt(&mut self, data: &[u8]) -> Result<Box<[u8]>, EncryprtorError>;
+    fn decrypt(&mut self, data: &[u8], aad: &[u8]) -> Result<Box<[u8]>, EncryprtorError>;
 }
 
-pub struct DynamicEncryptor(pub u8, pub Box<dyn Encryprtor>);
+pub struct DynamicEncryptor(pub u8, pub Box<dyn Encryprtor + Send>);
 impl Encryprtor for DynamicEncryptor {
-    fn encrypt(&mut self, data: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
-        self.1.encrypt(data)
+    fn encrypt(&mut self, data: &[u8], aad: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
+        self.1.encrypt(data, aad)
     }
 
-    fn decrypt(&mut self, data: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
-        self.1.decrypt(data)
+    fn decrypt(&mut self, data: &[u8], aad: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
+        self.1.decrypt(data, aad)
     }
 }
 
+#[derive(Default)]
 pub struct BlankEncryptor;
 
 impl BlankEncryptor {
@@ -40,17 +47,18 @@ impl BlankEncryptor {
 }
 
 impl Encryprtor for BlankEncryptor {
-    fn encrypt(&mut self, data: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
+    fn encrypt(&mut self, data: &[u8], _aad: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
         Ok(data.into())
     }
 
-    fn decrypt(&mut self, data: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
+    fn decrypt(&mut self, data: &[u8], _aad: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
         Ok(data.into())
     }
 }
 
 pub struct AESEncryptor {
     cipher: aes_gcm::Aes256Gcm,
+    nonces: NonceGenerator,
 }
 
 impl AESEncryptor {
@@ -69,23 +77,26 @@ impl AESEncryptor {
             key_bytes = byte_key
                 .iter()
                 .copied()
-                .chain(iter::repeat(0).take(key_size - byte_key.len()))
+                .chain(iter::repeat_n(0, key_size - byte_key.len()))
                 .collect();
             byte_key = &key_bytes[..key_size]
         };
 
         let cipher = aes_gcm::Aes256Gcm::new_from_slice(byte_key)
             .expect("byte_key is not resized correctly. this should not happen");
-        Self { cipher }
+        Self {
+            cipher,
+            nonces: NonceGenerator::new(),
+        }
     }
 }
 
 impl Encryprtor for AESEncryptor {
-    fn encrypt(&mut self, data: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
-        let nonce = aes_gcm::Aes256Gcm::generate_nonce(OsRng);
+    fn encrypt(&mut self, data: &[u8], aad: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
+        let nonce = self.nonces.generate()?;
         let encrypted_bytes = self
             .cipher
-            .encrypt(&nonce, data.as_ref())
+            .encrypt(&nonce.into(), Payload { msg: data, aad })
             .map_err(|err| EncryprtorError::EncryptionError(err.to_string()))?;
 
         let ciphertext = nonce.into_iter().chain(encrypted_bytes);
@@ -93,9 +104,7 @@ impl Encryprtor for AESEncryptor {
         Ok(ciphertext.collect::<Vec<_>>().into_boxed_slice())
     }
 
-    fn decrypt(&mut self, data: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
-        const NONCE_LENGTH: usize = 12;
-
+    fn decrypt(&mut self, data: &[u8], aad: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
         if data.len() < NONCE_LENGTH {
             return Err(EncryprtorError::DecryptionError(
                 "invalid nonce size".to_string(),
@@ -103,9 +112,17 @@ impl Encryprtor for AESEncryptor {
         };
 
         let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+        self.nonces
+            .observe(nonce.try_into().expect("nonce length is checked above"));
         Ok(self
             .cipher
-            .decrypt(nonce.into(), ciphertext)
+            .decrypt(
+                nonce.into(),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
             .map_err(|err| EncryprtorError::DecryptionError(err.to_string()))?
             .into_boxed_slice())
     }
@@ -128,24 +145,37 @@ mod tests {
         let mut encryptor = AESEncryptor::new("foobar");
 
         let data = "foo";
-        let encrypted = encryptor.encrypt(data.as_ref()).unwrap();
+        let encrypted = encryptor.encrypt(data.as_ref(), &[]).unwrap();
         assert_eq!(
             Ok(data.to_owned()),
-            String::from_utf8(encryptor.decrypt(encrypted.as_ref()).unwrap().into())
+            String::from_utf8(encryptor.decrypt(encrypted.as_ref(), &[]).unwrap().into())
         );
 
         let data = "";
-        let encrypted = encryptor.encrypt(data.as_ref()).unwrap();
+        let encrypted = encryptor.encrypt(data.as_ref(), &[]).unwrap();
         assert_eq!(
             Ok(data.to_owned()),
-            String::from_utf8(encryptor.decrypt(encrypted.as_ref()).unwrap().into())
+            String::from_utf8(encryptor.decrypt(encrypted.as_ref(), &[]).unwrap().into())
         );
 
         let data = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
-        let encrypted = encryptor.encrypt(data.as_ref()).unwrap();
+        let encrypted = encryptor.encrypt(data.as_ref(), &[]).unwrap();
         assert_eq!(
             Ok(data.to_owned()),
-            String::from_utf8(encryptor.decrypt(encrypted.as_ref()).unwrap().into())
+            String::from_utf8(encryptor.decrypt(encrypted.as_ref(), &[]).unwrap().into())
+        );
+    }
+
+    #[test]
+    fn test_aad() {
+        let mut encryptor = AESEncryptor::new("foobar");
+
+        let encrypted = encryptor.encrypt(b"foo", b"header").unwrap();
+        assert_eq!(
+            encryptor.decrypt(&encrypted, b"header").unwrap().as_ref(),
+            b"foo"
         );
+        assert!(encryptor.decrypt(&encrypted, b"other").is_err());
+        assert!(encryptor.decrypt(&encrypted, &[]).is_err());
     }
 }
@@ -0,0 +1,174 @@
+use std::{
+    collections::{hash_map, BTreeMap, HashMap, HashSet},
+    ops::Deref,
+};
+
+use super::{
+    dynamic,
+    fingerprint::Fingerprint,
+    hasher::{Hasher, Sha256Hasher},
+    history,
+};
+
+pub type KeyHash = [u8; 32];
+
+/// Upper bounds on the sizes stored in a vault body. They are enforced when
+/// entries are stored and again when a body is parsed, so a malformed file
+/// cannot make the parser allocate without bound.
+pub const MAX_KEY_LENGTH: usize = 4 * 1024;
+pub const MAX_VALUE_LENGTH: usize = 16 * 1024 * 1024;
+pub const MAX_META_LENGTH: usize = 1024 * 1024;
+
+/// Metadata describing the login an entry belongs to. Unlike the rest of
+/// the metadata it is kept when the password is overwritten.
+pub const LOGIN_FIELDS: [&str; 5] = ["username", "url", "match", "notes", TAGS_FIELD];
+
+/// Comma-separated labels, the only metadata published in catalogs.
+pub const TAGS_FIELD: &str = "tags";
+
+/// A stored password: the encrypted value, when it was last modified, a
+/// keyed fingerprint of the plaintext, if one has been computed yet, and
+/// free-form metadata (only encrypted as part of the body).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub(in crate::core) value: Box<[u8]>,
+    pub(in crate::core) modified: u64,
+    pub(in crate::core) fingerprint: Option<Fingerprint>,
+    pub(in crate::core) meta: BTreeMap<String, String>,
+}
+
+impl Entry {
+    pub fn new(value: Box<[u8]>, modified: u64) -> Self {
+        Self {
+            value,
+            modified,
+            fingerprint: None,
+            meta: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_meta(mut self, meta: BTreeMap<String, String>) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    pub fn modified(&self) -> u64 {
+        self.modified
+    }
+
+    pub fn meta(&self, name: &str) -> Option<&str> {
+        self.meta.get(name).map(String::as_str)
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: Option<Fingerprint>) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+}
+
+/// The entries of a vault by key. Every write goes through it, so it knows
+/// which keys were written since the merkle tree last took them and the
+/// tree does not have to compare or keep copies of the entries.
+#[derive(Debug, Clone, Default)]
+pub struct Entries {
+    map: HashMap<String, Entry>,
+    changed: HashSet<String>,
+}
+
+impl Entries {
+    pub fn insert(&mut self, key: String, entry: Entry) -> Option<Entry> {
+        self.changed.insert(key.clone());
+        self.map.insert(key, entry)
+    }
+
+    /// Removed keys are not recorded, the tree drops the keys it no longer
+    /// finds.
+    pub fn remove(&mut self, key: &str) -> Option<Entry> {
+        self.map.remove(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        let entry = self.map.get_mut(key)?;
+        self.changed.insert(key.to_string());
+        Some(entry)
+    }
+
+    pub fn values_mut(&mut self) -> hash_map::ValuesMut<'_, String, Entry> {
+        self.changed.extend(self.map.keys().cloned());
+        self.map.values_mut()
+    }
+
+    pub fn iter_mut(&mut self) -> hash_map::IterMut<'_, String, Entry> {
+        self.changed.extend(self.map.keys().cloned());
+        self.map.iter_mut()
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&String, &Entry) -> bool) {
+        self.map.retain(|key, entry| keep(key, entry));
+    }
+
+    /// The keys written since the last call.
+    pub(in crate::core) fn take_changed(&mut self) -> HashSet<String> {
+        std::mem::take(&mut self.changed)
+    }
+}
+
+impl Deref for Entries {
+    type Target = HashMap<String, Entry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+/// Equal when they hold the same entries, whatever was written since.
+impl PartialEq for Entries {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl Eq for Entries {}
+
+impl From<HashMap<String, Entry>> for Entries {
+    fn from(map: HashMap<String, Entry>) -> Self {
+        Self {
+            map,
+            changed: HashSet::new(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Entries {
+    type Item = (&'a String, &'a Entry);
+    type IntoIter = hash_map::Iter<'a, String, Entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter()
+    }
+}
+
+/// Whether an entry with this name and stored (encrypted) value fits the
+/// body limits.
+pub fn fits(key: &str, value: &[u8]) -> bool {
+    key.len() <= MAX_KEY_LENGTH && value.len() <= MAX_VALUE_LENGTH
+}
+
+pub fn meta_fits(name: &str, value: &str) -> bool {
+    name.len() <= MAX_META_LENGTH && value.len() <= MAX_META_LENGTH
+}
+
+/// Whether the metadata `name` holds values encrypted for this vault, the
+/// cache of a dynamic entry or previous values, rather than something the
+/// user stored. It is not copied into other vaults.
+pub(in crate::core) fn is_sealed_meta(name: &str) -> bool {
+    dynamic::is_cache_meta(name) || name == history::HISTORY
+}
+
+/// Tombstones are keyed by a hash of the entry name so that deleted names
+/// are not kept around in the clear.
+pub fn key_hash(key: &str) -> KeyHash {
+    Sha256Hasher::new().hash(key.as_bytes())[..]
+        .try_into()
+        .expect("sha256 digest is 32 bytes long")
+}
@@ -0,0 +1,186 @@
+use std::{
+    io::{Read, Write},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::exec::sandbox::Sandbox;
+
+const ALLOWED_ENV: [&str; 7] = ["PATH", "HOME", "USER", "LOGNAME", "LANG", "TMPDIR", "TZ"];
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ExecutorError {
+    #[error("cannot run the command: `{0}`")]
+    SpawnError(String),
+    #[error("the command did not finish in time")]
+    Timeout,
+    #[error("the command failed: `{0}`")]
+    CommandFailed(String),
+    #[error("the command output is not valid utf-8")]
+    InvalidOutput,
+}
+
+/// Runs user supplied commands that produce secrets. Commands run in the
+/// sandbox with a minimal environment and without network unless allowed,
+/// get no stdin unless given one and a hard timeout after which they are
+/// killed.
+#[derive(Clone)]
+pub struct Executor {
+    timeout: Duration,
+    env: Vec<(String, String)>,
+    stdin: Option<String>,
+    network: bool,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
+
+impl Executor {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            env: Vec::new(),
+            stdin: None,
+            network: false,
+        }
+    }
+
+    /// Passes `name` to the commands in addition to the allowed variables.
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.env.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Writes `input` to the stdin of the commands, where secrets can be
+    /// passed without showing up in the environment of the process.
+    pub fn stdin(mut self, input: &str) -> Self {
+        self.stdin = Some(input.to_string());
+        self
+    }
+
+    /// Lets the commands open network sockets.
+    pub fn network(mut self, allowed: bool) -> Self {
+        self.network = allowed;
+        self
+    }
+
+    /// Runs `command` through `sh -c` and returns its trimmed stdout.
+    pub fn run(&self, command: &str) -> Result<String, ExecutorError> {
+        let mut child = Sandbox::new(&ALLOWED_ENV)
+            .network(self.network)
+            .apply(Command::new("sh").arg("-c").arg(command))
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(match self.stdin {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| ExecutorError::SpawnError(err.to_string()))?;
+
+        if let (Some(input), Some(mut stdin)) = (self.stdin.clone(), child.stdin.take()) {
+            // A command that does not read its input is not an error.
+            std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        }
+
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let reader = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            stdout.read_to_end(&mut buf).map(|_| buf)
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(ExecutorError::Timeout);
+                }
+                Ok(None) => std::thread::sleep(POLL_INTERVAL),
+                Err(err) => return Err(ExecutorError::CommandFailed(err.to_string())),
+            }
+        };
+        if !status.success() {
+            return Err(ExecutorError::CommandFailed(status.to_string()));
+        }
+
+        let output = reader
+            .join()
+            .map_err(|_| ExecutorError::InvalidOutput)?
+            .map_err(|err| ExecutorError::CommandFailed(err.to_string()))?;
+        String::from_utf8(output)
+            .map(|v| v.trim_end_matches(['\r', '\n']).to_string())
+            .or(Err(ExecutorError::InvalidOutput))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let executor = Executor::default();
+        assert_eq!(executor.run("echo token"), Ok("token".to_string()));
+        assert!(matches!(
+            executor.run("exit 3"),
+            Err(ExecutorError::CommandFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_stdin() {
+        let executor = Executor::default().stdin("old\nnew\n");
+        assert_eq!(
+            executor.run("read a; read b; echo $b"),
+            Ok("new".to_string())
+        );
+        assert_eq!(executor.run("true"), Ok(String::new()));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let executor = Executor::new(Duration::from_millis(50));
+        assert_eq!(executor.run("sleep 5"), Err(ExecutorError::Timeout));
+    }
+
+    #[test]
+    fn test_minimal_env() {
+        std::env::set_var("MOPM_EXECUTOR_TEST_SECRET", "leak");
+        let executor = Executor::default();
+        assert_eq!(
+            executor.run("echo \"$MOPM_EXECUTOR_TEST_SECRET\""),
+            Ok("".to_string())
+        );
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn test_network() {
+        let connect = "bash -c 'exec 3<>/dev/tcp/127.0.0.1/9' 2>&1 | grep -q 'Permission denied'";
+        assert_eq!(Executor::default().run(connect), Ok(String::new()));
+        assert!(matches!(
+            Executor::default().network(true).run(connect),
+            Err(ExecutorError::CommandFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_env() {
+        let executor = Executor::default().env("MOPM_KEY", "work/db");
+        assert_eq!(
+            executor.run("echo \"$MOPM_KEY\""),
+            Ok("work/db".to_string())
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::rng;
+
+pub const FINGERPRINT_LENGTH: usize = 32;
+
+pub type Fingerprint = [u8; FINGERPRINT_LENGTH];
+pub type FingerprintKey = [u8; FINGERPRINT_LENGTH];
+
+/// Generates the vault-local secret that fingerprints are keyed with. It
+/// lives in the encrypted body, so fingerprints are useless without it.
+pub fn new_key() -> FingerprintKey {
+    let mut key = [0; FINGERPRINT_LENGTH];
+    rng::fill(&mut key);
+    key
+}
+
+/// HMAC-SHA256 of a plaintext password, used to compare passwords without
+/// decrypting them.
+pub fn fingerprint(key: &FingerprintKey, value: &[u8]) -> Fingerprint {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(value);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let key = new_key();
+        assert_eq!(fingerprint(&key, b"foo"), fingerprint(&key, b"foo"));
+        assert_ne!(fingerprint(&key, b"foo"), fingerprint(&key, b"bar"));
+        assert_ne!(fingerprint(&key, b"foo"), fingerprint(&new_key(), b"foo"));
+    }
+}
@@ -0,0 +1,253 @@
+//! Generated secrets: random passwords for accounts, and passphrases made
+//! of pronounceable words for passwords that have to be read out or typed
+//! from a note. Every character or syllable is drawn uniformly, so the
+//! strength of what is generated is known exactly.
+//!
+//! Passwords follow a profile: a length and the classes of characters to
+//! draw from. `default`, `pin`, `wifi` and `bank` are built in, and
+//! profiles are added or replaced in the config file:
+//!
+//! ```text
+//! generate.wifi = 63 alnum
+//! generate.bank = 16 letters,digits,unambiguous
+//! ```
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use super::rng;
+
+const CONSONANTS: &[u8] = b"bdfghjklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+const SYLLABLES_PER_WORD: usize = 3;
+const SEPARATOR: char = '-';
+
+const PREFIX: &str = "generate.";
+pub const DEFAULT_PROFILE: &str = "default";
+const BUILTIN_PROFILES: [(&str, &str); 4] = [
+    (DEFAULT_PROFILE, "24 alnum,symbols"),
+    ("pin", "6 digits"),
+    ("wifi", "63 alnum"),
+    ("bank", "16 letters,digits,unambiguous"),
+];
+const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+/// Printable ASCII symbols without quotes, which sites accept least.
+const SYMBOLS: &[u8] = b"!#$%&()*+,-./:;<=>?@[]^_{|}~";
+/// Characters mistaken for one another when read or typed from a note.
+const AMBIGUOUS: &[u8] = b"0O1lI|";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum GeneratorError {
+    #[error("invalid value for `generate.{0}`: `{1}`, expected `<length> <class,..>`")]
+    InvalidProfile(String, String),
+    #[error("unknown character class `{0}`, expected lower, upper, letters, digits, alnum, symbols or unambiguous")]
+    UnknownClass(String),
+    #[error("unknown generator profile `{0}`")]
+    UnknownProfile(String),
+}
+
+/// How a password is generated: its length and the characters it is drawn
+/// from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    length: usize,
+    charset: Vec<u8>,
+}
+
+impl Profile {
+    /// Parses `<length> <class,..>`, e.g. `16 letters,digits,unambiguous`.
+    /// `unambiguous` drops the characters of the other classes that are
+    /// easily confused.
+    pub fn parse(value: &str) -> Result<Self, GeneratorError> {
+        let invalid = || GeneratorError::InvalidProfile(String::new(), value.to_string());
+        let (length, classes) = value.split_once(' ').ok_or_else(invalid)?;
+        let length = length.parse().ok().filter(|v| *v > 0).ok_or_else(invalid)?;
+        let mut charset = Vec::new();
+        let mut unambiguous = false;
+        for class in classes.split(',').map(str::trim) {
+            match class {
+                "lower" => charset.extend(LOWER),
+                "upper" => charset.extend(UPPER),
+                "letters" => charset.extend([LOWER, UPPER].concat()),
+                "digits" => charset.extend(DIGITS),
+                "alnum" => charset.extend([LOWER, UPPER, DIGITS].concat()),
+                "symbols" => charset.extend(SYMBOLS),
+                "unambiguous" => unambiguous = true,
+                _ => return Err(GeneratorError::UnknownClass(class.to_string())),
+            }
+        }
+        charset.sort_unstable();
+        charset.dedup();
+        if unambiguous {
+            charset.retain(|c| !AMBIGUOUS.contains(c));
+        }
+        if charset.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self { length, charset })
+    }
+
+    /// The same profile with another length.
+    pub fn with_length(self, length: usize) -> Self {
+        Self { length, ..self }
+    }
+
+    /// A password of the profile's length, each character drawn from its
+    /// classes.
+    pub fn generate(&self) -> String {
+        (0..self.length).map(|_| pick(&self.charset)).collect()
+    }
+}
+
+/// The built-in profiles, and those of the config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profiles(BTreeMap<String, Profile>);
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self(
+            BUILTIN_PROFILES
+                .into_iter()
+                .map(|(name, value)| {
+                    let profile = Profile::parse(value).expect("built-in profiles are valid");
+                    (name.to_string(), profile)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Profiles {
+    /// The built-in profiles with the `generate.` settings of the config
+    /// file over them.
+    pub fn from_settings(settings: &[(&str, &str)]) -> Result<Self, GeneratorError> {
+        let mut profiles = Self::default();
+        for (name, value) in settings
+            .iter()
+            .filter_map(|(name, value)| Some((name.strip_prefix(PREFIX)?, *value)))
+        {
+            let profile = Profile::parse(value).map_err(|err| match err {
+                GeneratorError::InvalidProfile(_, value) => {
+                    GeneratorError::InvalidProfile(name.to_string(), value)
+                }
+                err => err,
+            })?;
+            profiles.0.insert(name.to_string(), profile);
+        }
+        Ok(profiles)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile, GeneratorError> {
+        self.0
+            .get(name)
+            .ok_or_else(|| GeneratorError::UnknownProfile(name.to_string()))
+    }
+}
+
+pub fn is_profile_setting(name: &str) -> bool {
+    name.starts_with(PREFIX)
+}
+
+/// The bits of a passphrase of `words` words.
+fn bits(words: usize) -> f64 {
+    let syllables = (CONSONANTS.len() * VOWELS.len()) as f64;
+    (words * SYLLABLES_PER_WORD) as f64 * syllables.log2()
+}
+
+/// A passphrase of at least `min_bits` bits, such as
+/// `dizome-habuki-tosale-kivapu`.
+pub fn passphrase(min_bits: f64) -> String {
+    let words = (1..)
+        .find(|&words| bits(words) >= min_bits)
+        .expect("every word adds bits");
+    (0..words)
+        .map(|_| {
+            (0..SYLLABLES_PER_WORD)
+                .flat_map(|_| [pick(CONSONANTS), pick(VOWELS)])
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(&SEPARATOR.to_string())
+}
+
+/// A uniformly drawn character of `set`, rejecting the draws that would
+/// favor its first characters.
+fn pick(set: &[u8]) -> char {
+    let limit = u32::MAX - u32::MAX % set.len() as u32;
+    loop {
+        let value = rng::next_u32();
+        if value < limit {
+            return set[value as usize % set.len()] as char;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passphrase() {
+        let passphrase = passphrase(60.0);
+        let words: Vec<&str> = passphrase.split(SEPARATOR).collect();
+        assert_eq!(words.len(), 4);
+        for word in words {
+            assert_eq!(word.len(), 2 * SYLLABLES_PER_WORD);
+            for (i, c) in word.bytes().enumerate() {
+                let set = if i % 2 == 0 { CONSONANTS } else { VOWELS };
+                assert!(set.contains(&c), "{}", word);
+            }
+        }
+        assert!(bits(3) < 60.0);
+    }
+
+    #[test]
+    fn test_profile() {
+        let profile = Profile::parse("32 alnum,symbols").unwrap();
+        let password = profile.generate();
+        assert_eq!(password.len(), 32);
+        assert!(password.bytes().all(|c| c.is_ascii_graphic() && c != b'"'));
+        assert_ne!(password, profile.generate());
+        assert_eq!(profile.with_length(8).generate().len(), 8);
+
+        let pin = Profile::parse("6 digits").unwrap().generate();
+        assert!(pin.len() == 6 && pin.bytes().all(|c| c.is_ascii_digit()));
+        let unambiguous = Profile::parse("200 letters,digits,unambiguous").unwrap();
+        assert!(!unambiguous
+            .generate()
+            .bytes()
+            .any(|c| AMBIGUOUS.contains(&c)));
+
+        assert_eq!(
+            Profile::parse("6 dice"),
+            Err(GeneratorError::UnknownClass("dice".to_string()))
+        );
+        for invalid in ["0 digits", "six digits", "6", "6 unambiguous"] {
+            assert!(Profile::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_profiles() {
+        let profiles =
+            Profiles::from_settings(&[("generate.pin", "4 digits"), ("generate.hex", "32 digits")])
+                .unwrap();
+        assert_eq!(profiles.get("pin").unwrap().length, 4);
+        assert_eq!(profiles.get("wifi").unwrap().length, 63);
+        assert!(profiles.get("hex").is_ok());
+        assert_eq!(
+            profiles.get("dice"),
+            Err(GeneratorError::UnknownProfile("dice".to_string()))
+        );
+        assert_eq!(
+            Profiles::from_settings(&[("generate.pin", "four digits")]),
+            Err(GeneratorError::InvalidProfile(
+                "pin".to_string(),
+                "four digits".to_string()
+            ))
+        );
+    }
+}
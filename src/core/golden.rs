@@ -0,0 +1,133 @@
+//! Vault files in every released format, bundled into the binary so that
+//! the decoder can be checked against all of them: by the tests, and by
+//! `mopm selftest` for packagers verifying a build. Each holds the same two
+//! entries under the same password.
+
+use thiserror::Error;
+
+use super::{
+    encoder::{Encoder, EncoderError},
+    encoding::version::Version,
+    keycache::KeyCache,
+    manager::PasswordManagerError,
+};
+
+const PASSWORD: &str = "golden";
+const ENTRIES: [(&str, &str); 2] = [("golden", "fixture"), ("work/golden", "namespaced")];
+
+macro_rules! golden_file {
+    ($name:literal) => {
+        include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/golden/",
+            $name,
+            ".data"
+        ))
+    };
+}
+
+/// One file per format, oldest first. A new format gets its file with the
+/// release that introduces it, written by `write_golden_files`.
+const FILES: [(Version, &[u8]); 12] = [
+    (Version::V0_0, golden_file!("v0_0")),
+    (Version::V0_1, golden_file!("v0_1")),
+    (Version::V0_2, golden_file!("v0_2")),
+    (Version::V0_3, golden_file!("v0_3")),
+    (Version::V0_4, golden_file!("v0_4")),
+    (Version::V0_5, golden_file!("v0_5")),
+    (Version::V0_6, golden_file!("v0_6")),
+    (Version::V0_7, golden_file!("v0_7")),
+    (Version::V0_8, golden_file!("v0_8")),
+    (Version::V0_9, golden_file!("v0_9")),
+    (Version::V0_10, golden_file!("v0_10")),
+    (Version::V0_11, golden_file!("v0_11")),
+];
+
+#[derive(Error, Debug)]
+pub enum GoldenError {
+    #[error("cannot decode the vault: {0}")]
+    DecodeError(#[from] EncoderError),
+    #[error("decoded as {0}")]
+    WrongVersion(Version),
+    #[error("cannot read `{0}`: {1}")]
+    EntryError(&'static str, PasswordManagerError),
+    #[error("`{0}` has the wrong value")]
+    WrongValue(&'static str),
+}
+
+/// Decodes every golden file and reads its entries back.
+pub fn check_all() -> Vec<(Version, Result<(), GoldenError>)> {
+    FILES
+        .iter()
+        .map(|&(version, bytes)| (version, check(version, bytes)))
+        .collect()
+}
+
+fn check(version: Version, mut bytes: &[u8]) -> Result<(), GoldenError> {
+    let mut pm = Encoder::decode(PASSWORD.as_ref(), &mut bytes, &mut KeyCache::default())?;
+    if pm.version() != version {
+        return Err(GoldenError::WrongVersion(pm.version()));
+    }
+    for (key, value) in ENTRIES {
+        match pm.get_password(key) {
+            Ok(v) if v == value => {}
+            Ok(_) => return Err(GoldenError::WrongValue(key)),
+            Err(err) => return Err(GoldenError::EntryError(key, err)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        kdf::{Kdf, KdfParams},
+        manager::PasswordManager,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_golden_files() {
+        for (version, result) in check_all() {
+            assert!(result.is_ok(), "{}: {}", version, result.unwrap_err());
+        }
+        let versions: Vec<u8> = FILES.iter().map(|(v, _)| v.to_u8()).collect();
+        let expected: Vec<u8> = (0..=Version::current_version().to_u8()).collect();
+        assert_eq!(versions, expected, "every format needs a golden file");
+    }
+
+    /// Fills the empty golden files, run with `cargo test -- --ignored`
+    /// after adding an empty file and its line in `FILES` for a new format.
+    /// Files of released formats are never rewritten.
+    #[test]
+    #[ignore]
+    fn write_golden_files() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/golden");
+        for (version, bytes) in FILES {
+            if !bytes.is_empty() {
+                continue;
+            }
+            let kdf = match version.has_kdf() {
+                true => Kdf::argon2id(KdfParams {
+                    memory_kib: 64,
+                    iterations: 1,
+                    parallelism: 1,
+                })
+                .unwrap(),
+                false => Kdf::Raw,
+            };
+            let mut pm = PasswordManager::init(PASSWORD, kdf).unwrap();
+            for (key, value) in ENTRIES {
+                pm.store_password(key.to_string(), value).unwrap();
+            }
+            let mut bytes = Vec::new();
+            Encoder::encode_version(&mut bytes, &mut pm, version).unwrap();
+            let name = version
+                .to_string()
+                .trim_start_matches('v')
+                .replace('.', "_");
+            std::fs::write(dir.join(format!("v{}.data", name)), bytes).unwrap();
+        }
+    }
+}
@@ -1,8 +1,13 @@
 use sha2::Digest;
 
+/// Every hasher yields `DIGEST_LENGTH` bytes, the size of the body digest in
+/// the header.
+pub const DIGEST_LENGTH: usize = 32;
+
 pub trait Hasher {
     fn hash(&mut self, data: &[u8]) -> Box<[u8]>;
 }
+#[derive(Default)]
 pub struct Sha256Hasher {}
 
 impl Sha256Hasher {
@@ -20,3 +25,65 @@ impl Hasher for Sha256Hasher {
         Box::from(v)
     }
 }
+
+/// SHA-512 truncated to 256 bits, faster than SHA-256 on 64-bit machines
+/// without SHA extensions.
+#[derive(Default)]
+pub struct Sha512_256Hasher {}
+
+impl Sha512_256Hasher {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Hasher for Sha512_256Hasher {
+    fn hash(&mut self, data: &[u8]) -> Box<[u8]> {
+        let v: [u8; 32] = sha2::Sha512_256::digest(data).into();
+        Box::from(v)
+    }
+}
+
+#[derive(Default)]
+pub struct Blake2bHasher {}
+
+impl Blake2bHasher {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Hasher for Blake2bHasher {
+    fn hash(&mut self, data: &[u8]) -> Box<[u8]> {
+        let v: [u8; 32] = blake2::Blake2b::<blake2::digest::consts::U32>::digest(data).into();
+        Box::from(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_digests() {
+        let cases: [(Box<dyn Hasher>, &str); 3] = [
+            (
+                Box::new(Sha256Hasher::new()),
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                Box::new(Sha512_256Hasher::new()),
+                "53048e2681941ef99b2e29b76b4c7dabe4c2d0c634fc6d46e0e2f13107e7af23",
+            ),
+            (
+                Box::new(Blake2bHasher::new()),
+                "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319",
+            ),
+        ];
+        for (mut hasher, digest) in cases {
+            let v = hasher.hash(b"abc");
+            assert_eq!(v.len(), DIGEST_LENGTH);
+            assert_eq!(hex::encode(v), digest);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Hidden vaults, kept in the slack after the body of an outer vault file so
+//! that giving up the outer password under coercion does not reveal them.
+//! Vault files written with the `hidden-volume` feature always carry slack,
+//! filled with random bytes until a hidden vault is created. A hidden vault
+//! looks the same: `salt || cost || nonce || AES-GCM(length || vault ||
+//! padding)`, keyed with Argon2id over the hidden password.
+//!
+//! The cost byte holds the Argon2id parameters of the hidden vault, so that
+//! rekeying the outer vault leaves it alone. Every value of the byte is a
+//! valid point of a small grid, which random slack picks at random: the
+//! byte tells no more than which costs the user chose. The parameters are
+//! rounded down to the grid when the hidden vault is created.
+//!
+//! The encoded hidden vault itself uses the wrapping key as its raw key.
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit,
+};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::{
+    bug::OrBug,
+    encoder::{Encoder, EncoderError, Header, SLACK_SIZE},
+    encryptor::{AESEncryptor, DynamicEncryptor, Encryprtor},
+    identifiers::Identifiable,
+    kdf::{Kdf, KdfParams, SALT_LENGTH},
+    keycache::KeyCache,
+    manager::PasswordManager,
+    nonce::NONCE_LENGTH,
+    rng,
+};
+
+const TAG_LENGTH: usize = 16;
+const LENGTH_SIZE: usize = size_of::<u32>();
+const COST_LENGTH: usize = 1;
+const HEADER_SIZE: usize = SALT_LENGTH + COST_LENGTH;
+const PLAINTEXT_SIZE: usize = SLACK_SIZE - HEADER_SIZE - NONCE_LENGTH - TAG_LENGTH;
+
+/// The grid of the cost byte: memory in its low 3 bits, from 8 MiB to
+/// 1 GiB, iterations in the next 3, from 1 to 8, and parallelism in the top
+/// 2, from 1 to 8.
+#[cfg(not(test))]
+const GRID_MEMORY_KIB: u32 = 8 * 1024;
+/// Small enough for the tests to derive keys quickly.
+#[cfg(test)]
+const GRID_MEMORY_KIB: u32 = 64;
+const GRID_STEPS: u32 = 8;
+const GRID_PARALLELISM_STEPS: u32 = 4;
+
+#[derive(Error, Debug)]
+pub enum HiddenError {
+    #[error("the hidden vault does not fit in the {SLACK_SIZE} bytes of slack")]
+    TooLarge,
+    #[error(
+        "the outer vault has no key derivation parameters to reuse, run `bench --apply` first"
+    )]
+    NoKdfParams,
+    #[error("{0}")]
+    EncoderError(#[from] EncoderError),
+}
+
+/// The key a hidden vault is wrapped with, and the salt and cost byte it
+/// was derived with.
+pub struct HiddenKey {
+    salt: [u8; SALT_LENGTH],
+    cost: u8,
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl HiddenKey {
+    /// A key for a new hidden vault, derived with a fresh salt and `params`
+    /// rounded down to the grid.
+    pub fn new(password: &[u8], params: KdfParams) -> Result<Self, HiddenError> {
+        let mut salt = [0; SALT_LENGTH];
+        rng::fill(&mut salt);
+        Self::derive(password, salt, encode_cost(params))
+    }
+
+    fn derive(password: &[u8], salt: [u8; SALT_LENGTH], cost: u8) -> Result<Self, HiddenError> {
+        let kdf = Kdf::Argon2id {
+            params: decode_cost(cost),
+            salt,
+        };
+        let key = Zeroizing::new(kdf.derive(password).map_err(EncoderError::from)?);
+        Ok(Self { salt, cost, key })
+    }
+
+    fn header(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..SALT_LENGTH].copy_from_slice(&self.salt);
+        header[SALT_LENGTH] = self.cost;
+        header
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new_from_slice(&self.key).or_bug("argon2 derives keys of the right length")
+    }
+}
+
+/// Slack that holds no hidden vault.
+pub fn random_slack() -> Vec<u8> {
+    let mut slack = vec![0; SLACK_SIZE];
+    rng::fill(&mut slack);
+    slack
+}
+
+/// The cost byte of the largest grid point within `params`.
+fn encode_cost(params: KdfParams) -> u8 {
+    let memory = (params.memory_kib / GRID_MEMORY_KIB)
+        .checked_ilog2()
+        .unwrap_or(0)
+        .min(GRID_STEPS - 1);
+    let iterations = params.iterations.clamp(1, GRID_STEPS) - 1;
+    let parallelism = params
+        .parallelism
+        .checked_ilog2()
+        .unwrap_or(0)
+        .min(GRID_PARALLELISM_STEPS - 1);
+    (memory | iterations << 3 | parallelism << 6) as u8
+}
+
+fn decode_cost(cost: u8) -> KdfParams {
+    let cost = u32::from(cost);
+    KdfParams {
+        memory_kib: GRID_MEMORY_KIB << (cost & 7),
+        iterations: (cost >> 3 & 7) + 1,
+        parallelism: 1 << (cost >> 6),
+    }
+}
+
+/// A new, empty hidden vault, as slack.
+pub fn create(key: &HiddenKey) -> Result<Vec<u8>, HiddenError> {
+    let mut pm = PasswordManager::from_raw_parts(Default::default(), AESEncryptor::new(&*key.key))
+        .with_master_key(&key.key);
+    seal(&mut pm, key)
+}
+
+/// Encodes `pm` and wraps it into slack.
+pub fn seal<T>(pm: &mut PasswordManager<T>, key: &HiddenKey) -> Result<Vec<u8>, HiddenError>
+where
+    T: Encryprtor + Identifiable,
+{
+    let mut vault = Vec::new();
+    Encoder::encode(&mut vault, pm)?;
+    if vault.len() > PLAINTEXT_SIZE - LENGTH_SIZE {
+        return Err(HiddenError::TooLarge);
+    }
+    let mut plaintext = Zeroizing::new(vec![0; PLAINTEXT_SIZE]);
+    plaintext[..LENGTH_SIZE].copy_from_slice(&(vault.len() as u32).to_be_bytes());
+    plaintext[LENGTH_SIZE..LENGTH_SIZE + vault.len()].copy_from_slice(&vault);
+
+    let mut nonce = [0; NONCE_LENGTH];
+    rng::fill(&mut nonce);
+    let header = key.header();
+    let payload = Payload {
+        msg: &plaintext[..],
+        aad: &header,
+    };
+    let ciphertext = key
+        .cipher()
+        .encrypt(&nonce.into(), payload)
+        .or_bug("encrypting into a vec cannot fail");
+    Ok([&header[..], &nonce, &ciphertext].concat())
+}
+
+/// Opens the hidden vault in the slack of `file` with `password`. `None`
+/// if there is no slack, or it does not open with the password.
+pub fn open(
+    file: &[u8],
+    password: &[u8],
+) -> Option<(PasswordManager<DynamicEncryptor>, HiddenKey)> {
+    Header::try_from_reader(&mut &file[..]).ok()?;
+    let slack = file.len().checked_sub(SLACK_SIZE).map(|v| &file[v..])?;
+    let salt = slack[..SALT_LENGTH].try_into().ok()?;
+    let key = HiddenKey::derive(password, salt, slack[SALT_LENGTH]).ok()?;
+    let vault = unwrap(slack, &key)?;
+    let pm = Encoder::decode(&key.key, &mut &vault[..], &mut KeyCache::default()).ok()?;
+    Some((pm, key))
+}
+
+/// The generation of the hidden vault in `slack`, `None` if `key` does not
+/// open it.
+pub fn generation(slack: &[u8], key: &HiddenKey) -> Option<u64> {
+    let vault = unwrap(slack, key)?;
+    Some(Header::try_from_reader(&mut &vault[..]).ok()?.generation())
+}
+
+fn unwrap(slack: &[u8], key: &HiddenKey) -> Option<Zeroizing<Vec<u8>>> {
+    let header = key.header();
+    if slack.len() != SLACK_SIZE || slack[..HEADER_SIZE] != header {
+        return None;
+    }
+    let (nonce, ciphertext) = slack[HEADER_SIZE..].split_at(NONCE_LENGTH);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: &header,
+    };
+    let plaintext = Zeroizing::new(key.cipher().decrypt(nonce.into(), payload).ok()?);
+    let length = u32::from_be_bytes(plaintext[..LENGTH_SIZE].try_into().ok()?) as usize;
+    let vault = plaintext.get(LENGTH_SIZE..LENGTH_SIZE.checked_add(length)?)?;
+    Some(Zeroizing::new(vault.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const TEST_PARAMS: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    /// An outer vault file with a hidden vault in its slack.
+    fn file(slack: &[u8]) -> Vec<u8> {
+        let kdf = Kdf::argon2id(TEST_PARAMS).unwrap();
+        let mut outer = PasswordManager::init("outer", kdf).unwrap();
+        outer.store_password("decoy".to_string(), "x").unwrap();
+        let mut file = Vec::new();
+        Encoder::encode(&mut file, &mut outer).unwrap();
+        file.extend(slack);
+        file
+    }
+
+    #[test]
+    fn test_hidden_vault() {
+        let key = HiddenKey::new(b"hidden", TEST_PARAMS).unwrap();
+        let file = file(&create(&key).unwrap());
+
+        let (mut pm, key) = open(&file, b"hidden").unwrap();
+        assert!(pm.keys().is_empty());
+        pm.store_password("real".to_string(), "secret").unwrap();
+        let file = [
+            &file[..file.len() - SLACK_SIZE],
+            &seal(&mut pm, &key).unwrap(),
+        ]
+        .concat();
+
+        let (mut pm, key) = open(&file, b"hidden").unwrap();
+        assert_eq!(pm.get_password("real"), Ok("secret".to_string()));
+        assert_eq!(generation(&file[file.len() - SLACK_SIZE..], &key), Some(2));
+        assert!(open(&file, b"outer").is_none());
+
+        let mut outer =
+            Encoder::decode(b"outer", &mut &file[..], &mut KeyCache::default()).unwrap();
+        assert!(outer.has_slack());
+        assert_eq!(outer.get_password("decoy"), Ok("x".to_string()));
+        assert!(outer.get_password("real").is_err());
+    }
+
+    #[test]
+    fn test_cost() {
+        for cost in 0..=u8::MAX {
+            let params = decode_cost(cost);
+            assert!(Kdf::argon2id(params).is_ok());
+            assert_eq!(encode_cost(params), cost);
+        }
+        let bench = KdfParams {
+            memory_kib: 5 * GRID_MEMORY_KIB,
+            iterations: 3,
+            parallelism: 3,
+        };
+        assert_eq!(
+            decode_cost(encode_cost(bench)),
+            KdfParams {
+                memory_kib: 4 * GRID_MEMORY_KIB,
+                iterations: 3,
+                parallelism: 2,
+            }
+        );
+        assert_eq!(decode_cost(encode_cost(TEST_PARAMS)), TEST_PARAMS);
+    }
+
+    #[test]
+    fn test_random_slack() {
+        let file = file(&random_slack());
+        assert!(open(&file, b"hidden").is_none());
+        assert!(Encoder::decode(b"outer", &mut &file[..], &mut KeyCache::default()).is_ok());
+    }
+
+    #[test]
+    fn test_too_large() {
+        let key = HiddenKey::new(b"hidden", TEST_PARAMS).unwrap();
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new(&*key.key));
+        pm.store_password("big".to_string(), &"x".repeat(SLACK_SIZE))
+            .unwrap();
+        assert!(matches!(seal(&mut pm, &key), Err(HiddenError::TooLarge)));
+    }
+}
@@ -0,0 +1,119 @@
+//! Optional password hints. A hint is stored in plaintext next to the vault,
+//! so one that gives the password away is refused: the password, reversed or
+//! with a few characters changed, may not appear in it, ignoring case,
+//! punctuation and common letter-for-digit swaps.
+
+use thiserror::Error;
+
+const MAX_LENGTH: usize = 200;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HintError {
+    #[error("the hint is empty")]
+    Empty,
+    #[error("the hint is longer than {MAX_LENGTH} characters")]
+    TooLong,
+    #[error("the hint is too close to the password, it is stored unencrypted")]
+    RevealsPassword,
+}
+
+pub fn check(hint: &str, password: &str) -> Result<(), HintError> {
+    if hint.trim().is_empty() {
+        return Err(HintError::Empty);
+    }
+    if hint.chars().count() > MAX_LENGTH {
+        return Err(HintError::TooLong);
+    }
+    if hint.contains(password) {
+        return Err(HintError::RevealsPassword);
+    }
+
+    let hint = normalize(hint);
+    let password = normalize(password);
+    if password.is_empty() {
+        return Ok(());
+    }
+    let reversed: Vec<char> = password.iter().rev().copied().collect();
+    let tolerance = password.len() / 4;
+    let reveals = [password, reversed].iter().any(|password| {
+        hint.windows(password.len().min(hint.len()))
+            .any(|window| distance(window, password) <= tolerance)
+    });
+    match reveals {
+        true => Err(HintError::RevealsPassword),
+        false => Ok(()),
+    }
+}
+
+/// Lowercase letters and digits, with digits and symbols that commonly
+/// stand in for letters turned back into them.
+pub(in crate::core) fn normalize(value: &str) -> Vec<char> {
+    value
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | '!' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Levenshtein distance.
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(x != y);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check("first pet + year", "Rex2019!"), Ok(()));
+        assert_eq!(check("  ", "hunter2"), Err(HintError::Empty));
+        assert_eq!(check(&"x".repeat(201), "hunter2"), Err(HintError::TooLong));
+    }
+
+    #[test]
+    fn test_check_refuses_similar_hints() {
+        for hint in [
+            "it is hunter2",
+            "HUNTER-2",
+            "hunter3",
+            "2retnuh backwards",
+            "Hunt3r2",
+        ] {
+            assert_eq!(
+                check(hint, "hunter2"),
+                Err(HintError::RevealsPassword),
+                "{}",
+                hint
+            );
+        }
+        assert_eq!(check("max", "x"), Err(HintError::RevealsPassword));
+        assert_eq!(check("the usual", "--"), Ok(()));
+    }
+
+    #[test]
+    fn test_distance() {
+        let chars = |v: &str| v.chars().collect::<Vec<_>>();
+        assert_eq!(distance(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(distance(&chars(""), &chars("abc")), 3);
+    }
+}
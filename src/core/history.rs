@@ -0,0 +1,238 @@
+//! Previous values of entries. Storing over an entry keeps the value it
+//! replaces in the `history` metadata of the entry, encrypted like the
+//! value itself, so that an overwrite can be undone. The last
+//! `MAX_VERSIONS` values are kept, fewer when they would not fit the
+//! metadata limit.
+//!
+//! The previous values are encrypted again whenever the values are, and
+//! are not copied into another vault.
+
+use zeroize::Zeroizing;
+
+use super::{
+    encryptor::Encryprtor,
+    entry::{self, Entry},
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+pub(in crate::core) const HISTORY: &str = "history";
+const MAX_VERSIONS: usize = 5;
+
+/// A previous value of an entry and when it was stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub modified: u64,
+    pub value: Zeroizing<String>,
+}
+
+/// Decrypted previous values, by key, see `take_histories`.
+pub(in crate::core) type Histories = Vec<(String, Vec<(u64, Zeroizing<Box<[u8]>>)>)>;
+
+/// The stored versions, most recent first, as `modified:value` pairs with
+/// the encrypted value in hex.
+fn parse(text: &str) -> Vec<(u64, Box<[u8]>)> {
+    text.split(',')
+        .filter_map(|item| {
+            let (modified, value) = item.split_once(':')?;
+            Some((modified.parse().ok()?, hex::decode(value).ok()?.into()))
+        })
+        .collect()
+}
+
+fn format(versions: &[(u64, Box<[u8]>)]) -> String {
+    versions
+        .iter()
+        .map(|(modified, value)| format!("{}:{}", modified, hex::encode(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn stored(entry: &Entry) -> Vec<(u64, Box<[u8]>)> {
+    entry.meta(HISTORY).map(parse).unwrap_or_default()
+}
+
+/// Stores `versions` in the history of `entry`, dropping the oldest ones
+/// that do not fit.
+fn set_versions(entry: &mut Entry, mut versions: Vec<(u64, Box<[u8]>)>) {
+    versions.truncate(MAX_VERSIONS);
+    loop {
+        if versions.is_empty() {
+            entry.meta.remove(HISTORY);
+            return;
+        }
+        let text = format(&versions);
+        if entry::meta_fits(HISTORY, &text) {
+            entry.meta.insert(HISTORY.to_string(), text);
+            return;
+        }
+        versions.pop();
+    }
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Moves the current value of `key`, if it is stored, into its
+    /// history, for an entry about to replace it.
+    pub(in crate::core) fn supersede(&self, key: &str, new: &mut Entry) {
+        let Some(entry) = self.kv.get(key) else {
+            return;
+        };
+        let mut versions = stored(entry);
+        versions.insert(0, (entry.modified, entry.value.clone()));
+        set_versions(new, versions);
+    }
+
+    /// Carries the history of `key` over to an entry replacing it with the
+    /// same value.
+    #[cfg(feature = "crdt")]
+    pub(in crate::core) fn keep_history(&self, key: &str, new: &mut Entry) {
+        if let Some(entry) = self.kv.get(key) {
+            set_versions(new, stored(entry));
+        }
+    }
+
+    /// The previous values of `key`, most recent first. Like
+    /// `get_password`, the access policy of the entry is checked.
+    pub fn history(&mut self, key: &str) -> Result<Vec<Version>, PasswordManagerError> {
+        let entry = self
+            .kv
+            .get(key)
+            .ok_or(PasswordManagerError::NoPasswordFound)?;
+        let versions = stored(entry);
+        self.authorize(key)?;
+        versions
+            .into_iter()
+            .map(|(modified, value)| {
+                let value = Zeroizing::new(self.decrypt_value(key, &value)?);
+                let value = String::from_utf8(value.to_vec())
+                    .or(Err(PasswordManagerError::NoPasswordFound))?;
+                Ok(Version {
+                    modified,
+                    value: Zeroizing::new(value),
+                })
+            })
+            .collect()
+    }
+
+    /// Decrypts and removes the history of every entry, before the values
+    /// are encrypted again. `restore_histories` puts it back.
+    pub(in crate::core) fn take_histories(&mut self) -> Result<Histories, PasswordManagerError> {
+        let keys: Vec<String> = self
+            .kv
+            .iter()
+            .filter(|(_, entry)| entry.meta.contains_key(HISTORY))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut histories = Vec::with_capacity(keys.len());
+        for key in keys {
+            let mut versions = Vec::new();
+            for (modified, value) in stored(&self.kv[&key]) {
+                versions.push((modified, Zeroizing::new(self.decrypt_value(&key, &value)?)));
+            }
+            if let Some(entry) = self.kv.get_mut(&key) {
+                entry.meta.remove(HISTORY);
+            }
+            histories.push((key, versions));
+        }
+        Ok(histories)
+    }
+
+    /// Encrypts the histories taken by `take_histories` again, each for the
+    /// key it is given with.
+    pub(in crate::core) fn restore_histories(
+        &mut self,
+        histories: Histories,
+    ) -> Result<(), PasswordManagerError> {
+        for (key, versions) in histories {
+            let mut encrypted = Vec::with_capacity(versions.len());
+            for (modified, value) in versions {
+                encrypted.push((modified, self.encrypt_value(&key, &value)?));
+            }
+            if let Some(entry) = self.kv.get_mut(&key) {
+                set_versions(entry, encrypted);
+            }
+        }
+        Ok(())
+    }
+
+    /// Encrypts the history of `entry`, stored under `from`, for `to`.
+    pub(in crate::core) fn move_history(
+        &mut self,
+        entry: &mut Entry,
+        from: &str,
+        to: &str,
+    ) -> Result<(), PasswordManagerError> {
+        let mut moved = Vec::new();
+        for (modified, value) in stored(entry) {
+            let value = Zeroizing::new(self.decrypt_value(from, &value)?);
+            moved.push((modified, self.encrypt_value(to, &value)?));
+        }
+        set_versions(entry, moved);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{encryptor::AESEncryptor, refactor::Change};
+
+    use super::*;
+
+    fn values(versions: Vec<Version>) -> Vec<String> {
+        versions.into_iter().map(|v| v.value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_history() {
+        let mut pm = PasswordManager::init_with_key(&[7; 32]);
+        pm.store_password("db".to_owned(), "one").unwrap();
+        assert_eq!(pm.history("db"), Ok(Vec::new()));
+        pm.store_password("db".to_owned(), "two").unwrap();
+        pm.store_password("db".to_owned(), "three").unwrap();
+        assert_eq!(values(pm.history("db").unwrap()), ["two", "one"]);
+        assert_eq!(
+            pm.history("missing"),
+            Err(PasswordManagerError::NoPasswordFound)
+        );
+
+        for n in 0..MAX_VERSIONS {
+            pm.store_password("db".to_owned(), &n.to_string()).unwrap();
+        }
+        assert_eq!(
+            values(pm.history("db").unwrap()),
+            ["3", "2", "1", "0", "three"]
+        );
+    }
+
+    #[test]
+    fn test_encrypted_again() {
+        let mut pm = PasswordManager::init_with_key(&[7; 32]);
+        pm.store_password("db".to_owned(), "old").unwrap();
+        pm.store_password("db".to_owned(), "new").unwrap();
+
+        pm.bind_values(false).unwrap();
+        assert_eq!(values(pm.history("db").unwrap()), ["old"]);
+        pm.rekey(AESEncryptor::new([9; 32]), pm.kdf(), &[9; 32])
+            .unwrap();
+        assert_eq!(values(pm.history("db").unwrap()), ["old"]);
+        pm.bind_values(true).unwrap();
+        pm.apply(&[Change::Rename {
+            from: "db".to_owned(),
+            to: "work/db".to_owned(),
+        }])
+        .unwrap();
+        assert_eq!(values(pm.history("work/db").unwrap()), ["old"]);
+    }
+
+    #[test]
+    fn test_not_copied() {
+        let mut pm = PasswordManager::init_with_key(&[7; 32]);
+        pm.store_password("db".to_owned(), "old").unwrap();
+        pm.store_password("db".to_owned(), "new").unwrap();
+        let mut other = PasswordManager::init_with_key(&[8; 32]);
+        pm.copy_into(&["db".to_owned()], &mut other).unwrap();
+        assert_eq!(other.history("db"), Ok(Vec::new()));
+    }
+}
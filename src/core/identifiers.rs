@@ -1,7 +1,26 @@
-use super::encryptor::{AESEncryptor, BlankEncryptor, DynamicEncryptor, Encryprtor};
+use super::{
+    encryptor::{AESEncryptor, BlankEncryptor, DynamicEncryptor, Encryprtor},
+    hasher::{Blake2bHasher, Hasher, Sha256Hasher, Sha512_256Hasher},
+};
 
 const BLANKENCRYPTOR_ID: u8 = 0;
-const AESENCRYPTOR_ID: u8 = 1;
+pub(in crate::core) const AESENCRYPTOR_ID: u8 = 1;
+const ENCRYPTOR_NAMES: [(u8, &str); 2] = [
+    (BLANKENCRYPTOR_ID, "none"),
+    (AESENCRYPTOR_ID, "aes-256-gcm"),
+];
+
+const SHA256HASHER_ID: u8 = 0;
+const SHA512_256HASHER_ID: u8 = 1;
+const BLAKE2BHASHER_ID: u8 = 2;
+/// The body hasher of new vaults and of vaults written before the header
+/// recorded one.
+pub const DEFAULT_HASHER_ID: u8 = SHA256HASHER_ID;
+const HASHER_NAMES: [(u8, &str); 3] = [
+    (SHA256HASHER_ID, "sha256"),
+    (SHA512_256HASHER_ID, "sha512-256"),
+    (BLAKE2BHASHER_ID, "blake2b"),
+];
 
 pub trait Identifiable {
     fn id(&self) -> u8;
@@ -25,7 +44,7 @@ impl Identifiable for AESEncryptor {
     }
 }
 
-pub fn encryptor_from_id(id: u8, key: &[u8]) -> Option<Box<dyn Encryprtor>> {
+pub fn encryptor_from_id(id: u8, key: &[u8]) -> Option<Box<dyn Encryprtor + Send>> {
     match id {
         BLANKENCRYPTOR_ID => Some(Box::new(BlankEncryptor::new())),
         AESENCRYPTOR_ID => Some(Box::new(AESEncryptor::new(key))),
@@ -33,6 +52,54 @@ pub fn encryptor_from_id(id: u8, key: &[u8]) -> Option<Box<dyn Encryprtor>> {
     }
 }
 
+impl Identifiable for Sha256Hasher {
+    fn id(&self) -> u8 {
+        SHA256HASHER_ID
+    }
+}
+
+impl Identifiable for Sha512_256Hasher {
+    fn id(&self) -> u8 {
+        SHA512_256HASHER_ID
+    }
+}
+
+impl Identifiable for Blake2bHasher {
+    fn id(&self) -> u8 {
+        BLAKE2BHASHER_ID
+    }
+}
+
+pub fn hasher_from_id(id: u8) -> Option<Box<dyn Hasher>> {
+    match id {
+        SHA256HASHER_ID => Some(Box::new(Sha256Hasher::new())),
+        SHA512_256HASHER_ID => Some(Box::new(Sha512_256Hasher::new())),
+        BLAKE2BHASHER_ID => Some(Box::new(Blake2bHasher::new())),
+        _ => None,
+    }
+}
+
+pub fn hasher_id_from_name(name: &str) -> Option<u8> {
+    HASHER_NAMES
+        .iter()
+        .find(|(_, v)| *v == name)
+        .map(|(id, _)| *id)
+}
+
+pub fn encryptor_name(id: u8) -> &'static str {
+    ENCRYPTOR_NAMES
+        .iter()
+        .find(|(v, _)| *v == id)
+        .map_or("unknown", |(_, name)| *name)
+}
+
+pub fn hasher_name(id: u8) -> &'static str {
+    HASHER_NAMES
+        .iter()
+        .find(|(v, _)| *v == id)
+        .map_or("unknown", |(_, name)| *name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,8 +110,22 @@ mod tests {
         let mut a = encryptor_from_id(BLANKENCRYPTOR_ID, k.as_ref()).unwrap();
         let s = "foobar";
         assert_eq!(
-            a.encrypt(s.as_ref()).unwrap(),
+            a.encrypt(s.as_ref(), &[]).unwrap(),
             s.bytes().collect::<Vec<u8>>().into_boxed_slice()
         );
+        assert_eq!(encryptor_name(AESENCRYPTOR_ID), "aes-256-gcm");
+        assert_eq!(encryptor_name(200), "unknown");
+    }
+
+    #[test]
+    fn test_hasher_from_id() {
+        for (id, name) in HASHER_NAMES {
+            assert_eq!(hasher_id_from_name(name), Some(id));
+            assert_eq!(hasher_name(id), name);
+            assert!(hasher_from_id(id).is_some());
+        }
+        assert_eq!(Blake2bHasher::new().id(), BLAKE2BHASHER_ID);
+        assert!(hasher_from_id(200).is_none());
+        assert_eq!(hasher_id_from_name("md5"), None);
     }
 }
@@ -0,0 +1,152 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use super::{
+    bug::OrBug,
+    hasher::{Hasher, Sha256Hasher},
+    rng,
+};
+
+pub const ID_LENGTH: usize = 16;
+
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+static DEVICE_ID_FILE: OnceLock<PathBuf> = OnceLock::new();
+static DEVICE_ID: OnceLock<DeviceId> = OnceLock::new();
+
+pub type VaultId = [u8; ID_LENGTH];
+pub type DeviceId = [u8; ID_LENGTH];
+
+/// Generates a random (version 4) UUID for a freshly initialized vault.
+pub fn new_vault_id() -> VaultId {
+    let mut id = [0; ID_LENGTH];
+    rng::fill(&mut id);
+    id[6] = (id[6] & 0x0f) | 0x40;
+    id[8] = (id[8] & 0x3f) | 0x80;
+    id
+}
+
+/// Makes `path`, next to the vault, where the identity of a machine
+/// without a machine id is kept. It can only be set once, at startup,
+/// before the identity is first asked for.
+pub fn configure_device_id_file(path: PathBuf) {
+    let _ = DEVICE_ID_FILE.set(path);
+}
+
+/// Returns a stable identity of the current machine, derived from the
+/// systemd machine id so it never has to be stored by mopm itself.
+/// Without one, e.g. in containers, it is derived from a random id kept
+/// in the configured file instead, or drawn for this process alone when
+/// that cannot be read or written, so that such machines do not all
+/// share an identity.
+pub fn current_device_id() -> DeviceId {
+    *DEVICE_ID.get_or_init(|| {
+        let machine_id = MACHINE_ID_PATHS
+            .iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        let seed = match machine_id {
+            Some(v) => v,
+            None => DEVICE_ID_FILE
+                .get()
+                .and_then(|path| stored_device_seed(path).ok())
+                .unwrap_or_else(random_seed),
+        };
+
+        let mut hasher = Sha256Hasher::new();
+        let digest = hasher.hash(format!("mopm-device:{}", seed).as_bytes());
+        digest[..ID_LENGTH]
+            .try_into()
+            .or_bug("sha256 digest is longer than the id")
+    })
+}
+
+/// The random id kept in `path`, written there first if it is missing.
+fn stored_device_seed(path: &Path) -> io::Result<String> {
+    let seed = random_seed();
+    let created = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path);
+    match created {
+        Ok(mut file) => {
+            file.write_all(seed.as_bytes())?;
+            Ok(seed)
+        }
+        // Also when another process wrote it first.
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            let stored = std::fs::read_to_string(path)?;
+            match stored.trim() {
+                "" => Err(io::ErrorKind::InvalidData.into()),
+                v => Ok(v.to_string()),
+            }
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn random_seed() -> String {
+    let mut seed = [0; ID_LENGTH];
+    rng::fill(&mut seed);
+    format!("random:{}", hex::encode(seed))
+}
+
+pub fn is_unset(id: &[u8; ID_LENGTH]) -> bool {
+    id.iter().all(|&v| v == 0)
+}
+
+pub fn format_id(id: &[u8; ID_LENGTH]) -> String {
+    let hex = hex::encode(id);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_id() {
+        let a = new_vault_id();
+        assert_ne!(a, new_vault_id());
+        assert!(!is_unset(&a));
+        assert_eq!(a[6] >> 4, 4);
+    }
+
+    #[test]
+    fn test_device_id_is_stable() {
+        assert_eq!(current_device_id(), current_device_id());
+    }
+
+    #[test]
+    fn test_stored_device_seed() {
+        let path = std::env::temp_dir().join(format!("mopm-device-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let seed = stored_device_seed(&path).unwrap();
+        assert_eq!(stored_device_seed(&path).unwrap(), seed);
+        std::fs::remove_file(&path).unwrap();
+        assert_ne!(stored_device_seed(&path).unwrap(), seed);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_format_id() {
+        assert_eq!(
+            format_id(&[0xab; ID_LENGTH]),
+            "abababab-abab-abab-abab-abababababab"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Derivation of the encryption key from the master password.
+
+use std::time::{Duration, Instant};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use thiserror::Error;
+
+use super::rng;
+
+pub const SALT_LENGTH: usize = 16;
+pub const KEY_LENGTH: usize = 32;
+
+const RAW_ID: u8 = 0;
+const ARGON2ID_ID: u8 = 1;
+
+const CALIBRATION_ITERATIONS: u32 = 3;
+const CALIBRATION_PARALLELISM: u32 = 1;
+const MIN_MEMORY_KIB: u32 = 8 * 1024;
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const START_MEMORY_KIB: u32 = 16 * 1024;
+const MAX_ITERATIONS: u32 = 1024;
+const MAX_PARALLELISM: u32 = 64;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum KdfError {
+    #[error("unknown key derivation function `{0}`")]
+    UnknownKdf(u8),
+    #[error("invalid key derivation parameters: `{0}`")]
+    InvalidParams(String),
+}
+
+impl From<argon2::Error> for KdfError {
+    fn from(value: argon2::Error) -> Self {
+        Self::InvalidParams(value.to_string())
+    }
+}
+
+/// Argon2id cost parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Checks the bounds of mopm before argon2 does, so parameters read from
+    /// a vault cannot make it allocate, loop or overflow without limit.
+    fn argon2(&self) -> Result<Argon2<'static>, KdfError> {
+        if self.memory_kib > MAX_MEMORY_KIB
+            || self.iterations > MAX_ITERATIONS
+            || self.parallelism > MAX_PARALLELISM
+        {
+            return Err(KdfError::InvalidParams("cost too high".to_string()));
+        }
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LENGTH),
+        )?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kdf {
+    /// The password itself is the key. Vaults written before v0.7 use it.
+    Raw,
+    Argon2id {
+        params: KdfParams,
+        salt: [u8; SALT_LENGTH],
+    },
+}
+
+impl Kdf {
+    pub const ENCODED_SIZE: usize = 1 + 3 * size_of::<u32>() + SALT_LENGTH;
+
+    /// Argon2id with `params` and a fresh random salt.
+    pub fn argon2id(params: KdfParams) -> Result<Self, KdfError> {
+        params.argon2()?;
+        let mut salt = [0; SALT_LENGTH];
+        rng::fill(&mut salt);
+        Ok(Self::Argon2id { params, salt })
+    }
+
+    pub fn derive(&self, password: &[u8]) -> Result<Vec<u8>, KdfError> {
+        match self {
+            Self::Raw => Ok(password.to_vec()),
+            Self::Argon2id { params, salt } => {
+                let mut key = vec![0; KEY_LENGTH];
+                params
+                    .argon2()?
+                    .hash_password_into(password, salt, &mut key)?;
+                Ok(key)
+            }
+        }
+    }
+
+    pub fn params(&self) -> Option<KdfParams> {
+        match self {
+            Self::Raw => None,
+            Self::Argon2id { params, .. } => Some(*params),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::ENCODED_SIZE] {
+        let mut res = [0; Self::ENCODED_SIZE];
+        if let Self::Argon2id { params, salt } = self {
+            res[0] = ARGON2ID_ID;
+            res[1..5].copy_from_slice(&params.memory_kib.to_be_bytes());
+            res[5..9].copy_from_slice(&params.iterations.to_be_bytes());
+            res[9..13].copy_from_slice(&params.parallelism.to_be_bytes());
+            res[13..].copy_from_slice(&salt);
+        }
+        res
+    }
+
+    pub fn try_from_bytes(bytes: &[u8; Self::ENCODED_SIZE]) -> Result<Self, KdfError> {
+        let u32_at =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        match bytes[0] {
+            RAW_ID => Ok(Self::Raw),
+            ARGON2ID_ID => {
+                let params = KdfParams {
+                    memory_kib: u32_at(1),
+                    iterations: u32_at(5),
+                    parallelism: u32_at(9),
+                };
+                params.argon2()?;
+                let mut salt = [0; SALT_LENGTH];
+                salt.copy_from_slice(&bytes[13..]);
+                Ok(Self::Argon2id { params, salt })
+            }
+            id => Err(KdfError::UnknownKdf(id)),
+        }
+    }
+}
+
+/// Argon2id parameters found by `calibrate` and how long they took.
+#[derive(Debug, Clone, Copy)]
+pub struct Calibration {
+    pub params: KdfParams,
+    pub elapsed: Duration,
+}
+
+/// How long deriving a key with `params` takes on this machine.
+pub fn measure(params: KdfParams) -> Result<Duration, KdfError> {
+    let kdf = Kdf::Argon2id {
+        params,
+        salt: [0; SALT_LENGTH],
+    };
+    let start = Instant::now();
+    kdf.derive(b"mopm calibration")?;
+    Ok(start.elapsed())
+}
+
+/// Finds Argon2id parameters that take about `target` to derive a key.
+/// Memory is scaled first, since it is what makes guessing expensive on
+/// GPUs; iterations only grow once the memory cap is reached.
+pub fn calibrate(target: Duration) -> Result<Calibration, KdfError> {
+    let mut params = KdfParams {
+        memory_kib: START_MEMORY_KIB,
+        iterations: CALIBRATION_ITERATIONS,
+        parallelism: CALIBRATION_PARALLELISM,
+    };
+    let mut elapsed = measure(params)?;
+    while elapsed * 2 < target && params.memory_kib < MAX_MEMORY_KIB {
+        params.memory_kib = (params.memory_kib * 2).min(MAX_MEMORY_KIB);
+        elapsed = measure(params)?;
+    }
+
+    let scale = target.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON);
+    let memory_kib = (params.memory_kib as f64 * scale) as u32;
+    params.memory_kib = memory_kib.clamp(MIN_MEMORY_KIB, MAX_MEMORY_KIB);
+    if memory_kib > MAX_MEMORY_KIB {
+        let scale = memory_kib as f64 / MAX_MEMORY_KIB as f64;
+        params.iterations = ((params.iterations as f64 * scale).ceil() as u32).min(MAX_ITERATIONS);
+    }
+
+    Ok(Calibration {
+        params,
+        elapsed: measure(params)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PARAMS: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_derive() {
+        assert_eq!(Kdf::Raw.derive(b"foo").unwrap(), b"foo");
+
+        let kdf = Kdf::argon2id(TEST_PARAMS).unwrap();
+        let key = kdf.derive(b"foo").unwrap();
+        assert_eq!(key.len(), KEY_LENGTH);
+        assert_eq!(kdf.derive(b"foo").unwrap(), key);
+        assert_ne!(kdf.derive(b"bar").unwrap(), key);
+        assert_ne!(
+            Kdf::argon2id(TEST_PARAMS).unwrap().derive(b"foo").unwrap(),
+            key
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        let kdf = Kdf::argon2id(TEST_PARAMS).unwrap();
+        assert_eq!(Kdf::try_from_bytes(&kdf.to_bytes()), Ok(kdf));
+        assert_eq!(Kdf::try_from_bytes(&Kdf::Raw.to_bytes()), Ok(Kdf::Raw));
+
+        let mut bytes = kdf.to_bytes();
+        bytes[0] = 9;
+        assert_eq!(Kdf::try_from_bytes(&bytes), Err(KdfError::UnknownKdf(9)));
+        bytes[0] = ARGON2ID_ID;
+        bytes[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Kdf::try_from_bytes(&bytes).is_err());
+        bytes[1..5].copy_from_slice(&0u32.to_be_bytes());
+        assert!(Kdf::try_from_bytes(&bytes).is_err());
+        bytes[1..5].copy_from_slice(&64u32.to_be_bytes());
+        bytes[9..13].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Kdf::try_from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Keys derived in this process, so a command that unlocks the same vault
+//! more than once, e.g. `diff` or `merge` of two replicas, runs the KDF
+//! once. Entries are keyed by the vault id and the KDF salt and parameters,
+//! and only returned for the same password, which is checked through an
+//! HMAC under a secret of the cache rather than kept.
+
+use std::collections::HashMap;
+
+use zeroize::Zeroizing;
+
+use super::{
+    ct,
+    fingerprint::{self, Fingerprint, FingerprintKey},
+    identity::VaultId,
+    kdf::{Kdf, KdfError},
+    memsec,
+};
+
+struct CachedKey {
+    password_tag: Zeroizing<Fingerprint>,
+    key: Zeroizing<Vec<u8>>,
+}
+
+/// Zeroizes the cached keys when dropped.
+pub struct KeyCache {
+    secret: Zeroizing<FingerprintKey>,
+    entries: HashMap<(VaultId, Kdf), CachedKey>,
+}
+
+impl Default for KeyCache {
+    fn default() -> Self {
+        Self {
+            secret: Zeroizing::new(fingerprint::new_key()),
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for KeyCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyCache")
+            .field("entries", &self.entries.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeyCache {
+    /// Like `kdf.derive(password)`, reusing the key derived earlier for the
+    /// same vault, KDF and password.
+    pub fn derive(
+        &mut self,
+        vault_id: &VaultId,
+        kdf: &Kdf,
+        password: &[u8],
+    ) -> Result<Vec<u8>, KdfError> {
+        if *kdf == Kdf::Raw {
+            return kdf.derive(password);
+        }
+        let password_tag = Zeroizing::new(fingerprint::fingerprint(&self.secret, password));
+        let id = (*vault_id, *kdf);
+        if let Some(cached) = self.entries.get(&id) {
+            if ct::eq(&*cached.password_tag, &*password_tag) {
+                return Ok(cached.key.to_vec());
+            }
+        }
+
+        let key = kdf.derive(password)?;
+        let cached = Zeroizing::new(key.clone());
+        memsec::dont_dump(&cached);
+        self.entries.insert(
+            id,
+            CachedKey {
+                password_tag,
+                key: cached,
+            },
+        );
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::kdf::KdfParams;
+
+    const TEST_PARAMS: KdfParams = KdfParams {
+        memory_kib: 8,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_derive() {
+        let mut cache = KeyCache::default();
+        let kdf = Kdf::argon2id(TEST_PARAMS).unwrap();
+        let key = cache.derive(&[1; 16], &kdf, b"foo").unwrap();
+        assert_eq!(key, kdf.derive(b"foo").unwrap());
+        assert_eq!(cache.derive(&[1; 16], &kdf, b"foo").unwrap(), key);
+        assert_eq!(cache.entries.len(), 1);
+
+        assert_eq!(
+            cache.derive(&[1; 16], &kdf, b"bar").unwrap(),
+            kdf.derive(b"bar").unwrap()
+        );
+        assert_eq!(cache.derive(&[2; 16], &kdf, b"foo").unwrap(), key);
+        assert_eq!(cache.entries.len(), 2);
+
+        let other = Kdf::argon2id(TEST_PARAMS).unwrap();
+        assert_ne!(cache.derive(&[1; 16], &other, b"foo").unwrap(), key);
+    }
+
+    #[test]
+    fn test_raw_is_not_cached() {
+        let mut cache = KeyCache::default();
+        assert_eq!(cache.derive(&[1; 16], &Kdf::Raw, b"foo").unwrap(), b"foo");
+        assert!(cache.entries.is_empty());
+    }
+}
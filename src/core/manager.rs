@@ -2,7 +2,26 @@ use std::collections::HashMap;
 
 use thiserror::Error;
 
-use super::encryptor::{AESEncryptor, Encryprtor, EncryprtorError};
+#[cfg(feature = "sqlite")]
+use super::rows::RowCache;
+use super::{
+    clock, ct,
+    encoding::{capability::Capabilities, version::Version},
+    encryptor::{AESEncryptor, Encryprtor, EncryprtorError},
+    entry::{self, Entries, Entry, KeyHash},
+    executor::ExecutorError,
+    fingerprint::{self, Fingerprint, FingerprintKey},
+    identifiers::{self, Identifiable},
+    identity::{self, DeviceId, VaultId},
+    kdf::{Kdf, KdfError},
+    merkle::MerkleTree,
+    namespace::Keyring,
+    policy::{self, AccessGuard, Unattended},
+    pool::Pool,
+    provenance,
+    schema::Schema,
+    timelock,
+};
 
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum PasswordManagerError {
@@ -10,6 +29,16 @@ pub enum PasswordManagerError {
     EncryptorError(#[from] EncryprtorError),
     #[error("no matching passwords found")]
     NoPasswordFound,
+    #[error("the entry is too large to be stored")]
+    EntryTooLarge,
+    #[error("error while running the secret command: `{0}`")]
+    ExecutorError(#[from] ExecutorError),
+    #[error("key derivation error: `{0}`")]
+    KdfError(#[from] KdfError),
+    #[error("access to the entry was denied")]
+    AccessDenied,
+    #[error("the entry is locked until {}", timelock::format_time(*.0))]
+    TimeLocked(u64),
 }
 
 #[derive(Debug)]
@@ -17,16 +46,101 @@ pub struct PasswordManager<T>
 where
     T: Encryprtor,
 {
-    pub(in crate::core) kv: HashMap<String, Box<[u8]>>,
+    pub(in crate::core) kv: Entries,
+    pub(in crate::core) tombstones: HashMap<KeyHash, u64>,
+    pub(in crate::core) fingerprint_key: FingerprintKey,
     pub(in crate::core) encryptor: T,
+    pub(in crate::core) vault_id: VaultId,
+    pub(in crate::core) last_device: DeviceId,
+    pub(in crate::core) generation: u64,
+    pub(in crate::core) kdf: Kdf,
+    pub(in crate::core) hasher_id: u8,
+    pub(in crate::core) version: Version,
+    pub(in crate::core) capabilities: Capabilities,
+    /// The schema as it was read, see `schema`.
+    pub(in crate::core) schema: Schema,
+    pub(in crate::core) merkle: MerkleTree,
+    pub(in crate::core) slack: bool,
+    pub(in crate::core) guard: Box<dyn AccessGuard>,
+    pub(in crate::core) keyring: Keyring,
+    #[cfg(feature = "sqlite")]
+    pub(in crate::core) rows: RowCache,
 }
 
 impl PasswordManager<AESEncryptor> {
-    pub fn init(key: &str) -> Self {
-        Self {
-            kv: HashMap::new(),
-            encryptor: AESEncryptor::new(key),
+    /// A new vault binds its values, see `namespace`.
+    pub fn init(password: &str, kdf: Kdf) -> Result<Self, PasswordManagerError> {
+        let key = kdf.derive(password.as_ref())?;
+        Ok(
+            Self::from_raw_parts(HashMap::new(), AESEncryptor::new(&key))
+                .with_kdf(kdf)
+                .with_master_key(&key)
+                .with_bound_values(),
+        )
+    }
+
+    /// A vault locked by `key` itself rather than a password, for keys
+    /// that are random already and gain nothing from a slow derivation.
+    pub fn init_with_key(key: &[u8]) -> Self {
+        Self::from_raw_parts(HashMap::new(), AESEncryptor::new(key))
+            .with_kdf(Kdf::Raw)
+            .with_master_key(key)
+            .with_bound_values()
+    }
+
+    fn with_bound_values(mut self) -> Self {
+        self.capabilities = self.capabilities.with(Capabilities::BOUND_VALUES);
+        self
+    }
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor + Identifiable,
+{
+    pub fn encryptor_id(&self) -> u8 {
+        self.encryptor.id()
+    }
+
+    /// Computes fingerprints for entries stored before fingerprints existed.
+    /// This is the only time their values have to be decrypted, which is
+    /// done on the threads of `pool` once the master key is known.
+    pub fn backfill_fingerprints(&mut self, pool: &Pool) -> Result<usize, PasswordManagerError> {
+        let pending: Vec<(String, Box<[u8]>)> = self
+            .kv
+            .iter()
+            .filter(|(_, entry)| entry.fingerprint.is_none())
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect();
+        let fingerprint_key = self.fingerprint_key;
+        let fingerprints: Vec<(String, Fingerprint)> = match self.vault_keys() {
+            Some(keys) => pool
+                .map(
+                    pending,
+                    || keys.decryptor(),
+                    |decryptor, (key, value)| {
+                        let value = decryptor.decrypt(&key, &value)?;
+                        Ok((key, fingerprint::fingerprint(&fingerprint_key, &value)))
+                    },
+                )
+                .into_iter()
+                .collect::<Result<_, EncryprtorError>>()?,
+            None => pending
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = self.decrypt_value(&key, &value)?;
+                    Ok((key, fingerprint::fingerprint(&fingerprint_key, &value)))
+                })
+                .collect::<Result<_, EncryprtorError>>()?,
+        };
+        let count = fingerprints.len();
+        for (key, fingerprint) in fingerprints {
+            self.kv
+                .get_mut(&key)
+                .expect("the key was just listed")
+                .fingerprint = Some(fingerprint);
         }
+        Ok(count)
     }
 }
 
@@ -34,31 +148,342 @@ impl<T> PasswordManager<T>
 where
     T: Encryprtor,
 {
-    pub fn from_raw_parts(kv: HashMap<String, Box<[u8]>>, encryptor: T) -> Self {
-        Self { kv, encryptor }
+    pub fn from_raw_parts(kv: HashMap<String, Entry>, encryptor: T) -> Self {
+        Self {
+            kv: kv.into(),
+            tombstones: HashMap::new(),
+            fingerprint_key: fingerprint::new_key(),
+            encryptor,
+            vault_id: identity::new_vault_id(),
+            last_device: [0; identity::ID_LENGTH],
+            generation: 0,
+            kdf: Kdf::Raw,
+            hasher_id: identifiers::DEFAULT_HASHER_ID,
+            version: Version::current_version(),
+            capabilities: Capabilities::default(),
+            schema: Schema::default(),
+            merkle: MerkleTree::default(),
+            slack: false,
+            guard: Box::new(Unattended),
+            keyring: Keyring::default(),
+            #[cfg(feature = "sqlite")]
+            rows: RowCache::default(),
+        }
+    }
+
+    pub fn with_identity(mut self, vault_id: VaultId, last_device: DeviceId) -> Self {
+        if !identity::is_unset(&vault_id) {
+            self.vault_id = vault_id;
+            self.keyring.forget_subkeys();
+        }
+        self.last_device = last_device;
+        self
+    }
+
+    pub fn with_tombstones(mut self, tombstones: HashMap<KeyHash, u64>) -> Self {
+        self.tombstones = tombstones;
+        self
+    }
+
+    pub fn with_fingerprint_key(mut self, fingerprint_key: FingerprintKey) -> Self {
+        self.fingerprint_key = fingerprint_key;
+        self
+    }
+
+    pub fn with_generation(mut self, generation: u64) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    /// `kdf` must be the function the encryption key was derived with.
+    pub fn with_kdf(mut self, kdf: Kdf) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
+    pub fn kdf(&self) -> Kdf {
+        self.kdf
+    }
+
+    /// `hasher_id` names the hasher of the body digest from the next save on.
+    pub fn with_hasher(mut self, hasher_id: u8) -> Self {
+        self.hasher_id = hasher_id;
+        self
+    }
+
+    pub fn hasher_id(&self) -> u8 {
+        self.hasher_id
+    }
+
+    /// The format the vault was read in, written in the current one.
+    pub fn with_format(mut self, version: Version, capabilities: Capabilities) -> Self {
+        self.version = version;
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Whether the vault file carries slack after the body, which saves
+    /// must keep in place.
+    pub fn set_slack(&mut self, slack: bool) {
+        self.slack = slack;
+    }
+
+    pub fn has_slack(&self) -> bool {
+        self.slack
+    }
+
+    /// Re-encrypts every value with `encryptor`, created with `master_key`
+    /// derived with `kdf`, and the previous values with them. Cached
+    /// dynamic values are dropped rather than re-encrypted.
+    pub fn rekey(
+        &mut self,
+        encryptor: T,
+        kdf: Kdf,
+        master_key: &[u8],
+    ) -> Result<(), PasswordManagerError> {
+        self.drop_dynamic_caches();
+        let histories = self.take_histories()?;
+        let encrypted: Vec<(String, Box<[u8]>)> = self
+            .kv
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect();
+        let mut values = Vec::with_capacity(encrypted.len());
+        for (key, value) in encrypted {
+            let value = self.decrypt_value(&key, &value)?;
+            values.push((key, value));
+        }
+        self.encryptor = encryptor;
+        #[cfg(feature = "sqlite")]
+        self.rows.clear();
+        self.kdf = kdf;
+        self.set_master_key(master_key);
+        for (key, value) in values {
+            let value = self.encrypt_value(&key, &value)?;
+            self.kv
+                .get_mut(&key)
+                .expect("the key was just listed")
+                .value = value;
+        }
+        self.restore_histories(histories)
     }
 
+    /// The generation the vault had on disk when it was read.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn vault_id(&self) -> &VaultId {
+        &self.vault_id
+    }
+
+    /// The device that last saved this vault, unset for vaults written
+    /// before device identities were recorded.
+    pub fn last_device(&self) -> &DeviceId {
+        &self.last_device
+    }
+
+    /// Decrypts the password stored under `key` once its access policy,
+    /// if any, is satisfied.
     pub fn get_password(&mut self, key: &str) -> Result<String, PasswordManagerError> {
-        let encrypted_password = self
+        self.authorize(key)?;
+        self.decrypt_password(key)
+    }
+
+    /// Like `get_password`, without checking the access policy.
+    pub(in crate::core) fn decrypt_password(
+        &mut self,
+        key: &str,
+    ) -> Result<String, PasswordManagerError> {
+        let value = self
             .kv
             .get(key)
-            .ok_or(PasswordManagerError::NoPasswordFound)?;
+            .ok_or(PasswordManagerError::NoPasswordFound)?
+            .value
+            .clone();
 
-        String::from_utf8(
-            self.encryptor
-                .decrypt(encrypted_password.as_ref())
-                .map_err(PasswordManagerError::from)?
-                .to_vec(),
-        )
-        .or(Err(PasswordManagerError::NoPasswordFound))
+        String::from_utf8(self.decrypt_value(key, &value)?.to_vec())
+            .or(Err(PasswordManagerError::NoPasswordFound))
+    }
+
+    /// When the vault was last changed, as far as its entries tell.
+    pub fn last_modified(&self) -> u64 {
+        self.kv.values().map(Entry::modified).max().unwrap_or(0)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.kv.contains_key(key)
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.kv.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// A tag that names `key` outside the vault without telling it: the
+    /// tag is keyed with the vault, and stays the same across saves.
+    #[cfg(feature = "monitor")]
+    pub fn key_tag(&self, key: &str) -> Fingerprint {
+        fingerprint::fingerprint(&self.fingerprint_key, format!("key:{}", key).as_bytes())
+    }
+
+    pub fn search(&self, pattern: &str) -> Vec<&str> {
+        self.keys()
+            .into_iter()
+            .filter(|key| key.contains(pattern))
+            .collect()
+    }
+
+    /// Entries matching `pattern` in key order. Values stay encrypted, only
+    /// the name and metadata are readable; `get_password` decrypts one.
+    pub fn entries<'a>(&'a self, pattern: &str) -> impl Iterator<Item = (&'a str, &'a Entry)> {
+        self.search(pattern)
+            .into_iter()
+            .filter_map(|key| Some((key, self.kv.get(key)?)))
     }
 
     pub fn store_password(&mut self, key: String, value: &str) -> Result<(), PasswordManagerError> {
-        let encrypted_password = self.encryptor.encrypt(value.as_ref())?;
+        let encrypted_password = self.encrypt_value(&key, value.as_ref())?;
+        if !entry::fits(&key, &encrypted_password) {
+            return Err(PasswordManagerError::EntryTooLarge);
+        }
+        let modified = clock::after(self.kv.get(&key).map_or(0, |v| v.modified));
+        let fingerprint = fingerprint::fingerprint(&self.fingerprint_key, value.as_ref());
+        let login = self
+            .kv
+            .get(&key)
+            .map(|v| {
+                v.meta
+                    .iter()
+                    .filter(|(name, _)| {
+                        entry::LOGIN_FIELDS.contains(&name.as_str())
+                            || provenance::KEPT_FIELDS.contains(&name.as_str())
+                            || *name == policy::POLICY_FIELD
+                    })
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut entry = Entry::new(encrypted_password, modified)
+            .with_fingerprint(Some(fingerprint))
+            .with_meta(login);
+        self.supersede(&key, &mut entry);
 
-        self.kv.insert(key.to_string(), encrypted_password);
+        self.tombstones.remove(&entry::key_hash(&key));
+        self.kv.insert(key, entry);
         Ok(())
     }
+
+    /// Marks the entry as modified now, leaving its value alone, or
+    /// reserves the key with an empty placeholder. Returns whether the
+    /// placeholder was created.
+    pub fn touch(&mut self, key: &str) -> Result<bool, PasswordManagerError> {
+        match self.kv.get_mut(key) {
+            Some(entry) => {
+                entry.modified = clock::after(entry.modified);
+                Ok(false)
+            }
+            None => self.store_password(key.to_string(), "").map(|_| true),
+        }
+    }
+
+    pub fn meta(&self, key: &str, name: &str) -> Option<&str> {
+        self.kv.get(key)?.meta(name)
+    }
+
+    pub fn set_meta(
+        &mut self,
+        key: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<(), PasswordManagerError> {
+        if !entry::meta_fits(name, value) {
+            return Err(PasswordManagerError::EntryTooLarge);
+        }
+        let entry = self
+            .kv
+            .get_mut(key)
+            .ok_or(PasswordManagerError::NoPasswordFound)?;
+        entry.meta.insert(name.to_string(), value.to_string());
+        entry.modified = clock::after(entry.modified);
+        Ok(())
+    }
+
+    pub fn remove_meta(&mut self, key: &str, name: &str) -> Result<(), PasswordManagerError> {
+        let entry = self
+            .kv
+            .get_mut(key)
+            .ok_or(PasswordManagerError::NoPasswordFound)?;
+        if entry.meta.remove(name).is_some() {
+            entry.modified = clock::after(entry.modified);
+        }
+        Ok(())
+    }
+
+    /// Other entries whose password is the same as `value`, compared by
+    /// fingerprint so nothing has to be decrypted.
+    pub fn reused_by(&self, key: &str, value: &str) -> Vec<&str> {
+        let fingerprint = fingerprint::fingerprint(&self.fingerprint_key, value.as_ref());
+        let mut keys: Vec<&str> = self
+            .kv
+            .iter()
+            .filter(|(k, entry)| *k != key && ct::eq_opt(entry.fingerprint.as_ref(), &fingerprint))
+            .map(|(k, _)| k.as_str())
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Groups of two or more entries sharing the same password.
+    pub fn reuse_groups(&self) -> Vec<Vec<&str>> {
+        let mut groups: HashMap<_, Vec<&str>> = HashMap::new();
+        for (key, entry) in &self.kv {
+            if let Some(fingerprint) = entry.fingerprint {
+                groups.entry(fingerprint).or_default().push(key);
+            }
+        }
+
+        let mut groups: Vec<Vec<&str>> = groups
+            .into_values()
+            .filter(|keys| keys.len() > 1)
+            .map(|mut keys| {
+                keys.sort_unstable();
+                keys
+            })
+            .collect();
+        groups.sort_unstable();
+        groups
+    }
+
+    /// Removes the entry and leaves a tombstone behind so that merging with
+    /// an older copy of the vault does not bring it back.
+    pub fn delete(&mut self, key: &str) -> Result<(), PasswordManagerError> {
+        let removed = self
+            .kv
+            .remove(key)
+            .ok_or(PasswordManagerError::NoPasswordFound)?;
+        self.tombstones
+            .insert(entry::key_hash(key), clock::after(removed.modified));
+        Ok(())
+    }
+
+    /// Drops tombstones of entries deleted before `cutoff` and returns how
+    /// many were removed.
+    pub fn compact(&mut self, cutoff: u64) -> usize {
+        let before = self.tombstones.len();
+        self.tombstones.retain(|_, deleted| *deleted >= cutoff);
+        before - self.tombstones.len()
+    }
 }
 
 #[cfg(test)]
@@ -69,10 +494,7 @@ mod tests {
 
     #[test]
     fn test_load_store() {
-        let mut pm = PasswordManager {
-            kv: HashMap::new(),
-            encryptor: AESEncryptor::new("foo"),
-        };
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
 
         assert!(pm.store_password("foo".to_owned(), "bar").is_ok());
         assert_eq!(pm.get_password("foo"), Ok("bar".to_owned()));
@@ -82,4 +504,160 @@ mod tests {
             Err(PasswordManagerError::NoPasswordFound)
         );
     }
+
+    #[test]
+    fn test_keys() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("b".to_owned(), "bar");
+        let _ = pm.store_password("a".to_owned(), "baz");
+
+        assert_eq!(pm.keys(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("work/mail".to_owned(), "a");
+        let _ = pm.store_password("work/vpn".to_owned(), "b");
+        let _ = pm.store_password("home/mail".to_owned(), "c");
+
+        assert_eq!(pm.search("work/"), vec!["work/mail", "work/vpn"]);
+        assert_eq!(pm.search("mail"), vec!["home/mail", "work/mail"]);
+        assert_eq!(pm.search("x"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_entries() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("web/b".to_owned(), "1");
+        let _ = pm.store_password("web/a".to_owned(), "2");
+        let _ = pm.store_password("mail".to_owned(), "3");
+        let _ = pm.set_meta("web/a", "username", "alice");
+
+        let entries: Vec<_> = pm
+            .entries("web/")
+            .map(|(key, entry)| (key, entry.meta("username")))
+            .collect();
+        assert_eq!(entries, [("web/a", Some("alice")), ("web/b", None)]);
+    }
+
+    #[test]
+    fn test_rekey() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("foo".to_owned(), "bar");
+        let kdf = Kdf::argon2id(crate::core::kdf::KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        })
+        .unwrap();
+
+        let key = kdf.derive(b"baz").unwrap();
+        pm.rekey(AESEncryptor::new(&key), kdf, &key).unwrap();
+        assert_eq!(pm.kdf(), kdf);
+        assert_eq!(pm.get_password("foo"), Ok("bar".to_string()));
+        assert!(AESEncryptor::new("foo")
+            .decrypt(&pm.kv["foo"].value, &[])
+            .is_err());
+    }
+
+    #[test]
+    fn test_meta() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("foo".to_owned(), "bar");
+
+        assert_eq!(pm.set_meta("foo", "url", "https://example.com"), Ok(()));
+        assert_eq!(pm.set_meta("foo", "other", "x"), Ok(()));
+        assert_eq!(
+            pm.set_meta("bar", "url", "x"),
+            Err(PasswordManagerError::NoPasswordFound)
+        );
+
+        let _ = pm.store_password("foo".to_owned(), "baz");
+        assert_eq!(pm.meta("foo", "url"), Some("https://example.com"));
+        assert_eq!(pm.meta("foo", "other"), None);
+
+        assert_eq!(pm.remove_meta("foo", "url"), Ok(()));
+        assert_eq!(pm.meta("foo", "url"), None);
+
+        let long = "a".repeat(entry::MAX_KEY_LENGTH + 1);
+        assert_eq!(
+            pm.store_password(long.clone(), "bar"),
+            Err(PasswordManagerError::EntryTooLarge)
+        );
+        let long = "a".repeat(entry::MAX_META_LENGTH + 1);
+        assert_eq!(
+            pm.set_meta("foo", "notes", &long),
+            Err(PasswordManagerError::EntryTooLarge)
+        );
+    }
+
+    #[test]
+    fn test_delete_compact() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("foo".to_owned(), "bar");
+
+        assert_eq!(pm.delete("foo"), Ok(()));
+        assert_eq!(pm.delete("foo"), Err(PasswordManagerError::NoPasswordFound));
+        assert!(pm.tombstones.contains_key(&entry::key_hash("foo")));
+
+        assert_eq!(pm.compact(0), 0);
+        assert_eq!(pm.compact(u64::MAX), 1);
+
+        let _ = pm.delete("missing");
+        let _ = pm.store_password("foo".to_owned(), "bar");
+        let _ = pm.delete("foo");
+        let _ = pm.store_password("foo".to_owned(), "baz");
+        assert!(pm.tombstones.is_empty());
+    }
+
+    #[test]
+    fn test_touch() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("foo".to_owned(), "bar");
+        let modified = pm.kv["foo"].modified;
+
+        assert_eq!(pm.touch("foo"), Ok(false));
+        assert!(pm.kv["foo"].modified > modified);
+        assert_eq!(pm.get_password("foo"), Ok("bar".to_string()));
+
+        assert!(pm.last_modified() >= pm.kv["foo"].modified);
+        assert!(!pm.contains("new"));
+        assert_eq!(pm.touch("new"), Ok(true));
+        assert!(pm.contains("new"));
+        assert_eq!(pm.get_password("new"), Ok(String::new()));
+    }
+
+    #[test]
+    fn test_reuse() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("a".to_owned(), "same");
+        let _ = pm.store_password("b".to_owned(), "same");
+        let _ = pm.store_password("c".to_owned(), "other");
+
+        assert_eq!(pm.reused_by("d", "same"), vec!["a", "b"]);
+        assert_eq!(pm.reused_by("a", "same"), vec!["b"]);
+        assert_eq!(pm.reuse_groups(), vec![vec!["a", "b"]]);
+
+        pm.kv.get_mut("c").unwrap().fingerprint = None;
+        let _ = pm.store_password("d".to_owned(), "other");
+        assert!(pm.reuse_groups().len() == 1);
+        assert_eq!(pm.backfill_fingerprints(&Pool::default()), Ok(1));
+        assert_eq!(pm.reuse_groups(), vec![vec!["a", "b"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn test_backfill_on_workers() {
+        let mut pm = PasswordManager::init("pw", Kdf::Raw).unwrap();
+        for key in ["a", "work/b", "work/c", "home/d", "e"] {
+            pm.store_password(key.to_string(), "same").unwrap();
+            pm.kv.get_mut(key).unwrap().fingerprint = None;
+        }
+        let pool = Pool::new(std::num::NonZeroUsize::new(3).unwrap());
+        assert_eq!(pm.backfill_fingerprints(&pool), Ok(5));
+        assert_eq!(
+            pm.reuse_groups(),
+            vec![vec!["a", "e", "home/d", "work/b", "work/c"]]
+        );
+    }
 }
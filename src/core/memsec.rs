@@ -0,0 +1,91 @@
+//! Keeps secrets in memory out of core dumps and away from debuggers run
+//! by the same user. It is turned on in the config file:
+//!
+//! ```text
+//! memsec = true
+//! ```
+//!
+//! At startup the process is then made non-dumpable (`PR_SET_DUMPABLE`),
+//! which stops core dumps and ptrace by other processes of the user, and
+//! the pages holding derived keys are excluded from dumps
+//! (`MADV_DONTDUMP`) should the process become dumpable again.
+
+use std::{
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use nix::{
+    sys::{
+        mman::{self, MmapAdvise},
+        prctl,
+    },
+    unistd::{self, SysconfVar},
+};
+use thiserror::Error;
+
+pub const SETTING: &str = "memsec";
+
+static HARDENED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MemsecError {
+    #[error("invalid value for `memsec`: `{0}`, expected true or false")]
+    InvalidValue(String),
+    #[error("cannot make the process non-dumpable: `{0}`")]
+    NotApplied(String),
+}
+
+/// Parses the value of the `memsec` setting.
+pub fn parse(value: &str) -> Result<bool, MemsecError> {
+    value
+        .parse()
+        .map_err(|_| MemsecError::InvalidValue(value.to_string()))
+}
+
+/// Makes the process non-dumpable, and `dont_dump` take effect from now on.
+pub fn harden() -> Result<(), MemsecError> {
+    prctl::set_dumpable(false).map_err(|err| MemsecError::NotApplied(err.to_string()))?;
+    HARDENED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn is_hardened() -> bool {
+    HARDENED.load(Ordering::Relaxed)
+}
+
+/// Excludes the pages holding `secret` from core dumps once hardened. The
+/// whole pages are excluded, with whatever else they hold.
+pub fn dont_dump(secret: &[u8]) {
+    if !is_hardened() || secret.is_empty() {
+        return;
+    }
+    let page = match unistd::sysconf(SysconfVar::PAGE_SIZE) {
+        Ok(Some(v)) => v as usize,
+        _ => return,
+    };
+    let start = secret.as_ptr() as usize & !(page - 1);
+    let end = secret.as_ptr() as usize + secret.len();
+    let Some(addr) = NonNull::new(start as *mut _) else {
+        return;
+    };
+    // SAFETY: the range covers the pages of a live allocation, and
+    // MADV_DONTDUMP only changes how they are dumped. Failing is harmless,
+    // the process is not dumpable anyway.
+    let _ = unsafe { mman::madvise(addr, end - start, MmapAdvise::MADV_DONTDUMP) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("true"), Ok(true));
+        assert_eq!(parse("false"), Ok(false));
+        assert_eq!(
+            parse("yes"),
+            Err(MemsecError::InvalidValue("yes".to_string()))
+        );
+    }
+}
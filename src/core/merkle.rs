@@ -0,0 +1,250 @@
+//! Merkle-style digest of a vault body, stored in the header from v0.10 on
+//! in place of the digest of the serialized body. Entries are spread over
+//! `BUCKETS` buckets by the first byte of their key hash and a binary tree
+//! is built over the buckets. Digests are kept between saves, so a save
+//! re-hashes the entries written since, their buckets and the path to the
+//! root only. The root also covers the fingerprint key and the tombstones,
+//! not the schema, which a binary ignoring it could not check; the
+//! encryption of the body authenticates it.
+//!
+//! The tree only saves hashing: a vault file is still encrypted as a whole
+//! on every save, a database only re-encrypts the rows that changed.
+
+use std::collections::HashMap;
+
+use super::{
+    encoder::Body,
+    entry::{self, Entries, KeyHash},
+    fingerprint::FingerprintKey,
+    hasher::{Hasher, DIGEST_LENGTH},
+};
+
+const BUCKETS: usize = 256;
+const NODES: usize = 2 * BUCKETS - 1;
+
+// Domain separation, so that a leaf cannot be passed off as a node.
+const LEAF: u8 = 0;
+const BUCKET: u8 = 1;
+const NODE: u8 = 2;
+const ROOT: u8 = 3;
+
+pub type Digest = [u8; DIGEST_LENGTH];
+
+/// The leaf digests of a body and the tree over them. A leaf is hashed
+/// again when `Entries` reports its key as written.
+#[derive(Debug, Default)]
+pub struct MerkleTree {
+    hasher_id: Option<u8>,
+    leaves: HashMap<String, Leaf>,
+    /// Heap-ordered, the buckets are the last `BUCKETS` nodes.
+    nodes: Vec<Digest>,
+}
+
+#[derive(Debug)]
+struct Leaf {
+    bucket: usize,
+    digest: Digest,
+}
+
+impl MerkleTree {
+    /// The root digest of a body, hashing with the hasher `hasher_id` names.
+    pub fn root(
+        &mut self,
+        hasher_id: u8,
+        hasher: &mut dyn Hasher,
+        kv: &mut Entries,
+        tombstones: &HashMap<KeyHash, u64>,
+        fingerprint_key: &FingerprintKey,
+    ) -> Digest {
+        let changed = kv.take_changed();
+        let mut dirty = vec![false; NODES];
+        if self.hasher_id != Some(hasher_id) || self.nodes.len() != NODES {
+            self.hasher_id = Some(hasher_id);
+            self.leaves.clear();
+            self.nodes = vec![[0; DIGEST_LENGTH]; NODES];
+            dirty.fill(true);
+        }
+
+        self.leaves.retain(|key, leaf| {
+            let keep = kv.contains_key(key);
+            dirty[BUCKETS - 1 + leaf.bucket] |= !keep;
+            keep
+        });
+        for (key, entry) in kv.iter() {
+            if self.leaves.contains_key(key) && !changed.contains(key) {
+                continue;
+            }
+            let mut bytes = vec![LEAF];
+            Body::write_entry(&mut bytes, key, entry);
+            let leaf = Leaf {
+                bucket: usize::from(entry::key_hash(key)[0]),
+                digest: digest(hasher, &bytes),
+            };
+            dirty[BUCKETS - 1 + leaf.bucket] = true;
+            self.leaves.insert(key.clone(), leaf);
+        }
+        let mut members: Vec<Vec<(&String, &Digest)>> = vec![Vec::new(); BUCKETS];
+        for (key, leaf) in &self.leaves {
+            if dirty[BUCKETS - 1 + leaf.bucket] {
+                members[leaf.bucket].push((key, &leaf.digest));
+            }
+        }
+        for (bucket, mut members) in members.into_iter().enumerate() {
+            let node = BUCKETS - 1 + bucket;
+            if dirty[node] {
+                members.sort_unstable_by_key(|(key, _)| *key);
+                let mut bytes = vec![BUCKET];
+                members.iter().for_each(|(_, v)| bytes.extend(*v));
+                self.nodes[node] = digest(hasher, &bytes);
+            }
+        }
+        for node in (0..BUCKETS - 1).rev() {
+            let (left, right) = (2 * node + 1, 2 * node + 2);
+            if dirty[left] || dirty[right] {
+                let mut bytes = vec![NODE];
+                bytes.extend(self.nodes[left]);
+                bytes.extend(self.nodes[right]);
+                self.nodes[node] = digest(hasher, &bytes);
+                dirty[node] = true;
+            }
+        }
+
+        let mut bytes = vec![ROOT];
+        bytes.extend(self.nodes[0]);
+        bytes.extend(fingerprint_key);
+        let mut tombstones: Vec<_> = tombstones.iter().collect();
+        tombstones.sort_unstable();
+        for (key_hash, deleted) in tombstones {
+            bytes.extend(key_hash);
+            bytes.extend(deleted.to_be_bytes());
+        }
+        digest(hasher, &bytes)
+    }
+}
+
+fn digest(hasher: &mut dyn Hasher, bytes: &[u8]) -> Digest {
+    hasher.hash(bytes)[..]
+        .try_into()
+        .expect("hashers yield DIGEST_LENGTH bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        entry::Entry,
+        hasher::{Sha256Hasher, Sha512_256Hasher},
+    };
+
+    use super::*;
+
+    /// Counts the bytes it hashes.
+    struct Counting(Sha256Hasher, usize);
+
+    impl Hasher for Counting {
+        fn hash(&mut self, data: &[u8]) -> Box<[u8]> {
+            self.1 += data.len();
+            self.0.hash(data)
+        }
+    }
+
+    fn body(n: usize) -> Entries {
+        (0..n)
+            .map(|i| {
+                (
+                    format!("key{}", i),
+                    Entry::new(vec![i as u8; 1024].into(), 1),
+                )
+            })
+            .collect::<HashMap<_, _>>()
+            .into()
+    }
+
+    #[test]
+    fn test_root_is_incremental() {
+        let mut kv = body(100);
+        let tombstones = HashMap::from([([5; 32], 10)]);
+        let mut hasher = Counting(Sha256Hasher::new(), 0);
+        let mut tree = MerkleTree::default();
+        let root = tree.root(0, &mut hasher, &mut kv, &tombstones, &[7; 32]);
+        assert!(hasher.1 > 100 * 1024);
+
+        hasher.1 = 0;
+        assert_eq!(
+            tree.root(0, &mut hasher, &mut kv, &tombstones, &[7; 32]),
+            root
+        );
+        assert!(hasher.1 < 1024);
+        kv.get_mut("key3").unwrap().modified = 2;
+        let changed = tree.root(0, &mut hasher, &mut kv, &tombstones, &[7; 32]);
+        assert_ne!(changed, root);
+        assert!(hasher.1 < 2 * 1024 + 2048);
+
+        let fresh = MerkleTree::default().root(
+            0,
+            &mut Sha256Hasher::new(),
+            &mut kv.clone(),
+            &tombstones,
+            &[7; 32],
+        );
+        assert_eq!(changed, fresh);
+    }
+
+    #[test]
+    fn test_root_covers_the_body() {
+        let kv = body(3);
+        let tombstones = HashMap::new();
+        let root = |kv: &Entries, tombstones: &HashMap<KeyHash, u64>, key: u8| {
+            MerkleTree::default().root(
+                0,
+                &mut Sha256Hasher::new(),
+                &mut kv.clone(),
+                tombstones,
+                &[key; 32],
+            )
+        };
+        let base = root(&kv, &tombstones, 7);
+
+        let mut removed = kv.clone();
+        removed.remove("key1");
+        let mut renamed = kv.clone();
+        let entry = renamed.remove("key1").unwrap();
+        renamed.insert("key9".to_string(), entry);
+        for other in [
+            root(&removed, &tombstones, 7),
+            root(&renamed, &tombstones, 7),
+            root(&kv, &HashMap::from([([1; 32], 1)]), 7),
+            root(&kv, &tombstones, 8),
+        ] {
+            assert_ne!(other, base);
+        }
+
+        let mut tree = MerkleTree::default();
+        tree.root(
+            0,
+            &mut Sha256Hasher::new(),
+            &mut kv.clone(),
+            &tombstones,
+            &[7; 32],
+        );
+        assert_ne!(
+            tree.root(
+                1,
+                &mut Sha512_256Hasher::new(),
+                &mut kv.clone(),
+                &tombstones,
+                &[7; 32]
+            ),
+            base
+        );
+        assert_eq!(
+            tree.root(
+                0,
+                &mut Sha256Hasher::new(),
+                &mut removed.clone(),
+                &tombstones,
+                &[7; 32]
+            ),
+            root(&removed, &tombstones, 7)
+        );
+    }
+}
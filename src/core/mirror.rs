@@ -0,0 +1,105 @@
+//! Read-only mirrors: vaults holding copies of some low-sensitivity entries
+//! of another vault, meant to be committed to a dotfiles repository and
+//! used as they are on other machines. A mirror carries the `MIRROR`
+//! capability and is never saved back; it only changes when it is
+//! published again from the vault it mirrors.
+
+use std::collections::BTreeMap;
+
+use zeroize::Zeroizing;
+
+use super::{
+    encoding::capability::Capabilities,
+    encryptor::Encryprtor,
+    entry,
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    pub fn is_mirror(&self) -> bool {
+        self.capabilities.contains(Capabilities::MIRROR)
+    }
+
+    /// Makes `mirror` hold exactly the entries under `keys`, with their
+    /// metadata. Entries it already holds as they are are left alone, so
+    /// an unchanged selection leaves the mirror unchanged; returns whether
+    /// anything changed.
+    pub fn publish_into<U: Encryprtor>(
+        &mut self,
+        keys: &[String],
+        mirror: &mut PasswordManager<U>,
+    ) -> Result<bool, PasswordManagerError> {
+        let mut changed = !mirror.is_mirror();
+        mirror.capabilities = mirror.capabilities.with(Capabilities::MIRROR);
+        mirror.adopt_schema(&self.schema());
+
+        let before = mirror.kv.len();
+        mirror.kv.retain(|key, _| keys.contains(key));
+        mirror.tombstones.clear();
+        changed |= mirror.kv.len() != before;
+
+        for key in keys {
+            let value = Zeroizing::new(self.get_password(key)?);
+            let meta: BTreeMap<String, String> = self.kv[key]
+                .meta
+                .iter()
+                .filter(|(name, _)| !entry::is_sealed_meta(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            let unchanged = mirror.kv.get(key).is_some_and(|v| v.meta == meta)
+                && mirror
+                    .decrypt_password(key)
+                    .is_ok_and(|v| *Zeroizing::new(v) == *value);
+            if unchanged {
+                continue;
+            }
+            mirror.store_password(key.clone(), &value)?;
+            mirror.kv.get_mut(key).expect("just stored").meta = meta;
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_publish_into() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"))
+            .with_master_key(b"master");
+        for key in ["public/wifi", "public/printer", "bank"] {
+            pm.store_password(key.to_string(), key).unwrap();
+        }
+        pm.set_meta("public/wifi", "username", "guest").unwrap();
+
+        let mut mirror = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("bar"))
+            .with_master_key(b"mirror");
+        assert!(!mirror.is_mirror());
+        let keys = pm.select(Some("public/*"), None);
+        assert!(pm.publish_into(&keys, &mut mirror).unwrap());
+        assert!(mirror.is_mirror());
+        assert_eq!(mirror.keys(), vec!["public/printer", "public/wifi"]);
+        assert_eq!(mirror.meta("public/wifi", "username"), Some("guest"));
+
+        let before = mirror.kv.clone();
+        assert!(!pm.publish_into(&keys, &mut mirror).unwrap());
+        assert_eq!(mirror.kv, before);
+
+        pm.store_password("public/printer".to_string(), "new")
+            .unwrap();
+        pm.delete("public/wifi").unwrap();
+        let keys = pm.select(Some("public/*"), None);
+        assert!(pm.publish_into(&keys, &mut mirror).unwrap());
+        assert_eq!(mirror.keys(), vec!["public/printer"]);
+        assert_eq!(mirror.get_password("public/printer"), Ok("new".to_string()));
+    }
+}
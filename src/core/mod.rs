@@ -1,6 +1,54 @@
+pub mod bug;
+pub mod catalog;
+pub mod clock;
+#[cfg(any(
+    feature = "browser",
+    feature = "crdt",
+    feature = "hashivault",
+    feature = "otpauth"
+))]
+pub mod conflict;
+#[cfg(feature = "crdt")]
+pub mod crdt;
+pub mod ct;
+pub mod diff;
+pub mod dynamic;
 pub mod encoder;
 pub mod encoding;
 pub mod encryptor;
+pub mod entry;
+pub mod executor;
+pub mod fingerprint;
+pub mod generator;
+pub mod golden;
 pub mod hasher;
+#[cfg(feature = "hidden-volume")]
+pub mod hidden;
+pub mod hint;
+pub mod history;
 pub mod identifiers;
+pub mod identity;
+pub mod kdf;
+pub mod keycache;
 pub mod manager;
+pub mod memsec;
+pub mod merkle;
+pub mod mirror;
+pub mod namespace;
+pub mod nonce;
+pub mod policy;
+pub mod pool;
+pub mod provenance;
+pub mod refactor;
+pub mod rng;
+#[cfg(feature = "sqlite")]
+pub mod rows;
+pub mod scan;
+pub mod schema;
+pub mod session;
+pub mod site;
+pub mod strength;
+pub mod subset;
+pub mod timelock;
+pub mod trace;
+pub mod verify;
@@ -0,0 +1,355 @@
+//! Per-namespace encryption keys. The value of an entry under a top-level
+//! namespace, e.g. `work/db`, is encrypted with a subkey derived from the
+//! master key with HKDF, so the key of `work` cannot decrypt `personal/*`.
+//! Entries outside any namespace keep using the vault encryptor.
+//!
+//! In a vault with the `BOUND_VALUES` capability, values are also bound to
+//! their key and the vault id as associated data, so that a value copied
+//! into another entry or another vault no longer decrypts.
+
+use std::collections::HashMap;
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use super::{
+    encoding::capability::Capabilities,
+    encryptor::{AESEncryptor, Encryprtor, EncryprtorError},
+    identifiers::{self, Identifiable},
+    identity::VaultId,
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+pub const NAMESPACE_KEY_LENGTH: usize = 32;
+
+const SEPARATOR: char = '/';
+const INFO_PREFIX: &[u8] = b"mopm-namespace:";
+
+pub type NamespaceKey = [u8; NAMESPACE_KEY_LENGTH];
+
+/// The top-level namespace of `key`, if it has one.
+pub fn namespace(key: &str) -> Option<&str> {
+    key.split_once(SEPARATOR)
+        .map(|(namespace, _)| namespace)
+        .filter(|v| !v.is_empty())
+}
+
+/// HKDF-SHA256 of the master key, salted with the vault id so equal master
+/// keys of different vaults give different subkeys.
+pub fn derive_key(master_key: &[u8], vault_id: &VaultId, namespace: &str) -> NamespaceKey {
+    let mut key = [0; NAMESPACE_KEY_LENGTH];
+    Hkdf::<Sha256>::new(Some(vault_id), master_key)
+        .expand_multi_info(&[INFO_PREFIX, namespace.as_bytes()], &mut key)
+        .expect("the key is shorter than 255 hash lengths");
+    key
+}
+
+/// The associated data of the value under `key`, like the rows of a
+/// database: the vault id, then the key.
+fn value_aad(vault_id: &VaultId, key: &str) -> Vec<u8> {
+    let mut aad = vault_id.to_vec();
+    aad.extend(key.as_bytes());
+    aad
+}
+
+/// The master key and the encryptors of the namespaces used so far.
+#[derive(Default)]
+pub struct Keyring {
+    master_key: Option<Box<[u8]>>,
+    encryptors: HashMap<String, AESEncryptor>,
+}
+
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyring")
+            .field("namespaces", &self.encryptors.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Keyring {
+    pub(in crate::core) fn master_key(&self) -> Option<&[u8]> {
+        self.master_key.as_deref()
+    }
+
+    /// Subkeys are salted with the vault id and must be derived again when
+    /// it changes.
+    pub(in crate::core) fn forget_subkeys(&mut self) {
+        self.encryptors.clear();
+    }
+}
+
+/// What it takes to decrypt the values of a vault away from it, e.g. on a
+/// worker thread. Each `Decryptor` made from it has ciphers of its own.
+pub struct VaultKeys {
+    encryptor_id: u8,
+    master_key: Box<[u8]>,
+    vault_id: VaultId,
+    bound_values: bool,
+}
+
+impl VaultKeys {
+    pub fn decryptor(&self) -> Decryptor<'_> {
+        Decryptor {
+            keys: self,
+            vault: identifiers::encryptor_from_id(self.encryptor_id, &self.master_key)
+                .expect("the encryptor of an opened vault is supported"),
+            namespaces: HashMap::new(),
+        }
+    }
+}
+
+pub struct Decryptor<'a> {
+    keys: &'a VaultKeys,
+    vault: Box<dyn Encryprtor + Send>,
+    namespaces: HashMap<String, AESEncryptor>,
+}
+
+impl Decryptor<'_> {
+    /// Decrypts the value of the entry under `key`, like the vault would.
+    pub fn decrypt(&mut self, key: &str, value: &[u8]) -> Result<Box<[u8]>, EncryprtorError> {
+        let keys = self.keys;
+        let aad = match keys.bound_values {
+            true => value_aad(&keys.vault_id, key),
+            false => Vec::new(),
+        };
+        if let Some(namespace) = namespace(key) {
+            return self
+                .namespaces
+                .entry(namespace.to_string())
+                .or_insert_with(|| {
+                    AESEncryptor::new(derive_key(&keys.master_key, &keys.vault_id, namespace))
+                })
+                .decrypt(value, &aad);
+        }
+        self.vault.decrypt(value, &aad)
+    }
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor + Identifiable,
+{
+    /// The keys of the vault, `None` until its master key is known.
+    pub fn vault_keys(&self) -> Option<VaultKeys> {
+        Some(VaultKeys {
+            encryptor_id: self.encryptor.id(),
+            master_key: self.keyring.master_key.clone()?,
+            vault_id: self.vault_id,
+            bound_values: self.has_bound_values(),
+        })
+    }
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// `master_key` is the key the vault encryptor was created with. Until
+    /// it is known, namespaced entries are encrypted like any other.
+    pub fn with_master_key(mut self, master_key: &[u8]) -> Self {
+        self.set_master_key(master_key);
+        self
+    }
+
+    pub(in crate::core) fn set_master_key(&mut self, master_key: &[u8]) {
+        self.keyring = Keyring {
+            master_key: Some(master_key.into()),
+            encryptors: HashMap::new(),
+        };
+    }
+
+    /// Encrypts the value of the entry under `key` with the key of its
+    /// namespace.
+    pub(in crate::core) fn encrypt_value(
+        &mut self,
+        key: &str,
+        value: &[u8],
+    ) -> Result<Box<[u8]>, EncryprtorError> {
+        let aad = self.value_aad(key);
+        match self.namespace_encryptor(key) {
+            Some(encryptor) => encryptor.encrypt(value, &aad),
+            None => self.encryptor.encrypt(value, &aad),
+        }
+    }
+
+    pub(in crate::core) fn decrypt_value(
+        &mut self,
+        key: &str,
+        value: &[u8],
+    ) -> Result<Box<[u8]>, EncryprtorError> {
+        let aad = self.value_aad(key);
+        match self.namespace_encryptor(key) {
+            Some(encryptor) => encryptor.decrypt(value, &aad),
+            None => self.encryptor.decrypt(value, &aad),
+        }
+    }
+
+    pub fn has_bound_values(&self) -> bool {
+        self.capabilities.contains(Capabilities::BOUND_VALUES)
+    }
+
+    /// Binds the values to their key and the vault, or unbinds them, by
+    /// encrypting them again with their previous values. Cached dynamic
+    /// values are dropped rather than re-encrypted.
+    pub fn bind_values(&mut self, bound: bool) -> Result<(), PasswordManagerError> {
+        if bound == self.has_bound_values() {
+            return Ok(());
+        }
+        self.drop_dynamic_caches();
+        let histories = self.take_histories()?;
+        let encrypted: Vec<(String, Box<[u8]>)> = self
+            .kv
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect();
+        let mut values = Vec::with_capacity(encrypted.len());
+        for (key, value) in encrypted {
+            let value = self.decrypt_value(&key, &value)?;
+            values.push((key, value));
+        }
+        self.capabilities = match bound {
+            true => self.capabilities.with(Capabilities::BOUND_VALUES),
+            false => self.capabilities.without(Capabilities::BOUND_VALUES),
+        };
+        for (key, value) in values {
+            let value = self.encrypt_value(&key, &value)?;
+            self.kv
+                .get_mut(&key)
+                .expect("the key was just listed")
+                .value = value;
+        }
+        self.restore_histories(histories)
+    }
+
+    fn value_aad(&self, key: &str) -> Vec<u8> {
+        match self.has_bound_values() {
+            true => value_aad(&self.vault_id, key),
+            false => Vec::new(),
+        }
+    }
+
+    /// Re-encrypts namespaced values of a vault written before namespaces
+    /// had their own keys, and returns how many were moved.
+    pub(in crate::core) fn migrate_namespaces(&mut self) -> Result<usize, PasswordManagerError> {
+        if self.keyring.master_key.is_none() {
+            return Ok(0);
+        }
+        self.drop_dynamic_caches();
+        let keys: Vec<String> = self
+            .kv
+            .keys()
+            .filter(|key| namespace(key).is_some())
+            .cloned()
+            .collect();
+        for key in &keys {
+            let aad = self.value_aad(key);
+            let value = self.encryptor.decrypt(&self.kv[key].value, &aad)?;
+            let value = self.encrypt_value(key, &value)?;
+            self.kv.get_mut(key).expect("the key was just listed").value = value;
+        }
+        Ok(keys.len())
+    }
+
+    fn namespace_encryptor(&mut self, key: &str) -> Option<&mut AESEncryptor> {
+        let namespace = namespace(key)?;
+        let master_key = self.keyring.master_key.as_deref()?;
+        if !self.keyring.encryptors.contains_key(namespace) {
+            let encryptor = AESEncryptor::new(derive_key(master_key, &self.vault_id, namespace));
+            self.keyring
+                .encryptors
+                .insert(namespace.to_string(), encryptor);
+        }
+        self.keyring.encryptors.get_mut(namespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault() -> PasswordManager<AESEncryptor> {
+        PasswordManager::init("pw", crate::core::kdf::Kdf::Raw).unwrap()
+    }
+
+    #[test]
+    fn test_namespace() {
+        assert_eq!(namespace("work/db"), Some("work"));
+        assert_eq!(namespace("work/db/user"), Some("work"));
+        assert_eq!(namespace("db"), None);
+        assert_eq!(namespace("/db"), None);
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let work = derive_key(b"master", &[1; 16], "work");
+        assert_ne!(work, derive_key(b"master", &[1; 16], "personal"));
+        assert_ne!(work, derive_key(b"master", &[2; 16], "work"));
+        assert_ne!(work, derive_key(b"other", &[1; 16], "work"));
+        assert_eq!(work, derive_key(b"master", &[1; 16], "work"));
+    }
+
+    #[test]
+    fn test_namespace_keys() {
+        let mut pm = vault();
+        pm.store_password("work/db".to_string(), "a").unwrap();
+        pm.store_password("personal/mail".to_string(), "b").unwrap();
+        pm.store_password("plain".to_string(), "c").unwrap();
+
+        let work_key = derive_key(b"pw", pm.vault_id(), "work");
+        let mut work = AESEncryptor::new(work_key);
+        let vault_id = *pm.vault_id();
+        let aad = |key| value_aad(&vault_id, key);
+        assert!(work
+            .decrypt(&pm.kv["work/db"].value, &aad("work/db"))
+            .is_ok());
+        assert!(work
+            .decrypt(&pm.kv["personal/mail"].value, &aad("personal/mail"))
+            .is_err());
+        assert!(pm
+            .encryptor
+            .decrypt(&pm.kv["work/db"].value, &aad("work/db"))
+            .is_err());
+        assert!(pm
+            .encryptor
+            .decrypt(&pm.kv["plain"].value, &aad("plain"))
+            .is_ok());
+        assert_eq!(pm.get_password("personal/mail"), Ok("b".to_string()));
+    }
+
+    #[test]
+    fn test_bound_values() {
+        let mut pm = vault();
+        assert!(pm.has_bound_values());
+        pm.store_password("work/db".to_string(), "a").unwrap();
+        pm.store_password("work/mail".to_string(), "b").unwrap();
+        pm.store_password("plain".to_string(), "c").unwrap();
+
+        let moved = pm.kv["work/db"].value.clone();
+        assert!(pm.decrypt_value("work/mail", &moved).is_err());
+        assert!(pm.decrypt_value("work/db", &moved).is_ok());
+        let keys = pm.vault_keys().unwrap();
+        assert!(keys.decryptor().decrypt("work/db", &moved).is_ok());
+        assert!(keys.decryptor().decrypt("work/mail", &moved).is_err());
+
+        pm.bind_values(false).unwrap();
+        assert!(!pm.has_bound_values());
+        assert!(pm.encryptor.decrypt(&pm.kv["plain"].value, &[]).is_ok());
+        assert_eq!(pm.get_password("work/db"), Ok("a".to_string()));
+        pm.bind_values(true).unwrap();
+        assert!(pm.encryptor.decrypt(&pm.kv["plain"].value, &[]).is_err());
+        assert_eq!(pm.get_password("plain"), Ok("c".to_string()));
+    }
+
+    #[test]
+    fn test_migrate_namespaces() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("pw"));
+        pm.store_password("work/db".to_string(), "a").unwrap();
+        pm.store_password("plain".to_string(), "b").unwrap();
+        let mut pm = pm.with_master_key(b"pw");
+        assert_eq!(pm.migrate_namespaces(), Ok(1));
+        assert!(pm.encryptor.decrypt(&pm.kv["work/db"].value, &[]).is_err());
+        assert_eq!(pm.get_password("work/db"), Ok("a".to_string()));
+        assert_eq!(pm.get_password("plain"), Ok("b".to_string()));
+    }
+}
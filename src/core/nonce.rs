@@ -0,0 +1,74 @@
+//! Random AES-GCM nonces that one generator never hands out twice. What it
+//! remembers is local to the process, and to the encryptor it belongs to:
+//! nonces written by another process or device are only known once they
+//! are read back from the vault and observed, and before that uniqueness
+//! rests on 96 random bits, as it does for any random-nonce AES-GCM.
+
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use super::rng;
+
+pub const NONCE_LENGTH: usize = 12;
+
+const MAX_COLLISION_RETRIES: usize = 4;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum NonceError {
+    #[error("the random number generator is unavailable: `{0}`")]
+    RngUnavailable(String),
+    #[error("could not generate a unique nonce after `{0}` collisions")]
+    NonceCollision(usize),
+}
+
+/// Issues random AES-GCM nonces and keeps track of every nonce it has
+/// issued or observed, so the same nonce is never handed out twice.
+#[derive(Debug, Default)]
+pub struct NonceGenerator {
+    seen: HashSet<[u8; NONCE_LENGTH]>,
+}
+
+impl NonceGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn generate(&mut self) -> Result<[u8; NONCE_LENGTH], NonceError> {
+        for _ in 0..=MAX_COLLISION_RETRIES {
+            let mut nonce = [0; NONCE_LENGTH];
+            rng::try_fill(&mut nonce).map_err(|err| NonceError::RngUnavailable(err.to_string()))?;
+
+            if self.seen.insert(nonce) {
+                return Ok(nonce);
+            }
+        }
+        Err(NonceError::NonceCollision(MAX_COLLISION_RETRIES + 1))
+    }
+
+    /// Records a nonce found in existing ciphertext so it is never reissued.
+    pub fn observe(&mut self, nonce: [u8; NONCE_LENGTH]) {
+        self.seen.insert(nonce);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_unique() {
+        let mut nonces = NonceGenerator::new();
+        let a = nonces.generate().unwrap();
+        let b = nonces.generate().unwrap();
+        assert_ne!(a, b);
+        assert_eq!(nonces.seen.len(), 2);
+    }
+
+    #[test]
+    fn test_observe() {
+        let mut nonces = NonceGenerator::new();
+        nonces.observe([7; NONCE_LENGTH]);
+        assert!(!nonces.seen.insert([7; NONCE_LENGTH]));
+    }
+}
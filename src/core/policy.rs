@@ -0,0 +1,273 @@
+//! Access policies for high-value entries. An entry can require a y/N
+//! confirmation and/or the master password to be entered again before it
+//! is decrypted. The checks happen in `PasswordManager` itself, through
+//! the `AccessGuard` the frontend installed, so no frontend can skip them.
+
+use std::fmt;
+
+use thiserror::Error;
+
+use super::{
+    ct,
+    encryptor::{AESEncryptor, Encryprtor},
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+pub const POLICY_FIELD: &str = "policy";
+
+const CONFIRM: &str = "confirm";
+const REAUTH: &str = "reauth";
+const NONE: &str = "none";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PolicyError {
+    #[error("unknown access policy `{0}`, expected a list of confirm, reauth or none")]
+    UnknownPolicy(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessPolicy {
+    pub confirm: bool,
+    pub reauth: bool,
+}
+
+impl AccessPolicy {
+    /// Parses a comma-separated list such as "confirm,reauth" or "none".
+    pub fn parse(value: &str) -> Result<Self, PolicyError> {
+        let mut policy = Self::default();
+        for part in value.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            match part {
+                CONFIRM => policy.confirm = true,
+                REAUTH => policy.reauth = true,
+                NONE => {}
+                _ => return Err(PolicyError::UnknownPolicy(part.to_string())),
+            }
+        }
+        Ok(policy)
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.confirm && !self.reauth
+    }
+}
+
+impl fmt::Display for AccessPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<&str> = [(self.confirm, CONFIRM), (self.reauth, REAUTH)]
+            .into_iter()
+            .filter_map(|(enabled, name)| enabled.then_some(name))
+            .collect();
+        match parts.is_empty() {
+            true => f.write_str(NONE),
+            false => f.write_str(&parts.join(",")),
+        }
+    }
+}
+
+/// Asks the person using a frontend to approve reading a guarded entry.
+pub trait AccessGuard: fmt::Debug + Send {
+    /// Whether `key` may be revealed.
+    fn confirm(&mut self, key: &str) -> bool;
+
+    /// The master password entered again, if the user provided it.
+    fn reauthenticate(&mut self, key: &str) -> Option<String>;
+}
+
+/// The guard of frontends with nobody to ask, e.g. mounted files. Guarded
+/// entries cannot be read through it.
+#[derive(Debug, Default)]
+pub struct Unattended;
+
+impl AccessGuard for Unattended {
+    fn confirm(&mut self, _key: &str) -> bool {
+        false
+    }
+
+    fn reauthenticate(&mut self, _key: &str) -> Option<String> {
+        None
+    }
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    pub fn with_guard(mut self, guard: Box<dyn AccessGuard>) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    pub fn policy(&self, key: &str) -> AccessPolicy {
+        self.kv
+            .get(key)
+            .and_then(|entry| entry.meta(POLICY_FIELD))
+            .and_then(|v| AccessPolicy::parse(v).ok())
+            .unwrap_or_default()
+    }
+
+    /// Changing a policy is guarded by the current one, so a policy cannot
+    /// be lifted without satisfying it.
+    pub fn set_policy(
+        &mut self,
+        key: &str,
+        policy: AccessPolicy,
+    ) -> Result<(), PasswordManagerError> {
+        self.authorize(key)?;
+        match policy.is_open() {
+            true => self.remove_meta(key, POLICY_FIELD),
+            false => self.set_meta(key, POLICY_FIELD, &policy.to_string()),
+        }
+    }
+
+    /// Checks the time lock and the policy of `key` with the installed
+    /// guard. The re-entered password must derive the master key; before
+    /// that is known, it must decrypt the entry as an AES key.
+    pub(in crate::core) fn authorize(&mut self, key: &str) -> Result<(), PasswordManagerError> {
+        self.check_time_lock(key)?;
+        let policy = self.policy(key);
+        if policy.confirm && !self.guard.confirm(key) {
+            return Err(PasswordManagerError::AccessDenied);
+        }
+        if !policy.reauth {
+            return Ok(());
+        }
+        let password = self
+            .guard
+            .reauthenticate(key)
+            .ok_or(PasswordManagerError::AccessDenied)?;
+        let candidate = self.kdf.derive(password.as_bytes())?;
+        let matches = match self.keyring.master_key() {
+            Some(master_key) => ct::eq(&candidate, master_key),
+            None => AESEncryptor::new(&candidate)
+                .decrypt(&self.kv[key].value, &[])
+                .is_ok(),
+        };
+        if !matches {
+            ct::reject();
+            return Err(PasswordManagerError::AccessDenied);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Scripted {
+        confirm: bool,
+        password: Option<&'static str>,
+    }
+
+    impl AccessGuard for Scripted {
+        fn confirm(&mut self, _key: &str) -> bool {
+            self.confirm
+        }
+
+        fn reauthenticate(&mut self, _key: &str) -> Option<String> {
+            self.password.map(str::to_string)
+        }
+    }
+
+    fn guarded(policy: &str, guard: Scripted) -> PasswordManager<AESEncryptor> {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("pw"));
+        pm.store_password("key".to_string(), "secret").unwrap();
+        pm.set_policy("key", AccessPolicy::parse(policy).unwrap())
+            .unwrap();
+        pm.with_guard(Box::new(guard))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(AccessPolicy::parse("none"), Ok(AccessPolicy::default()));
+        let both = AccessPolicy::parse("reauth, confirm").unwrap();
+        assert!(both.confirm && both.reauth);
+        assert_eq!(both.to_string(), "confirm,reauth");
+        assert_eq!(AccessPolicy::default().to_string(), "none");
+        assert!(AccessPolicy::parse("maybe").is_err());
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut pm = guarded(
+            "confirm",
+            Scripted {
+                confirm: true,
+                password: None,
+            },
+        );
+        assert_eq!(pm.get_password("key"), Ok("secret".to_string()));
+
+        let mut pm = guarded(
+            "confirm",
+            Scripted {
+                confirm: false,
+                password: None,
+            },
+        );
+        assert_eq!(
+            pm.get_password("key"),
+            Err(PasswordManagerError::AccessDenied)
+        );
+        assert_eq!(
+            pm.set_policy("key", AccessPolicy::default()),
+            Err(PasswordManagerError::AccessDenied)
+        );
+    }
+
+    #[test]
+    fn test_reauth() {
+        let mut pm = guarded(
+            "reauth",
+            Scripted {
+                confirm: false,
+                password: Some("pw"),
+            },
+        );
+        assert_eq!(pm.get_password("key"), Ok("secret".to_string()));
+
+        let mut pm = guarded(
+            "reauth",
+            Scripted {
+                confirm: true,
+                password: Some("wrong"),
+            },
+        );
+        assert_eq!(
+            pm.get_password("key"),
+            Err(PasswordManagerError::AccessDenied)
+        );
+
+        let mut pm = guarded(
+            "reauth",
+            Scripted {
+                confirm: true,
+                password: None,
+            },
+        );
+        assert_eq!(
+            pm.get_password("key"),
+            Err(PasswordManagerError::AccessDenied)
+        );
+    }
+
+    #[test]
+    fn test_unattended() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("pw"));
+        pm.store_password("open".to_string(), "a").unwrap();
+        pm.store_password("key".to_string(), "b").unwrap();
+        pm.set_policy("key", AccessPolicy::parse("confirm").unwrap())
+            .unwrap();
+        assert_eq!(pm.get_password("open"), Ok("a".to_string()));
+        assert_eq!(
+            pm.get_password("key"),
+            Err(PasswordManagerError::AccessDenied)
+        );
+
+        pm.store_password("key".to_string(), "c").unwrap();
+        assert_eq!(pm.policy("key"), AccessPolicy::parse("confirm").unwrap());
+    }
+}
@@ -0,0 +1,102 @@
+//! A bounded pool of scoped worker threads for work that is independent per
+//! item, like decrypting every entry of an audit or fetching every secret
+//! of an import. Results come back in the order of the items.
+
+use std::{
+    num::NonZeroUsize,
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pool {
+    jobs: NonZeroUsize,
+}
+
+impl Default for Pool {
+    /// As many workers as the machine runs threads in parallel.
+    fn default() -> Self {
+        Self {
+            jobs: thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+        }
+    }
+}
+
+impl Pool {
+    pub fn new(jobs: NonZeroUsize) -> Self {
+        Self { jobs }
+    }
+
+    /// Maps `items` with `f` on up to `jobs` threads. Every thread starts
+    /// from its own `state`, so nothing mutable is shared between them.
+    pub fn map<I, O, S>(
+        &self,
+        items: Vec<I>,
+        state: impl Fn() -> S + Sync,
+        f: impl Fn(&mut S, I) -> O + Sync,
+    ) -> Vec<O>
+    where
+        I: Send,
+        O: Send,
+    {
+        let workers = self.jobs.get().min(items.len());
+        if workers <= 1 {
+            let mut state = state();
+            return items.into_iter().map(|item| f(&mut state, item)).collect();
+        }
+
+        let count = items.len();
+        let queue = Mutex::new(items.into_iter().enumerate());
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let sender = sender.clone();
+                let (queue, state, f) = (&queue, &state, &f);
+                scope.spawn(move || {
+                    let mut state = state();
+                    loop {
+                        let next = queue.lock().expect("no worker panics holding it").next();
+                        let Some((index, item)) = next else {
+                            break;
+                        };
+                        let _ = sender.send((index, f(&mut state, item)));
+                    }
+                });
+            }
+        });
+        drop(sender);
+
+        let mut results: Vec<Option<O>> = (0..count).map(|_| None).collect();
+        for (index, output) in receiver {
+            results[index] = Some(output);
+        }
+        results
+            .into_iter()
+            .map(|v| v.expect("every item is mapped"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_map_keeps_order() {
+        let pool = Pool::new(NonZeroUsize::new(4).unwrap());
+        let states = AtomicUsize::new(0);
+        let results = pool.map(
+            (0..100).collect(),
+            || states.fetch_add(1, Ordering::Relaxed),
+            |_, item: u64| item * item,
+        );
+        assert_eq!(results, (0..100).map(|v| v * v).collect::<Vec<_>>());
+        assert!(states.load(Ordering::Relaxed) <= 4);
+
+        let pool = Pool::new(NonZeroUsize::MIN);
+        assert_eq!(pool.map(vec![1, 2], || 0, |_, v| v + 1), vec![2, 3]);
+        assert!(pool.map(Vec::<u8>::new(), || (), |_, v| v).is_empty());
+    }
+}
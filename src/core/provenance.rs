@@ -0,0 +1,128 @@
+//! Where an imported entry came from, kept in its metadata: the tool it
+//! was imported from, the folder it was in there and when it was created
+//! and last changed there, as unix seconds. `list --fields` shows them.
+//!
+//! The value of an imported entry is as old as it was in that tool, not as
+//! the import, which `audit --summary` takes into account. Replacing the
+//! value drops `imported_modified`, so the entries still holding the
+//! imported value are those that have it.
+
+#[cfg(any(feature = "browser", feature = "hashivault", feature = "otpauth"))]
+use super::manager::PasswordManagerError;
+use super::{encryptor::Encryprtor, manager::PasswordManager};
+
+pub const SOURCE_FIELD: &str = "imported_from";
+pub const FOLDER_FIELD: &str = "imported_folder";
+pub const CREATED_FIELD: &str = "imported_created";
+pub const MODIFIED_FIELD: &str = "imported_modified";
+/// Kept when the value is replaced, they describe the entry rather than
+/// the value.
+pub const KEPT_FIELDS: [&str; 3] = [SOURCE_FIELD, FOLDER_FIELD, CREATED_FIELD];
+
+#[cfg(any(feature = "browser", feature = "hashivault", feature = "otpauth"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    pub source: &'static str,
+    pub folder: Option<String>,
+    pub created: Option<u64>,
+    pub modified: Option<u64>,
+}
+
+#[cfg(any(feature = "browser", feature = "hashivault", feature = "otpauth"))]
+impl Provenance {
+    pub fn new(source: &'static str) -> Self {
+        Self {
+            source,
+            ..Self::default()
+        }
+    }
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Records that the entry under `key` was just imported as described
+    /// by `provenance`. Without a modified time from the tool, the value
+    /// dates from the import.
+    #[cfg(any(feature = "browser", feature = "hashivault", feature = "otpauth"))]
+    pub fn set_provenance(
+        &mut self,
+        key: &str,
+        provenance: &Provenance,
+    ) -> Result<(), PasswordManagerError> {
+        let imported = self
+            .kv
+            .get(key)
+            .ok_or(PasswordManagerError::NoPasswordFound)?
+            .modified;
+        let modified = provenance.modified.unwrap_or(imported);
+        self.set_meta(key, SOURCE_FIELD, provenance.source)?;
+        if let Some(folder) = provenance.folder.as_deref().filter(|v| !v.is_empty()) {
+            self.set_meta(key, FOLDER_FIELD, folder)?;
+        }
+        if let Some(created) = provenance.created {
+            self.set_meta(key, CREATED_FIELD, &created.to_string())?;
+        }
+        self.set_meta(key, MODIFIED_FIELD, &modified.to_string())
+    }
+
+    /// When the value under `key` was set: in the tool it was imported
+    /// from while it is still the imported one, else in the vault.
+    pub fn value_modified(&self, key: &str) -> Option<u64> {
+        let entry = self.kv.get(key)?;
+        Some(
+            entry
+                .meta(MODIFIED_FIELD)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(entry.modified),
+        )
+    }
+
+    /// Whether the entry under `key` still holds the value it was
+    /// imported with.
+    pub fn is_unrotated_import(&self, key: &str) -> bool {
+        self.meta(key, MODIFIED_FIELD).is_some()
+    }
+}
+
+#[cfg(all(
+    test,
+    any(feature = "browser", feature = "hashivault", feature = "otpauth")
+))]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_provenance() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        pm.store_password("old".to_string(), "x").unwrap();
+        pm.store_password("new".to_string(), "y").unwrap();
+        let provenance = Provenance {
+            folder: Some("work".to_string()),
+            created: Some(1_000),
+            modified: Some(2_000),
+            ..Provenance::new("firefox")
+        };
+        pm.set_provenance("old", &provenance).unwrap();
+        pm.set_provenance("new", &Provenance::new("otpauth"))
+            .unwrap();
+
+        assert_eq!(pm.meta("old", SOURCE_FIELD), Some("firefox"));
+        assert_eq!(pm.meta("old", FOLDER_FIELD), Some("work"));
+        assert_eq!(pm.value_modified("old"), Some(2_000));
+        assert!(pm.is_unrotated_import("old"));
+        assert_eq!(pm.meta("new", CREATED_FIELD), None);
+        assert!(pm.value_modified("new").unwrap() > 2_000);
+
+        pm.store_password("old".to_string(), "rotated").unwrap();
+        assert!(!pm.is_unrotated_import("old"));
+        assert_eq!(pm.meta("old", SOURCE_FIELD), Some("firefox"));
+        assert_eq!(pm.meta("old", CREATED_FIELD), Some("1000"));
+        assert!(pm.value_modified("old").unwrap() > 2_000);
+    }
+}
@@ -0,0 +1,212 @@
+//! Bulk changes to the names and tags of many entries: moving every key
+//! under a prefix to another prefix, and renaming or removing a tag. A
+//! change is planned and checked in full before any entry is touched, then
+//! applied to the vault in memory, so it is saved with a single write or
+//! not at all.
+
+use thiserror::Error;
+
+use super::{
+    clock, dynamic,
+    encryptor::Encryprtor,
+    entry::{self, TAGS_FIELD},
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RefactorError {
+    #[error("`{0}` would be renamed to `{1}`, which already exists")]
+    Collision(String, String),
+    #[error("`{0}` would be renamed to an empty or too long key")]
+    InvalidKey(String),
+    #[error("tags cannot be empty or contain commas: `{0}`")]
+    InvalidTag(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    Rename {
+        from: String,
+        to: String,
+    },
+    /// The tags of `key` before and after, `None` once the last is removed.
+    Retag {
+        key: String,
+        before: String,
+        after: Option<String>,
+    },
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Renames every key starting with `from` to start with `to` instead.
+    pub fn plan_rename_prefix(&self, from: &str, to: &str) -> Result<Vec<Change>, RefactorError> {
+        let mut changes = Vec::new();
+        for key in self.keys().into_iter().filter(|key| key.starts_with(from)) {
+            let renamed = format!("{}{}", to, &key[from.len()..]);
+            if renamed.is_empty() || !entry::fits(&renamed, &[]) {
+                return Err(RefactorError::InvalidKey(key.to_string()));
+            }
+            // Keys that are renamed themselves make room for the others.
+            if self.kv.contains_key(&renamed) && !renamed.starts_with(from) {
+                return Err(RefactorError::Collision(key.to_string(), renamed));
+            }
+            changes.push(Change::Rename {
+                from: key.to_string(),
+                to: renamed,
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Replaces the tag `from` with `to` on every entry carrying it, or
+    /// removes it if `to` is `None`.
+    pub fn plan_retag(&self, from: &str, to: Option<&str>) -> Result<Vec<Change>, RefactorError> {
+        for tag in [Some(from), to].into_iter().flatten() {
+            if tag.trim().is_empty() || tag.contains(',') {
+                return Err(RefactorError::InvalidTag(tag.to_string()));
+            }
+        }
+        let (from, to) = (from.trim(), to.map(str::trim));
+        let mut changes = Vec::new();
+        for (key, entry) in self.entries("") {
+            let Some(before) = entry.meta(TAGS_FIELD) else {
+                continue;
+            };
+            let tags: Vec<&str> = before
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .collect();
+            if !tags.contains(&from) {
+                continue;
+            }
+            let mut after: Vec<&str> = Vec::new();
+            for tag in tags {
+                let tag = if tag == from { to } else { Some(tag) };
+                if let Some(tag) = tag.filter(|v| !after.contains(v)) {
+                    after.push(tag);
+                }
+            }
+            changes.push(Change::Retag {
+                key: key.to_string(),
+                before: before.to_string(),
+                after: (!after.is_empty()).then(|| after.join(", ")),
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Applies a plan. Renamed entries keep their metadata and leave a
+    /// tombstone behind, their values are encrypted again for the
+    /// namespace they move to.
+    pub fn apply(&mut self, changes: &[Change]) -> Result<(), PasswordManagerError> {
+        let mut moved = Vec::new();
+        for change in changes {
+            let Change::Rename { from, to } = change else {
+                continue;
+            };
+            let mut entry = self
+                .kv
+                .remove(from)
+                .ok_or(PasswordManagerError::NoPasswordFound)?;
+            let value = self.decrypt_value(from, &entry.value)?;
+            entry.value = self.encrypt_value(to, &value)?;
+            entry.meta.retain(|name, _| !dynamic::is_cache_meta(name));
+            self.move_history(&mut entry, from, to)?;
+            entry.modified = clock::after(entry.modified);
+            self.tombstones
+                .insert(entry::key_hash(from), entry.modified);
+            moved.push((to.clone(), entry));
+        }
+        for (key, entry) in moved {
+            self.tombstones.remove(&entry::key_hash(&key));
+            self.kv.insert(key, entry);
+        }
+
+        for change in changes {
+            if let Change::Retag { key, after, .. } = change {
+                match after {
+                    Some(tags) => self.set_meta(key, TAGS_FIELD, tags)?,
+                    None => self.remove_meta(key, TAGS_FIELD)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    fn manager(keys: &[&str]) -> PasswordManager<AESEncryptor> {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"))
+            .with_master_key(b"master");
+        for key in keys {
+            pm.store_password(key.to_string(), key).unwrap();
+        }
+        pm
+    }
+
+    #[test]
+    fn test_rename_prefix() {
+        let mut pm = manager(&["work/db", "work/mail", "home/wifi"]);
+        pm.set_meta("work/db", "username", "admin").unwrap();
+        let changes = pm.plan_rename_prefix("work/", "acme/").unwrap();
+        assert_eq!(changes.len(), 2);
+        pm.apply(&changes).unwrap();
+
+        assert_eq!(pm.keys(), vec!["acme/db", "acme/mail", "home/wifi"]);
+        assert_eq!(pm.get_password("acme/db"), Ok("work/db".to_string()));
+        assert_eq!(pm.meta("acme/db", "username"), Some("admin"));
+        assert!(pm.tombstones.contains_key(&entry::key_hash("work/db")));
+    }
+
+    #[test]
+    fn test_rename_prefix_refuses_collisions() {
+        let pm = manager(&["old/a", "new/a", "x"]);
+        assert_eq!(
+            pm.plan_rename_prefix("old/", "new/"),
+            Err(RefactorError::Collision(
+                "old/a".to_string(),
+                "new/a".to_string()
+            ))
+        );
+        assert_eq!(
+            pm.plan_rename_prefix("x", ""),
+            Err(RefactorError::InvalidKey("x".to_string()))
+        );
+
+        let mut pm = manager(&["a/b", "a/b/c"]);
+        let changes = pm.plan_rename_prefix("a/", "a/b/").unwrap();
+        pm.apply(&changes).unwrap();
+        assert_eq!(pm.keys(), vec!["a/b/b", "a/b/b/c"]);
+    }
+
+    #[test]
+    fn test_retag() {
+        let mut pm = manager(&["a", "b", "c"]);
+        pm.set_meta("a", TAGS_FIELD, "team, prod").unwrap();
+        pm.set_meta("b", TAGS_FIELD, "prod,production").unwrap();
+        pm.set_meta("c", TAGS_FIELD, "prod").unwrap();
+
+        let changes = pm.plan_retag("prod", Some("production")).unwrap();
+        pm.apply(&changes).unwrap();
+        assert_eq!(pm.meta("a", TAGS_FIELD), Some("team, production"));
+        assert_eq!(pm.meta("b", TAGS_FIELD), Some("production"));
+
+        let changes = pm.plan_retag("production", None).unwrap();
+        assert_eq!(changes.len(), 3);
+        pm.apply(&changes).unwrap();
+        assert_eq!(pm.meta("a", TAGS_FIELD), Some("team"));
+        assert_eq!(pm.meta("c", TAGS_FIELD), None);
+        assert!(pm.plan_retag("a,b", None).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! The source of every random byte: keys, salts, nonces and generated
+//! passwords. It is chosen in the config file:
+//!
+//! ```text
+//! rng = getrandom
+//! ```
+//!
+//! `getrandom` (the default) asks the kernel through the getrandom syscall,
+//! `urandom` reads /dev/urandom for sandboxes that filter the syscall, and
+//! `rdrand` mixes the CPU's RDRAND output into getrandom, so that neither
+//! has to be trusted on its own.
+
+use std::{fs::File, io::Read, sync::OnceLock};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use thiserror::Error;
+
+pub const SETTING: &str = "rng";
+
+const URANDOM: &str = "/dev/urandom";
+const HEALTH_SAMPLE_LENGTH: usize = 32;
+/// RDRAND can run dry for a moment under load, Intel recommends retrying.
+#[cfg(target_arch = "x86_64")]
+const RDRAND_RETRIES: usize = 10;
+
+static SOURCE: OnceLock<Source> = OnceLock::new();
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RngError {
+    #[error("unknown entropy source `{0}`, expected getrandom, urandom or rdrand")]
+    UnknownSource(String),
+    #[error("the {0} entropy source is unavailable: `{1}`")]
+    Unavailable(Source, String),
+    #[error("the {0} entropy source failed the health check")]
+    Unhealthy(Source),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    #[default]
+    Getrandom,
+    Urandom,
+    Rdrand,
+}
+
+impl Source {
+    pub fn parse(value: &str) -> Result<Self, RngError> {
+        match value {
+            "getrandom" => Ok(Self::Getrandom),
+            "urandom" => Ok(Self::Urandom),
+            "rdrand" => Ok(Self::Rdrand),
+            _ => Err(RngError::UnknownSource(value.to_string())),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Getrandom => "getrandom",
+            Self::Urandom => "urandom",
+            Self::Rdrand => "rdrand",
+        }
+    }
+
+    pub fn try_fill(self, buf: &mut [u8]) -> Result<(), RngError> {
+        let result = match self {
+            Self::Getrandom => OsRng.try_fill_bytes(buf).map_err(|err| err.to_string()),
+            Self::Urandom => File::open(URANDOM)
+                .and_then(|mut v| v.read_exact(buf))
+                .map_err(|err| err.to_string()),
+            Self::Rdrand => OsRng
+                .try_fill_bytes(buf)
+                .map_err(|err| err.to_string())
+                .and_then(|_| mix_rdrand(buf)),
+        };
+        result.map_err(|err| RngError::Unavailable(self, err))
+    }
+
+    /// Draws two samples and rejects obviously broken output (failures,
+    /// all-zero or repeated samples).
+    pub fn health_check(self) -> Result<(), RngError> {
+        let mut a = [0; HEALTH_SAMPLE_LENGTH];
+        let mut b = [0; HEALTH_SAMPLE_LENGTH];
+        self.try_fill(&mut a)?;
+        self.try_fill(&mut b)?;
+        if a == b || a.iter().all(|&v| v == 0) || b.iter().all(|&v| v == 0) {
+            return Err(RngError::Unhealthy(self));
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Makes `source` the one used from now on. It can only be set once, at
+/// startup, so that a process never mixes sources.
+pub fn configure(source: Source) {
+    let _ = SOURCE.set(source);
+}
+
+pub fn source() -> Source {
+    SOURCE.get().copied().unwrap_or_default()
+}
+
+pub fn try_fill(buf: &mut [u8]) -> Result<(), RngError> {
+    source().try_fill(buf)
+}
+
+/// Like `try_fill`, panicking if the source fails, as `OsRng` does. The
+/// source passed its health check at startup.
+pub fn fill(buf: &mut [u8]) {
+    try_fill(buf).expect("the entropy source passed its health check")
+}
+
+pub fn next_u32() -> u32 {
+    let mut bytes = [0; 4];
+    fill(&mut bytes);
+    u32::from_le_bytes(bytes)
+}
+
+pub fn next_u64() -> u64 {
+    let mut bytes = [0; 8];
+    fill(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn mix_rdrand(buf: &mut [u8]) -> Result<(), String> {
+    if !std::arch::is_x86_feature_detected!("rdrand") {
+        return Err("the CPU has no RDRAND instruction".to_string());
+    }
+    for chunk in buf.chunks_mut(8) {
+        let mut value = 0;
+        // SAFETY: the CPU supports RDRAND, checked above.
+        let drawn = (0..RDRAND_RETRIES)
+            .any(|_| unsafe { std::arch::x86_64::_rdrand64_step(&mut value) } == 1);
+        if !drawn {
+            return Err("RDRAND returned no value".to_string());
+        }
+        for (byte, mixed) in chunk.iter_mut().zip(value.to_le_bytes()) {
+            *byte ^= mixed;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn mix_rdrand(_: &mut [u8]) -> Result<(), String> {
+    Err("RDRAND is only available on x86_64".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_check() {
+        assert_eq!(Source::Getrandom.health_check(), Ok(()));
+        assert_eq!(Source::Urandom.health_check(), Ok(()));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Source::parse("urandom"), Ok(Source::Urandom));
+        assert_eq!(
+            Source::parse("dice"),
+            Err(RngError::UnknownSource("dice".to_string()))
+        );
+        for source in [Source::Getrandom, Source::Urandom, Source::Rdrand] {
+            assert_eq!(Source::parse(source.name()), Ok(source));
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! The vault as rows of a database rather than a single file. Every entry
+//! and tombstone is a row encrypted on its own, so that a save only rewrites
+//! the rows that changed. A settings row, encrypted over the header, holds
+//! the fingerprint key and, with the `SCHEMA` capability, the schema; the
+//! header carries the merkle root of the entries, which ties the rows
+//! together the way the single body of a file does.
+
+use std::{collections::HashMap, mem::size_of};
+
+use super::{
+    ct,
+    encoder::{Body, Encoder, EncoderError, Header},
+    encoding::capability::Capabilities,
+    encryptor::{DynamicEncryptor, Encryprtor, EncryprtorError},
+    entry::KeyHash,
+    fingerprint::{self, Fingerprint, FingerprintKey},
+    identifiers::Identifiable,
+    identity::VaultId,
+    keycache::KeyCache,
+    manager::PasswordManager,
+    schema::MAX_SCHEMA_LENGTH,
+    trace,
+};
+
+pub const SETTINGS_ROW: u8 = 0;
+pub const ENTRY_ROW: u8 = 1;
+pub const TOMBSTONE_ROW: u8 = 2;
+
+/// Rows are keyed by a fingerprint of what they hold, so that the keys
+/// tell nothing without the fingerprint key.
+pub type RowId = Fingerprint;
+
+/// The settings row is the only one found before the fingerprint key is.
+pub const SETTINGS_ID: RowId = [0; size_of::<RowId>()];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub id: RowId,
+    pub kind: u8,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rows {
+    pub header: Vec<u8>,
+    pub rows: Vec<Row>,
+}
+
+/// The ciphertext of every row as last read or written, with a keyed digest
+/// of its plaintext. A row whose plaintext did not change is written back
+/// as it was instead of being encrypted again.
+#[derive(Debug, Default)]
+pub struct RowCache(HashMap<RowId, (Fingerprint, Vec<u8>)>);
+
+impl RowCache {
+    /// Forgets every ciphertext, which must happen whenever the key does.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl Encoder {
+    pub fn decode_rows(
+        key: &[u8],
+        rows: &Rows,
+        cache: &mut KeyCache,
+    ) -> Result<PasswordManager<DynamicEncryptor>, EncoderError> {
+        let _span = trace::span("decode rows");
+        let header = Header::try_from_bytes(&rows.header)?;
+        if !header.version().has_merkle_root() {
+            return Err(EncoderError::HeaderParseError);
+        }
+        let (key, mut encryptor, hasher) = Self::open(&header, key, cache)?;
+
+        let settings = rows
+            .rows
+            .iter()
+            .find(|row| row.kind == SETTINGS_ROW && row.id == SETTINGS_ID)
+            .ok_or(EncoderError::BodyParseError)?;
+        let settings = match encryptor.decrypt(&settings.data, &settings_aad(&header)) {
+            Ok(v) => v,
+            Err(EncryprtorError::DecryptionError(_)) => {
+                ct::reject();
+                return Err(EncoderError::IvalidKeyError);
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (fingerprint_key, schema) = settings_from_bytes(&header, &settings)?;
+
+        let decrypt_span = trace::span("decrypt");
+        let mut body = Body {
+            kv: HashMap::new(),
+            tombstones: HashMap::new(),
+            fingerprint_key: Some(fingerprint_key),
+            schema,
+        };
+        let mut row_cache = RowCache::default();
+        for row in rows.rows.iter().filter(|row| row.kind != SETTINGS_ROW) {
+            let plain = encryptor.decrypt(&row.data, &row_aad(header.vault_id(), row))?;
+            let name = match row.kind {
+                ENTRY_ROW => {
+                    let (key, entry) = Body::entry_from_bytes(&plain)?;
+                    let name = key.clone().into_bytes();
+                    body.kv.insert(key, entry);
+                    name
+                }
+                TOMBSTONE_ROW => {
+                    if plain.len() != size_of::<KeyHash>() + size_of::<u64>() {
+                        return Err(EncoderError::BodyParseError);
+                    }
+                    let (key_hash, deleted) = plain.split_at(size_of::<KeyHash>());
+                    let key_hash: KeyHash = key_hash.try_into().expect("the length was checked");
+                    let deleted = deleted.try_into().expect("the length was checked");
+                    body.tombstones
+                        .insert(key_hash, u64::from_be_bytes(deleted));
+                    key_hash.to_vec()
+                }
+                _ => return Err(EncoderError::BodyParseError),
+            };
+            // A row moved under the id of another would otherwise go
+            // unnoticed until the next save.
+            if !ct::eq(&row_id(&fingerprint_key, row.kind, &name), &row.id) {
+                return Err(EncoderError::BodyParseError);
+            }
+            let digest = fingerprint::fingerprint(&fingerprint_key, &plain);
+            row_cache.0.insert(row.id, (digest, row.data.clone()));
+        }
+        drop(decrypt_span);
+
+        let mut pm = Self::assemble(&header, body, encryptor, hasher, &key)?;
+        pm.rows = row_cache;
+        Ok(pm)
+    }
+
+    /// Encodes every row of the vault, reusing the ciphertext of those that
+    /// did not change since they were read or last written.
+    pub fn encode_rows<T>(pm: &mut PasswordManager<T>) -> Result<Rows, EncoderError>
+    where
+        T: Encryprtor + Identifiable,
+    {
+        let _span = trace::span("encode rows");
+        let schema = pm.schema().to_bytes();
+        let body_sha = Self::merkle_root(pm)?;
+        let header = Header::next(pm, body_sha);
+
+        let mut plain = Vec::with_capacity(pm.kv.len() + pm.tombstones.len());
+        for (key, entry) in &pm.kv {
+            let mut bytes = Vec::new();
+            Body::write_entry(&mut bytes, key, entry);
+            plain.push((
+                row_id(&pm.fingerprint_key, ENTRY_ROW, key.as_bytes()),
+                ENTRY_ROW,
+                bytes,
+            ));
+        }
+        for (key_hash, deleted) in &pm.tombstones {
+            let mut bytes = key_hash.to_vec();
+            bytes.extend(deleted.to_be_bytes());
+            plain.push((
+                row_id(&pm.fingerprint_key, TOMBSTONE_ROW, key_hash),
+                TOMBSTONE_ROW,
+                bytes,
+            ));
+        }
+
+        let _span = trace::span("encrypt");
+        let mut settings = pm.fingerprint_key.to_vec();
+        settings.extend((schema.len() as u64).to_be_bytes());
+        settings.extend(&schema);
+        let mut rows = vec![Row {
+            id: SETTINGS_ID,
+            kind: SETTINGS_ROW,
+            data: pm
+                .encryptor
+                .encrypt(&settings, &settings_aad(&header))?
+                .into(),
+        }];
+        let mut row_cache = RowCache::default();
+        for (id, kind, bytes) in plain {
+            let digest = fingerprint::fingerprint(&pm.fingerprint_key, &bytes);
+            let mut row = Row {
+                id,
+                kind,
+                data: Vec::new(),
+            };
+            row.data = match pm.rows.0.remove(&id) {
+                Some((cached, data)) if ct::eq(&cached, &digest) => data,
+                _ => pm
+                    .encryptor
+                    .encrypt(&bytes, &row_aad(&pm.vault_id, &row))?
+                    .into(),
+            };
+            row_cache.0.insert(id, (digest, row.data.clone()));
+            rows.push(row);
+        }
+        pm.rows = row_cache;
+        pm.generation = header.generation();
+        Ok(Rows {
+            header: header.to_bytes(),
+            rows,
+        })
+    }
+}
+
+fn row_id(fingerprint_key: &FingerprintKey, kind: u8, name: &[u8]) -> RowId {
+    let mut bytes = vec![kind];
+    bytes.extend(name);
+    fingerprint::fingerprint(fingerprint_key, &bytes)
+}
+
+/// Splits the plaintext of the settings row into the fingerprint key and
+/// the schema of vaults that have one. Like a body, it may end with the
+/// data of optional features this binary ignores.
+fn settings_from_bytes(
+    header: &Header,
+    bytes: &[u8],
+) -> Result<(FingerprintKey, Option<Vec<u8>>), EncoderError> {
+    let capabilities = header.capabilities();
+    let (fingerprint_key, mut rest) = bytes
+        .split_first_chunk::<{ size_of::<FingerprintKey>() }>()
+        .ok_or(EncoderError::BodyParseError)?;
+    let mut schema = None;
+    if capabilities.contains(Capabilities::SCHEMA) {
+        let (length, tail) = rest
+            .split_first_chunk::<{ size_of::<u64>() }>()
+            .ok_or(EncoderError::BodyParseError)?;
+        let length = usize::try_from(u64::from_be_bytes(*length))
+            .ok()
+            .filter(|v| *v <= MAX_SCHEMA_LENGTH && *v <= tail.len())
+            .ok_or(EncoderError::BodyParseError)?;
+        schema = Some(tail[..length].to_vec());
+        rest = &tail[length..];
+    }
+    if !rest.is_empty() && capabilities.ignored().is_empty() {
+        return Err(EncoderError::BodyParseError);
+    }
+    Ok((*fingerprint_key, schema))
+}
+
+/// The settings row is bound to the header, which carries the merkle root
+/// every other row is checked against.
+fn settings_aad(header: &Header) -> Vec<u8> {
+    let mut aad = header.to_bytes();
+    aad.push(SETTINGS_ROW);
+    aad
+}
+
+/// Other rows are bound to their vault and id only, so that they survive
+/// the saves that do not touch them.
+fn row_aad(vault_id: &VaultId, row: &Row) -> Vec<u8> {
+    let mut aad = vault_id.to_vec();
+    aad.push(row.kind);
+    aad.extend(row.id);
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    fn pm() -> PasswordManager<AESEncryptor> {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foobar"));
+        pm.store_password("foo".to_string(), "bar").unwrap();
+        pm.store_password("gone".to_string(), "baz").unwrap();
+        pm.delete("gone").unwrap();
+        pm
+    }
+
+    #[test]
+    fn test_rows_roundtrip() {
+        let mut pm = pm();
+        let rows = Encoder::encode_rows(&mut pm).unwrap();
+        assert_eq!(rows.rows.len(), 3);
+        assert_eq!(pm.generation(), 1);
+
+        let mut pm2 = Encoder::decode_rows(b"foobar", &rows, &mut KeyCache::default()).unwrap();
+        assert_eq!(pm.kv, pm2.kv);
+        assert_eq!(pm.tombstones, pm2.tombstones);
+        assert_eq!(pm2.generation(), 1);
+        assert_eq!(pm2.get_password("foo"), Ok("bar".to_string()));
+
+        assert!(matches!(
+            Encoder::decode_rows(b"wrong", &rows, &mut KeyCache::default()),
+            Err(EncoderError::IvalidKeyError)
+        ));
+    }
+
+    #[test]
+    fn test_unchanged_rows_are_reused() {
+        let mut pm = pm();
+        let first = Encoder::encode_rows(&mut pm).unwrap();
+        let mut pm = Encoder::decode_rows(b"foobar", &first, &mut KeyCache::default()).unwrap();
+        pm.store_password("new".to_string(), "value").unwrap();
+        let second = Encoder::encode_rows(&mut pm).unwrap();
+
+        let reused = second
+            .rows
+            .iter()
+            .filter(|row| first.rows.contains(row))
+            .count();
+        // Everything but the settings row and the new entry.
+        assert_eq!(reused, second.rows.len() - 2);
+    }
+
+    #[test]
+    fn test_tampered_rows() {
+        let mut pm = pm();
+        let rows = Encoder::encode_rows(&mut pm).unwrap();
+
+        let mut dropped = rows.clone();
+        dropped.rows.retain(|row| row.kind != TOMBSTONE_ROW);
+        assert!(matches!(
+            Encoder::decode_rows(b"foobar", &dropped, &mut KeyCache::default()),
+            Err(EncoderError::IvalidKeyError)
+        ));
+
+        let mut moved = rows.clone();
+        let entry = moved
+            .rows
+            .iter_mut()
+            .find(|row| row.kind == ENTRY_ROW)
+            .unwrap();
+        entry.id[0] ^= 1;
+        assert!(Encoder::decode_rows(b"foobar", &moved, &mut KeyCache::default()).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//! A description of the fields the entries of a vault carry, stored at the
+//! end of the body behind the optional `SCHEMA` capability. Another binary,
+//! newer, older or not mopm at all, reads from it what a field holds and
+//! how to show it, rather than showing unknown metadata as opaque strings:
+//!
+//! ```text
+//! {"fields": {"expires": {"type": "time", "description": ".."}, ..},
+//!  "templates": {"login": ["username", "url", ..]},
+//!  "policies": ["confirm"], "version": 1}
+//! ```
+//!
+//! The schema is described again on every save. Fields and templates this
+//! binary does not know, and any other member, are kept as they were read,
+//! so a vault goes through an older binary or an export without losing
+//! the descriptions written by a newer one.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::{json, Map, Value};
+
+use super::{
+    dynamic,
+    encryptor::Encryprtor,
+    entry::{self, Entry},
+    manager::PasswordManager,
+    policy, provenance, site, timelock,
+};
+
+/// Large enough for the field names of any vault within the entry limits.
+pub const MAX_SCHEMA_LENGTH: usize = 16 * 1024 * 1024;
+
+const VERSION: u64 = 1;
+const FIELDS: &str = "fields";
+const TEMPLATES: &str = "templates";
+const POLICIES: &str = "policies";
+/// The type of fields that are in use but described nowhere.
+const UNKNOWN_TYPE: &str = "text";
+
+/// The fields this binary knows: name, type and description.
+const KNOWN_FIELDS: [(&str, &str, &str); 17] = [
+    ("username", "text", "login name"),
+    (site::URL_FIELD, "url", "site the login is for"),
+    (site::MATCH_FIELD, "text", "how the url is matched"),
+    ("notes", "text", "free-form notes"),
+    (entry::TAGS_FIELD, "list", "comma-separated tags"),
+    (
+        policy::POLICY_FIELD,
+        "policy",
+        "checks before the value is read",
+    ),
+    (
+        timelock::NOT_BEFORE_FIELD,
+        "time",
+        "unix time before which the value is locked",
+    ),
+    (
+        timelock::EXPIRES_FIELD,
+        "time",
+        "unix time the value expires at",
+    ),
+    (
+        dynamic::KIND,
+        "text",
+        "`dynamic` when the value is a command",
+    ),
+    (
+        dynamic::TTL,
+        "seconds",
+        "how long the output of the command is cached",
+    ),
+    (
+        dynamic::NETWORK,
+        "text",
+        "`true` when the command may use the network",
+    ),
+    (dynamic::CACHE, "secret", "cached output of the command"),
+    (
+        dynamic::CACHE_EXPIRES,
+        "time",
+        "unix time the cached output expires at",
+    ),
+    (
+        provenance::SOURCE_FIELD,
+        "text",
+        "tool the entry was imported from",
+    ),
+    (
+        provenance::FOLDER_FIELD,
+        "text",
+        "folder the entry was in there",
+    ),
+    (
+        provenance::CREATED_FIELD,
+        "time",
+        "unix time the entry was created there",
+    ),
+    (
+        provenance::MODIFIED_FIELD,
+        "time",
+        "unix time the imported value was set",
+    ),
+];
+
+/// The sets of fields entries of a kind are stored with.
+const KNOWN_TEMPLATES: [(&str, &[&str]); 2] = [
+    ("login", &entry::LOGIN_FIELDS),
+    ("dynamic", &[dynamic::KIND, dynamic::TTL]),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema(Map<String, Value>);
+
+impl Schema {
+    /// Parses a stored schema, `None` if it is not a JSON object.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match serde_json::from_slice(bytes).ok()? {
+            Value::Object(v) => Some(Self(v)),
+            _ => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.0).expect("json values serialize")
+    }
+
+    /// The fields described, in name order, with their type.
+    pub fn fields(&self) -> Vec<(&str, &str)> {
+        self.members(FIELDS)
+            .map(|(name, field)| {
+                let kind = field.get("type").and_then(Value::as_str);
+                (name.as_str(), kind.unwrap_or(UNKNOWN_TYPE))
+            })
+            .collect()
+    }
+
+    pub fn templates(&self) -> Vec<&str> {
+        self.members(TEMPLATES).map(|(v, _)| v.as_str()).collect()
+    }
+
+    /// The access policies entries were stored with.
+    pub fn policies(&self) -> Vec<&str> {
+        self.0
+            .get(POLICIES)
+            .and_then(Value::as_array)
+            .map(|v| v.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Adds what `other` describes and this schema does not: its fields,
+    /// its templates and any member of its own.
+    pub fn merge(&mut self, other: &Schema) {
+        for section in [FIELDS, TEMPLATES] {
+            let mine = self.section(section);
+            for (name, value) in other.members(section) {
+                mine.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        for (name, value) in &other.0 {
+            self.0.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    /// This schema brought up to date with the entries of `kv`: the known
+    /// fields and templates are described as this binary has them, fields
+    /// in use that nothing describes are added as text, and the policies
+    /// are those in use.
+    fn describe(&self, kv: &HashMap<String, Entry>) -> Self {
+        let mut schema = self.clone();
+        schema.0.insert("version".to_string(), json!(VERSION));
+        let fields = schema.section(FIELDS);
+        for (name, kind, description) in KNOWN_FIELDS {
+            fields.insert(
+                name.to_string(),
+                json!({"type": kind, "description": description}),
+            );
+        }
+        for name in kv.values().flat_map(|entry| entry.meta.keys()) {
+            fields
+                .entry(name.clone())
+                .or_insert_with(|| json!({ "type": UNKNOWN_TYPE }));
+        }
+        let templates = schema.section(TEMPLATES);
+        for (name, members) in KNOWN_TEMPLATES {
+            templates.insert(name.to_string(), json!(members));
+        }
+        let policies: BTreeSet<&str> = kv
+            .values()
+            .filter_map(|entry| entry.meta(policy::POLICY_FIELD))
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect();
+        schema.0.insert(POLICIES.to_string(), json!(policies));
+        schema
+    }
+
+    fn members(&self, section: &str) -> impl Iterator<Item = (&String, &Value)> {
+        self.0
+            .get(section)
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+    }
+
+    /// The object under `section`, replacing whatever else it held.
+    fn section(&mut self, section: &str) -> &mut Map<String, Value> {
+        let value = self
+            .0
+            .entry(section.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if !value.is_object() {
+            *value = Value::Object(Map::new());
+        }
+        value.as_object_mut().expect("just made an object")
+    }
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// The schema the vault is saved with.
+    pub fn schema(&self) -> Schema {
+        self.schema.describe(&self.kv)
+    }
+
+    /// Keeps the descriptions of `schema` for fields and templates the
+    /// vault does not describe yet, such as those of a vault it takes
+    /// entries from.
+    pub fn adopt_schema(&mut self, schema: &Schema) {
+        self.schema.merge(schema);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        pm.store_password("db".to_string(), "x").unwrap();
+        pm.set_meta("db", "username", "admin").unwrap();
+        pm.set_meta("db", "port", "5432").unwrap();
+        pm.set_meta("db", policy::POLICY_FIELD, "confirm,reauth")
+            .unwrap();
+
+        let schema = pm.schema();
+        assert!(schema.fields().contains(&("username", "text")));
+        assert!(schema.fields().contains(&("expires", "time")));
+        assert!(schema.fields().contains(&("port", "text")));
+        assert_eq!(schema.templates(), vec!["dynamic", "login"]);
+        assert_eq!(schema.policies(), vec!["confirm", "reauth"]);
+        assert_eq!(Schema::from_bytes(&schema.to_bytes()), Some(schema));
+        assert_eq!(Schema::from_bytes(b"[1]"), None);
+    }
+
+    #[test]
+    fn test_foreign_descriptions_are_kept() {
+        let foreign = Schema::from_bytes(
+            br#"{"fields": {"totp": {"type": "otp"}, "username": {"type": "email"}},
+                "templates": {"card": ["number"]}, "writer": "other"}"#,
+        )
+        .unwrap();
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        pm.adopt_schema(&foreign);
+
+        let schema = pm.schema();
+        assert!(schema.fields().contains(&("totp", "otp")));
+        assert!(schema.fields().contains(&("username", "text")));
+        assert!(schema.templates().contains(&"card"));
+        assert!(String::from_utf8(schema.to_bytes())
+            .unwrap()
+            .contains(r#""writer":"other""#));
+    }
+}
@@ -0,0 +1,120 @@
+//! Entries that only last for the login session, for one-off tokens that
+//! should never reach the disk. They are kept apart from the vault, sealed
+//! with a key derived from its master key, in a file of the runtime
+//! directory, a tmpfs removed when the session ends. `get` and `exists`
+//! look them up after the vault.
+
+use std::collections::BTreeMap;
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::{
+    encryptor::{AESEncryptor, Encryprtor, EncryprtorError},
+    manager::PasswordManager,
+};
+
+const KEY_LENGTH: usize = 32;
+const INFO: &[u8] = b"mopm-session";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SessionError {
+    #[error("the vault was opened without its master key")]
+    NoMasterKey,
+    #[error("the session entries are of another vault, see `mopm clear --session`")]
+    Undecryptable,
+    #[error("encryptor error: `{0}`")]
+    EncryptorError(#[from] EncryprtorError),
+    #[error("cannot serialize the session entries")]
+    SerializeError,
+    #[error("cannot derive the session key")]
+    KeyDerivationError,
+}
+
+/// The session entries, zeroized when dropped.
+#[derive(Debug, Default)]
+pub struct Session {
+    entries: BTreeMap<String, Zeroizing<String>>,
+}
+
+impl Session {
+    /// Opens the session entries sealed for the vault of `pm`.
+    pub fn open<T: Encryprtor>(
+        pm: &PasswordManager<T>,
+        sealed: &[u8],
+    ) -> Result<Self, SessionError> {
+        let plain = encryptor(pm)?
+            .decrypt(sealed, &pm.vault_id)
+            .map_err(|_| SessionError::Undecryptable)?;
+        let entries: BTreeMap<String, String> =
+            serde_json::from_slice(&plain).map_err(|_| SessionError::Undecryptable)?;
+        Ok(Self {
+            entries: entries
+                .into_iter()
+                .map(|(key, value)| (key, Zeroizing::new(value)))
+                .collect(),
+        })
+    }
+
+    /// Seals the entries for the vault of `pm`.
+    pub fn seal<T: Encryprtor>(&self, pm: &PasswordManager<T>) -> Result<Vec<u8>, SessionError> {
+        let entries: BTreeMap<&str, &str> = self
+            .entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        let plain =
+            Zeroizing::new(serde_json::to_vec(&entries).map_err(|_| SessionError::SerializeError)?);
+        encryptor(pm)?
+            .encrypt(&plain, &pm.vault_id)
+            .map(Vec::from)
+            .map_err(SessionError::from)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(|v| v.as_str())
+    }
+
+    pub fn insert(&mut self, key: String, value: &str) {
+        self.entries.insert(key, Zeroizing::new(value.to_string()));
+    }
+}
+
+/// AES-GCM under HKDF-SHA256 of the master key, salted with the vault id
+/// like the namespace keys.
+fn encryptor<T: Encryprtor>(pm: &PasswordManager<T>) -> Result<AESEncryptor, SessionError> {
+    let master_key = pm.keyring.master_key().ok_or(SessionError::NoMasterKey)?;
+    let mut key = Zeroizing::new([0; KEY_LENGTH]);
+    Hkdf::<Sha256>::new(Some(&pm.vault_id), master_key)
+        .expand(INFO, key.as_mut())
+        .map_err(|_| SessionError::KeyDerivationError)?;
+    Ok(AESEncryptor::new(key.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::kdf::Kdf;
+
+    use super::*;
+
+    #[test]
+    fn test_seal() {
+        let pm = PasswordManager::init("foobar", Kdf::Raw).unwrap();
+        let mut session = Session::default();
+        session.insert("token".to_string(), "secret");
+        let sealed = session.seal(&pm).unwrap();
+        assert!(!sealed.windows(6).any(|v| v == b"secret"));
+
+        let opened = Session::open(&pm, &sealed).unwrap();
+        assert_eq!(opened.get("token"), Some("secret"));
+        assert_eq!(opened.get("other"), None);
+
+        let other = PasswordManager::init("foobar", Kdf::Raw).unwrap();
+        assert_eq!(
+            Session::open(&other, &sealed).unwrap_err(),
+            SessionError::Undecryptable
+        );
+    }
+}
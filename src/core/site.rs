@@ -0,0 +1,282 @@
+//! Which logins belong to a site. The `url` of an entry is stored in a
+//! canonical form and matches any page of the same registrable domain
+//! (eTLD+1), the `match` field holds further comma-separated rules:
+//!
+//! ```text
+//! example.org                    the registrable domain of example.org
+//! host:login.example.com         that host only
+//! prefix:https://example.com/a   URLs starting with this one
+//! exact:https://example.com/a    this URL only
+//! ```
+//!
+//! Registrable domains come from the public suffix list, private suffixes
+//! like `github.io` included, as compiled into the `psl` crate. A host under
+//! a suffix the list does not know, like an intranet name, only matches
+//! itself rather than every host of its last label.
+
+use std::net::IpAddr;
+
+use thiserror::Error;
+use url::{Host, Url};
+
+use super::{encryptor::Encryprtor, entry::Entry, manager::PasswordManager};
+
+pub const URL_FIELD: &str = "url";
+pub const MATCH_FIELD: &str = "match";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SiteError {
+    #[error("`{0}` is not a valid http(s) URL")]
+    InvalidUrl(String),
+    #[error("unknown match rule `{0}`, expected host:, prefix:, exact: or a domain")]
+    UnknownRule(String),
+}
+
+/// How closely an entry matches a URL, the closest last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Match {
+    Domain,
+    Host,
+    Prefix,
+    Exact,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Rule {
+    Domain(String),
+    Host(String),
+    Prefix(String),
+    Exact(String),
+}
+
+impl Rule {
+    fn parse(rule: &str) -> Result<Self, SiteError> {
+        match rule.split_once(':') {
+            Some(("host", host)) => Ok(Self::Host(host_of(&parse(host)?))),
+            Some(("prefix", url)) => Ok(Self::Prefix(canonicalize(url)?)),
+            Some(("exact", url)) => Ok(Self::Exact(canonicalize(url)?)),
+            Some((kind, _)) if !kind.contains('.') && kind != "http" && kind != "https" => {
+                Err(SiteError::UnknownRule(rule.to_string()))
+            }
+            _ => Ok(Self::Domain(domain(&host_of(&parse(rule)?)))),
+        }
+    }
+
+    fn render(&self) -> String {
+        match self {
+            Self::Domain(v) => v.clone(),
+            Self::Host(v) => format!("host:{}", v),
+            Self::Prefix(v) => format!("prefix:{}", v),
+            Self::Exact(v) => format!("exact:{}", v),
+        }
+    }
+
+    fn matches(&self, url: &str, host: &str) -> Option<Match> {
+        let matches = match self {
+            Self::Domain(v) => domain(host) == *v,
+            Self::Host(v) => host == v,
+            Self::Prefix(v) => url.starts_with(v.as_str()),
+            Self::Exact(v) => url == v,
+        };
+        matches.then_some(match self {
+            Self::Domain(_) => Match::Domain,
+            Self::Host(_) => Match::Host,
+            Self::Prefix(_) => Match::Prefix,
+            Self::Exact(_) => Match::Exact,
+        })
+    }
+}
+
+/// `url` without credentials, query and fragment, with a lowercase ASCII
+/// host and no default port. `https://` is assumed when there is no scheme.
+pub fn canonicalize(url: &str) -> Result<String, SiteError> {
+    Ok(parse(url)?.to_string())
+}
+
+/// The canonical form of the `url` and `match` fields, other metadata as
+/// it is.
+pub fn lint(name: &str, value: &str) -> Result<String, SiteError> {
+    match name {
+        URL_FIELD => canonicalize(value),
+        MATCH_FIELD => Ok(rules(value)?
+            .iter()
+            .map(Rule::render)
+            .collect::<Vec<_>>()
+            .join(", ")),
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// How closely `entry` matches the canonical `url`, `None` if it does not.
+/// Fields that do not parse, like those imported before they were linted,
+/// match nothing.
+pub fn rank(entry: &Entry, url: &Url) -> Option<Match> {
+    let host = host_of(url);
+    let url = url.as_str();
+    let own = entry
+        .meta(URL_FIELD)
+        .and_then(|v| parse(v).ok())
+        .map(|v| Rule::Domain(domain(&host_of(&v))));
+    let extra = entry
+        .meta(MATCH_FIELD)
+        .and_then(|v| rules(v).ok())
+        .unwrap_or_default();
+    own.iter()
+        .chain(&extra)
+        .filter_map(|rule| rule.matches(url, &host))
+        .max()
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Keys of the entries matching `url`, closest first. No value is
+    /// decrypted.
+    pub fn matching(&self, url: &str) -> Result<Vec<(&str, Match)>, SiteError> {
+        let url = parse(url)?;
+        let mut matches: Vec<(&str, Match)> = self
+            .kv
+            .iter()
+            .filter_map(|(key, entry)| Some((key.as_str(), rank(entry, &url)?)))
+            .collect();
+        matches.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        Ok(matches)
+    }
+}
+
+fn rules(value: &str) -> Result<Vec<Rule>, SiteError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(Rule::parse)
+        .collect()
+}
+
+fn parse(url: &str) -> Result<Url, SiteError> {
+    let invalid = || SiteError::InvalidUrl(url.to_string());
+    let trimmed = url.trim();
+    let mut parsed = match trimmed.contains("://") {
+        true => Url::parse(trimmed),
+        false => Url::parse(&format!("https://{}", trimmed)),
+    }
+    .map_err(|_| invalid())?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host().is_none() {
+        return Err(invalid());
+    }
+    if let Some(Host::Domain(host)) = parsed.host() {
+        if host.ends_with('.') {
+            let host = host.trim_end_matches('.').to_string();
+            parsed.set_host(Some(&host)).map_err(|_| invalid())?;
+        }
+    }
+    parsed.set_username("").map_err(|_| invalid())?;
+    parsed.set_password(None).map_err(|_| invalid())?;
+    parsed.set_query(None);
+    parsed.set_fragment(None);
+    Ok(parsed)
+}
+
+fn host_of(url: &Url) -> String {
+    url.host_str().unwrap_or_default().to_string()
+}
+
+/// The registrable domain of `host`. IP addresses, hosts under an unknown
+/// suffix and suffixes themselves are kept as they are.
+fn domain(host: &str) -> String {
+    if host.trim_matches(['[', ']']).parse::<IpAddr>().is_ok() {
+        return host.to_string();
+    }
+    match psl::domain(host.as_bytes()) {
+        Some(v) if v.suffix().is_known() => String::from_utf8_lossy(v.as_bytes()).into_owned(),
+        _ => host.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_canonicalize() {
+        for (url, canonical) in [
+            ("Example.COM", "https://example.com/"),
+            (
+                "HTTPS://user:pw@Login.Example.com.:443/a/b?next=x#top",
+                "https://login.example.com/a/b",
+            ),
+            ("http://example.com:8080/", "http://example.com:8080/"),
+            ("bücher.de/shop", "https://xn--bcher-kva.de/shop"),
+        ] {
+            assert_eq!(canonicalize(url).as_deref(), Ok(canonical), "{}", url);
+        }
+        for url in ["ftp://example.com", "", "https://"] {
+            assert!(canonicalize(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_domain() {
+        assert_eq!(domain("a.b.example.com"), "example.com");
+        assert_eq!(domain("shop.example.co.uk"), "example.co.uk");
+        assert_eq!(domain("me.github.io"), "me.github.io");
+        assert_eq!(domain("www.me.github.io"), "me.github.io");
+        assert_eq!(domain("foo.blogspot.com"), "foo.blogspot.com");
+        assert_eq!(domain("a.example.com.br"), "example.com.br");
+        assert_eq!(domain("co.uk"), "co.uk");
+        assert_eq!(domain("localhost"), "localhost");
+        assert_eq!(domain("wiki.corp.internal"), "wiki.corp.internal");
+        assert_eq!(domain("10.0.0.1"), "10.0.0.1");
+        assert_eq!(domain("[::1]"), "[::1]");
+    }
+
+    #[test]
+    fn test_lint() {
+        assert_eq!(
+            lint(
+                MATCH_FIELD,
+                "www.Example.com, host:A.example.com,exact:x.org/p?q"
+            ),
+            Ok("example.com, host:a.example.com, exact:https://x.org/p".to_string())
+        );
+        assert!(matches!(
+            lint(MATCH_FIELD, "regex:.*"),
+            Err(SiteError::UnknownRule(_))
+        ));
+        assert_eq!(lint("notes", "x?y"), Ok("x?y".to_string()));
+    }
+
+    #[test]
+    fn test_matching() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        for (key, name, value) in [
+            ("mail", URL_FIELD, "https://mail.example.com/"),
+            ("admin", MATCH_FIELD, "prefix:example.com/admin"),
+            ("sso", MATCH_FIELD, "host:login.example.com, other.org"),
+            ("unrelated", URL_FIELD, "https://example.co.uk/"),
+            ("broken", URL_FIELD, "not a url"),
+        ] {
+            pm.store_password(key.to_string(), "x").unwrap();
+            pm.set_meta(key, name, value).unwrap();
+        }
+
+        assert_eq!(
+            pm.matching("https://login.example.com/admin?x").unwrap(),
+            vec![("sso", Match::Host), ("mail", Match::Domain)]
+        );
+        assert_eq!(
+            pm.matching("example.com/admin/users").unwrap(),
+            vec![("admin", Match::Prefix), ("mail", Match::Domain)]
+        );
+        assert_eq!(
+            pm.matching("https://www.other.org").unwrap(),
+            vec![("sso", Match::Domain)]
+        );
+        assert!(pm.matching("mailto:x").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! A rough estimate of how hard a password is to guess, in bits, for
+//! passwords that protect data leaving the machine. Characters count for
+//! the size of the character classes in use, but repeats, runs like `abc`
+//! or `321`, and common words, spotted through letter-for-digit swaps, count
+//! for little: they are what guessers try first.
+
+use super::hint;
+
+/// What a common word is worth, however long it is.
+const COMMON_WORD_BITS: f64 = 10.0;
+const REPEAT_BITS: f64 = 1.0;
+const RUN_BITS: f64 = 2.0;
+
+const COMMON_WORDS: [&str; 24] = [
+    "password", "passwort", "qwerty", "azerty", "letmein", "welcome", "admin", "dragon", "monkey",
+    "iloveyou", "football", "baseball", "master", "secret", "login", "shadow", "sunshine",
+    "princess", "trustno", "hello", "export", "backup", "vault", "mopm",
+];
+
+pub fn bits(password: &str) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let pool = pool_size(&chars);
+    if pool == 0 {
+        return 0.0;
+    }
+    let per_char = (pool as f64).log2();
+    let mut bits = 0.0;
+    for (i, c) in chars.iter().enumerate() {
+        let previous = i.checked_sub(1).map(|i| chars[i] as i64);
+        bits += match previous.map(|v| *c as i64 - v) {
+            Some(0) => REPEAT_BITS,
+            Some(1 | -1) => RUN_BITS,
+            _ => per_char,
+        };
+    }
+
+    let normalized: String = hint::normalize(password).into_iter().collect();
+    for word in COMMON_WORDS {
+        if normalized.contains(word) {
+            bits -= (word.len() as f64 * per_char - COMMON_WORD_BITS).max(0.0);
+        }
+    }
+    bits.max(0.0)
+}
+
+/// The number of characters in the classes `chars` draws from.
+fn pool_size(chars: &[char]) -> usize {
+    let uses = |class: fn(&char) -> bool| chars.iter().any(class);
+    let mut size = 0;
+    if uses(char::is_ascii_lowercase) {
+        size += 26;
+    }
+    if uses(char::is_ascii_uppercase) {
+        size += 26;
+    }
+    if uses(char::is_ascii_digit) {
+        size += 10;
+    }
+    if uses(|c| c.is_ascii() && !c.is_ascii_alphanumeric()) {
+        size += 33;
+    }
+    if uses(|c| !c.is_ascii()) {
+        size += 100;
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits() {
+        assert_eq!(bits(""), 0.0);
+        assert!(bits("aaaaaaaaaaaaaaaaaaaa") < 30.0);
+        assert!(bits("abcdefghijklmnopqrst") < 50.0);
+        assert!(bits("P@ssw0rd2024!") < 60.0);
+        assert!(bits("hunter2") < 40.0);
+        assert!(bits("correct horse battery staple") > 80.0);
+        assert!(bits("vKq7#pL2!xR9") > 70.0);
+    }
+
+    #[test]
+    fn test_common_words_count_little() {
+        assert!(bits("xkcdpassword") + 20.0 < bits("xkcdqhvmtzbn"));
+        assert!(bits("Passw0rd") < bits("Pzqwr0kd"));
+    }
+}
@@ -0,0 +1,122 @@
+//! Sub-vaults holding some of the entries of a vault, selected by a key
+//! pattern and a tag, for handing a subset of the credentials to someone
+//! else. The sub-vault is a vault of its own, with its own password and
+//! identity.
+
+use super::{
+    encryptor::Encryprtor,
+    entry::{self, TAGS_FIELD},
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+/// Whether `key` matches `pattern`, where `*` stands for any run of
+/// characters, slashes included.
+pub fn matches_glob(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Keys matching the glob `filter` and carrying `tag`, in key order.
+    pub fn select(&self, filter: Option<&str>, tag: Option<&str>) -> Vec<String> {
+        self.entries("")
+            .filter(|(key, _)| filter.is_none_or(|v| matches_glob(v, key)))
+            .filter(|(_, entry)| {
+                tag.is_none_or(|tag| {
+                    entry
+                        .meta(TAGS_FIELD)
+                        .is_some_and(|v| v.split(',').any(|v| v.trim() == tag))
+                })
+            })
+            .map(|(key, _)| key.to_string())
+            .collect()
+    }
+
+    /// Copies the entries under `keys` into `other`, with their metadata
+    /// and the schema describing it. Values are read like `get_password`
+    /// does, so access policies are asked for.
+    pub fn copy_into<U: Encryprtor>(
+        &mut self,
+        keys: &[String],
+        other: &mut PasswordManager<U>,
+    ) -> Result<(), PasswordManagerError> {
+        other.adopt_schema(&self.schema());
+        for key in keys {
+            let value = self.get_password(key)?;
+            other.store_password(key.clone(), &value)?;
+            let meta = &self.kv[key].meta;
+            let copied = &mut other.kv.get_mut(key).expect("just stored").meta;
+            copied.extend(
+                meta.iter()
+                    .filter(|(name, _)| !entry::is_sealed_meta(name))
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_matches_glob() {
+        assert!(matches_glob("work/*", "work/db"));
+        assert!(matches_glob("work/*", "work/a/b"));
+        assert!(!matches_glob("work/*", "home/work/db"));
+        assert!(matches_glob("*db*", "work/db/prod"));
+        assert!(matches_glob("a*b*c", "abc"));
+        assert!(!matches_glob("a*b*c", "acb"));
+        assert!(matches_glob("exact", "exact"));
+        assert!(!matches_glob("exact", "exactly"));
+    }
+
+    #[test]
+    fn test_select_and_copy() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"))
+            .with_master_key(b"master");
+        for key in ["work/db", "work/mail", "home/wifi"] {
+            pm.store_password(key.to_string(), key).unwrap();
+        }
+        pm.set_meta("work/db", TAGS_FIELD, "infra, prod").unwrap();
+        pm.set_meta("home/wifi", TAGS_FIELD, "infra").unwrap();
+
+        assert_eq!(
+            pm.select(Some("work/*"), None),
+            vec!["work/db", "work/mail"]
+        );
+        assert_eq!(pm.select(None, Some("infra")), vec!["home/wifi", "work/db"]);
+        let keys = pm.select(Some("work/*"), Some("infra"));
+        assert_eq!(keys, vec!["work/db"]);
+
+        let mut other = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("bar"))
+            .with_master_key(b"other");
+        pm.copy_into(&keys, &mut other).unwrap();
+        assert_eq!(other.keys(), vec!["work/db"]);
+        assert_eq!(other.get_password("work/db"), Ok("work/db".to_string()));
+        assert_eq!(other.meta("work/db", TAGS_FIELD), Some("infra, prod"));
+        assert_eq!(other.schema().fields(), pm.schema().fields());
+    }
+}
@@ -0,0 +1,225 @@
+//! Time-locked entries, which mopm refuses to show before a given time.
+//!
+//! The lock is a guard in mopm, not encryption: the value is encrypted with
+//! the vault key like any other, and the time is only checked against the
+//! local clock when the entry is decrypted. It keeps a secret from being
+//! used too early by mistake, but anyone with the vault password can read
+//! it by changing the clock, or with another build or another tool.
+
+use thiserror::Error;
+
+use super::{
+    clock,
+    encryptor::Encryprtor,
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+pub const NOT_BEFORE_FIELD: &str = "not_before";
+/// When the credential stops working, for listing; nothing is enforced.
+pub const EXPIRES_FIELD: &str = "expires";
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TimeLockError {
+    #[error(
+        "invalid time `{0}`, expected YYYY-MM-DD, YYYY-MM-DDTHH:MM[:SS] (UTC) or @<unix seconds>"
+    )]
+    InvalidTime(String),
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Keeps the entry under `key` unreadable until `not_before`, in seconds
+    /// since the unix epoch.
+    pub fn lock_until(&mut self, key: &str, not_before: u64) -> Result<(), PasswordManagerError> {
+        self.set_meta(key, NOT_BEFORE_FIELD, &not_before.to_string())
+    }
+
+    pub fn not_before(&self, key: &str) -> Option<u64> {
+        self.kv
+            .get(key)
+            .and_then(|entry| entry.meta(NOT_BEFORE_FIELD))
+            .and_then(|v| v.parse().ok())
+    }
+
+    /// Records that the entry under `key` expires at `expires`, in seconds
+    /// since the unix epoch.
+    pub fn expire_at(&mut self, key: &str, expires: u64) -> Result<(), PasswordManagerError> {
+        self.set_meta(key, EXPIRES_FIELD, &expires.to_string())
+    }
+
+    pub fn expires(&self, key: &str) -> Option<u64> {
+        self.kv
+            .get(key)
+            .and_then(|entry| entry.meta(EXPIRES_FIELD))
+            .and_then(|v| v.parse().ok())
+    }
+
+    pub(in crate::core) fn check_time_lock(&self, key: &str) -> Result<(), PasswordManagerError> {
+        match self.not_before(key) {
+            Some(not_before) if clock::now() < not_before => {
+                Err(PasswordManagerError::TimeLocked(not_before))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses a UTC date or date and time, or `@` followed by unix seconds.
+pub fn parse_time(value: &str) -> Result<u64, TimeLockError> {
+    let invalid = || TimeLockError::InvalidTime(value.to_string());
+    if let Some(secs) = value.strip_prefix('@') {
+        return secs.parse().map_err(|_| invalid());
+    }
+
+    let (date, time) = value.split_once(['T', ' ']).unwrap_or((value, "00:00"));
+    let date: Vec<u64> = numbers(date, '-').ok_or_else(invalid)?;
+    let time: Vec<u64> = numbers(time, ':').ok_or_else(invalid)?;
+    let (&[year, month, day], &[hour, minute, ref second @ ..]) = (&date[..], &time[..]) else {
+        return Err(invalid());
+    };
+    let second = match second {
+        [] => 0,
+        [second] => *second,
+        _ => return Err(invalid()),
+    };
+    if !(1970..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+    Ok(days_from_epoch(year, month, day) * SECS_PER_DAY + hour * 3600 + minute * 60 + second)
+}
+
+/// Formats unix seconds as a UTC date and time.
+pub fn format_time(secs: u64) -> String {
+    let (days, rest) = (secs / SECS_PER_DAY, secs % SECS_PER_DAY);
+    // Inverse of `days_from_epoch`, see Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+fn numbers(value: &str, separator: char) -> Option<Vec<u64>> {
+    value
+        .split(separator)
+        .map(|v| {
+            (!v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| v.parse().ok())
+                .flatten()
+        })
+        .collect()
+}
+
+fn is_leap_year(year: u64) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to the given date, see Howard Hinnant's
+/// `days_from_civil`.
+fn days_from_epoch(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = (month + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("1970-01-01"), Ok(0));
+        assert_eq!(parse_time("2025-01-01"), Ok(1_735_689_600));
+        assert_eq!(parse_time("2024-02-29T12:30"), Ok(1_709_209_800));
+        assert_eq!(parse_time("2024-02-29 12:30:15"), Ok(1_709_209_815));
+        assert_eq!(parse_time("@42"), Ok(42));
+        for invalid in [
+            "2023-02-29",
+            "2025-13-01",
+            "2025-1",
+            "tomorrow",
+            "2025-01-01T24:00",
+            "@-1",
+        ] {
+            assert!(parse_time(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_time(1_709_209_815), "2024-02-29 12:30:15 UTC");
+        let secs = parse_time("2100-12-31T23:59:59").unwrap();
+        assert_eq!(format_time(secs), "2100-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn test_time_lock() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("pw"));
+        pm.store_password("later".to_string(), "a").unwrap();
+        pm.store_password("earlier".to_string(), "b").unwrap();
+        pm.lock_until("later", clock::now() + 3600).unwrap();
+        pm.lock_until("earlier", clock::now() - 1).unwrap();
+
+        assert!(matches!(
+            pm.get_password("later"),
+            Err(PasswordManagerError::TimeLocked(_))
+        ));
+        assert_eq!(pm.get_password("earlier"), Ok("b".to_string()));
+    }
+
+    #[test]
+    fn test_expires() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("pw"));
+        pm.store_password("a".to_string(), "a").unwrap();
+        assert_eq!(pm.expires("a"), None);
+        pm.expire_at("a", clock::now() - 1).unwrap();
+        assert_eq!(pm.expires("a"), Some(clock::now() - 1));
+        assert_eq!(pm.get_password("a"), Ok("a".to_string()));
+    }
+}
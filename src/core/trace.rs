@@ -0,0 +1,172 @@
+//! Timing spans for `--trace`. Spans are only kept once tracing is
+//! enabled, and the report never contains keys or values, only the names
+//! of the steps and how long they took.
+
+use std::{
+    cell::Cell,
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SPANS: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Human,
+    Json,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "human" => Some(Self::Human),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    name: &'static str,
+    depth: usize,
+    start: Duration,
+    duration: Duration,
+}
+
+/// Measures the time until it is dropped. Spans opened while another one
+/// is alive on the same thread are nested under it.
+#[must_use = "the span ends when it is dropped"]
+pub struct Span {
+    name: &'static str,
+    depth: usize,
+    start: Instant,
+}
+
+pub fn span(name: &'static str) -> Span {
+    let depth = DEPTH.with(|v| v.replace(v.get() + 1));
+    let start = Instant::now();
+    ORIGIN.get_or_init(|| start);
+    Span { name, depth, start }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        DEPTH.with(|v| v.set(self.depth));
+        if !enabled() {
+            return;
+        }
+        let origin = *ORIGIN.get_or_init(|| self.start);
+        let record = Record {
+            name: self.name,
+            depth: self.depth,
+            start: self.start.saturating_duration_since(origin),
+            duration: self.start.elapsed(),
+        };
+        if let Ok(mut spans) = SPANS.lock() {
+            spans.push(record);
+        }
+    }
+}
+
+/// Called for `--trace`. Spans that are still open when it is called are
+/// recorded too.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The spans recorded so far in the order they started.
+pub fn report(format: Format) -> String {
+    let mut spans = SPANS.lock().map(|v| v.clone()).unwrap_or_default();
+    spans.sort_by_key(|v| (v.start, v.depth));
+    match format {
+        Format::Human => render_human(&spans),
+        Format::Json => render_json(&spans),
+    }
+}
+
+fn render_human(spans: &[Record]) -> String {
+    let mut out = String::from("Trace:\n");
+    for span in spans {
+        let _ = writeln!(
+            out,
+            "  {:<24}{:>10.2} ms",
+            format!("{}{}", "  ".repeat(span.depth), span.name),
+            millis(span.duration)
+        );
+    }
+    out
+}
+
+fn render_json(spans: &[Record]) -> String {
+    let spans: Vec<serde_json::Value> = spans
+        .iter()
+        .map(|span| {
+            serde_json::json!({
+                "name": span.name,
+                "depth": span.depth,
+                "start_ms": millis(span.start),
+                "duration_ms": millis(span.duration),
+            })
+        })
+        .collect();
+    format!("{}\n", serde_json::Value::from(spans))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &'static str, depth: usize, start: u64, duration: u64) -> Record {
+        Record {
+            name,
+            depth,
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(duration),
+        }
+    }
+
+    #[test]
+    fn test_span_depth() {
+        let outer = span("outer");
+        let inner = span("inner");
+        assert_eq!((outer.depth, inner.depth), (0, 1));
+        drop(inner);
+        let sibling = span("sibling");
+        assert_eq!(sibling.depth, 1);
+        drop(sibling);
+        drop(outer);
+        assert_eq!(span("next").depth, 0);
+    }
+
+    #[test]
+    fn test_render() {
+        let spans = [record("decode", 0, 0, 812), record("kdf", 1, 1, 790)];
+        assert_eq!(
+            render_human(&spans),
+            "Trace:\n  decode                      812.00 ms\n    kdf                       790.00 ms\n"
+        );
+        assert_eq!(
+            render_json(&spans[1..]),
+            "[{\"depth\":1,\"duration_ms\":790.0,\"name\":\"kdf\",\"start_ms\":1.0}]\n"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Test decryption of the entries of a vault, to tell whether a backup
+//! would restore without installing it. Values are checked against their
+//! fingerprints and never leave this module, so access policies are not
+//! asked for.
+
+use super::{ct, encryptor::Encryprtor, fingerprint, manager::PasswordManager, rng};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SampleReport {
+    pub checked: usize,
+    /// Keys whose value cannot be decrypted or does not match its
+    /// fingerprint.
+    pub failed: Vec<String>,
+}
+
+impl<T> PasswordManager<T>
+where
+    T: Encryprtor,
+{
+    /// Decrypts `size` entries picked at random, every entry if `None`.
+    pub fn verify_sample(&mut self, size: Option<usize>) -> SampleReport {
+        let mut keys: Vec<String> = self.kv.keys().cloned().collect();
+        keys.sort_unstable();
+        let size = size.unwrap_or(keys.len()).min(keys.len());
+        for i in 0..size {
+            let j = i + (rng::next_u64() % (keys.len() - i) as u64) as usize;
+            keys.swap(i, j);
+        }
+
+        let mut report = SampleReport::default();
+        for key in keys.into_iter().take(size) {
+            report.checked += 1;
+            let intact = self.decrypt_password(&key).is_ok_and(|value| {
+                let stored = self.kv.get(&key).and_then(|v| v.fingerprint);
+                let actual = fingerprint::fingerprint(&self.fingerprint_key, value.as_bytes());
+                stored.is_none_or(|v| ct::eq(&v, &actual))
+            });
+            if !intact {
+                report.failed.push(key);
+            }
+        }
+        report.failed.sort_unstable();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_verify_sample() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        for key in ["a", "b", "c"] {
+            pm.store_password(key.to_string(), key).unwrap();
+        }
+        assert_eq!(pm.verify_sample(Some(2)).checked, 2);
+        assert_eq!(
+            pm.verify_sample(None),
+            SampleReport {
+                checked: 3,
+                failed: vec![]
+            }
+        );
+
+        pm.kv.get_mut("b").unwrap().value = pm.kv["a"].value.clone();
+        pm.kv.get_mut("c").unwrap().value = b"garbage".to_vec().into();
+        assert_eq!(
+            pm.verify_sample(Some(10)).failed,
+            vec!["b".to_string(), "c".to_string()]
+        );
+    }
+}
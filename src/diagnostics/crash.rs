@@ -0,0 +1,243 @@
+//! Opt-in crash reports. With `--crash-report` or `MOPM_CRASH_REPORT=1`
+//! a panic prints a report that is safe to share and offers to save or
+//! submit it. Nothing is sent anywhere without asking; submitting pipes
+//! the report into `MOPM_CRASH_REPORT_COMMAND`.
+
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    fmt::Write as _,
+    io::{self, BufRead, IsTerminal, Write},
+    panic::PanicHookInfo,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::bug::Bug;
+
+const CRASH_REPORT_ENV: &str = "MOPM_CRASH_REPORT";
+const SUBMIT_COMMAND_ENV: &str = "MOPM_CRASH_REPORT_COMMAND";
+const REDACTED: &str = "<redacted>";
+const PANIC_FRAMES: [&str; 4] = [
+    "std::panicking::",
+    "core::panicking::",
+    "std::panic::",
+    "std::sys::backtrace::",
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the panic hook. Until reports are enabled it behaves like the
+/// default hook, so it can be installed before the arguments are parsed.
+pub fn install() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if !enabled() {
+            return default(info);
+        }
+        let report = CrashReport::capture(info).render();
+        eprintln!("\n{}", report);
+        if let Err(err) = offer(&report) {
+            eprintln!("Cannot handle the crash report: {}", err);
+        }
+    }));
+}
+
+/// Called for `--crash-report`.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed) || std::env::var_os(CRASH_REPORT_ENV).is_some_and(|v| v == "1")
+}
+
+pub struct CrashReport {
+    message: String,
+    location: Option<String>,
+    symbols: Vec<String>,
+}
+
+impl CrashReport {
+    fn capture(info: &PanicHookInfo) -> Self {
+        Self {
+            message: sanitize(info.payload()),
+            location: info
+                .location()
+                .map(|v| format!("{}:{}:{}", v.file(), v.line(), v.column())),
+            symbols: symbols(&Backtrace::force_capture().to_string()),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut report = String::from("mopm crash report\n");
+        let _ = writeln!(report, "version: {}", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(
+            report,
+            "os: {} ({})",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        let _ = writeln!(report, "message: {}", self.message);
+        if let Some(location) = &self.location {
+            let _ = writeln!(report, "location: {}", location);
+        }
+        report.push_str("backtrace:\n");
+        for symbol in &self.symbols {
+            let _ = writeln!(report, "  {}", symbol);
+        }
+        report
+    }
+}
+
+/// Static messages and `Bug`s are kept. Formatted messages may contain
+/// anything, including secrets, so only the part before the first ": "
+/// survives, e.g. "called `Result::unwrap()` on an `Err` value".
+fn sanitize(payload: &(dyn Any + Send)) -> String {
+    if let Some(bug) = payload.downcast_ref::<Bug>() {
+        bug.to_string()
+    } else if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        match message.split_once(": ") {
+            Some((head, _)) => format!("{}: {}", head, REDACTED),
+            None => REDACTED.to_string(),
+        }
+    } else {
+        REDACTED.to_string()
+    }
+}
+
+/// Function names of the backtrace frames, without file paths, starting
+/// below the panic machinery.
+fn symbols(backtrace: &str) -> Vec<String> {
+    let symbols: Vec<String> = backtrace
+        .lines()
+        .filter_map(|line| {
+            let (index, symbol) = line.trim().split_once(": ")?;
+            index
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+                .then(|| symbol.to_string())
+        })
+        .collect();
+    let is_panic_frame = |v: &String| PANIC_FRAMES.iter().any(|prefix| v.starts_with(prefix));
+    let first = symbols.iter().position(is_panic_frame).unwrap_or(0);
+    let start = symbols[first..]
+        .iter()
+        .position(|v| !is_panic_frame(v))
+        .map_or(first, |v| first + v);
+    symbols[start..].to_vec()
+}
+
+fn offer(report: &str) -> io::Result<()> {
+    if !io::stdin().is_terminal() {
+        return Ok(());
+    }
+    eprint!("Save the report to a file [w], submit it [s] or neither [N]? ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "w" => eprintln!("The report was saved to {}", save(report)?.display()),
+        "s" => submit(report)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn save(report: &str) -> io::Result<PathBuf> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |v| v.as_secs());
+    let path = std::env::temp_dir().join(format!("mopm-crash-{}.txt", secs));
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(report.as_bytes())?;
+    Ok(path)
+}
+
+fn submit(report: &str) -> io::Result<()> {
+    let Ok(command) = std::env::var(SUBMIT_COMMAND_ENV) else {
+        eprintln!(
+            "Set {} to a command that reads the report from stdin",
+            SUBMIT_COMMAND_ENV
+        );
+        return Ok(());
+    };
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(report.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "`{}` exited with {}",
+            command, status
+        )));
+    }
+    eprintln!("The report was submitted");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        let bug: Box<dyn Any + Send> = Box::new(Bug {
+            context: "cannot lock",
+            error: "PoisonError",
+        });
+        assert_eq!(sanitize(bug.as_ref()), "cannot lock (PoisonError)");
+
+        let message: Box<dyn Any + Send> = Box::new("static message");
+        assert_eq!(sanitize(message.as_ref()), "static message");
+
+        let message: Box<dyn Any + Send> =
+            Box::new("called `Result::unwrap()` on an `Err` value: hunter2".to_string());
+        let sanitized = sanitize(message.as_ref());
+        assert!(!sanitized.contains("hunter2"));
+        assert!(sanitized.starts_with("called `Result::unwrap()`"));
+
+        let message: Box<dyn Any + Send> = Box::new(42);
+        assert_eq!(sanitize(message.as_ref()), REDACTED);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let backtrace =
+            "   0: mopm::main\n             at ./src/main.rs:10:5\n   1: std::rt::lang_start\n";
+        assert_eq!(symbols(backtrace), ["mopm::main", "std::rt::lang_start"]);
+
+        let backtrace = format!(
+            "   0: std::panicking::begin_panic\n   1: std::panic::panic_any\n{}",
+            backtrace
+        );
+        assert_eq!(symbols(&backtrace), ["mopm::main", "std::rt::lang_start"]);
+    }
+
+    #[test]
+    fn test_render() {
+        let report = CrashReport {
+            message: "boom".to_string(),
+            location: Some("src/main.rs:1:1".to_string()),
+            symbols: vec!["mopm::main".to_string()],
+        };
+        let rendered = report.render();
+        assert!(rendered.contains("message: boom\n"));
+        assert!(rendered.contains("location: src/main.rs:1:1\n"));
+        assert!(rendered.ends_with("backtrace:\n  mopm::main\n"));
+    }
+}
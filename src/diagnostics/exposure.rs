@@ -0,0 +1,104 @@
+//! Ways secrets can leave the memory of mopm, reported by `mopm doctor`:
+//! swap that is not encrypted, core dumps handed to a program, and ptrace
+//! open to every process of the user.
+
+use std::{fs, path::Path};
+
+const SWAPS: &str = "/proc/swaps";
+const CORE_PATTERN: &str = "/proc/sys/kernel/core_pattern";
+const PTRACE_SCOPE: &str = "/proc/sys/kernel/yama/ptrace_scope";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exposure {
+    Swap,
+    CoreDumps,
+    Ptrace,
+}
+
+impl Exposure {
+    pub const ALL: [Exposure; 3] = [Self::Swap, Self::CoreDumps, Self::Ptrace];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Swap => "Swap",
+            Self::CoreDumps => "Core dumps",
+            Self::Ptrace => "Ptrace",
+        }
+    }
+
+    /// Whether a non-dumpable mopm is safe from it, see `memsec`.
+    pub fn mitigated_by_memsec(self) -> bool {
+        self != Self::Swap
+    }
+
+    /// What exposes secrets, `Ok` if nothing does.
+    pub fn check(self) -> Result<(), String> {
+        let read =
+            |path| fs::read_to_string(path).map_err(|err| format!("cannot read {}: {}", path, err));
+        match self {
+            Self::Swap => {
+                let unencrypted = unencrypted_swaps(&read(SWAPS)?, is_dm_crypt);
+                match unencrypted.is_empty() {
+                    true => Ok(()),
+                    false => Err(format!("not encrypted: {}", unencrypted.join(", "))),
+                }
+            }
+            Self::CoreDumps => match read(CORE_PATTERN)?.trim() {
+                handler if handler.starts_with('|') => Err(format!("piped to `{}`", &handler[1..])),
+                _ => Ok(()),
+            },
+            Self::Ptrace => match fs::read_to_string(PTRACE_SCOPE) {
+                Ok(v) if v.trim() != "0" => Ok(()),
+                Ok(_) => Err("any process of the user can attach (ptrace_scope is 0)".to_string()),
+                Err(_) => {
+                    Err("Yama is not enabled, any process of the user can attach".to_string())
+                }
+            },
+        }
+    }
+}
+
+/// The swap areas of a /proc/swaps listing that are neither dm-crypt
+/// devices nor compressed RAM. Swap files are reported too, since the file
+/// system they sit on is not known.
+fn unencrypted_swaps(swaps: &str, is_dm_crypt: impl Fn(&Path) -> bool) -> Vec<String> {
+    swaps
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| !name.starts_with("/dev/zram") && !is_dm_crypt(Path::new(name)))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `device` is a device-mapper target set up by cryptsetup, told by
+/// the `CRYPT-` prefix of its uuid.
+fn is_dm_crypt(device: &Path) -> bool {
+    let Some(name) = device
+        .canonicalize()
+        .ok()
+        .and_then(|v| Some(v.file_name()?.to_string_lossy().into_owned()))
+        .filter(|v| v.starts_with("dm-"))
+    else {
+        return false;
+    };
+    fs::read_to_string(format!("/sys/block/{}/dm/uuid", name))
+        .is_ok_and(|uuid| uuid.starts_with("CRYPT-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unencrypted_swaps() {
+        let swaps = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+            /dev/sda2                               partition\t8388604\t\t0\t\t-2\n\
+            /dev/dm-1                               partition\t8388604\t\t0\t\t-3\n\
+            /dev/zram0                              partition\t4194300\t\t0\t\t100\n\
+            /swapfile                               file\t\t2097148\t\t0\t\t-4\n";
+        let unencrypted = unencrypted_swaps(swaps, |v| v == Path::new("/dev/dm-1"));
+        assert_eq!(unencrypted, ["/dev/sda2", "/swapfile"]);
+        assert!(unencrypted_swaps("Filename Type Size Used Priority\n", |_| false).is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+//! Incident records for the shield. When the honeypot is touched, what is
+//! known about it is encrypted with a key derived from the master password
+//! by Argon2id and appended to a log outside the root, so it survives the
+//! wipe that follows. Every record holds the SHA-256 of the line before it,
+//! so lines removed from the middle of the log or reordered show up when it
+//! is read. Nothing anchors the last line, so lines cut from the end, or
+//! the whole log deleted, go unnoticed: whoever can write the log can hide
+//! the latest incidents, just not rewrite them.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{
+    core::{
+        clock,
+        kdf::{Kdf, KdfError, KdfParams},
+        nonce::NONCE_LENGTH,
+        rng,
+    },
+    diagnostics::bug::OrBug,
+};
+
+const GENESIS: [u8; 32] = [0; 32];
+
+#[derive(Error, Debug)]
+pub enum IncidentError {
+    #[error("cannot derive the incident key: `{0}`")]
+    KdfError(#[from] KdfError),
+    #[error("error while reading/writing the incident log: `{0}`")]
+    IoError(#[from] io::Error),
+    #[error("line {0} of the incident log is malformed")]
+    Malformed(usize),
+    #[error("line {0} of the incident log does not open with this password or was altered")]
+    Undecryptable(usize),
+}
+
+/// The log records are appended to, with the key for them derived once up
+/// front, since there is no time to run Argon2 once the shield triggers.
+pub struct IncidentLog {
+    path: PathBuf,
+    kdf: Kdf,
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl IncidentLog {
+    pub fn new(path: PathBuf, password: &str, params: KdfParams) -> Result<Self, IncidentError> {
+        let kdf = Kdf::argon2id(params)?;
+        let key = Zeroizing::new(kdf.derive(password.as_bytes())?);
+        Ok(Self { path, kdf, key })
+    }
+
+    pub fn append(&self, incident: &Value) -> Result<(), IncidentError> {
+        let prev = match fs::read_to_string(&self.path) {
+            Ok(v) => v.lines().last().map_or(GENESIS, line_hash),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => GENESIS,
+            Err(err) => return Err(err.into()),
+        };
+        let mut nonce = [0; NONCE_LENGTH];
+        rng::fill(&mut nonce);
+        let kdf = self.kdf.to_bytes();
+        let plaintext = json!({ "prev": hex::encode(prev), "incident": incident }).to_string();
+        let ciphertext = Aes256Gcm::new_from_slice(&self.key)
+            .or_bug("argon2 derives keys of the right length")
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: &kdf,
+                },
+            )
+            .or_bug("encrypting into a vec cannot fail");
+        let line = json!({
+            "kdf": hex::encode(kdf),
+            "nonce": hex::encode(nonce),
+            "data": hex::encode(ciphertext),
+        });
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Incident {
+    pub record: Value,
+    /// Whether the record follows the line before it in the log.
+    pub chained: bool,
+}
+
+/// Decrypts every record of the log at `path`.
+pub fn read(path: &Path, password: &str) -> Result<Vec<Incident>, IncidentError> {
+    let log = fs::read_to_string(path)?;
+    let mut keys: Vec<(Kdf, Zeroizing<Vec<u8>>)> = Vec::new();
+    let mut prev = GENESIS;
+    let mut incidents = Vec::new();
+    for (i, line) in log.lines().enumerate() {
+        let number = i + 1;
+        let (kdf, nonce, data) = parse_line(line).ok_or(IncidentError::Malformed(number))?;
+        let index = match keys.iter().position(|(v, _)| *v == kdf) {
+            Some(v) => v,
+            None => {
+                keys.push((kdf, Zeroizing::new(kdf.derive(password.as_bytes())?)));
+                keys.len() - 1
+            }
+        };
+        let key = &keys[index].1;
+        let plaintext = Aes256Gcm::new_from_slice(key)
+            .ok()
+            .and_then(|cipher| {
+                let aad = kdf.to_bytes();
+                let payload = Payload {
+                    msg: &data,
+                    aad: &aad,
+                };
+                cipher.decrypt(&nonce.into(), payload).ok()
+            })
+            .ok_or(IncidentError::Undecryptable(number))?;
+        let mut plaintext: Value =
+            serde_json::from_slice(&plaintext).map_err(|_| IncidentError::Malformed(number))?;
+        incidents.push(Incident {
+            chained: plaintext["prev"].as_str() == Some(&hex::encode(prev)),
+            record: plaintext["incident"].take(),
+        });
+        prev = line_hash(line);
+    }
+    Ok(incidents)
+}
+
+fn parse_line(line: &str) -> Option<(Kdf, [u8; NONCE_LENGTH], Vec<u8>)> {
+    let line: Value = serde_json::from_str(line).ok()?;
+    let field = |name: &str| hex::decode(line[name].as_str()?).ok();
+    let kdf = Kdf::try_from_bytes(&field("kdf")?.try_into().ok()?).ok()?;
+    kdf.params()?;
+    Some((kdf, field("nonce")?.try_into().ok()?, field("data")?))
+}
+
+fn line_hash(line: &str) -> [u8; 32] {
+    Sha256::digest(line.as_bytes()).into()
+}
+
+/// What is known about a trigger of the shield on `path`: the time, the
+/// inotify event and the processes that hold the file open.
+pub fn capture(path: &Path, event: &str) -> Value {
+    json!({
+        "time": clock::now(),
+        "path": path.to_string_lossy(),
+        "event": event,
+        "processes": holders(path),
+    })
+}
+
+/// Scans `/proc/<pid>/fd` for processes with `path` open, under any name
+/// since the shield mounts it over the root. Processes of other users are
+/// only visible to root.
+fn holders(path: &Path) -> Vec<Value> {
+    let (Ok(target), Ok(procs)) = (fs::metadata(path), fs::read_dir("/proc")) else {
+        return vec![];
+    };
+    let is_target = |v: fs::Metadata| v.dev() == target.dev() && v.ino() == target.ino();
+    let own = std::process::id().to_string();
+    procs
+        .flatten()
+        .map(|v| v.path())
+        .filter(|v| {
+            v.file_name()
+                .and_then(|v| v.to_str())
+                .is_some_and(|v| v != own && v.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .filter(|v| {
+            fs::read_dir(v.join("fd")).is_ok_and(|fds| {
+                fds.flatten()
+                    .any(|fd| fs::metadata(fd.path()).is_ok_and(is_target))
+            })
+        })
+        .map(|v| process_info(&v))
+        .collect()
+}
+
+fn process_info(dir: &Path) -> Value {
+    let read = |name: &str| fs::read(dir.join(name)).unwrap_or_default();
+    let status = String::from_utf8_lossy(&read("status")).into_owned();
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|v| v.strip_prefix(name))
+            .and_then(|v| v.split_whitespace().next())
+            .map(str::to_string)
+    };
+    let cmdline: Vec<String> = read("cmdline")
+        .split(|b| *b == 0)
+        .filter(|v| !v.is_empty())
+        .map(|v| String::from_utf8_lossy(v).into_owned())
+        .collect();
+    json!({
+        "pid": dir.file_name().map(|v| v.to_string_lossy()),
+        "ppid": field("PPid:"),
+        "uid": field("Uid:"),
+        "comm": String::from_utf8_lossy(&read("comm")).trim(),
+        "cmdline": cmdline,
+        "exe": fs::read_link(dir.join("exe")).ok(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PARAMS: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn log_path(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("mopm-incident-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("incidents")
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let path = log_path("read");
+        let log = IncidentLog::new(path.clone(), "pw", TEST_PARAMS).unwrap();
+        log.append(&json!({ "n": 1 })).unwrap();
+        IncidentLog::new(path.clone(), "pw", TEST_PARAMS)
+            .unwrap()
+            .append(&json!({ "n": 2 }))
+            .unwrap();
+
+        let incidents = read(&path, "pw").unwrap();
+        assert_eq!(incidents.len(), 2);
+        assert_eq!(incidents[1].record, json!({ "n": 2 }));
+        assert!(incidents.iter().all(|v| v.chained));
+        assert!(matches!(
+            read(&path, "wrong"),
+            Err(IncidentError::Undecryptable(1))
+        ));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_removed_line_breaks_chain() {
+        let path = log_path("chain");
+        let log = IncidentLog::new(path.clone(), "pw", TEST_PARAMS).unwrap();
+        for n in 0..3 {
+            log.append(&json!(n)).unwrap();
+        }
+        let lines: Vec<String> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+
+        let chained: Vec<bool> = read(&path, "pw")
+            .unwrap()
+            .iter()
+            .map(|v| v.chained)
+            .collect();
+        assert_eq!(chained, vec![true, false]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_capture() {
+        let path = log_path("capture");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "").unwrap();
+        assert_eq!(capture(&path, "open")["processes"], json!([]));
+
+        let mut holder = std::process::Command::new("sleep")
+            .arg("10")
+            .stdin(fs::File::open(&path).unwrap())
+            .spawn()
+            .unwrap();
+        let record = capture(&path, "open");
+        holder.kill().unwrap();
+        holder.wait().unwrap();
+        assert_eq!(record["event"], "open");
+        assert_eq!(record["processes"][0]["pid"], holder.id().to_string());
+        assert_eq!(record["processes"][0]["comm"], "sleep");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
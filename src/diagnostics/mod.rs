@@ -0,0 +1,5 @@
+// In the library, so that the vault format reports bugs the same way.
+pub use crate::core::bug;
+pub mod crash;
+pub mod exposure;
+pub mod incident;
@@ -0,0 +1,376 @@
+//! The restrictions of the commands mopm runs while the vault is unlocked:
+//! hooks, the commands of leased secrets and pinentry. They run as the
+//! user, so they can read what the user can, but they should not carry a
+//! secret off the machine or out of the memory of mopm by accident:
+//!
+//! - the environment is reduced to a list of variables, it never holds
+//!   the password of a `--password-source=env:` or a session key,
+//! - no new privileges, setuid programs run with the privileges of the
+//!   user,
+//! - a seccomp filter refuses the sockets of any family but unix, unless
+//!   the command declared that it needs the network, and ptrace,
+//!   process_vm_readv and pidfd_getfd, which read other processes, and
+//!   io_uring, which opens sockets around the filter,
+//! - without the network, a Landlock scope refuses connections to
+//!   abstract unix sockets, which no file permission guards.
+//!
+//! The filter is inherited by everything the command starts and cannot be
+//! removed. It is only built for x86_64 and aarch64, elsewhere commands
+//! keep their network access, which `mopm doctor` reports. The scope needs
+//! Linux 6.12, older kernels run the command without it, which `mopm
+//! doctor` reports as well.
+//!
+//! This does not contain a hostile command. Unix sockets with a path stay
+//! reachable, seccomp cannot see which one is connected to: through the
+//! D-Bus session bus at `/run/user/$UID/bus` a command can have systemd or
+//! another service of the user start a process outside the sandbox, with
+//! the network. It can also write what the user can, e.g. a systemd user
+//! unit or an autostart entry that runs at the next login. Only hooks and
+//! commands the user trusts belong in the config file.
+//!
+//! mopm and its children may also be confined by an AppArmor profile or
+//! an SELinux domain, which `confinement` reads for `mopm doctor`.
+
+use std::{
+    fs, io,
+    os::unix::process::CommandExt,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use nix::libc;
+
+/// Architectures of `seccomp_data.arch` from linux/audit.h.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Offsets of the fields of `seccomp_data`.
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const ARG0_OFFSET: u32 = 16;
+/// Numbers of the x32 ABI of x86_64, which the filter would not match.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+const DENIED: [libc::c_long; 5] = [
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_pidfd_getfd,
+    libc::SYS_io_uring_setup,
+];
+
+/// From linux/landlock.h: the version query of `landlock_create_ruleset`,
+/// and the scope of abstract unix sockets, added in ABI 6.
+const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1 << 0;
+const LANDLOCK_SCOPE_ABSTRACT_UNIX_SOCKET: u64 = 1 << 0;
+const LANDLOCK_SCOPE_ABI: libc::c_long = 6;
+
+/// `struct landlock_ruleset_attr`, which the libc crate does not define.
+#[repr(C)]
+struct LandlockRulesetAttr {
+    handled_access_fs: u64,
+    handled_access_net: u64,
+    scoped: u64,
+}
+
+const APPARMOR_LABEL: &str = "/proc/self/attr/apparmor/current";
+const LSM_LABEL: &str = "/proc/self/attr/current";
+const SELINUX_FS: &str = "/sys/fs/selinux";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    env: &'static [&'static str],
+    network: bool,
+}
+
+impl Sandbox {
+    /// A sandbox passing the variables of `env` alone, without network.
+    pub fn new(env: &'static [&'static str]) -> Self {
+        Self {
+            env,
+            network: false,
+        }
+    }
+
+    /// Whether the command may open sockets other than unix ones.
+    pub fn network(mut self, allowed: bool) -> Self {
+        self.network = allowed;
+        self
+    }
+
+    /// Runs `command` in the sandbox. Spawning fails if it cannot be set
+    /// up, the command never runs without it.
+    pub fn apply<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        command.env_clear().envs(
+            self.env
+                .iter()
+                .filter_map(|k| Some((k, std::env::var_os(k)?))),
+        );
+        // Built before the fork: the child may not allocate.
+        let filter = AUDIT_ARCH.map(|arch| filter(arch, self.network));
+        let network = self.network;
+        // SAFETY: the closure only makes system calls, which are
+        // async-signal-safe, on memory allocated before the fork.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if !network {
+                    scope_abstract_sockets()?;
+                }
+                if let Some(filter) = &filter {
+                    let program = libc::sock_fprog {
+                        len: filter.len() as libc::c_ushort,
+                        filter: filter.as_ptr() as *mut libc::sock_filter,
+                    };
+                    let mode = libc::SECCOMP_MODE_FILTER as libc::c_ulong;
+                    if libc::prctl(libc::PR_SET_SECCOMP, mode, &program, 0, 0) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+/// Restricts the calling process and its children to the abstract unix
+/// sockets they create themselves. Kernels without the scope are left
+/// as they are.
+///
+/// # Safety
+///
+/// Called between fork and exec: it only makes system calls.
+unsafe fn scope_abstract_sockets() -> io::Result<()> {
+    let attr = LandlockRulesetAttr {
+        handled_access_fs: 0,
+        handled_access_net: 0,
+        scoped: LANDLOCK_SCOPE_ABSTRACT_UNIX_SOCKET,
+    };
+    let ruleset = libc::syscall(
+        libc::SYS_landlock_create_ruleset,
+        &attr as *const LandlockRulesetAttr,
+        std::mem::size_of::<LandlockRulesetAttr>(),
+        0,
+    );
+    if ruleset < 0 {
+        return match io::Error::last_os_error().raw_os_error() {
+            Some(libc::ENOSYS | libc::EOPNOTSUPP | libc::EINVAL | libc::E2BIG) => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        };
+    }
+    let restricted = libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0);
+    let error = io::Error::last_os_error();
+    libc::close(ruleset as libc::c_int);
+    match restricted {
+        0 => Ok(()),
+        _ => Err(error),
+    }
+}
+
+/// Whether the kernel scopes abstract unix sockets, see
+/// `scope_abstract_sockets`.
+pub fn scopes_abstract_sockets() -> bool {
+    // SAFETY: the version query reads no memory.
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<LandlockRulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    abi >= LANDLOCK_SCOPE_ABI
+}
+
+/// Whether commands can be sandboxed here, checked by running `true` in
+/// the sandbox.
+pub fn check() -> Result<(), String> {
+    if AUDIT_ARCH.is_none() {
+        return Err("no seccomp filter for this architecture, commands keep the network".into());
+    }
+    let status = Sandbox::new(&[])
+        .apply(&mut Command::new("true"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(v) if v.success() => Ok(()),
+        Ok(v) => Err(format!("a sandboxed command failed: {}", v)),
+        Err(err) => Err(format!("cannot sandbox commands: {}", err)),
+    }
+}
+
+/// The security module confining mopm and its label, `None` when it is
+/// unconfined.
+pub fn confinement() -> Option<(&'static str, String)> {
+    if let Ok(label) = fs::read_to_string(APPARMOR_LABEL) {
+        return confined_label("apparmor", &label).map(|v| ("apparmor", v));
+    }
+    let label = fs::read_to_string(LSM_LABEL).ok()?;
+    let module = match Path::new(SELINUX_FS).exists() {
+        true => "selinux",
+        false => "apparmor",
+    };
+    confined_label(module, &label).map(|v| (module, v))
+}
+
+fn confined_label(module: &str, label: &str) -> Option<String> {
+    let label = label.trim_end_matches(['\0', '\n']);
+    let unconfined = match module {
+        "selinux" => label.split(':').nth(2) == Some("unconfined_t"),
+        _ => label == "unconfined",
+    };
+    match label.is_empty() || unconfined {
+        true => None,
+        false => Some(label.to_string()),
+    }
+}
+
+fn filter(arch: u32, network: bool) -> Vec<libc::sock_filter> {
+    let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+    let mut filter = vec![
+        load(ARCH_OFFSET),
+        jump(libc::BPF_JEQ, arch, 1, 0),
+        ret(libc::SECCOMP_RET_KILL_PROCESS),
+        load(NR_OFFSET),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        jump(libc::BPF_JGE, X32_SYSCALL_BIT, 0, 1),
+        ret(libc::SECCOMP_RET_KILL_PROCESS),
+    ]);
+    for nr in DENIED {
+        filter.extend([jump(libc::BPF_JEQ, nr as u32, 0, 1), ret(deny)]);
+    }
+    if !network {
+        filter.extend([
+            jump(libc::BPF_JEQ, libc::SYS_socket as u32, 0, 3),
+            load(ARG0_OFFSET),
+            jump(libc::BPF_JEQ, libc::AF_UNIX as u32, 1, 0),
+            ret(libc::SECCOMP_RET_ERRNO | libc::EACCES as u32),
+        ]);
+    }
+    filter.push(ret(libc::SECCOMP_RET_ALLOW));
+    filter
+}
+
+fn load(offset: u32) -> libc::sock_filter {
+    statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset)
+}
+
+fn ret(value: u32) -> libc::sock_filter {
+    statement(libc::BPF_RET | libc::BPF_K, value)
+}
+
+fn statement(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(condition: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: (libc::BPF_JMP | condition | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(sandbox: &Sandbox, script: &str) -> String {
+        let output = sandbox
+            .apply(Command::new("sh").args(["-c", script]))
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_sandbox() {
+        if AUDIT_ARCH.is_none() {
+            return;
+        }
+        let status = run(&Sandbox::new(&["PATH"]), "cat /proc/self/status");
+        assert!(status.contains("NoNewPrivs:\t1"));
+        assert!(status.contains("Seccomp:\t2"));
+        assert!(check().is_ok());
+    }
+
+    #[test]
+    fn test_abstract_sockets() {
+        use std::os::{linux::net::SocketAddrExt, unix::net};
+
+        let python = Command::new("python3").args(["-c", ""]).status();
+        if !scopes_abstract_sockets() || !python.is_ok_and(|v| v.success()) {
+            return;
+        }
+        let name = format!("mopm-sandbox-test-{}", std::process::id());
+        let address = net::SocketAddr::from_abstract_name(&name).unwrap();
+        let _listener = net::UnixListener::bind_addr(&address).unwrap();
+        let script = format!(
+            "import socket\ns = socket.socket(socket.AF_UNIX)\n\
+             try:\n    s.connect('\\0{}')\n    print('connected')\n\
+             except OSError:\n    print('refused')\n",
+            name
+        );
+        let connect = |sandbox: &Sandbox| {
+            let output = sandbox
+                .apply(Command::new("python3").args(["-c", &script]))
+                .output()
+                .unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+        assert_eq!(connect(&Sandbox::new(&["PATH"])), "refused\n");
+        assert_eq!(
+            connect(&Sandbox::new(&["PATH"]).network(true)),
+            "connected\n"
+        );
+    }
+
+    #[test]
+    fn test_env() {
+        std::env::set_var("MOPM_SANDBOX_TEST_SECRET", "leak");
+        let sandbox = Sandbox::new(&["PATH"]);
+        assert_eq!(run(&sandbox, "echo \"$MOPM_SANDBOX_TEST_SECRET\""), "\n");
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = filter(0xC000_003E, true);
+        assert_eq!(filter.last().unwrap().k, libc::SECCOMP_RET_ALLOW);
+        assert_eq!(super::filter(0xC000_003E, false).len(), filter.len() + 4);
+    }
+
+    #[test]
+    fn test_confined_label() {
+        assert_eq!(confined_label("apparmor", "unconfined\n"), None);
+        assert_eq!(
+            confined_label("apparmor", "mopm (enforce)\n"),
+            Some("mopm (enforce)".to_string())
+        );
+        assert_eq!(
+            confined_label("selinux", "unconfined_u:unconfined_r:unconfined_t:s0\0"),
+            None
+        );
+        assert_eq!(
+            confined_label("selinux", "user_u:user_r:mopm_t:s0\0"),
+            Some("user_u:user_r:mopm_t:s0".to_string())
+        );
+        assert_eq!(confined_label("apparmor", ""), None);
+    }
+}
@@ -0,0 +1,471 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    sync::Mutex,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use fuser::{
+    Errno, FileAttr, FileHandle, FileType, Filesystem, FopenFlags, Generation, INodeNo, LockOwner,
+    OpenFlags, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
+    ReplyOpen, ReplyWrite, Request, TimeOrNow, WriteFlags,
+};
+
+use crate::{
+    core::{encryptor::DynamicEncryptor, entry, manager::PasswordManager, policy::Unattended},
+    diagnostics::bug::OrBug,
+    storage::{backup::BackupPolicy, store::Storage},
+};
+
+const TTL: Duration = Duration::ZERO;
+const FIRST_ENTRY_INO: u64 = 2;
+const LOCK_POISONED: &str = "a filesystem operation panicked while holding the vault";
+
+/// Exposes every vault entry as a regular file in a flat directory.
+///
+/// Values are decrypted only when a file is read. Writes are buffered per
+/// inode and re-encrypted into the vault when the file is flushed.
+pub struct VaultFs {
+    uid: u32,
+    gid: u32,
+    state: Mutex<State>,
+}
+
+struct State {
+    pm: PasswordManager<DynamicEncryptor>,
+    names: Vec<String>,
+    dirty: HashMap<u64, Vec<u8>>,
+    backups: BackupPolicy,
+}
+
+impl VaultFs {
+    pub fn new(pm: PasswordManager<DynamicEncryptor>, backups: BackupPolicy) -> Self {
+        // Reads come from other processes, there is nobody to ask, so
+        // entries with an access policy stay unreadable.
+        let pm = pm.with_guard(Box::new(Unattended));
+        let names = pm
+            .keys()
+            .into_iter()
+            .filter(|key| !key.is_empty() && !key.contains('/'))
+            .map(String::from)
+            .collect();
+
+        Self {
+            uid: nix::unistd::getuid().as_raw(),
+            gid: nix::unistd::getgid().as_raw(),
+            state: Mutex::new(State {
+                pm,
+                names,
+                dirty: HashMap::new(),
+                backups,
+            }),
+        }
+    }
+
+    fn is_owner(&self, req: &Request) -> bool {
+        req.uid() == self.uid
+    }
+
+    fn attr(&self, ino: u64, kind: FileType, size: u64) -> FileAttr {
+        FileAttr {
+            ino: INodeNo(ino),
+            size,
+            blocks: size.div_ceil(512),
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm: if kind == FileType::Directory {
+                0o700
+            } else {
+                0o600
+            },
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        }
+    }
+}
+
+impl State {
+    fn name(&self, ino: u64) -> Option<&str> {
+        let index = ino.checked_sub(FIRST_ENTRY_INO)?;
+        self.names.get(index as usize).map(String::as_str)
+    }
+
+    fn ino(&self, name: &str) -> Option<u64> {
+        self.names
+            .iter()
+            .position(|v| v == name)
+            .map(|index| index as u64 + FIRST_ENTRY_INO)
+    }
+
+    fn contents(&mut self, ino: u64) -> Option<Vec<u8>> {
+        if let Some(buf) = self.dirty.get(&ino) {
+            return Some(buf.clone());
+        }
+        let name = self.name(ino)?.to_string();
+        self.pm.get_password(&name).ok().map(String::into_bytes)
+    }
+
+    /// Sets the length of the buffer of `ino`, returning it.
+    fn truncate(&mut self, ino: u64, size: u64) -> Result<usize, Errno> {
+        let size = value_length(size)?;
+        let mut data = self.contents(ino).ok_or(Errno::ENOENT)?;
+        data.resize(size, 0);
+        self.dirty.insert(ino, data);
+        Ok(size)
+    }
+
+    /// Writes `data` at `offset` into the buffer of `ino`, returning the
+    /// length of the buffer.
+    fn write(&mut self, ino: u64, offset: u64, data: &[u8]) -> Result<usize, Errno> {
+        let end = value_length(offset.saturating_add(data.len() as u64))?;
+        let mut buf = self.contents(ino).ok_or(Errno::ENOENT)?;
+        if buf.len() < end {
+            buf.resize(end, 0);
+        }
+        buf[end - data.len()..end].copy_from_slice(data);
+        let len = buf.len();
+        self.dirty.insert(ino, buf);
+        Ok(len)
+    }
+
+    fn create(&mut self, name: &str) -> Result<u64, Errno> {
+        if name.len() > entry::MAX_KEY_LENGTH {
+            return Err(Errno::ENAMETOOLONG);
+        }
+        let ino = match self.ino(name) {
+            Some(ino) => ino,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() as u64 - 1 + FIRST_ENTRY_INO
+            }
+        };
+        self.dirty.insert(ino, Vec::new());
+        Ok(ino)
+    }
+
+    /// Moves the buffer of `ino` into the vault, returning whether there
+    /// was one. A buffer that is not a valid value is dropped.
+    fn stage(&mut self, ino: u64) -> Result<bool, Errno> {
+        let Some(buf) = self.dirty.remove(&ino) else {
+            return Ok(false);
+        };
+        let name = self.name(ino).ok_or(Errno::ENOENT)?.to_string();
+        let value = String::from_utf8(buf).or(Err(Errno::EINVAL))?;
+
+        self.pm.store_password(name, &value).or(Err(Errno::EIO))?;
+        Ok(true)
+    }
+
+    fn commit(&mut self, ino: u64) -> Result<(), Errno> {
+        match self.stage(ino)? {
+            true => Storage::save(&mut self.pm, &self.backups).or(Err(Errno::EIO)),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Values are capped like everywhere else, so that a write far past the
+/// end of a file cannot make the filesystem allocate the gap.
+fn value_length(size: u64) -> Result<usize, Errno> {
+    match usize::try_from(size) {
+        Ok(v) if v <= entry::MAX_VALUE_LENGTH => Ok(v),
+        _ => Err(Errno::EFBIG),
+    }
+}
+
+impl Filesystem for VaultFs {
+    fn lookup(&self, req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        let mut state = self.state.lock().or_bug(LOCK_POISONED);
+        let ino = match (parent, name.to_str()) {
+            (INodeNo::ROOT, Some(name)) => state.ino(name),
+            _ => None,
+        };
+        match ino.and_then(|ino| Some((ino, state.contents(ino)?))) {
+            Some((ino, data)) => reply.entry(
+                &TTL,
+                &self.attr(ino, FileType::RegularFile, data.len() as u64),
+                Generation(0),
+            ),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn getattr(&self, req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        if ino == INodeNo::ROOT {
+            return reply.attr(&TTL, &self.attr(ino.0, FileType::Directory, 0));
+        }
+        match self.state.lock().or_bug(LOCK_POISONED).contents(ino.0) {
+            Some(data) => reply.attr(
+                &TTL,
+                &self.attr(ino.0, FileType::RegularFile, data.len() as u64),
+            ),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn setattr(
+        &self,
+        req: &Request,
+        ino: INodeNo,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<std::time::SystemTime>,
+        _fh: Option<FileHandle>,
+        _crtime: Option<std::time::SystemTime>,
+        _chgtime: Option<std::time::SystemTime>,
+        _bkuptime: Option<std::time::SystemTime>,
+        _flags: Option<fuser::BsdFileFlags>,
+        reply: ReplyAttr,
+    ) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        let mut state = self.state.lock().or_bug(LOCK_POISONED);
+        let len = match size {
+            Some(size) => state.truncate(ino.0, size),
+            None => state.contents(ino.0).map(|v| v.len()).ok_or(Errno::ENOENT),
+        };
+        match len {
+            Ok(len) => reply.attr(&TTL, &self.attr(ino.0, FileType::RegularFile, len as u64)),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn open(&self, req: &Request, ino: INodeNo, _flags: OpenFlags, reply: ReplyOpen) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        if self
+            .state
+            .lock()
+            .or_bug(LOCK_POISONED)
+            .name(ino.0)
+            .is_none()
+        {
+            return reply.error(Errno::ENOENT);
+        }
+        reply.opened(FileHandle(0), FopenFlags::FOPEN_DIRECT_IO)
+    }
+
+    fn read(
+        &self,
+        req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        let Some(data) = self.state.lock().or_bug(LOCK_POISONED).contents(ino.0) else {
+            return reply.error(Errno::ENOENT);
+        };
+        let start = (offset as usize).min(data.len());
+        let end = (start + size as usize).min(data.len());
+        reply.data(&data[start..end])
+    }
+
+    fn write(
+        &self,
+        req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        data: &[u8],
+        _write_flags: WriteFlags,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyWrite,
+    ) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        match self
+            .state
+            .lock()
+            .or_bug(LOCK_POISONED)
+            .write(ino.0, offset, data)
+        {
+            Ok(_) => reply.written(data.len() as u32),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn flush(
+        &self,
+        req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        _lock_owner: LockOwner,
+        reply: ReplyEmpty,
+    ) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        match self.state.lock().or_bug(LOCK_POISONED).commit(ino.0) {
+            Ok(_) => reply.ok(),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn create(
+        &self,
+        req: &Request,
+        parent: INodeNo,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        let Some(name) = name.to_str().filter(|_| parent == INodeNo::ROOT) else {
+            return reply.error(Errno::EINVAL);
+        };
+        let ino = match self.state.lock().or_bug(LOCK_POISONED).create(name) {
+            Ok(ino) => ino,
+            Err(err) => return reply.error(err),
+        };
+        reply.created(
+            &TTL,
+            &self.attr(ino, FileType::RegularFile, 0),
+            Generation(0),
+            FileHandle(0),
+            FopenFlags::FOPEN_DIRECT_IO,
+        )
+    }
+
+    fn readdir(
+        &self,
+        req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        if !self.is_owner(req) {
+            return reply.error(Errno::EACCES);
+        }
+        if ino != INodeNo::ROOT {
+            return reply.error(Errno::ENOENT);
+        }
+        let state = self.state.lock().or_bug(LOCK_POISONED);
+        let entries = [
+            (ino.0, FileType::Directory, "."),
+            (ino.0, FileType::Directory, ".."),
+        ]
+        .into_iter()
+        .chain(state.names.iter().enumerate().map(|(index, name)| {
+            (
+                index as u64 + FIRST_ENTRY_INO,
+                FileType::RegularFile,
+                name.as_str(),
+            )
+        }));
+
+        for (i, (ino, kind, name)) in entries.enumerate().skip(offset as usize) {
+            if reply.add(INodeNo(ino), (i + 1) as u64, kind, name) {
+                break;
+            }
+        }
+        reply.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io::Cursor};
+
+    use crate::core::{
+        encoder::{Encoder, EncoderError},
+        encryptor::AESEncryptor,
+        keycache::KeyCache,
+    };
+
+    use super::*;
+
+    fn vault_fs() -> VaultFs {
+        let encryptor = DynamicEncryptor(1, Box::new(AESEncryptor::new("foobar")));
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), encryptor);
+        pm.store_password("db".to_string(), "it's db").unwrap();
+        VaultFs::new(pm, BackupPolicy::default())
+    }
+
+    fn encode(state: &mut State) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        Encoder::encode(&mut bytes, &mut state.pm).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let fs = vault_fs();
+        let mut state = fs.state.lock().unwrap();
+        let db = state.ino("db").unwrap();
+        assert_eq!(state.write(db, 5, b"DB"), Ok(7));
+        assert_eq!(state.contents(db).unwrap(), b"it's DB");
+        let new = state.create("new").unwrap();
+        assert_eq!(state.write(new, 2, b"ok"), Ok(4));
+        assert_eq!(state.truncate(new, 3), Ok(3));
+        assert_eq!(state.stage(db), Ok(true));
+        assert_eq!(state.stage(new), Ok(true));
+        assert_eq!(state.stage(new), Ok(false));
+
+        let bytes = encode(&mut state);
+        let mut pm =
+            Encoder::decode(b"foobar", &mut Cursor::new(bytes), &mut KeyCache::default()).unwrap();
+        assert_eq!(pm.get_password("db"), Ok("it's DB".to_string()));
+        assert_eq!(pm.get_password("new"), Ok("\0\0o".to_string()));
+    }
+
+    #[test]
+    fn test_corruption() {
+        let fs = vault_fs();
+        let mut state = fs.state.lock().unwrap();
+        let db = state.ino("db").unwrap();
+        let max = entry::MAX_VALUE_LENGTH as u64;
+        assert_eq!(state.write(db, max, b"x"), Err(Errno::EFBIG));
+        assert_eq!(state.write(db, u64::MAX, b"x"), Err(Errno::EFBIG));
+        assert_eq!(state.truncate(db, max + 1), Err(Errno::EFBIG));
+        assert_eq!(
+            state.create(&"x".repeat(entry::MAX_KEY_LENGTH + 1)),
+            Err(Errno::ENAMETOOLONG)
+        );
+        assert_eq!(state.write(99, 0, b"x"), Err(Errno::ENOENT));
+
+        state.write(db, 0, &[0xff]).unwrap();
+        assert_eq!(state.stage(db), Err(Errno::EINVAL));
+        assert_eq!(state.contents(db).unwrap(), b"it's db");
+
+        let mut bytes = encode(&mut state);
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(
+            Encoder::decode(b"foobar", &mut Cursor::new(bytes), &mut KeyCache::default()),
+            Err(EncoderError::IvalidKeyError)
+        ));
+    }
+}
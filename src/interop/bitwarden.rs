@@ -0,0 +1,127 @@
+//! Unencrypted JSON export in the format read by Bitwarden's importer.
+//!
+//! The namespace of a key becomes its folder (`work/mail` is the login
+//! `mail` in the folder `work`, nested folders keep their slashes) and the
+//! `username`, `url` and `notes` metadata fill the matching login fields.
+
+use std::collections::BTreeSet;
+
+use serde_json::{json, Value};
+
+use crate::core::{
+    encryptor::Encryprtor,
+    hasher::{Hasher, Sha256Hasher},
+    identity::{self, ID_LENGTH},
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+const LOGIN_TYPE: u8 = 1;
+
+pub fn export<T: Encryprtor>(pm: &mut PasswordManager<T>) -> Result<Value, PasswordManagerError> {
+    let keys: Vec<String> = pm
+        .keys()
+        .into_iter()
+        .filter(|key| !pm.is_dynamic(key))
+        .map(String::from)
+        .collect();
+
+    let folders: BTreeSet<&str> = keys.iter().filter_map(|key| split(key).0).collect();
+    let folders: Vec<Value> = folders
+        .into_iter()
+        .map(|name| json!({ "id": stable_id(pm, "folder", name), "name": name }))
+        .collect();
+
+    let mut items = vec![];
+    for key in &keys {
+        let (folder, name) = split(key);
+        let uris: Vec<Value> = pm
+            .meta(key, "url")
+            .map(|uri| json!({ "match": null, "uri": uri }))
+            .into_iter()
+            .collect();
+        items.push(json!({
+            "id": stable_id(pm, "item", key),
+            "organizationId": null,
+            "folderId": folder.map(|v| stable_id(pm, "folder", v)),
+            "type": LOGIN_TYPE,
+            "reprompt": 0,
+            "name": name,
+            "notes": pm.meta(key, "notes"),
+            "favorite": false,
+            "login": {
+                "uris": uris,
+                "username": pm.meta(key, "username"),
+                "password": pm.get_password(key)?,
+                "totp": null,
+            },
+            "collectionIds": null,
+        }));
+    }
+
+    Ok(json!({ "encrypted": false, "folders": folders, "items": items }))
+}
+
+fn split(key: &str) -> (Option<&str>, &str) {
+    match key.rsplit_once('/') {
+        Some((folder, name)) if !folder.is_empty() => (Some(folder), name),
+        _ => (None, key),
+    }
+}
+
+/// Ids are derived from the vault id so that exporting twice gives the same
+/// ids and re-importing updates items instead of duplicating them.
+fn stable_id<T: Encryprtor>(pm: &PasswordManager<T>, kind: &str, name: &str) -> String {
+    let mut input = pm.vault_id().to_vec();
+    input.extend_from_slice(format!("bitwarden:{}:{}", kind, name).as_bytes());
+    let digest = Sha256Hasher::new().hash(&input);
+    let id: [u8; ID_LENGTH] = digest[..ID_LENGTH]
+        .try_into()
+        .expect("sha256 digest is longer than the id");
+    identity::format_id(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("mail"), (None, "mail"));
+        assert_eq!(split("work/mail"), (Some("work"), "mail"));
+        assert_eq!(split("a/b/c"), (Some("a/b"), "c"));
+        assert_eq!(split("/root"), (None, "/root"));
+    }
+
+    #[test]
+    fn test_export() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let _ = pm.store_password("work/mail".to_owned(), "a");
+        let _ = pm.store_password("bank".to_owned(), "b");
+        let _ = pm.store_dynamic("token".to_owned(), "echo c", 60, false);
+        let _ = pm.set_meta("work/mail", "url", "https://mail.example.com");
+        let _ = pm.set_meta("work/mail", "username", "me");
+
+        let export = export(&mut pm).unwrap();
+        assert_eq!(export["encrypted"], false);
+        assert_eq!(export["folders"].as_array().unwrap().len(), 1);
+        assert_eq!(export["folders"][0]["name"], "work");
+
+        let items = export["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["name"], "bank");
+        assert_eq!(items[0]["folderId"], Value::Null);
+        assert_eq!(items[1]["name"], "mail");
+        assert_eq!(items[1]["folderId"], export["folders"][0]["id"]);
+        assert_eq!(items[1]["login"]["password"], "a");
+        assert_eq!(items[1]["login"]["username"], "me");
+        assert_eq!(
+            items[1]["login"]["uris"][0]["uri"],
+            "https://mail.example.com"
+        );
+        assert_eq!(export, super::export(&mut pm).unwrap());
+    }
+}
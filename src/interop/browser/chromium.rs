@@ -0,0 +1,216 @@
+//! Chromium keeps logins in the `Login Data` SQLite database. Passwords are
+//! encrypted with AES-128-CBC under a key derived from a secret held by the
+//! platform keyring, tagged with a `v10`/`v11` prefix.
+
+use std::{collections::HashMap, path::Path, process::Command};
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use sha1::Sha1;
+
+use super::{BrowserError, Login};
+
+pub const LOGIN_DATA: &str = "Login Data";
+
+const SALT: &[u8] = b"saltysalt";
+const IV: [u8; 16] = [b' '; 16];
+const PREFIX_LENGTH: usize = 3;
+/// Since this database version the plaintext starts with a SHA-256 hash of
+/// the site it belongs to.
+const DOMAIN_HASH_VERSION: i64 = 24;
+const DOMAIN_HASH_LENGTH: usize = 32;
+/// Overrides the command printing the keyring secret, e.g. for Brave.
+const KEY_COMMAND_ENV: &str = "MOPM_BROWSER_KEY_COMMAND";
+/// Seconds from 1601-01-01, where the times of the database start, to the
+/// unix epoch.
+const WEBKIT_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+type Key = [u8; 16];
+
+pub fn read(profile: &Path) -> Result<Vec<Login>, BrowserError> {
+    let connection = super::open_database(&profile.join(LOGIN_DATA))?;
+    let version: i64 = connection
+        .query_row("SELECT value FROM meta WHERE key = 'version'", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .map(|v| v.parse().unwrap_or_default())
+        .unwrap_or_default();
+
+    // Older databases only record when a login was saved.
+    let modified = match connection
+        .prepare("SELECT 1 FROM pragma_table_info('logins') WHERE name = 'date_password_modified'")?
+        .exists([])?
+    {
+        true => "date_password_modified",
+        false => "date_created",
+    };
+    let mut statement = connection.prepare(&format!(
+        "SELECT origin_url, username_value, password_value, date_created, {} FROM logins WHERE blacklisted_by_user = 0",
+        modified
+    ))?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Vec<u8>>(2)?,
+            row.get::<_, Option<i64>>(3)?,
+            row.get::<_, Option<i64>>(4)?,
+        ))
+    })?;
+
+    let mut keys: HashMap<Vec<u8>, Key> = HashMap::new();
+    let mut logins = vec![];
+    for row in rows {
+        let (url, username, encrypted, created, modified) = row?;
+        if encrypted.len() < PREFIX_LENGTH {
+            continue;
+        }
+        let (prefix, ciphertext) = encrypted.split_at(PREFIX_LENGTH);
+        let key = match keys.get(prefix) {
+            Some(key) => *key,
+            None => *keys.entry(prefix.to_vec()).or_insert(key(prefix)?),
+        };
+
+        let mut password = decrypt(&key, ciphertext)?;
+        if version >= DOMAIN_HASH_VERSION {
+            password.drain(..DOMAIN_HASH_LENGTH.min(password.len()));
+        }
+        logins.push(Login {
+            url,
+            username,
+            password: String::from_utf8(password)
+                .map_err(|err| BrowserError::DecryptionFailed(err.to_string()))?,
+            source: "chromium",
+            created: created.and_then(from_webkit_time),
+            modified: modified.and_then(from_webkit_time),
+        });
+    }
+    Ok(logins)
+}
+
+/// Unix seconds of a time in microseconds since 1601, `None` for 0, which
+/// stands for unknown.
+fn from_webkit_time(micros: i64) -> Option<u64> {
+    u64::try_from(micros / 1_000_000 - WEBKIT_EPOCH_OFFSET)
+        .ok()
+        .filter(|_| micros > 0)
+}
+
+fn key(prefix: &[u8]) -> Result<Key, BrowserError> {
+    let (secret, iterations) = secret(prefix)?;
+    Ok(pbkdf2::pbkdf2_hmac_array::<Sha1, 16>(
+        secret.as_bytes(),
+        SALT,
+        iterations,
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn secret(prefix: &[u8]) -> Result<(String, u32), BrowserError> {
+    match prefix {
+        b"v10" => Ok(("peanuts".to_string(), 1)),
+        b"v11" => Ok((
+            keyring_secret(&[
+                "secret-tool lookup application chrome",
+                "secret-tool lookup application chromium",
+            ])?,
+            1,
+        )),
+        _ => Err(BrowserError::KeyUnavailable(format!(
+            "unknown encryption version `{}`",
+            String::from_utf8_lossy(prefix)
+        ))),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn secret(prefix: &[u8]) -> Result<(String, u32), BrowserError> {
+    match prefix {
+        b"v10" => Ok((
+            keyring_secret(&[
+                "security find-generic-password -wa Chrome",
+                "security find-generic-password -wa Chromium",
+            ])?,
+            1003,
+        )),
+        _ => Err(BrowserError::KeyUnavailable(format!(
+            "unknown encryption version `{}`",
+            String::from_utf8_lossy(prefix)
+        ))),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn secret(_prefix: &[u8]) -> Result<(String, u32), BrowserError> {
+    Err(BrowserError::KeyUnavailable(
+        "reading Chromium keys is not supported on this platform".to_string(),
+    ))
+}
+
+/// Returns the output of the first of `commands` that succeeds, or of the
+/// command set in `MOPM_BROWSER_KEY_COMMAND`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn keyring_secret(commands: &[&str]) -> Result<String, BrowserError> {
+    let custom = std::env::var(KEY_COMMAND_ENV).ok();
+    let commands: Vec<&str> = match &custom {
+        Some(command) => vec![command.as_str()],
+        None => commands.to_vec(),
+    };
+
+    commands
+        .iter()
+        .find_map(|command| {
+            let output = Command::new("sh").arg("-c").arg(command).output().ok()?;
+            let secret = String::from_utf8(output.stdout).ok()?;
+            Some(secret.trim_end_matches('\n').to_string())
+                .filter(|v| output.status.success() && !v.is_empty())
+        })
+        .ok_or_else(|| {
+            BrowserError::KeyUnavailable(format!(
+                "the keyring has no browser secret (set {})",
+                KEY_COMMAND_ENV
+            ))
+        })
+}
+
+fn decrypt(key: &Key, ciphertext: &[u8]) -> Result<Vec<u8>, BrowserError> {
+    cbc::Decryptor::<aes::Aes128>::new(key.into(), &IV.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .map_err(|err| BrowserError::DecryptionFailed(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::BlockEncryptMut;
+
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_key() {
+        assert_eq!(
+            hex::encode(key(b"v10").unwrap()),
+            "fd621fe5a2b402539dfa147ca9272778"
+        );
+        assert!(key(b"v99").is_err());
+    }
+
+    #[test]
+    fn test_from_webkit_time() {
+        assert_eq!(
+            from_webkit_time(13_300_000_000_000_000),
+            Some(1_655_526_400)
+        );
+        assert_eq!(from_webkit_time(0), None);
+        assert_eq!(from_webkit_time(1_000), None);
+    }
+
+    #[test]
+    fn test_decrypt() {
+        let key = [7; 16];
+        let ciphertext = cbc::Encryptor::<aes::Aes128>::new(&key.into(), &IV.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(b"hunter2");
+
+        assert_eq!(decrypt(&key, &ciphertext).unwrap(), b"hunter2");
+        assert!(decrypt(&[0; 16], &ciphertext).is_err());
+    }
+}
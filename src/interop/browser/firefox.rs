@@ -0,0 +1,334 @@
+//! Firefox keeps logins in `logins.json`, encrypted with a key stored in the
+//! NSS database `key4.db`. That key is itself encrypted with PBES2 under the
+//! primary password, which is assumed to be empty.
+
+use std::path::Path;
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use super::{BrowserError, Login};
+use crate::core::ct;
+
+pub const LOGINS: &str = "logins.json";
+
+const KEY_DB: &str = "key4.db";
+const PASSWORD_CHECK: &[u8] = b"password-check";
+/// CKA_ID of the key that encrypts the logins.
+const LOGIN_KEY_ID: [u8; 16] = [0xf8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+
+const OID_PBES2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+const OID_PBKDF2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+const OID_HMAC_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+const OID_AES256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+const OID_DES_EDE3_CBC: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x03, 0x07];
+
+pub fn read(profile: &Path) -> Result<Vec<Login>, BrowserError> {
+    let key = login_key(&profile.join(KEY_DB))?;
+    let logins: Value = serde_json::from_slice(&std::fs::read(profile.join(LOGINS))?)
+        .map_err(|err| BrowserError::DecryptionFailed(err.to_string()))?;
+
+    logins["logins"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|login| {
+            let field = |name: &str| login[name].as_str().unwrap_or_default();
+            // In milliseconds.
+            let time = |name: &str| login[name].as_u64().map(|v| v / 1000);
+            Ok(Login {
+                url: field("hostname").to_string(),
+                username: decrypt_field(&key, field("encryptedUsername"))?,
+                password: decrypt_field(&key, field("encryptedPassword"))?,
+                source: "firefox",
+                created: time("timeCreated"),
+                modified: time("timePasswordChanged"),
+            })
+        })
+        .collect()
+}
+
+/// Decrypts the key used for `logins.json` from the NSS key database.
+fn login_key(path: &Path) -> Result<Vec<u8>, BrowserError> {
+    let connection = super::open_database(path)?;
+    let (global_salt, check): (Vec<u8>, Vec<u8>) = connection.query_row(
+        "SELECT item1, item2 FROM metaData WHERE id = 'password'",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    if !ct::eq(&decrypt_pbe(&check, &global_salt)?, PASSWORD_CHECK) {
+        return Err(BrowserError::KeyUnavailable(
+            "the profile is protected by a primary password".to_string(),
+        ));
+    }
+
+    let mut statement = connection.prepare("SELECT a11, a102 FROM nssPrivate")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        if row.get::<_, Vec<u8>>(1)? == LOGIN_KEY_ID {
+            return decrypt_pbe(&row.get::<_, Vec<u8>>(0)?, &global_salt);
+        }
+    }
+    Err(BrowserError::KeyUnavailable(
+        "the key database has no login key".to_string(),
+    ))
+}
+
+/// Decrypts a PBES2 blob (PBKDF2-HMAC-SHA256 and AES-256-CBC) whose
+/// password is the SHA-1 of the global salt and the empty primary password.
+fn decrypt_pbe(blob: &[u8], global_salt: &[u8]) -> Result<Vec<u8>, BrowserError> {
+    let invalid = || BrowserError::DecryptionFailed("unsupported key encryption".to_string());
+
+    let mut outer = Der::new(blob).sequence().ok_or_else(invalid)?;
+    let mut algorithm = outer.sequence().ok_or_else(invalid)?;
+    let ciphertext = outer.octet_string().ok_or_else(invalid)?;
+    if algorithm.oid() != Some(OID_PBES2) {
+        return Err(invalid());
+    }
+    let mut params = algorithm.sequence().ok_or_else(invalid)?;
+
+    let mut kdf = params.sequence().ok_or_else(invalid)?;
+    if kdf.oid() != Some(OID_PBKDF2) {
+        return Err(invalid());
+    }
+    let mut kdf_params = kdf.sequence().ok_or_else(invalid)?;
+    let salt = kdf_params.octet_string().ok_or_else(invalid)?;
+    let iterations = kdf_params.integer().ok_or_else(invalid)?;
+    let _key_length = kdf_params.integer();
+    if let Some(mut prf) = kdf_params.sequence() {
+        if prf.oid() != Some(OID_HMAC_SHA256) {
+            return Err(invalid());
+        }
+    }
+
+    let mut cipher = params.sequence().ok_or_else(invalid)?;
+    if cipher.oid() != Some(OID_AES256_CBC) {
+        return Err(invalid());
+    }
+    let iv = cipher.octet_string().ok_or_else(invalid)?;
+    // NSS stores 14 bytes and uses them with their DER header as the IV.
+    let iv: [u8; 16] = match iv.len() {
+        14 => [&[0x04, 0x0e], iv].concat().try_into().expect("16 bytes"),
+        _ => iv.try_into().map_err(|_| invalid())?,
+    };
+
+    let password = Sha1::digest(global_salt);
+    let key = pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(&password, salt, iterations);
+    cbc::Decryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .map_err(|err| BrowserError::DecryptionFailed(err.to_string()))
+}
+
+/// Decrypts a base64 `encryptedUsername`/`encryptedPassword` value.
+fn decrypt_field(key: &[u8], value: &str) -> Result<String, BrowserError> {
+    let invalid = || BrowserError::DecryptionFailed("malformed login field".to_string());
+    let blob = STANDARD.decode(value).map_err(|_| invalid())?;
+
+    let mut outer = Der::new(&blob).sequence().ok_or_else(invalid)?;
+    let _key_id = outer.octet_string().ok_or_else(invalid)?;
+    let mut algorithm = outer.sequence().ok_or_else(invalid)?;
+    let ciphertext = outer.octet_string().ok_or_else(invalid)?;
+    let oid = algorithm.oid().ok_or_else(invalid)?;
+    let iv = algorithm.octet_string().ok_or_else(invalid)?;
+
+    let plaintext = match oid {
+        OID_AES256_CBC if key.len() >= 32 => {
+            cbc::Decryptor::<aes::Aes256>::new_from_slices(&key[..32], iv)
+                .map_err(|_| invalid())?
+                .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        }
+        OID_DES_EDE3_CBC if key.len() >= 24 => {
+            cbc::Decryptor::<des::TdesEde3>::new_from_slices(&key[..24], iv)
+                .map_err(|_| invalid())?
+                .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        }
+        _ => return Err(invalid()),
+    }
+    .map_err(|err| BrowserError::DecryptionFailed(err.to_string()))?;
+
+    String::from_utf8(plaintext).map_err(|err| BrowserError::DecryptionFailed(err.to_string()))
+}
+
+/// Just enough of a DER reader for the structures NSS produces.
+struct Der<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    const INTEGER: u8 = 0x02;
+    const OCTET_STRING: u8 = 0x04;
+    const OID: u8 = 0x06;
+    const SEQUENCE: u8 = 0x30;
+
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn read(&mut self, tag: u8) -> Option<&'a [u8]> {
+        let (&actual, rest) = self.data.split_first()?;
+        if actual != tag {
+            return None;
+        }
+        let (&first, mut rest) = rest.split_first()?;
+        let length = match first {
+            0..=0x7f => first as usize,
+            0x81..=0x84 => {
+                let (bytes, tail) = rest.split_at_checked((first & 0x7f) as usize)?;
+                rest = tail;
+                bytes.iter().fold(0, |acc, &v| (acc << 8) | v as usize)
+            }
+            _ => return None,
+        };
+        let (value, rest) = rest.split_at_checked(length)?;
+        self.data = rest;
+        Some(value)
+    }
+
+    fn sequence(&mut self) -> Option<Der<'a>> {
+        self.read(Self::SEQUENCE).map(Der::new)
+    }
+
+    fn octet_string(&mut self) -> Option<&'a [u8]> {
+        self.read(Self::OCTET_STRING)
+    }
+
+    fn oid(&mut self) -> Option<&'a [u8]> {
+        self.read(Self::OID)
+    }
+
+    fn integer(&mut self) -> Option<u32> {
+        let bytes = self.read(Self::INTEGER)?;
+        if bytes.len() > 5 {
+            return None;
+        }
+        bytes
+            .iter()
+            .try_fold(0u64, |acc, &v| Some((acc << 8) | v as u64))
+            .and_then(|v| u32::try_from(v).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::BlockEncryptMut;
+
+    use super::*;
+
+    fn tlv(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+        let value = parts.concat();
+        let mut out = vec![tag];
+        if value.len() < 0x80 {
+            out.push(value.len() as u8);
+        } else {
+            out.extend([0x82, (value.len() >> 8) as u8, value.len() as u8]);
+        }
+        out.extend(value);
+        out
+    }
+
+    fn pbe_blob(plaintext: &[u8], global_salt: &[u8]) -> Vec<u8> {
+        let (salt, iterations, iv) = ([9; 32], 10u8, [3; 14]);
+        let key = pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(
+            &Sha1::digest(global_salt),
+            &salt,
+            iterations as u32,
+        );
+        let full_iv: [u8; 16] = [&[0x04, 0x0e], &iv[..]].concat().try_into().unwrap();
+        let ciphertext = cbc::Encryptor::<aes::Aes256>::new(&key.into(), &full_iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+
+        let kdf = tlv(
+            Der::SEQUENCE,
+            &[
+                &tlv(Der::OID, &[OID_PBKDF2]),
+                &tlv(
+                    Der::SEQUENCE,
+                    &[
+                        &tlv(Der::OCTET_STRING, &[&salt]),
+                        &tlv(Der::INTEGER, &[&[iterations]]),
+                        &tlv(Der::INTEGER, &[&[32]]),
+                        &tlv(Der::SEQUENCE, &[&tlv(Der::OID, &[OID_HMAC_SHA256])]),
+                    ],
+                ),
+            ],
+        );
+        let cipher = tlv(
+            Der::SEQUENCE,
+            &[
+                &tlv(Der::OID, &[OID_AES256_CBC]),
+                &tlv(Der::OCTET_STRING, &[&iv]),
+            ],
+        );
+        let algorithm = tlv(
+            Der::SEQUENCE,
+            &[
+                &tlv(Der::OID, &[OID_PBES2]),
+                &tlv(Der::SEQUENCE, &[&kdf, &cipher]),
+            ],
+        );
+        tlv(
+            Der::SEQUENCE,
+            &[&algorithm, &tlv(Der::OCTET_STRING, &[&ciphertext])],
+        )
+    }
+
+    #[test]
+    fn test_der() {
+        let long = vec![1; 300];
+        let blob = tlv(
+            Der::SEQUENCE,
+            &[
+                &tlv(Der::INTEGER, &[&[1, 0]]),
+                &tlv(Der::OCTET_STRING, &[&long]),
+            ],
+        );
+        let mut sequence = Der::new(&blob).sequence().unwrap();
+
+        assert_eq!(sequence.oid(), None);
+        assert_eq!(sequence.integer(), Some(256));
+        assert_eq!(sequence.octet_string(), Some(&long[..]));
+        assert_eq!(sequence.octet_string(), None);
+        assert!(Der::new(&blob[..10]).sequence().is_none());
+    }
+
+    #[test]
+    fn test_decrypt_pbe() {
+        let blob = pbe_blob(PASSWORD_CHECK, b"salt");
+
+        assert_eq!(decrypt_pbe(&blob, b"salt").unwrap(), PASSWORD_CHECK);
+        assert!(decrypt_pbe(&blob, b"other").map_or(true, |v| v != PASSWORD_CHECK));
+        assert!(decrypt_pbe(&blob[1..], b"salt").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_field() {
+        let (key, iv) = ([5; 32], [6; 16]);
+        let ciphertext = cbc::Encryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(b"hunter2");
+        let blob = tlv(
+            Der::SEQUENCE,
+            &[
+                &tlv(Der::OCTET_STRING, &[&LOGIN_KEY_ID]),
+                &tlv(
+                    Der::SEQUENCE,
+                    &[
+                        &tlv(Der::OID, &[OID_AES256_CBC]),
+                        &tlv(Der::OCTET_STRING, &[&iv]),
+                    ],
+                ),
+                &tlv(Der::OCTET_STRING, &[&ciphertext]),
+            ],
+        );
+
+        assert_eq!(
+            decrypt_field(&key, &STANDARD.encode(&blob)).unwrap(),
+            "hunter2"
+        );
+        assert!(decrypt_field(&key[..16], &STANDARD.encode(&blob)).is_err());
+        assert!(decrypt_field(&key, "not base64!").is_err());
+    }
+}
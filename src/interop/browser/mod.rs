@@ -0,0 +1,172 @@
+//! Import of the logins saved by Chromium based browsers and Firefox.
+//!
+//! Every login becomes an entry named after the host it belongs to,
+//! followed by the username if there is one (`example.com/me`), with the
+//! `url` and `username` metadata filled in and the browser and times of
+//! the login recorded as its provenance.
+
+use std::{io, path::Path};
+
+use thiserror::Error;
+
+use crate::core::{
+    conflict::Resolver,
+    encryptor::Encryprtor,
+    manager::{PasswordManager, PasswordManagerError},
+    provenance::Provenance,
+};
+
+pub mod chromium;
+pub mod firefox;
+
+#[derive(Error, Debug)]
+pub enum BrowserError {
+    #[error("no supported login store found in `{0}`")]
+    UnknownProfile(String),
+    #[error("error while reading the profile: `{0}`")]
+    IoError(#[from] io::Error),
+    #[error("error while reading the login database: `{0}`")]
+    DatabaseError(#[from] rusqlite::Error),
+    #[error("cannot obtain the browser key: `{0}`")]
+    KeyUnavailable(String),
+    #[error("cannot decrypt a saved login: `{0}`")]
+    DecryptionFailed(String),
+    #[error("{0}")]
+    PasswordManagerError(#[from] PasswordManagerError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Login {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    /// The browser the login was saved in.
+    pub source: &'static str,
+    /// When the login was saved and its password last changed, as unix
+    /// seconds, if the browser records it.
+    pub created: Option<u64>,
+    pub modified: Option<u64>,
+}
+
+impl Login {
+    fn key(&self) -> String {
+        let host = self
+            .url
+            .split_once("://")
+            .map_or(self.url.as_str(), |(_, rest)| rest);
+        let host = host.split(['/', '?', '#']).next().unwrap_or_default();
+        let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+        let host = if host.is_empty() { &self.url } else { host };
+
+        match self.username.as_str() {
+            "" => host.to_string(),
+            username => format!("{}/{}", host, username),
+        }
+    }
+}
+
+/// Reads the logins of the profile directory `profile`, detecting the
+/// browser by the files it contains.
+pub fn read_profile(profile: &Path) -> Result<Vec<Login>, BrowserError> {
+    if profile.join(chromium::LOGIN_DATA).is_file() {
+        chromium::read(profile)
+    } else if profile.join(firefox::LOGINS).is_file() {
+        firefox::read(profile)
+    } else {
+        Err(BrowserError::UnknownProfile(profile.display().to_string()))
+    }
+}
+
+/// Stores `logins` in `pm`, resolving duplicates of existing entries with
+/// `resolver`, and returns the number of stored entries.
+pub fn import<T: Encryprtor>(
+    pm: &mut PasswordManager<T>,
+    logins: &[Login],
+    resolver: &mut Resolver,
+) -> Result<usize, BrowserError> {
+    let mut count = 0;
+    for login in logins {
+        let Some(key) = pm.store_resolved(login.key(), &login.password, resolver)? else {
+            continue;
+        };
+        pm.set_meta(&key, "url", &login.url)?;
+        if !login.username.is_empty() {
+            pm.set_meta(&key, "username", &login.username)?;
+        }
+        let provenance = Provenance {
+            created: login.created,
+            modified: login.modified,
+            ..Provenance::new(login.source)
+        };
+        pm.set_provenance(&key, &provenance)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Opens an SQLite database of a possibly running browser without taking
+/// any locks on it.
+fn open_database(path: &Path) -> Result<rusqlite::Connection, BrowserError> {
+    let path = path
+        .display()
+        .to_string()
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+    Ok(rusqlite::Connection::open_with_flags(
+        format!("file:{}?immutable=1", path),
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_URI,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::{conflict::ConflictPolicy, encryptor::AESEncryptor, provenance};
+
+    use super::*;
+
+    fn login(url: &str, username: &str) -> Login {
+        Login {
+            url: url.to_string(),
+            username: username.to_string(),
+            password: "pw".to_string(),
+            source: "firefox",
+            created: Some(1_000),
+            modified: Some(2_000),
+        }
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(
+            login("https://example.com/login", "me").key(),
+            "example.com/me"
+        );
+        assert_eq!(
+            login("https://a@example.com:8443", "").key(),
+            "example.com:8443"
+        );
+        assert_eq!(login("android://hash@com.app/", "").key(), "com.app");
+        assert_eq!(login("example.com", "").key(), "example.com");
+    }
+
+    #[test]
+    fn test_import() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let logins = [
+            login("https://example.com/", "me"),
+            login("https://x.org", ""),
+        ];
+
+        let mut resolver = Resolver::new(ConflictPolicy::Skip, |_| unreachable!());
+        assert_eq!(import(&mut pm, &logins, &mut resolver).unwrap(), 2);
+        assert_eq!(pm.keys(), vec!["example.com/me", "x.org"]);
+        assert_eq!(pm.get_password("x.org"), Ok("pw".to_string()));
+        assert_eq!(pm.meta("example.com/me", "username"), Some("me"));
+        assert_eq!(pm.meta("x.org", "url"), Some("https://x.org"));
+        assert_eq!(pm.meta("x.org", provenance::SOURCE_FIELD), Some("firefox"));
+        assert_eq!(pm.value_modified("x.org"), Some(2_000));
+    }
+}
@@ -0,0 +1,96 @@
+//! Secrets as shell variable assignments, one per line, e.g. `DB_PASSWORD='x'`,
+//! for `source` or `eval` in provisioning scripts and for `.env` files.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum DotenvError {
+    #[error("`{0}` and `{1}` are both assigned to `{2}`, get them separately")]
+    Collision(String, String, String),
+}
+
+/// The variable a key is assigned to: upper case, with every character
+/// that cannot be in a name replaced by `_`, and a leading `_` when the key
+/// starts with a digit. `work/db-password` becomes `WORK_DB_PASSWORD`.
+pub fn variable_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+    match name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+        true => format!("_{}", name),
+        false => name,
+    }
+}
+
+/// Single quotes `value`, the only quoting in which the shell expands
+/// nothing.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Fails when two of `keys` are assigned to the same variable, where the
+/// value of the later one would silently win.
+pub fn check_names<'a>(keys: impl IntoIterator<Item = &'a str>) -> Result<(), DotenvError> {
+    let mut names: HashMap<String, &str> = HashMap::new();
+    for key in keys {
+        let name = variable_name(key);
+        if let Some(other) = names.get(&name) {
+            return Err(DotenvError::Collision(
+                other.to_string(),
+                key.to_string(),
+                name,
+            ));
+        }
+        names.insert(name, key);
+    }
+    Ok(())
+}
+
+/// The assignments of `secrets`, in their order.
+pub fn to_env(secrets: &[(&str, &str)]) -> Result<String, DotenvError> {
+    check_names(secrets.iter().map(|(key, _)| *key))?;
+    Ok(secrets
+        .iter()
+        .map(|(key, value)| format!("{}={}\n", variable_name(key), quote(value)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_env() {
+        assert_eq!(variable_name("work/db-password"), "WORK_DB_PASSWORD");
+        assert_eq!(variable_name("2fa.backup"), "_2FA_BACKUP");
+        assert_eq!(variable_name("ключ"), "____");
+        assert_eq!(
+            to_env(&[("db", "it's $HOME"), ("api/token", "x")]),
+            Ok("DB='it'\\''s $HOME'\nAPI_TOKEN='x'\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_collision() {
+        assert_eq!(
+            to_env(&[
+                ("work/db-password", "a"),
+                ("db", "b"),
+                ("work.db.password", "c")
+            ]),
+            Err(DotenvError::Collision(
+                "work/db-password".to_string(),
+                "work.db.password".to_string(),
+                "WORK_DB_PASSWORD".to_string()
+            ))
+        );
+        assert!(check_names(["ключ", "пароль", "тест"]).is_err());
+        assert_eq!(check_names(["db", "DB2", "api/token"]), Ok(()));
+    }
+}
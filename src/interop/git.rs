@@ -0,0 +1,126 @@
+//! Git integration for the leak scanner: reading the staged version of
+//! files and managing the pre-commit hook that runs `mopm scan --staged`.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use thiserror::Error;
+
+/// Identifies hooks written by mopm. The number after it is the version of
+/// the hook script, hooks with an older one are replaced on install.
+const HOOK_MARKER: &str = "# mopm pre-commit hook, version ";
+const HOOK_VERSION: u32 = 1;
+const HOOK_NAME: &str = "pre-commit";
+pub const SKIP_SCAN_ENV: &str = "MOPM_SKIP_SCAN";
+
+#[derive(Error, Debug)]
+pub enum GitError {
+    #[error("cannot run git: `{0}`")]
+    SpawnError(#[from] io::Error),
+    #[error("git failed: `{0}`")]
+    CommandFailed(String),
+    #[error("a {HOOK_NAME} hook not written by mopm exists at `{0}`, pass --force to replace it")]
+    ForeignHook(PathBuf),
+    #[error("the {HOOK_NAME} hook at `{0}` was written by a newer mopm")]
+    NewerHook(PathBuf),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HookStatus {
+    Installed(PathBuf),
+    Updated(PathBuf),
+    UpToDate(PathBuf),
+}
+
+/// Paths and contents of the files staged for the next commit, deletions
+/// excluded.
+pub fn staged_files() -> Result<Vec<(PathBuf, Vec<u8>)>, GitError> {
+    let names = git(&[
+        "diff",
+        "--cached",
+        "--name-only",
+        "--diff-filter=ACMR",
+        "-z",
+    ])?;
+    names
+        .split(|&b| b == 0)
+        .filter(|v| !v.is_empty())
+        .map(|name| {
+            let name = String::from_utf8_lossy(name).into_owned();
+            let contents = git(&["show", &format!(":{}", name)])?;
+            Ok((PathBuf::from(name), contents))
+        })
+        .collect()
+}
+
+/// Writes the pre-commit hook of the current repository. Hooks of older
+/// mopm versions are updated, other hooks are only replaced with `force`.
+pub fn install_hook(executable: &Path, force: bool) -> Result<HookStatus, GitError> {
+    let hooks =
+        PathBuf::from(String::from_utf8_lossy(&git(&["rev-parse", "--git-path", "hooks"])?).trim());
+    let path = hooks.join(HOOK_NAME);
+
+    let status = match fs::read_to_string(&path) {
+        Ok(existing) => match hook_version(&existing) {
+            Some(HOOK_VERSION) => return Ok(HookStatus::UpToDate(path)),
+            Some(version) if version > HOOK_VERSION => return Err(GitError::NewerHook(path)),
+            Some(_) => HookStatus::Updated(path.clone()),
+            None if force => HookStatus::Updated(path.clone()),
+            None => return Err(GitError::ForeignHook(path)),
+        },
+        Err(err) if err.kind() == ErrorKind::NotFound => HookStatus::Installed(path.clone()),
+        Err(err) => return Err(err.into()),
+    };
+
+    fs::create_dir_all(&hooks)?;
+    fs::write(&path, hook_script(executable))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    Ok(status)
+}
+
+fn hook_script(executable: &Path) -> String {
+    let executable = executable.to_string_lossy().replace('\'', r"'\''");
+    format!(
+        "#!/bin/sh\n\
+         {HOOK_MARKER}{HOOK_VERSION}\n\
+         # Blocks commits that contain passwords stored in the vault. Skip it\n\
+         # with `git commit --no-verify` or {SKIP_SCAN_ENV}=1.\n\
+         [ \"${SKIP_SCAN_ENV}\" = 1 ] && exit 0\n\
+         exec '{executable}' scan --staged\n"
+    )
+}
+
+fn hook_version(script: &str) -> Option<u32> {
+    script
+        .lines()
+        .find_map(|line| line.strip_prefix(HOOK_MARKER))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn git(args: &[&str]) -> Result<Vec<u8>, GitError> {
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        return Err(GitError::CommandFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_script() {
+        let script = hook_script(Path::new("/opt/it's/mopm"));
+        assert_eq!(hook_version(&script), Some(HOOK_VERSION));
+        assert!(script.contains(r"exec '/opt/it'\''s/mopm' scan --staged"));
+        assert_eq!(hook_version("#!/bin/sh\nexec lint\n"), None);
+    }
+}
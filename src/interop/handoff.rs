@@ -0,0 +1,237 @@
+//! Moving a single entry to another machine by typing it, for machines
+//! that share neither a network nor a clipboard, e.g. an air-gapped one.
+//!
+//! The entry is encrypted to a random key used for this handoff alone,
+//! and the ciphertext is shown as numbered chunks of Crockford base32,
+//! such as `3FQ9-KZ2M`. The last character of a chunk is a check digit
+//! over the chunk and its number, so that a mistyped, swapped or skipped
+//! chunk is caught as it is typed rather than at the end. The key is shown
+//! apart as the code, in the same alphabet, to be carried another way than
+//! the chunks, e.g. read aloud while the chunks are written down.
+//!
+//! Crockford base32 has no I, L, O or U and ignores case, so `o` is read
+//! as `0` and `i` or `l` as `1`.
+
+use aes_gcm::{aead::Aead, Aes128Gcm, KeyInit};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::core::rng;
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const KEY_LENGTH: usize = 16;
+/// The key is used once, so a fixed nonce never repeats under it.
+const NONCE: [u8; 12] = [0; 12];
+/// Characters of data per chunk, followed by the check digit.
+const CHUNK_LENGTH: usize = 7;
+/// Characters shown between dashes.
+const GROUP_LENGTH: usize = 4;
+/// Chunk numbers go into the check digit as two base32 digits.
+pub const MAX_CHUNKS: usize = 32 * 32 - 1;
+/// The number the code is checked with, chunks start at 1.
+const CODE_NUMBER: usize = 0;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HandoffError {
+    #[error("the entry is too large to hand off, it needs more than {MAX_CHUNKS} chunks")]
+    TooLarge,
+    #[error("`{0}` is not a valid character")]
+    InvalidCharacter(char),
+    #[error("the check digit does not match, it was mistyped or is not chunk {0}")]
+    Mistyped(usize),
+    #[error("the chunks and the code do not open an entry")]
+    InvalidPayload,
+}
+
+/// An entry encrypted for a handoff: the chunks to type and the code that
+/// opens them.
+pub struct Handoff {
+    pub chunks: Vec<String>,
+    pub code: Zeroizing<String>,
+}
+
+impl Handoff {
+    pub fn seal(name: &str, value: &str) -> Result<Self, HandoffError> {
+        let mut key = Zeroizing::new([0; KEY_LENGTH]);
+        rng::fill(&mut key[..]);
+        let plaintext = Zeroizing::new(format!("{}\n{}", name, value));
+        let ciphertext = Aes128Gcm::new_from_slice(&key[..])
+            .expect("the key has the length of an AES-128 key")
+            .encrypt(&NONCE.into(), plaintext.as_bytes())
+            .expect("encrypting into a vec cannot fail");
+        let digits = encode(&ciphertext);
+        if digits.len().div_ceil(CHUNK_LENGTH) > MAX_CHUNKS {
+            return Err(HandoffError::TooLarge);
+        }
+        let chunks = digits
+            .chunks(CHUNK_LENGTH)
+            .enumerate()
+            .map(|(i, chunk)| format_digits(&with_check(i + 1, chunk)))
+            .collect();
+        let code = Zeroizing::new(format_digits(&with_check(
+            CODE_NUMBER,
+            &encode(key.as_ref()),
+        )));
+        Ok(Self { chunks, code })
+    }
+}
+
+/// Reads chunk `number` as typed, returning its digits without the check
+/// digit.
+pub fn parse_chunk(number: usize, text: &str) -> Result<Vec<u8>, HandoffError> {
+    let digits = parse_digits(text)?;
+    match digits.split_last() {
+        Some((check, data)) if *check == check_digit(number, data) => Ok(data.to_vec()),
+        _ => Err(HandoffError::Mistyped(number)),
+    }
+}
+
+/// Decrypts the digits of every chunk, in order, with the code. Returns
+/// the name and the value of the entry.
+pub fn open(digits: &[u8], code: &str) -> Result<(String, Zeroizing<String>), HandoffError> {
+    let key = Zeroizing::new(decode(&parse_chunk(CODE_NUMBER, code)?));
+    let plaintext = Aes128Gcm::new_from_slice(&key)
+        .map_err(|_| HandoffError::InvalidPayload)?
+        .decrypt(&NONCE.into(), decode(digits).as_ref())
+        .map(Zeroizing::new)
+        .map_err(|_| HandoffError::InvalidPayload)?;
+    let text = std::str::from_utf8(&plaintext).map_err(|_| HandoffError::InvalidPayload)?;
+    let (name, value) = text.split_once('\n').ok_or(HandoffError::InvalidPayload)?;
+    Ok((name.to_string(), Zeroizing::new(value.to_string())))
+}
+
+/// Base32 digits, 5 bits each, the last one padded with zero bits.
+fn encode(bytes: &[u8]) -> Vec<u8> {
+    let mut digits = Vec::with_capacity((bytes.len() * 8).div_ceil(5));
+    let (mut buffer, mut bits) = (0u16, 0);
+    for byte in bytes {
+        buffer = buffer << 8 | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            digits.push((buffer >> bits & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        digits.push((buffer << (5 - bits) & 31) as u8);
+    }
+    digits
+}
+
+/// The bytes of base32 digits, the padding bits dropped.
+fn decode(digits: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(digits.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u16, 0);
+    for digit in digits {
+        buffer = buffer << 5 | u16::from(*digit);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    bytes
+}
+
+fn parse_digits(text: &str) -> Result<Vec<u8>, HandoffError> {
+    text.chars()
+        .filter(|v| *v != '-' && !v.is_whitespace())
+        .map(|v| {
+            let v = match v.to_ascii_uppercase() {
+                'O' => '0',
+                'I' | 'L' => '1',
+                v => v,
+            };
+            ALPHABET
+                .iter()
+                .position(|a| char::from(*a) == v)
+                .map(|i| i as u8)
+                .ok_or(HandoffError::InvalidCharacter(v))
+        })
+        .collect()
+}
+
+fn with_check(number: usize, digits: &[u8]) -> Vec<u8> {
+    let mut digits = digits.to_vec();
+    digits.push(check_digit(number, &digits));
+    digits
+}
+
+/// The Luhn mod 32 check digit of `number` followed by `digits`, which
+/// catches any single wrong digit and most swapped neighbours.
+fn check_digit(number: usize, digits: &[u8]) -> u8 {
+    let prefix = [(number / 32 % 32) as u8, (number % 32) as u8];
+    let mut sum = 0;
+    for (i, digit) in prefix.iter().chain(digits).rev().enumerate() {
+        let addend = u32::from(*digit) * if i % 2 == 0 { 2 } else { 1 };
+        sum += addend / 32 + addend % 32;
+    }
+    ((32 - sum % 32) % 32) as u8
+}
+
+fn format_digits(digits: &[u8]) -> String {
+    let text: Vec<char> = digits
+        .iter()
+        .map(|v| char::from(ALPHABET[*v as usize]))
+        .collect();
+    text.chunks(GROUP_LENGTH)
+        .map(|v| v.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let handoff = Handoff::seal("work/db", "it's\na secret").unwrap();
+        let digits: Vec<u8> = handoff
+            .chunks
+            .iter()
+            .enumerate()
+            .flat_map(|(i, chunk)| parse_chunk(i + 1, &chunk.to_lowercase()).unwrap())
+            .collect();
+        let (name, value) = open(&digits, &handoff.code).unwrap();
+        assert_eq!(name, "work/db");
+        assert_eq!(value.as_str(), "it's\na secret");
+
+        let other = Handoff::seal("work/db", "it's\na secret").unwrap();
+        assert_eq!(
+            open(&digits, &other.code),
+            Err(HandoffError::InvalidPayload)
+        );
+        assert_eq!(
+            open(&digits[1..], &handoff.code),
+            Err(HandoffError::InvalidPayload)
+        );
+    }
+
+    #[test]
+    fn test_parse_chunk() {
+        let chunk = format_digits(&with_check(3, &[0, 1, 31, 18, 4, 9, 27]));
+        assert_eq!(chunk.len(), 9);
+        assert_eq!(parse_chunk(3, &chunk), Ok(vec![0, 1, 31, 18, 4, 9, 27]));
+        assert_eq!(
+            parse_chunk(3, &chunk.replace('0', "o").replace('1', "l")),
+            Ok(vec![0, 1, 31, 18, 4, 9, 27])
+        );
+        assert_eq!(parse_chunk(4, &chunk), Err(HandoffError::Mistyped(4)));
+        let swapped = format!("{}{}{}", &chunk[1..2], &chunk[..1], &chunk[2..]);
+        assert_eq!(parse_chunk(3, &swapped), Err(HandoffError::Mistyped(3)));
+        assert_eq!(
+            parse_chunk(3, "3FQU-KZ2M"),
+            Err(HandoffError::InvalidCharacter('U'))
+        );
+        assert_eq!(parse_chunk(1, ""), Err(HandoffError::Mistyped(1)));
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(encode(b""), b"");
+        assert_eq!(encode(&[0xff]), [31, 28]);
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&bytes)), bytes);
+    }
+}
@@ -0,0 +1,246 @@
+//! Bridge to the KV v2 secrets engine of HashiCorp Vault.
+//!
+//! A secret at `<prefix>/team/db` with the fields `value` and `user` maps to
+//! the mopm keys `team/db` and `team/db/user`. Exporting writes every key as
+//! a secret with a single `value` field, so an export followed by an import
+//! gives back the same keys. Imported entries record the folder of their
+//! secret, e.g. `secret/team`, and when its version was written.
+
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::core::{
+    conflict::Resolver,
+    encryptor::Encryprtor,
+    manager::{PasswordManager, PasswordManagerError},
+    pool::Pool,
+    provenance::Provenance,
+    timelock,
+};
+
+const VALUE_FIELD: &str = "value";
+const SOURCE: &str = "hashivault";
+
+#[derive(Error, Debug)]
+pub enum HashiVaultError {
+    #[error("request to vault failed: `{0}`")]
+    RequestError(String),
+    #[error("unexpected response from vault at `{0}`")]
+    InvalidResponse(String),
+    #[error("{0}")]
+    PasswordManagerError(#[from] PasswordManagerError),
+}
+
+impl From<ureq::Error> for HashiVaultError {
+    fn from(value: ureq::Error) -> Self {
+        Self::RequestError(value.to_string())
+    }
+}
+
+pub struct HashiVault {
+    addr: String,
+    token: String,
+    mount: String,
+    prefix: String,
+}
+
+impl HashiVault {
+    /// `path` is the mount of the secrets engine optionally followed by a
+    /// prefix inside it, e.g. `secret/` or `secret/team/`.
+    pub fn new(addr: &str, token: &str, path: &str) -> Self {
+        let path = path.trim_matches('/');
+        let (mount, prefix) = path.split_once('/').unwrap_or((path, ""));
+
+        Self {
+            addr: addr.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            mount: mount.to_string(),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    /// Copies every secret below the prefix into `pm`, resolving duplicates
+    /// of existing entries with `resolver`, and returns the number of stored
+    /// keys. Secrets are fetched on the threads of `pool` and stored in the
+    /// order they are listed.
+    pub fn import<T: Encryprtor>(
+        &self,
+        pm: &mut PasswordManager<T>,
+        resolver: &mut Resolver,
+        pool: &Pool,
+    ) -> Result<usize, HashiVaultError> {
+        let secrets = pool.map(
+            self.list("")?,
+            || (),
+            |_, path| -> Result<_, HashiVaultError> {
+                let (data, modified) = self.read(&path)?;
+                let provenance = Provenance {
+                    folder: Some(self.folder(&path)),
+                    modified,
+                    ..Provenance::new(SOURCE)
+                };
+                Ok((secret_to_entries(&path, &data), provenance))
+            },
+        );
+        let mut count = 0;
+        for secret in secrets {
+            let (entries, provenance) = secret?;
+            for (key, value) in entries {
+                if let Some(key) = pm.store_resolved(key, &value, resolver)? {
+                    pm.set_provenance(&key, &provenance)?;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Writes every static entry of `pm` below the prefix and returns the
+    /// number of written secrets.
+    pub fn export<T: Encryprtor>(
+        &self,
+        pm: &mut PasswordManager<T>,
+    ) -> Result<usize, HashiVaultError> {
+        let keys: Vec<String> = pm
+            .keys()
+            .into_iter()
+            .filter(|key| !key.is_empty() && !pm.is_dynamic(key))
+            .map(String::from)
+            .collect();
+        for key in &keys {
+            let value = pm.get_password(key)?;
+            ureq::post(&self.url("data", key))
+                .set("X-Vault-Token", &self.token)
+                .send_json(json!({ "data": { VALUE_FIELD: value } }))?;
+        }
+        Ok(keys.len())
+    }
+
+    fn url(&self, kind: &str, path: &str) -> String {
+        let full = [self.prefix.as_str(), path]
+            .into_iter()
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        format!("{}/v1/{}/{}/{}", self.addr, self.mount, kind, full)
+    }
+
+    /// The folder of the secret at `path` as Vault shows it, with the mount.
+    fn folder(&self, path: &str) -> String {
+        let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        [self.mount.as_str(), self.prefix.as_str(), dir]
+            .into_iter()
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Recursively lists secret paths below `dir`, relative to the prefix.
+    fn list(&self, dir: &str) -> Result<Vec<String>, HashiVaultError> {
+        let response = match ureq::get(&self.url("metadata", dir))
+            .set("X-Vault-Token", &self.token)
+            .query("list", "true")
+            .call()
+        {
+            Ok(v) => v,
+            Err(ureq::Error::Status(404, _)) => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let body: Value = response
+            .into_json()
+            .map_err(|_| HashiVaultError::InvalidResponse(dir.to_string()))?;
+        let names = body["data"]["keys"]
+            .as_array()
+            .ok_or_else(|| HashiVaultError::InvalidResponse(dir.to_string()))?;
+
+        let mut paths = vec![];
+        for name in names.iter().filter_map(Value::as_str) {
+            let path = format!("{}{}", dir, name);
+            if name.ends_with('/') {
+                paths.extend(self.list(&path)?);
+            } else {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// The fields of the secret at `path` and when its current version was
+    /// written.
+    fn read(&self, path: &str) -> Result<(Map<String, Value>, Option<u64>), HashiVaultError> {
+        let body: Value = ureq::get(&self.url("data", path))
+            .set("X-Vault-Token", &self.token)
+            .call()?
+            .into_json()
+            .map_err(|_| HashiVaultError::InvalidResponse(path.to_string()))?;
+        let data = body["data"]["data"]
+            .as_object()
+            .cloned()
+            .ok_or_else(|| HashiVaultError::InvalidResponse(path.to_string()))?;
+        // RFC 3339 with nanoseconds, e.g. `2018-03-22T02:24:06.945319214Z`.
+        let written = body["data"]["metadata"]["created_time"]
+            .as_str()
+            .and_then(|v| v.get(..19))
+            .and_then(|v| timelock::parse_time(v).ok());
+        Ok((data, written))
+    }
+}
+
+fn secret_to_entries(path: &str, data: &Map<String, Value>) -> Vec<(String, String)> {
+    data.iter()
+        .filter_map(|(field, value)| {
+            let value = match value {
+                Value::String(v) => v.clone(),
+                Value::Null => return None,
+                other => other.to_string(),
+            };
+            let key = match field.as_str() {
+                VALUE_FIELD => path.to_string(),
+                field => format!("{}/{}", path, field),
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url() {
+        let vault = HashiVault::new("http://127.0.0.1:8200/", "t", "secret/");
+        assert_eq!(
+            vault.url("data", "team/db"),
+            "http://127.0.0.1:8200/v1/secret/data/team/db"
+        );
+
+        let vault = HashiVault::new("http://127.0.0.1:8200", "t", "/kv/team/");
+        assert_eq!(
+            vault.url("metadata", ""),
+            "http://127.0.0.1:8200/v1/kv/metadata/team"
+        );
+        assert_eq!(
+            vault.url("data", "db"),
+            "http://127.0.0.1:8200/v1/kv/data/team/db"
+        );
+        assert_eq!(vault.folder("db"), "kv/team");
+        assert_eq!(vault.folder("prod/db"), "kv/team/prod");
+    }
+
+    #[test]
+    fn test_secret_to_entries() {
+        let data = json!({ "value": "a", "user": "b", "port": 5432, "empty": null });
+        let mut entries = secret_to_entries("team/db", data.as_object().unwrap());
+        entries.sort();
+
+        assert_eq!(
+            entries,
+            vec![
+                ("team/db".to_string(), "a".to_string()),
+                ("team/db/port".to_string(), "5432".to_string()),
+                ("team/db/user".to_string(), "b".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Checks passwords against the Pwned Passwords list of Have I Been Pwned
+//! by k-anonymity: only the first five hex digits of the SHA-1 of a password
+//! leave the machine, and the matching suffixes are compared locally.
+//! Responses are padded so their size tells nothing about the prefix.
+
+use std::collections::HashMap;
+
+use sha1::{Digest, Sha1};
+use thiserror::Error;
+
+const RANGE_URL: &str = "https://api.pwnedpasswords.com/range/";
+const PREFIX_LENGTH: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum HibpError {
+    #[error("request to Have I Been Pwned failed: `{0}`")]
+    RequestError(String),
+}
+
+impl From<ureq::Error> for HibpError {
+    fn from(value: ureq::Error) -> Self {
+        Self::RequestError(value.to_string())
+    }
+}
+
+impl From<std::io::Error> for HibpError {
+    fn from(value: std::io::Error) -> Self {
+        Self::RequestError(value.to_string())
+    }
+}
+
+/// A client that asks for every range once.
+#[derive(Default)]
+pub struct Hibp {
+    ranges: HashMap<String, String>,
+}
+
+impl Hibp {
+    /// How many times `password` appears in known breaches.
+    pub fn count(&mut self, password: &[u8]) -> Result<u64, HibpError> {
+        let digest = hex::encode_upper(Sha1::digest(password));
+        let (prefix, suffix) = digest.split_at(PREFIX_LENGTH);
+        if !self.ranges.contains_key(prefix) {
+            let body = ureq::get(&format!("{}{}", RANGE_URL, prefix))
+                .set("Add-Padding", "true")
+                .call()?
+                .into_string()?;
+            self.ranges.insert(prefix.to_string(), body);
+        }
+        Ok(parse_range(&self.ranges[prefix], suffix))
+    }
+}
+
+/// The count of `suffix` in a range response of `SUFFIX:COUNT` lines.
+/// Padding lines have a count of zero.
+fn parse_range(body: &str, suffix: &str) -> u64 {
+    body.lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .find(|(v, _)| v.eq_ignore_ascii_case(suffix))
+        .and_then(|(_, count)| count.parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let body = "0018A45C4D1DEF81644B54AB7F969B88D65:10\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n00D4F6E8FA6EECAD2A3AA415EEC418D38EC:0\r\n";
+        // The suffix of the SHA-1 of "password".
+        assert_eq!(
+            parse_range(body, "1E4C9B93F3F0682250B6CF8331B7EE68FD8"),
+            3861493
+        );
+        assert_eq!(
+            parse_range(body, "1e4c9b93f3f0682250b6cf8331b7ee68fd8"),
+            3861493
+        );
+        assert_eq!(parse_range(body, "00D4F6E8FA6EECAD2A3AA415EEC418D38EC"), 0);
+        assert_eq!(parse_range(body, "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"), 0);
+    }
+}
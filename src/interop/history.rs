@@ -0,0 +1,101 @@
+//! Shell history files, which keep every password typed as an argument,
+//! e.g. `mopm store db hunter2`.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// History files of bash, zsh and fish, relative to the home directory.
+const HISTORY_FILES: [&str; 5] = [
+    ".bash_history",
+    ".zsh_history",
+    ".zhistory",
+    ".histfile",
+    ".local/share/fish/fish_history",
+];
+/// Fish writes an entry as a `- cmd:` line followed by indented fields.
+const FISH_ENTRY: &[u8] = b"- cmd: ";
+const FISH_FIELD: &[u8] = b"  ";
+
+/// The existing history files of the user, `$HISTFILE` first.
+pub fn history_files(home: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::env::var_os("HISTFILE")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(HISTORY_FILES.iter().map(|v| home.join(v)))
+        .filter(|v| v.is_file())
+        .collect();
+    let mut seen = BTreeSet::new();
+    files.retain(|v| seen.insert(fs::canonicalize(v).unwrap_or_else(|_| v.clone())));
+    files
+}
+
+/// Rewrites the file at `path` without the given 1-based `lines` and
+/// returns how many were removed. The file is replaced atomically and
+/// keeps its permissions.
+pub fn scrub(path: &Path, lines: &BTreeSet<usize>) -> io::Result<usize> {
+    let contents = fs::read(path)?;
+    let (kept, removed) = remove_lines(&contents, lines);
+    if removed == 0 {
+        return Ok(0);
+    }
+
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".mopm-scrub");
+    let tmp = PathBuf::from(tmp_name);
+    let mut file = fs::File::create(&tmp)?;
+    file.set_permissions(fs::metadata(path)?.permissions())?;
+    file.write_all(&kept)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(removed)
+}
+
+fn remove_lines(contents: &[u8], lines: &BTreeSet<usize>) -> (Vec<u8>, usize) {
+    let mut kept = Vec::with_capacity(contents.len());
+    let mut removed = 0;
+    let mut in_removed_entry = false;
+    for (index, line) in contents.split_inclusive(|&b| b == b'\n').enumerate() {
+        if lines.contains(&(index + 1)) {
+            in_removed_entry = line.starts_with(FISH_ENTRY);
+            removed += 1;
+        } else if in_removed_entry && line.starts_with(FISH_FIELD) {
+            removed += 1;
+        } else {
+            in_removed_entry = false;
+            kept.extend_from_slice(line);
+        }
+    }
+    (kept, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_lines() {
+        let history = b"ls\nmopm store db hunter2\ncd /\n";
+        assert_eq!(
+            remove_lines(history, &BTreeSet::from([2])),
+            (b"ls\ncd /\n".to_vec(), 1)
+        );
+        assert_eq!(
+            remove_lines(b"ls\nsecret", &BTreeSet::from([2])),
+            (b"ls\n".to_vec(), 1)
+        );
+    }
+
+    #[test]
+    fn test_remove_fish_entry() {
+        let history = b"- cmd: ls\n  when: 1\n- cmd: echo hunter2\n  when: 2\n  paths:\n    - x\n- cmd: cd\n  when: 3\n";
+        assert_eq!(
+            remove_lines(history, &BTreeSet::from([3])),
+            (b"- cmd: ls\n  when: 1\n- cmd: cd\n  when: 3\n".to_vec(), 4)
+        );
+    }
+}
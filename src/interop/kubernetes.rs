@@ -0,0 +1,354 @@
+//! Keeps Kubernetes Secrets in sync with vault entries through the REST API.
+//!
+//! A Secret opts in with the annotation `mopm/entries`, a comma separated
+//! list of `field=key` pairs: `password=work/db,token=ci/deploy` fills the
+//! `password` field of the Secret with the vault entry `work/db`. Syncing
+//! into the vault goes the other way and stores the fields under the keys.
+//!
+//! Whoever can annotate a Secret chooses the keys, so only keys under a
+//! prefix given on the command line, `k8s/` by default, may be named: an
+//! annotation cannot read `work/db` into the cluster or overwrite it.
+//!
+//! The default server is `kubectl proxy` on localhost, which handles the
+//! credentials of the kubeconfig; other servers take a bearer token. Plain
+//! http is only accepted on loopback and never carries the token.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+use url::{Host, Url};
+
+use crate::core::{
+    encryptor::Encryprtor,
+    manager::{PasswordManager, PasswordManagerError},
+};
+
+pub const DEFAULT_SERVER: &str = "http://127.0.0.1:8001";
+pub const DEFAULT_NAMESPACE: &str = "default";
+pub const DEFAULT_PREFIX: &str = "k8s/";
+const ENTRIES_ANNOTATION: &str = "mopm/entries";
+
+#[derive(Error, Debug)]
+pub enum KubernetesError {
+    #[error("request to the cluster failed: `{0}`")]
+    RequestError(String),
+    #[error("unexpected response from the cluster for `{0}`")]
+    InvalidResponse(String),
+    #[error("invalid `{ENTRIES_ANNOTATION}` annotation on secret `{0}`")]
+    InvalidAnnotation(String),
+    #[error("field `{1}` of secret `{0}` is not valid utf-8 base64")]
+    InvalidField(String, String),
+    #[error("secret `{0}` names the entry `{1}`, which is not under `{2}`")]
+    KeyNotAllowed(String, String, String),
+    #[error("invalid server `{0}`")]
+    InvalidServer(String),
+    #[error("server `{0}` is neither https nor loopback")]
+    InsecureServer(String),
+    #[error("refusing to send the token to `{0}` over plain http")]
+    InsecureToken(String),
+    #[error("{0}")]
+    PasswordManagerError(#[from] PasswordManagerError),
+}
+
+impl From<ureq::Error> for KubernetesError {
+    fn from(value: ureq::Error) -> Self {
+        Self::RequestError(value.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ToCluster,
+    ToVault,
+}
+
+impl Direction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "to-cluster" => Some(Self::ToCluster),
+            "to-vault" => Some(Self::ToVault),
+            _ => None,
+        }
+    }
+}
+
+/// An annotated Secret and the vault key of each of its fields.
+#[derive(Debug, PartialEq, Eq)]
+struct Secret {
+    name: String,
+    data: Map<String, Value>,
+    entries: Vec<(String, String)>,
+}
+
+pub struct Kubernetes {
+    server: String,
+    token: Option<String>,
+    namespace: String,
+    prefix: String,
+}
+
+impl Kubernetes {
+    /// Fails unless `server` is https, or plain http on loopback without a
+    /// token.
+    pub fn new(
+        server: &str,
+        token: Option<&str>,
+        namespace: &str,
+        prefix: &str,
+    ) -> Result<Self, KubernetesError> {
+        let url = Url::parse(server).map_err(|_| KubernetesError::InvalidServer(server.into()))?;
+        match url.scheme() {
+            "https" => {}
+            "http" if !is_loopback(&url) => {
+                return Err(KubernetesError::InsecureServer(server.into()))
+            }
+            "http" if token.is_some() => return Err(KubernetesError::InsecureToken(server.into())),
+            "http" => {}
+            _ => return Err(KubernetesError::InvalidServer(server.into())),
+        }
+        Ok(Self {
+            server: server.trim_end_matches('/').to_string(),
+            token: token.map(String::from),
+            namespace: namespace.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Syncs the annotated Secrets matching the label `selector` and returns
+    /// the number of Secret fields or vault entries that changed.
+    pub fn sync<T: Encryprtor>(
+        &self,
+        pm: &mut PasswordManager<T>,
+        selector: Option<&str>,
+        direction: Direction,
+    ) -> Result<usize, KubernetesError> {
+        let mut changed = 0;
+        for secret in self.secrets(selector)? {
+            changed += match direction {
+                Direction::ToCluster => self.fill_secret(pm, &secret)?,
+                Direction::ToVault => store_secret(pm, &secret)?,
+            };
+        }
+        Ok(changed)
+    }
+
+    fn fill_secret<T: Encryprtor>(
+        &self,
+        pm: &mut PasswordManager<T>,
+        secret: &Secret,
+    ) -> Result<usize, KubernetesError> {
+        let mut patch = Map::new();
+        for (field, key) in &secret.entries {
+            let value = Value::from(STANDARD.encode(pm.get_password(key)?));
+            if secret.data.get(field) != Some(&value) {
+                patch.insert(field.clone(), value);
+            }
+        }
+        if patch.is_empty() {
+            return Ok(0);
+        }
+        self.request("PATCH", &self.secrets_url(Some(&secret.name)))
+            .set("Content-Type", "application/merge-patch+json")
+            .send_string(&json!({ "data": patch }).to_string())?;
+        Ok(patch.len())
+    }
+
+    fn secrets(&self, selector: Option<&str>) -> Result<Vec<Secret>, KubernetesError> {
+        let url = self.secrets_url(None);
+        let mut request = self.request("GET", &url);
+        if let Some(selector) = selector {
+            request = request.query("labelSelector", selector);
+        }
+        let body: Value = request
+            .call()?
+            .into_json()
+            .map_err(|_| KubernetesError::InvalidResponse(url.clone()))?;
+        let items = body["items"]
+            .as_array()
+            .ok_or(KubernetesError::InvalidResponse(url))?;
+        items
+            .iter()
+            .filter_map(|item| {
+                let name = item["metadata"]["name"].as_str()?.to_string();
+                let annotation = item["metadata"]["annotations"][ENTRIES_ANNOTATION].as_str()?;
+                Some(
+                    parse_entries(annotation)
+                        .ok_or_else(|| KubernetesError::InvalidAnnotation(name.clone()))
+                        .and_then(|entries| self.check_entries(&name, entries))
+                        .map(|entries| Secret {
+                            data: item["data"].as_object().cloned().unwrap_or_default(),
+                            name,
+                            entries,
+                        }),
+                )
+            })
+            .collect()
+    }
+
+    /// Fails on the first key out of the prefix, before anything is synced.
+    fn check_entries(
+        &self,
+        name: &str,
+        entries: Vec<(String, String)>,
+    ) -> Result<Vec<(String, String)>, KubernetesError> {
+        match entries
+            .iter()
+            .find(|(_, key)| !key.starts_with(&self.prefix))
+        {
+            Some((_, key)) => Err(KubernetesError::KeyNotAllowed(
+                name.to_string(),
+                key.clone(),
+                self.prefix.clone(),
+            )),
+            None => Ok(entries),
+        }
+    }
+
+    fn secrets_url(&self, name: Option<&str>) -> String {
+        let url = format!(
+            "{}/api/v1/namespaces/{}/secrets",
+            self.server, self.namespace
+        );
+        match name {
+            Some(name) => format!("{}/{}", url, name),
+            None => url,
+        }
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = ureq::request(method, url);
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+}
+
+fn store_secret<T: Encryprtor>(
+    pm: &mut PasswordManager<T>,
+    secret: &Secret,
+) -> Result<usize, KubernetesError> {
+    let mut changed = 0;
+    for (field, key) in &secret.entries {
+        let Some(encoded) = secret.data.get(field).and_then(Value::as_str) else {
+            continue;
+        };
+        let value = STANDARD
+            .decode(encoded)
+            .ok()
+            .and_then(|v| String::from_utf8(v).ok())
+            .ok_or_else(|| KubernetesError::InvalidField(secret.name.clone(), field.clone()))?;
+        if pm.keys().contains(&key.as_str()) && pm.get_password(key)? == value {
+            continue;
+        }
+        pm.store_password(key.clone(), &value)?;
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Ipv4(v)) => v.is_loopback(),
+        Some(Host::Ipv6(v)) => v.is_loopback(),
+        Some(Host::Domain(v)) => v == "localhost",
+        None => false,
+    }
+}
+
+/// Parses `field=key` pairs, `None` if any pair is malformed.
+fn parse_entries(annotation: &str) -> Option<Vec<(String, String)>> {
+    annotation
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|pair| {
+            let (field, key) = pair.split_once('=')?;
+            let (field, key) = (field.trim(), key.trim());
+            (!field.is_empty() && !key.is_empty()).then(|| (field.to_string(), key.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::encryptor::AESEncryptor;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_entries() {
+        assert_eq!(
+            parse_entries("password=work/db, token = ci/deploy,"),
+            Some(vec![
+                ("password".to_string(), "work/db".to_string()),
+                ("token".to_string(), "ci/deploy".to_string()),
+            ])
+        );
+        assert_eq!(parse_entries("password"), None);
+        assert_eq!(parse_entries("=work/db"), None);
+    }
+
+    #[test]
+    fn test_secrets_url() {
+        let cluster = Kubernetes::new("http://127.0.0.1:8001/", None, "apps", "").unwrap();
+        assert_eq!(
+            cluster.secrets_url(Some("db")),
+            "http://127.0.0.1:8001/api/v1/namespaces/apps/secrets/db"
+        );
+    }
+
+    #[test]
+    fn test_server() {
+        let new = |server, token| Kubernetes::new(server, token, DEFAULT_NAMESPACE, DEFAULT_PREFIX);
+        assert!(new(DEFAULT_SERVER, None).is_ok());
+        assert!(new("http://localhost:8001", None).is_ok());
+        assert!(new("http://[::1]:8001", None).is_ok());
+        assert!(new("https://cluster.example:6443", Some("token")).is_ok());
+        assert!(matches!(
+            new("http://cluster.example:6443", None),
+            Err(KubernetesError::InsecureServer(_))
+        ));
+        assert!(matches!(
+            new(DEFAULT_SERVER, Some("token")),
+            Err(KubernetesError::InsecureToken(_))
+        ));
+        assert!(matches!(
+            new("cluster.example", None),
+            Err(KubernetesError::InvalidServer(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let cluster = Kubernetes::new(DEFAULT_SERVER, None, DEFAULT_NAMESPACE, "k8s/").unwrap();
+        let entries = parse_entries("password=k8s/db,token=k8s/ci").unwrap();
+        assert_eq!(
+            cluster.check_entries("db", entries.clone()).unwrap(),
+            entries
+        );
+        assert!(matches!(
+            cluster.check_entries("db", parse_entries("password=k8s/db,key=work/db").unwrap()),
+            Err(KubernetesError::KeyNotAllowed(_, key, _)) if key == "work/db"
+        ));
+    }
+
+    #[test]
+    fn test_store_secret() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("pw"));
+        pm.store_password("work/db".to_string(), "same").unwrap();
+        let secret = Secret {
+            name: "db".to_string(),
+            data: json!({ "password": STANDARD.encode("same"), "token": STANDARD.encode("new") })
+                .as_object()
+                .cloned()
+                .unwrap(),
+            entries: parse_entries("password=work/db,token=ci/deploy,missing=x").unwrap(),
+        };
+        assert_eq!(store_secret(&mut pm, &secret).unwrap(), 1);
+        assert_eq!(pm.get_password("ci/deploy"), Ok("new".to_string()));
+        assert!(!pm.keys().contains(&"x"));
+    }
+}
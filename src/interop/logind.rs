@@ -0,0 +1,186 @@
+//! Notices when the session is locked or the machine goes to sleep, so that
+//! a process holding the unlocked vault can let go of it. With the `logind`
+//! feature the `Lock` and `PrepareForSleep` signals of systemd-logind are
+//! read from the system bus, and a `delay` inhibitor holds the sleep off
+//! until the caller has let go; otherwise, or when the bus cannot be
+//! reached, the `LockedHint` of the session is polled and a resume is told
+//! by the wall clock running ahead of the monotonic one, after the fact.
+
+use std::{
+    io,
+    os::fd::OwnedFd,
+    process::Command,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+const SESSION_ENV: &str = "XDG_SESSION_ID";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How far the wall clock may run ahead of the monotonic clock between two
+/// polls before it counts as a suspend.
+const SUSPEND_SLACK: Duration = Duration::from_secs(5);
+#[cfg(feature = "logind")]
+const LOGIND: &str = "org.freedesktop.login1";
+#[cfg(feature = "logind")]
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+#[cfg(feature = "logind")]
+const MANAGER: &str = "org.freedesktop.login1.Manager";
+#[cfg(feature = "logind")]
+const SESSION: &str = "org.freedesktop.login1.Session";
+#[cfg(feature = "logind")]
+const INHIBIT_WHY: &str = "Unmount the vault before sleeping";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockEvent {
+    Locked,
+    Suspended,
+}
+
+/// Holds off the sleep of the machine until it is dropped, for as long as
+/// logind allows (`InhibitDelayMaxSec`). Empty when the event was polled.
+#[derive(Debug)]
+pub struct Inhibitor {
+    _fd: Option<OwnedFd>,
+}
+
+/// Blocks until the session in `$XDG_SESSION_ID` is locked, or the one of
+/// the caller without it, or the machine suspends. The inhibitor is to be
+/// dropped once the vault is let go of.
+pub fn wait() -> io::Result<(LockEvent, Inhibitor)> {
+    let session = std::env::var(SESSION_ENV).ok();
+    #[cfg(feature = "logind")]
+    if let Ok(event) = monitor(session.as_deref()) {
+        return Ok(event);
+    }
+    let event = poll(session.as_deref().unwrap_or("self"))?;
+    Ok((event, Inhibitor { _fd: None }))
+}
+
+#[cfg(feature = "logind")]
+fn monitor(session: Option<&str>) -> zbus::Result<(LockEvent, Inhibitor)> {
+    use zbus::{
+        blocking::{Connection, MessageIterator, Proxy},
+        message::Type,
+        zvariant::{self, OwnedObjectPath},
+        MatchRule,
+    };
+
+    let connection = Connection::system()?;
+    let manager = Proxy::new(&connection, LOGIND, MANAGER_PATH, MANAGER)?;
+    let session: OwnedObjectPath = match session {
+        Some(id) => manager.call("GetSession", &(id,))?,
+        None => manager.call("GetSessionByPID", &(std::process::id(),))?,
+    };
+    // Subscribed before the inhibitor is taken, so that no sleep is missed
+    // once it is held.
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender(LOGIND)?
+        .build();
+    let mut signals = MessageIterator::for_match_rule(rule, &connection, None)?;
+    let fd: zvariant::OwnedFd = manager.call(
+        "Inhibit",
+        &("sleep", env!("CARGO_PKG_NAME"), INHIBIT_WHY, "delay"),
+    )?;
+    let inhibitor = Inhibitor {
+        _fd: Some(fd.into()),
+    };
+
+    for message in &mut signals {
+        let message = message?;
+        let header = message.header();
+        let (Some(interface), Some(member), Some(path)) =
+            (header.interface(), header.member(), header.path())
+        else {
+            continue;
+        };
+        let sleeping = match (interface.as_str(), member.as_str()) {
+            (MANAGER, "PrepareForSleep") => message.body().deserialize::<bool>().ok(),
+            _ => None,
+        };
+        let event = classify(
+            interface.as_str(),
+            member.as_str(),
+            path.as_str(),
+            sleeping,
+            session.as_str(),
+        );
+        if let Some(event) = event {
+            return Ok((event, inhibitor));
+        }
+    }
+    Err(zbus::Error::InputOutput(
+        io::Error::new(io::ErrorKind::UnexpectedEof, "the system bus went away").into(),
+    ))
+}
+
+/// The event a signal of logind stands for. `Lock` signals of sessions
+/// other than `session` are ignored, as is the end of a sleep.
+#[cfg(feature = "logind")]
+fn classify(
+    interface: &str,
+    member: &str,
+    path: &str,
+    sleeping: Option<bool>,
+    session: &str,
+) -> Option<LockEvent> {
+    match (interface, member) {
+        (MANAGER, "PrepareForSleep") if sleeping == Some(true) => Some(LockEvent::Suspended),
+        (SESSION, "Lock") if path == session => Some(LockEvent::Locked),
+        _ => None,
+    }
+}
+
+fn poll(session: &str) -> io::Result<LockEvent> {
+    let (mut instant, mut wall) = (Instant::now(), SystemTime::now());
+    loop {
+        if is_locked(session)? {
+            return Ok(LockEvent::Locked);
+        }
+        thread::sleep(POLL_INTERVAL);
+
+        let (now_instant, now_wall) = (Instant::now(), SystemTime::now());
+        let slept = now_wall.duration_since(wall).unwrap_or_default();
+        if slept > now_instant.duration_since(instant) + SUSPEND_SLACK {
+            return Ok(LockEvent::Suspended);
+        }
+        (instant, wall) = (now_instant, now_wall);
+    }
+}
+
+fn is_locked(session: &str) -> io::Result<bool> {
+    let output = Command::new("loginctl")
+        .args(["show-session", session, "--property=LockedHint", "--value"])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "yes")
+}
+
+#[cfg(all(test, feature = "logind"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let ours = "/org/freedesktop/login1/session/_32";
+        let other = "/org/freedesktop/login1/session/_33";
+        assert_eq!(
+            classify(SESSION, "Lock", ours, None, ours),
+            Some(LockEvent::Locked)
+        );
+        assert_eq!(classify(SESSION, "Lock", other, None, ours), None);
+        assert_eq!(classify(SESSION, "Unlock", ours, None, ours), None);
+        assert_eq!(
+            classify(MANAGER, "PrepareForSleep", MANAGER_PATH, Some(true), ours),
+            Some(LockEvent::Suspended)
+        );
+        assert_eq!(
+            classify(MANAGER, "PrepareForSleep", MANAGER_PATH, Some(false), ours),
+            None
+        );
+    }
+}
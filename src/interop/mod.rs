@@ -0,0 +1,25 @@
+pub mod bitwarden;
+#[cfg(feature = "browser")]
+pub mod browser;
+pub mod dotenv;
+pub mod git;
+pub mod handoff;
+#[cfg(feature = "hashivault")]
+pub mod hashivault;
+#[cfg(feature = "monitor")]
+pub mod hibp;
+pub mod history;
+#[cfg(feature = "k8s")]
+pub mod kubernetes;
+#[cfg(feature = "fuse")]
+pub mod logind;
+#[cfg(feature = "otpauth")]
+pub mod otpauth;
+#[cfg(feature = "ssh-agent")]
+pub mod peers;
+#[cfg(feature = "share")]
+pub mod share;
+#[cfg(feature = "ssh-agent")]
+pub mod sshagent;
+#[cfg(feature = "tpm")]
+pub mod tpm;
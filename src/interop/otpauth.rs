@@ -0,0 +1,357 @@
+//! Import of the accounts in Google Authenticator's "Transfer accounts" QR
+//! codes. Each code holds an `otpauth-migration://offline?data=...` link,
+//! the data being a base64 protobuf `MigrationPayload`. Every account is
+//! stored under `otp/<issuer>:<name>` as the `otpauth://` URI other
+//! authenticators read, with the account name as its username. The codes
+//! carry no times, so the secrets date from the import.
+
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use thiserror::Error;
+use url::Url;
+
+use crate::core::{
+    conflict::Resolver,
+    encryptor::Encryprtor,
+    manager::{PasswordManager, PasswordManagerError},
+    provenance::Provenance,
+};
+
+const SCHEME: &str = "otpauth-migration";
+const KEY_PREFIX: &str = "otp/";
+const SOURCE: &str = "google-authenticator";
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+// Field numbers of the protobuf messages.
+const PAYLOAD_ACCOUNT: u64 = 1;
+const PAYLOAD_BATCH_SIZE: u64 = 3;
+const PAYLOAD_BATCH_INDEX: u64 = 4;
+const ACCOUNT_SECRET: u64 = 1;
+const ACCOUNT_NAME: u64 = 2;
+const ACCOUNT_ISSUER: u64 = 3;
+const ACCOUNT_ALGORITHM: u64 = 4;
+const ACCOUNT_DIGITS: u64 = 5;
+const ACCOUNT_TYPE: u64 = 6;
+const ACCOUNT_COUNTER: u64 = 7;
+
+#[derive(Error, Debug)]
+pub enum OtpauthError {
+    #[error("not an otpauth-migration:// link")]
+    InvalidLink,
+    #[error("the link holds no valid base64 data")]
+    InvalidData,
+    #[error("the migration payload is malformed")]
+    Malformed,
+    #[error("{0}")]
+    PasswordManagerError(#[from] PasswordManagerError),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Account {
+    pub name: String,
+    pub issuer: String,
+    secret: Vec<u8>,
+    algorithm: &'static str,
+    digits: u8,
+    /// The counter of an HOTP account, `None` for TOTP.
+    counter: Option<u64>,
+}
+
+impl Account {
+    /// The key the account is stored under. Slashes would nest it in
+    /// namespaces, so they are replaced.
+    pub fn key(&self) -> String {
+        format!("{}{}", KEY_PREFIX, self.label().replace('/', "-"))
+    }
+
+    /// The `otpauth://` URI of the account.
+    pub fn uri(&self) -> String {
+        let label = match self.issuer.is_empty() {
+            true => percent_encode(&self.name),
+            false => format!(
+                "{}:{}",
+                percent_encode(&self.issuer),
+                percent_encode(&self.name)
+            ),
+        };
+        let mut uri = format!(
+            "otpauth://{}/{}?secret={}",
+            match self.counter {
+                Some(_) => "hotp",
+                None => "totp",
+            },
+            label,
+            base32(&self.secret),
+        );
+        if !self.issuer.is_empty() {
+            uri.push_str(&format!("&issuer={}", percent_encode(&self.issuer)));
+        }
+        uri.push_str(&format!(
+            "&algorithm={}&digits={}",
+            self.algorithm, self.digits
+        ));
+        if let Some(counter) = self.counter {
+            uri.push_str(&format!("&counter={}", counter));
+        }
+        uri
+    }
+
+    fn label(&self) -> String {
+        match self.issuer.is_empty() || self.name.starts_with(&self.issuer) {
+            true => self.name.clone(),
+            false => format!("{}:{}", self.issuer, self.name),
+        }
+    }
+}
+
+/// The accounts of one QR code. Large exports are split over several codes,
+/// `batch` is the index of this one and their count.
+#[derive(Debug)]
+pub struct Migration {
+    pub accounts: Vec<Account>,
+    pub batch: (u64, u64),
+}
+
+pub fn parse(link: &str) -> Result<Migration, OtpauthError> {
+    let link = Url::parse(link.trim()).map_err(|_| OtpauthError::InvalidLink)?;
+    if link.scheme() != SCHEME {
+        return Err(OtpauthError::InvalidLink);
+    }
+    // A `+` of the base64 data that was not escaped reads as a space.
+    let data = link
+        .query_pairs()
+        .find(|(name, _)| name == "data")
+        .map(|(_, v)| v.replace(' ', "+"))
+        .ok_or(OtpauthError::InvalidData)?;
+    let engine = GeneralPurpose::new(
+        &alphabet::STANDARD,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+    let payload = engine.decode(data).map_err(|_| OtpauthError::InvalidData)?;
+
+    let mut migration = Migration {
+        accounts: Vec::new(),
+        batch: (0, 1),
+    };
+    for field in Fields(&payload) {
+        match field? {
+            (PAYLOAD_ACCOUNT, Value::Bytes(v)) => migration.accounts.push(account(v)?),
+            (PAYLOAD_BATCH_SIZE, Value::Varint(v)) => migration.batch.1 = v.max(1),
+            (PAYLOAD_BATCH_INDEX, Value::Varint(v)) => migration.batch.0 = v,
+            _ => {}
+        }
+    }
+    Ok(migration)
+}
+
+/// Stores `accounts` in `pm`, resolving duplicates of existing entries with
+/// `resolver`, and returns the number of stored entries.
+pub fn import<T: Encryprtor>(
+    pm: &mut PasswordManager<T>,
+    accounts: &[Account],
+    resolver: &mut Resolver,
+) -> Result<usize, OtpauthError> {
+    let mut count = 0;
+    for account in accounts {
+        let Some(key) = pm.store_resolved(account.key(), &account.uri(), resolver)? else {
+            continue;
+        };
+        if !account.name.is_empty() {
+            pm.set_meta(&key, "username", &account.name)?;
+        }
+        pm.set_provenance(&key, &Provenance::new(SOURCE))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn account(message: &[u8]) -> Result<Account, OtpauthError> {
+    let mut account = Account {
+        name: String::new(),
+        issuer: String::new(),
+        secret: Vec::new(),
+        algorithm: "SHA1",
+        digits: 6,
+        counter: None,
+    };
+    let mut hotp = false;
+    let mut counter = 0;
+    let text = |v: &[u8]| String::from_utf8(v.to_vec()).map_err(|_| OtpauthError::Malformed);
+    for field in Fields(message) {
+        match field? {
+            (ACCOUNT_SECRET, Value::Bytes(v)) => account.secret = v.to_vec(),
+            (ACCOUNT_NAME, Value::Bytes(v)) => account.name = text(v)?,
+            (ACCOUNT_ISSUER, Value::Bytes(v)) => account.issuer = text(v)?,
+            (ACCOUNT_ALGORITHM, Value::Varint(v)) => {
+                account.algorithm = match v {
+                    2 => "SHA256",
+                    3 => "SHA512",
+                    4 => "MD5",
+                    _ => "SHA1",
+                }
+            }
+            (ACCOUNT_DIGITS, Value::Varint(v)) => account.digits = if v == 2 { 8 } else { 6 },
+            (ACCOUNT_TYPE, Value::Varint(v)) => hotp = v == 1,
+            (ACCOUNT_COUNTER, Value::Varint(v)) => counter = v,
+            _ => {}
+        }
+    }
+    if account.secret.is_empty() {
+        return Err(OtpauthError::Malformed);
+    }
+    account.counter = hotp.then_some(counter);
+    Ok(account)
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// The fields of a protobuf message, with their numbers.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn varint(&mut self) -> Result<u64, OtpauthError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.0.split_first().ok_or(OtpauthError::Malformed)?;
+            self.0 = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(OtpauthError::Malformed)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], OtpauthError> {
+        if length > self.0.len() {
+            return Err(OtpauthError::Malformed);
+        }
+        let (value, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(value)
+    }
+
+    fn field(&mut self) -> Result<(u64, Value<'a>), OtpauthError> {
+        let tag = self.varint()?;
+        let value = match tag & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => self.take(8).map(|_| Value::Fixed)?,
+            2 => {
+                let length =
+                    usize::try_from(self.varint()?).map_err(|_| OtpauthError::Malformed)?;
+                Value::Bytes(self.take(length)?)
+            }
+            5 => self.take(4).map(|_| Value::Fixed)?,
+            _ => return Err(OtpauthError::Malformed),
+        };
+        Ok((tag >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, Value<'a>), OtpauthError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        if field.is_err() {
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+/// RFC 4648 base32 without padding, as otpauth URIs carry secrets.
+fn base32(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut block = [0; 5];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let bits = block.iter().fold(0u64, |v, b| v << 8 | u64::from(*b));
+        for i in 0..(chunk.len() * 8).div_ceil(5) {
+            encoded.push(char::from(BASE32[(bits >> (35 - 5 * i) & 31) as usize]));
+        }
+    }
+    encoded
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                char::from(b).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::{conflict::ConflictPolicy, encryptor::AESEncryptor};
+
+    use super::*;
+
+    /// Two accounts: a TOTP one with an issuer, and an HOTP one with eight
+    /// digits, SHA256 and a counter of 5.
+    const LINK: &str = "otpauth-migration://offline?data=Ci4KCkhlbGxvId6tvu8SEWFsaWNlQGV4YW1wbGUuY29tGgdFeGFtcGxlIAEoATACChQKBQECAwQFEgNib2IgAigCMAE4BRABGAIgACi5YA%3D%3D";
+
+    #[test]
+    fn test_parse() {
+        let migration = parse(LINK).unwrap();
+        assert_eq!(migration.batch, (0, 2));
+        let [totp, hotp] = &migration.accounts[..] else {
+            panic!("expected two accounts");
+        };
+        assert_eq!(totp.key(), "otp/Example:alice@example.com");
+        assert_eq!(
+            totp.uri(),
+            "otpauth://totp/Example:alice@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example&algorithm=SHA1&digits=6"
+        );
+        assert_eq!(hotp.key(), "otp/bob");
+        assert_eq!(
+            hotp.uri(),
+            "otpauth://hotp/bob?secret=AEBAGBAF&algorithm=SHA256&digits=8&counter=5"
+        );
+
+        assert!(matches!(
+            parse("otpauth://totp/x?secret=AA"),
+            Err(OtpauthError::InvalidLink)
+        ));
+        assert!(matches!(
+            parse("otpauth-migration://offline?data=CgM"),
+            Err(OtpauthError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"f"), "MY");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn test_import() {
+        let mut pm = PasswordManager::from_raw_parts(HashMap::new(), AESEncryptor::new("foo"));
+        let accounts = parse(LINK).unwrap().accounts;
+        let mut resolver = Resolver::new(ConflictPolicy::Skip, |_| unreachable!());
+        assert_eq!(import(&mut pm, &accounts, &mut resolver).unwrap(), 2);
+        assert_eq!(pm.meta("otp/bob", "username"), Some("bob"));
+        assert!(pm.is_unrotated_import("otp/bob"));
+        assert_eq!(import(&mut pm, &accounts, &mut resolver).unwrap(), 0);
+        assert_eq!(resolver.report.unchanged, 2);
+    }
+}
@@ -0,0 +1,141 @@
+//! The peers of the unix sockets mopm serves, as an agent, and the limits
+//! on what they may ask. A peer is known by SO_PEERCRED when it connects:
+//! its user, its process and the program the process runs, read from
+//! `/proc/<pid>/exe`.
+//!
+//! A server may admit only some programs, and may cap the attempts each
+//! user makes in a window, e.g. to unlock, so that a flood of guesses is
+//! refused rather than answered. A process of the user can exec an allowed
+//! program after connecting, so the list stops mistakes and runaway
+//! scripts rather than a determined attacker.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    fs, io,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+
+use crate::diagnostics::bug::OrBug;
+
+const LIMIT_POISONED: &str = "a session panicked while counting attempts";
+
+/// A process connected to a socket.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub pid: i32,
+    pub uid: u32,
+    pub exe: Option<PathBuf>,
+}
+
+impl Peer {
+    pub fn of(stream: &UnixStream) -> io::Result<Self> {
+        let credentials = getsockopt(stream, PeerCredentials)?;
+        Ok(Self {
+            pid: credentials.pid(),
+            uid: credentials.uid(),
+            exe: fs::read_link(format!("/proc/{}/exe", credentials.pid())).ok(),
+        })
+    }
+
+    /// Whether the program of the peer is in `allowed`, by path or by file
+    /// name.
+    pub fn is_allowed(&self, allowed: &[String]) -> bool {
+        let Some(exe) = &self.exe else {
+            return false;
+        };
+        allowed.iter().any(|v| match v.contains('/') {
+            true => exe == Path::new(v),
+            false => exe.file_name().is_some_and(|name| name == v.as_str()),
+        })
+    }
+}
+
+impl Display for Peer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.exe {
+            Some(exe) => write!(f, "{} (pid {}, uid {})", exe.display(), self.pid, self.uid),
+            None => write!(f, "pid {} (uid {})", self.pid, self.uid),
+        }
+    }
+}
+
+/// At most `max` attempts by each user in any `window`.
+#[derive(Debug)]
+pub struct RateLimit {
+    max: usize,
+    window: Duration,
+    attempts: Mutex<HashMap<u32, VecDeque<Instant>>>,
+}
+
+impl RateLimit {
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `uid` may make another attempt now, counting it if so.
+    pub fn take(&self, uid: u32) -> bool {
+        let mut attempts = self.attempts.lock().or_bug(LIMIT_POISONED);
+        let now = Instant::now();
+        // Users whose attempts all fell out of the window are forgotten.
+        attempts.retain(|_, v| {
+            while v
+                .front()
+                .is_some_and(|v| now.duration_since(*v) >= self.window)
+            {
+                v.pop_front();
+            }
+            !v.is_empty()
+        });
+        let attempts = attempts.entry(uid).or_default();
+        if attempts.len() >= self.max {
+            return false;
+        }
+        attempts.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = RateLimit::new(2, Duration::from_secs(60));
+        assert!(limit.take(1000));
+        assert!(limit.take(1000));
+        assert!(!limit.take(1000));
+        assert!(limit.take(1001));
+
+        for attempts in limit.attempts.lock().unwrap().values_mut() {
+            attempts.iter_mut().for_each(|v| *v -= limit.window);
+        }
+        assert!(limit.take(1000));
+        assert_eq!(limit.attempts.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_peer_is_allowed() {
+        let peer = Peer {
+            pid: 1,
+            uid: 1000,
+            exe: Some(PathBuf::from("/usr/bin/ssh")),
+        };
+        assert!(peer.is_allowed(&["git".to_string(), "ssh".to_string()]));
+        assert!(peer.is_allowed(&["/usr/bin/ssh".to_string()]));
+        assert!(!peer.is_allowed(&["/usr/local/bin/ssh".to_string()]));
+        assert!(!peer.is_allowed(&["sh".to_string()]));
+        let unknown = Peer { exe: None, ..peer };
+        assert!(!unknown.is_allowed(&["ssh".to_string()]));
+    }
+}
@@ -0,0 +1,261 @@
+//! One-time links to a single entry. The entry is encrypted to a random key
+//! on this machine, only the ciphertext leaves it, and the key travels in
+//! the fragment of the link (`http://host/<id>#<key>`), which HTTP clients
+//! never send to the server.
+//!
+//! The ciphertext is either uploaded to a relay, with
+//! `PUT <relay>/<id>` and an `X-Mopm-Ttl` header in seconds, or served by
+//! mopm itself until it is fetched once or the ttl runs out. A relay is
+//! expected to drop the ciphertext after the first `GET` or the ttl.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    core::{nonce::NONCE_LENGTH, rng},
+    diagnostics::bug::OrBug,
+};
+
+const TTL_HEADER: &str = "X-Mopm-Ttl";
+const KEY_LENGTH: usize = 32;
+const ID_LENGTH: usize = 16;
+const MAX_BLOB_SIZE: u64 = 1 << 20;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum ShareError {
+    #[error("request for the shared entry failed: `{0}`")]
+    RequestError(String),
+    #[error("invalid link, expected <url>#<key>")]
+    InvalidLink,
+    #[error("the link does not open the shared entry, it may be damaged or already used")]
+    InvalidBlob,
+    #[error("the link expired before it was used")]
+    Expired,
+    #[error("{0}")]
+    IoError(#[from] io::Error),
+}
+
+impl From<ureq::Error> for ShareError {
+    fn from(value: ureq::Error) -> Self {
+        Self::RequestError(value.to_string())
+    }
+}
+
+/// An entry encrypted for a link, and the key that opens it.
+pub struct Sealed {
+    id: String,
+    blob: Vec<u8>,
+    key: [u8; KEY_LENGTH],
+}
+
+impl Sealed {
+    pub fn new(name: &str, value: &str) -> Self {
+        let mut key = [0; KEY_LENGTH];
+        rng::fill(&mut key);
+        let mut id = [0; ID_LENGTH];
+        rng::fill(&mut id);
+        let mut nonce = [0; NONCE_LENGTH];
+        rng::fill(&mut nonce);
+
+        let plaintext = json!({ "key": name, "value": value }).to_string();
+        let ciphertext = Aes256Gcm::new(&key.into())
+            .encrypt(&nonce.into(), plaintext.as_bytes())
+            .or_bug("encrypting into a vec cannot fail");
+        Self {
+            id: hex::encode(id),
+            blob: [&nonce[..], &ciphertext].concat(),
+            key,
+        }
+    }
+
+    /// The link for the ciphertext once it is reachable at `<base>/<id>`.
+    pub fn link(&self, base: &str) -> String {
+        format!(
+            "{}/{}#{}",
+            base.trim_end_matches('/'),
+            self.id,
+            URL_SAFE_NO_PAD.encode(self.key)
+        )
+    }
+
+    pub fn upload(&self, relay: &str, ttl: Duration) -> Result<(), ShareError> {
+        ureq::put(&format!("{}/{}", relay.trim_end_matches('/'), self.id))
+            .set(TTL_HEADER, &ttl.as_secs().to_string())
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(&self.blob)?;
+        Ok(())
+    }
+
+    /// Serves the ciphertext to the first `GET /<id>` on `listener`, other
+    /// requests get a 404. Fails with `Expired` if nobody fetched it in time.
+    pub fn serve_once(&self, listener: &TcpListener, ttl: Duration) -> Result<(), ShareError> {
+        listener.set_nonblocking(true)?;
+        let deadline = Instant::now() + ttl;
+        let path = format!("/{}", self.id);
+        while Instant::now() < deadline {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if self.respond(stream, &path).unwrap_or(false) {
+                return Ok(());
+            }
+        }
+        Err(ShareError::Expired)
+    }
+
+    /// Answers one request and returns whether it fetched the ciphertext.
+    fn respond(&self, mut stream: TcpStream, path: &str) -> io::Result<bool> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+
+        let found = request_line.split_whitespace().take(2).eq(["GET", path]);
+        let (status, body): (_, &[u8]) = match found {
+            true => ("200 OK", &self.blob),
+            false => ("404 Not Found", b""),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+        stream.flush()?;
+        Ok(found)
+    }
+}
+
+/// The base url for links served from `addr`.
+pub fn served_base(addr: SocketAddr) -> String {
+    format!("http://{}", addr)
+}
+
+/// Fetches and decrypts the entry behind `link`, returning its key and value.
+pub fn receive(link: &str) -> Result<(String, String), ShareError> {
+    let (url, key) = parse_link(link)?;
+    let mut blob = Vec::new();
+    ureq::get(url)
+        .call()?
+        .into_reader()
+        .take(MAX_BLOB_SIZE)
+        .read_to_end(&mut blob)?;
+    open(&blob, &key)
+}
+
+fn parse_link(link: &str) -> Result<(&str, [u8; KEY_LENGTH]), ShareError> {
+    let (url, fragment) = link.split_once('#').ok_or(ShareError::InvalidLink)?;
+    let key = URL_SAFE_NO_PAD
+        .decode(fragment)
+        .ok()
+        .and_then(|v| v.try_into().ok())
+        .ok_or(ShareError::InvalidLink)?;
+    Ok((url, key))
+}
+
+fn open(blob: &[u8], key: &[u8; KEY_LENGTH]) -> Result<(String, String), ShareError> {
+    if blob.len() < NONCE_LENGTH {
+        return Err(ShareError::InvalidBlob);
+    }
+    let (nonce, ciphertext) = blob.split_at(NONCE_LENGTH);
+    let plaintext = Aes256Gcm::new(key.into())
+        .decrypt(nonce.into(), ciphertext)
+        .map_err(|_| ShareError::InvalidBlob)?;
+    let entry: Value = serde_json::from_slice(&plaintext).map_err(|_| ShareError::InvalidBlob)?;
+    match (entry["key"].as_str(), entry["value"].as_str()) {
+        (Some(key), Some(value)) => Ok((key.to_string(), value.to_string())),
+        _ => Err(ShareError::InvalidBlob),
+    }
+}
+
+/// Parses a duration such as `90`, `90s`, `10m`, `2h` or `1d`.
+pub fn parse_ttl(value: &str) -> Option<Duration> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (count, unit) = value.split_at(split);
+    let unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    let secs = count.parse::<u64>().ok()?.checked_mul(unit)?;
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let sealed = Sealed::new("work/db", "hunter2");
+        let link = sealed.link("http://relay.example/");
+        let (url, key) = parse_link(&link).unwrap();
+        assert_eq!(url, format!("http://relay.example/{}", sealed.id));
+        assert_eq!(
+            open(&sealed.blob, &key).unwrap(),
+            ("work/db".to_string(), "hunter2".to_string())
+        );
+
+        let mut damaged = sealed.blob.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(matches!(open(&damaged, &key), Err(ShareError::InvalidBlob)));
+        assert!(matches!(
+            parse_link("http://relay.example/x"),
+            Err(ShareError::InvalidLink)
+        ));
+    }
+
+    #[test]
+    fn test_serve_once() {
+        let sealed = Sealed::new("k", "v");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let link = sealed.link(&served_base(listener.local_addr().unwrap()));
+        let base = link.split_once('#').unwrap().0.rsplit_once('/').unwrap().0;
+        let missing = format!("{}/other", base);
+        let client = std::thread::spawn(move || {
+            assert!(ureq::get(&missing).call().is_err());
+            receive(&link).unwrap()
+        });
+        sealed
+            .serve_once(&listener, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(client.join().unwrap(), ("k".to_string(), "v".to_string()));
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("10m"), Some(Duration::from_secs(600)));
+        assert_eq!(parse_ttl("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_ttl("1d"), Some(Duration::from_secs(86_400)));
+        assert_eq!(parse_ttl("0s"), None);
+        assert_eq!(parse_ttl("m"), None);
+        assert_eq!(parse_ttl("5w"), None);
+    }
+}
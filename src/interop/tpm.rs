@@ -0,0 +1,533 @@
+//! Sealing a secret to the TPM 2.0 of this machine, for `mopm tpm enroll`.
+//! The TPM unseals it only while the PCRs hold the values they had when it
+//! was sealed, i.e. on this machine booted the same way: other firmware,
+//! another bootloader or secure boot turned off change them.
+//!
+//! The vault key is made of two random shares kept in key slots: one
+//! wrapped by a key derived from the password, the other sealed to the TPM
+//! and wrapped by a recovery code, shown once, which stands in for the TPM
+//! after a firmware update. Neither the password elsewhere nor this
+//! machine without the password opens the vault, and changing the password
+//! only wraps its share again, the vault itself is not re-encrypted.
+//!
+//! The TPM is driven through tpm2-tools rather than tss-esapi, so building
+//! with the `tpm` feature needs neither the TSS libraries nor their
+//! headers, and a missing tpm2-tools is an error when unlocking. The slots
+//! are kept as text next to the vault:
+//!
+//! ```text
+//! pcrs = sha256:0,2,4,7
+//! generation = 12
+//! public = <hex>
+//! private = <hex>
+//! kdf = <hex>
+//! password = <hex>
+//! recovery = <hex>
+//! ```
+//!
+//! They are written before the vault is encrypted again, with the
+//! generation the vault is saved with. A vault of an older generation was
+//! never bound to them, e.g. when mopm stopped between the two writes, and
+//! is still opened with the password alone.
+
+use std::{
+    fs::{self, DirBuilder},
+    io::{self, Write},
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{
+    core::{
+        encryptor::{AESEncryptor, Encryprtor},
+        kdf::{Kdf, KdfParams},
+        rng,
+    },
+    diagnostics::bug::OrBug,
+};
+
+pub const SECRET_LENGTH: usize = 32;
+/// The firmware, the option ROMs, the bootloader and the secure boot state.
+pub const DEFAULT_PCRS: &str = "sha256:0,2,4,7";
+
+const BANKS: [&str; 4] = ["sha1", "sha256", "sha384", "sha512"];
+const MAX_PCR: u8 = 23;
+/// The sealed object can be loaded by its policy alone, not with an
+/// authorization value.
+const ATTRIBUTES: &str = "fixedtpm|fixedparent";
+/// Hex digits per group of the recovery code.
+const GROUP_LENGTH: usize = 8;
+/// Bound to what each slot wraps, so that one cannot stand in for another.
+const PASSWORD_SLOT: &[u8] = b"mopm-tpm-password";
+const RECOVERY_SLOT: &[u8] = b"mopm-tpm-recovery";
+/// Attempts at a private directory whose name is not taken.
+const WORK_DIR_ATTEMPTS: usize = 8;
+
+#[derive(Error, Debug)]
+pub enum TpmError {
+    #[error("cannot run tpm2-tools: `{0}`")]
+    IoError(#[from] io::Error),
+    #[error("`{0}` failed: `{1}`")]
+    CommandFailed(String, String),
+    #[error("invalid PCR selection `{0}`, expected e.g. `{DEFAULT_PCRS}`")]
+    InvalidPcrs(String),
+    #[error("the sealed key is malformed")]
+    Malformed,
+    #[error("the recovery code is not {SECRET_LENGTH} hex-encoded bytes")]
+    InvalidRecoveryCode,
+    #[error("the recovery code does not open the key")]
+    WrongRecoveryCode,
+    #[error("the password does not open its key slot")]
+    WrongPassword,
+    #[error("cannot wrap the key: `{0}`")]
+    WrapError(String),
+}
+
+/// A secret sealed to the PCRs of `pcrs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sealed {
+    pub pcrs: String,
+    public: Vec<u8>,
+    private: Vec<u8>,
+}
+
+impl Sealed {
+    /// Seals `secret` to the current values of the PCRs of `pcrs`.
+    pub fn seal(secret: &[u8], pcrs: &str) -> Result<Self, TpmError> {
+        check_pcrs(pcrs)?;
+        let dir = WorkDir::new()?;
+        let (primary, policy) = (dir.path("primary.ctx"), dir.path("pcr.policy"));
+        let (public, private) = (dir.path("seal.pub"), dir.path("seal.priv"));
+        create_primary(&primary)?;
+        run(
+            Command::new("tpm2_createpolicy")
+                .args(["--policy-pcr", "-l", pcrs, "-L"])
+                .arg(&policy),
+            None,
+        )?;
+        run(
+            Command::new("tpm2_create")
+                .arg("-C")
+                .arg(&primary)
+                .arg("-L")
+                .arg(&policy)
+                .args(["-a", ATTRIBUTES, "-i", "-", "-u"])
+                .arg(&public)
+                .arg("-r")
+                .arg(&private),
+            Some(secret),
+        )?;
+        Ok(Self {
+            pcrs: pcrs.to_string(),
+            public: fs::read(public)?,
+            private: fs::read(private)?,
+        })
+    }
+
+    /// The secret, as long as the PCRs hold the values it was sealed to.
+    pub fn unseal(&self) -> Result<Zeroizing<Vec<u8>>, TpmError> {
+        let dir = WorkDir::new()?;
+        let (primary, object) = (dir.path("primary.ctx"), dir.path("seal.ctx"));
+        let (public, private) = (dir.path("seal.pub"), dir.path("seal.priv"));
+        fs::write(&public, &self.public)?;
+        fs::write(&private, &self.private)?;
+        create_primary(&primary)?;
+        run(
+            Command::new("tpm2_load")
+                .arg("-C")
+                .arg(&primary)
+                .arg("-u")
+                .arg(&public)
+                .arg("-r")
+                .arg(&private)
+                .arg("-c")
+                .arg(&object),
+            None,
+        )?;
+        // Written to stdout rather than a file, it never reaches the disk.
+        let secret = Zeroizing::new(run(
+            Command::new("tpm2_unseal")
+                .arg("-c")
+                .arg(&object)
+                .arg("-p")
+                .arg(format!("pcr:{}", self.pcrs)),
+            None,
+        )?);
+        match secret.len() {
+            SECRET_LENGTH => Ok(secret),
+            _ => Err(TpmError::Malformed),
+        }
+    }
+}
+
+/// The key slots of a vault bound to the TPM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slots {
+    pub sealed: Sealed,
+    /// The first generation of the vault bound to the slots.
+    pub generation: u64,
+    /// The share of the password, wrapped by a key `kdf` derives from it.
+    kdf: Kdf,
+    password: Vec<u8>,
+    /// The secret sealed to the TPM, wrapped by the recovery code.
+    recovery: Vec<u8>,
+}
+
+/// Slots of a new binding, with the vault key and the recovery code.
+pub struct Enrollment {
+    pub slots: Slots,
+    pub key: Zeroizing<Vec<u8>>,
+    pub recovery_code: Zeroizing<String>,
+}
+
+impl Slots {
+    /// Fills the slots with new shares for `password`, the TPM share sealed
+    /// to the PCRs of `pcrs`, for the vault from `generation` on.
+    pub fn enroll(
+        password: &str,
+        pcrs: &str,
+        params: KdfParams,
+        generation: u64,
+    ) -> Result<Enrollment, TpmError> {
+        let share = random_secret();
+        let secret = random_secret();
+        let code = random_secret();
+        let kdf = Kdf::argon2id(params).map_err(|_| TpmError::Malformed)?;
+        let slots = Self {
+            sealed: Sealed::seal(&secret, pcrs)?,
+            generation,
+            kdf,
+            password: wrap(&password_key(&kdf, password)?, PASSWORD_SLOT, &share)?,
+            recovery: wrap(&code, RECOVERY_SLOT, &secret)?,
+        };
+        Ok(Enrollment {
+            slots,
+            key: bind(&share, &secret),
+            recovery_code: recovery_code(&code),
+        })
+    }
+
+    /// The share of `password`.
+    pub fn open(&self, password: &str) -> Result<Zeroizing<Vec<u8>>, TpmError> {
+        unwrap(
+            &password_key(&self.kdf, password)?,
+            PASSWORD_SLOT,
+            &self.password,
+        )
+        .ok_or(TpmError::WrongPassword)
+    }
+
+    /// The secret sealed to the TPM, from the recovery code `code`.
+    pub fn recover(&self, code: &str) -> Result<Zeroizing<Vec<u8>>, TpmError> {
+        unwrap(&parse_recovery_code(code)?, RECOVERY_SLOT, &self.recovery)
+            .ok_or(TpmError::WrongRecoveryCode)
+    }
+
+    /// Wraps the share of the password, from `open`, for `password` instead.
+    pub fn set_password(
+        &mut self,
+        share: &[u8],
+        password: &str,
+        params: KdfParams,
+    ) -> Result<(), TpmError> {
+        let kdf = Kdf::argon2id(params).map_err(|_| TpmError::Malformed)?;
+        self.password = wrap(&password_key(&kdf, password)?, PASSWORD_SLOT, share)?;
+        self.kdf = kdf;
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> Result<Self, TpmError> {
+        let field = |name: &str| {
+            text.lines()
+                .filter_map(|line| line.split_once('='))
+                .find(|(key, _)| key.trim() == name)
+                .map(|(_, value)| value.trim())
+                .ok_or(TpmError::Malformed)
+        };
+        let bytes = |name: &str| hex::decode(field(name)?).map_err(|_| TpmError::Malformed);
+        let pcrs = field("pcrs")?;
+        check_pcrs(pcrs)?;
+        let kdf = bytes("kdf")?
+            .try_into()
+            .ok()
+            .and_then(|v| Kdf::try_from_bytes(&v).ok())
+            .filter(|v| v.params().is_some())
+            .ok_or(TpmError::Malformed)?;
+        Ok(Self {
+            sealed: Sealed {
+                pcrs: pcrs.to_string(),
+                public: bytes("public")?,
+                private: bytes("private")?,
+            },
+            generation: field("generation")?
+                .parse()
+                .map_err(|_| TpmError::Malformed)?,
+            kdf,
+            password: bytes("password")?,
+            recovery: bytes("recovery")?,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "pcrs = {}\ngeneration = {}\npublic = {}\nprivate = {}\nkdf = {}\npassword = {}\nrecovery = {}\n",
+            self.sealed.pcrs,
+            self.generation,
+            hex::encode(&self.sealed.public),
+            hex::encode(&self.sealed.private),
+            hex::encode(self.kdf.to_bytes()),
+            hex::encode(&self.password),
+            hex::encode(&self.recovery)
+        )
+    }
+}
+
+/// What a vault bound to the TPM is encrypted with: the share of the
+/// password followed by the secret sealed to the TPM.
+pub fn bind(share: &[u8], secret: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut key = Zeroizing::new(Vec::with_capacity(share.len() + secret.len()));
+    key.extend_from_slice(share);
+    key.extend_from_slice(secret);
+    key
+}
+
+/// The secret as groups of hex digits, to be written down.
+fn recovery_code(secret: &[u8]) -> Zeroizing<String> {
+    let digits = Zeroizing::new(hex::encode(secret));
+    let groups: Vec<&str> = digits
+        .as_bytes()
+        .chunks(GROUP_LENGTH)
+        .map(|v| std::str::from_utf8(v).or_bug("hex digits are ascii"))
+        .collect();
+    Zeroizing::new(groups.join("-"))
+}
+
+/// Reads a recovery code back, dashes and spaces ignored.
+fn parse_recovery_code(code: &str) -> Result<Zeroizing<Vec<u8>>, TpmError> {
+    let digits: Zeroizing<String> = Zeroizing::new(
+        code.chars()
+            .filter(|v| *v != '-' && !v.is_whitespace())
+            .collect(),
+    );
+    hex::decode(digits.as_str())
+        .ok()
+        .map(Zeroizing::new)
+        .filter(|v| v.len() == SECRET_LENGTH)
+        .ok_or(TpmError::InvalidRecoveryCode)
+}
+
+fn random_secret() -> Zeroizing<Vec<u8>> {
+    let mut secret = Zeroizing::new(vec![0; SECRET_LENGTH]);
+    rng::fill(&mut secret);
+    secret
+}
+
+fn password_key(kdf: &Kdf, password: &str) -> Result<Zeroizing<Vec<u8>>, TpmError> {
+    kdf.derive(password.as_bytes())
+        .map(Zeroizing::new)
+        .map_err(|_| TpmError::Malformed)
+}
+
+fn wrap(key: &[u8], slot: &[u8], secret: &[u8]) -> Result<Vec<u8>, TpmError> {
+    AESEncryptor::new(key)
+        .encrypt(secret, slot)
+        .map(Vec::from)
+        .map_err(|err| TpmError::WrapError(err.to_string()))
+}
+
+fn unwrap(key: &[u8], slot: &[u8], wrapped: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+    AESEncryptor::new(key)
+        .decrypt(wrapped, slot)
+        .ok()
+        .map(|v| Zeroizing::new(v.into_vec()))
+        .filter(|v| v.len() == SECRET_LENGTH)
+}
+
+/// Checks that `pcrs` is a single bank with PCR indices, as in
+/// `sha256:0,2,4,7`.
+pub fn check_pcrs(pcrs: &str) -> Result<(), TpmError> {
+    let invalid = || TpmError::InvalidPcrs(pcrs.to_string());
+    let (bank, indices) = pcrs.split_once(':').ok_or_else(invalid)?;
+    if !BANKS.contains(&bank) {
+        return Err(invalid());
+    }
+    for index in indices.split(',') {
+        match index.parse::<u8>() {
+            Ok(v) if v <= MAX_PCR => {}
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(())
+}
+
+/// The primary key of the owner hierarchy is derived from a seed of the
+/// TPM, the same template gives the same key every time.
+fn create_primary(path: &Path) -> Result<(), TpmError> {
+    run(
+        Command::new("tpm2_createprimary")
+            .args(["-C", "o", "-g", "sha256", "-G", "ecc", "-c"])
+            .arg(path),
+        None,
+    )?;
+    Ok(())
+}
+
+/// Runs `command` with `input` on stdin, returning its stdout.
+fn run(command: &mut Command, input: Option<&[u8]>) -> Result<Vec<u8>, TpmError> {
+    let mut child = command
+        .stdin(match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    match output.status.success() {
+        true => Ok(output.stdout),
+        false => Err(TpmError::CommandFailed(
+            command.get_program().to_string_lossy().into_owned(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
+
+/// A private directory for the files tpm2-tools pass between runs, removed
+/// when dropped.
+struct WorkDir(PathBuf);
+
+impl WorkDir {
+    fn new() -> io::Result<Self> {
+        let mut attempts = 0;
+        loop {
+            let mut suffix = [0; 8];
+            rng::fill(&mut suffix);
+            let path = std::env::temp_dir().join(format!("mopm-tpm-{}", hex::encode(suffix)));
+            match DirBuilder::new().mode(0o700).create(&path) {
+                Err(err)
+                    if err.kind() == io::ErrorKind::AlreadyExists
+                        && attempts + 1 < WORK_DIR_ATTEMPTS =>
+                {
+                    attempts += 1
+                }
+                result => return result.map(|_| Self(path)),
+            }
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: KdfParams = KdfParams {
+        memory_kib: 8 * 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    /// Slots as `enroll` fills them, with a sealed object tpm2-tools never
+    /// saw.
+    fn slots(password: &str) -> (Slots, [Zeroizing<Vec<u8>>; 3]) {
+        let (share, secret, code) = (random_secret(), random_secret(), random_secret());
+        let kdf = Kdf::argon2id(PARAMS).unwrap();
+        let slots = Slots {
+            sealed: Sealed {
+                pcrs: DEFAULT_PCRS.to_string(),
+                public: vec![1, 2, 3],
+                private: vec![4, 5],
+            },
+            generation: 12,
+            kdf,
+            password: wrap(
+                &password_key(&kdf, password).unwrap(),
+                PASSWORD_SLOT,
+                &share,
+            )
+            .unwrap(),
+            recovery: wrap(&code, RECOVERY_SLOT, &secret).unwrap(),
+        };
+        (slots, [share, secret, code])
+    }
+
+    #[test]
+    fn test_slots_text() {
+        let (slots, _) = slots("pw");
+        assert_eq!(Slots::parse(&slots.to_text()).unwrap(), slots);
+        assert!(matches!(
+            Slots::parse("pcrs = sha256:0\npublic = 01\nprivate = 02\n"),
+            Err(TpmError::Malformed)
+        ));
+        let text = slots.to_text().replace(DEFAULT_PCRS, "md5:0");
+        assert!(matches!(Slots::parse(&text), Err(TpmError::InvalidPcrs(_))));
+    }
+
+    #[test]
+    fn test_slots() {
+        let (mut slots, [share, secret, code]) = slots("pw");
+        assert_eq!(slots.open("pw").unwrap(), share);
+        assert!(matches!(slots.open("other"), Err(TpmError::WrongPassword)));
+
+        slots.set_password(&share, "new", PARAMS).unwrap();
+        assert!(slots.open("pw").is_err());
+        assert_eq!(slots.open("new").unwrap(), share);
+
+        assert_eq!(slots.recover(&recovery_code(&code)).unwrap(), secret);
+        assert!(matches!(
+            slots.recover(&recovery_code(&random_secret())),
+            Err(TpmError::WrongRecoveryCode)
+        ));
+        // The recovery slot is the only one that unwraps the TPM share.
+        assert!(unwrap(&share, RECOVERY_SLOT, &slots.recovery).is_none());
+        assert_eq!(*bind(&share, &secret), [&share[..], &secret[..]].concat());
+    }
+
+    #[test]
+    fn test_check_pcrs() {
+        assert!(check_pcrs(DEFAULT_PCRS).is_ok());
+        assert!(check_pcrs("sha1:7").is_ok());
+        assert!(check_pcrs("sha256:24").is_err());
+        assert!(check_pcrs("sha256:").is_err());
+        assert!(check_pcrs("0,2,4").is_err());
+    }
+
+    #[test]
+    fn test_recovery_code() {
+        let secret: Vec<u8> = (0..SECRET_LENGTH as u8).collect();
+        let code = recovery_code(&secret);
+        assert_eq!(code.split('-').count(), 8);
+        assert_eq!(&code[..17], "00010203-04050607");
+        assert_eq!(*parse_recovery_code(&code).unwrap(), secret);
+        assert_eq!(
+            *parse_recovery_code(&code.replace('-', " ")).unwrap(),
+            secret
+        );
+        assert!(parse_recovery_code(&code[..20]).is_err());
+    }
+
+    #[test]
+    fn test_work_dir() {
+        let (a, b) = (WorkDir::new().unwrap(), WorkDir::new().unwrap());
+        assert_ne!(a.0, b.0);
+        let path = a.0.clone();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
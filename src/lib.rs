@@ -0,0 +1,5 @@
+//! The vault format and the sandbox of the commands mopm runs, as a
+//! library so that the fuzz targets build the same code as the binary.
+
+pub mod core;
+pub mod exec;
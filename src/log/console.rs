@@ -0,0 +1,141 @@
+use std::io::{self, IsTerminal, Stderr, Stdout, Write};
+
+use term::{color::Color, Attr, Terminal, TerminfoTerminal};
+
+const DUMB_TERMINALS: [&str; 2] = ["", "dumb"];
+
+/// Standard output that only emits control sequences when it is a terminal
+/// with a usable terminfo entry. Pipes, CI logs and dumb terminals get the
+/// plain text, and a missing terminfo database is no longer fatal.
+pub struct Console<W = Stdout> {
+    out: W,
+    terminfo: Option<TerminfoTerminal<W>>,
+}
+
+impl Console {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout)
+    }
+}
+
+impl Console<Stderr> {
+    /// Standard error, for when standard output carries data.
+    pub fn stderr() -> Self {
+        Self::new(io::stderr)
+    }
+}
+
+impl<W> Console<W>
+where
+    W: Write + IsTerminal + Send,
+{
+    fn new(open: impl Fn() -> W) -> Self {
+        let out = open();
+        let capable = out.is_terminal()
+            && std::env::var("TERM").is_ok_and(|v| !DUMB_TERMINALS.contains(&v.as_str()));
+        Self {
+            terminfo: capable.then(|| TerminfoTerminal::new(open())).flatten(),
+            out,
+        }
+    }
+
+    fn styled(
+        &mut self,
+        f: impl FnOnce(&mut TerminfoTerminal<W>) -> term::Result<()>,
+    ) -> term::Result<()> {
+        match &mut self.terminfo {
+            Some(terminfo) => f(terminfo),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W> Write for Console<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W> Terminal for Console<W>
+where
+    W: Write + IsTerminal + Send,
+{
+    type Output = W;
+
+    fn fg(&mut self, color: Color) -> term::Result<()> {
+        self.styled(|t| t.fg(color))
+    }
+
+    fn bg(&mut self, color: Color) -> term::Result<()> {
+        self.styled(|t| t.bg(color))
+    }
+
+    fn attr(&mut self, attr: Attr) -> term::Result<()> {
+        self.styled(|t| t.attr(attr))
+    }
+
+    fn supports_attr(&self, attr: Attr) -> bool {
+        self.terminfo
+            .as_ref()
+            .is_some_and(|t| t.supports_attr(attr))
+    }
+
+    fn reset(&mut self) -> term::Result<()> {
+        self.styled(|t| t.reset())
+    }
+
+    fn supports_reset(&self) -> bool {
+        self.terminfo.as_ref().is_some_and(|t| t.supports_reset())
+    }
+
+    fn supports_color(&self) -> bool {
+        self.terminfo.as_ref().is_some_and(|t| t.supports_color())
+    }
+
+    fn cursor_up(&mut self) -> term::Result<()> {
+        self.styled(|t| t.cursor_up())
+    }
+
+    fn delete_line(&mut self) -> term::Result<()> {
+        self.styled(|t| t.delete_line())
+    }
+
+    fn carriage_return(&mut self) -> term::Result<()> {
+        self.styled(|t| t.carriage_return())
+    }
+
+    fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    fn get_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain() {
+        let mut console = Console {
+            out: io::stdout(),
+            terminfo: None,
+        };
+        assert!(!console.supports_color());
+        assert!(console.fg(term::color::RED).is_ok());
+        assert!(console.reset().is_ok());
+    }
+}
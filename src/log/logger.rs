@@ -1,4 +1,8 @@
-use std::{error::Error, io::Stdout};
+use std::error::Error;
+
+use term::Terminal as _;
+
+use super::console::Console;
 
 pub struct Logger<T>
 where
@@ -6,6 +10,7 @@ where
 {
     terminal: T,
     debug: bool,
+    color: bool,
 }
 
 #[cfg(debug_assertions)]
@@ -26,28 +31,42 @@ where
         Self {
             terminal,
             debug: debug(),
+            color: true,
         }
     }
 
+    #[allow(dead_code)]
     pub fn debug(mut self, debug: bool) -> Self {
         self.debug = debug;
         self
     }
 
+    /// Colors are only ever written to terminals that support them.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color && self.terminal.supports_color();
+        self
+    }
+
     pub fn info(&mut self, buf: &[u8]) {
         let _ = self.terminal.write(buf);
     }
 
+    pub fn colored(&mut self, color: term::color::Color, buf: &[u8]) {
+        self.fg(color);
+        let _ = self.terminal.write(buf);
+        self.reset();
+    }
+
     pub fn warn(&mut self, buf: &[u8]) {
-        let _ = self.terminal.fg(term::color::RED);
+        self.fg(term::color::RED);
         let _ = self.terminal.write(buf);
-        let _ = self.terminal.reset();
+        self.reset();
     }
 
     pub fn fatal(&mut self, buf: &[u8]) -> ! {
-        let _ = self.terminal.fg(term::color::BRIGHT_RED);
+        self.fg(term::color::BRIGHT_RED);
         let _ = self.terminal.write(buf);
-        let _ = self.terminal.reset();
+        self.reset();
         std::process::exit(1);
     }
 
@@ -55,22 +74,36 @@ where
         if !self.debug {
             return;
         }
-        let _ = self.terminal.fg(term::color::RED);
+        self.fg(term::color::RED);
         let _ = self.terminal.write(b"[DEBUG] error: ");
         let _ = self.terminal.write(error.to_string().as_ref());
         let _ = self.terminal.write(b"\n");
-        let _ = self.terminal.reset();
+        self.reset();
     }
 
     pub fn flush(&mut self) {
         let _ = self.terminal.flush();
     }
+
+    fn fg(&mut self, color: term::color::Color) {
+        if self.color {
+            let _ = self.terminal.fg(color);
+        }
+    }
+
+    fn reset(&mut self) {
+        if self.color {
+            let _ = self.terminal.reset();
+        }
+    }
 }
 
-impl Default for Logger<term::TerminfoTerminal<Stdout>> {
+impl Default for Logger<Console> {
     fn default() -> Self {
+        let terminal = Console::stdout();
         Self {
-            terminal: term::TerminfoTerminal::new(std::io::stdout()).unwrap(),
+            color: terminal.supports_color(),
+            terminal,
             debug: debug(),
         }
     }
@@ -1 +1,3 @@
+pub mod console;
 pub mod logger;
+pub mod table;
@@ -0,0 +1,139 @@
+//! Rows of named cells, rendered either as aligned columns cut to the width
+//! of the terminal or as a JSON array of objects, so that both views of a
+//! listing always show the same data.
+
+/// Marks a cell that was cut to fit.
+const ELLIPSIS: char = '…';
+/// Columns are never cut narrower than this.
+const MIN_WIDTH: usize = 4;
+const GAP: &str = "  ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: Vec<String>) -> Self {
+        Self {
+            headers,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row, with one cell per header.
+    pub fn push(&mut self, row: Vec<String>) {
+        debug_assert_eq!(row.len(), self.headers.len());
+        self.rows.push(row);
+    }
+
+    /// The header line followed by one line per row, without line breaks.
+    /// With a `width`, the widest columns are cut until the lines fit.
+    pub fn render(&self, width: Option<usize>) -> Vec<String> {
+        let widths = self.widths(width);
+        std::iter::once(&self.headers)
+            .chain(&self.rows)
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:<width$}", truncate(cell, *width)))
+                    .collect();
+                cells.join(GAP).trim_end().to_string()
+            })
+            .collect()
+    }
+
+    /// The rows as objects keyed by header, empty cells as `null`.
+    pub fn to_json(&self) -> serde_json::Value {
+        self.rows
+            .iter()
+            .map(|row| {
+                self.headers
+                    .iter()
+                    .zip(row)
+                    .map(|(header, cell)| {
+                        let value = match cell.is_empty() {
+                            true => serde_json::Value::Null,
+                            false => serde_json::Value::from(cell.as_str()),
+                        };
+                        (header.clone(), value)
+                    })
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .collect()
+    }
+
+    fn widths(&self, width: Option<usize>) -> Vec<usize> {
+        let mut widths: Vec<usize> = (0..self.headers.len())
+            .map(|i| {
+                std::iter::once(&self.headers)
+                    .chain(&self.rows)
+                    .map(|row| row[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let Some(width) = width else {
+            return widths;
+        };
+        let gaps = GAP.len() * widths.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + gaps > width {
+            let Some(widest) = widths
+                .iter_mut()
+                .filter(|v| **v > MIN_WIDTH)
+                .max_by_key(|v| **v)
+            else {
+                break;
+            };
+            *widest -= 1;
+        }
+        widths
+    }
+}
+
+fn truncate(cell: &str, width: usize) -> String {
+    if cell.chars().count() <= width {
+        return cell.to_string();
+    }
+    let mut cut: String = cell.chars().take(width.saturating_sub(1)).collect();
+    cut.push(ELLIPSIS);
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let mut table = Table::new(vec!["name".to_string(), "tags".to_string()]);
+        table.push(vec!["work/database".to_string(), "infra, prod".to_string()]);
+        table.push(vec!["mail".to_string(), String::new()]);
+        table
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            table().render(None),
+            vec!["name           tags", "work/database  infra, prod", "mail"]
+        );
+        assert_eq!(
+            table().render(Some(20)),
+            vec!["name       tags", "work/dat…  infra, p…", "mail"]
+        );
+        assert_eq!(table().render(Some(1))[1], "wor…  inf…");
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            table().to_json(),
+            serde_json::json!([
+                {"name": "work/database", "tags": "infra, prod"},
+                {"name": "mail", "tags": null},
+            ])
+        );
+    }
+}
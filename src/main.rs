@@ -1,15 +1,30 @@
+use crate::core::trace;
 use app::application::App;
-use cli::config::{CliError, Config};
-use log::logger::Logger;
+use cli::{
+    config::{CliError, Command, Config},
+    interact,
+};
+use diagnostics::crash;
+use log::{console::Console, logger::Logger};
+use mopm::{core, exec};
 
 mod app;
 mod cli;
-mod core;
+mod diagnostics;
+#[cfg(feature = "fuse")]
+mod fuse;
+mod interop;
 mod log;
 mod storage;
+#[cfg(feature = "self-update")]
+mod update;
+#[cfg(feature = "web")]
+mod web;
 
 fn main() {
-    let mut logger = Logger::default();
+    crash::install();
+    let mut logger = Logger::default().color(interact::use_color(false));
+    let span = trace::span("parse arguments");
     let config = match Config::from_args() {
         Ok(v) => v,
         Err(err) => {
@@ -27,6 +42,32 @@ fn main() {
             }
         }
     };
-    let mut app = App::new(config, logger);
+    if config.crash_report {
+        crash::enable();
+    }
+    if config.trace.is_some() {
+        trace::enable();
+    }
+    drop(span);
+    let color = interact::use_color(config.no_color);
+    let trace_format = config.trace;
+    // The vault goes to stdout with `--out -`, and terraform reads its
+    // result from stdout and its errors from stderr, so the messages do not.
+    let to_stderr = config.out.as_deref() == Some("-")
+        || matches!(config.command, Some(Command::TerraformExternal));
+    match to_stderr {
+        true => run(App::new(
+            config,
+            Logger::new(Console::stderr()).color(color),
+        )),
+        false => run(App::new(config, logger.color(color))),
+    }
+    if let Some(format) = trace_format {
+        eprint!("{}", trace::report(format));
+    }
+}
+
+fn run<T: term::Terminal>(mut app: App<T>) {
+    let _span = trace::span("command");
     app.run();
 }
@@ -0,0 +1,243 @@
+//! Copies of the vault file taken before every write, configured in the
+//! config file:
+//!
+//! ```text
+//! backup.before_write = true
+//! backup.keep = 5
+//! backup.max_age_days = 30
+//! ```
+//!
+//! Copies sit next to the vault file as `.data.bak.N`, `.data.bak.1` being
+//! the most recent.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use thiserror::Error;
+
+const PREFIX: &str = "backup.";
+const BEFORE_WRITE_SETTING: &str = "backup.before_write";
+const KEEP_SETTING: &str = "backup.keep";
+const MAX_AGE_SETTING: &str = "backup.max_age_days";
+const DEFAULT_KEEP: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("unknown config setting `{0}`")]
+    UnknownSetting(String),
+    #[error("invalid value for `{0}`: `{1}`")]
+    InvalidValue(&'static str, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupPolicy {
+    before_write: bool,
+    keep: usize,
+    max_age: Option<Duration>,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            before_write: false,
+            keep: DEFAULT_KEEP,
+            max_age: None,
+        }
+    }
+}
+
+impl BackupPolicy {
+    /// The policy of the `backup.` settings of the config file.
+    pub fn from_settings(settings: &[(&str, &str)]) -> Result<Self, BackupError> {
+        let mut policy = Self::default();
+        for &(name, value) in settings {
+            let invalid = |name| BackupError::InvalidValue(name, value.to_string());
+            match name {
+                BEFORE_WRITE_SETTING => {
+                    policy.before_write =
+                        value.parse().map_err(|_| invalid(BEFORE_WRITE_SETTING))?
+                }
+                KEEP_SETTING => {
+                    policy.keep = value
+                        .parse()
+                        .ok()
+                        .filter(|v| *v > 0)
+                        .ok_or_else(|| invalid(KEEP_SETTING))?
+                }
+                MAX_AGE_SETTING => {
+                    policy.max_age = value
+                        .parse::<u64>()
+                        .ok()
+                        .and_then(|v| v.checked_mul(86_400))
+                        .map(|v| Some(Duration::from_secs(v)))
+                        .ok_or_else(|| invalid(MAX_AGE_SETTING))?
+                }
+                _ => return Err(BackupError::UnknownSetting(name.to_string())),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Moves the copies of `data` one place down the rotation and copies
+    /// `data` in as the first, then drops the copies past `keep` or older
+    /// than `max_age`.
+    pub fn rotate(&self, data: &Path) -> io::Result<()> {
+        if !self.before_write || !data.exists() {
+            return Ok(());
+        }
+        let count = (1..).take_while(|n| path(data, *n).exists()).count();
+        for n in (1..=count).rev() {
+            match n < self.keep {
+                true => fs::rename(path(data, n), path(data, n + 1))?,
+                false => fs::remove_file(path(data, n))?,
+            }
+        }
+        fs::copy(data, path(data, 1))?;
+
+        let Some(max_age) = self.max_age else {
+            return Ok(());
+        };
+        let now = SystemTime::now();
+        let expired = (2..).take_while(|n| path(data, *n).exists()).find(|n| {
+            fs::metadata(path(data, *n))
+                .and_then(|v| v.modified())
+                .is_ok_and(|v| now.duration_since(v).unwrap_or_default() > max_age)
+        });
+        if let Some(first) = expired {
+            for n in (first..).take_while(|n| path(data, *n).exists()) {
+                fs::remove_file(path(data, n))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deletes every copy of `data`, the oldest first so that a deletion cut
+/// short leaves the rotation whole. Returns how many there were.
+#[cfg(feature = "tpm")]
+pub fn remove_all(data: &Path) -> io::Result<usize> {
+    let count = (1..).take_while(|n| path(data, *n).exists()).count();
+    for n in (1..=count).rev() {
+        fs::remove_file(path(data, n))?;
+    }
+    Ok(count)
+}
+
+pub fn is_backup_setting(name: &str) -> bool {
+    name.starts_with(PREFIX)
+}
+
+/// The `n`th copy of `data`, counting from 1.
+pub fn path(data: &Path, n: usize) -> PathBuf {
+    let mut name = data.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".bak.{}", n));
+    data.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mopm-backup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(".data")
+    }
+
+    #[test]
+    fn test_parse() {
+        let policy =
+            BackupPolicy::from_settings(&[("backup.before_write", "true"), ("backup.keep", "2")])
+                .unwrap();
+        assert_eq!(
+            policy,
+            BackupPolicy {
+                before_write: true,
+                keep: 2,
+                max_age: None,
+            }
+        );
+        assert_eq!(
+            BackupPolicy::from_settings(&[]).unwrap(),
+            BackupPolicy::default()
+        );
+        assert!(matches!(
+            BackupPolicy::from_settings(&[("backup.keep", "0")]),
+            Err(BackupError::InvalidValue(KEEP_SETTING, _))
+        ));
+        assert!(matches!(
+            BackupPolicy::from_settings(&[("backup.max_age_days", u64::MAX.to_string().as_str())]),
+            Err(BackupError::InvalidValue(MAX_AGE_SETTING, _))
+        ));
+        assert!(matches!(
+            BackupPolicy::from_settings(&[("backup.before", "yes")]),
+            Err(BackupError::UnknownSetting(_))
+        ));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let data = data_file("rotate");
+        let policy =
+            BackupPolicy::from_settings(&[("backup.before_write", "true"), ("backup.keep", "2")])
+                .unwrap();
+        for generation in 1..=4 {
+            fs::write(&data, generation.to_string()).unwrap();
+            policy.rotate(&data).unwrap();
+        }
+        assert_eq!(fs::read_to_string(path(&data, 1)).unwrap(), "4");
+        assert_eq!(fs::read_to_string(path(&data, 2)).unwrap(), "3");
+        assert!(!path(&data, 3).exists());
+
+        BackupPolicy::default().rotate(&data).unwrap();
+        assert_eq!(fs::read_to_string(path(&data, 2)).unwrap(), "3");
+        fs::remove_dir_all(data.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(feature = "tpm")]
+    #[test]
+    fn test_remove_all() {
+        let data = data_file("remove");
+        fs::write(&data, "vault").unwrap();
+        for n in 1..=3 {
+            fs::write(path(&data, n), "old").unwrap();
+        }
+        assert_eq!(remove_all(&data).unwrap(), 3);
+        assert!(!path(&data, 1).exists());
+        assert!(data.exists());
+        assert_eq!(remove_all(&data).unwrap(), 0);
+        fs::remove_dir_all(data.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_drops_expired() {
+        let data = data_file("expired");
+        fs::write(&data, "new").unwrap();
+        for n in 1..=3 {
+            fs::write(path(&data, n), "old").unwrap();
+        }
+        let old = SystemTime::now() - Duration::from_secs(3 * 24 * 60 * 60);
+        fs::File::options()
+            .write(true)
+            .open(path(&data, 2))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let policy = BackupPolicy::from_settings(&[
+            ("backup.before_write", "true"),
+            ("backup.max_age_days", "1"),
+        ])
+        .unwrap();
+        policy.rotate(&data).unwrap();
+        assert_eq!(fs::read_to_string(path(&data, 1)).unwrap(), "new");
+        assert!(path(&data, 2).exists());
+        assert!(!path(&data, 3).exists());
+        assert!(!path(&data, 4).exists());
+        fs::remove_dir_all(data.parent().unwrap()).unwrap();
+    }
+}
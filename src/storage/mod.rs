@@ -1 +1,8 @@
+pub mod backup;
+pub mod shield;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 pub mod store;
+pub mod system;
+pub mod trash;
+pub mod vaults;
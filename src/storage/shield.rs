@@ -0,0 +1,139 @@
+//! What the shield leaves behind, for `shield status` and `shield reset`:
+//! the bind mount over the root, the decoys it shows, and a pid file of
+//! the process watching the honeypot, written by `shield up`. The last
+//! trigger is the last change of the incident log.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// The process watching the honeypot, as recorded by `shield up`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watcher {
+    pub pid: u32,
+    pub since: SystemTime,
+}
+
+impl Watcher {
+    /// Records the current process as the watcher.
+    pub fn register(path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, format!("{}\n", std::process::id()))
+    }
+
+    /// The recorded watcher, `None` without a pid file.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let pid = text
+            .trim()
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed pid file"))?;
+        let since = fs::metadata(path)?.modified()?;
+        Ok(Some(Self { pid, since }))
+    }
+
+    pub fn unregister(path: &Path) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the process still runs and is a mopm, not an unrelated one
+    /// that was given the pid after the watcher was killed.
+    pub fn is_alive(&self) -> bool {
+        let comm = |pid: &str| fs::read_to_string(format!("/proc/{}/comm", pid));
+        match (comm(&self.pid.to_string()), comm("self")) {
+            (Ok(theirs), Ok(ours)) => theirs == ours,
+            _ => false,
+        }
+    }
+}
+
+/// Whether something is mounted on `path`.
+pub fn is_mount_point(path: &Path) -> io::Result<bool> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    Ok(mount_points(&fs::read_to_string(MOUNTINFO)?).any(|v| v == path))
+}
+
+/// The decoy files shown in place of the vault, in name order.
+pub fn decoys(dummy: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut decoys = match fs::read_dir(dummy) {
+        Ok(entries) => entries
+            .map(|entry| Ok(entry?.path()))
+            .collect::<io::Result<Vec<_>>>()?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    decoys.sort();
+    Ok(decoys)
+}
+
+/// The mount points of a mountinfo file, the fifth field of each line,
+/// with the octal escapes of spaces and the like undone.
+fn mount_points(mountinfo: &str) -> impl Iterator<Item = PathBuf> + '_ {
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(|v| PathBuf::from(unescape(v)))
+}
+
+fn unescape(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&first, tail)) = rest.split_first() {
+        let octal = tail
+            .get(..3)
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(|v| u8::from_str_radix(v, 8).ok());
+        match (first, octal) {
+            (b'\\', Some(v)) => {
+                bytes.push(v);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(first);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_points() {
+        let mountinfo = "22 1 8:1 / / rw,relatime - ext4 /dev/sda1 rw\n\
+            48 22 0:40 / /home/me/my\\040vault rw - tmpfs tmpfs rw\n";
+        let points: Vec<_> = mount_points(mountinfo).collect();
+        assert_eq!(
+            points,
+            [PathBuf::from("/"), PathBuf::from("/home/me/my vault")]
+        );
+    }
+
+    #[test]
+    fn test_watcher() {
+        let path = std::env::temp_dir().join(format!("mopm-shield-{}", std::process::id()));
+        assert_eq!(Watcher::load(&path).unwrap(), None);
+        Watcher::register(&path).unwrap();
+        let watcher = Watcher::load(&path).unwrap().unwrap();
+        assert_eq!(watcher.pid, std::process::id());
+        assert!(watcher.is_alive());
+        Watcher::unregister(&path).unwrap();
+        assert_eq!(Watcher::load(&path).unwrap(), None);
+    }
+}
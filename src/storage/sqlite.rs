@@ -0,0 +1,155 @@
+//! Keeps the rows of a vault in an SQLite database in WAL mode. Only the
+//! rows whose ciphertext changed are written, in a transaction that holds
+//! the write lock from the generation check to the commit.
+
+use std::{collections::HashMap, path::Path};
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use super::store::StorageError;
+use crate::core::{
+    encoder::Header,
+    rows::{Row, RowId, Rows},
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS header (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        generation INTEGER NOT NULL,
+        bytes BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS rows (
+        id BLOB PRIMARY KEY,
+        kind INTEGER NOT NULL,
+        data BLOB NOT NULL
+    ) WITHOUT ROWID;
+";
+
+fn open(path: &Path) -> Result<Connection, StorageError> {
+    let connection = Connection::open(path)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "synchronous", "FULL")?;
+    Ok(connection)
+}
+
+/// Creates the database at `path` holding `rows`.
+pub fn create(path: &Path, rows: &Rows) -> Result<(), StorageError> {
+    let mut connection = open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    write_rows(&mut connection, rows, 0)
+}
+
+pub fn read(path: &Path) -> Result<Rows, StorageError> {
+    let mut connection = open(path)?;
+    // One transaction, so that a concurrent save is seen whole or not at all.
+    let transaction = connection.transaction()?;
+    let header = transaction
+        .query_row("SELECT bytes FROM header WHERE id = 0", [], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .unwrap_or_default();
+    let rows = transaction
+        .prepare("SELECT id, kind, data FROM rows")?
+        .query_map([], |row| {
+            Ok(Row {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                data: row.get(2)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(Rows { header, rows })
+}
+
+/// Writes `rows` unless another process saved the vault since the one at
+/// `expected` generation was read.
+pub fn write(path: &Path, rows: &Rows, expected: u64) -> Result<(), StorageError> {
+    write_rows(&mut open(path)?, rows, expected)
+}
+
+fn write_rows(connection: &mut Connection, rows: &Rows, expected: u64) -> Result<(), StorageError> {
+    let generation = Header::try_from_bytes(&rows.header)?.generation();
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let found: u64 = transaction
+        .query_row("SELECT generation FROM header WHERE id = 0", [], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .unwrap_or_default();
+    if found != expected {
+        return Err(StorageError::ConflictError { expected, found });
+    }
+
+    let mut stored: HashMap<RowId, Vec<u8>> = transaction
+        .prepare("SELECT id, data FROM rows")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    {
+        let mut upsert = transaction
+            .prepare("INSERT OR REPLACE INTO rows (id, kind, data) VALUES (?1, ?2, ?3)")?;
+        for row in &rows.rows {
+            if stored.remove(&row.id).as_ref() != Some(&row.data) {
+                upsert.execute(params![row.id, row.kind, row.data])?;
+            }
+        }
+        let mut delete = transaction.prepare("DELETE FROM rows WHERE id = ?1")?;
+        for id in stored.keys() {
+            delete.execute(params![id])?;
+        }
+    }
+    transaction.execute(
+        "INSERT OR REPLACE INTO header (id, generation, bytes) VALUES (0, ?1, ?2)",
+        params![generation, rows.header],
+    )?;
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap as Map;
+
+    use crate::core::{
+        encoder::Encoder, encryptor::AESEncryptor, keycache::KeyCache, manager::PasswordManager,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = std::env::temp_dir().join(format!("mopm-sqlite-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("vault.sqlite");
+
+        let mut pm = PasswordManager::from_raw_parts(Map::new(), AESEncryptor::new("foobar"));
+        pm.store_password("foo".to_string(), "bar").unwrap();
+        create(&path, &Encoder::encode_rows(&mut pm).unwrap()).unwrap();
+
+        let mut pm =
+            Encoder::decode_rows(b"foobar", &read(&path).unwrap(), &mut KeyCache::default())
+                .unwrap();
+        pm.delete("foo").unwrap();
+        pm.store_password("baz".to_string(), "qux").unwrap();
+        let stale = pm.generation();
+        write(&path, &Encoder::encode_rows(&mut pm).unwrap(), stale).unwrap();
+
+        let mut read_back =
+            Encoder::decode_rows(b"foobar", &read(&path).unwrap(), &mut KeyCache::default())
+                .unwrap();
+        assert_eq!(read_back.get_password("baz"), Ok("qux".to_string()));
+        assert!(read_back.get_password("foo").is_err());
+        assert_eq!(read_back.generation(), 2);
+
+        let rows = Encoder::encode_rows(&mut read_back).unwrap();
+        assert!(matches!(
+            write(&path, &rows, stale),
+            Err(StorageError::ConflictError {
+                expected: 1,
+                found: 2
+            })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
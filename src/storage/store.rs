@@ -1,18 +1,54 @@
 use std::{
-    fs::create_dir,
-    io::{self, Read, Write},
-    path::PathBuf,
+    ffi::OsString,
+    fs::{create_dir, File, Permissions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
+use nix::{
+    fcntl::{Flock, FlockArg},
+    sys::statfs::{statfs, TMPFS_MAGIC},
+};
 use thiserror::Error;
+use zeroize::Zeroizing;
 
+#[cfg(feature = "sqlite")]
+use super::sqlite;
+use super::{
+    backup::{self, BackupPolicy},
+    trash::{self, Trash},
+};
+#[cfg(feature = "hidden-volume")]
+use crate::core::hidden::{self, HiddenError, HiddenKey};
 use crate::core::{
-    encoder::{Encoder, EncoderError},
+    clock,
+    encoder::{Encoder, EncoderError, Header, StoredVault, SLACK_SIZE},
     encryptor::Encryprtor,
     identifiers::Identifiable,
     manager::PasswordManager,
+    trace,
 };
+use crate::diagnostics::bug::OrBug;
+
+const HONEYPOT_FILE: &str = "not-a-honeypot.txt";
+const HINT_FILE: &str = ".hint-plaintext";
+const DEVICE_ID_FILE: &str = ".device-id";
+#[cfg(feature = "tpm")]
+const TPM_FILE: &str = ".tpm-sealed";
+const ROOT_DIR: &str = "mopm";
+/// Files written by mopm are readable by the owner only.
+const PRIVATE_MODE: u32 = 0o600;
+/// The trash is named after the root, next to it.
+const TRASH_SUFFIX: &str = "-trash";
+#[cfg(feature = "sqlite")]
+const SQLITE_FILE: &str = "vault.sqlite";
+#[cfg(feature = "legacy-layout")]
+const LEGACY_DATA_FILE: &str = "data";
+/// Where the root was kept before it moved to the XDG data directory.
+#[cfg(feature = "legacy-layout")]
+const LEGACY_ROOT_DIR: &str = ".mopm";
 
 pub struct Storage {}
 
@@ -28,8 +64,60 @@ pub enum StorageError {
     IoError(#[from] io::Error),
     #[error("encoder error: `{0}`")]
     EncoderError(#[from] EncoderError),
+    #[error("the vault was saved by another process in the meantime (generation {found}, expected {expected})")]
+    ConflictError { expected: u64, found: u64 },
+    #[error("$XDG_RUNTIME_DIR is not set, session entries need a runtime directory")]
+    NoRuntimeDirError,
+    #[error("the runtime directory is not a tmpfs, session entries would reach the disk")]
+    RuntimeDirOnDiskError,
+    #[error("the vault is a read-only mirror, change it where it is published")]
+    MirrorError,
     #[error("path buf error: `{0}`")]
     PathBufError(#[from] core::convert::Infallible),
+    #[cfg(feature = "hidden-volume")]
+    #[error("{0}")]
+    HiddenError(#[from] HiddenError),
+    #[cfg(feature = "sqlite")]
+    #[error("database error: `{0}`")]
+    SqliteError(#[from] rusqlite::Error),
+}
+
+/// A root left in the home directory by early revisions, see
+/// `Storage::migrate_legacy_root`.
+#[cfg(feature = "legacy-layout")]
+#[derive(Debug, PartialEq, Eq)]
+pub enum LegacyRoot {
+    /// There is none.
+    Absent,
+    /// It was moved to the data directory.
+    Moved(PathBuf),
+    /// It is ignored, a root already exists in the data directory.
+    Ignored(PathBuf),
+    /// It is kept in place while the shield is mounted over it.
+    Shielded(PathBuf),
+}
+
+/// A plaintext export, kept in memory until it is committed to its file.
+pub struct PrivateWriter {
+    path: PathBuf,
+    bytes: Zeroizing<Vec<u8>>,
+}
+
+impl PrivateWriter {
+    /// Replaces the file with what was written, see `Storage::replace`.
+    pub fn commit(self) -> Result<(), StorageError> {
+        Storage::replace(&self.path, &self.bytes)
+    }
+}
+
+impl Write for PrivateWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 impl Storage {
@@ -43,7 +131,7 @@ impl Storage {
             return Err(StorageError::RootAlreadyExistsErorr);
         }
 
-        create_dir(&root)?;
+        Self::create_root(&root)?;
 
         let mut password_file = std::fs::OpenOptions::new()
             .write(true)
@@ -53,9 +141,26 @@ impl Storage {
             .map_err(StorageError::from)?;
 
         Encoder::encode(&mut password_file, pm)?;
+        password_file.write_all(&Self::new_slack(pm))?;
         Ok(())
     }
 
+    /// Like `init`, but keeps the vault as rows of an SQLite database.
+    #[cfg(feature = "sqlite")]
+    pub fn init_sqlite<T>(pm: &mut PasswordManager<T>) -> Result<(), StorageError>
+    where
+        T: Encryprtor + Identifiable,
+    {
+        let root = Self::root()?;
+
+        if root.exists() {
+            return Err(StorageError::RootAlreadyExistsErorr);
+        }
+
+        Self::create_root(&root)?;
+        sqlite::create(&Self::sqlite_file()?, &Encoder::encode_rows(pm)?)
+    }
+
     pub fn create_dummy() -> Result<(), StorageError> {
         let dummy = Self::dummy()?;
         let dummy_file = Self::upper_file()?;
@@ -71,66 +176,619 @@ impl Storage {
                 .truncate(true)
                 .open(dummy_file)
                 .map_err(StorageError::from)?;
-            password_file.write(b"You are not supposed to see this. Get out.")?;
+            password_file.write_all(b"You are not supposed to see this. Get out.")?;
         }
 
         Ok(())
     }
 
     pub fn get_data_reader() -> Result<impl Read, StorageError> {
+        Self::get_reader(&Self::data_file()?)
+    }
+
+    /// The installed vault, from the database when there is one.
+    pub fn read_installed() -> Result<StoredVault, StorageError> {
+        #[cfg(feature = "sqlite")]
+        if Self::is_sqlite()? {
+            return Ok(StoredVault::Rows(sqlite::read(&Self::sqlite_file()?)?));
+        }
+        let mut bytes = Vec::new();
+        Self::get_data_reader()?.read_to_end(&mut bytes)?;
+        Ok(StoredVault::File(bytes))
+    }
+
+    /// Installs the mirror at `path` as the vault, by linking to it so that
+    /// pulling a new version of it is enough to see the changes.
+    pub fn subscribe(path: &Path) -> Result<(), StorageError> {
+        if Self::is_initialized()? {
+            return Err(StorageError::RootAlreadyExistsErorr);
+        }
+        std::fs::create_dir_all(Self::root()?)?;
+        std::os::unix::fs::symlink(path.canonicalize()?, Self::data_file()?)?;
+        Ok(())
+    }
+
+    pub fn get_reader(path: &Path) -> Result<impl Read, StorageError> {
         std::fs::OpenOptions::new()
             .read(true)
-            .open(Self::data_file()?)
+            .open(path)
             .map_err(StorageError::from)
     }
 
-    pub fn get_data_writer() -> Result<impl Write, StorageError> {
-        std::fs::OpenOptions::new()
-            .write(true)
-            .open(Self::data_file()?)
-            .map_err(StorageError::from)
+    /// Writes the vault unless another process saved it since `pm` was
+    /// read. The file stays locked between the check and the write, so two
+    /// concurrent saves cannot both pass the check. The vault file is copied
+    /// into the rotation of `backups` before it is written. A vault kept in
+    /// a database only has its changed rows written, and is not backed up.
+    pub fn save<T>(pm: &mut PasswordManager<T>, backups: &BackupPolicy) -> Result<(), StorageError>
+    where
+        T: Encryprtor + Identifiable,
+    {
+        let _span = trace::span("save");
+        Self::check_writable(pm)?;
+        #[cfg(feature = "sqlite")]
+        if Self::is_sqlite()? {
+            let expected = pm.generation();
+            let rows = Encoder::encode_rows(pm)?;
+            return sqlite::write(&Self::sqlite_file()?, &rows, expected);
+        }
+        Self::save_file(pm, &Self::data_file()?, backups)
+    }
+
+    /// Writes the vault file at `path` the way `save` writes the installed
+    /// one: locked, checked for a save by another process, backed up and
+    /// replaced. A file that does not exist yet is created.
+    pub fn save_file<T>(
+        pm: &mut PasswordManager<T>,
+        path: &Path,
+        backups: &BackupPolicy,
+    ) -> Result<(), StorageError>
+    where
+        T: Encryprtor + Identifiable,
+    {
+        Self::check_writable(pm)?;
+        let mut file = Self::lock_existing(path)?;
+        if let Some(file) = &mut file {
+            Self::check_generation(file, pm)?;
+        }
+
+        let slack = match (&mut file, pm.has_slack()) {
+            (Some(file), true) => {
+                let mut slack = vec![0; SLACK_SIZE];
+                file.seek(SeekFrom::End(-(SLACK_SIZE as i64)))?;
+                file.read_exact(&mut slack)?;
+                slack
+            }
+            _ => Self::new_slack(pm),
+        };
+        let mut bytes = Vec::new();
+        Encoder::encode(&mut bytes, pm)?;
+        bytes.extend(slack);
+        backups.rotate(path)?;
+        let _span = trace::span("write");
+        Self::replace(path, &bytes)
+    }
+
+    /// Fails if the vault in `file` was saved by another process since `pm`
+    /// was read.
+    fn check_generation<T: Encryprtor>(
+        file: &mut File,
+        pm: &PasswordManager<T>,
+    ) -> Result<(), StorageError> {
+        let found = Header::try_from_reader(file)?.generation();
+        match found == pm.generation() {
+            true => Ok(()),
+            false => Err(StorageError::ConflictError {
+                expected: pm.generation(),
+                found,
+            }),
+        }
+    }
+
+    /// Locks the vault file at `path`. Saves replace the file rather than
+    /// write into it, so the lock is taken again when the file was replaced
+    /// while waiting for it.
+    fn lock(path: &Path) -> Result<Flock<File>, StorageError> {
+        loop {
+            let file = std::fs::OpenOptions::new().read(true).open(path)?;
+            let file = Flock::lock(file, FlockArg::LockExclusive)
+                .map_err(|(_, errno)| io::Error::from(errno))?;
+            let (locked, current) = (file.metadata()?, std::fs::metadata(path)?);
+            if (locked.dev(), locked.ino()) == (current.dev(), current.ino()) {
+                return Ok(file);
+            }
+        }
+    }
+
+    /// Locks the file at `path` like `lock`, `None` when there is no file
+    /// to lock yet.
+    fn lock_existing(path: &Path) -> Result<Option<Flock<File>>, StorageError> {
+        match std::fs::metadata(path) {
+            Ok(_) => Self::lock(path).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Replaces the file at `path` with `bytes`. They are written to a file
+    /// next to it, synced, and renamed over it, so that a crash leaves
+    /// either the old file or the new one. A symlink is followed, and the
+    /// new file keeps the permissions of the old one.
+    fn replace(path: &Path, bytes: &[u8]) -> Result<(), StorageError> {
+        let path = match path.canonicalize() {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+            Err(err) => return Err(err.into()),
+        };
+        let mode =
+            std::fs::metadata(&path).map_or(PRIVATE_MODE, |v| v.permissions().mode() & 0o7777);
+        let mut name = OsString::from(".");
+        name.push(path.file_name().unwrap_or_default());
+        name.push(format!(".{}.tmp", std::process::id()));
+        let tmp = path.with_file_name(name);
+
+        let written = (|| {
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(PRIVATE_MODE)
+                .open(&tmp)?;
+            file.set_permissions(Permissions::from_mode(mode))?;
+            file.write_all(bytes)?;
+            file.sync_all()?;
+            std::fs::rename(&tmp, &path)
+        })();
+        if let Err(err) = written {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(err.into());
+        }
+        if let Some(dir) = path.parent().filter(|v| !v.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the vault to `path`, or to stdout for `-`, rather than to the
+    /// vault file. The slack is copied from `source`, the bytes the vault
+    /// was read from. The file at `path` is locked and replaced like the
+    /// vault file, and with `in_place`, when `pm` was read from it, it is
+    /// checked for a save by another process the same way. No backup is
+    /// taken.
+    pub fn save_to<T>(
+        pm: &mut PasswordManager<T>,
+        source: &[u8],
+        path: &str,
+        in_place: bool,
+    ) -> Result<(), StorageError>
+    where
+        T: Encryprtor + Identifiable,
+    {
+        let _span = trace::span("save");
+        Self::check_writable(pm)?;
+        let mut file = match path {
+            "-" => None,
+            path => Self::lock_existing(Path::new(path))?,
+        };
+        if let (Some(file), true) = (&mut file, in_place) {
+            Self::check_generation(file, pm)?;
+        }
+        let slack = match pm.has_slack() {
+            true => match source.len().checked_sub(SLACK_SIZE) {
+                Some(start) => source[start..].to_vec(),
+                None => return Err(EncoderError::InvalidHeaderSize.into()),
+            },
+            false => Self::new_slack(pm),
+        };
+        let mut bytes = Vec::new();
+        Encoder::encode(&mut bytes, pm)?;
+        bytes.extend(slack);
+        let _span = trace::span("write");
+        match path {
+            "-" => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&bytes)?;
+                stdout.flush()?;
+            }
+            path => Self::replace(Path::new(path), &bytes)?,
+        }
+        Ok(())
+    }
+
+    /// Mirrors are only written by `mopm publish`, which encodes them
+    /// itself.
+    fn check_writable<T: Encryprtor>(pm: &PasswordManager<T>) -> Result<(), StorageError> {
+        match pm.is_mirror() {
+            true => Err(StorageError::MirrorError),
+            false => Ok(()),
+        }
+    }
+
+    /// Slack for a vault file that has none yet: random bytes with the
+    /// `hidden-volume` feature, nothing without.
+    #[cfg_attr(not(feature = "hidden-volume"), allow(unused_variables))]
+    fn new_slack<T: Encryprtor>(pm: &mut PasswordManager<T>) -> Vec<u8> {
+        #[cfg(feature = "hidden-volume")]
+        {
+            pm.set_slack(true);
+            hidden::random_slack()
+        }
+        #[cfg(not(feature = "hidden-volume"))]
+        Vec::new()
+    }
+
+    /// Writes a hidden vault into the slack of the vault file, leaving the
+    /// outer vault as it is. Like `save`, it fails if the hidden vault was
+    /// saved by another process since `pm` was read. The backups are not
+    /// rotated: copies that differ in their slack alone would show that it
+    /// holds a vault.
+    #[cfg(feature = "hidden-volume")]
+    pub fn save_hidden<T>(pm: &mut PasswordManager<T>, key: &HiddenKey) -> Result<(), StorageError>
+    where
+        T: Encryprtor + Identifiable,
+    {
+        let _span = trace::span("save");
+        let path = Self::data_file()?;
+        let mut file = Self::lock(&path)?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let Some(outer) = bytes.len().checked_sub(SLACK_SIZE) else {
+            return Err(EncoderError::InvalidHeaderSize.into());
+        };
+        let found = hidden::generation(&bytes[outer..], key).unwrap_or(0);
+        if found != pm.generation() {
+            return Err(StorageError::ConflictError {
+                expected: pm.generation(),
+                found,
+            });
+        }
+
+        bytes.truncate(outer);
+        bytes.extend(hidden::seal(pm, key)?);
+        let _span = trace::span("write");
+        Self::replace(&path, &bytes)
+    }
+
+    /// Replaces the slack of the vault file with `slack`, without rotating
+    /// the backups, see `save_hidden`.
+    #[cfg(feature = "hidden-volume")]
+    pub fn replace_slack(slack: &[u8]) -> Result<(), StorageError> {
+        let path = Self::data_file()?;
+        let mut file = Self::lock(&path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let Some(outer) = bytes.len().checked_sub(SLACK_SIZE) else {
+            return Err(EncoderError::InvalidHeaderSize.into());
+        };
+        bytes.truncate(outer);
+        bytes.extend(slack);
+        Self::replace(&path, &bytes)
+    }
+
+    /// The `n`th copy in the backup rotation, 1 being the most recent.
+    pub fn backup_file(n: usize) -> Result<PathBuf, StorageError> {
+        Ok(backup::path(&Self::data_file()?, n))
+    }
+
+    /// Deletes every backup of the vault file, see `backup::remove_all`.
+    #[cfg(feature = "tpm")]
+    pub fn remove_backups() -> Result<usize, StorageError> {
+        Ok(backup::remove_all(&Self::data_file()?)?)
+    }
+
+    /// Replaces the vault file with `vault`, read from a backup. Like before
+    /// any other write, the vault file is copied into the rotation first.
+    pub fn restore_backup(vault: &[u8], backups: &BackupPolicy) -> Result<(), StorageError> {
+        let path = Self::data_file()?;
+        let _file = Self::lock(&path)?;
+        backups.rotate(&path)?;
+        let _span = trace::span("write");
+        Self::replace(&path, vault)
+    }
+
+    /// A writer for a plaintext export to `path`, readable by the owner
+    /// only. Nothing is written to `path` until `PrivateWriter::commit`,
+    /// which replaces it at once.
+    pub fn get_private_writer(path: &Path) -> PrivateWriter {
+        PrivateWriter {
+            path: path.to_path_buf(),
+            bytes: Zeroizing::new(Vec::new()),
+        }
+    }
+
+    /// Stores the password hint unencrypted next to the vault.
+    pub fn save_hint(hint: &str) -> Result<(), StorageError> {
+        let mut writer = Self::get_private_writer(&Self::root()?.join(HINT_FILE));
+        writer.write_all(hint.as_bytes())?;
+        writer.commit()
+    }
+
+    pub fn hint() -> Result<Option<String>, StorageError> {
+        match std::fs::read_to_string(Self::root()?.join(HINT_FILE)) {
+            Ok(v) => Ok(Some(v)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
     }
 
+    /// The identity of this machine when it has no machine id, see
+    /// `identity::current_device_id`.
+    pub fn device_id_file() -> Result<PathBuf, StorageError> {
+        Ok(Self::root()?.join(DEVICE_ID_FILE))
+    }
+
+    /// The key slots of the vault bound to the TPM. They are kept next to
+    /// the vault so that `clear` removes both.
+    #[cfg(feature = "tpm")]
+    pub fn tpm_file() -> Result<PathBuf, StorageError> {
+        Ok(Self::root()?.join(TPM_FILE))
+    }
+
+    /// Moves the storage into the trash, where it stays for the grace
+    /// period unless it is purged before.
     pub fn clear() -> Result<(), StorageError> {
         let root = Self::root()?;
         if !root.exists() {
             return Err(StorageError::RootDoesNotExistErorr);
         }
 
-        std::fs::remove_dir_all(root).map_err(StorageError::from)
+        Self::trash()?.put(&root, clock::now())?;
+        Ok(())
+    }
+
+    /// Purges what was cleared before the grace period, or everything with
+    /// `all`, and returns how many storages were purged.
+    pub fn purge_trash(all: bool) -> Result<usize, StorageError> {
+        let now = clock::now();
+        let cutoff = match all {
+            true => u64::MAX,
+            false => now.saturating_sub(trash::GRACE_PERIOD),
+        };
+        Ok(Self::trash()?.purge(cutoff, now)?)
+    }
+
+    /// Brings back the storage cleared last, `None` if the trash is empty.
+    pub fn restore_trash() -> Result<Option<String>, StorageError> {
+        let root = Self::root()?;
+        if root.exists() {
+            return Err(StorageError::RootAlreadyExistsErorr);
+        }
+        Ok(Self::trash()?.restore(&root, clock::now())?)
+    }
+
+    /// Next to the root so that clearing is a rename on the same file system.
+    fn trash() -> Result<Trash, StorageError> {
+        Ok(Trash::new(Self::trash_dir(&Self::root()?)))
+    }
+
+    fn trash_dir(root: &Path) -> PathBuf {
+        let mut name = root.file_name().unwrap_or_default().to_os_string();
+        name.push(TRASH_SUFFIX);
+        root.with_file_name(name)
     }
 
     pub fn is_initialized() -> Result<bool, StorageError> {
+        #[cfg(feature = "sqlite")]
+        if Self::is_sqlite()? {
+            return Ok(true);
+        }
         Ok(Self::root()?.exists() && Self::data_file()?.exists())
     }
 
+    /// Whether the installed vault is kept in a database.
+    #[cfg(feature = "sqlite")]
+    pub fn is_sqlite() -> Result<bool, StorageError> {
+        Ok(Self::sqlite_file()?.exists())
+    }
+
+    /// The directory the vault is kept in, `$XDG_DATA_HOME/mopm`, or
+    /// `~/.mopm` while a root left there has not been moved, see
+    /// `migrate_legacy_root`.
     pub fn root() -> Result<PathBuf, StorageError> {
-        let mut root = Self::homedir()?;
-        root.push(".mopm");
+        let root = Self::data_home()?.join(ROOT_DIR);
 
+        #[cfg(feature = "legacy-layout")]
+        {
+            let legacy = Self::homedir()?.join(LEGACY_ROOT_DIR);
+            if !root.exists() && legacy.exists() {
+                return Ok(legacy);
+            }
+        }
         Ok(root)
     }
 
+    /// The parent of the root may not exist yet, the root itself must not.
+    fn create_root(root: &Path) -> Result<(), StorageError> {
+        if let Some(dir) = root.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        create_dir(root)?;
+        Ok(())
+    }
+
+    /// The user configuration, kept outside the root so that `clear` does
+    /// not remove it.
+    pub fn config_file() -> Result<PathBuf, StorageError> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+            Some(v) => PathBuf::from(v),
+            None => Self::homedir()?.join(".config"),
+        };
+        Ok(dir.join("mopm").join("config"))
+    }
+
+    /// Incident records of the shield, kept outside the root so that they
+    /// survive the wipe.
+    pub fn incident_log() -> Result<PathBuf, StorageError> {
+        Ok(Self::state_dir()?.join("incidents"))
+    }
+
+    /// The pid of the process watching the honeypot, while the shield is
+    /// up.
+    pub fn shield_pid_file() -> Result<PathBuf, StorageError> {
+        Ok(Self::state_dir()?.join("shield.pid"))
+    }
+
+    /// Entries of `store --session`, in the runtime directory so that they
+    /// stay in memory and go with the login session.
+    pub fn session_file() -> Result<PathBuf, StorageError> {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .filter(|v| !v.is_empty())
+            .ok_or(StorageError::NoRuntimeDirError)?;
+        Ok(PathBuf::from(dir).join("mopm").join("session"))
+    }
+
+    /// Where `ssh-agent` listens by default, next to the session entries.
+    #[cfg(feature = "ssh-agent")]
+    pub fn ssh_agent_socket() -> Result<PathBuf, StorageError> {
+        Ok(Self::session_file()?.with_file_name("ssh-agent"))
+    }
+
+    /// The sealed session entries, `None` if there are none.
+    pub fn read_session() -> Result<Option<Vec<u8>>, StorageError> {
+        match std::fs::read(Self::session_file()?) {
+            Ok(v) => Ok(Some(v)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Replaces the sealed session entries, refusing a runtime directory
+    /// that is not a tmpfs.
+    pub fn write_session(sealed: &[u8]) -> Result<(), StorageError> {
+        let path = Self::session_file()?;
+        let dir = path.parent().or_bug("the session file is in a directory");
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        if !statfs(dir).is_ok_and(|v| v.filesystem_type() == TMPFS_MAGIC) {
+            return Err(StorageError::RuntimeDirOnDiskError);
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?
+            .write_all(sealed)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Removes the session entries, returning whether there were any.
+    pub fn forget_session() -> Result<bool, StorageError> {
+        match std::fs::remove_file(Self::session_file()?) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Findings of `monitor`, kept until they are cleared.
+    #[cfg(feature = "monitor")]
+    pub fn notifications_file() -> Result<PathBuf, StorageError> {
+        Ok(Self::state_dir()?.join("notifications"))
+    }
+
+    fn data_home() -> Result<PathBuf, StorageError> {
+        match std::env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+            Some(v) => Ok(PathBuf::from(v)),
+            None => Ok(Self::homedir()?.join(".local").join("share")),
+        }
+    }
+
+    fn state_dir() -> Result<PathBuf, StorageError> {
+        let dir = match std::env::var_os("XDG_STATE_HOME").filter(|v| !v.is_empty()) {
+            Some(v) => PathBuf::from(v),
+            None => Self::homedir()?.join(".local").join("state"),
+        };
+        Ok(dir.join("mopm"))
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn sqlite_file() -> Result<PathBuf, StorageError> {
+        Ok(Self::root()?.join(SQLITE_FILE))
+    }
+
     fn data_file() -> Result<PathBuf, StorageError> {
         let mut data = Self::root()?;
         data.push(".data");
 
+        #[cfg(feature = "legacy-layout")]
+        Self::migrate_legacy_layout(&data)?;
         Ok(data)
     }
 
+    /// Early revisions stored the vault as `data` instead of `.data`. Such a
+    /// vault is moved to the current location the first time it is opened;
+    /// when both files exist the current one wins and the old one is kept.
+    #[cfg(feature = "legacy-layout")]
+    fn migrate_legacy_layout(data: &Path) -> Result<(), StorageError> {
+        let legacy = data.with_file_name(LEGACY_DATA_FILE);
+        if !data.exists() && legacy.is_file() {
+            std::fs::rename(legacy, data)?;
+        }
+        Ok(())
+    }
+
+    /// Early revisions kept the root and its trash in the home directory.
+    /// They are moved to the data directory when the application starts;
+    /// when both roots exist the current one is used and the old one is
+    /// left alone. A root under the shield is moved once it is lowered.
+    #[cfg(feature = "legacy-layout")]
+    pub fn migrate_legacy_root() -> Result<LegacyRoot, StorageError> {
+        let legacy = Self::homedir()?.join(LEGACY_ROOT_DIR);
+        Self::move_legacy_root(&legacy, &Self::data_home()?.join(ROOT_DIR))
+    }
+
+    #[cfg(feature = "legacy-layout")]
+    fn move_legacy_root(legacy: &Path, root: &Path) -> Result<LegacyRoot, StorageError> {
+        let move_dir = |from: &Path, to: &Path| -> io::Result<()> {
+            if let Some(dir) = to.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::rename(from, to)
+        };
+        let moved = match (legacy.exists(), root.exists()) {
+            (false, _) => LegacyRoot::Absent,
+            (true, true) => return Ok(LegacyRoot::Ignored(legacy.to_path_buf())),
+            (true, false) if legacy.join(HONEYPOT_FILE).exists() => {
+                return Ok(LegacyRoot::Shielded(legacy.to_path_buf()))
+            }
+            (true, false) => {
+                move_dir(legacy, root)?;
+                LegacyRoot::Moved(legacy.to_path_buf())
+            }
+        };
+        // Also a trash left behind by a move that failed half way.
+        let (legacy_trash, trash) = (Self::trash_dir(legacy), Self::trash_dir(root));
+        if !trash.exists() && legacy_trash.exists() {
+            move_dir(&legacy_trash, &trash)?;
+        }
+        Ok(moved)
+    }
+
     pub fn dummy() -> Result<PathBuf, StorageError> {
         PathBuf::from_str("/tmp/mopm-dummy").map_err(StorageError::from)
     }
 
     pub fn upper_file() -> Result<PathBuf, StorageError> {
         let mut upper = Self::dummy()?;
-        upper.push("not-a-honeypot.txt");
+        upper.push(HONEYPOT_FILE);
 
         Ok(upper)
     }
 
+    /// Whether the dummy directory is mounted over the root, which shows
+    /// the honeypot file in place of the vault.
+    pub fn is_shielded() -> Result<bool, StorageError> {
+        Ok(Self::root()?.join(HONEYPOT_FILE).exists())
+    }
+
     #[cfg(unix)]
-    fn homedir() -> Result<PathBuf, StorageError> {
+    pub fn homedir() -> Result<PathBuf, StorageError> {
         match std::env::var_os("HOME") {
             Some(user) => Ok(PathBuf::from(user)),
             None => nix::unistd::User::from_uid(nix::unistd::Uid::current())
@@ -140,3 +798,153 @@ impl Storage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace() {
+        let dir = std::env::temp_dir().join(format!("mopm-replace-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_dir(&dir).unwrap();
+        let data = dir.join(".data");
+        std::fs::write(&data, b"old").unwrap();
+        std::fs::set_permissions(&data, Permissions::from_mode(0o640)).unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&data, &link).unwrap();
+
+        let locked = Storage::lock(&link).unwrap();
+        Storage::replace(&link, b"new").unwrap();
+        assert_eq!(std::fs::read(&data).unwrap(), b"new");
+        assert!(link.is_symlink());
+        let mode = std::fs::metadata(&data).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        // A lock taken after the replace is on the new file.
+        drop(locked);
+        let mut locked = Storage::lock(&data).unwrap();
+        let mut bytes = Vec::new();
+        locked.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, b"new");
+
+        Storage::replace(&dir.join("export"), b"plain").unwrap();
+        let mode = std::fs::metadata(dir.join("export"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, PRIVATE_MODE);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_to() {
+        let dir = std::env::temp_dir().join(format!("mopm-save-to-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_dir(&dir).unwrap();
+        let path = dir.join("vault");
+        let path = path.to_str().unwrap();
+
+        let mut pm = PasswordManager::init_with_key(&[7; 32]);
+        Storage::save_to(&mut pm, &[], path, false).unwrap();
+        let source = std::fs::read(path).unwrap();
+        Storage::save_to(&mut pm, &source, path, true).unwrap();
+        let mut stale = PasswordManager::init_with_key(&[7; 32]);
+        assert!(matches!(
+            Storage::save_to(&mut stale, &[], path, true),
+            Err(StorageError::ConflictError { .. })
+        ));
+        Storage::save_to(&mut stale, &[], path, false).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_file() {
+        let dir = std::env::temp_dir().join(format!("mopm-save-file-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_dir(&dir).unwrap();
+        let path = dir.join("system.data");
+        let backups = BackupPolicy::from_settings(&[("backup.before_write", "true")]).unwrap();
+
+        let mut pm = PasswordManager::init_with_key(&[7; 32]);
+        Storage::save_file(&mut pm, &path, &backups).unwrap();
+        assert!(!backup::path(&path, 1).exists());
+        Storage::save_file(&mut pm, &path, &backups).unwrap();
+        assert!(backup::path(&path, 1).exists());
+        let mut stale = PasswordManager::init_with_key(&[7; 32]);
+        assert!(matches!(
+            Storage::save_file(&mut stale, &path, &backups),
+            Err(StorageError::ConflictError { .. })
+        ));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "legacy-layout")]
+    #[test]
+    fn test_migrate_legacy_layout() {
+        let root = std::env::temp_dir().join(format!("mopm-layout-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        create_dir(&root).unwrap();
+        let data = root.join(".data");
+        let legacy = root.join(LEGACY_DATA_FILE);
+
+        std::fs::write(&legacy, b"old").unwrap();
+        Storage::migrate_legacy_layout(&data).unwrap();
+        assert_eq!(std::fs::read(&data).unwrap(), b"old");
+        assert!(!legacy.exists());
+
+        std::fs::write(&legacy, b"older").unwrap();
+        Storage::migrate_legacy_layout(&data).unwrap();
+        assert_eq!(std::fs::read(&data).unwrap(), b"old");
+        assert!(legacy.exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(feature = "legacy-layout")]
+    #[test]
+    fn test_migrate_legacy_root() {
+        let home = std::env::temp_dir().join(format!("mopm-home-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&home);
+        let legacy = home.join(LEGACY_ROOT_DIR);
+        let root = home.join(".local").join("share").join(ROOT_DIR);
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::create_dir(Storage::trash_dir(&legacy)).unwrap();
+        std::fs::write(legacy.join(".data"), b"old").unwrap();
+
+        std::fs::write(legacy.join(HONEYPOT_FILE), b"").unwrap();
+        assert_eq!(
+            Storage::move_legacy_root(&legacy, &root).unwrap(),
+            LegacyRoot::Shielded(legacy.clone())
+        );
+        assert!(!root.exists());
+        std::fs::remove_file(legacy.join(HONEYPOT_FILE)).unwrap();
+
+        assert_eq!(
+            Storage::move_legacy_root(&legacy, &root).unwrap(),
+            LegacyRoot::Moved(legacy.clone())
+        );
+        assert_eq!(std::fs::read(root.join(".data")).unwrap(), b"old");
+        assert!(home.join(".local/share/mopm-trash").is_dir());
+        assert!(!legacy.exists());
+        assert_eq!(
+            Storage::move_legacy_root(&legacy, &root).unwrap(),
+            LegacyRoot::Absent
+        );
+
+        create_dir(&legacy).unwrap();
+        assert_eq!(
+            Storage::move_legacy_root(&legacy, &root).unwrap(),
+            LegacyRoot::Ignored(legacy.clone())
+        );
+        assert!(legacy.exists());
+
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+}
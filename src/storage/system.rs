@@ -0,0 +1,140 @@
+//! The vault of the machine rather than of a user, for daemons that read
+//! their secrets unattended, e.g. at boot with `mopm get --system <key>`.
+//! It is locked by a random key in a file only root can read instead of a
+//! password:
+//!
+//! ```text
+//! /etc/mopm/system.key       the key as hex, owned by root, mode 0400
+//! /var/lib/mopm/system.data  the vault
+//! ```
+//!
+//! The vault file is saved like the installed one, locked and replaced
+//! as a whole, so that a daemon reading it meanwhile gets either the old
+//! vault or the new one. It can be backed up or copied like any other,
+//! the key file must not leave the machine: whoever reads it reads every
+//! secret. A key file that someone other than root owns, or that group or
+//! others could read or write, is refused rather than used.
+
+use std::{
+    fs::{self, DirBuilder, File, OpenOptions},
+    io::{self, Read, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::core::{kdf::KEY_LENGTH, rng};
+
+pub const KEY_FILE: &str = "/etc/mopm/system.key";
+pub const DATA_FILE: &str = "/var/lib/mopm/system.data";
+
+const KEY_MODE: u32 = 0o400;
+const DIR_MODE: u32 = 0o755;
+/// Bits of the mode that must be clear: any access by group and others.
+const FORBIDDEN_MODE: u32 = 0o077;
+
+#[derive(Error, Debug)]
+pub enum SystemError {
+    #[error("cannot access the system key: `{0}`")]
+    IoError(#[from] io::Error),
+    #[error("the system key `{0}` must be owned by root")]
+    NotOwnedByRoot(PathBuf),
+    #[error("the system key `{0}` is accessible by group or others (mode {1:o}), it must be 0400 or 0600")]
+    TooOpen(PathBuf, u32),
+    #[error("the system key `{0}` is not {KEY_LENGTH} hex-encoded bytes")]
+    Malformed(PathBuf),
+    #[error("the system key `{0}` already exists")]
+    AlreadyExists(PathBuf),
+}
+
+/// Reads the key at `path`, checked on the opened file so that it cannot
+/// be swapped between the check and the read.
+pub fn read_key(path: &Path) -> Result<Zeroizing<Vec<u8>>, SystemError> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    check_access(path, metadata.uid(), metadata.mode())?;
+    let mut text = Zeroizing::new(String::new());
+    file.read_to_string(&mut text)?;
+    hex::decode(text.trim())
+        .ok()
+        .map(Zeroizing::new)
+        .filter(|key| key.len() == KEY_LENGTH)
+        .ok_or_else(|| SystemError::Malformed(path.to_path_buf()))
+}
+
+/// Creates a key at `path`, along with its directory, never replacing one:
+/// the vault it locks would be lost with it.
+pub fn create_key(path: &Path) -> Result<Zeroizing<Vec<u8>>, SystemError> {
+    create_parent(path)?;
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(KEY_MODE)
+        .open(path)
+    {
+        Ok(v) => v,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(SystemError::AlreadyExists(path.to_path_buf()))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let mut key = Zeroizing::new(vec![0; KEY_LENGTH]);
+    rng::fill(&mut key);
+    let text = Zeroizing::new(hex::encode(key.as_slice()));
+    file.write_all(text.as_bytes())?;
+    file.write_all(b"\n")?;
+    file.sync_all()?;
+    Ok(key)
+}
+
+/// Creates the directory of the file at `path`, readable by all like the
+/// rest of `/etc` and `/var/lib`: the files in it are private.
+pub fn create_parent(path: &Path) -> Result<(), SystemError> {
+    if let Some(dir) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(DIR_MODE)
+            .create(dir)?;
+    }
+    Ok(())
+}
+
+pub fn is_initialized() -> bool {
+    fs::metadata(DATA_FILE).is_ok()
+}
+
+fn check_access(path: &Path, uid: u32, mode: u32) -> Result<(), SystemError> {
+    if uid != 0 {
+        return Err(SystemError::NotOwnedByRoot(path.to_path_buf()));
+    }
+    if mode & FORBIDDEN_MODE != 0 {
+        return Err(SystemError::TooOpen(path.to_path_buf(), mode & 0o7777));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_access() {
+        let path = Path::new(KEY_FILE);
+        assert!(check_access(path, 0, 0o100400).is_ok());
+        assert!(check_access(path, 0, 0o100600).is_ok());
+        assert!(matches!(
+            check_access(path, 1000, 0o100400),
+            Err(SystemError::NotOwnedByRoot(_))
+        ));
+        assert!(matches!(
+            check_access(path, 0, 0o100640),
+            Err(SystemError::TooOpen(_, 0o640))
+        ));
+        assert!(matches!(
+            check_access(path, 0, 0o100602),
+            Err(SystemError::TooOpen(_, 0o602))
+        ));
+    }
+}
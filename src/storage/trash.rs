@@ -0,0 +1,229 @@
+//! The trash `clear` moves the storage into. Clearing renames the root into
+//! the trash, which is atomic, and the copy is purged once the grace period
+//! is over or at once with `clear --now`. Purging overwrites every file
+//! before it is removed.
+//!
+//! Every step is appended to a journal in the trash before it is taken:
+//!
+//! ```text
+//! trash 1767225600 1767225600
+//! purge 1767225600 1767830400
+//! purged 1767225600 1767830401
+//! ```
+//!
+//! An entry whose purge was cut short, e.g. by a crash, is purged again the
+//! next time the trash is looked at, and no longer restored.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const JOURNAL_FILE: &str = "journal";
+/// Seconds a cleared storage stays in the trash.
+pub const GRACE_PERIOD: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Trash,
+    Restore,
+    Purge,
+    Purged,
+}
+
+impl Step {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Trash => "trash",
+            Self::Restore => "restore",
+            Self::Purge => "purge",
+            Self::Purged => "purged",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        [Self::Trash, Self::Restore, Self::Purge, Self::Purged]
+            .into_iter()
+            .find(|v| v.name() == name)
+    }
+}
+
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Moves `root` into the trash and returns the name of the entry.
+    pub fn put(&self, root: &Path, now: u64) -> io::Result<String> {
+        fs::create_dir_all(&self.dir)?;
+        let name = (0..)
+            .map(|n| match n {
+                0 => now.to_string(),
+                n => format!("{}-{}", now, n),
+            })
+            .find(|v| !self.dir.join(v).exists())
+            .expect("some name is free");
+        self.log(Step::Trash, &name, now)?;
+        fs::rename(root, self.dir.join(&name))?;
+        Ok(name)
+    }
+
+    /// Moves the most recent entry back to `root`, `None` when there is
+    /// nothing to restore.
+    pub fn restore(&self, root: &Path, now: u64) -> io::Result<Option<String>> {
+        let Some((name, _)) = self
+            .entries()?
+            .into_iter()
+            .filter(|(_, (step, _))| *step == Step::Trash)
+            .max_by_key(|(_, (_, time))| *time)
+        else {
+            return Ok(None);
+        };
+        self.log(Step::Restore, &name, now)?;
+        fs::rename(self.dir.join(&name), root)?;
+        Ok(Some(name))
+    }
+
+    /// Purges the entries trashed before `cutoff`, and those whose purge
+    /// did not finish, returning how many were purged.
+    pub fn purge(&self, cutoff: u64, now: u64) -> io::Result<usize> {
+        let due: Vec<String> = self
+            .entries()?
+            .into_iter()
+            .filter(|(_, (step, time))| *step == Step::Purge || *time < cutoff)
+            .map(|(name, _)| name)
+            .collect();
+        for name in &due {
+            self.log(Step::Purge, name, now)?;
+            shred_dir(&self.dir.join(name))?;
+            self.log(Step::Purged, name, now)?;
+        }
+        Ok(due.len())
+    }
+
+    /// The entries still in the trash with their last step, and when they
+    /// were trashed.
+    fn entries(&self) -> io::Result<BTreeMap<String, (Step, u64)>> {
+        let journal = match fs::read_to_string(self.dir.join(JOURNAL_FILE)) {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => return Err(err),
+        };
+        let mut entries = BTreeMap::new();
+        for line in journal.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(step), Some(name), Some(time)) = (
+                fields.next().and_then(Step::parse),
+                fields.next(),
+                fields.next().and_then(|v| v.parse().ok()),
+            ) else {
+                continue;
+            };
+            match step {
+                Step::Trash => {
+                    entries.insert(name.to_string(), (step, time));
+                }
+                Step::Purge => {
+                    if let Some(entry) = entries.get_mut(name) {
+                        entry.0 = step;
+                    }
+                }
+                Step::Restore | Step::Purged => {
+                    entries.remove(name);
+                }
+            }
+        }
+        // A crash between the journal and the rename leaves nothing behind.
+        entries.retain(|name, _| self.dir.join(name).exists());
+        Ok(entries)
+    }
+
+    fn log(&self, step: Step, name: &str, time: u64) -> io::Result<()> {
+        let mut journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(JOURNAL_FILE))?;
+        writeln!(journal, "{} {} {}", step.name(), name, time)?;
+        journal.sync_all()
+    }
+}
+
+/// Overwrites every file under `dir` with zeros, then removes it.
+fn shred_dir(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            shred_dir(&entry.path())?;
+        } else if file_type.is_file() {
+            let len = entry.metadata()?.len();
+            let mut file = OpenOptions::new().write(true).open(entry.path())?;
+            io::copy(&mut io::repeat(0).take(len), &mut file)?;
+            file.sync_all()?;
+        }
+    }
+    fs::remove_dir_all(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dirs(name: &str) -> (PathBuf, Trash) {
+        let dir = std::env::temp_dir().join(format!("mopm-trash-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root").join("sub")).unwrap();
+        fs::write(dir.join("root").join(".data"), "vault").unwrap();
+        fs::write(dir.join("root").join("sub").join("x"), "x").unwrap();
+        (dir.clone(), Trash::new(dir.join("trash")))
+    }
+
+    #[test]
+    fn test_put_and_restore() {
+        let (dir, trash) = dirs("restore");
+        let root = dir.join("root");
+        let name = trash.put(&root, 100).unwrap();
+        assert_eq!(name, "100");
+        assert!(!root.exists());
+
+        assert_eq!(trash.restore(&root, 101).unwrap(), Some(name));
+        assert_eq!(fs::read_to_string(root.join(".data")).unwrap(), "vault");
+        assert_eq!(trash.restore(&root, 102).unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_purge() {
+        let (dir, trash) = dirs("purge");
+        let root = dir.join("root");
+        trash.put(&root, 100).unwrap();
+        fs::create_dir_all(&root).unwrap();
+        trash.put(&root, 100).unwrap();
+        assert_eq!(trash.entries().unwrap().len(), 2);
+
+        assert_eq!(trash.purge(100, 200).unwrap(), 0);
+        assert_eq!(trash.purge(101, 200).unwrap(), 2);
+        assert!(trash.entries().unwrap().is_empty());
+        assert!(!dir.join("trash").join("100").exists());
+        assert_eq!(trash.restore(&root, 300).unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unfinished_purge_is_resumed() {
+        let (dir, trash) = dirs("resume");
+        let name = trash.put(&dir.join("root"), 100).unwrap();
+        trash.log(Step::Purge, &name, 150).unwrap();
+
+        assert_eq!(trash.restore(&dir.join("root"), 160).unwrap(), None);
+        assert_eq!(trash.purge(0, 200).unwrap(), 1);
+        assert!(!dir.join("trash").join(&name).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+//! Other vault files known by a label, for the commands working across
+//! vaults, configured in the config file:
+//!
+//! ```text
+//! vault.work = /home/me/work.data
+//! vault.family = /mnt/shared/family.data
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+const PREFIX: &str = "vault.";
+
+#[derive(Error, Debug)]
+pub enum VaultsError {
+    #[error("invalid setting `{0}`, expected `vault.<label> = <path>`")]
+    InvalidSetting(String),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Vaults(BTreeMap<String, PathBuf>);
+
+impl Vaults {
+    /// The vaults of the `vault.` settings of the config file.
+    pub fn from_settings(settings: &[(&str, &str)]) -> Result<Self, VaultsError> {
+        let mut vaults = Self::default();
+        for &(name, value) in settings {
+            let label = &name[PREFIX.len()..];
+            if label.is_empty() || value.is_empty() {
+                return Err(VaultsError::InvalidSetting(name.to_string()));
+            }
+            vaults.0.insert(label.to_string(), PathBuf::from(value));
+        }
+        Ok(vaults)
+    }
+
+    /// The labels and paths in label order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.0
+            .iter()
+            .map(|(label, path)| (label.as_str(), path.as_path()))
+    }
+}
+
+pub fn is_vault_setting(name: &str) -> bool {
+    name.starts_with(PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let vaults = Vaults::from_settings(&[
+            ("vault.work", "/tmp/work.data"),
+            ("vault.family", "/tmp/family.data"),
+        ])
+        .unwrap();
+        let vaults: Vec<_> = vaults.iter().collect();
+        assert_eq!(
+            vaults,
+            [
+                ("family", Path::new("/tmp/family.data")),
+                ("work", Path::new("/tmp/work.data"))
+            ]
+        );
+        assert!(Vaults::from_settings(&[("vault.work", "")]).is_err());
+        assert!(Vaults::from_settings(&[("vault.", "/tmp/work.data")]).is_err());
+    }
+}
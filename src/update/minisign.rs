@@ -0,0 +1,153 @@
+//! Verification of minisign signatures, see
+//! <https://jedisct1.github.io/minisign/#signature-format>.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signature, VerifyingKey};
+use thiserror::Error;
+
+const KEY_ID_LENGTH: usize = 8;
+const ALGORITHM_PURE: &[u8; 2] = b"Ed";
+const ALGORITHM_HASHED: &[u8; 2] = b"ED";
+const TRUSTED_COMMENT: &str = "trusted comment: ";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MinisignError {
+    #[error("malformed minisign {0}")]
+    Malformed(&'static str),
+    #[error("the signature was made with another key")]
+    KeyMismatch,
+    #[error("the signature does not match")]
+    InvalidSignature,
+}
+
+pub struct PublicKey {
+    id: [u8; KEY_ID_LENGTH],
+    key: VerifyingKey,
+}
+
+impl PublicKey {
+    /// Parses the base64 line of a minisign public key file.
+    pub fn from_base64(value: &str) -> Result<Self, MinisignError> {
+        let malformed = || MinisignError::Malformed("public key");
+        let bytes = STANDARD.decode(value.trim()).map_err(|_| malformed())?;
+        let (algorithm, rest) = bytes.split_at_checked(2).ok_or_else(malformed)?;
+        if algorithm != ALGORITHM_PURE {
+            return Err(malformed());
+        }
+        let (id, key) = rest.split_at_checked(KEY_ID_LENGTH).ok_or_else(malformed)?;
+
+        Ok(Self {
+            id: id.try_into().map_err(|_| malformed())?,
+            key: key
+                .try_into()
+                .ok()
+                .and_then(|key| VerifyingKey::from_bytes(key).ok())
+                .ok_or_else(malformed)?,
+        })
+    }
+
+    /// Checks the contents of a `.minisig` file against `data`, including
+    /// the global signature over the trusted comment, which is returned.
+    pub fn verify<'a>(&self, data: &[u8], minisig: &'a str) -> Result<&'a str, MinisignError> {
+        let malformed = || MinisignError::Malformed("signature");
+        let mut lines = minisig.lines().skip(1);
+        let signature = lines
+            .next()
+            .and_then(|v| STANDARD.decode(v.trim()).ok())
+            .ok_or_else(malformed)?;
+        let trusted_comment = lines
+            .next()
+            .and_then(|v| v.strip_prefix(TRUSTED_COMMENT))
+            .ok_or_else(malformed)?;
+        let global_signature = lines
+            .next()
+            .and_then(|v| STANDARD.decode(v.trim()).ok())
+            .ok_or_else(malformed)?;
+
+        let (algorithm, rest) = signature.split_at_checked(2).ok_or_else(malformed)?;
+        let (id, signature) = rest.split_at_checked(KEY_ID_LENGTH).ok_or_else(malformed)?;
+        if id != self.id {
+            return Err(MinisignError::KeyMismatch);
+        }
+
+        let message = match <&[u8; 2]>::try_from(algorithm) {
+            Ok(ALGORITHM_PURE) => data.to_vec(),
+            Ok(ALGORITHM_HASHED) => Blake2b512::digest(data).to_vec(),
+            _ => return Err(malformed()),
+        };
+        self.verify_raw(&message, signature)?;
+        self.verify_raw(
+            &[signature, trusted_comment.as_bytes()].concat(),
+            &global_signature,
+        )?;
+        Ok(trusted_comment)
+    }
+
+    fn verify_raw(&self, message: &[u8], signature: &[u8]) -> Result<(), MinisignError> {
+        let signature =
+            Signature::from_slice(signature).map_err(|_| MinisignError::Malformed("signature"))?;
+        self.key
+            .verify_strict(message, &signature)
+            .map_err(|_| MinisignError::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    const KEY_ID: [u8; KEY_ID_LENGTH] = [7; KEY_ID_LENGTH];
+
+    fn public_key(key: &SigningKey) -> String {
+        STANDARD.encode([&ALGORITHM_PURE[..], &KEY_ID, key.verifying_key().as_bytes()].concat())
+    }
+
+    fn sign(key: &SigningKey, data: &[u8], comment: &str) -> String {
+        let signature = key.sign(&Blake2b512::digest(data)).to_bytes();
+        let global = key.sign(&[&signature[..], comment.as_bytes()].concat());
+        format!(
+            "untrusted comment: test\n{}\n{}{}\n{}\n",
+            STANDARD.encode([&ALGORITHM_HASHED[..], &KEY_ID, &signature].concat()),
+            TRUSTED_COMMENT,
+            comment,
+            STANDARD.encode(global.to_bytes()),
+        )
+    }
+
+    #[test]
+    fn test_verify() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let public = PublicKey::from_base64(&public_key(&key)).unwrap();
+        let minisig = sign(&key, b"binary", "file:mopm");
+
+        assert_eq!(public.verify(b"binary", &minisig), Ok("file:mopm"));
+        assert_eq!(
+            public.verify(b"tampered", &minisig),
+            Err(MinisignError::InvalidSignature)
+        );
+        assert_eq!(
+            public.verify(b"binary", &minisig.replace("file:mopm", "file:other")),
+            Err(MinisignError::InvalidSignature)
+        );
+
+        let other = SigningKey::from_bytes(&[2; 32]);
+        assert_eq!(
+            public.verify(b"binary", &sign(&other, b"binary", "file:mopm")),
+            Err(MinisignError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(PublicKey::from_base64("not base64").is_err());
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let public = PublicKey::from_base64(&public_key(&key)).unwrap();
+        assert_eq!(
+            public.verify(b"binary", "untrusted comment: test\n"),
+            Err(MinisignError::Malformed("signature"))
+        );
+    }
+}
@@ -0,0 +1,2 @@
+pub mod minisign;
+pub mod updater;
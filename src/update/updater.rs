@@ -0,0 +1,282 @@
+//! Updates the running binary from the release metadata published at
+//! `RELEASE_ENDPOINT`:
+//!
+//! ```json
+//! {
+//!   "version": "0.4.0",
+//!   "assets": {
+//!     "x86_64-linux": { "url": "https://…/mopm", "minisig": "untrusted comment: …" }
+//!   }
+//! }
+//! ```
+//!
+//! The binary is only installed when its minisign signature verifies
+//! against the key given as `MOPM_RELEASE_PUBLIC_KEY` at build time, a
+//! build without it refuses to update, and
+//! when the trusted comment of the signature names a version newer than
+//! the running one, e.g. `version:0.4.0 file:mopm`. The metadata itself is
+//! not signed, so the signed version is what stops an old release, signed
+//! and vulnerable, from being served as the latest.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde_json::Value;
+use thiserror::Error;
+
+use super::minisign::{MinisignError, PublicKey};
+
+const RELEASE_ENDPOINT: &str =
+    "https://github.com/mikeyQwn/mopm-fork/releases/latest/download/release.json";
+const RELEASE_ENV: &str = "MOPM_RELEASE_URL";
+/// There is no default: a build that updates itself must name the key its
+/// releases are signed with.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("MOPM_RELEASE_PUBLIC_KEY");
+const SIGNED_VERSION: &str = "version:";
+const MAX_BINARY_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum UpdateError {
+    #[error("this build cannot update itself, it was built without MOPM_RELEASE_PUBLIC_KEY")]
+    NoPublicKey,
+    #[error("request to the release endpoint failed: `{0}`")]
+    RequestError(String),
+    #[error("invalid release metadata: `{0}`")]
+    InvalidRelease(String),
+    #[error("the release has no binary for `{0}`")]
+    MissingTarget(String),
+    #[error("the release binary exceeds the size limit")]
+    BinaryTooLarge,
+    #[error("the release binary is not authentic: `{0}`")]
+    SignatureError(#[from] MinisignError),
+    #[error("the signature of the release does not name its version")]
+    UnsignedVersion,
+    #[error("the signed version {signed} does not match the release {release}")]
+    VersionMismatch { signed: Version, release: Version },
+    #[error("the signed version {signed} is not newer than {current}, refusing to downgrade")]
+    Downgrade { signed: Version, current: Version },
+    #[error("cannot replace the executable: `{0}`")]
+    IoError(#[from] io::Error),
+}
+
+impl From<ureq::Error> for UpdateError {
+    fn from(value: ureq::Error) -> Self {
+        Self::RequestError(value.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct Release {
+    pub version: Version,
+    url: String,
+    minisig: String,
+}
+
+pub struct Updater {
+    endpoint: String,
+    public_key: PublicKey,
+}
+
+impl Updater {
+    pub fn new() -> Result<Self, UpdateError> {
+        Ok(Self {
+            endpoint: std::env::var(RELEASE_ENV).unwrap_or_else(|_| RELEASE_ENDPOINT.to_string()),
+            public_key: PublicKey::from_base64(
+                RELEASE_PUBLIC_KEY.ok_or(UpdateError::NoPublicKey)?,
+            )?,
+        })
+    }
+
+    pub fn current_version() -> Version {
+        Version::parse(env!("CARGO_PKG_VERSION")).expect("the package version is semver")
+    }
+
+    /// The latest release, if it is newer than the running binary.
+    pub fn newer_release(&self) -> Result<Option<Release>, UpdateError> {
+        let metadata: Value = ureq::get(&self.endpoint).call()?.into_json()?;
+        let release = parse_release(&metadata, &target())?;
+        Ok(Some(release).filter(|v| v.version > Self::current_version()))
+    }
+
+    /// Downloads and verifies `release`, then atomically replaces the
+    /// running executable with it. Returns the path of the executable.
+    pub fn install(&self, release: &Release) -> Result<PathBuf, UpdateError> {
+        let mut binary = Vec::new();
+        ureq::get(&release.url)
+            .call()?
+            .into_reader()
+            .take(MAX_BINARY_SIZE + 1)
+            .read_to_end(&mut binary)?;
+        if binary.len() as u64 > MAX_BINARY_SIZE {
+            return Err(UpdateError::BinaryTooLarge);
+        }
+        let comment = self.public_key.verify(&binary, &release.minisig)?;
+        check_signed_version(comment, &release.version, &Self::current_version())?;
+
+        let path = std::env::current_exe()?.canonicalize()?;
+        replace_executable(&path, &binary)?;
+        Ok(path)
+    }
+}
+
+fn target() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+fn parse_release(metadata: &Value, target: &str) -> Result<Release, UpdateError> {
+    let field = |value: &Value, name: &str| {
+        value[name]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| UpdateError::InvalidRelease(format!("missing `{}`", name)))
+    };
+
+    let version = field(metadata, "version")?;
+    let asset = &metadata["assets"][target];
+    if asset.is_null() {
+        return Err(UpdateError::MissingTarget(target.to_string()));
+    }
+    Ok(Release {
+        version: Version::parse(version.trim_start_matches('v'))
+            .map_err(|err| UpdateError::InvalidRelease(err.to_string()))?,
+        url: field(asset, "url")?,
+        minisig: field(asset, "minisig")?,
+    })
+}
+
+/// Checks the version in the trusted `comment` of the signature against
+/// the release and the running binary.
+fn check_signed_version(
+    comment: &str,
+    release: &Version,
+    current: &Version,
+) -> Result<(), UpdateError> {
+    let signed = comment
+        .split_whitespace()
+        .find_map(|v| v.strip_prefix(SIGNED_VERSION))
+        .and_then(|v| Version::parse(v.trim_start_matches('v')).ok())
+        .ok_or(UpdateError::UnsignedVersion)?;
+    if signed != *release {
+        return Err(UpdateError::VersionMismatch {
+            signed,
+            release: release.clone(),
+        });
+    }
+    if signed <= *current {
+        return Err(UpdateError::Downgrade {
+            signed,
+            current: current.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Writes `binary` next to `path` and renames it over `path`, so the
+/// executable is never observed half-written.
+fn replace_executable(path: &Path, binary: &[u8]) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let staged = path.with_file_name(format!(".{}.update-{}", name, std::process::id()));
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&staged)
+        .and_then(|mut file| {
+            file.write_all(binary)?;
+            #[cfg(unix)]
+            file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+            file.sync_all()?;
+            std::fs::rename(&staged, path)
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&staged);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_release() {
+        let metadata = json!({
+            "version": "v9.1.0",
+            "assets": { "x86_64-linux": { "url": "https://example.com/mopm", "minisig": "sig" } }
+        });
+        let release = parse_release(&metadata, "x86_64-linux").unwrap();
+        assert_eq!(release.version, Version::new(9, 1, 0));
+        assert_eq!(release.url, "https://example.com/mopm");
+        assert_eq!(release.minisig, "sig");
+
+        assert!(matches!(
+            parse_release(&metadata, "aarch64-macos"),
+            Err(UpdateError::MissingTarget(_))
+        ));
+        assert!(matches!(
+            parse_release(&json!({ "assets": {} }), "x86_64-linux"),
+            Err(UpdateError::InvalidRelease(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_signed_version() {
+        let current = Version::new(0, 4, 0);
+        let release = Version::new(0, 5, 0);
+        let check = |comment| check_signed_version(comment, &release, &current);
+        assert!(check("timestamp:1700000000 version:v0.5.0 file:mopm").is_ok());
+        assert!(matches!(
+            check("timestamp:1700000000 file:mopm"),
+            Err(UpdateError::UnsignedVersion)
+        ));
+        assert!(matches!(
+            check("version:0.6.0"),
+            Err(UpdateError::VersionMismatch { .. })
+        ));
+
+        let old = Version::new(0, 3, 0);
+        assert!(matches!(
+            check_signed_version("version:0.3.0", &old, &current),
+            Err(UpdateError::Downgrade { .. })
+        ));
+        assert!(matches!(
+            check_signed_version("version:0.4.0", &current, &current),
+            Err(UpdateError::Downgrade { .. })
+        ));
+    }
+
+    #[test]
+    fn test_public_key() {
+        match RELEASE_PUBLIC_KEY {
+            Some(v) => assert!(PublicKey::from_base64(v).is_ok()),
+            None => assert!(matches!(Updater::new(), Err(UpdateError::NoPublicKey))),
+        }
+    }
+
+    #[test]
+    fn test_replace_executable() {
+        let dir = std::env::temp_dir().join(format!("mopm-update-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("mopm");
+        std::fs::write(&path, b"old").unwrap();
+
+        replace_executable(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
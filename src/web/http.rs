@@ -0,0 +1,194 @@
+//! Just enough HTTP/1.1 for the web UI: one request per connection, bodies
+//! sized by `Content-Length`, and every response closing the connection.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Read, Write},
+};
+
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+const MAX_BODY: usize = 64 * 1024;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: BTreeMap<String, String>,
+    /// Header names are lowercase.
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn read(reader: &mut impl BufRead) -> io::Result<Self> {
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        let line = read_line(reader)?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(invalid("malformed request line"));
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        let mut headers = BTreeMap::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(invalid("too many headers"));
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| invalid("malformed header"))?;
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+
+        let length = match headers.get("content-length") {
+            Some(v) => v.parse().map_err(|_| invalid("malformed content length"))?,
+            None => 0,
+        };
+        if length > MAX_BODY {
+            return Err(invalid("body too large"));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: parse_form(query.as_bytes()),
+            headers,
+            body,
+        })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// The body as an `application/x-www-form-urlencoded` form.
+    pub fn form(&self) -> BTreeMap<String, String> {
+        parse_form(&self.body)
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.header("cookie")?
+            .split(';')
+            .filter_map(|v| v.trim().split_once('='))
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "line too long or cut short",
+        ));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn parse_form(bytes: &[u8]) -> BTreeMap<String, String> {
+    url::form_urlencoded::parse(bytes).into_owned().collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: &'static str,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: &'static str, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn html(status: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(status, "text/html; charset=utf-8", body)
+    }
+
+    pub fn json(value: &serde_json::Value) -> Self {
+        Self::new("200 OK", "application/json", value.to_string())
+    }
+
+    /// A response with its status as the body.
+    pub fn status(status: &'static str) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", status)
+    }
+
+    /// Sends the browser to `location` with a GET.
+    pub fn see_other(location: &str) -> Self {
+        Self::status("303 See Other").with_header("Location", location.to_string())
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Writes the response with headers that keep it out of caches and
+    /// frames, and scripts to those served by the UI itself.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nContent-Security-Policy: default-src 'none'; script-src 'self'; style-src 'self'; connect-src 'self'; form-action 'self'; frame-ancestors 'none'\r\nX-Content-Type-Options: nosniff\r\nX-Frame-Options: DENY\r\nReferrer-Policy: no-referrer\r\nConnection: close\r\n",
+            self.status,
+            self.content_type,
+            self.body.len()
+        )?;
+        for (name, value) in &self.headers {
+            write!(writer, "{}: {}\r\n", name, value)?;
+        }
+        writer.write_all(b"\r\n")?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_request() {
+        let raw = b"POST /get?q=a%20b HTTP/1.1\r\nHost: 127.0.0.1:9090\r\nCookie: a=1; mopm_session=abc\r\nContent-Length: 9\r\n\r\nkey=x%2Fy";
+        let request = Request::read(&mut &raw[..]).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/get");
+        assert_eq!(request.query["q"], "a b");
+        assert_eq!(request.header("host"), Some("127.0.0.1:9090"));
+        assert_eq!(request.cookie("mopm_session"), Some("abc"));
+        assert_eq!(request.cookie("b"), None);
+        assert_eq!(request.form()["key"], "x/y");
+
+        assert!(Request::read(&mut &b"GET / HTTP/1.1\r\n"[..]).is_err());
+        let huge = b"POST / HTTP/1.1\r\nContent-Length: 999999\r\n\r\n";
+        assert!(Request::read(&mut &huge[..]).is_err());
+    }
+
+    #[test]
+    fn test_write_response() {
+        let mut out = Vec::new();
+        Response::see_other("/")
+            .with_header("Set-Cookie", "a=b".to_string())
+            .write_to(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 303 See Other\r\n"));
+        assert!(out.contains("\r\nLocation: /\r\nSet-Cookie: a=b\r\n\r\n303 See Other"));
+        assert!(out.contains("Cache-Control: no-store"));
+    }
+}
@@ -0,0 +1,6 @@
+//! A local web UI to list, search, show and copy entries, for those who
+//! would rather use a browser (requires the `web` feature).
+
+pub mod http;
+mod pages;
+pub mod server;
@@ -0,0 +1,112 @@
+//! The pages of the web UI. Scripts and styles are served from their own
+//! paths, as the content security policy forbids inline ones.
+
+pub const LOGIN: &str = r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>mopm</title>
+<link rel="stylesheet" href="/app.css">
+</head>
+<body>
+<main>
+<h1>mopm</h1>
+<form method="post" action="/login">
+<input type="password" name="password" placeholder="Master password" autofocus required>
+<button type="submit">Unlock</button>
+</form>
+<p class="error">{error}</p>
+</main>
+</body>
+</html>
+"#;
+
+pub const APP: &str = r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="csrf-token" content="{csrf}">
+<title>mopm</title>
+<link rel="stylesheet" href="/app.css">
+<script src="/app.js" defer></script>
+</head>
+<body>
+<main>
+<h1>mopm</h1>
+<form method="post" action="/logout">
+<input type="hidden" name="csrf" value="{csrf}">
+<button type="submit">Lock</button>
+</form>
+<input id="search" type="search" placeholder="Search" autofocus>
+<ul id="entries"></ul>
+<p id="status"></p>
+</main>
+</body>
+</html>
+"#;
+
+pub const SCRIPT: &str = r#""use strict";
+const csrf = document.querySelector('meta[name="csrf-token"]').content;
+const entries = document.getElementById("entries");
+const status = document.getElementById("status");
+
+async function value(key) {
+  const response = await fetch("/get", {
+    method: "POST",
+    headers: {
+      "Content-Type": "application/x-www-form-urlencoded",
+      "X-CSRF-Token": csrf,
+    },
+    body: new URLSearchParams({ key }),
+  });
+  if (!response.ok) {
+    throw new Error(await response.text());
+  }
+  return (await response.json()).value;
+}
+
+function button(label, action) {
+  const element = document.createElement("button");
+  element.textContent = label;
+  element.addEventListener("click", () =>
+    action().catch((err) => (status.textContent = err.message)),
+  );
+  return element;
+}
+
+async function search(query) {
+  const response = await fetch("/entries?q=" + encodeURIComponent(query));
+  if (response.status === 401) {
+    location.reload();
+    return;
+  }
+  entries.replaceChildren();
+  for (const key of await response.json()) {
+    const item = document.createElement("li");
+    const name = document.createElement("span");
+    const shown = document.createElement("code");
+    name.textContent = key;
+    item.append(
+      name,
+      button("Show", async () => (shown.textContent = await value(key))),
+      button("Copy", async () => {
+        await navigator.clipboard.writeText(await value(key));
+        status.textContent = "Copied " + key;
+      }),
+      shown,
+    );
+    entries.append(item);
+  }
+}
+
+document.getElementById("search").addEventListener("input", (event) =>
+  search(event.target.value),
+);
+search("");
+"#;
+
+pub const STYLE: &str = r#"body { font-family: sans-serif; margin: 2em auto; max-width: 40em; }
+li { display: flex; gap: 0.5em; align-items: center; margin: 0.25em 0; }
+li span { flex: 1; }
+.error, #status { color: #b00; }
+"#;
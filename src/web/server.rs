@@ -0,0 +1,396 @@
+//! Serves the web UI from a loopback address, one connection at a time.
+//!
+//! Nothing is unlocked until the master password is posted to `/login`,
+//! which opens the vault for a session kept behind an `HttpOnly`,
+//! `SameSite=Strict` cookie. Requests must name the served address in
+//! `Host`, which keeps out pages that rebind their domain to loopback;
+//! posts must come from the same origin and, past the login, carry the
+//! token of the session.
+//!
+//! After a few wrong passwords logins are refused for a while, doubling
+//! with every further one, so that another local process cannot guess at
+//! the speed of the key derivation. A connection has a few seconds to send
+//! its request however slowly it trickles in, or it would hold up every
+//! other one.
+//!
+//! There is no WebAuthn: the master password is the only way in, and the
+//! UI is no better protected than it is.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufReader, Read},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+use crate::core::{
+    ct,
+    encoder::{Encoder, StoredVault},
+    encryptor::DynamicEncryptor,
+    keycache::KeyCache,
+    manager::{PasswordManager, PasswordManagerError},
+    policy::Unattended,
+    rng,
+};
+
+use super::{
+    http::{Request, Response},
+    pages,
+};
+
+const SESSION_COOKIE: &str = "mopm_session";
+const CSRF_HEADER: &str = "x-csrf-token";
+const CSRF_FIELD: &str = "csrf";
+/// Sessions end after this long without a request.
+const SESSION_IDLE: Duration = Duration::from_secs(10 * 60);
+/// How long a connection has to send its whole request, and a response to
+/// be written.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Wrong passwords allowed before logins are refused for `BASE_LOCKOUT`,
+/// doubled with every further one up to `MAX_LOCKOUT`.
+const FREE_LOGIN_FAILURES: u32 = 3;
+const BASE_LOCKOUT: Duration = Duration::from_secs(1);
+const MAX_LOCKOUT: Duration = Duration::from_secs(5 * 60);
+const TOKEN_LENGTH: usize = 32;
+
+struct Session {
+    pm: PasswordManager<DynamicEncryptor>,
+    csrf: String,
+    seen: Instant,
+}
+
+pub struct Server {
+    vault: StoredVault,
+    /// The `Host` headers the UI answers to.
+    hosts: Vec<String>,
+    origins: Vec<String>,
+    sessions: HashMap<String, Session>,
+    key_cache: KeyCache,
+    /// Wrong passwords since the last login.
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+/// Reads from a connection until `deadline`, so that a client sending a
+/// byte at a time cannot keep it open.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        (&mut self.stream).read(buf)
+    }
+}
+
+impl Server {
+    /// A server for `vault`, reached at `addr`.
+    pub fn new(vault: StoredVault, addr: SocketAddr) -> Self {
+        let hosts = vec![addr.to_string(), format!("localhost:{}", addr.port())];
+        let origins = hosts.iter().map(|v| format!("http://{}", v)).collect();
+        Self {
+            vault,
+            hosts,
+            origins,
+            sessions: HashMap::new(),
+            key_cache: KeyCache::default(),
+            failures: 0,
+            locked_until: None,
+        }
+    }
+
+    /// Answers connections on `listener` until it fails.
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            // A broken connection only concerns its own client.
+            let _ = self.respond(stream?);
+        }
+        Ok(())
+    }
+
+    fn respond(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let request = Request::read(&mut BufReader::new(Deadline {
+            stream: &stream,
+            deadline: Instant::now() + REQUEST_TIMEOUT,
+        }));
+        let response = match request {
+            Ok(request) => self.handle(&request),
+            Err(_) => Response::status("400 Bad Request"),
+        };
+        response.write_to(&mut stream)
+    }
+
+    pub fn handle(&mut self, request: &Request) -> Response {
+        if !request
+            .header("host")
+            .is_some_and(|v| self.hosts.iter().any(|host| host == v))
+        {
+            return Response::status("421 Misdirected Request");
+        }
+        if request.method == "POST"
+            && !request
+                .header("origin")
+                .is_some_and(|v| self.origins.iter().any(|origin| origin == v))
+        {
+            return Response::status("403 Forbidden");
+        }
+        self.sessions
+            .retain(|_, session| session.seen.elapsed() < SESSION_IDLE);
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/app.js") => Response::new("200 OK", "text/javascript", pages::SCRIPT),
+            ("GET", "/app.css") => Response::new("200 OK", "text/css", pages::STYLE),
+            ("GET", "/") => match self.session(request) {
+                Some(session) => {
+                    Response::html("200 OK", pages::APP.replace("{csrf}", &session.csrf))
+                }
+                None => Response::html("200 OK", pages::LOGIN.replace("{error}", "")),
+            },
+            ("POST", "/login") => self.login(request),
+            ("POST", "/logout") => {
+                let csrf = request.form().remove(CSRF_FIELD);
+                if self.authorized(request, csrf.as_deref()).is_some() {
+                    if let Some(token) = request.cookie(SESSION_COOKIE) {
+                        self.sessions.remove(token);
+                    }
+                }
+                Response::see_other("/").with_header("Set-Cookie", cookie(""))
+            }
+            ("GET", "/entries") => match self.session(request) {
+                Some(session) => {
+                    let query = request.query.get("q").map_or("", String::as_str);
+                    let keys: Vec<&str> = session.pm.entries(query).map(|(key, _)| key).collect();
+                    Response::json(&serde_json::json!(keys))
+                }
+                None => Response::status("401 Unauthorized"),
+            },
+            ("POST", "/get") => {
+                let csrf = request.header(CSRF_HEADER);
+                let Some(session) = self.authorized(request, csrf) else {
+                    return Response::status("403 Forbidden");
+                };
+                let key = request.form().remove("key").unwrap_or_default();
+                match session.pm.get_password(&key) {
+                    Ok(value) => Response::json(&serde_json::json!({ "value": value })),
+                    Err(PasswordManagerError::NoPasswordFound) => Response::status("404 Not Found"),
+                    Err(err) => Response::new(
+                        "403 Forbidden",
+                        "text/plain; charset=utf-8",
+                        err.to_string(),
+                    ),
+                }
+            }
+            _ => Response::status("404 Not Found"),
+        }
+    }
+
+    fn login(&mut self, request: &Request) -> Response {
+        let now = Instant::now();
+        if let Some(until) = self.locked_until.filter(|v| *v > now) {
+            return Response::status("429 Too Many Requests")
+                .with_header("Retry-After", ((until - now).as_secs() + 1).to_string());
+        }
+        let password = request.form().remove("password").unwrap_or_default();
+        let pm = match Encoder::decode_stored(
+            password.trim().as_bytes(),
+            &self.vault,
+            &mut self.key_cache,
+        ) {
+            Ok(v) => v,
+            Err(_) => {
+                ct::reject();
+                self.failures = self.failures.saturating_add(1);
+                if let Some(doublings) = self.failures.checked_sub(FREE_LOGIN_FAILURES) {
+                    let lockout = BASE_LOCKOUT.saturating_mul(1 << doublings.min(16));
+                    self.locked_until = Some(now + lockout.min(MAX_LOCKOUT));
+                }
+                let page = pages::LOGIN.replace("{error}", "Wrong password");
+                return Response::html("401 Unauthorized", page);
+            }
+        };
+        // Nobody is at the terminal to answer, entries with an access
+        // policy stay locked.
+        let pm = pm.with_guard(Box::new(Unattended));
+        (self.failures, self.locked_until) = (0, None);
+        let token = new_token();
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                pm,
+                csrf: new_token(),
+                seen: Instant::now(),
+            },
+        );
+        Response::see_other("/").with_header("Set-Cookie", cookie(&token))
+    }
+
+    fn session(&mut self, request: &Request) -> Option<&mut Session> {
+        let session = self.sessions.get_mut(request.cookie(SESSION_COOKIE)?)?;
+        session.seen = Instant::now();
+        Some(session)
+    }
+
+    /// The session of `request`, if `csrf` is its token.
+    fn authorized(&mut self, request: &Request, csrf: Option<&str>) -> Option<&mut Session> {
+        let session = self.session(request)?;
+        ct::eq(session.csrf.as_bytes(), csrf?.as_bytes()).then_some(session)
+    }
+}
+
+fn new_token() -> String {
+    let mut token = [0; TOKEN_LENGTH];
+    rng::fill(&mut token);
+    hex::encode(token)
+}
+
+/// The session cookie, cleared if `token` is empty.
+fn cookie(token: &str) -> String {
+    let max_age = match token.is_empty() {
+        true => "; Max-Age=0",
+        false => "",
+    };
+    format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Strict{}",
+        SESSION_COOKIE, token, max_age
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::core::kdf::{Kdf, KdfParams};
+
+    use super::*;
+
+    const ADDR: &str = "127.0.0.1:9090";
+    const ORIGIN: &str = "http://127.0.0.1:9090";
+
+    fn server() -> Server {
+        let params = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let mut pm = PasswordManager::init("pw", Kdf::argon2id(params).unwrap()).unwrap();
+        pm.store_password("mail".to_string(), "secret").unwrap();
+        let mut vault = Vec::new();
+        Encoder::encode(&mut vault, &mut pm).unwrap();
+        Server::new(StoredVault::File(vault), ADDR.parse().unwrap())
+    }
+
+    fn request(method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> Request {
+        let mut request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.as_bytes().to_vec(),
+            ..Request::default()
+        };
+        request.headers = BTreeMap::from([("host".to_string(), ADDR.to_string())]);
+        for (name, value) in headers {
+            request.headers.insert(name.to_string(), value.to_string());
+        }
+        request
+    }
+
+    fn body(response: &Response) -> String {
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        out.split_once("\r\n\r\n").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn test_requests_are_checked() {
+        let mut server = server();
+        let mut rebound = request("GET", "/", &[], "");
+        rebound
+            .headers
+            .insert("host".to_string(), "evil.example:9090".to_string());
+        assert_eq!(server.handle(&rebound).status, "421 Misdirected Request");
+
+        let cross_site = request(
+            "POST",
+            "/login",
+            &[("origin", "http://evil.example")],
+            "password=pw",
+        );
+        assert_eq!(server.handle(&cross_site).status, "403 Forbidden");
+        assert_eq!(
+            server.handle(&request("GET", "/entries", &[], "")).status,
+            "401 Unauthorized"
+        );
+    }
+
+    #[test]
+    fn test_login_and_get() {
+        let mut server = server();
+        let wrong = request("POST", "/login", &[("origin", ORIGIN)], "password=nope");
+        assert_eq!(server.handle(&wrong).status, "401 Unauthorized");
+        assert!(server.sessions.is_empty());
+
+        let login = request("POST", "/login", &[("origin", ORIGIN)], "password=pw");
+        assert_eq!(server.handle(&login).status, "303 See Other");
+        let (token, session) = server.sessions.iter().next().unwrap();
+        let (cookie, csrf) = (
+            format!("{}={}", SESSION_COOKIE, token),
+            session.csrf.clone(),
+        );
+
+        let entries = request("GET", "/entries", &[("cookie", &cookie)], "");
+        assert_eq!(body(&server.handle(&entries)), r#"["mail"]"#);
+
+        let forged = request(
+            "POST",
+            "/get",
+            &[("origin", ORIGIN), ("cookie", &cookie)],
+            "key=mail",
+        );
+        assert_eq!(server.handle(&forged).status, "403 Forbidden");
+        let get = request(
+            "POST",
+            "/get",
+            &[
+                ("origin", ORIGIN),
+                ("cookie", &cookie),
+                (CSRF_HEADER, &csrf),
+            ],
+            "key=mail",
+        );
+        assert_eq!(body(&server.handle(&get)), r#"{"value":"secret"}"#);
+
+        let logout = request(
+            "POST",
+            "/logout",
+            &[("origin", ORIGIN), ("cookie", &cookie)],
+            &format!("csrf={}", csrf),
+        );
+        assert_eq!(server.handle(&logout).status, "303 See Other");
+        assert!(server.sessions.is_empty());
+    }
+
+    #[test]
+    fn test_login_throttled() {
+        let mut server = server();
+        let wrong = request("POST", "/login", &[("origin", ORIGIN)], "password=nope");
+        for _ in 0..FREE_LOGIN_FAILURES {
+            assert_eq!(server.handle(&wrong).status, "401 Unauthorized");
+        }
+        assert!(server.locked_until.is_some());
+        let login = request("POST", "/login", &[("origin", ORIGIN)], "password=pw");
+        assert_eq!(server.handle(&login).status, "429 Too Many Requests");
+        assert!(server.sessions.is_empty());
+
+        server.locked_until = Some(Instant::now());
+        assert_eq!(server.handle(&login).status, "303 See Other");
+        assert_eq!((server.failures, server.locked_until), (0, None));
+    }
+}